@@ -0,0 +1,54 @@
+//! Measures the throughput of waking 10,000 MAC addresses on the loopback interface, once with
+//! `WolClient::send_all` and once with a `WolClient::send` call, and thus a socket, per packet.
+//!
+//! Run with `cargo bench`; no benchmark framework is needed, so it builds offline and on stable.
+extern crate wol;
+
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+use wol::{MacAddr, MacRange, MagicPacket, WolClient};
+
+/// The number of MAC addresses woken per run.
+const HOSTS: usize = 10_000;
+
+/// The number of runs, of which the fastest is reported.
+const RUNS: usize = 5;
+
+/// Returns the fastest of `RUNS` runs of `run`.
+fn fastest<F: FnMut()>(mut run: F) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn print(name: &str, elapsed: Duration) {
+    println!("{:<16} {:>8.2} ms  {:>10.0} packets/s",
+             name,
+             elapsed.as_secs_f64() * 1e3,
+             HOSTS as f64 / elapsed.as_secs_f64());
+}
+
+fn main() {
+    // Datagrams beyond the receive buffer are dropped, which does not slow down the sender.
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let client = WolClient::new(receiver.local_addr().unwrap());
+    let first = MacAddr::new([0x02, 0, 0, 0, 0, 0]);
+    let packets: Vec<MagicPacket> = MacRange::new(first, MacAddr::new([0x02, 0, 0, 0, 0xFF, 0xFF]))
+        .take(HOSTS)
+        .map(MagicPacket::new)
+        .collect();
+    print("send_all",
+          fastest(|| {
+              client.send_all(packets.iter().cloned()).unwrap();
+          }));
+    print("send per packet",
+          fastest(|| for packet in &packets {
+              client.send(packet).unwrap();
+          }));
+}
@@ -0,0 +1,52 @@
+/*
+ * C bindings of the wol crate, built with
+ * `cargo rustc --release --lib --features ffi --crate-type cdylib`.
+ *
+ * All functions return a non-negative value on success and one of the negative
+ * WOL_ERR_* codes on failure. MAC addresses are passed as six bytes.
+ */
+#ifndef WOL_H
+#define WOL_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A required pointer argument is NULL. */
+#define WOL_ERR_NULL (-1)
+/* A string argument is not a valid MAC or IP address. */
+#define WOL_ERR_PARSE (-2)
+/* The packet could not be sent. */
+#define WOL_ERR_SEND (-3)
+/* The output buffer is too small. */
+#define WOL_ERR_BUFFER (-4)
+
+/*
+ * Sends a magic packet for `mac` per UDP/IPv4 to `broadcast` (the limited broadcast address
+ * if NULL) and `port`. Returns the number of bytes sent.
+ */
+int wol_send_v4(const uint8_t mac[6], const char *broadcast, uint16_t port);
+
+/*
+ * Sends a magic packet for `mac` per UDP/IPv6 to `group` (the default multicast group if
+ * NULL) and `port`. Returns the number of bytes sent.
+ */
+int wol_send_v6(const uint8_t mac[6], const char *group, uint16_t port);
+
+/* Parses a MAC address like "AA:FF:B0:12:34:56" into `out`. Returns 0. */
+int wol_parse_mac(const char *s, uint8_t out[6]);
+
+/*
+ * Writes the 102 byte magic packet for `mac` to `buf` of size `len`, e.g. to send it with
+ * another network stack. Returns the number of bytes written.
+ */
+int wol_build_packet(const uint8_t mac[6], uint8_t *buf, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* WOL_H */
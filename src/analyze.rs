@@ -0,0 +1,264 @@
+//! Finding magic packets in captures, e.g. to audit who wakes which hosts on a network.
+//!
+//! Magic packets are recognised in UDP datagrams over IPv4 or IPv6 on any port and in Ethernet
+//! frames of EtherType 0x0842, also behind an 802.1Q VLAN tag. Fragmented datagrams and IPv6
+//! extension headers are not reassembled or followed.
+//!
+//! The packets found can be sent again, e.g. to reproduce what a network saw or to test a relay
+//! with real traffic, see `Sighting::client`.
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::SystemTime;
+
+use listen::Source;
+use pcap::{PcapReader, ETHERTYPE_IPV4, ETHERTYPE_IPV6, ETHERTYPE_VLAN, ETHERTYPE_WOL};
+use {MacAddr, MagicPacket, WolClient};
+
+/// The IP protocol number of UDP.
+const PROTOCOL_UDP: u8 = 17;
+
+/// A magic packet found in a capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sighting {
+    /// The time the frame was captured.
+    pub time: SystemTime,
+    /// The sender, the IP address and port for UDP and the MAC address for raw frames.
+    pub source: Source,
+    /// The destination of UDP datagrams, `None` for raw frames.
+    pub destination: Option<SocketAddr>,
+    pub packet: MagicPacket,
+}
+
+impl Sighting {
+    /// Returns a client sending the packet again to its captured destination with the settings
+    /// of `client`, e.g. its interface and TTL. Raw frames are sent to the destination of
+    /// `client` per UDP, as their Ethernet destination cannot be given to a UDP socket.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::time::UNIX_EPOCH;
+    /// use wol::analyze::decode_frame;
+    /// use wol::pcap::udp_frame;
+    /// use wol::{MacAddr, MagicPacket, WolClient};
+    ///
+    /// let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+    /// let frame = udp_frame("10.0.0.1:4000".parse().unwrap(),
+    ///                       "10.0.0.255:9".parse().unwrap(),
+    ///                       &packet.to_bytes());
+    /// let sighting = decode_frame(UNIX_EPOCH, &frame).unwrap();
+    /// let client = sighting.client(&WolClient::v4().with_ttl(4));
+    /// assert_eq!(client.destination(), "10.0.0.255:9".parse().unwrap());
+    /// assert_eq!(client.ttl(), Some(4));
+    /// ```
+    pub fn client(&self, client: &WolClient) -> WolClient {
+        match self.destination {
+            Some(destination) => {
+                client.clone().with_broadcast(destination.ip()).with_port(destination.port())
+            },
+            None => client.clone(),
+        }
+    }
+}
+
+/// Returns the magic packet in an Ethernet frame, if any.
+///
+/// #Example
+///
+/// ```
+/// use std::time::SystemTime;
+/// use wol::analyze::decode_frame;
+/// use wol::pcap::udp_frame;
+/// use wol::{MacAddr, MagicPacket};
+///
+/// let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+/// let frame = udp_frame("10.0.0.1:4000".parse().unwrap(),
+///                       "10.0.0.255:9".parse().unwrap(),
+///                       &packet.to_bytes());
+/// let sighting = decode_frame(SystemTime::now(), &frame).unwrap();
+/// assert_eq!(sighting.packet, packet);
+/// assert_eq!(sighting.destination, Some("10.0.0.255:9".parse().unwrap()));
+/// ```
+pub fn decode_frame(time: SystemTime, frame: &[u8]) -> Option<Sighting> {
+    let mut ethertype = u16_at(frame, 12)?;
+    let mut offset = 14;
+    if ethertype == ETHERTYPE_VLAN {
+        ethertype = u16_at(frame, 16)?;
+        offset = 18;
+    }
+    let payload = &frame[offset..];
+    let (source, destination, payload) = match ethertype {
+        ETHERTYPE_WOL => {
+            let mut source = [0; 6];
+            source.copy_from_slice(&frame[6..12]);
+            (Source::Ethernet(MacAddr::new(source)), None, payload)
+        },
+        ETHERTYPE_IPV4 => {
+            let header_len = usize::from(*payload.first()? & 0x0F) * 4;
+            let more_fragments = payload.get(6)? & 0x20 != 0;
+            let fragment_offset = u16_at(payload, 6)? & 0x1FFF;
+            if header_len < 20 || *payload.get(9)? != PROTOCOL_UDP || more_fragments ||
+               fragment_offset != 0 {
+                return None;
+            }
+            let source = Ipv4Addr::from(array4(payload.get(12..16)?));
+            let destination = Ipv4Addr::from(array4(payload.get(16..20)?));
+            udp(source.into(), destination.into(), payload.get(header_len..)?)?
+        },
+        ETHERTYPE_IPV6 => {
+            if *payload.get(6)? != PROTOCOL_UDP {
+                return None;
+            }
+            let source = Ipv6Addr::from(array16(payload.get(8..24)?));
+            let destination = Ipv6Addr::from(array16(payload.get(24..40)?));
+            udp(source.into(), destination.into(), payload.get(40..)?)?
+        },
+        _ => return None,
+    };
+    MagicPacket::parse(payload).map(|packet| {
+        Sighting {
+            time,
+            source,
+            destination,
+            packet,
+        }
+    })
+}
+
+/// Returns the addresses and the payload of a UDP datagram.
+fn udp(source: IpAddr,
+       destination: IpAddr,
+       datagram: &[u8])
+       -> Option<(Source, Option<SocketAddr>, &[u8])> {
+    let source = SocketAddr::new(source, u16_at(datagram, 0)?);
+    let destination = SocketAddr::new(destination, u16_at(datagram, 2)?);
+    // The length field excludes trailing Ethernet padding.
+    let len = usize::from(u16_at(datagram, 4)?);
+    let payload = datagram.get(8..len)?;
+    Some((Source::Udp(source), Some(destination), payload))
+}
+
+/// Returns the big endian `u16` at `offset`, if the data is long enough.
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn array4(bytes: &[u8]) -> [u8; 4] {
+    let mut array = [0; 4];
+    array.copy_from_slice(bytes);
+    array
+}
+
+fn array16(bytes: &[u8]) -> [u8; 16] {
+    let mut array = [0; 16];
+    array.copy_from_slice(bytes);
+    array
+}
+
+/// Returns the magic packets of a capture and the number of frames it holds.
+pub fn scan<R: Read>(capture: PcapReader<R>) -> io::Result<(Vec<Sighting>, usize)> {
+    let mut sightings = Vec::new();
+    let mut frames = 0;
+    for frame in capture {
+        let (time, frame) = frame?;
+        frames += 1;
+        sightings.extend(decode_frame(time, &frame));
+    }
+    Ok((sightings, frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use listen::Source;
+    use pcap::{udp_frame, PcapReader, PcapWriter};
+    use {MacAddr, MagicPacket, WolClient};
+
+    use super::{decode_frame, scan};
+
+    #[test]
+    fn test_decode_raw_and_vlan() {
+        let packet = MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]))
+            .with_password([1, 2, 3, 4, 5, 6]);
+        let mut frame = vec![0xFF; 6];
+        frame.extend_from_slice(&[2, 0, 0, 0, 0, 9]);
+        frame.extend_from_slice(&[0x81, 0x00, 0x00, 0x05, 0x08, 0x42]);
+        frame.extend_from_slice(&packet.to_bytes());
+        let sighting = decode_frame(UNIX_EPOCH, &frame).unwrap();
+        assert_eq!(sighting.source, Source::Ethernet(MacAddr::new([2, 0, 0, 0, 0, 9])));
+        assert_eq!(sighting.destination, None);
+        assert_eq!(sighting.packet.password(), Some([1, 2, 3, 4, 5, 6]));
+    }
+
+    #[test]
+    fn test_decode_v6_with_padding() {
+        let packet = MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]));
+        let mut frame = udp_frame("[fe80::1]:4000".parse().unwrap(),
+                                  "[ff02::1]:9".parse().unwrap(),
+                                  &packet.to_bytes());
+        frame.extend_from_slice(&[0; 4]);
+        let sighting = decode_frame(UNIX_EPOCH, &frame).unwrap();
+        assert_eq!(sighting.source, Source::Udp("[fe80::1]:4000".parse().unwrap()));
+        assert_eq!(sighting.packet, packet);
+    }
+
+    #[test]
+    fn test_decode_other() {
+        assert!(decode_frame(UNIX_EPOCH, &[]).is_none());
+        let frame = udp_frame("10.0.0.1:53".parse().unwrap(),
+                              "10.0.0.2:53".parse().unwrap(),
+                              b"not a magic packet");
+        assert!(decode_frame(UNIX_EPOCH, &frame).is_none());
+        // Truncated in the IP header.
+        assert!(decode_frame(UNIX_EPOCH, &frame[..20]).is_none());
+        // Every truncation of a frame with a packet, and lengths in the headers larger than the
+        // frame, are rejected without panicking.
+        let packet = MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]));
+        let mut frame = udp_frame("10.0.0.1:4000".parse().unwrap(),
+                                  "10.0.0.255:9".parse().unwrap(),
+                                  &packet.to_bytes());
+        for len in 0..frame.len() {
+            assert!(decode_frame(UNIX_EPOCH, &frame[..len]).is_none());
+        }
+        frame[14] = 0x4F;
+        frame[14 + 20 + 4] = 0xFF;
+        assert!(decode_frame(UNIX_EPOCH, &frame).is_none());
+    }
+
+    #[test]
+    fn test_client() {
+        let packet = MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]));
+        let frame = udp_frame("[fe80::1]:4000".parse().unwrap(),
+                              "[ff02::1]:7".parse().unwrap(),
+                              &packet.to_bytes());
+        let sighting = decode_frame(UNIX_EPOCH, &frame).unwrap();
+        let client = WolClient::v4().with_ports(&[7, 9]).with_repeat(3);
+        let resent = sighting.client(&client);
+        assert_eq!(resent.destination(), "[ff02::1]:7".parse().unwrap());
+        assert_eq!(resent.ports(), vec![7]);
+        assert_eq!(resent.repeat(), 3);
+        // Raw frames keep the destination of the client.
+        let mut frame = vec![0xFF; 6];
+        frame.extend_from_slice(&[2, 0, 0, 0, 0, 9, 0x08, 0x42]);
+        frame.extend_from_slice(&packet.to_bytes());
+        let sighting = decode_frame(UNIX_EPOCH, &frame).unwrap();
+        assert_eq!(sighting.client(&client), client);
+    }
+
+    #[test]
+    fn test_scan() {
+        let packet = MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]));
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        let frame = udp_frame("10.0.0.1:4000".parse().unwrap(),
+                              "10.0.0.255:9".parse().unwrap(),
+                              &packet.to_bytes());
+        writer.write_frame(UNIX_EPOCH, &frame).unwrap();
+        writer.write_frame(UNIX_EPOCH, &[0; 60]).unwrap();
+        let capture = writer.into_inner();
+        let (sightings, frames) = scan(PcapReader::new(&capture[..]).unwrap()).unwrap();
+        assert_eq!(frames, 2);
+        assert_eq!(sightings.len(), 1);
+        assert_eq!(sightings[0].packet, packet);
+    }
+}
@@ -0,0 +1,191 @@
+//! Verification by ARP requests, for hosts on the local subnet which neither answer pings nor
+//! have open TCP ports.
+//!
+//! A host answers the ARP request for its IPv4 address as soon as its network stack is up,
+//! whatever its firewall does. ARP only reaches hosts on the same link, so the target must be on
+//! a subnet of a local interface; the interface is taken from the routing table. Sending the
+//! request needs a raw socket, i.e. Linux and the `CAP_NET_RAW` capability.
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use pcap::ETHERTYPE_IPV4;
+use sys::{self, RawSocket};
+use MacAddr;
+
+/// The EtherType of ARP.
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+/// The length of an ARP request for IPv4 over Ethernet, including the Ethernet header.
+const REQUEST_LEN: usize = 42;
+
+/// Sends ARP requests through the interface of one subnet.
+#[derive(Debug)]
+pub struct Prober {
+    socket: RawSocket,
+    interface: String,
+    mac: MacAddr,
+    ip: Ipv4Addr,
+}
+
+impl Prober {
+    /// Opens a raw socket on the interface of the subnet `target` is on. Fails with
+    /// `io::ErrorKind::InvalidInput` if no local subnet contains it and with
+    /// `io::ErrorKind::PermissionDenied` without the privileges for raw sockets.
+    pub fn open(target: Ipv4Addr) -> io::Result<Prober> {
+        let interface = interface_for(target)?;
+        let socket = RawSocket::open(ETHERTYPE_ARP, Some(&interface)).map_err(|err| {
+            if err.kind() == io::ErrorKind::PermissionDenied {
+                io::Error::new(err.kind(), "ARP probes need the CAP_NET_RAW capability")
+            } else {
+                err
+            }
+        })?;
+        Ok(Prober {
+            socket,
+            mac: sys::interface_mac(&interface)?,
+            ip: sys::interface_ipv4(&interface)?,
+            interface,
+        })
+    }
+
+    /// Returns the interface the requests are sent through.
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    /// Asks who has `target` and returns the MAC address of the host if it answered within
+    /// `timeout`.
+    pub fn probe(&mut self, target: Ipv4Addr, timeout: Duration) -> io::Result<Option<MacAddr>> {
+        self.socket.send(&request(self.mac, self.ip, target))?;
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0; 1514];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(remaining)?;
+            match self.socket.recv(&mut buf) {
+                Ok(len) => {
+                    if let Some(mac) = reply_from(&buf[..len], target) {
+                        debug!("{} is at {} on {}", target, mac, self.interface);
+                        return Ok(Some(mac));
+                    }
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Asks once who has `target` and returns the MAC address of the host if it answered within
+/// `timeout`.
+pub fn probe(target: Ipv4Addr, timeout: Duration) -> io::Result<Option<MacAddr>> {
+    Prober::open(target)?.probe(target, timeout)
+}
+
+/// Returns the interface of the local subnet `target` is on, which frames to it are sent
+/// through. Fails with `io::ErrorKind::InvalidInput` if no local subnet contains it.
+pub fn interface_for(target: Ipv4Addr) -> io::Result<String> {
+    let routes = fs::read_to_string("/proc/net/route")?;
+    local_interface(&routes, target).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not on a local subnet", target))
+    })
+}
+
+/// Returns the interface of the most specific route in `/proc/net/route` which reaches `target`
+/// directly, without gateway.
+fn local_interface(routes: &str, target: Ipv4Addr) -> Option<String> {
+    // The addresses are in network byte order, printed as hexadecimal numbers of the host.
+    let address = |hex: &str| u32::from_str_radix(hex, 16).ok().map(|n| n.to_ne_bytes());
+    let target = u32::from(target);
+    routes.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 || address(fields[2])? != [0; 4] {
+                return None;
+            }
+            let destination = u32::from(Ipv4Addr::from(address(fields[1])?));
+            let mask = u32::from(Ipv4Addr::from(address(fields[7])?));
+            // The default route has no gateway on point-to-point links, which ARP cannot use.
+            if mask == 0 || target & mask != destination {
+                return None;
+            }
+            Some((mask, fields[0]))
+        })
+        .max_by_key(|&(mask, _)| mask)
+        .map(|(_, interface)| interface.to_string())
+}
+
+/// Builds the broadcast frame asking who has `target`, to be answered to `mac` and `ip`.
+fn request(mac: MacAddr, ip: Ipv4Addr, target: Ipv4Addr) -> [u8; REQUEST_LEN] {
+    let mut frame = [0; REQUEST_LEN];
+    frame[..6].copy_from_slice(&[0xFF; 6]);
+    frame[6..12].copy_from_slice(&mac.octets());
+    frame[12..14].copy_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+    // Ethernet, IPv4, the lengths of their addresses and the operation, 1 for requests.
+    frame[14..22].copy_from_slice(&[0, 1, 0, 0, 6, 4, 0, 1]);
+    frame[16..18].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    frame[22..28].copy_from_slice(&mac.octets());
+    frame[28..32].copy_from_slice(&ip.octets());
+    frame[38..42].copy_from_slice(&target.octets());
+    frame
+}
+
+/// Returns the MAC address of the sender if the frame is an ARP reply from `target`.
+fn reply_from(frame: &[u8], target: Ipv4Addr) -> Option<MacAddr> {
+    if frame.len() < REQUEST_LEN || frame[12..14] != ETHERTYPE_ARP.to_be_bytes() ||
+       frame[20..22] != [0, 2] || frame[28..32] != target.octets() {
+        return None;
+    }
+    let mut mac = [0; 6];
+    mac.copy_from_slice(&frame[22..28]);
+    Some(MacAddr::new(mac))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::{local_interface, reply_from, request};
+    use MacAddr;
+
+    const ROUTES: &str = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\
+                          \tWindow\tIRTT\n\
+                          eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0\n\
+                          eth0\t0000A8C0\t00000000\t0001\t0\t0\t0\t0000FFFF\t0\t0\t0\n\
+                          eth1\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n";
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn test_local_interface() {
+        let interface = |ip: [u8; 4]| local_interface(ROUTES, Ipv4Addr::from(ip));
+        assert_eq!(interface([192, 168, 1, 20]), Some("eth1".to_string()));
+        assert_eq!(interface([192, 168, 7, 20]), Some("eth0".to_string()));
+        assert_eq!(interface([10, 0, 0, 1]), None);
+    }
+
+    #[test]
+    fn test_request_and_reply() {
+        let mac = MacAddr::new([2, 0, 0, 0, 0, 1]);
+        let target = Ipv4Addr::new(192, 168, 1, 20);
+        let frame = request(mac, Ipv4Addr::new(192, 168, 1, 2), target);
+        assert_eq!(&frame[12..22], &[0x08, 0x06, 0, 1, 0x08, 0x00, 6, 4, 0, 1]);
+        assert_eq!(&frame[38..], &[192, 168, 1, 20]);
+        assert_eq!(reply_from(&frame, target), None);
+
+        // The reply swaps the addresses and has the operation 2.
+        let host = [0, 0x11, 0x22, 0x33, 0x44, 0x55];
+        let mut reply = frame.to_vec();
+        reply[21] = 2;
+        reply[22..28].copy_from_slice(&host);
+        reply[28..32].copy_from_slice(&target.octets());
+        reply.resize(60, 0);
+        assert_eq!(reply_from(&reply, target), Some(MacAddr::new(host)));
+        assert_eq!(reply_from(&reply, Ipv4Addr::new(192, 168, 1, 21)), None);
+    }
+}
@@ -0,0 +1,119 @@
+//! Append-only audit log of wake requests.
+//!
+//! Every wake request is recorded as one JSON object per line, containing the time, who
+//! requested the wake, the MAC address, the target address and the outcome. The file is opened
+//! in append mode and each event is written with a single write, so several processes can share
+//! one audit log.
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use output::{timestamp, Record};
+
+/// Who requested a wake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Initiator {
+    /// A user of the command line tool.
+    Cli(String),
+    /// A client of the HTTP daemon, identified by its IP address.
+    Api(String),
+}
+
+impl Initiator {
+    /// Returns the initiator for the user running this process, taken from `USER`, `USERNAME` or
+    /// `LOGNAME`.
+    pub fn current_user() -> Initiator {
+        let user = ["USER", "USERNAME", "LOGNAME"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|user| !user.is_empty())
+            .unwrap_or_else(|| "unknown".to_string());
+        Initiator::Cli(user)
+    }
+
+    /// Returns the kind of the initiator, e.g. `cli`.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Initiator::Cli(_) => "cli",
+            Initiator::Api(_) => "api",
+        }
+    }
+
+    /// Returns the name identifying the initiator, e.g. the user name or client address.
+    pub fn name(&self) -> &str {
+        match *self {
+            Initiator::Cli(ref user) | Initiator::Api(ref user) => user,
+        }
+    }
+}
+
+/// A single wake request and its outcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    pub initiator: Initiator,
+    pub mac: String,
+    pub target: String,
+    /// `Ok` if the packet was sent, otherwise the error message.
+    pub outcome: Result<(), String>,
+}
+
+impl AuditEvent {
+    /// Converts the event into a record, stamped with the given time.
+    pub fn to_record(&self, time: SystemTime) -> Record {
+        Record::new()
+            .field("time", timestamp(time))
+            .field("initiator", self.initiator.kind())
+            .field("requested_by", self.initiator.name())
+            .field("mac", self.mac.as_str())
+            .field("target", self.target.as_str())
+            .field("outcome", if self.outcome.is_ok() { "ok" } else { "error" })
+            .field("error", self.outcome.as_ref().err().map(|e| e.as_str()))
+    }
+}
+
+/// An append-only audit log file with one JSON object per line.
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Opens the audit log, creating the file if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<AuditLog> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(AuditLog { file: Mutex::new(file) })
+    }
+
+    /// Appends an event, stamped with the current time.
+    pub fn record(&self, event: &AuditEvent) -> io::Result<()> {
+        let line = format!("{}\n", event.to_record(SystemTime::now()).to_json());
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::{AuditEvent, Initiator};
+
+    #[test]
+    fn test_event_to_json() {
+        let event = AuditEvent {
+            initiator: Initiator::Cli("felix".to_string()),
+            mac: "00:01:02:03:04:05".to_string(),
+            target: "255.255.255.255:0".to_string(),
+            outcome: Err("network unreachable".to_string()),
+        };
+        assert_eq!(event.to_record(UNIX_EPOCH).to_json(),
+                   "{\"time\":\"1970-01-01T00:00:00.000Z\",\"initiator\":\"cli\",\
+                    \"requested_by\":\"felix\",\"mac\":\"00:01:02:03:04:05\",\
+                    \"target\":\"255.255.255.255:0\",\"outcome\":\"error\",\
+                    \"error\":\"network unreachable\"}");
+    }
+}
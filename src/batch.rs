@@ -0,0 +1,929 @@
+//! Waking many hosts in parallel.
+//!
+//! `Batch` sends the magic packets and verifies that the hosts came up with a bounded number of
+//! threads, so that waiting for slow hosts does not hold up the others. The packets can be
+//! staggered, so that hundreds of machines do not power up in the same instant, and woken in the
+//! stages of a plan, see `Hosts::plan`. A batch can be cancelled, e.g. by ctrl-C, and then
+//! returns the results of the hosts woken so far. `Batch::wake_many` yields the result of each
+//! host as soon as it is done, for callers showing progress.
+//!
+//! Every host is sent to per its own transport: per IPv4 or IPv6, as its broadcast address has
+//! it, or through its relay, so that a group can mix hosts of different networks. The result of
+//! each host tells which transport it was woken by.
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use hosts::{Host, Ready};
+use output::{Record, Status, Value};
+use redact::Secret;
+use relay::{self, Relay};
+use strategy::{self, Path, Strategy};
+use subnet;
+use verify::{self, Prober};
+use {MagicPacket, SendReport, WolClient};
+
+/// The default number of hosts woken at the same time.
+pub const DEFAULT_CONCURRENCY: usize = 16;
+
+/// The number of hosts which may be woken without asking for confirmation.
+pub const CONFIRM_ABOVE: usize = 10;
+
+/// Returns whether the user should confirm waking `count` hosts, because these are more than
+/// `CONFIRM_ABOVE` or all hosts of the hosts file (`all`), which tend to grow unnoticed.
+pub fn needs_confirmation(count: usize, all: bool) -> bool {
+    count > CONFIRM_ABOVE || (all && count > 1)
+}
+
+/// How the magic packet of a host is sent, see `HostResult::transport`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    /// Per UDP over IPv4, e.g. to a directed broadcast address.
+    Ipv4,
+    /// Per UDP over IPv6, e.g. to a multicast group.
+    Ipv6,
+    /// Through the relay with the given URL, see `Host::relay`.
+    Relay(String),
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Transport::Ipv4 => f.write_str("ipv4"),
+            Transport::Ipv6 => f.write_str("ipv6"),
+            Transport::Relay(ref url) => write!(f, "relay {}", url),
+        }
+    }
+}
+
+/// The result of waking one host.
+#[derive(Debug)]
+pub struct HostResult {
+    /// The result of sending the magic packet, `None` if nothing was sent because the host was
+    /// already up, see `Batch::with_skip_up`, or skipped, see `Batch::with_skip`.
+    pub sent: Option<Result<SendReport, io::Error>>,
+    /// Whether the host came up, `None` if it has no IP address, was not verified or the batch
+    /// was cancelled while waiting for it.
+    pub verified: Option<bool>,
+    /// The time from sending until the host came up or the verification gave up.
+    pub elapsed: Duration,
+    /// The time from sending the magic packet until the host was verified to be up, `None`
+    /// unless it was.
+    pub boot_time: Option<Duration>,
+    /// The path last sent along before the host came up, `None` unless it was woken with
+    /// `Strategy::Auto`, see `Batch::with_strategy`.
+    pub path: Option<Path>,
+    /// How often the host was woken, more than once if it did not come up, see
+    /// `Batch::with_attempts`.
+    pub attempts: u32,
+    /// How the packet was sent, or would have been for hosts which were skipped.
+    pub transport: Transport,
+}
+
+impl HostResult {
+    /// Returns the status of the result as shown on a terminal.
+    pub fn status(&self) -> Status {
+        match (&self.sent, self.verified) {
+            (Some(Err(err)), _) if err.kind() == io::ErrorKind::TimedOut => Status::Timeout,
+            (Some(Err(_)), _) => Status::Fail,
+            (_, Some(false)) => Status::Timeout,
+            _ => Status::Ok,
+        }
+    }
+}
+
+/// The results of the hosts of `Batch::wake_many` by host name, in the order they are done.
+/// The iterator ends once all hosts are done or the batch was cancelled.
+#[derive(Debug)]
+pub struct WakeStream {
+    results: Receiver<(String, HostResult)>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Iterator for WakeStream {
+    type Item = (String, HostResult);
+
+    fn next(&mut self) -> Option<(String, HostResult)> {
+        self.results.recv().ok()
+    }
+}
+
+impl Drop for WakeStream {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Wakes a list of hosts in parallel.
+///
+/// #Example
+///
+/// ```
+/// use std::net::UdpSocket;
+/// use wol::batch::Batch;
+/// use wol::hosts::Host;
+/// use wol::{MacAddr, WolClient};
+///
+/// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let hosts: Vec<Host> = (1..=3)
+///     .map(|i| Host::new(&format!("pc{}", i), MacAddr::new([0, 0, 0, 0, 0, i])))
+///     .collect();
+/// let results = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+///     .with_concurrency(2)
+///     .run(&hosts);
+/// assert_eq!(results.len(), 3);
+/// assert!(results["pc2"].sent.as_ref().unwrap().is_ok());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Batch {
+    client: WolClient,
+    concurrency: usize,
+    password: Option<[u8; 6]>,
+    probe: (Vec<u16>, Duration),
+    method: verify::Method,
+    verify: bool,
+    attempts: u32,
+    skip_up: bool,
+    skip: Vec<String>,
+    strategy: Strategy,
+    stagger: Duration,
+    jitter: Duration,
+    cancel: Option<&'static AtomicBool>,
+    relay_token: Option<Secret<String>>,
+}
+
+impl Batch {
+    /// Creates a batch sending with the given client and without verification.
+    pub fn new(client: WolClient) -> Batch {
+        Batch {
+            client,
+            concurrency: DEFAULT_CONCURRENCY,
+            password: None,
+            probe: (vec![verify::DEFAULT_PORT], verify::DEFAULT_TIMEOUT),
+            method: verify::Method::Tcp,
+            verify: false,
+            attempts: 1,
+            skip_up: false,
+            skip: Vec::new(),
+            strategy: Strategy::Single,
+            stagger: Duration::from_secs(0),
+            jitter: Duration::from_secs(0),
+            cancel: None,
+            relay_token: None,
+        }
+    }
+
+    /// Wakes at most `concurrency` hosts at the same time, at least one.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Batch {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Appends a SecureOn password to the packets of the hosts without a password of their own.
+    pub fn with_password(mut self, password: [u8; 6]) -> Batch {
+        self.password = Some(password);
+        self
+    }
+
+    /// Verifies that hosts with an IP address come up by probing the given TCP ports in order,
+    /// waiting at most `timeout` per host. Hosts with ports of their own are probed on those.
+    pub fn with_verify(mut self, ports: &[u16], timeout: Duration) -> Batch {
+        self.probe = (ports.to_vec(), timeout);
+        self.verify = true;
+        self
+    }
+
+    /// Probes the given TCP ports, waiting at most `timeout`, for the hosts which are verified
+    /// although the batch does not verify, see `Ready::Verify`.
+    pub fn with_probe(mut self, ports: &[u16], timeout: Duration) -> Batch {
+        self.probe = (ports.to_vec(), timeout);
+        self
+    }
+
+    /// Probes hosts with the given method instead of connecting to the TCP port, e.g. with ICMP
+    /// for hosts without open ports.
+    pub fn with_method(mut self, method: verify::Method) -> Batch {
+        self.method = method;
+        self
+    }
+
+    /// Wakes the hosts which were verified but did not come up again, until they come up or
+    /// were woken `attempts` times in all, at least once. Hosts which could not be sent to or
+    /// verified are not woken again.
+    pub fn with_attempts(mut self, attempts: u32) -> Batch {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Probes the hosts with an IP address first and does not wake those which are already up,
+    /// so that running the batch twice changes nothing.
+    pub fn with_skip_up(mut self, skip_up: bool) -> Batch {
+        self.skip_up = skip_up;
+        self
+    }
+
+    /// Does not wake the hosts with the given names, e.g. those woken within their cooldown.
+    /// Their results have neither a packet sent nor a verification, and the later stages of a
+    /// plan are woken as if they were ready.
+    pub fn with_skip(mut self, names: &[String]) -> Batch {
+        self.skip = names.to_vec();
+        self
+    }
+
+    /// Sends the packets of hosts with an IP address along the paths of `strategy::paths` in turn
+    /// with `Strategy::Auto`, verifying after each for an equal share of the timeout, until the
+    /// host comes up. Hosts without IP address are sent to once.
+    pub fn with_strategy(mut self, strategy: Strategy) -> Batch {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sends the packet for the n-th host `stagger` after the one for the previous host, delayed
+    /// by a random time of up to `jitter`.
+    pub fn with_pacing(mut self, stagger: Duration, jitter: Duration) -> Batch {
+        self.stagger = stagger;
+        self.jitter = jitter;
+        self
+    }
+
+    /// Stops waking further hosts and waiting for hosts to come up as soon as `cancel` is set,
+    /// e.g. by `interrupt::catch_interrupt`. The hosts not woken are missing from the results.
+    pub fn with_cancel(mut self, cancel: &'static AtomicBool) -> Batch {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Authenticates to the relays of the hosts woken through one with the given token, see
+    /// `Relay::with_token`.
+    pub fn with_relay_token(mut self, token: &str) -> Batch {
+        self.relay_token = Some(Secret(token.to_string()));
+        self
+    }
+
+    /// Returns the transport the packet of `host` is sent per: its relay if it has one, or else
+    /// the IP version of its destination.
+    pub fn transport(&self, host: &Host) -> Transport {
+        match (&host.relay, host.client(&self.client).destination()) {
+            (Some(url), _) => Transport::Relay(url.clone()),
+            (None, SocketAddr::V4(_)) => Transport::Ipv4,
+            (None, SocketAddr::V6(_)) => Transport::Ipv6,
+        }
+    }
+
+    /// Returns whether the batch was cancelled.
+    pub fn cancelled(&self) -> bool {
+        self.cancel.is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+    }
+
+    /// Wakes all hosts and returns the results by host name.
+    pub fn run(&self, hosts: &[Host]) -> BTreeMap<String, HostResult> {
+        self.wake_many(hosts.to_vec()).collect()
+    }
+
+    /// Wakes all hosts in the background and returns their names and results in the order they
+    /// are done, so that a host which does not come up does not hold up the results of the
+    /// others. Dropping the stream wakes no further hosts; the hosts being woken at the time are
+    /// still sent to and verified, but their results are discarded.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use wol::batch::Batch;
+    /// use wol::hosts::Host;
+    /// use wol::{MacAddr, WolClient};
+    ///
+    /// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let hosts = vec![Host::new("pc", MacAddr::new([0, 1, 2, 3, 4, 5]))];
+    /// for (name, result) in Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+    ///     .wake_many(hosts) {
+    ///     println!("{}: {:?}", name, result.sent);
+    /// }
+    /// ```
+    pub fn wake_many(&self, hosts: Vec<Host>) -> WakeStream {
+        let (sender, results) = mpsc::channel();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let workers = self.concurrency.min(hosts.len());
+        let shared = Arc::new((self.clone(), hosts, AtomicUsize::new(0), Instant::now()));
+        for _ in 0..workers {
+            let (shared, sender, stopped) = (shared.clone(), sender.clone(), stopped.clone());
+            thread::spawn(move || {
+                let (ref batch, ref hosts, ref next, start) = *shared;
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let host = match hosts.get(i) {
+                        Some(host) => host,
+                        None => break,
+                    };
+                    let due = start + batch.stagger * i as u32 + batch.random_jitter();
+                    if !batch.sleep_until(due) || stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let result = batch.wake(host);
+                    if sender.send((host.name.clone(), result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        WakeStream { results, stopped }
+    }
+
+    /// Wakes the stages of a plan one after the other. A stage is woken once all hosts of the
+    /// previous stage are ready; if any of them could not be woken or did not come up, the later
+    /// stages are skipped and their hosts are missing from the results.
+    pub fn run_plan(&self, plan: &[Vec<Host>]) -> BTreeMap<String, HostResult> {
+        self.run_plan_with(plan, |_, _| {})
+    }
+
+    /// Wakes the stages of a plan like `run_plan`, calling `done` with the name and the result
+    /// of each host as soon as it is done, e.g. to show progress.
+    pub fn run_plan_with<F>(&self, plan: &[Vec<Host>], mut done: F) -> BTreeMap<String, HostResult>
+        where F: FnMut(&str, &HostResult)
+    {
+        let mut results = BTreeMap::new();
+        for stage in plan {
+            let mut failed = false;
+            for (name, result) in self.wake_many(stage.to_vec()) {
+                failed |= match result.sent {
+                    Some(Err(_)) => true,
+                    _ => result.verified == Some(false),
+                };
+                done(&name, &result);
+                results.insert(name, result);
+            }
+            if failed || self.cancelled() {
+                break;
+            }
+        }
+        results
+    }
+
+    /// Sleeps until `due` and returns whether the batch was not cancelled in the meantime.
+    fn sleep_until(&self, due: Instant) -> bool {
+        const STEP: Duration = Duration::from_millis(100);
+        loop {
+            if self.cancelled() {
+                return false;
+            }
+            match due.checked_duration_since(Instant::now()) {
+                Some(wait) if wait > Duration::from_secs(0) => thread::sleep(wait.min(STEP)),
+                _ => return true,
+            }
+        }
+    }
+
+    /// Returns a random duration up to the jitter.
+    fn random_jitter(&self) -> Duration {
+        let nanos = self.jitter.as_nanos() as u64;
+        if nanos == 0 {
+            return Duration::from_secs(0);
+        }
+        // The hasher is seeded randomly, which is good enough to spread the packets.
+        let random = RandomState::new().build_hasher().finish();
+        Duration::from_nanos(random % (nanos + 1))
+    }
+
+    /// Returns the ports the host is probed on.
+    fn ports<'a>(&'a self, host: &'a Host) -> &'a [u16] {
+        if host.verify_ports.is_empty() {
+            &self.probe.0
+        } else {
+            &host.verify_ports
+        }
+    }
+
+    /// Probes once whether the host is up, with its probe command or at its IP address, false
+    /// for hosts with neither.
+    fn is_up(&self, host: &Host) -> bool {
+        match (&host.probe, host.ip) {
+            (Some(probe), _) => probe.is_up(),
+            (None, Some(ip)) => self.method.is_up_on(ip, self.ports(host)),
+            (None, None) => false,
+        }
+    }
+
+    /// Probes the host like `is_up` until it is up or `timeout` has passed, or with
+    /// `Method::Dhcp` waits until it booted, also without IP address. Returns whether it came
+    /// up, or `None` if it cannot be probed or the batch was cancelled.
+    fn wait_until_up(&self, host: &Host, timeout: Duration) -> Option<bool> {
+        let never = AtomicBool::new(false);
+        let cancel = self.cancel.unwrap_or(&never);
+        match (&host.probe, host.ip) {
+            (Some(probe), _) => verify::wait_for(probe, timeout, cancel),
+            (None, _) if self.method == verify::Method::Dhcp => {
+                verify::wait_until_booted(host.mac, timeout, cancel)
+            },
+            (None, Some(ip)) => {
+                verify::wait_until_up(self.method, ip, self.ports(host), timeout, cancel)
+            },
+            (None, None) => None,
+        }
+    }
+
+    /// Wakes the host once or, if it did not come up, up to the attempts of the batch. The
+    /// elapsed time of the result is that since the first attempt.
+    fn wake(&self, host: &Host) -> HostResult {
+        let start = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let mut result = self.wake_once(host);
+            result.attempts = attempt;
+            if result.verified != Some(false) || attempt >= self.attempts || self.cancelled() {
+                result.elapsed = start.elapsed();
+                return result;
+            }
+            attempt += 1;
+            debug!("{} did not come up, waking it again (attempt {} of {})",
+                   host.name,
+                   attempt,
+                   self.attempts);
+        }
+    }
+
+    fn wake_once(&self, host: &Host) -> HostResult {
+        let mut packet = host.packet();
+        if let (None, Some(password)) = (host.password, self.password) {
+            packet = packet.with_password(password);
+        }
+        let start = Instant::now();
+        if self.skip.contains(&host.name) {
+            return HostResult {
+                sent: None,
+                verified: None,
+                elapsed: start.elapsed(),
+                boot_time: None,
+                path: None,
+                attempts: 1,
+                transport: self.transport(host),
+            };
+        }
+        if self.skip_up && self.is_up(host) {
+            return HostResult {
+                sent: None,
+                verified: Some(true),
+                elapsed: start.elapsed(),
+                boot_time: None,
+                path: None,
+                attempts: 1,
+                transport: self.transport(host),
+            };
+        }
+        if let Some(ref url) = host.relay {
+            return self.wake_via_relay(host, url, packet.password().is_some(), start);
+        }
+        if let (Strategy::Auto, Some(ip)) = (self.strategy, host.ip) {
+            return self.wake_along_paths(host, &packet, ip, start);
+        }
+        let sent_at = Instant::now();
+        let sent = host.client(&self.client).send(&packet);
+        // Hosts which others wait for are verified even if the batch does not verify.
+        let verify = self.verify || host.ready == Ready::Verify;
+        let verified = match sent {
+            Ok(_) if verify => self.wait_until_up(host, self.probe.1),
+            _ => None,
+        };
+        let boot_time = if verified == Some(true) { Some(sent_at.elapsed()) } else { None };
+        let elapsed = start.elapsed();
+        if let (Ok(_), Ready::Delay(delay)) = (&sent, host.ready) {
+            self.sleep_until(Instant::now() + delay);
+        }
+        HostResult {
+            sent: Some(sent),
+            verified,
+            elapsed,
+            boot_time,
+            path: None,
+            attempts: 1,
+            transport: self.transport(host),
+        }
+    }
+
+    /// Asks the relay at `url` to wake the host and, if it is verified, to wait until it is up,
+    /// as it may not be reachable from here. Relays cannot send SecureOn passwords.
+    fn wake_via_relay(&self, host: &Host, url: &str, password: bool, start: Instant) -> HostResult {
+        let verify = self.verify || host.ready == Ready::Verify;
+        let woken = Relay::new(url).and_then(|mut relay| {
+            if password {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "relays cannot send SecureOn passwords"));
+            }
+            if let Some(ref token) = self.relay_token {
+                relay = relay.with_token(&token.0);
+            }
+            let ip = host.ip.filter(|_| verify && relay.can_verify());
+            if ip.is_some() {
+                // The relay answers once the host is up, which takes up to the timeout.
+                relay = relay.with_timeout(self.probe.1 + relay::DEFAULT_TIMEOUT);
+            }
+            relay.wake(host.mac, ip)
+        });
+        let (sent, verified, boot_time) = match woken {
+            Ok(ref record) => relay_result(record),
+            Err(err) => (Err(err), None, None),
+        };
+        if let Err(ref err) = sent {
+            debug!("cannot wake {} via {}: {}", host.name, url, err);
+        }
+        let elapsed = start.elapsed();
+        if let (Ok(_), Ready::Delay(delay)) = (&sent, host.ready) {
+            self.sleep_until(Instant::now() + delay);
+        }
+        HostResult {
+            sent: Some(sent),
+            verified,
+            elapsed,
+            boot_time,
+            path: None,
+            attempts: 1,
+            transport: Transport::Relay(url.to_string()),
+        }
+    }
+
+    /// Sends along one path after the other until the host at `ip` comes up. The result holds
+    /// the report of the last datagram sent, or the last error if nothing could be sent.
+    fn wake_along_paths(&self,
+                        host: &Host,
+                        packet: &MagicPacket,
+                        ip: IpAddr,
+                        start: Instant)
+                        -> HostResult {
+        let networks = subnet::connected_networks().unwrap_or_else(|err| {
+            debug!("cannot read the connected networks, not sending directed broadcasts: {}",
+                   err);
+            Vec::new()
+        });
+        let paths = strategy::paths(ip, &networks);
+        let timeout = self.probe.1 / paths.len() as u32;
+        let client = host.client(&self.client);
+        let sent_at = Instant::now();
+        let mut sent = None;
+        let mut verified = None;
+        let mut up_after = None;
+        for path in paths {
+            match path.send(&client, packet) {
+                Ok(report) => {
+                    debug!("sent the packet for {} as {}", host.name, path);
+                    if let Some(report) = report {
+                        sent = Some(Ok(report));
+                    }
+                },
+                Err(err) => {
+                    debug!("cannot send the packet for {} as {}: {}", host.name, path, err);
+                    if !matches!(sent, Some(Ok(_))) {
+                        sent = Some(Err(err));
+                    }
+                    continue;
+                },
+            }
+            verified = self.wait_until_up(host, timeout);
+            match verified {
+                Some(true) => {
+                    up_after = Some(path);
+                    break;
+                },
+                None => break,
+                Some(false) => {},
+            }
+        }
+        // Without any datagram sent, e.g. if only the raw frame went out, the sending failed.
+        let sent = sent.unwrap_or_else(|| Err(io::Error::other("no path to send along")));
+        if sent.is_err() {
+            verified = None;
+        }
+        let boot_time = if verified == Some(true) { Some(sent_at.elapsed()) } else { None };
+        let elapsed = start.elapsed();
+        if let (Ok(_), Ready::Delay(delay)) = (&sent, host.ready) {
+            self.sleep_until(Instant::now() + delay);
+        }
+        HostResult {
+            sent: Some(sent),
+            verified,
+            elapsed,
+            boot_time,
+            path: up_after,
+            attempts: 1,
+            transport: self.transport(host),
+        }
+    }
+}
+
+/// Returns the report of sending, the verification and the boot time of the record a relay
+/// answered with, see `Relay::wake`. The report has the addresses the relay sent from and to.
+fn relay_result(record: &Record) -> (io::Result<SendReport>, Option<bool>, Option<Duration>) {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid answer from the relay");
+    let address = |name: &str| match record.get(name) {
+        Some(Value::Str(address)) => address.parse::<SocketAddr>().map_err(|_| invalid()),
+        _ => Err(invalid()),
+    };
+    if let Some(Value::Str(error)) = record.get("error") {
+        return (Err(io::Error::other(error.clone())), None, None);
+    }
+    let report = address("target").and_then(|destination| {
+        Ok(SendReport {
+            bytes_sent: match record.get("bytes") {
+                Some(&Value::Int(bytes)) => bytes as usize,
+                _ => 0,
+            },
+            destination,
+            local_addr: address("local")?,
+            timestamp: SystemTime::now(),
+        })
+    });
+    let verified = match record.get("verified") {
+        Some(&Value::Bool(verified)) => Some(verified),
+        _ => None,
+    };
+    let boot_time = match record.get("boot_ms") {
+        Some(&Value::Float(ms)) => Duration::try_from_secs_f64(ms / 1e3).ok(),
+        Some(&Value::Int(ms)) => Some(Duration::from_millis(ms)),
+        _ => None,
+    };
+    (report, verified, boot_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, UdpSocket};
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{needs_confirmation, relay_result, Batch, Transport, CONFIRM_ABOVE};
+    use hosts::{Host, Ready};
+    use output::Record;
+    use strategy::Strategy;
+    use verify::CommandProbe;
+    use {MacAddr, WolClient};
+
+    #[test]
+    fn test_run() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = |name: &str, ip| {
+            Host {
+                ip,
+                ..Host::new(name, MacAddr::new([0, 1, 2, 3, 4, 5]))
+            }
+        };
+        let hosts = vec![host("up", Some("127.0.0.1".parse().unwrap())), host("unknown", None)];
+        let results = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_verify(&[listener.local_addr().unwrap().port()], Duration::from_secs(1))
+            .run(&hosts);
+        assert_eq!(results["up"].verified, Some(true));
+        assert!(results["up"].boot_time.unwrap() <= results["up"].elapsed);
+        assert_eq!(results["unknown"].verified, None);
+        assert_eq!(results["unknown"].boot_time, None);
+        assert_eq!(results["unknown"].sent.as_ref().unwrap().as_ref().unwrap().bytes_sent, 102);
+
+        let results = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_verify(&[listener.local_addr().unwrap().port()], Duration::from_secs(1))
+            .with_skip_up(true)
+            .run(&hosts);
+        assert!(results["up"].sent.is_none());
+        assert_eq!(results["up"].verified, Some(true));
+        assert_eq!(results["up"].boot_time, None);
+        assert!(results["unknown"].sent.is_some());
+
+        // The ports of a host replace those of the batch.
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut windows = host("windows", Some("127.0.0.1".parse().unwrap()));
+        windows.verify_ports = vec![closed, listener.local_addr().unwrap().port()];
+        let results = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_verify(&[closed], Duration::from_millis(10))
+            .run(&[windows]);
+        assert_eq!(results["windows"].verified, Some(true));
+
+        // A probe command verifies hosts even without IP address.
+        let nas = Host {
+            probe: Some(CommandProbe::new("exit 0")),
+            ..host("nas", None)
+        };
+        let results = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_verify(&[closed], Duration::from_secs(1))
+            .run(&[nas]);
+        assert_eq!(results["nas"].verified, Some(true));
+    }
+
+    #[test]
+    fn test_attempts() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let host = |name: &str, cmd| {
+            Host {
+                probe: Some(CommandProbe::new(cmd)),
+                ..Host::new(name, MacAddr::new([0, 1, 2, 3, 4, 5]))
+            }
+        };
+        let unknown = Host::new("unknown", MacAddr::new([0, 1, 2, 3, 4, 6]));
+        let hosts = vec![host("up", "exit 0"), host("down", "exit 1"), unknown];
+        let results = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_verify(&[9], Duration::from_millis(10))
+            .with_attempts(3)
+            .run(&hosts);
+        assert_eq!((results["up"].verified, results["up"].attempts), (Some(true), 1));
+        assert_eq!((results["down"].verified, results["down"].attempts), (Some(false), 3));
+        assert_eq!((results["unknown"].verified, results["unknown"].attempts), (None, 1));
+        // Only the host which did not come up was woken again.
+        let mut buf = [0; 102];
+        receiver.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        for _ in 0..5 {
+            receiver.recv(&mut buf).unwrap();
+        }
+        receiver.set_nonblocking(true).unwrap();
+        assert!(receiver.recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_host_send_options() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let other = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let printer = Host {
+            port: Some(other.local_addr().unwrap().port()),
+            password: Some([1; 6]),
+            ..Host::new("printer", MacAddr::new([0, 1, 2, 3, 4, 5]))
+        };
+        let hosts = vec![printer, Host::new("pc", MacAddr::new([0, 1, 2, 3, 4, 6]))];
+        let results = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_password([2; 6])
+            .run(&hosts);
+        let sent = |name: &str| *results[name].sent.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(sent("printer").destination, other.local_addr().unwrap());
+        assert_eq!(sent("pc").destination, receiver.local_addr().unwrap());
+        let mut buf = [0; 200];
+        assert_eq!(other.recv(&mut buf).unwrap(), 108);
+        assert_eq!(&buf[102..108], &[1; 6]);
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 108);
+        assert_eq!(&buf[102..108], &[2; 6]);
+    }
+
+    #[test]
+    fn test_mixed_transports() {
+        let v4 = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let v6 = UdpSocket::bind("[::1]:0").unwrap();
+        let relay = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let host = |name: &str, last| Host::new(name, MacAddr::new([0, 1, 2, 3, 4, last]));
+        let hosts = vec![host("nas", 1),
+                         Host {
+                             broadcast: Some(v6.local_addr().unwrap().ip()),
+                             port: Some(v6.local_addr().unwrap().port()),
+                             ..host("printer", 2)
+                         },
+                         Host {
+                             relay: Some(format!("udp://{}", relay.local_addr().unwrap())),
+                             ..host("branch-pc", 3)
+                         }];
+        let batch = Batch::new(WolClient::new(v4.local_addr().unwrap())).with_relay_token("s3cret");
+        let results = batch.run(&hosts);
+        assert_eq!(results["nas"].transport, Transport::Ipv4);
+        assert_eq!(results["printer"].transport, Transport::Ipv6);
+        let url = format!("udp://{}", relay.local_addr().unwrap());
+        assert_eq!(results["branch-pc"].transport, Transport::Relay(url.clone()));
+        assert_eq!(results["branch-pc"].transport.to_string(), format!("relay {}", url));
+        let sent = |name: &str| *results[name].sent.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(sent("branch-pc").destination, relay.local_addr().unwrap());
+        let mut buf = [0; 200];
+        assert_eq!(v4.recv(&mut buf).unwrap(), 102);
+        assert_eq!(v6.recv(&mut buf).unwrap(), 102);
+        assert!(relay.recv(&mut buf).is_ok());
+        // Relays only get the MAC address.
+        let results = batch.with_password([1; 6]).run(&hosts[2..]);
+        assert!(results["branch-pc"].sent.as_ref().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_relay_result() {
+        let record: Record = "{\"mac\":\"00:01:02:03:04:05\",\"target\":\"10.0.2.255:9\",\
+                              \"local\":\"10.0.2.2:40000\",\"bytes\":102,\"verified\":true,\
+                              \"boot_ms\":1500.0,\"error\":null}"
+            .parse()
+            .unwrap();
+        let (sent, verified, boot_time) = relay_result(&record);
+        let sent = sent.unwrap();
+        assert_eq!((sent.bytes_sent, sent.destination), (102, "10.0.2.255:9".parse().unwrap()));
+        assert_eq!((verified, boot_time), (Some(true), Some(Duration::from_millis(1500))));
+        let record: Record = "{\"target\":\"10.0.2.255:9\",\"error\":\"network unreachable\"}"
+            .parse()
+            .unwrap();
+        assert_eq!(relay_result(&record).0.unwrap_err().to_string(), "network unreachable");
+    }
+
+    #[test]
+    fn test_pacing() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let hosts: Vec<Host> =
+            (0..3).map(|i| Host::new(&i.to_string(), MacAddr::new([0, 0, 0, 0, 0, i]))).collect();
+        let start = Instant::now();
+        let results = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_pacing(Duration::from_millis(50), Duration::from_millis(10))
+            .run(&hosts);
+        assert_eq!(results.len(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_run_plan() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let host = |name: &str, ready| {
+            Host {
+                ip: Some("127.0.0.1".parse().unwrap()),
+                ready,
+                ..Host::new(name, MacAddr::new([0, 1, 2, 3, 4, 5]))
+            }
+        };
+        let router = Host {
+            ready: Ready::Delay(Duration::from_millis(10)),
+            ..Host::new("router", MacAddr::new([0, 1, 2, 3, 4, 6]))
+        };
+        let plan = vec![vec![router],
+                        vec![host("nas", Ready::Verify)],
+                        vec![host("vm", Ready::Sent)]];
+        let results = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_verify(&[closed], Duration::from_millis(10))
+            .run_plan(&plan);
+        assert_eq!(results["router"].verified, None);
+        assert_eq!(results["nas"].verified, Some(false));
+        assert!(!results.contains_key("vm"));
+
+        let results = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_verify(&[closed], Duration::from_millis(10))
+            .with_skip(&["nas".to_string()])
+            .run_plan(&plan);
+        assert!(results["nas"].sent.is_none());
+        assert_eq!(results["nas"].verified, None);
+        assert!(results["vm"].sent.is_some());
+    }
+
+    #[test]
+    fn test_strategy() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let hosts = vec![Host {
+                             ip: Some("127.0.0.1".parse().unwrap()),
+                             ..Host::new("pc", MacAddr::new([0, 1, 2, 3, 4, 5]))
+                         }];
+        let batch = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_strategy(Strategy::Auto);
+        let results = batch.clone()
+            .with_verify(&[listener.local_addr().unwrap().port()], Duration::from_secs(1))
+            .run(&hosts);
+        assert_eq!(results["pc"].verified, Some(true));
+        assert!(results["pc"].path.is_some());
+
+        // Every path is tried, the last one by unicast to the receiver.
+        let results = batch.with_verify(&[closed], Duration::from_millis(20)).run(&hosts);
+        assert_eq!(results["pc"].verified, Some(false));
+        assert_eq!(results["pc"].path, None);
+        let mut buf = [0; 200];
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+    }
+
+    #[test]
+    fn test_wake_many() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let hosts: Vec<Host> =
+            (0..3).map(|i| Host::new(&i.to_string(), MacAddr::new([0, 0, 0, 0, 0, i]))).collect();
+        let batch = Batch::new(WolClient::new(receiver.local_addr().unwrap()))
+            .with_concurrency(1)
+            .with_pacing(Duration::from_millis(100), Duration::from_secs(0));
+        let mut results = batch.wake_many(hosts);
+        let (name, result) = results.next().unwrap();
+        assert_eq!(name, "0");
+        assert!(result.sent.unwrap().is_ok());
+
+        // No further host is woken once the stream is dropped.
+        drop(results);
+        thread::sleep(Duration::from_millis(300));
+        receiver.set_nonblocking(true).unwrap();
+        let mut buf = [0; 200];
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+        assert!(receiver.recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_cancel() {
+        static CANCEL: AtomicBool = AtomicBool::new(true);
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let hosts = vec![Host::new("pc", MacAddr::new([0, 1, 2, 3, 4, 5]))];
+        let batch = Batch::new(WolClient::new(receiver.local_addr().unwrap())).with_cancel(&CANCEL);
+        assert!(batch.cancelled());
+        assert!(batch.run(&hosts).is_empty());
+    }
+
+    #[test]
+    fn test_needs_confirmation() {
+        assert!(!needs_confirmation(CONFIRM_ABOVE, false));
+        assert!(needs_confirmation(CONFIRM_ABOVE + 1, false));
+        assert!(needs_confirmation(2, true));
+        assert!(!needs_confirmation(1, true));
+    }
+}
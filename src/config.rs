@@ -0,0 +1,1006 @@
+//! Global defaults read from configuration files and environment variables.
+//!
+//! The defaults are read from `/etc/wol.toml` and `~/.config/wol/config.toml`, or the locations
+//! of the platform, see `paths`, where the values of the latter take precedence. Command line
+//! flags override both, while the `WOL_*` environment variables have the lowest precedence. The
+//! files use a subset of TOML: tables, comments and keys with string, integer or boolean values.
+//!
+//! ```toml
+//! broadcast = "192.168.1.255"
+//! multicast_group = "ff05::1:3"
+//! port = [7, 9]
+//! repeat = 3
+//! interface = "eth0"
+//! check_link = true
+//! netns = "blue"
+//! source = "10.8.0.2"
+//! source_port = 40000
+//! ttl = 4
+//! dscp = "ef"
+//! dual_stack = true
+//! hosts_file = "/etc/wol/hosts.toml"
+//! audit_log = "/var/log/wol/audit.log"
+//! mac_check = "reject"
+//! cooldown = "5m"
+//! addressing = "auto"
+//!
+//! [verify]
+//! port = [22, 3389]
+//! timeout = 60
+//! method = "tcp"
+//!
+//! [relay]
+//! token = "s3cret"
+//! drain_timeout = "30s"
+//!
+//! [tokens]
+//! guest = { token = "g4est", hosts = ["media-pc"], scope = "wake" }
+//!
+//! [resolve]
+//! order = ["hosts", "arp", "leases", "dns"]
+//! leases = "/var/lib/misc/dnsmasq.leases"
+//!
+//! [switch]
+//! address = "10.0.0.2"
+//! community = "public"
+//! ```
+//!
+//! `multicast_group` sends per IPv6 to a multicast group instead of the broadcast address, e.g.
+//! a site-local group which the routers forward to the networks of the hosts; such groups need a
+//! `ttl` above 1. Both are set together: a file or flag setting one of them replaces both of the
+//! files read before.
+//! `audit_log = true` writes the audit log to its default location, see `Paths::audit_log`.
+//! Within the `cooldown` after a wake, further wakes of the host are skipped, unless the host has
+//! a cooldown of its own, see `hosts`. `addressing` chooses where the packets of hosts with an IP
+//! address are sent, see `subnet::Addressing`.
+//! `dscp` tags the packets with a class, a number or a name like "ef", by which managed switches
+//! and routers prioritize them, and `mark` sets the mark policy routing matches, see
+//! `WolClient::with_dscp` and `WolClient::with_mark`.
+//! The relay token is required by `wol serve` from its clients and sent by `wol wake --via`,
+//! and `drain_timeout` is how long `wol serve` waits for the requests in progress when it shuts
+//! down; the `[tokens]` table adds tokens limited to some hosts or to waking them, see `tokens`.
+//! The `[resolve]` table chooses how targets which are no MAC address are resolved, see
+//! `resolve::Source`. `wol wake --diagnose` asks the switch of the `[switch]` table per SNMP on
+//! which port it saw a host, with the `snmp` feature.
+//! The tokens and the community can be referenced in the keyring of the system or by a command
+//! printing them instead of being given, e.g. `token = { keyring = "relay" }`, see `secrets`.
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use hosts;
+use paths::Paths;
+use redact::{self, REDACTED};
+use resolve::{self, Source};
+use subnet::Addressing;
+use tokens::{ApiToken, Scope};
+use toml;
+use transport::{Bursts, Dscp, MulticastGroup};
+use units::{self, Port, RepeatCount};
+use verify;
+use WolClient;
+
+/// The configuration file of the system on Unix systems, see `Paths::system_config`.
+pub const SYSTEM_PATH: &str = "/etc/wol.toml";
+
+/// The configuration file written by `wol init`, with every setting commented out at its
+/// default or an example value.
+pub const TEMPLATE: &str = "\
+# The configuration of wol. Remove the # in front of a setting to change it; the options of
+# the command line override these settings. `wol config show` prints the effective ones.
+
+# Where magic packets are sent: the broadcast address of the network of the hosts, the UDP
+# ports, each packet to all of them (default: 0), and how often every packet is sent.
+#broadcast = \"255.255.255.255\"
+#port = [7, 9]
+#repeat = 1
+
+# Send the repetitions of every packet COUNT times, GAP apart, e.g. for routers which drop the
+# first packets to a sleeping host while they look up its MAC address.
+#burst = \"3x2s\"
+
+# Or an IPv6 multicast group instead of the broadcast address, e.g. a site-local one which the
+# routers forward to the networks of the hosts; it also needs a ttl above 1.
+#multicast_group = \"ff05::1:3\"
+
+# The network interface (Linux only), the address of this machine and the UDP port to send
+# from, and the hops packets may take through routers.
+#interface = \"eth0\"
+#source = \"192.168.1.2\"
+#source_port = 40000
+#ttl = 1
+
+# Fail before sending if the interface is down or has no carrier, e.g. an unplugged cable,
+# instead of sending into a link which drops the packets.
+#check_link = false
+
+# The network namespace to send from (Linux only), a name of `ip netns` or the path of a
+# namespace file like /proc/1234/ns/net.
+#netns = \"blue\"
+
+# The class, a number or a name like \"ef\", by which managed switches and routers prioritize the
+# packets, and the mark which policy routing matches (both Linux only).
+#dscp = \"ef\"
+#mark = 7
+
+# Send every packet per IPv4 and IPv6.
+#dual_stack = false
+
+# The hosts file with the names of the hosts, see `wol init` and `wol hosts check`.
+#hosts_file = \"/etc/wol/hosts.toml\"
+
+# Log every wake, to the default location with true or to a file.
+#audit_log = true
+
+# What to do about MAC addresses no network card can have: ignore, warn or reject.
+#mac_check = \"warn\"
+
+# Skip further wakes of a host within this time after a wake.
+#cooldown = \"5m\"
+
+# Where the packets of hosts with an IP address are sent: auto, broadcast or unicast.
+#addressing = \"auto\"
+
+# How --verify and --wait find out whether a host came up: the TCP ports tried in order, how
+# long to wait in seconds, and the method, tcp, icmp, arp or dhcp.
+#[verify]
+#port = [22, 3389]
+#timeout = 60
+#method = \"tcp\"
+
+# The token `wol serve` requires from its clients and `wol wake --via` sends, and how long
+# `wol serve` waits for the requests in progress when it shuts down.
+#[relay]
+#token = \"s3cret\"
+#drain_timeout = \"30s\"
+
+# Further tokens of `wol serve`, each limited to some hosts, and to waking them unless its scope
+# is admin, which allows reloading the daemon as well.
+#[tokens]
+#guest = { token = \"g4est\", hosts = [\"media-pc\"], scope = \"wake\" }
+
+# How targets which are no MAC address are found: in the hosts file, the neighbor table, the
+# leases of the DHCP server or the DNS.
+#[resolve]
+#order = [\"hosts\", \"arp\", \"leases\", \"dns\"]
+#leases = \"/var/lib/misc/dnsmasq.leases\"
+
+# The managed switch --diagnose asks per SNMP on which port it saw a host (snmp feature).
+#[switch]
+#address = \"10.0.0.2\"
+#community = \"public\"
+";
+
+/// Defaults for sending and verifying magic packets. Unset values fall back to the built-in
+/// defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Config {
+    /// The broadcast or multicast address to send to.
+    pub broadcast: Option<IpAddr>,
+    /// The IPv6 multicast group to send to instead of the broadcast address.
+    pub multicast_group: Option<MulticastGroup>,
+    /// The UDP ports to send to, each packet to all of them.
+    pub ports: Option<Vec<u16>>,
+    /// How often to send every packet.
+    pub repeat: Option<u32>,
+    /// How often the repetitions are sent, and how far apart.
+    pub bursts: Option<Bursts>,
+    /// The network interface to send through.
+    pub interface: Option<String>,
+    /// Whether to check the link of the interface before sending, see
+    /// `WolClient::with_link_check`.
+    pub check_link: Option<bool>,
+    /// The network namespace to send from, a name or the path of a namespace file.
+    pub netns: Option<String>,
+    /// The address to send from, e.g. that of a VPN interface.
+    pub source: Option<IpAddr>,
+    /// The UDP port to send from, for firewalls which only let known source ports out.
+    pub source_port: Option<u16>,
+    /// The TTL or IPv6 hop limit to send with.
+    pub ttl: Option<u32>,
+    /// The DSCP to tag packets with.
+    pub dscp: Option<Dscp>,
+    /// The mark of the packets, for policy routing.
+    pub mark: Option<u32>,
+    /// Whether to send per IPv4 and IPv6, see `WolClient::with_dual_stack`.
+    pub dual_stack: Option<bool>,
+    /// The TCP ports probed in order to verify that a host is up.
+    pub verify_ports: Option<Vec<u16>>,
+    /// How long to wait for a host to come up.
+    pub verify_timeout: Option<Duration>,
+    /// How to probe whether a host is up.
+    pub verify_method: Option<verify::Method>,
+    /// The file with the known hosts.
+    pub hosts_file: Option<PathBuf>,
+    /// The file to append a record of every wake to.
+    pub audit_log: Option<PathBuf>,
+    /// What to do about MAC addresses which are most likely typos.
+    pub mac_check: Option<MacCheck>,
+    /// How long after a wake further wakes of the same host are skipped.
+    pub cooldown: Option<Duration>,
+    /// Where to send the packets of hosts with an IP address.
+    pub addressing: Option<Addressing>,
+    /// The bearer token of the REST API of the daemon.
+    pub relay_token: Option<String>,
+    /// How long the daemon waits for the requests in progress when it shuts down.
+    pub drain_timeout: Option<Duration>,
+    /// The tokens of the REST API of the daemon besides the relay token, in the order given.
+    pub api_tokens: Option<Vec<ApiToken>>,
+    /// The resolvers of targets in the order they are asked.
+    pub resolve_order: Option<Vec<Source>>,
+    /// The leases file of the DHCP server for `Source::Leases`.
+    pub leases_file: Option<PathBuf>,
+    /// The managed switch asked per SNMP by `--diagnose`, a host with an optional port.
+    pub switch: Option<String>,
+    /// The SNMP community of the switch.
+    pub switch_community: Option<String>,
+}
+
+impl Config {
+    /// Returns the configuration files in the order they are read.
+    pub fn default_paths() -> Vec<PathBuf> {
+        Paths::new().config_files()
+    }
+
+    /// Reads a configuration file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        content.parse().map_err(|err: ConfigError| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}", path.display(), err))
+        })
+    }
+
+    /// Reads and merges all existing files of `default_paths()`.
+    pub fn load_default() -> io::Result<Config> {
+        Config::load_all(Config::default_paths())
+    }
+
+    /// Reads and merges the given files which exist, later ones taking precedence.
+    pub fn load_all<I>(paths: I) -> io::Result<Config>
+        where I: IntoIterator<Item = PathBuf>
+    {
+        let mut config = Config::default();
+        for path in paths {
+            match Config::load(&path) {
+                Ok(file) => {
+                    debug!("read configuration from {}", path.display());
+                    config = config.merge(file);
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => {},
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Reads the configuration from the environment variables `WOL_BROADCAST`, `WOL_PORT`,
+    /// `WOL_INTERFACE`, `WOL_HOSTS_FILE` and `WOL_RELAY_TOKEN`. They have the lowest precedence,
+    /// below the configuration files.
+    pub fn from_env() -> io::Result<Config> {
+        Config::from_vars(env::vars_os().filter_map(|(k, v)| Some((k.into_string().ok()?, v))))
+    }
+
+    /// Reads the configuration from the given `WOL_*` variables, ignoring all others.
+    pub fn from_vars<I>(vars: I) -> io::Result<Config>
+        where I: IntoIterator<Item = (String, OsString)>
+    {
+        fn parse<T: FromStr>(name: &str, value: &OsStr) -> io::Result<T> {
+            parse_with(name, value, |s| s.parse().ok())
+        }
+
+        fn parse_with<T, F>(name: &str, value: &OsStr, parse: F) -> io::Result<T>
+            where F: FnOnce(&str) -> Option<T>
+        {
+            value.to_str().and_then(parse).ok_or_else(|| {
+                let message = format!("invalid {} '{}'", name, value.to_string_lossy());
+                io::Error::new(io::ErrorKind::InvalidInput, message)
+            })
+        }
+
+        let mut config = Config::default();
+        for (name, value) in vars {
+            match name.as_str() {
+                "WOL_BROADCAST" => config.broadcast = Some(parse(&name, &value)?),
+                "WOL_PORT" => {
+                    config.ports =
+                        Some(parse_with(&name, &value, |s| Port::parse_list(s).ok())?)
+                },
+                "WOL_INTERFACE" => config.interface = Some(parse(&name, &value)?),
+                "WOL_HOSTS_FILE" => config.hosts_file = Some(PathBuf::from(value)),
+                "WOL_RELAY_TOKEN" => config.relay_token = Some(parse(&name, &value)?),
+                _ => {},
+            }
+        }
+        Ok(config)
+    }
+
+    /// Returns the configured hosts file or the default.
+    pub fn hosts_file(&self) -> PathBuf {
+        self.hosts_file.clone().unwrap_or_else(hosts::default_path)
+    }
+
+    /// Returns this configuration with all values set in `other` replaced.
+    pub fn merge(self, other: Config) -> Config {
+        // The broadcast address and the multicast group are one destination.
+        let (broadcast, multicast_group) =
+            if other.broadcast.is_some() || other.multicast_group.is_some() {
+                (other.broadcast, other.multicast_group)
+            } else {
+                (self.broadcast, self.multicast_group)
+            };
+        Config {
+            broadcast,
+            multicast_group,
+            ports: other.ports.or(self.ports),
+            repeat: other.repeat.or(self.repeat),
+            bursts: other.bursts.or(self.bursts),
+            interface: other.interface.or(self.interface),
+            check_link: other.check_link.or(self.check_link),
+            netns: other.netns.or(self.netns),
+            source: other.source.or(self.source),
+            source_port: other.source_port.or(self.source_port),
+            ttl: other.ttl.or(self.ttl),
+            dscp: other.dscp.or(self.dscp),
+            mark: other.mark.or(self.mark),
+            dual_stack: other.dual_stack.or(self.dual_stack),
+            verify_ports: other.verify_ports.or(self.verify_ports),
+            verify_timeout: other.verify_timeout.or(self.verify_timeout),
+            verify_method: other.verify_method.or(self.verify_method),
+            hosts_file: other.hosts_file.or(self.hosts_file),
+            audit_log: other.audit_log.or(self.audit_log),
+            mac_check: other.mac_check.or(self.mac_check),
+            cooldown: other.cooldown.or(self.cooldown),
+            addressing: other.addressing.or(self.addressing),
+            relay_token: other.relay_token.or(self.relay_token),
+            drain_timeout: other.drain_timeout.or(self.drain_timeout),
+            api_tokens: other.api_tokens.or(self.api_tokens),
+            resolve_order: other.resolve_order.or(self.resolve_order),
+            leases_file: other.leases_file.or(self.leases_file),
+            switch: other.switch.or(self.switch),
+            switch_community: other.switch_community.or(self.switch_community),
+        }
+    }
+
+    /// Creates a client sending per IPv4 or IPv6 with the configured values. A configured
+    /// broadcast address determines the address family on its own; with dual stack, the client
+    /// sends per IPv4 and to the IPv6 all-nodes multicast group.
+    pub fn client(&self, use_v4: bool) -> WolClient {
+        let dual_stack = self.dual_stack == Some(true);
+        let mut client = if use_v4 || dual_stack { WolClient::v4() } else { WolClient::v6() };
+        match (self.multicast_group, self.broadcast) {
+            (Some(group), _) => {
+                if group.is_routed() && self.ttl.unwrap_or(1) <= 1 {
+                    warn!("packets to {} do not leave the link without a ttl above 1", group);
+                }
+                client = client.with_multicast_group(group)
+            },
+            (None, Some(broadcast)) => client = client.with_broadcast(broadcast),
+            (None, None) => {},
+        }
+        if let Some(ref ports) = self.ports {
+            client = client.with_ports(ports);
+        }
+        if let Some(repeat) = self.repeat {
+            client = client.with_repeat(repeat);
+        }
+        if let Some(bursts) = self.bursts {
+            client = client.with_bursts(bursts);
+        }
+        if let Some(ref interface) = self.interface {
+            client = client.with_interface(interface.as_str());
+        }
+        client = client.with_link_check(self.check_link == Some(true));
+        if let Some(ref netns) = self.netns {
+            client = client.with_netns(netns);
+        }
+        if let Some(source) = self.source {
+            client = client.with_source(source);
+        }
+        if let Some(port) = self.source_port {
+            client = client.with_source_port(port);
+        }
+        if let Some(ttl) = self.ttl {
+            client = client.with_ttl(ttl);
+        }
+        if let Some(dscp) = self.dscp {
+            client = client.with_dscp(dscp);
+        }
+        if let Some(mark) = self.mark {
+            client = client.with_mark(mark);
+        }
+        client = client.with_dual_stack(dual_stack);
+        client
+    }
+
+    /// Returns the configured verification ports or the default.
+    pub fn verify_ports(&self) -> Vec<u16> {
+        self.verify_ports.clone().unwrap_or_else(|| vec![verify::DEFAULT_PORT])
+    }
+
+    /// Returns the configured verification timeout or the default.
+    pub fn verify_timeout(&self) -> Duration {
+        self.verify_timeout.unwrap_or(verify::DEFAULT_TIMEOUT)
+    }
+
+    /// Returns the configured verification method or the default, `Method::Tcp`.
+    pub fn verify_method(&self) -> verify::Method {
+        self.verify_method.unwrap_or(verify::Method::Tcp)
+    }
+
+    /// Returns the configured resolvers or the default, `resolve::DEFAULT_SOURCES`.
+    pub fn resolve_order(&self) -> Vec<Source> {
+        self.resolve_order.clone().unwrap_or_else(|| resolve::DEFAULT_SOURCES.to_vec())
+    }
+
+    /// Returns the configured addressing or the default, `Addressing::Auto`.
+    pub fn addressing(&self) -> Addressing {
+        self.addressing.unwrap_or(Addressing::Auto)
+    }
+
+    /// Returns the tokens the daemon accepts: the relay token, as admin token for all hosts,
+    /// and the configured API tokens.
+    pub fn api_tokens(&self) -> Vec<ApiToken> {
+        let relay = self.relay_token.iter().map(|token| {
+            ApiToken::new("relay", token).with_scope(Scope::Admin)
+        });
+        relay.chain(self.api_tokens.iter().flatten().cloned()).collect()
+    }
+
+    /// Returns the configured SNMP community of the switch or the default, "public".
+    pub fn switch_community(&self) -> &str {
+        self.switch_community.as_deref().unwrap_or("public")
+    }
+
+    /// Returns the configured check of MAC addresses or the default, `MacCheck::Warn`.
+    pub fn mac_check(&self) -> MacCheck {
+        self.mac_check.unwrap_or(MacCheck::Warn)
+    }
+}
+
+impl fmt::Display for Config {
+    /// Formats the configuration as TOML. Unset values are shown as comments with their default.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn line<T: fmt::Display>(f: &mut fmt::Formatter,
+                                 key: &str,
+                                 value: Option<T>,
+                                 default: &str)
+                                 -> fmt::Result {
+            match value {
+                Some(value) => writeln!(f, "{} = {}", key, value),
+                None => writeln!(f, "# {} = {}", key, default),
+            }
+        }
+
+        line(f,
+             "broadcast",
+             self.broadcast.map(|ip| toml::quote(&ip.to_string())),
+             "(limited broadcast or IPv6 multicast group)")?;
+        line(f,
+             "multicast_group",
+             self.multicast_group.map(|group| toml::quote(&group.to_string())),
+             "(none)")?;
+        line(f, "port", self.ports.as_ref().map(|ports| format_ports(ports)), "0")?;
+        line(f, "repeat", self.repeat, "1")?;
+        line(f, "burst", self.bursts.map(|bursts| toml::quote(&bursts.to_string())), "\"1\"")?;
+        line(f,
+             "interface",
+             self.interface.as_ref().map(|s| toml::quote(s)),
+             "(chosen by the operating system)")?;
+        line(f, "check_link", self.check_link, "false")?;
+        line(f, "netns", self.netns.as_ref().map(|s| toml::quote(s)), "(that of the process)")?;
+        line(f,
+             "source",
+             self.source.map(|ip| toml::quote(&ip.to_string())),
+             "(chosen by the operating system)")?;
+        line(f, "source_port", self.source_port, "(chosen by the operating system)")?;
+        line(f, "ttl", self.ttl, "(chosen by the operating system)")?;
+        line(f, "dscp", self.dscp, "0")?;
+        line(f, "mark", self.mark, "(none)")?;
+        line(f, "dual_stack", self.dual_stack, "false")?;
+        line(f,
+             "hosts_file",
+             self.hosts_file.as_ref().map(|p| toml::quote(&p.to_string_lossy())),
+             &toml::quote(&hosts::default_path().to_string_lossy()))?;
+        line(f,
+             "audit_log",
+             self.audit_log.as_ref().map(|p| toml::quote(&p.to_string_lossy())),
+             "(none)")?;
+        line(f,
+             "mac_check",
+             self.mac_check.map(|check| toml::quote(&check.to_string())),
+             "\"warn\"")?;
+        line(f,
+             "cooldown",
+             self.cooldown.map(|cooldown| toml::quote(&units::format_duration(cooldown))),
+             "(none)")?;
+        line(f,
+             "addressing",
+             self.addressing.map(|addressing| toml::quote(&addressing.to_string())),
+             "\"auto\"")?;
+        writeln!(f, "\n[verify]")?;
+        line(f,
+             "port",
+             self.verify_ports.as_ref().map(|ports| format_ports(ports)),
+             &verify::DEFAULT_PORT.to_string())?;
+        line(f,
+             "timeout",
+             self.verify_timeout.map(format_timeout),
+             &verify::DEFAULT_TIMEOUT.as_secs().to_string())?;
+        line(f,
+             "method",
+             self.verify_method.map(|method| toml::quote(&method.to_string())),
+             "\"tcp\"")?;
+        writeln!(f, "\n[relay]")?;
+        // The token is a secret, which should not end up in a terminal's scrollback.
+        match self.relay_token {
+            Some(ref token) if redact::show_secrets() => {
+                writeln!(f, "token = {}", toml::quote(token))?
+            },
+            Some(_) => writeln!(f, "# token = {}", REDACTED)?,
+            None => writeln!(f, "# token = (none)")?,
+        }
+        line(f,
+             "drain_timeout",
+             self.drain_timeout.map(|timeout| toml::quote(&units::format_duration(timeout))),
+             "\"30s\"")?;
+        writeln!(f, "\n[tokens]")?;
+        match self.api_tokens {
+            Some(ref tokens) => {
+                for token in tokens {
+                    let secret = if redact::show_secrets() {
+                        toml::quote(&token.token.0)
+                    } else {
+                        REDACTED.to_string()
+                    };
+                    let hosts: Vec<String> = token.hosts.iter().map(|h| toml::quote(h)).collect();
+                    writeln!(f,
+                             "{}{} = {{ token = {}, hosts = [{}], scope = {} }}",
+                             if redact::show_secrets() { "" } else { "# " },
+                             token.name,
+                             secret,
+                             hosts.join(", "),
+                             toml::quote(&token.scope.to_string()))?;
+                }
+            },
+            None => writeln!(f, "# (none)")?,
+        }
+        writeln!(f, "\n[resolve]")?;
+        line(f,
+             "order",
+             self.resolve_order.as_ref().map(|order| format_sources(order)),
+             &format_sources(&resolve::DEFAULT_SOURCES))?;
+        line(f,
+             "leases",
+             self.leases_file.as_ref().map(|p| toml::quote(&p.to_string_lossy())),
+             "(the first of dnsmasq's and dhcpd's which exists)")?;
+        writeln!(f, "\n[switch]")?;
+        line(f, "address", self.switch.as_ref().map(|s| toml::quote(s)), "(none)")?;
+        // Communities are passwords, though often the default one.
+        match self.switch_community {
+            Some(ref community) if redact::show_secrets() => {
+                writeln!(f, "community = {}", toml::quote(community))
+            },
+            Some(_) => writeln!(f, "# community = {}", REDACTED),
+            None => writeln!(f, "# community = \"public\""),
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        for entry in toml::parse(s)? {
+            match (entry.table.as_str(), entry.key.as_str()) {
+                ("", "broadcast") => config.broadcast = Some(entry.parse("an IP address")?),
+                ("", "multicast_group") => {
+                    config.multicast_group = Some(entry.parse("an IPv6 multicast group")?)
+                },
+                ("", "port") => {
+                    let ports = entry.as_checked_array::<Port>()?;
+                    config.ports = Some(ports.into_iter().map(Port::get).collect())
+                },
+                ("", "repeat") => config.repeat = Some(entry.as_checked::<RepeatCount>()?.get()),
+                ("", "burst") => {
+                    let bursts = entry.as_str()?.parse().map_err(|err: String| entry.error(&err))?;
+                    config.bursts = Some(bursts)
+                },
+                ("", "interface") => config.interface = Some(entry.as_str()?.to_string()),
+                ("", "check_link") => config.check_link = Some(entry.as_bool()?),
+                ("", "netns") => config.netns = Some(entry.as_str()?.to_string()),
+                ("", "source") => config.source = Some(entry.parse("an IP address")?),
+                ("", "source_port") => config.source_port = Some(entry.as_checked::<Port>()?.get()),
+                ("", "ttl") => config.ttl = Some(entry.as_int()?),
+                ("", "dscp") => config.dscp = Some(parse_dscp(&entry)?),
+                ("", "mark") => config.mark = Some(entry.as_int()?),
+                ("", "dual_stack") => config.dual_stack = Some(entry.as_bool()?),
+                ("", "hosts_file") => config.hosts_file = Some(PathBuf::from(entry.as_str()?)),
+                ("", "audit_log") if entry.value == toml::Value::Bool(true) => {
+                    let path = Paths::new().audit_log();
+                    let err = || entry.error("the default audit log is unknown without HOME");
+                    config.audit_log = Some(path.ok_or_else(err)?)
+                },
+                ("", "audit_log") => config.audit_log = Some(PathBuf::from(entry.as_str()?)),
+                ("", "mac_check") => {
+                    config.mac_check = Some(entry.parse("ignore, warn or reject")?)
+                },
+                ("", "cooldown") => config.cooldown = Some(entry.as_duration()?),
+                ("", "addressing") => {
+                    config.addressing = Some(entry.parse("auto, broadcast or unicast")?)
+                },
+                ("verify", "port") => {
+                    let ports = entry.as_checked_array::<Port>()?;
+                    config.verify_ports = Some(ports.into_iter().map(Port::get).collect())
+                },
+                ("verify", "timeout") => {
+                    config.verify_timeout = Some(entry.as_duration()?)
+                },
+                ("verify", "method") => {
+                    config.verify_method = Some(entry.parse("tcp, icmp, arp or dhcp")?)
+                },
+                ("relay", "token") => config.relay_token = Some(entry.as_secret()?),
+                ("relay", "drain_timeout") => config.drain_timeout = Some(entry.as_duration()?),
+                ("tokens", _) => {
+                    config.api_tokens.get_or_insert_with(Vec::new).push(parse_token(&entry)?)
+                },
+                ("resolve", "order") => {
+                    let order = entry.as_str_array()?
+                        .iter()
+                        .map(|source| source.parse().map_err(|err: String| entry.error(&err)))
+                        .collect::<Result<_, _>>()?;
+                    config.resolve_order = Some(order)
+                },
+                ("resolve", "leases") => config.leases_file = Some(PathBuf::from(entry.as_str()?)),
+                ("switch", "address") => config.switch = Some(entry.as_str()?.to_string()),
+                ("switch", "community") => {
+                    config.switch_community = Some(entry.as_secret()?)
+                },
+                _ => return Err(entry.unknown()),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Parses an API token of the `[tokens]` table, an inline table named like the token.
+fn parse_token(entry: &toml::Entry) -> Result<ApiToken, ConfigError> {
+    let (mut token, mut hosts, mut scope) = (None, Vec::new(), Scope::Wake);
+    for key in entry.as_table()? {
+        match key.key.as_str() {
+            "token" => token = Some(key.as_secret()?),
+            "hosts" => hosts = key.as_str_array()?,
+            "scope" => scope = key.parse("wake or admin")?,
+            _ => return Err(key.unknown()),
+        }
+    }
+    let token = token.ok_or_else(|| entry.error("missing the token of the API token"))?;
+    Ok(ApiToken::new(&entry.key, &token).with_hosts(hosts).with_scope(scope))
+}
+
+/// Parses a DSCP, a number or the name of a class.
+fn parse_dscp(entry: &toml::Entry) -> Result<Dscp, ConfigError> {
+    let dscp = match entry.value {
+        toml::Value::Int(_) => Dscp::new(entry.as_int()?),
+        _ => entry.as_str()?.parse().ok(),
+    };
+    dscp.ok_or_else(|| entry.error("expected a DSCP from 0 to 63 or a class like \"ef\""))
+}
+
+/// Formats a timeout as TOML, a number of seconds if it is a whole one and else a string like
+/// "500ms".
+fn format_timeout(timeout: Duration) -> String {
+    if timeout.subsec_nanos() == 0 {
+        timeout.as_secs().to_string()
+    } else {
+        toml::quote(&units::format_duration(timeout))
+    }
+}
+
+/// Formats ports as TOML, a single one as number and several as array.
+fn format_ports(ports: &[u16]) -> String {
+    match ports {
+        [port] => port.to_string(),
+        _ => {
+            let ports: Vec<String> = ports.iter().map(|port| port.to_string()).collect();
+            format!("[{}]", ports.join(", "))
+        },
+    }
+}
+
+/// Formats resolvers as TOML array.
+fn format_sources(sources: &[Source]) -> String {
+    let sources: Vec<String> = sources.iter().map(|s| toml::quote(&s.to_string())).collect();
+    format!("[{}]", sources.join(", "))
+}
+
+/// What to do about MAC addresses which no network card can have, see `MacAddr::suspicious`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacCheck {
+    /// Wake them without a word, e.g. for testing.
+    Ignore,
+    /// Log a warning and wake them.
+    Warn,
+    /// Refuse to wake them.
+    Reject,
+}
+
+impl fmt::Display for MacCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            MacCheck::Ignore => "ignore",
+            MacCheck::Warn => "warn",
+            MacCheck::Reject => "reject",
+        })
+    }
+}
+
+impl FromStr for MacCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MacCheck, String> {
+        match s {
+            "ignore" => Ok(MacCheck::Ignore),
+            "warn" => Ok(MacCheck::Warn),
+            "reject" => Ok(MacCheck::Reject),
+            _ => Err(format!("unknown MAC check '{}', expected ignore, warn or reject", s)),
+        }
+    }
+}
+
+/// An error in a configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// The line of the error, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl ConfigError {
+    /// Creates an error at the given line.
+    pub fn new(line: usize, message: &str) -> ConfigError {
+        ConfigError {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use resolve::Source;
+    use subnet::Addressing;
+    use super::{Config, MacCheck, TEMPLATE};
+    use tokens::Scope;
+    use transport::Dscp;
+    use verify::Method;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = "# defaults\n\
+                              broadcast = \"192.168.1.255\"  # office\n\
+                              port = [7, 9]\n\
+                              interface = \"eth0\"\n\
+                              check_link = true\n\
+                              netns = \"blue\"\n\
+                              ttl = 4\n\
+                              dscp = \"af41\"\n\
+                              mark = 7\n\
+                              mac_check = \"reject\"\n\
+                              cooldown = \"5m\"\n\
+                              addressing = \"unicast\"\n\
+                              \n\
+                              [verify]\n\
+                              timeout = 1_000\n\
+                              method = \"icmp\"\n\
+                              [relay]\n\
+                              token = \"s3cret\"\n\
+                              drain_timeout = \"1m\"\n\
+                              [tokens]\n\
+                              guest = { token = \"g4est\", hosts = [\"media-pc\"] }\n\
+                              [resolve]\n\
+                              order = [\"leases\", \"hosts\"]\n\
+                              leases = \"/var/lib/dhcp/dhcpd.leases\"\n\
+                              [switch]\n\
+                              address = \"core-sw:1161\"\n"
+            .parse()
+            .unwrap();
+        assert_eq!(config.broadcast, Some("192.168.1.255".parse().unwrap()));
+        assert_eq!(config.ports, Some(vec![7, 9]));
+        assert_eq!(config.repeat, None);
+        assert_eq!(config.interface, Some("eth0".to_string()));
+        assert_eq!(config.verify_timeout(), Duration::from_secs(1000));
+        assert_eq!(config.verify_ports(), vec![22]);
+        assert_eq!(config.mac_check(), MacCheck::Reject);
+        assert_eq!(config.cooldown, Some(Duration::from_secs(300)));
+        assert_eq!(config.addressing(), Addressing::Unicast);
+        assert_eq!(config.verify_method(), Method::Icmp);
+        assert_eq!(config.relay_token, Some("s3cret".to_string()));
+        assert_eq!(config.drain_timeout, Some(Duration::from_secs(60)));
+        let tokens = config.api_tokens();
+        assert_eq!(tokens.iter().map(|t| (t.name.as_str(), t.scope)).collect::<Vec<_>>(),
+                   [("relay", Scope::Admin), ("guest", Scope::Wake)]);
+        assert_eq!(tokens[1].hosts, ["media-pc"]);
+        assert_eq!(config.resolve_order(), [Source::Leases, Source::Hosts]);
+        assert_eq!(config.leases_file, Some(PathBuf::from("/var/lib/dhcp/dhcpd.leases")));
+        assert_eq!(config.switch, Some("core-sw:1161".to_string()));
+        assert_eq!(config.switch_community(), "public");
+
+        let client = config.client(false);
+        assert_eq!(client.destination(), "192.168.1.255:7".parse().unwrap());
+        assert_eq!(client.ports(), [7, 9]);
+        assert_eq!(client.interface(), Some("eth0"));
+        assert!(client.link_check());
+        assert_eq!(client.netns(), Some(Path::new("/run/netns/blue")));
+        assert_eq!(client.ttl(), Some(4));
+        assert_eq!(client.dscp().map(Dscp::value), Some(34));
+        assert_eq!(client.mark(), Some(7));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |s: &str| s.parse::<Config>().unwrap_err().to_string();
+        assert_eq!(err("port = 9\nprot = 9"), "2: unknown key 'prot'");
+        assert_eq!(err("port = 65536"), "1: invalid port '65536', expected 1 to 65535");
+        assert_eq!(err("port = \"9\""), "1: expected a number or an array of numbers");
+        assert_eq!(err("repeat = \"3\""), "1: expected a number");
+        assert_eq!(err("dual_stack = 1"), "1: expected true or false");
+        assert_eq!(err("broadcast = \"192.168.1\""), "1: expected an IP address");
+        assert_eq!(err("multicast_group = \"fd00::1\""), "1: expected an IPv6 multicast group");
+        let dscp = "1: expected a DSCP from 0 to 63 or a class like \"ef\"";
+        assert_eq!((err("dscp = 64"), err("dscp = \"af5\"")), (dscp.into(), dscp.into()));
+        assert_eq!(err("interface = \"eth0"), "1: unterminated string");
+        assert_eq!(err("[verify\nport = 22"), "1: missing ']'");
+        assert_eq!(err("mac_check = \"strict\""), "1: expected ignore, warn or reject");
+        assert_eq!(err("[verify]\nmethod = \"udp\""), "2: expected tcp, icmp, arp or dhcp");
+        assert_eq!(err("[tokens]\nguest = { hosts = [\"nas\"] }"),
+                   "2: missing the token of the API token");
+        assert_eq!(err("[tokens]\nguest = { token = \"g\", scope = \"root\" }"),
+                   "2: expected wake or admin");
+        assert_eq!(err("[resolve]\norder = [\"mdns\"]"),
+                   "2: unknown resolver 'mdns', expected hosts, arp, leases or dns");
+        assert_eq!(err("repeat = 0"), "1: invalid repeat count '0', expected 1 to 100");
+        assert_eq!(err("port = [9, 0]"), "1: invalid port '0', expected 1 to 65535");
+        assert_eq!(err("burst = \"3x5ms\""), "1: invalid interval '5ms', expected at least 10ms");
+        assert_eq!(err("burst = \"0x2s\""), "1: expected at least one burst");
+        assert_eq!(err("source_port = 0"), "1: invalid port '0', expected 1 to 65535");
+        assert_eq!(err("[verify]\nport = [22, 0]"), "2: invalid port '0', expected 1 to 65535");
+        assert_eq!(err("[relay]\ntoken = 7"),
+                   "2: expected a string or an inline table like { keyring = \"account\" }");
+        assert_eq!(err("[relay]\ntoken = { cmd = \"exit 1\", keyring = \"relay\" }"),
+                   "2: expected either keyring or cmd");
+    }
+
+    #[test]
+    fn test_secrets() {
+        let config: Config = "[relay]\n\
+                              token = { cmd = \"echo s3cret\" }\n\
+                              [tokens]\n\
+                              guest = { token = { cmd = \"echo g4est\" } }\n\
+                              [switch]\n\
+                              community = { cmd = \"echo private\" }"
+            .parse()
+            .unwrap();
+        assert_eq!(config.relay_token, Some("s3cret".to_string()));
+        assert!(config.api_tokens()[1].matches("g4est"));
+        assert_eq!(config.switch_community(), "private");
+        let err = "[relay]\ntoken = { cmd = \"exit 1\" }".parse::<Config>().unwrap_err();
+        assert!(err.to_string().starts_with("2: cannot read the secret of the command 'exit 1': \
+                                             the command failed with"));
+    }
+
+    #[test]
+    fn test_template() {
+        assert_eq!(TEMPLATE.parse::<Config>().unwrap(), Config::default());
+        // The examples are valid settings once the # in front of them is removed.
+        let uncommented: String = TEMPLATE.lines()
+            .map(|line| match line.strip_prefix('#') {
+                Some(example) if !example.starts_with(' ') => format!("{}\n", example),
+                _ => format!("{}\n", line),
+            })
+            .collect();
+        let config: Config = uncommented.parse().unwrap();
+        assert_eq!(config.verify_method, Some(Method::Tcp));
+        assert_eq!(config.switch_community(), "public");
+        assert_eq!(config.api_tokens().len(), 2);
+        assert_eq!(config.client(false).destination(), "[ff05::1:3]:7".parse().unwrap());
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let vars = |vars: &[(&str, &str)]| {
+            Config::from_vars(vars.iter().map(|&(k, v)| (k.to_string(), OsString::from(v))))
+        };
+        let config = vars(&[("WOL_PORT", "9"), ("WOL_INTERFACE", "eth1"), ("HOME", "/root")])
+            .unwrap();
+        assert_eq!(config.ports, Some(vec![9]));
+        assert_eq!(config.interface, Some("eth1".to_string()));
+        assert_eq!(config.merge("port = 7".parse().unwrap()).ports, Some(vec![7]));
+        assert_eq!(vars(&[("WOL_PORT", "7,9")]).unwrap().ports, Some(vec![7, 9]));
+        assert_eq!(vars(&[("WOL_BROADCAST", "10.0.0")]).unwrap_err().to_string(),
+                   "invalid WOL_BROADCAST '10.0.0'");
+    }
+
+    #[test]
+    fn test_merge_and_display() {
+        let system: Config = "port = 9\nrepeat = 3\n[verify]\nport = [22, 3389]".parse().unwrap();
+        let user: Config = "port = 7\nhosts_file = \"/etc/wol/hosts.toml\"\n\
+                            audit_log = \"/var/log/wol.log\""
+            .parse()
+            .unwrap();
+        let config = system.merge(user);
+        assert_eq!((config.ports.clone(), config.repeat), (Some(vec![7]), Some(3)));
+        let group: Config = "multicast_group = \"ff05::1:3\"".parse().unwrap();
+        let routed = config.clone().merge(group.clone());
+        assert_eq!((routed.broadcast, routed.multicast_group), (None, group.multicast_group));
+        let broadcast: Config = "broadcast = \"10.0.0.255\"".parse().unwrap();
+        assert_eq!(routed.merge(broadcast).multicast_group, None);
+        assert_eq!(config.to_string(),
+                   "# broadcast = (limited broadcast or IPv6 multicast group)\n\
+                    # multicast_group = (none)\n\
+                    port = 7\n\
+                    repeat = 3\n\
+                    # burst = \"1\"\n\
+                    # interface = (chosen by the operating system)\n\
+                    # check_link = false\n\
+                    # netns = (that of the process)\n\
+                    # source = (chosen by the operating system)\n\
+                    # source_port = (chosen by the operating system)\n\
+                    # ttl = (chosen by the operating system)\n\
+                    # dscp = 0\n\
+                    # mark = (none)\n\
+                    # dual_stack = false\n\
+                    hosts_file = \"/etc/wol/hosts.toml\"\n\
+                    audit_log = \"/var/log/wol.log\"\n\
+                    # mac_check = \"warn\"\n\
+                    # cooldown = (none)\n\
+                    # addressing = \"auto\"\n\
+                    \n\
+                    [verify]\n\
+                    port = [22, 3389]\n\
+                    # timeout = 60\n\
+                    # method = \"tcp\"\n\
+                    \n\
+                    [relay]\n\
+                    # token = (none)\n\
+                    # drain_timeout = \"30s\"\n\
+                    \n\
+                    [tokens]\n\
+                    # (none)\n\
+                    \n\
+                    [resolve]\n\
+                    # order = [\"hosts\", \"arp\"]\n\
+                    # leases = (the first of dnsmasq's and dhcpd's which exists)\n\
+                    \n\
+                    [switch]\n\
+                    # address = (none)\n\
+                    # community = \"public\"\n");
+        let token: Config = "[relay]\ntoken = \"s3cret\"".parse().unwrap();
+        assert!(token.to_string().contains("[relay]\n# token = ••••\n"));
+        let guest: Config = "[tokens]\nguest = { token = \"g4est\", scope = \"admin\" }"
+            .parse()
+            .unwrap();
+        let shown = "[tokens]\n# guest = { token = ••••, hosts = [], scope = \"admin\" }\n";
+        assert!(guest.to_string().contains(shown));
+    }
+}
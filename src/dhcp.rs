@@ -0,0 +1,219 @@
+//! Verification by DHCP snooping, which confirms that a host powered on before any of its
+//! services is reachable.
+//!
+//! Most hosts broadcast a DHCP DISCOVER or REQUEST as soon as their network stack starts, even
+//! with a static address in the firmware's network boot. A `Snooper` receives these broadcasts
+//! of the local segment as raw frames, next to a DHCP server on the same machine, and picks those
+//! of one MAC address. Hosts which are already up do not broadcast, so a snooper cannot tell
+//! whether a host is up before it was woken. Receiving raw frames needs Linux and the
+//! `CAP_NET_RAW` capability.
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use pcap::{ETHERTYPE_IPV4, ETHERTYPE_VLAN};
+use sys::RawSocket;
+use verify::Prober;
+use MacAddr;
+
+/// The port of DHCP servers, which clients broadcast to.
+pub const SERVER_PORT: u16 = 67;
+
+/// How long `Snooper::is_up` receives frames.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The protocol number of UDP in the IPv4 header.
+const PROTOCOL_UDP: u8 = 17;
+
+/// The offset of the options in a DHCP message, after the fixed BOOTP fields.
+const OPTIONS_OFFSET: usize = 236;
+
+/// The magic cookie starting the options of DHCP messages.
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+/// The option holding the type of a DHCP message.
+const OPTION_MESSAGE_TYPE: u8 = 53;
+
+/// The DHCP messages a booting client broadcasts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    /// Looking for DHCP servers, sent first.
+    Discover,
+    /// Requesting an address, after an offer or to confirm the previous lease.
+    Request,
+}
+
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            MessageType::Discover => "DHCPDISCOVER",
+            MessageType::Request => "DHCPREQUEST",
+        })
+    }
+}
+
+/// Returns the client hardware address and the type of a DHCP DISCOVER or REQUEST in an
+/// Ethernet frame, with or without VLAN tag, or `None` for all other frames.
+pub fn client_message(frame: &[u8]) -> Option<(MacAddr, MessageType)> {
+    let mut ethertype = u16_at(frame, 12)?;
+    let mut offset = 14;
+    if ethertype == ETHERTYPE_VLAN {
+        ethertype = u16_at(frame, 16)?;
+        offset = 18;
+    }
+    let ip = frame.get(offset..)?;
+    let header_len = usize::from(*ip.first()? & 0x0F) * 4;
+    if ethertype != ETHERTYPE_IPV4 || header_len < 20 || *ip.get(9)? != PROTOCOL_UDP {
+        return None;
+    }
+    let udp = ip.get(header_len..)?;
+    if u16_at(udp, 2)? != SERVER_PORT {
+        return None;
+    }
+    let message = udp.get(8..usize::from(u16_at(udp, 4)?))?;
+    // A request of a client (op 1) with an Ethernet address (type 1, length 6).
+    if message.get(..3)? != [1, 1, 6] ||
+       message.get(OPTIONS_OFFSET..OPTIONS_OFFSET + 4)? != MAGIC_COOKIE {
+        return None;
+    }
+    let mut mac = [0; 6];
+    mac.copy_from_slice(message.get(28..34)?);
+    let mut options = &message[OPTIONS_OFFSET + 4..];
+    loop {
+        match *options.first()? {
+            0 => options = &options[1..],
+            255 => return None,
+            code => {
+                let len = usize::from(*options.get(1)?);
+                let value = options.get(2..2 + len)?;
+                if code == OPTION_MESSAGE_TYPE {
+                    return match *value.first()? {
+                        1 => Some((MacAddr::new(mac), MessageType::Discover)),
+                        3 => Some((MacAddr::new(mac), MessageType::Request)),
+                        _ => None,
+                    };
+                }
+                options = &options[2 + len..];
+            },
+        }
+    }
+}
+
+/// Receives the DHCP broadcasts of the local segment and counts a host as up once it sent a
+/// DISCOVER or REQUEST.
+///
+/// Open the snooper right after sending the magic packet: the broadcasts of hosts booting
+/// before are not seen.
+#[derive(Debug)]
+pub struct Snooper {
+    socket: Mutex<RawSocket>,
+    mac: MacAddr,
+}
+
+impl Snooper {
+    /// Opens a raw socket receiving the IPv4 frames of all interfaces or only of the given one,
+    /// for the messages of `mac`. Fails with `io::ErrorKind::PermissionDenied` without the
+    /// privileges for raw sockets.
+    pub fn open(mac: MacAddr, interface: Option<&str>) -> io::Result<Snooper> {
+        let socket = RawSocket::open(ETHERTYPE_IPV4, interface).map_err(|err| {
+            if err.kind() == io::ErrorKind::PermissionDenied {
+                io::Error::new(err.kind(), "DHCP snooping needs the CAP_NET_RAW capability")
+            } else {
+                err
+            }
+        })?;
+        Ok(Snooper {
+            socket: Mutex::new(socket),
+            mac,
+        })
+    }
+
+    /// Receives frames until the host sent a DISCOVER or REQUEST or `timeout` has passed, and
+    /// returns the type of its message.
+    pub fn wait(&self, timeout: Duration) -> io::Result<Option<MessageType>> {
+        let mut socket = self.socket.lock().unwrap();
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0; 1518];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Ok(None);
+            }
+            socket.set_read_timeout(remaining)?;
+            match socket.recv(&mut buf) {
+                Ok(len) => {
+                    if let Some((mac, message)) = client_message(&buf[..len]) {
+                        trace!("{} from {}", message, mac);
+                        if mac == self.mac {
+                            return Ok(Some(message));
+                        }
+                    }
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Prober for Snooper {
+    fn is_up(&self) -> bool {
+        match self.wait(ATTEMPT_TIMEOUT) {
+            Ok(message) => message.is_some(),
+            Err(err) => {
+                trace!("cannot receive the DHCP messages of {}: {}", self.mac, err);
+                false
+            },
+        }
+    }
+}
+
+/// Returns the big endian `u16` at `offset`, if the data is long enough.
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use pcap::udp_frame;
+    use MacAddr;
+
+    use super::{client_message, MessageType, MAGIC_COOKIE, OPTIONS_OFFSET};
+
+    /// Returns a DHCP message of a client with the given options after the magic cookie.
+    fn message(mac: [u8; 6], options: &[u8]) -> Vec<u8> {
+        let mut message = vec![0; OPTIONS_OFFSET];
+        message[..3].copy_from_slice(&[1, 1, 6]);
+        message[28..34].copy_from_slice(&mac);
+        message.extend_from_slice(&MAGIC_COOKIE);
+        message.extend_from_slice(options);
+        message
+    }
+
+    #[test]
+    fn test_client_message() {
+        let mac = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+        let frame = |options: &[u8]| {
+            udp_frame("0.0.0.0:68".parse().unwrap(),
+                      "255.255.255.255:67".parse().unwrap(),
+                      &message(mac, options))
+        };
+        assert_eq!(client_message(&frame(&[53, 1, 1, 255])),
+                   Some((MacAddr::new(mac), MessageType::Discover)));
+        // Padding and other options before the message type.
+        assert_eq!(client_message(&frame(&[0, 0, 61, 2, 1, 2, 53, 1, 3, 255])),
+                   Some((MacAddr::new(mac), MessageType::Request)));
+        // A DHCPRELEASE, no message type and a truncated option.
+        assert_eq!(client_message(&frame(&[53, 1, 7, 255])), None);
+        assert_eq!(client_message(&frame(&[255])), None);
+        assert_eq!(client_message(&frame(&[61, 9, 1])), None);
+        // The answer of a server.
+        let offer = udp_frame("10.0.0.1:67".parse().unwrap(),
+                              "255.255.255.255:68".parse().unwrap(),
+                              &message(mac, &[53, 1, 2, 255]));
+        assert_eq!(client_message(&offer), None);
+        assert_eq!(client_message(&[0; 10]), None);
+        assert_eq!(MessageType::Discover.to_string(), "DHCPDISCOVER");
+    }
+}
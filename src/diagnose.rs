@@ -0,0 +1,537 @@
+//! Checks of the local end of the path a magic packet takes, to find out why a host did not come
+//! up.
+//!
+//! Most failed wakes are not the fault of the host but of where the packet went: out of the
+//! wrong interface, to a broadcast address no router forwards, or into a local firewall. `run`
+//! checks what can be seen from this machine and returns a `Finding` per check, with a hint
+//! what to change if it looks wrong. It cannot see the network beyond, e.g. a switch dropping
+//! broadcasts; `wol listen` on a machine next to the host shows whether the packet arrives.
+//!
+//! `environment` checks this machine regardless of a host, for `wol doctor`: whether it may
+//! send broadcasts, which interfaces it could send through and whether `wol listen` can bind its
+//! port.
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+#[cfg(target_os = "linux")]
+use std::process::Command;
+
+use hosts::Host;
+use neighbors::{self, NeighborAddr};
+#[cfg(feature = "snmp")]
+use snmp::Switch;
+use subnet::{self, Cidr};
+use {MacAddr, WolClient, WolError};
+
+/// The outcome of a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Nothing to change.
+    Ok,
+    /// Likely a reason the packet did not arrive.
+    Warning,
+    /// The packet cannot have arrived.
+    Failed,
+    /// The check could not be done, e.g. without privileges.
+    Unknown,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Failed => "failed",
+            Status::Unknown => "unknown",
+        })
+    }
+}
+
+/// The result of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// The name of the check, e.g. "route".
+    pub check: &'static str,
+    pub status: Status,
+    /// What was found and, unless the status is ok, what to do about it.
+    pub message: String,
+}
+
+impl Finding {
+    /// Returns the finding of a check, e.g. of one done by the caller.
+    pub fn new<S: Into<String>>(check: &'static str, status: Status, message: S) -> Finding {
+        Finding {
+            check,
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.status, self.check, self.message)
+    }
+}
+
+/// Checks the path of the packets `client` sends to `host`: the interface, the route to the
+/// destination, whether the destination reaches the network of the host, the MAC address in
+/// the neighbor table and the local firewall.
+pub fn run(client: &WolClient, host: &Host) -> Vec<Finding> {
+    let destination = client.destination();
+    let networks = subnet::connected_networks();
+    let mut findings = vec![interface(client), route(client)];
+    match networks {
+        Ok(ref networks) => findings.push(addressing(destination.ip(), host.ip, networks)),
+        Err(ref err) => {
+            findings.push(Finding::new("addressing",
+                                       Status::Unknown,
+                                       format!("cannot read the connected networks: {}", err)))
+        },
+    }
+    if let Some(ip) = host.ip {
+        findings.push(neighbor(host, ip));
+    }
+    findings.push(firewall(destination.port()));
+    findings
+}
+
+/// Checks the environment of wol on this machine: that `client` may send to its destination,
+/// usually a broadcast address, the networks of each interface as candidates for
+/// `--interface`, that `wol listen` can bind UDP port `listen_port`, and the local firewall.
+pub fn environment(client: &WolClient, listen_port: u16) -> Vec<Finding> {
+    let mut findings = vec![broadcast(client)];
+    findings.extend(interfaces());
+    findings.push(listener(listen_port));
+    findings.push(firewall(client.destination().port()));
+    findings
+}
+
+/// Checks that the client may send to its destination by sending an empty datagram like it
+/// sends magic packets, which no host takes for one. Sockets need a permission to send
+/// broadcasts, which a local firewall may deny.
+fn broadcast(client: &WolClient) -> Finding {
+    let destination = client.destination();
+    let sent = client.open_socket().and_then(|(socket, destination)| {
+        socket.connect(destination)?;
+        socket.send(&[])
+    });
+    match sent {
+        Ok(_) => {
+            Finding::new("broadcast",
+                         Status::Ok,
+                         format!("an empty datagram to {} was sent", destination))
+        },
+        Err(err) => {
+            let message = format!("cannot send to {}: {}", destination, err);
+            let message = match WolError::from(err).hint() {
+                Some(hint) => format!("{}; {}", message, hint),
+                None => message,
+            };
+            Finding::new("broadcast", Status::Failed, message)
+        },
+    }
+}
+
+/// Lists the networks and broadcast addresses of each interface but the loopback interface,
+/// warning about those without any.
+fn interfaces() -> Vec<Finding> {
+    let names = match subnet::interface_names() {
+        Ok(names) => names,
+        Err(err) => {
+            return vec![Finding::new("interfaces",
+                                     Status::Unknown,
+                                     format!("cannot list the network interfaces: {}", err))];
+        },
+    };
+    if names.is_empty() {
+        return vec![Finding::new("interfaces",
+                                 Status::Warning,
+                                 "no network interface but the loopback interface")];
+    }
+    names.iter()
+        .map(|name| match subnet::interface_networks(name) {
+            Ok(ref networks) if networks.is_empty() => {
+                Finding::new("interface",
+                             Status::Warning,
+                             format!("{} has no network, e.g. because it is down", name))
+            },
+            Ok(networks) => Finding::new("interface", Status::Ok, describe(name, &networks)),
+            Err(err) => {
+                Finding::new("interface",
+                             Status::Unknown,
+                             format!("cannot read the networks of {}: {}", name, err))
+            },
+        })
+        .collect()
+}
+
+/// Describes the networks of an interface with their broadcast addresses, e.g.
+/// "eth0: 192.168.1.0/24 (broadcast 192.168.1.255), fd00::/64".
+fn describe(name: &str, networks: &[Cidr]) -> String {
+    let networks: Vec<String> = networks.iter()
+        .map(|network| match network.broadcast() {
+            Some(broadcast) => format!("{} (broadcast {})", network, broadcast),
+            None => network.to_string(),
+        })
+        .collect();
+    format!("{}: {}", name, networks.join(", "))
+}
+
+/// Checks that `wol listen` can bind UDP port `port` on all IPv4 addresses, as it does by
+/// default.
+fn listener(port: u16) -> Finding {
+    match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)) {
+        Ok(_) => Finding::new("listen", Status::Ok, format!("UDP port {} can be bound", port)),
+        Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            Finding::new("listen",
+                         Status::Warning,
+                         format!("cannot bind UDP port {}: {}; ports below 1024 need root or \
+                                  CAP_NET_BIND_SERVICE, or give wol listen another --port",
+                                 port,
+                                 err))
+        },
+        Err(ref err) if err.kind() == io::ErrorKind::AddrInUse => {
+            Finding::new("listen",
+                         Status::Warning,
+                         format!("UDP port {} is in use, e.g. by a discard service or another \
+                                  wol listen; give wol listen another --port",
+                                 port))
+        },
+        Err(err) => {
+            Finding::new("listen",
+                         Status::Failed,
+                         format!("cannot bind UDP port {}: {}", port, err))
+        },
+    }
+}
+
+/// Checks that the socket can be bound to the interface of the client and that its link is up,
+/// see `subnet::link_state`.
+fn interface(client: &WolClient) -> Finding {
+    match (client.interface(), client.open_socket()) {
+        (Some(interface), Ok(_)) if subnet::link_state(interface)
+            .and_then(|link| link.check(interface))
+            .is_err_and(|err| err.kind() == io::ErrorKind::NetworkDown) => {
+            Finding::new("interface",
+                         Status::Failed,
+                         format!("interface {} is down or has no carrier; plug in its cable \
+                                  or bring it up, e.g. with `ip link set {} up`",
+                                 interface,
+                                 interface))
+        },
+        (Some(interface), Ok(_)) => {
+            Finding::new("interface",
+                         Status::Ok,
+                         format!("the socket is bound to interface {}", interface))
+        },
+        (Some(interface), Err(err)) => {
+            Finding::new("interface",
+                         Status::Failed,
+                         format!("cannot bind to interface {}: {}; check its name with `ip link` \
+                                  or `ifconfig`",
+                                  interface,
+                                  err))
+        },
+        (None, Ok(_)) => {
+            Finding::new("interface",
+                         Status::Ok,
+                         "the system chooses the interface by its routing table; give \
+                          --interface if the host is on another one")
+        },
+        (None, Err(err)) => {
+            Finding::new("interface", Status::Failed, format!("cannot open a socket: {}", err))
+        },
+    }
+}
+
+/// Checks that the system has a route to the destination, by connecting a socket to it.
+fn route(client: &WolClient) -> Finding {
+    let connected = client.open_socket()
+        .and_then(|(socket, destination)| socket.connect(destination).and(socket.local_addr()));
+    match connected {
+        Ok(local) => {
+            Finding::new("route",
+                         Status::Ok,
+                         format!("the system routes {} from {}", client.destination(), local.ip()))
+        },
+        Err(err) => {
+            Finding::new("route",
+                         Status::Failed,
+                         format!("no route to {}: {}; give --interface or a broadcast address of \
+                                  a connected network",
+                                  client.destination(),
+                                  err))
+        },
+    }
+}
+
+/// Checks whether packets to `destination` reach the network of the host at `ip`, given the
+/// networks the system is connected to.
+fn addressing(destination: IpAddr, ip: Option<IpAddr>, networks: &[Cidr]) -> Finding {
+    let connected = |ip: IpAddr| networks.iter().find(|network| network.contains(ip));
+    let host_network = ip.and_then(connected);
+    let message = match (destination, ip) {
+        (IpAddr::V4(dest), Some(ip)) if dest == Ipv4Addr::BROADCAST && host_network.is_none() => {
+            format!("the limited broadcast does not cross routers, but {} is not on a connected \
+                     network; try --addressing auto or --strategy auto",
+                    ip)
+        },
+        (IpAddr::V4(dest), _) if dest == Ipv4Addr::BROADCAST => {
+            return Finding::new("addressing",
+                                Status::Ok,
+                                "the limited broadcast reaches the connected networks");
+        },
+        (dest, Some(ip)) if dest == ip && host_network.is_some() => {
+            format!("{} is on a connected network, where a sleeping host only gets unicast \
+                     packets while its MAC address is in the neighbor table; send to the \
+                     broadcast address instead",
+                    ip)
+        },
+        (dest, Some(ip)) if dest == ip => {
+            format!("{} is behind a router, which only forwards the packet if it knows the MAC \
+                     address of the host, e.g. from a static ARP entry",
+                    ip)
+        },
+        (dest, _) if networks.iter().any(|network| network.broadcast() == Some(dest)) => {
+            match (host_network, ip) {
+                (Some(network), Some(ip)) if network.broadcast() != Some(dest) => {
+                    format!("{} is the broadcast address of another network than the one of {}; \
+                             send to {} instead",
+                            dest,
+                            ip,
+                            network.broadcast().map_or(ip, |broadcast| broadcast))
+                },
+                (None, Some(ip)) => {
+                    format!("{} is not on a connected network, which the broadcast to {} does \
+                             not leave; try --addressing auto or --strategy auto",
+                            ip,
+                            dest)
+                },
+                _ => {
+                    return Finding::new("addressing",
+                                        Status::Ok,
+                                        format!("{} is the broadcast address of a connected \
+                                                 network",
+                                                dest));
+                },
+            }
+        },
+        (IpAddr::V4(dest), _) => {
+            format!("{} is not the broadcast address of a connected network, so a router must \
+                     forward directed broadcasts to it, which most do not by default",
+                    dest)
+        },
+        (dest, _) => {
+            return Finding::new("addressing",
+                                Status::Ok,
+                                format!("sending to {}, which IPv6 hosts on the link receive",
+                                        dest));
+        },
+    };
+    Finding::new("addressing", Status::Warning, message)
+}
+
+/// Checks that the neighbor table has no other MAC address for the IP address of the host,
+/// which would mean that the packet carries the wrong one.
+fn neighbor(host: &Host, ip: IpAddr) -> Finding {
+    let addr = NeighborAddr {
+        ip,
+        interface: None,
+    };
+    match neighbors::lookup(&addr) {
+        Ok(Some(mac)) if mac == host.mac => {
+            Finding::new("neighbor",
+                         Status::Ok,
+                         format!("the neighbor table has the same MAC address for {}", ip))
+        },
+        Ok(Some(mac)) => {
+            Finding::new("neighbor",
+                         Status::Failed,
+                         format!("the neighbor table has {} for {}, not {}; the MAC address of \
+                                  the host may be wrong, e.g. that of another network card",
+                                 mac,
+                                 ip,
+                                 host.mac))
+        },
+        Ok(None) => {
+            Finding::new("neighbor",
+                         Status::Ok,
+                         format!("{} is not in the neighbor table, so its MAC address cannot \
+                                  be compared",
+                                 ip))
+        },
+        Err(err) => {
+            Finding::new("neighbor",
+                         Status::Unknown,
+                         format!("cannot read the neighbor table: {}", err))
+        },
+    }
+}
+
+/// Asks the switch at `address`, a host with an optional port, per SNMP on which port it last
+/// saw `mac`, so that users can check that the host is connected where they think it is.
+#[cfg(feature = "snmp")]
+pub fn switch_port(address: &str, community: &str, mac: MacAddr) -> Finding {
+    let port = Switch::resolve(address, community).and_then(|switch| switch.port_of(mac));
+    match port {
+        Ok(Some(port)) => {
+            Finding::new("switch",
+                         Status::Ok,
+                         format!("switch {} last saw {} on {}; check that the host is \
+                                  connected there and not to another segment",
+                                 address,
+                                 mac,
+                                 port))
+        },
+        Ok(None) => {
+            Finding::new("switch",
+                         Status::Warning,
+                         format!("switch {} has not seen {} recently: the host is connected to \
+                                  another switch or has slept longer than the aging time of \
+                                  its forwarding database",
+                                 address,
+                                 mac))
+        },
+        Err(err) => {
+            Finding::new("switch",
+                         Status::Unknown,
+                         format!("cannot ask switch {} per SNMP: {}", address, err))
+        },
+    }
+}
+
+/// Asks the switch at `address`, a host with an optional port, per SNMP on which port it last
+/// saw `mac`, so that users can check that the host is connected where they think it is.
+#[cfg(not(feature = "snmp"))]
+pub fn switch_port(address: &str, _community: &str, _mac: MacAddr) -> Finding {
+    Finding::new("switch",
+                 Status::Unknown,
+                 format!("cannot ask switch {}: SNMP needs the snmp feature", address))
+}
+
+/// Checks the rules of the local firewall for outgoing UDP packets to `port`.
+#[cfg(target_os = "linux")]
+fn firewall(port: u16) -> Finding {
+    let output = Command::new("iptables").args(["-S", "OUTPUT"]).output();
+    let rules = match output {
+        Ok(ref output) if output.status.success() => String::from_utf8_lossy(&output.stdout),
+        Ok(ref output) => {
+            return Finding::new("firewall",
+                                Status::Unknown,
+                                format!("cannot read the firewall rules, e.g. without root: {}",
+                                        String::from_utf8_lossy(&output.stderr).trim()));
+        },
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            return Finding::new("firewall", Status::Unknown, "iptables is not installed");
+        },
+        Err(err) => {
+            return Finding::new("firewall",
+                                Status::Unknown,
+                                format!("cannot run iptables: {}", err));
+        },
+    };
+    match blocking_rule(&rules, port) {
+        Some(rule) => {
+            Finding::new("firewall",
+                         Status::Warning,
+                         format!("the rule `{}` may block UDP port {}; allow it with `iptables \
+                                  -I OUTPUT -p udp --dport {} -j ACCEPT`",
+                                 rule,
+                                 port,
+                                 port))
+        },
+        None => {
+            Finding::new("firewall",
+                         Status::Ok,
+                         format!("no iptables rule blocks UDP port {}", port))
+        },
+    }
+}
+
+/// Checks the rules of the local firewall for outgoing UDP packets to `port`.
+#[cfg(not(target_os = "linux"))]
+fn firewall(_port: u16) -> Finding {
+    Finding::new("firewall", Status::Unknown, "the firewall can only be checked on Linux")
+}
+
+/// Returns the first rule in the output of `iptables -S OUTPUT` which drops or rejects UDP
+/// packets to `port`, or the policy of the chain if it drops them and no rule accepts them.
+/// Rules with conditions other than the protocol and port are reported too, as they may match.
+#[cfg(any(target_os = "linux", test))]
+fn blocking_rule(rules: &str, port: u16) -> Option<String> {
+    let port = port.to_string();
+    let mut policy = None;
+    for rule in rules.lines().map(str::trim) {
+        let words: Vec<&str> = rule.split_whitespace().collect();
+        let value = |option: &str| {
+            words.iter().position(|word| *word == option).and_then(|i| words.get(i + 1)).cloned()
+        };
+        if words.first() == Some(&"-P") {
+            if matches!(words.get(2), Some(&"DROP") | Some(&"REJECT")) {
+                policy = Some(rule.to_string());
+            }
+            continue;
+        }
+        let protocol = value("-p");
+        let dport = value("--dport");
+        if !matches!(protocol, None | Some("udp") | Some("all")) ||
+           dport.is_some_and(|dport| dport != port) {
+            continue;
+        }
+        match value("-j") {
+            Some("DROP") | Some("REJECT") => return Some(rule.to_string()),
+            Some("ACCEPT") if dport.is_some() => return None,
+            _ => {},
+        }
+    }
+    policy
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{addressing, blocking_rule, describe, Status};
+    use subnet::Cidr;
+
+    #[test]
+    fn test_addressing() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let lan: Cidr = "192.168.1.0/24".parse().unwrap();
+        let status = |dest, host: Option<&str>| addressing(ip(dest), host.map(ip), &[lan]).status;
+        assert_eq!(status("255.255.255.255", Some("192.168.1.7")), Status::Ok);
+        assert_eq!(status("255.255.255.255", Some("10.0.0.7")), Status::Warning);
+        assert_eq!(status("192.168.1.255", Some("192.168.1.7")), Status::Ok);
+        assert_eq!(status("192.168.1.255", Some("10.0.0.7")), Status::Warning);
+        assert_eq!(status("192.168.1.255", None), Status::Ok);
+        assert_eq!(status("10.0.0.255", Some("10.0.0.7")), Status::Warning);
+        assert_eq!(status("192.168.1.7", Some("192.168.1.7")), Status::Warning);
+        assert_eq!(status("10.0.0.7", Some("10.0.0.7")), Status::Warning);
+        assert_eq!(status("ff02::1", None), Status::Ok);
+    }
+
+    #[test]
+    fn test_describe() {
+        let networks: Vec<Cidr> = vec!["192.168.1.0/24".parse().unwrap(),
+                                       "fd00::/64".parse().unwrap()];
+        assert_eq!(describe("eth0", &networks),
+                   "eth0: 192.168.1.0/24 (broadcast 192.168.1.255), fd00::/64");
+    }
+
+    #[test]
+    fn test_blocking_rule() {
+        let rules = "-P OUTPUT ACCEPT\n\
+                     -A OUTPUT -p tcp --dport 9 -j DROP\n\
+                     -A OUTPUT -p udp --dport 7 -j ACCEPT\n\
+                     -A OUTPUT -p udp --dport 9 -j REJECT --reject-with icmp-port-unreachable\n";
+        assert_eq!(blocking_rule(rules, 9),
+                   Some("-A OUTPUT -p udp --dport 9 -j REJECT --reject-with \
+                         icmp-port-unreachable"
+                       .to_string()));
+        assert_eq!(blocking_rule(rules, 7), None);
+        assert_eq!(blocking_rule("-P OUTPUT DROP\n", 9), Some("-P OUTPUT DROP".to_string()));
+        assert_eq!(blocking_rule("-P OUTPUT DROP\n-A OUTPUT -p udp --dport 9 -j ACCEPT\n", 9),
+                   None);
+    }
+}
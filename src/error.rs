@@ -0,0 +1,251 @@
+//! The error type of operations which both parse input and send packets, and the codes errors
+//! are reported with to machines.
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use mac::ParseError;
+
+/// How to allow sending if the operating system refused to, usually because of a firewall.
+#[cfg(windows)]
+const BLOCKED_HINT: &str = "the Windows Firewall or another security tool blocks outbound UDP \
+                            broadcasts of wol; allow them with an outbound rule, e.g. `netsh \
+                            advfirewall firewall add rule name=wol dir=out action=allow \
+                            protocol=udp program=C:\\path\\to\\wol.exe`";
+#[cfg(not(windows))]
+const BLOCKED_HINT: &str = "a firewall rejects the packet, check the rules for outgoing UDP, e.g. \
+                            with `iptables -L OUTPUT` or `nft list ruleset`";
+
+/// How to get the privileges for raw sockets.
+#[cfg(windows)]
+const PRIVILEGES_HINT: &str = "raw sockets need administrator rights, run wol from a command \
+                               prompt opened with \"Run as administrator\"";
+#[cfg(target_os = "linux")]
+const PRIVILEGES_HINT: &str = "raw sockets need the CAP_NET_RAW capability, e.g. with `setcap \
+                               cap_net_raw+ep wol`, or root privileges";
+#[cfg(not(any(windows, target_os = "linux")))]
+const PRIVILEGES_HINT: &str = "raw sockets need root privileges";
+
+/// How to bring up the link of the interface sent through.
+#[cfg(target_os = "linux")]
+const LINK_HINT: &str = "plug in the cable or connect the interface, and bring it up, e.g. with \
+                         `ip link set IFACE up` or `nmcli device connect IFACE`";
+#[cfg(not(target_os = "linux"))]
+const LINK_HINT: &str = "plug in the cable or connect the interface, and bring it up, e.g. with \
+                         `ifconfig IFACE up`";
+
+/// An error while waking a host.
+#[derive(Debug)]
+pub enum WolError {
+    /// The MAC address could not be parsed.
+    Parse(ParseError),
+    /// The packet could not be sent.
+    Io(io::Error),
+    /// The operating system refused to send the packet, usually because a firewall blocks it,
+    /// e.g. the outbound broadcasts of the Windows Firewall. See `hint` for the remedy.
+    Blocked(io::Error),
+    /// The process lacks the privileges to send, e.g. administrator rights or `CAP_NET_RAW` for
+    /// raw sockets. See `hint` for the remedy.
+    Privileges(io::Error),
+    /// The interface to send through is down or has no carrier, see
+    /// `WolClient::with_link_check`. See `hint` for the remedy.
+    LinkDown(io::Error),
+    /// Sending did not complete within the timeout, see `WolClient::with_timeout`.
+    Timeout,
+    /// Waiting for the host was cancelled, see `WolClient::wake_and_wait`.
+    Cancelled,
+}
+
+impl fmt::Display for WolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WolError::Parse(ref err) => write!(f, "invalid MAC address: {}", err),
+            WolError::Io(ref err) => write!(f, "cannot send magic packet: {}", err),
+            WolError::Blocked(ref err) => {
+                write!(f, "sending the magic packet was blocked: {}", err)
+            },
+            WolError::Privileges(ref err) => {
+                write!(f, "missing privileges to send the magic packet: {}", err)
+            },
+            WolError::LinkDown(ref err) => write!(f, "cannot send magic packet: {}", err),
+            WolError::Timeout => write!(f, "sending the magic packet timed out"),
+            WolError::Cancelled => write!(f, "waiting for the host was cancelled"),
+        }
+    }
+}
+
+impl Error for WolError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            WolError::Parse(ref err) => Some(err),
+            WolError::Io(ref err) |
+            WolError::Blocked(ref err) |
+            WolError::Privileges(ref err) |
+            WolError::LinkDown(ref err) => Some(err),
+            WolError::Timeout | WolError::Cancelled => None,
+        }
+    }
+}
+
+impl From<ParseError> for WolError {
+    fn from(err: ParseError) -> WolError {
+        WolError::Parse(err)
+    }
+}
+
+impl From<io::Error> for WolError {
+    /// Converts the error, mapping the errors of a timed out send to `WolError::Timeout` and
+    /// refused sends, e.g. `WSAEACCES` on Windows or `EPERM` of a firewall rule on Linux, to
+    /// `WolError::Blocked`, and those of a down interface to `WolError::LinkDown`.
+    fn from(err: io::Error) -> WolError {
+        match err.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => WolError::Timeout,
+            io::ErrorKind::PermissionDenied => WolError::Blocked(err),
+            io::ErrorKind::NetworkDown => WolError::LinkDown(err),
+            _ => WolError::Io(err),
+        }
+    }
+}
+
+impl WolError {
+    /// Converts an error of opening or sending on a raw socket, mapping a denied permission to
+    /// `WolError::Privileges` rather than `WolError::Blocked`.
+    pub fn from_raw(err: io::Error) -> WolError {
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => WolError::Privileges(err),
+            _ => WolError::from(err),
+        }
+    }
+
+    /// Returns how to fix the error, for the errors with a common cause the user can remedy.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::io;
+    /// use wol::WolError;
+    ///
+    /// let err = WolError::from(io::Error::from(io::ErrorKind::PermissionDenied));
+    /// assert!(err.hint().unwrap().contains("firewall"));
+    /// assert_eq!(WolError::Timeout.hint(), None);
+    /// ```
+    pub fn hint(&self) -> Option<&'static str> {
+        match *self {
+            WolError::Blocked(_) => Some(BLOCKED_HINT),
+            WolError::Privileges(_) => Some(PRIVILEGES_HINT),
+            WolError::LinkDown(_) => Some(LINK_HINT),
+            _ => None,
+        }
+    }
+
+    /// Returns the code the error is reported with.
+    pub fn code(&self) -> ErrorCode {
+        match *self {
+            WolError::Parse(_) => ErrorCode::ParseMac,
+            WolError::Io(ref err) |
+            WolError::Blocked(ref err) |
+            WolError::Privileges(ref err) |
+            WolError::LinkDown(ref err) => ErrorCode::of(err),
+            WolError::Timeout => ErrorCode::SendFailed,
+            WolError::Cancelled => ErrorCode::VerifyTimeout,
+        }
+    }
+}
+
+/// A machine-readable error code, the `code` field next to the `error` message of the JSON
+/// output of `wol wake` and the error responses of `wol serve`, so that integrators need not
+/// parse the messages, which may change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The target is neither a MAC address nor a known host.
+    ParseMac,
+    /// No socket could be bound to send from, e.g. because the interface has no address.
+    BindFailed,
+    /// The interface to send through is down or has no carrier.
+    LinkDown,
+    /// The magic packet could not be sent.
+    SendFailed,
+    /// The host did not come up within the verification timeout.
+    VerifyTimeout,
+    /// The request lacks the token of the daemon.
+    Unauthorized,
+    /// The token of the request may not do what it asks for, e.g. wake this host.
+    Forbidden,
+    /// The request is malformed, e.g. has an invalid query parameter.
+    BadRequest,
+    /// The daemon has no such resource.
+    NotFound,
+    /// The resource does not support the method of the request.
+    MethodNotAllowed,
+    /// A resolver failed to look up the target.
+    ResolveFailed,
+    /// The daemon could not read its new settings, e.g. because the configuration is invalid.
+    ReloadFailed,
+    /// The daemon could not read or replace its hosts file, see `wol hosts sync`.
+    HostsFileFailed,
+    /// The daemon has as many connections open as it handles at once.
+    Busy,
+}
+
+impl ErrorCode {
+    /// Returns the code of an error while sending: `BindFailed` for the errors of binding a
+    /// socket, `LinkDown` for a down interface, else `SendFailed`.
+    pub fn of(err: &io::Error) -> ErrorCode {
+        match err.kind() {
+            io::ErrorKind::AddrInUse | io::ErrorKind::AddrNotAvailable => ErrorCode::BindFailed,
+            io::ErrorKind::NetworkDown => ErrorCode::LinkDown,
+            _ => ErrorCode::SendFailed,
+        }
+    }
+
+    /// Returns the code as written, e.g. "PARSE_MAC".
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::ParseMac => "PARSE_MAC",
+            ErrorCode::BindFailed => "BIND_FAILED",
+            ErrorCode::LinkDown => "LINK_DOWN",
+            ErrorCode::SendFailed => "SEND_FAILED",
+            ErrorCode::VerifyTimeout => "VERIFY_TIMEOUT",
+            ErrorCode::Unauthorized => "UNAUTHORIZED",
+            ErrorCode::Forbidden => "FORBIDDEN",
+            ErrorCode::BadRequest => "BAD_REQUEST",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::MethodNotAllowed => "METHOD_NOT_ALLOWED",
+            ErrorCode::ResolveFailed => "RESOLVE_FAILED",
+            ErrorCode::ReloadFailed => "RELOAD_FAILED",
+            ErrorCode::HostsFileFailed => "HOSTS_FILE_FAILED",
+            ErrorCode::Busy => "BUSY",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{ErrorCode, WolError};
+
+    #[test]
+    fn test_hints() {
+        let denied = || io::Error::from(io::ErrorKind::PermissionDenied);
+        let blocked = WolError::from(denied());
+        assert!(matches!(blocked, WolError::Blocked(_)));
+        assert!(blocked.hint().is_some());
+        assert_eq!(blocked.code(), ErrorCode::SendFailed);
+        let raw = WolError::from_raw(denied());
+        assert!(matches!(raw, WolError::Privileges(_)));
+        assert_ne!(raw.hint(), blocked.hint());
+        assert!(matches!(WolError::from_raw(io::ErrorKind::TimedOut.into()), WolError::Timeout));
+        assert_eq!(WolError::from(io::Error::other("no route")).hint(), None);
+        let down = WolError::from(io::Error::new(io::ErrorKind::NetworkDown, "eth0 is down"));
+        assert!(matches!(down, WolError::LinkDown(_)));
+        assert!(down.hint().unwrap().contains("IFACE up"));
+        assert_eq!(down.code(), ErrorCode::LinkDown);
+    }
+}
@@ -0,0 +1,105 @@
+//! Live events of a long running process, e.g. `wol serve`, for dashboards showing the progress
+//! of wakes as server-sent events.
+//!
+//! Every event has a kind, e.g. `packet_sent`, and a record with the MAC address, the time and
+//! details depending on the kind. Subscribers receive the events published after they
+//! subscribed; subscribers which went away are dropped on the next event.
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use output::{timestamp, Record};
+
+/// A wake was requested, before the magic packet is sent.
+pub const WAKE_REQUESTED: &str = "wake_requested";
+/// The magic packet was sent.
+pub const PACKET_SENT: &str = "packet_sent";
+/// The magic packet could not be sent.
+pub const SEND_FAILED: &str = "send_failed";
+/// The host was verified to be up.
+pub const VERIFY_SUCCEEDED: &str = "verify_succeeded";
+/// The host did not come up before the verification timed out.
+pub const VERIFY_TIMEOUT: &str = "verify_timeout";
+
+/// An event with its kind and the record holding its details.
+pub type Event = (&'static str, Record);
+
+/// Distributes events to subscribers, which can be shared between threads.
+#[derive(Debug, Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<Event>>>,
+}
+
+impl EventBus {
+    /// Creates a bus without subscribers.
+    pub fn new() -> EventBus {
+        EventBus::default()
+    }
+
+    /// Returns a receiver of all events published from now on.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap_or_else(|e| e.into_inner()).push(sender);
+        receiver
+    }
+
+    /// Returns the number of subscribers, including those which went away since the last event.
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Publishes an event about `mac`, with the time prepended to the given details.
+    pub fn publish(&self, kind: &'static str, mac: &str, details: Record) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        if subscribers.is_empty() {
+            return;
+        }
+        let mut record = Record::new()
+            .field("mac", mac)
+            .field("time", timestamp(SystemTime::now()));
+        for (name, value) in details.fields() {
+            record.set(name, value.clone());
+        }
+        subscribers.retain(|subscriber| subscriber.send((kind, record.clone())).is_ok());
+    }
+}
+
+/// Formats an event as a server-sent event, whose data is the record as JSON.
+///
+/// #Example
+///
+/// ```
+/// use wol::events;
+/// use wol::output::Record;
+///
+/// let event = (events::PACKET_SENT, Record::new().field("mac", "00:01:02:03:04:05"));
+/// assert_eq!(events::sse_frame(&event),
+///            "event: packet_sent\ndata: {\"mac\":\"00:01:02:03:04:05\"}\n\n");
+/// ```
+pub fn sse_frame(event: &Event) -> String {
+    format!("event: {}\ndata: {}\n\n", event.0, event.1.to_json())
+}
+
+#[cfg(test)]
+mod tests {
+    use output::{Record, Value};
+
+    use super::{EventBus, PACKET_SENT, SEND_FAILED};
+
+    #[test]
+    fn test_publish() {
+        let bus = EventBus::new();
+        bus.publish(SEND_FAILED, "00:01:02:03:04:05", Record::new());
+        let receiver = bus.subscribe();
+        let gone = bus.subscribe();
+        drop(gone);
+        bus.publish(PACKET_SENT, "00:01:02:03:04:05", Record::new().field("bytes", 102u64));
+        let (kind, record) = receiver.try_recv().unwrap();
+        assert_eq!(kind, PACKET_SENT);
+        assert_eq!(record.get("mac"), Some(&Value::Str("00:01:02:03:04:05".to_string())));
+        assert!(record.get("time").is_some());
+        assert_eq!(record.get("bytes"), Some(&Value::Int(102)));
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(bus.subscribers(), 1);
+    }
+}
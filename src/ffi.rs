@@ -0,0 +1,164 @@
+//! C bindings, enabled with the `ffi` feature.
+//!
+//! The functions are declared in `include/wol.h`. All of them return a non-negative value on
+//! success and one of the negative `WOL_ERR_*` codes on failure.
+use std::ffi::CStr;
+use std::net::{IpAddr, SocketAddr};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use {destination_v4, destination_v6, MacAddr, MagicPacket, WolClient};
+
+/// A required pointer argument is NULL.
+pub const WOL_ERR_NULL: c_int = -1;
+/// A string argument is not a valid MAC or IP address.
+pub const WOL_ERR_PARSE: c_int = -2;
+/// The packet could not be sent.
+pub const WOL_ERR_SEND: c_int = -3;
+/// The output buffer is too small.
+pub const WOL_ERR_BUFFER: c_int = -4;
+
+/// Reads a MAC address from a pointer to six bytes.
+unsafe fn read_mac(mac: *const u8) -> Option<MacAddr> {
+    if mac.is_null() {
+        return None;
+    }
+    let mut octets = [0; 6];
+    ptr::copy_nonoverlapping(mac, octets.as_mut_ptr(), 6);
+    Some(MacAddr::new(octets))
+}
+
+/// Reads an optional address from a C string, `None` for NULL.
+unsafe fn read_addr(addr: *const c_char) -> Result<Option<IpAddr>, c_int> {
+    if addr.is_null() {
+        return Ok(None);
+    }
+    let addr = CStr::from_ptr(addr).to_str().map_err(|_| WOL_ERR_PARSE)?;
+    addr.parse().map(Some).map_err(|_| WOL_ERR_PARSE)
+}
+
+fn send(mac: MacAddr, destination: SocketAddr) -> c_int {
+    match WolClient::new(destination).send(&MagicPacket::new(mac)) {
+        Ok(report) => report.bytes_sent as c_int,
+        Err(_) => WOL_ERR_SEND,
+    }
+}
+
+/// Sends a magic packet for `mac` per UDP/IPv4 to `broadcast` (the limited broadcast address
+/// if NULL) and `port`. Returns the number of bytes sent.
+///
+/// # Safety
+///
+/// `mac` must point to six readable bytes and `broadcast` must be NULL or point to a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn wol_send_v4(mac: *const u8, broadcast: *const c_char, port: u16) -> c_int {
+    let mac = match read_mac(mac) {
+        Some(mac) => mac,
+        None => return WOL_ERR_NULL,
+    };
+    let ip = match read_addr(broadcast) {
+        Ok(Some(ip @ IpAddr::V4(_))) => ip,
+        Ok(None) => destination_v4().ip(),
+        Ok(Some(IpAddr::V6(_))) | Err(_) => return WOL_ERR_PARSE,
+    };
+    send(mac, SocketAddr::new(ip, port))
+}
+
+/// Sends a magic packet for `mac` per UDP/IPv6 to `group` (the default multicast group if NULL)
+/// and `port`. Returns the number of bytes sent.
+///
+/// # Safety
+///
+/// `mac` must point to six readable bytes and `group` must be NULL or point to a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn wol_send_v6(mac: *const u8, group: *const c_char, port: u16) -> c_int {
+    let mac = match read_mac(mac) {
+        Some(mac) => mac,
+        None => return WOL_ERR_NULL,
+    };
+    let ip = match read_addr(group) {
+        Ok(Some(ip @ IpAddr::V6(_))) => ip,
+        Ok(None) => destination_v6().ip(),
+        Ok(Some(IpAddr::V4(_))) | Err(_) => return WOL_ERR_PARSE,
+    };
+    send(mac, SocketAddr::new(ip, port))
+}
+
+/// Parses a MAC address like "AA:FF:B0:12:34:56" into the six bytes at `out`. Returns 0.
+///
+/// # Safety
+///
+/// `s` must point to a NUL-terminated string and `out` to six writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn wol_parse_mac(s: *const c_char, out: *mut u8) -> c_int {
+    if s.is_null() || out.is_null() {
+        return WOL_ERR_NULL;
+    }
+    let mac = match CStr::from_ptr(s).to_str().ok().and_then(|s| s.parse::<MacAddr>().ok()) {
+        Some(mac) => mac,
+        None => return WOL_ERR_PARSE,
+    };
+    ptr::copy_nonoverlapping(mac.octets().as_ptr(), out, 6);
+    0
+}
+
+/// Writes the 102 byte magic packet for `mac` to `buf` of size `len`, e.g. to send it with
+/// another network stack. Returns the number of bytes written.
+///
+/// # Safety
+///
+/// `mac` must point to six readable bytes and `buf` to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn wol_build_packet(mac: *const u8, buf: *mut u8, len: usize) -> c_int {
+    let mac = match read_mac(mac) {
+        Some(mac) if !buf.is_null() => mac,
+        _ => return WOL_ERR_NULL,
+    };
+    let payload = MagicPacket::new(mac).payload();
+    if len < payload.len() {
+        return WOL_ERR_BUFFER;
+    }
+    slice::from_raw_parts_mut(buf, payload.len()).copy_from_slice(&payload);
+    payload.len() as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::net::UdpSocket;
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_and_build() {
+        let s = CString::new("00:01:02:03:04:05").unwrap();
+        let mut mac = [0u8; 6];
+        assert_eq!(unsafe { wol_parse_mac(s.as_ptr(), mac.as_mut_ptr()) }, 0);
+        assert_eq!(mac, [0, 1, 2, 3, 4, 5]);
+
+        let mut buf = [0u8; 102];
+        assert_eq!(unsafe { wol_build_packet(mac.as_ptr(), buf.as_mut_ptr(), 101) },
+                   WOL_ERR_BUFFER);
+        assert_eq!(unsafe { wol_build_packet(mac.as_ptr(), buf.as_mut_ptr(), buf.len()) }, 102);
+        assert_eq!(&buf[96..], &mac);
+    }
+
+    #[test]
+    fn test_send_v4() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = receiver.local_addr().unwrap().port();
+        let mac = [0u8, 1, 2, 3, 4, 5];
+        let ip = CString::new("127.0.0.1").unwrap();
+        assert_eq!(unsafe { wol_send_v4(mac.as_ptr(), ip.as_ptr(), port) }, 102);
+        let mut buf = [0u8; 128];
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+
+        let ip = CString::new("::1").unwrap();
+        assert_eq!(unsafe { wol_send_v4(mac.as_ptr(), ip.as_ptr(), port) }, WOL_ERR_PARSE);
+        assert_eq!(unsafe { wol_send_v4(ptr::null(), ptr::null(), port) }, WOL_ERR_NULL);
+    }
+}
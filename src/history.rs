@@ -0,0 +1,284 @@
+//! The history of recent wakes, telling when a host was last woken, by whom and whether it came
+//! up, e.g. to debug hosts which keep going to sleep.
+//!
+//! Unlike the audit log, the history is kept without being asked for, in the state directory,
+//! see `Paths::history_file`. It holds one JSON object per line like the audit log, and is cut
+//! down to the last `MAX_ENTRIES` wakes whenever it grows beyond `MAX_SIZE` bytes.
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use audit::Initiator;
+use output::{self, parse_timestamp, timestamp, Record, Value};
+use MacAddr;
+
+/// The number of wakes kept when the history is cut down.
+pub const MAX_ENTRIES: usize = 1000;
+
+/// The size in bytes beyond which the history is cut down.
+pub const MAX_SIZE: u64 = 512 * 1024;
+
+/// A wake in the history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wake {
+    /// The name of the host, or its MAC address if it is not in the hosts file.
+    pub host: String,
+    pub mac: MacAddr,
+    /// When the magic packet was sent, as RFC 3339 timestamp in UTC.
+    pub time: String,
+    pub initiator: Initiator,
+    /// `Ok` if the packet was sent, otherwise the error message.
+    pub outcome: Result<(), String>,
+    /// Whether the host came up, if that was verified.
+    pub verified: Option<bool>,
+    /// How long the host took to come up after the packet was sent, if it was verified.
+    pub boot_time: Option<Duration>,
+}
+
+impl Wake {
+    /// Creates a wake of `host` which happened just now.
+    pub fn new(host: &str,
+               mac: MacAddr,
+               initiator: Initiator,
+               outcome: Result<(), String>,
+               verified: Option<bool>)
+               -> Wake {
+        Wake {
+            host: host.to_string(),
+            mac,
+            time: timestamp(SystemTime::now()),
+            initiator,
+            outcome,
+            verified,
+            boot_time: None,
+        }
+    }
+
+    /// Sets how long the host took to come up.
+    pub fn with_boot_time(mut self, boot_time: Option<Duration>) -> Wake {
+        self.boot_time = boot_time;
+        self
+    }
+
+    /// Converts the wake into a record.
+    pub fn to_record(&self) -> Record {
+        Record::new()
+            .field("time", self.time.as_str())
+            .field("host", self.host.as_str())
+            .field("mac", self.mac.to_string())
+            .field("initiator", self.initiator.kind())
+            .field("requested_by", self.initiator.name())
+            .field("outcome", if self.outcome.is_ok() { "ok" } else { "error" })
+            .field("error", self.outcome.as_ref().err().map(|e| e.as_str()))
+            .field("verified", self.verified)
+            .field("boot_ms", self.boot_time.map(output::millis))
+    }
+
+    /// Converts a record of `to_record` back, returning `None` if a field is missing or invalid.
+    pub fn from_record(record: &Record) -> Option<Wake> {
+        let string = |name| match record.get(name) {
+            Some(Value::Str(s)) => Some(s.clone()),
+            _ => None,
+        };
+        let name = string("requested_by")?;
+        let initiator = match string("initiator")?.as_str() {
+            "cli" => Initiator::Cli(name),
+            "api" => Initiator::Api(name),
+            _ => return None,
+        };
+        let outcome = match string("outcome")?.as_str() {
+            "ok" => Ok(()),
+            _ => Err(string("error").unwrap_or_default()),
+        };
+        Some(Wake {
+            host: string("host")?,
+            mac: string("mac")?.parse().ok()?,
+            time: string("time")?,
+            initiator,
+            outcome,
+            verified: match record.get("verified") {
+                Some(&Value::Bool(verified)) => Some(verified),
+                _ => None,
+            },
+            // Whole milliseconds are written without fraction, and older lines have none.
+            boot_time: match record.get("boot_ms") {
+                Some(&Value::Float(ms)) => Duration::try_from_secs_f64(ms / 1e3).ok(),
+                Some(&Value::Int(ms)) => Some(Duration::from_millis(ms)),
+                _ => None,
+            },
+        })
+    }
+
+    /// Returns the time since the wake, `None` if its time cannot be read or is in the future.
+    pub fn age(&self) -> Option<Duration> {
+        SystemTime::now().duration_since(parse_timestamp(&self.time)?).ok()
+    }
+
+    /// Returns whether this is a wake of the host with the given name or MAC address.
+    pub fn is_of(&self, host: &str) -> bool {
+        self.host == host || host.parse() == Ok(self.mac)
+    }
+}
+
+/// The history file, which can be shared between threads and processes.
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+    /// Keeps the threads of this process from cutting down the file at the same time.
+    lock: Mutex<()>,
+}
+
+impl History {
+    /// Uses the history at the given path, which is created with its directory on the first wake.
+    pub fn new<P: Into<PathBuf>>(path: P) -> History {
+        History {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns the path of the history file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a wake, cutting down the history if it grew too large.
+    pub fn record(&self, wake: &Wake) -> io::Result<()> {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().append(true).create(true).open(&self.path)?;
+        // A single write, so that the lines of several processes do not interleave.
+        file.write_all(format!("{}\n", wake.to_record().to_json()).as_bytes())?;
+        if file.metadata()?.len() > MAX_SIZE {
+            let content = fs::read_to_string(&self.path)?;
+            let lines: Vec<&str> = content.lines().collect();
+            let kept = &lines[lines.len().saturating_sub(MAX_ENTRIES)..];
+            let temp = self.path.with_extension("tmp");
+            fs::write(&temp, kept.iter().map(|line| format!("{}\n", line)).collect::<String>())?;
+            fs::rename(&temp, &self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the wakes in the history, the oldest first. Lines which cannot be read, e.g. of a
+    /// later version, are skipped, and a missing file is an empty history.
+    pub fn wakes(&self) -> io::Result<Vec<Wake>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        Ok(content.lines()
+            .filter_map(|line| line.parse().ok())
+            .filter_map(|record| Wake::from_record(&record))
+            .collect())
+    }
+
+    /// Returns the wakes at or after `since`, the oldest first, e.g. to export them for a
+    /// dashboard. Wakes whose time cannot be read are skipped.
+    pub fn wakes_since(&self, since: SystemTime) -> io::Result<Vec<Wake>> {
+        Ok(self.wakes()?
+            .into_iter()
+            .filter(|wake| parse_timestamp(&wake.time).is_some_and(|time| time >= since))
+            .collect())
+    }
+
+    /// Returns the last wake of every host, the most recent first.
+    pub fn last_of_each(&self) -> io::Result<Vec<Wake>> {
+        let mut last: Vec<Wake> = Vec::new();
+        for wake in self.wakes()?.into_iter().rev() {
+            if !last.iter().any(|seen| seen.host == wake.host) {
+                last.push(wake);
+            }
+        }
+        Ok(last)
+    }
+
+    /// Returns the last `n` wakes of the host with the given name or MAC address, the most
+    /// recent first.
+    pub fn last(&self, host: &str, n: usize) -> io::Result<Vec<Wake>> {
+        Ok(self.wakes()?.into_iter().rev().filter(|wake| wake.is_of(host)).take(n).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use audit::Initiator;
+    use MacAddr;
+    use super::{History, Wake, MAX_ENTRIES, MAX_SIZE};
+
+    fn wake(host: &str, last: u8, verified: Option<bool>) -> Wake {
+        Wake::new(host,
+                  MacAddr::new([0, 0, 0, 0, 0, last]),
+                  Initiator::Cli("felix".to_string()),
+                  Ok(()),
+                  verified)
+    }
+
+    #[test]
+    fn test_record() {
+        let dir = env::temp_dir().join(format!("wol-history-{}", process::id()));
+        let history = History::new(dir.join("state").join("history.jsonl"));
+        assert_eq!(history.wakes().unwrap(), []);
+        let failed = Wake {
+            outcome: Err("network unreachable".to_string()),
+            ..wake("nas", 1, None)
+        };
+        for wake in &[wake("nas", 1, Some(false)), wake("desktop", 2, None), failed.clone()] {
+            history.record(wake).unwrap();
+        }
+        fs::write(history.path(),
+                  format!("{}not json\n", fs::read_to_string(history.path()).unwrap()))
+            .unwrap();
+        assert_eq!(history.wakes().unwrap().len(), 3);
+        let last = history.last_of_each().unwrap();
+        assert_eq!(last.iter().map(|wake| wake.host.as_str()).collect::<Vec<_>>(),
+                   ["nas", "desktop"]);
+        assert_eq!(last[0], failed);
+        let nas = history.last("00:00:00:00:00:01", 5).unwrap();
+        assert_eq!(nas.len(), 2);
+        assert_eq!(nas[1].verified, Some(false));
+        assert!(nas[1].age().unwrap() < Duration::from_secs(60));
+        assert_eq!(Wake { time: "yesterday".to_string(), ..failed }.age(), None);
+
+        // Records until the history was cut down, which makes the file shrink.
+        let mut len = 0;
+        loop {
+            history.record(&wake("desktop", 2, Some(true))).unwrap();
+            let new_len = fs::metadata(history.path()).unwrap().len();
+            if new_len < len {
+                break;
+            }
+            assert!(new_len <= MAX_SIZE + 1024);
+            len = new_len;
+        }
+        assert_eq!(history.wakes().unwrap().len(), MAX_ENTRIES);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_wakes_since() {
+        let dir = env::temp_dir().join(format!("wol-history-since-{}", process::id()));
+        let history = History::new(dir.join("history.jsonl"));
+        let old = Wake { time: "2020-01-01T00:00:00.000Z".to_string(), ..wake("nas", 1, None) };
+        let booted = wake("nas", 1, Some(true)).with_boot_time(Some(Duration::from_millis(41250)));
+        let whole = wake("desktop", 2, Some(true)).with_boot_time(Some(Duration::from_secs(9)));
+        for wake in &[&old, &booted, &whole] {
+            history.record(wake).unwrap();
+        }
+        let week_ago = SystemTime::now() - Duration::from_secs(7 * 86400);
+        assert_eq!(history.wakes_since(week_ago).unwrap(), [booted, whole]);
+        assert_eq!(history.wakes_since(UNIX_EPOCH).unwrap().len(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,1143 @@
+//! Known hosts, so that they can be woken by name instead of MAC address.
+//!
+//! The hosts file uses the same TOML subset as the configuration, with one table per host:
+//!
+//! ```toml
+//! version = 1
+//!
+//! [desktop]
+//! mac = "00:11:22:33:44:55"
+//! ip = "192.168.1.10"
+//! ```
+//!
+//! The `version` of the format and the IP address are optional. A host can require other hosts,
+//! which are woken first, e.g. a virtual machine host and the NAS holding its disks:
+//!
+//! ```toml
+//! [nas]
+//! mac = "00:11:22:33:44:66"
+//! ip = "192.168.1.20"
+//! verify = true
+//!
+//! [vm-host]
+//! mac = "00:11:22:33:44:77"
+//! requires = ["nas"]
+//! ```
+//!
+//! The hosts requiring a host are woken once it accepts connections (`verify = true`) or after
+//! a delay in seconds (`delay = 30`), otherwise right after its magic packet was sent.
+//!
+//! Hosts without SSH can name the TCP ports probed to verify them, which are tried in order,
+//! e.g. `verify_port = [3389, 445]` for a Windows machine. Checks wol cannot do itself can run
+//! a command instead, which counts the host as up if it exits with 0, e.g.
+//! `probe = { type = "command", cmd = "ssh nas true", timeout = "10s" }`.
+//!
+//! A host can be given a cooldown, e.g. `cooldown = "5m"`, within which further wakes after a
+//! wake are skipped, replacing the `cooldown` of the configuration.
+//!
+//! Hosts which need other send options than the configuration can set them as well, which the
+//! options given on the command line override in turn:
+//!
+//! ```toml
+//! [printer]
+//! mac = "00:11:22:33:44:88"
+//! port = 7
+//! broadcast = "192.168.2.255"
+//! repeat = 3
+//! password = "01:02:03:04:05:06"
+//! ```
+//!
+//! The password can be referenced instead, e.g. `password = { keyring = "printer" }`, see
+//! `secrets`.
+//!
+//! Tags slice large hosts files without maintaining groups, e.g. `tags = ["rack=3", "gpu"]`,
+//! selected with `wol wake --tag rack=3`, see `Hosts::tagged`, and `notes` keep a free-form text
+//! with a host, e.g. where it is.
+//!
+//! Hosts which cannot be reached from here are woken through a relay in their network, e.g.
+//! `relay = "http://10.8.0.2:8080"`, see `relay::Relay`. The table `groups` names groups of
+//! hosts, which are woken together like their members one by one, each per its own transport:
+//!
+//! ```toml
+//! [groups]
+//! lab = ["nas", "printer", "branch-pc"]
+//!
+//! [printer]
+//! mac = "00:11:22:33:44:88"
+//! broadcast = "ff05::1:3"
+//!
+//! [branch-pc]
+//! mac = "00:11:22:33:44:99"
+//! relay = "http://10.8.0.2:8080"
+//! ```
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::str::FromStr;
+use std::time::Duration;
+
+use config::ConfigError;
+use mac::Separator;
+use packet::parse_password;
+use paths::Paths;
+use output::{Format, Printer, Record, Value};
+use relay::Relay;
+use toml;
+use units::{self, Port, RepeatCount};
+use verify::CommandProbe;
+use {MacAddr, MacFormat, MagicPacket, WolClient};
+
+/// Returns the default hosts file, e.g. `~/.config/wol/hosts.toml`, see `Paths::hosts_file`.
+pub fn default_path() -> PathBuf {
+    Paths::new().hosts_file()
+}
+
+/// The version of the hosts file format, given by the top-level key `version`. Files without it
+/// are of version 0, from before the format had versions, see `Hosts::migrate`.
+pub const VERSION: u32 = 1;
+
+/// The hosts file written by `wol init`, without hosts but with commented examples.
+pub const TEMPLATE: &str = "\
+# The hosts wol knows by name, one table per host, e.g. `wol desktop` wakes the first example
+# once the # in front of its lines is removed. `wol hosts check` finds the mistakes.
+version = 1
+
+# The MAC address is required; the IP address is optional and lets --wait verify the host,
+# by default on the TCP port 22 of SSH.
+#[desktop]
+#mac = \"00:11:22:33:44:55\"
+#ip = \"192.168.1.10\"
+
+# Hosts without SSH name the ports to try, e.g. remote desktop and SMB on Windows, and can
+# have tags to select them by, e.g. `wol wake --tag room=office`, notes, their own send options
+# and cooldown.
+#[gaming-pc]
+#mac = \"00:11:22:33:44:66\"
+#ip = \"192.168.1.11\"
+#verify_port = [3389, 445]
+#tags = [\"room=office\", \"windows\"]
+#notes = \"under the desk, needs the switch on\"
+#broadcast = \"192.168.1.255\"
+#port = 7
+#repeat = 3
+#cooldown = \"5m\"
+
+# A host can require others, which are woken first, once they are up (verify = true) or
+# after a delay in seconds (delay = 30). Checks wol cannot do itself run a command.
+#[nas]
+#mac = \"00:11:22:33:44:77\"
+#ip = \"192.168.1.20\"
+#verify = true
+#probe = { type = \"command\", cmd = \"ssh nas true\", timeout = \"10s\" }
+#
+#[vm-host]
+#mac = \"00:11:22:33:44:88\"
+#requires = [\"nas\"]
+
+# Hosts in another network are woken through a relay there, running wol serve. Groups wake
+# their hosts together, e.g. `wol lab`, each through its own broadcast address or relay.
+#[branch-pc]
+#mac = \"00:11:22:33:44:99\"
+#relay = \"http://10.8.0.2:8080\"
+#
+#[groups]
+#lab = [\"nas\", \"branch-pc\"]
+";
+
+/// The table of the hosts file naming groups of hosts, which no host can be named after.
+pub const GROUPS_TABLE: &str = "groups";
+
+/// A host of the hosts file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    pub name: String,
+    pub mac: MacAddr,
+    /// The IP address of the host while it is up.
+    pub ip: Option<IpAddr>,
+    /// The names of the hosts which must be up before this host is woken.
+    pub requires: Vec<String>,
+    /// When the hosts requiring this host may be woken.
+    pub ready: Ready,
+    /// The TCP ports probed to verify that the host is up, tried in order; empty for those of
+    /// the configuration.
+    pub verify_ports: Vec<u16>,
+    /// The command verifying that the host is up instead of probing the TCP ports.
+    pub probe: Option<CommandProbe>,
+    /// The UDP port to send to instead of that of the configuration.
+    pub port: Option<u16>,
+    /// The broadcast or multicast address to send to instead of that of the configuration.
+    pub broadcast: Option<IpAddr>,
+    /// How often to send every packet instead of as configured.
+    pub repeat: Option<u32>,
+    /// How long after a wake further wakes of the host are skipped, instead of as configured.
+    pub cooldown: Option<Duration>,
+    /// The SecureOn password appended to the magic packet.
+    pub password: Option<[u8; 6]>,
+    /// The URL of the relay to wake the host through instead of sending the magic packet from
+    /// here, see `relay::Relay`.
+    pub relay: Option<String>,
+    /// The tags selecting the host, like `rack=3` or `gpu`, see `Host::has_tag`.
+    pub tags: Vec<String>,
+    /// A free-form note about the host, e.g. where it is.
+    pub notes: Option<String>,
+}
+
+impl Host {
+    /// Creates a host without IP address and requirements.
+    pub fn new(name: &str, mac: MacAddr) -> Host {
+        Host {
+            name: name.to_string(),
+            mac,
+            ip: None,
+            requires: Vec::new(),
+            ready: Ready::Sent,
+            verify_ports: Vec::new(),
+            probe: None,
+            port: None,
+            broadcast: None,
+            repeat: None,
+            cooldown: None,
+            password: None,
+            relay: None,
+            tags: Vec::new(),
+            notes: None,
+        }
+    }
+
+    /// Returns whether the host has the given tag: `key=value` only matches that tag, a tag
+    /// without `=` also matches the tags with that key and any value, e.g. `rack` matches
+    /// `rack=3`.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|own| {
+            own == tag || !tag.contains('=') && own.split('=').next() == Some(tag)
+        })
+    }
+
+    /// Returns the client sending to this host: `client` with the send options of the host.
+    pub fn client(&self, client: &WolClient) -> WolClient {
+        let mut client = client.clone();
+        if let Some(broadcast) = self.broadcast {
+            client = client.with_broadcast(broadcast);
+        }
+        if let Some(port) = self.port {
+            client = client.with_port(port);
+        }
+        if let Some(repeat) = self.repeat {
+            client = client.with_repeat(repeat);
+        }
+        client
+    }
+
+    /// Returns the magic packet waking this host, with its password if it has one.
+    pub fn packet(&self) -> MagicPacket {
+        let packet = MagicPacket::new(self.mac);
+        match self.password {
+            Some(password) => packet.with_password(password),
+            None => packet,
+        }
+    }
+
+    /// Returns the host as a record with all keys of the hosts file, in a fixed order and null
+    /// if unset, so that exports of different hosts files have the same columns.
+    pub fn record(&self) -> Record {
+        let join = |items: Vec<String>| if items.is_empty() { None } else { Some(items.join(" ")) };
+        let delay = match self.ready {
+            Ready::Delay(delay) => Value::Int(delay.as_secs()),
+            _ => Value::Null,
+        };
+        Record::new()
+            .field("name", self.name.as_str())
+            .field("mac", self.mac.to_string())
+            .field("ip", self.ip.map(|ip| ip.to_string()))
+            .field("requires", join(self.requires.clone()))
+            .field("delay", delay)
+            .field("verify", self.ready == Ready::Verify)
+            .field("verify_port", join(self.verify_ports.iter().map(u16::to_string).collect()))
+            .field("probe", self.probe.as_ref().map(|probe| probe.command()))
+            .field("port", self.port.map(u64::from))
+            .field("broadcast", self.broadcast.map(|ip| ip.to_string()))
+            .field("repeat", self.repeat.map(u64::from))
+            .field("cooldown", self.cooldown.map(units::format_duration))
+            .field("password", self.password.map(|p| MacAddr::new(p).to_string()))
+            .field("relay", self.relay.clone())
+            .field("tags", join(self.tags.clone()))
+            .field("notes", self.notes.clone())
+    }
+}
+
+/// The formats of `Hosts::export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per host, see `Host::record`.
+    Json,
+    /// A CSV header and one row per host, see `Host::record`.
+    Csv,
+    /// The `/etc/ethers` format: the MAC address and the name of every host.
+    Ethers,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ExportFormat, String> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            "ethers" => Ok(ExportFormat::Ethers),
+            _ => Err(format!("unknown export format '{}', expected json, csv or ethers", s)),
+        }
+    }
+}
+
+/// When a woken host is considered ready for the hosts requiring it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ready {
+    /// Right after the magic packet was sent.
+    Sent,
+    /// After a fixed delay.
+    Delay(Duration),
+    /// Once the host accepts TCP connections at its IP address.
+    Verify,
+}
+
+/// A group of hosts of the hosts file, woken together by its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    /// The names of the hosts of the group.
+    pub members: Vec<String>,
+}
+
+/// The hosts of a hosts file, in the order of the file, and its groups.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Hosts {
+    hosts: Vec<Host>,
+    groups: Vec<Group>,
+}
+
+impl Hosts {
+    /// Reads a hosts file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Hosts> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        content.parse().map_err(|err: ConfigError| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}", path.display(), err))
+        })
+    }
+
+    /// Replaces the hosts file at `path` with `content`, e.g. one received from another wol, and
+    /// returns the number of its hosts. Fails with `io::ErrorKind::InvalidData` and all errors of
+    /// `content`, see `check`, leaving the file as it is. Like `check`, this reads none of the
+    /// secrets of `content`. The file is replaced at once, so that a daemon reading it never
+    /// reads half of it.
+    pub fn replace<P: AsRef<Path>>(path: P, content: &str) -> io::Result<usize> {
+        let path = path.as_ref();
+        let errors: Vec<String> = Hosts::check(content).iter().map(|e| e.to_string()).collect();
+        if !errors.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("invalid hosts file: {}", errors.join(", "))));
+        }
+        let hosts = parse(content, false).0.len();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, content)?;
+        fs::rename(&temp, path)?;
+        Ok(hosts)
+    }
+
+    /// Checks a hosts file, returning all its errors, sorted by line, instead of only the first
+    /// as parsing does. Besides the errors of parsing, these are requirements of unknown hosts,
+    /// circular requirements and groups with unknown members, which otherwise only fail waking
+    /// the hosts involved. Secrets referenced by keyring or command are not read.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::hosts::Hosts;
+    ///
+    /// let errors = Hosts::check("[nas]\nmac = \"00:11\"\n[vm]\nmac = \"00:11:22:33:44:77\"\n\
+    ///                            requires = [\"san\"]\nip = \"vm.lan\"");
+    /// let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    /// assert_eq!(errors, ["2: expected a MAC address",
+    ///                     "4: host 'vm' requires unknown host 'san'",
+    ///                     "6: expected an IP address"]);
+    /// ```
+    pub fn check(s: &str) -> Vec<ConfigError> {
+        let (tables, groups, mut errors) = parse(s, false);
+        let hosts = Hosts {
+            hosts: tables.iter().map(|(_, host)| host.clone()).collect(),
+            groups: Vec::new(),
+        };
+        let index = |name: &str| hosts.iter().position(|host| host.name == name);
+        for (line, host) in &tables {
+            for name in &host.requires {
+                if hosts.get(name).is_none() {
+                    errors.push(ConfigError::new(*line,
+                                                 &format!("host '{}' requires unknown host '{}'",
+                                                          host.name,
+                                                          name)));
+                }
+            }
+            // Reports every cycle once, at the first of its hosts in the file.
+            if let Some(cycle) = hosts.cycle(host) {
+                if cycle.iter().all(|name| index(name) >= index(&host.name)) {
+                    errors.push(ConfigError::new(*line,
+                                                 &format!("circular requirement {}",
+                                                          cycle.join(" -> "))));
+                }
+            }
+        }
+        for (line, group) in &groups {
+            for name in group.members.iter().filter(|name| hosts.get(name).is_none()) {
+                errors.push(ConfigError::new(*line,
+                                             &format!("group '{}' has unknown member '{}'",
+                                                      group.name,
+                                                      name)));
+            }
+        }
+        errors.sort_by_key(|err| err.line);
+        errors
+    }
+
+    /// Returns the hosts of a circular requirement of `start`, from `start` back to it.
+    fn cycle<'a>(&'a self, start: &'a Host) -> Option<Vec<&'a str>> {
+        fn find<'a>(hosts: &'a Hosts,
+                    host: &'a Host,
+                    start: &'a Host,
+                    path: &mut Vec<&'a str>,
+                    seen: &mut Vec<&'a str>)
+                    -> bool {
+            for name in &host.requires {
+                if *name == start.name {
+                    path.push(&start.name);
+                    return true;
+                }
+                let next = match hosts.get(name) {
+                    Some(next) if !seen.contains(&next.name.as_str()) => next,
+                    _ => continue,
+                };
+                seen.push(&next.name);
+                path.push(&next.name);
+                if find(hosts, next, start, path, seen) {
+                    return true;
+                }
+                path.pop();
+            }
+            false
+        }
+
+        let mut path = vec![start.name.as_str()];
+        if find(self, start, start, &mut path, &mut Vec::new()) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Converts a hosts file of an older version to the current `VERSION`, keeping its comments
+    /// and layout, or returns `None` if it is of the current version.
+    ///
+    /// Version 0 only lacks the `version` key, which is added at the top.
+    pub fn migrate(s: &str) -> Result<Option<String>, ConfigError> {
+        let mut version = 0;
+        for entry in toml::parse(s)? {
+            if entry.table.is_empty() {
+                version = parse_version(&entry)?;
+            }
+        }
+        if version == VERSION {
+            return Ok(None);
+        }
+        Ok(Some(format!("version = {}\n\n{}", VERSION, s)))
+    }
+
+    /// Returns the host with the given name.
+    pub fn get(&self, name: &str) -> Option<&Host> {
+        self.hosts.iter().find(|host| host.name == name)
+    }
+
+    /// Returns the group with the given name.
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    /// Returns the hosts of the group with the given name, in the order of the group, or `None`
+    /// if there is no such group. Fails if a member is not a host of the file.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::hosts::Hosts;
+    ///
+    /// let hosts: Hosts = "[groups]\nlab = [\"nas\", \"pc\"]\n\
+    ///                     [pc]\nmac = \"00:11:22:33:44:55\"\nrelay = \"http://10.8.0.2:8080\"\n\
+    ///                     [nas]\nmac = \"00:11:22:33:44:66\"\nbroadcast = \"ff05::1:3\""
+    ///     .parse()
+    ///     .unwrap();
+    /// let lab = hosts.members("lab").unwrap().unwrap();
+    /// assert_eq!(lab.iter().map(|host| host.name.as_str()).collect::<Vec<_>>(), ["nas", "pc"]);
+    /// assert!(hosts.members("nas").is_none());
+    /// ```
+    pub fn members(&self, name: &str) -> Option<Result<Vec<&Host>, String>> {
+        let group = self.group(name)?;
+        Some(group.members
+            .iter()
+            .map(|member| {
+                self.get(member).ok_or_else(|| {
+                    format!("group '{}' has unknown member '{}'", group.name, member)
+                })
+            })
+            .collect())
+    }
+
+    /// Iterates over the groups.
+    pub fn groups(&self) -> slice::Iter<'_, Group> {
+        self.groups.iter()
+    }
+
+    /// Returns the hosts which have all the given tags, see `Host::has_tag`, in the order of the
+    /// hosts file.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::hosts::Hosts;
+    ///
+    /// let hosts: Hosts = "[a]\nmac = \"00:11:22:33:44:55\"\n\
+    ///                     tags = [\"rack=3\", \"role=render\"]\n\
+    ///                     [b]\nmac = \"00:11:22:33:44:66\"\n\
+    ///                     tags = [\"rack=4\", \"role=render\"]"
+    ///     .parse()
+    ///     .unwrap();
+    /// let names = |tags: &[&str]| -> Vec<String> {
+    ///     hosts.tagged(tags).iter().map(|host| host.name.clone()).collect()
+    /// };
+    /// assert_eq!(names(&["role=render"]), ["a", "b"]);
+    /// assert_eq!(names(&["rack=3", "role=render"]), ["a"]);
+    /// assert_eq!(names(&["rack"]), ["a", "b"]);
+    /// ```
+    pub fn tagged<S: AsRef<str>>(&self, tags: &[S]) -> Vec<&Host> {
+        self.hosts
+            .iter()
+            .filter(|host| tags.iter().all(|tag| host.has_tag(tag.as_ref())))
+            .collect()
+    }
+
+    /// Returns the first host with the given MAC address.
+    pub fn by_mac(&self, mac: MacAddr) -> Option<&Host> {
+        self.hosts.iter().find(|host| host.mac == mac)
+    }
+
+    /// Returns the hosts to wake for the given targets, including the hosts they require, in
+    /// stages: every host requires only hosts of earlier stages. Targets which are not in the
+    /// hosts file are only looked up for their requirements.
+    ///
+    /// Fails if a required host is unknown or the requirements form a cycle.
+    pub fn plan(&self, targets: &[Host]) -> Result<Vec<Vec<Host>>, String> {
+        fn visit<'a>(hosts: &'a Hosts,
+                     host: &'a Host,
+                     path: &mut Vec<&'a str>,
+                     stages: &mut HashMap<&'a str, usize>,
+                     order: &mut Vec<&'a Host>)
+                     -> Result<usize, String> {
+            if let Some(&stage) = stages.get(host.name.as_str()) {
+                return Ok(stage);
+            }
+            if path.contains(&host.name.as_str()) {
+                path.push(&host.name);
+                return Err(format!("circular requirement {}", path.join(" -> ")));
+            }
+            path.push(&host.name);
+            let mut stage = 0;
+            for name in &host.requires {
+                let required = hosts.get(name).ok_or_else(|| {
+                    format!("host '{}' requires unknown host '{}'", host.name, name)
+                })?;
+                stage = stage.max(visit(hosts, required, path, stages, order)? + 1);
+            }
+            path.pop();
+            stages.insert(&host.name, stage);
+            order.push(host);
+            Ok(stage)
+        }
+
+        let mut stages = HashMap::new();
+        let mut order = Vec::new();
+        for target in targets {
+            visit(self, target, &mut Vec::new(), &mut stages, &mut order)?;
+        }
+        let mut plan: Vec<Vec<Host>> = Vec::new();
+        for host in order {
+            let stage = stages[host.name.as_str()];
+            if plan.len() <= stage {
+                plan.resize(stage + 1, Vec::new());
+            }
+            plan[stage].push(host.clone());
+        }
+        Ok(plan)
+    }
+
+    /// Iterates over the hosts.
+    pub fn iter(&self) -> slice::Iter<'_, Host> {
+        self.hosts.iter()
+    }
+
+    /// Returns the number of hosts.
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    /// Returns whether there are no hosts.
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    /// Writes the hosts in the given format, in the order of the hosts file.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::hosts::{ExportFormat, Hosts};
+    ///
+    /// let hosts: Hosts = "[nas]\nmac = \"00:11:22:33:44:AA\"".parse().unwrap();
+    /// let mut out = Vec::new();
+    /// hosts.export(ExportFormat::Ethers, &mut out).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "00:11:22:33:44:aa nas\n");
+    /// ```
+    pub fn export<W: Write>(&self, format: ExportFormat, mut out: W) -> io::Result<()> {
+        let format = match format {
+            ExportFormat::Json => Format::Json,
+            ExportFormat::Csv => Format::Csv,
+            ExportFormat::Ethers => {
+                let mac_format = MacFormat {
+                    separator: Separator::Colon,
+                    uppercase: false,
+                };
+                for host in &self.hosts {
+                    writeln!(out, "{} {}", host.mac.format(mac_format), host.name)?;
+                }
+                return Ok(());
+            },
+        };
+        let mut printer = Printer::new(format, out);
+        for host in &self.hosts {
+            printer.print(&host.record())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Hosts {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Hosts, ConfigError> {
+        let (hosts, groups, errors) = parse(s, true);
+        match errors.into_iter().next() {
+            Some(err) => Err(err),
+            None => {
+                Ok(Hosts {
+                    hosts: hosts.into_iter().map(|(_, host)| host).collect(),
+                    groups: groups.into_iter().map(|(_, group)| group).collect(),
+                })
+            },
+        }
+    }
+}
+
+/// The hosts of a hosts file with the first lines of their tables, its groups with their lines,
+/// and its errors.
+type Parsed = (Vec<(usize, Host)>, Vec<(usize, Group)>, Vec<ConfigError>);
+
+/// Parses a hosts file, collecting the errors of all entries instead of stopping at the first.
+/// Returns the valid hosts with the first lines of their tables and the groups with theirs.
+/// Without `read_secrets`, passwords referenced by keyring or command are only checked to be
+/// references and left unset, so that no command of the file runs.
+fn parse(s: &str, read_secrets: bool) -> Parsed {
+    let entries = match toml::parse(s) {
+        Ok(entries) => entries,
+        Err(err) => return (Vec::new(), Vec::new(), vec![err]),
+    };
+    let mut errors = Vec::new();
+    let mut groups: Vec<(usize, Group)> = Vec::new();
+    // The MAC address of a host is only known at the end of its table.
+    let mut tables: Vec<(usize, Option<MacAddr>, Host)> = Vec::new();
+    // The hosts whose MAC address was reported as invalid, not to report it as missing.
+    let mut invalid_macs = Vec::new();
+    // The table of a duplicate host, whose entries are skipped.
+    let mut duplicate: Option<String> = None;
+    for entry in entries {
+        if entry.table.is_empty() {
+            if let Err(err) = parse_version(&entry) {
+                errors.push(err);
+            }
+            continue;
+        }
+        if entry.table == GROUPS_TABLE {
+            match entry.as_str_array() {
+                Ok(_) if groups.iter().any(|(_, group)| group.name == entry.key) => {
+                    errors.push(entry.error(&format!("duplicate group '{}'", entry.key)));
+                },
+                Ok(members) => {
+                    let group = Group {
+                        name: entry.key.clone(),
+                        members,
+                    };
+                    groups.push((entry.line, group));
+                },
+                Err(err) => errors.push(err),
+            }
+            continue;
+        }
+        if duplicate.as_ref() == Some(&entry.table) {
+            continue;
+        }
+        if tables.last().map(|t| &t.2.name) != Some(&entry.table) {
+            if tables.iter().any(|t| t.2.name == entry.table) {
+                errors.push(entry.error(&format!("duplicate host '{}'", entry.table)));
+                duplicate = Some(entry.table);
+                continue;
+            }
+            let host = Host::new(&entry.table, MacAddr::new([0; 6]));
+            tables.push((entry.line, None, host));
+        }
+        let (_, ref mut mac, ref mut host) = *tables.last_mut().unwrap();
+        if let Err(err) = parse_entry(&entry, mac, host, read_secrets) {
+            if entry.key == "mac" {
+                invalid_macs.push(entry.table.clone());
+            }
+            errors.push(err);
+        }
+    }
+    let mut hosts = Vec::new();
+    for (line, mac, mut host) in tables {
+        match mac {
+            Some(mac) => {
+                host.mac = mac;
+                hosts.push((line, host));
+            },
+            None if invalid_macs.contains(&host.name) => {},
+            None => {
+                errors.push(ConfigError::new(line,
+                                             &format!("host '{}' has no MAC address", host.name)))
+            },
+        }
+    }
+    for (line, group) in &groups {
+        if hosts.iter().any(|(_, host)| host.name == group.name) {
+            errors.push(ConfigError::new(*line,
+                                         &format!("group '{}' has the name of a host",
+                                                  group.name)));
+        }
+    }
+    (hosts, groups, errors)
+}
+
+/// Parses the top-level `version` key, failing for versions newer than `VERSION`.
+fn parse_version(entry: &toml::Entry) -> Result<u32, ConfigError> {
+    if entry.key != "version" {
+        return Err(entry.unknown());
+    }
+    let version = entry.as_int()?;
+    if version > VERSION {
+        return Err(entry.error(&format!("hosts file version {} is newer than version {} \
+                                         supported by this wol",
+                                        version,
+                                        VERSION)));
+    }
+    Ok(version)
+}
+
+/// Applies a `key = value` line to the host of its table, see `parse` for `read_secrets`.
+fn parse_entry(entry: &toml::Entry,
+               mac: &mut Option<MacAddr>,
+               host: &mut Host,
+               read_secrets: bool)
+               -> Result<(), ConfigError> {
+    match entry.key.as_str() {
+        "mac" => *mac = Some(entry.parse("a MAC address")?),
+        "ip" => host.ip = Some(entry.parse("an IP address")?),
+        "requires" => host.requires = entry.as_str_array()?,
+        "delay" => host.ready = Ready::Delay(Duration::from_secs(entry.as_int()?)),
+        "verify_port" => {
+            let ports = entry.as_checked_array::<Port>()?;
+            host.verify_ports = ports.into_iter().map(Port::get).collect()
+        },
+        "probe" => host.probe = Some(parse_probe(entry)?),
+        "port" => host.port = Some(entry.as_checked::<Port>()?.get()),
+        "broadcast" => host.broadcast = Some(entry.parse("an IP address")?),
+        "repeat" => host.repeat = Some(entry.as_checked::<RepeatCount>()?.get()),
+        "cooldown" => host.cooldown = Some(entry.as_duration()?),
+        "relay" => {
+            let url = entry.as_str()?;
+            Relay::new(url).map_err(|err| entry.error(&format!("invalid relay: {}", err)))?;
+            host.relay = Some(url.to_string());
+        },
+        "tags" => {
+            let tags = entry.as_str_array()?;
+            if let Some(tag) = tags.iter().find(|tag| !is_tag(tag)) {
+                return Err(entry.error(&format!("invalid tag '{}', expected a word or \
+                                                 key=value without spaces",
+                                                tag)));
+            }
+            host.tags = tags;
+        },
+        "notes" => host.notes = Some(entry.as_str()?.to_string()),
+        "password" if !read_secrets && entry.as_secret_ref()?.is_some() => {},
+        "password" => {
+            let password = parse_password(&entry.as_secret()?)
+                .map_err(|_| entry.error("expected a password like 01:02:03:04:05:06"))?;
+            host.password = Some(password);
+        },
+        "verify" => {
+            if entry.as_bool()? {
+                host.ready = Ready::Verify;
+            }
+        },
+        _ => return Err(entry.unknown()),
+    }
+    Ok(())
+}
+
+/// Returns whether `tag` is a valid tag: not empty, without whitespace and with a key before
+/// any `=`, as tags are exported separated by spaces.
+pub fn is_tag(tag: &str) -> bool {
+    !tag.is_empty() && !tag.starts_with('=') && !tag.contains(char::is_whitespace)
+}
+
+/// Parses a probe like `{ type = "command", cmd = "ssh nas true", timeout = "10s" }`.
+fn parse_probe(entry: &toml::Entry) -> Result<CommandProbe, ConfigError> {
+    let (mut command, mut timeout) = (None, None);
+    let mut command_type = false;
+    for key in entry.as_table()? {
+        match key.key.as_str() {
+            "type" if key.as_str()? == "command" => command_type = true,
+            "type" => return Err(key.error("unknown probe type, expected \"command\"")),
+            "cmd" => command = Some(CommandProbe::new(key.as_str()?)),
+            "timeout" => timeout = Some(key.as_duration()?),
+            _ => return Err(key.unknown()),
+        }
+    }
+    if !command_type {
+        return Err(entry.error("missing type = \"command\" of the probe"));
+    }
+    let command = command.ok_or_else(|| entry.error("missing the cmd of the probe"))?;
+    Ok(match timeout {
+        Some(timeout) => command.with_timeout(timeout),
+        None => command,
+    })
+}
+
+impl<'a> IntoIterator for &'a Hosts {
+    type Item = &'a Host;
+    type IntoIter = slice::Iter<'a, Host>;
+
+    fn into_iter(self) -> slice::Iter<'a, Host> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::time::Duration;
+
+    use super::{ExportFormat, Host, Hosts, Ready, TEMPLATE};
+    use verify::CommandProbe;
+    use {MacAddr, WolClient};
+
+    #[test]
+    fn test_parse() {
+        let hosts: Hosts = "[desktop]\nmac = \"00:11:22:33:44:55\"\nip = \"192.168.1.10\"\n\
+                            verify_port = [3389, 445]\n\
+                            [nas]\nmac = \"00:11:22:33:44:66\""
+            .parse()
+            .unwrap();
+        assert_eq!(hosts.len(), 2);
+        let desktop = hosts.get("desktop").unwrap();
+        assert_eq!(desktop.mac, MacAddr::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]));
+        assert_eq!(desktop.ip, Some("192.168.1.10".parse().unwrap()));
+        assert_eq!(desktop.verify_ports, vec![3389, 445]);
+        assert_eq!(hosts.get("nas").unwrap().ip, None);
+        assert!(hosts.get("nas").unwrap().verify_ports.is_empty());
+        assert!(hosts.get("laptop").is_none());
+        let nas = hosts.by_mac(MacAddr::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x66]));
+        assert_eq!(nas.map(|host| host.name.as_str()), Some("nas"));
+    }
+
+    #[test]
+    fn test_template() {
+        assert!(TEMPLATE.parse::<Hosts>().unwrap().is_empty());
+        assert_eq!(Hosts::check(TEMPLATE), []);
+        // The examples are valid hosts once the # in front of them is removed.
+        let uncommented: String = TEMPLATE.lines()
+            .map(|line| match line.strip_prefix('#') {
+                Some(example) if !example.starts_with(' ') => format!("{}\n", example),
+                _ => format!("{}\n", line),
+            })
+            .collect();
+        let hosts: Hosts = uncommented.parse().unwrap();
+        assert_eq!(hosts.len(), 5);
+        assert_eq!(hosts.get("vm-host").unwrap().requires, ["nas"]);
+        assert_eq!(hosts.members("lab").unwrap().unwrap().len(), 2);
+        assert_eq!(Hosts::check(&uncommented), []);
+    }
+
+    #[test]
+    fn test_parse_probe() {
+        let probe = |s: &str| {
+            format!("[nas]\nmac = \"00:11:22:33:44:66\"\nprobe = {}", s)
+                .parse::<Hosts>()
+                .map(|hosts| hosts.get("nas").unwrap().probe.clone().unwrap())
+                .map_err(|err| err.to_string())
+        };
+        let nas = probe("{ type = \"command\", cmd = \"ssh nas true\", timeout = \"5s\" }");
+        let nas = nas.unwrap();
+        assert_eq!(nas.command(), "ssh nas true");
+        assert_eq!(nas.timeout(), Duration::from_secs(5));
+        assert_eq!(probe("{ type = \"command\", cmd = \"true\" }").unwrap().timeout(),
+                   CommandProbe::DEFAULT_TIMEOUT);
+        assert_eq!(probe("{ type = \"http\", cmd = \"true\" }").unwrap_err(),
+                   "3: unknown probe type, expected \"command\"");
+        assert_eq!(probe("{ type = \"command\" }").unwrap_err(), "3: missing the cmd of the probe");
+        assert_eq!(probe("{ type = \"command\", command = \"true\" }").unwrap_err(),
+                   "3: unknown key 'nas.probe.command'");
+    }
+
+    #[test]
+    fn test_send_options() {
+        let hosts: Hosts = "[printer]\nmac = \"00:11:22:33:44:88\"\nport = 7\n\
+                            broadcast = \"192.168.2.255\"\nrepeat = 3\n\
+                            password = \"01:02:03:04:05:06\"\n\
+                            [nas]\nmac = \"00:11:22:33:44:66\""
+            .parse()
+            .unwrap();
+        let base = WolClient::v4().with_port(9);
+        let printer = hosts.get("printer").unwrap();
+        let client = printer.client(&base);
+        assert_eq!(client.destination(), "192.168.2.255:7".parse().unwrap());
+        assert_eq!(client.repeat(), 3);
+        assert_eq!(printer.packet().password(), Some([1, 2, 3, 4, 5, 6]));
+        let nas = hosts.get("nas").unwrap();
+        assert_eq!(nas.client(&base), base);
+        assert_eq!(nas.packet().password(), None);
+
+        let err = "[a]\nmac = \"00:11:22:33:44:55\"\npassword = \"secret\"".parse::<Hosts>();
+        assert_eq!(err.unwrap_err().to_string(), "3: expected a password like 01:02:03:04:05:06");
+        let hosts: Hosts = "[a]\nmac = \"00:11:22:33:44:55\"\n\
+                            password = { cmd = \"echo 01:02:03:04:05:06\" }"
+            .parse()
+            .unwrap();
+        assert_eq!(hosts.get("a").unwrap().password, Some([1, 2, 3, 4, 5, 6]));
+        let err = "[a]\nmac = \"00:11:22:33:44:55\"\npassword = { vault = \"a\" }".parse::<Hosts>();
+        assert_eq!(err.unwrap_err().to_string(), "3: unknown key 'a.password.vault'");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |s: &str| s.parse::<Hosts>().unwrap_err().to_string();
+        assert_eq!(err("mac = \"00:11:22:33:44:55\""), "1: unknown key 'mac'");
+        assert_eq!(err("[nas]\nip = \"10.0.0.1\""), "2: host 'nas' has no MAC address");
+        assert_eq!(err("[nas]\nmac = \"00:11\""), "2: expected a MAC address");
+        assert_eq!(err("[nas]\nmac = \"00:11:22:33:44:55\"\nrepeat = 500"),
+                   "3: invalid repeat count '500', expected 1 to 100");
+        assert_eq!(err("[nas]\nmac = \"00:11:22:33:44:55\"\nport = 0"),
+                   "3: invalid port '0', expected 1 to 65535");
+        assert_eq!(err("[a]\nmac = \"00:11:22:33:44:55\"\n[b]\nmac = \"00:11:22:33:44:66\"\n\
+                        [a]\nip = \"10.0.0.1\""),
+                   "6: duplicate host 'a'");
+        assert_eq!(err("[a]\nmac = \"00:11:22:33:44:55\"\nrequires = \"b\""),
+                   "3: expected an array of strings");
+    }
+
+    #[test]
+    fn test_export() {
+        let hosts: Hosts = "[nas]\nmac = \"00:11:22:33:44:66\"\nip = \"192.168.1.20\"\n\
+                            verify = true\nverify_port = [22, 445]\n\
+                            tags = [\"rack=3\", \"gpu\"]\nnotes = \"in the closet\"\n\
+                            [vm]\nmac = \"00:11:22:33:44:77\"\nrequires = [\"nas\"]\n\
+                            delay = 30\nport = 7\ncooldown = \"5m\"\n\
+                            probe = { type = \"command\", cmd = \"ssh vm true\" }\n\
+                            password = \"01:02:03:04:05:06\""
+            .parse()
+            .unwrap();
+        let export = |format: &str| {
+            let mut out = Vec::new();
+            hosts.export(format.parse().unwrap(), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(export("json"),
+                   "{\"name\":\"nas\",\"mac\":\"00:11:22:33:44:66\",\"ip\":\"192.168.1.20\",\
+                    \"requires\":null,\"delay\":null,\"verify\":true,\"verify_port\":\"22 445\",\
+                    \"probe\":null,\"port\":null,\"broadcast\":null,\"repeat\":null,\
+                    \"cooldown\":null,\"password\":null,\"relay\":null,\"tags\":\"rack=3 gpu\",\
+                    \"notes\":\"in the closet\"}\n\
+                    {\"name\":\"vm\",\"mac\":\"00:11:22:33:44:77\",\"ip\":null,\
+                    \"requires\":\"nas\",\"delay\":30,\"verify\":false,\"verify_port\":null,\
+                    \"probe\":\"ssh vm true\",\"port\":7,\"broadcast\":null,\"repeat\":null,\
+                    \"cooldown\":\"5m\",\"password\":\"01:02:03:04:05:06\",\"relay\":null,\
+                    \"tags\":null,\"notes\":null}\n");
+        assert_eq!(export("csv"),
+                   "name,mac,ip,requires,delay,verify,verify_port,probe,port,broadcast,repeat,\
+                    cooldown,password,relay,tags,notes\n\
+                    nas,00:11:22:33:44:66,192.168.1.20,,,true,22 445,,,,,,,,rack=3 gpu,\
+                    in the closet\n\
+                    vm,00:11:22:33:44:77,,nas,30,false,,ssh vm true,7,,,5m,01:02:03:04:05:06,,,\n");
+        assert_eq!(export("ethers"), "00:11:22:33:44:66 nas\n00:11:22:33:44:77 vm\n");
+        assert_eq!("yaml".parse::<ExportFormat>().unwrap_err(),
+                   "unknown export format 'yaml', expected json, csv or ethers");
+    }
+
+    #[test]
+    fn test_groups() {
+        let hosts: Hosts = "[groups]\nlab = [\"printer\", \"nas\", \"branch-pc\"]\nempty = []\n\
+                            [nas]\nmac = \"00:11:22:33:44:66\"\nbroadcast = \"10.0.2.255\"\n\
+                            [printer]\nmac = \"00:11:22:33:44:88\"\nbroadcast = \"ff05::1:3\"\n\
+                            [branch-pc]\nmac = \"00:11:22:33:44:99\"\n\
+                            relay = \"http://10.8.0.2:8080\""
+            .parse()
+            .unwrap();
+        assert_eq!(hosts.len(), 3);
+        assert_eq!(hosts.groups().map(|group| group.name.as_str()).collect::<Vec<_>>(),
+                   ["lab", "empty"]);
+        let lab = hosts.members("lab").unwrap().unwrap();
+        let names: Vec<&str> = lab.iter().map(|host| host.name.as_str()).collect();
+        assert_eq!(names, ["printer", "nas", "branch-pc"]);
+        assert_eq!(lab[2].relay.as_deref(), Some("http://10.8.0.2:8080"));
+        assert_eq!(hosts.members("empty"), Some(Ok(Vec::new())));
+        assert_eq!(hosts.members("nas"), None);
+
+        let err = |s: &str| s.parse::<Hosts>().unwrap_err().to_string();
+        assert_eq!(err("[groups]\nlab = \"nas\""), "2: expected an array of strings");
+        assert_eq!(err("[groups]\nnas = []\n[nas]\nmac = \"00:11:22:33:44:66\""),
+                   "2: group 'nas' has the name of a host");
+        assert!(err("[nas]\nmac = \"00:11:22:33:44:66\"\nrelay = \"https://relay\"")
+            .starts_with("3: invalid relay"));
+        let unknown: Hosts = "[groups]\nlab = [\"nas\"]".parse().unwrap();
+        assert_eq!(unknown.members("lab"),
+                   Some(Err("group 'lab' has unknown member 'nas'".into())));
+        assert_eq!(Hosts::check("[groups]\nlab = [\"nas\"]")[0].to_string(),
+                   "2: group 'lab' has unknown member 'nas'");
+    }
+
+    #[test]
+    fn test_tags() {
+        let hosts: Hosts = "[a]\nmac = \"00:11:22:33:44:55\"\ntags = [\"rack=3\", \"gpu\"]\n\
+                            notes = \"top shelf\"\n\
+                            [b]\nmac = \"00:11:22:33:44:66\"\ntags = [\"rack=30\"]\n\
+                            [c]\nmac = \"00:11:22:33:44:77\""
+            .parse()
+            .unwrap();
+        let a = hosts.get("a").unwrap();
+        assert_eq!(a.tags, ["rack=3", "gpu"]);
+        assert_eq!(a.notes.as_deref(), Some("top shelf"));
+        assert!(a.has_tag("rack=3") && a.has_tag("rack") && a.has_tag("gpu"));
+        assert!(!a.has_tag("rack=30") && !a.has_tag("gpu=1") && !a.has_tag("rac"));
+        let names = |tags: &[&str]| -> Vec<String> {
+            hosts.tagged(tags).iter().map(|host| host.name.clone()).collect()
+        };
+        assert_eq!(names(&["rack"]), ["a", "b"]);
+        assert_eq!(names(&["rack=30"]), ["b"]);
+        assert_eq!(names(&["rack", "gpu"]), ["a"]);
+        assert_eq!(names(&[]), ["a", "b", "c"]);
+
+        let err = |s: &str| s.parse::<Hosts>().unwrap_err().to_string();
+        assert_eq!(err("[a]\nmac = \"00:11:22:33:44:55\"\ntags = [\"rack 3\"]"),
+                   "3: invalid tag 'rack 3', expected a word or key=value without spaces");
+        assert_eq!(err("[a]\nmac = \"00:11:22:33:44:55\"\ntags = [\"=3\"]"),
+                   "3: invalid tag '=3', expected a word or key=value without spaces");
+    }
+
+    #[test]
+    fn test_check() {
+        let check = |s: &str| {
+            Hosts::check(s).iter().map(|err| err.to_string()).collect::<Vec<_>>()
+        };
+        assert!(check("version = 1\n[a]\nmac = \"00:00:00:00:00:01\"").is_empty());
+        assert_eq!(check("version = 2\n\
+                          [a]\nmac = \"00:00:00:00:00:01\"\nrequires = [\"b\"]\n\
+                          [b]\nmac = \"00:00:00:00:00:02\"\nrequires = [\"c\"]\n\
+                          [c]\nmac = \"00:00:00:00:00:03\"\nrequires = [\"a\"]\nwake = true\n\
+                          [a]\nmac = \"00:00:00:00:00:04\"\nip = \"10.0.0.4\"\n\
+                          [d]\nip = \"10.0.0.5\""),
+                   ["1: hosts file version 2 is newer than version 1 supported by this wol",
+                    "3: circular requirement a -> b -> c -> a",
+                    "11: unknown key 'c.wake'",
+                    "13: duplicate host 'a'",
+                    "16: host 'd' has no MAC address"]);
+        assert_eq!(check("[a]\nmac = \"00:00:00:00:00:01\"\n["), ["3: missing ']'"]);
+    }
+
+    #[test]
+    fn test_replace() {
+        let dir = env::temp_dir().join(format!("wol-hosts-{}", process::id()));
+        let path = dir.join("hosts.toml");
+        let content = "# lab\n[a]\nmac = \"00:00:00:00:00:01\"\n";
+        assert_eq!(Hosts::replace(&path, content).unwrap(), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        let err = Hosts::replace(&path, "[b]\nip = \"10.0.0.2\"").unwrap_err();
+        assert_eq!(err.to_string(), "invalid hosts file: 2: host 'b' has no MAC address");
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        // Neither checking nor replacing runs the commands of secrets.
+        let ran = dir.join("ran");
+        let secret = format!("[a]\nmac = \"00:00:00:00:00:01\"\n\
+                              password = {{ cmd = \"touch {}\" }}",
+                             ran.display());
+        assert_eq!(Hosts::check(&secret), []);
+        assert_eq!(Hosts::replace(&path, &secret).unwrap(), 1);
+        assert!(!ran.exists());
+        let invalid = "[a]\nmac = \"00:00:00:00:00:01\"\npassword = { pw = \"x\" }";
+        let err = Hosts::replace(&path, invalid).unwrap_err();
+        assert_eq!(err.to_string(), "invalid hosts file: 3: unknown key 'a.password.pw'");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrate() {
+        let old = "# lab\n[a]\nmac = \"00:00:00:00:00:01\"\n";
+        let migrated = Hosts::migrate(old).unwrap().unwrap();
+        assert_eq!(migrated, "version = 1\n\n# lab\n[a]\nmac = \"00:00:00:00:00:01\"\n");
+        assert_eq!(migrated.parse::<Hosts>().unwrap(), old.parse::<Hosts>().unwrap());
+        assert_eq!(Hosts::migrate(&migrated).unwrap(), None);
+        assert_eq!(Hosts::migrate("version = 3").unwrap_err().to_string(),
+                   "1: hosts file version 3 is newer than version 1 supported by this wol");
+    }
+
+    #[test]
+    fn test_plan() {
+        let hosts: Hosts = "[router]\nmac = \"00:00:00:00:00:01\"\ndelay = 30\n\
+                            [nas]\nmac = \"00:00:00:00:00:02\"\nrequires = [\"router\"]\n\
+                            verify = true\n\
+                            [vm]\nmac = \"00:00:00:00:00:03\"\nrequires = [\"nas\", \"router\"]\n\
+                            [desktop]\nmac = \"00:00:00:00:00:04\""
+            .parse()
+            .unwrap();
+        assert_eq!(hosts.get("router").unwrap().ready, Ready::Delay(Duration::from_secs(30)));
+        assert_eq!(hosts.get("nas").unwrap().ready, Ready::Verify);
+        let plan = |targets: &[&str]| {
+            let targets: Vec<_> = targets.iter().map(|t| hosts.get(t).unwrap().clone()).collect();
+            let names = |stage: &Vec<Host>| {
+                stage.iter().map(|h| h.name.as_str()).collect::<Vec<_>>().join(",")
+            };
+            hosts.plan(&targets).map(|plan| plan.iter().map(names).collect::<Vec<_>>())
+        };
+        assert_eq!(plan(&["vm", "desktop"]).unwrap(), vec!["router,desktop", "nas", "vm"]);
+        assert_eq!(plan(&["nas", "router"]).unwrap(), vec!["router", "nas"]);
+
+        let cyclic: Hosts = "[a]\nmac = \"00:00:00:00:00:01\"\nrequires = [\"b\"]\n\
+                             [b]\nmac = \"00:00:00:00:00:02\"\nrequires = [\"a\"]\n\
+                             [c]\nmac = \"00:00:00:00:00:03\"\nrequires = [\"d\"]"
+            .parse()
+            .unwrap();
+        let a = cyclic.get("a").unwrap().clone();
+        assert_eq!(cyclic.plan(&[a]).unwrap_err(), "circular requirement a -> b -> a");
+        let c = cyclic.get("c").unwrap().clone();
+        assert_eq!(cyclic.plan(&[c]).unwrap_err(), "host 'c' requires unknown host 'd'");
+    }
+}
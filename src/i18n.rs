@@ -0,0 +1,985 @@
+//! The messages of the command line tool in the language of the user.
+//!
+//! The language is taken from `LC_ALL`, `LC_MESSAGES` or `LANG`, the first one set, e.g.
+//! `LANG=de_DE.UTF-8` for German, and can be chosen with `set_lang`, e.g. for `--lang`. Every
+//! `Message` has a text in each `Lang`; messages which are not in the catalog, e.g. the details
+//! of errors which follow a message, stay English, as do records for scripts, listings like the
+//! `--dry-run` dumps and the findings of `doctor`, log messages and the help.
+use std::env;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use resolve::Source;
+use MacAddr;
+
+/// A language of the message catalog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    English,
+    German,
+}
+
+impl Lang {
+    /// Returns the language of a locale like "de_DE.UTF-8" or "en", `None` for languages
+    /// without catalog.
+    pub fn from_locale(locale: &str) -> Option<Lang> {
+        let language = locale.split(['_', '.', '@', '-']).next().unwrap_or_default();
+        match language.to_lowercase().as_str() {
+            "en" | "c" | "posix" => Some(Lang::English),
+            "de" => Some(Lang::German),
+            _ => None,
+        }
+    }
+
+    /// Returns the language of the locale of the environment, English if none is set or it has
+    /// no catalog.
+    pub fn from_env() -> Lang {
+        Lang::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Returns the language of the locale variables looked up with `var`, like `from_env`.
+    pub fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Lang {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| var(name).filter(|value| !value.is_empty()))
+            .next()
+            .and_then(|locale| Lang::from_locale(&locale))
+            .unwrap_or_default()
+    }
+
+    /// Returns the code of the language, "en" or "de".
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::English => "en",
+            Lang::German => "de",
+        }
+    }
+}
+
+impl fmt::Display for Lang {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Lang, String> {
+        Lang::from_locale(s).ok_or_else(|| format!("unknown language '{}', expected en or de", s))
+    }
+}
+
+static LANG: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the language of the messages, for the whole process.
+pub fn set_lang(lang: Lang) {
+    LANG.store(lang as u8, Ordering::Relaxed);
+}
+
+/// Returns the language of the messages, English unless set otherwise.
+pub fn lang() -> Lang {
+    match LANG.load(Ordering::Relaxed) {
+        1 => Lang::German,
+        _ => Lang::English,
+    }
+}
+
+/// A message of the catalog, displayed in the language of `lang`.
+///
+/// #Example
+///
+/// ```
+/// use wol::i18n::{Lang, Message};
+///
+/// assert_eq!(Message::ConfirmWake(3).to_string(), "Wake 3 hosts?");
+/// assert_eq!(Message::ConfirmWake(3).in_lang(Lang::German), "3 Rechner wecken?");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message<'a> {
+    /// The start of an error message, followed by the error.
+    Error,
+    /// The start of the message about an invalid argument, followed by the problem.
+    IllegalArgument,
+    /// The start of the message about a magic packet which could not be sent.
+    SendError,
+    /// The answers of a yes/no question, the default last.
+    YesNo,
+    /// The question whether to wake that many hosts, followed by `YesNo`.
+    ConfirmWake(usize),
+    /// Neither a target nor an option choosing hosts was given.
+    NoMacGiven,
+    /// Several of the options choosing the hosts to wake were given.
+    ExclusiveTargets,
+    /// The user picked no host.
+    NoHostSelected,
+    /// The user declined to wake the hosts.
+    NoHostWoken,
+    /// The wake history is empty.
+    NoHostWokenYet,
+    /// The wake history has no wake of the host.
+    NotWokenYet(&'a str),
+    /// A wake interrupted by ctrl-C: the hosts woken of all, those verified up and those which
+    /// were not woken.
+    Interrupted {
+        woken: usize,
+        total: usize,
+        up: usize,
+        not_woken: &'a [&'a str],
+    },
+    /// A file was written.
+    Wrote(&'a Path),
+    /// The start of the error about the log destination, followed by the error.
+    CannotConnect(&'a str),
+    /// The destination of `--log-to` is unknown on this platform.
+    UnsupportedLogDestination(&'a str),
+    /// The start of the error about the value of an option, followed by the error.
+    InvalidOption {
+        name: &'a str,
+        value: &'a str,
+    },
+    /// A tag of `--tag` is neither a word nor key=value.
+    InvalidTag(&'a str),
+    /// The `--format` is unknown to the command.
+    UnsupportedFormat(&'a str),
+    /// Both `--password` and `--password-file` were given.
+    PasswordOptionsExclusive,
+    /// The start of the error about a SecureOn password from the given source, followed by the
+    /// error.
+    InvalidPassword(&'a str),
+    /// The errors of the configuration from the environment, followed by the error.
+    Environment,
+    /// The start of the error about the configuration files, followed by the error.
+    CannotReadConfig,
+    /// The network given by `--cidr` has no broadcast address.
+    NoBroadcastAddress(&'a str),
+    /// Both `--broadcast` and `--cidr` were given.
+    BroadcastOrCidr,
+    /// `--vpn` was given with `--interface` or `--source`.
+    VpnSetsInterface,
+    /// `--internet` was given with an option setting the destination.
+    InternetSetsAddress,
+    /// `--multicast-group` was given with an option setting the destination.
+    MulticastReplacesBroadcast,
+    /// The start of the error about the interface of `--vpn`, followed by the error.
+    CannotUseVpn(&'a str),
+    /// The destination of `--vpn` is not routed through the interface.
+    VpnNeedsNetwork(&'a str),
+    /// The host of `--internet` has no address.
+    NoAddressFound,
+    /// A target is neither a MAC address, whose parse error is given, nor in the hosts file,
+    /// the only resolver.
+    NotAHost {
+        target: &'a str,
+        err: &'a str,
+        hosts_file: &'a Path,
+    },
+    /// A target is neither a MAC address, whose parse error is given, nor known to the
+    /// resolvers, of which that of the leases reads the given file.
+    NotKnown {
+        target: &'a str,
+        err: &'a str,
+        sources: &'a [Source],
+        leases: Option<&'a Path>,
+    },
+    /// The start of the error about resolving a target, followed by the error.
+    CannotResolve(&'a str),
+    /// The address of `--ip --solicit` did not answer.
+    NoMacAnswer(&'a str),
+    /// The address of `--ip` is not in the neighbor table.
+    NotInNeighborTable(&'a str),
+    /// The start of the error about the neighbor table, followed by the error.
+    CannotReadNeighbors,
+    /// No host of the hosts file has all the tags of `--tag`.
+    NoHostTagged {
+        hosts_file: &'a Path,
+        tags: &'a [String],
+    },
+    /// The start of an error about a file, followed by the error.
+    CannotRead(&'a Path),
+    /// The start of an error about a file or directory, followed by the error.
+    CannotCreate(&'a Path),
+    /// The start of an error about a file, followed by the error.
+    CannotWrite(&'a Path),
+    /// The start of the error about the hosts file, followed by the error.
+    CannotReadHosts(&'a Path),
+    /// The start of the error about the hosts file, followed by the error.
+    CannotWriteHosts(&'a Path),
+    /// The start of the error about the hosts file, followed by the error.
+    CannotReplaceHosts(&'a Path),
+    /// The start of the error about a job file, followed by the error.
+    CannotReadJob(&'a Path),
+    /// The start of the error about the leases of a DHCP server, followed by the error.
+    CannotReadLeases(&'a Path),
+    /// The start of the error about the audit log, followed by the error.
+    CannotOpenAuditLog(&'a Path),
+    /// The start of the error about the audit log, followed by the error.
+    CannotWriteAuditLog,
+    /// The start of the error about the standard output, followed by the error.
+    CannotWriteOutput,
+    /// The start of the error about the wake history, followed by the error.
+    CannotReadHistory(&'a Path),
+    /// The start of the error about the last-known addresses, followed by the error.
+    CannotWriteSeen(&'a Path),
+    /// The platform has no configuration directory for `init` to write to.
+    UnknownConfigDir,
+    /// A file `init` would write exists.
+    Exists(&'a Path),
+    /// The state directory, where the wake history is kept, is unknown.
+    NoHistory,
+    /// The state directory, where the last-known addresses are kept, is unknown.
+    NoStateDir,
+    /// The start of the error about a socket, followed by the error.
+    CannotListen(&'a str),
+    /// `--udp` was given without the relay token to check signed requests with.
+    UdpNeedsKey,
+    /// The hosts file was migrated.
+    HostsMigrated {
+        path: &'a Path,
+        count: usize,
+        version: u32,
+    },
+    /// The hosts file has no errors, but an older version.
+    HostsOutdated {
+        path: &'a Path,
+        count: usize,
+        version: u32,
+    },
+    /// The hosts file has no errors.
+    HostsValid {
+        path: &'a Path,
+        count: usize,
+    },
+    /// `hosts sync` was given without `--via`.
+    SyncNeedsVia,
+    /// The start of the error about the daemon, followed by the error.
+    CannotPull(&'a str),
+    /// The start of the error about the daemon, followed by the error.
+    CannotPush(&'a str),
+    /// The hosts file was replaced by that of the daemon.
+    HostsPulled {
+        path: &'a Path,
+        count: usize,
+        url: &'a str,
+    },
+    /// The hosts file of the daemon was replaced by the local one.
+    HostsPushed {
+        url: &'a str,
+        count: usize,
+        path: &'a Path,
+    },
+    /// The last-known addresses were written.
+    HostsSeen {
+        path: &'a Path,
+        seen: usize,
+        total: usize,
+    },
+    /// Both `--raw` and `--promiscuous` were given.
+    PromiscuousIncludesRaw,
+    /// The start of the error about `--raw`, followed by the error.
+    CannotReceiveRaw,
+    /// The start of the error about `--promiscuous`, followed by the error.
+    CannotReceivePromiscuous(&'a str),
+    /// The start of the error about a magic packet which could not be resent, followed by the
+    /// error.
+    CannotSendTo {
+        mac: MacAddr,
+        to: SocketAddr,
+    },
+    /// `--verify` was given for several hosts.
+    VerifyNeedsSingleHost,
+    /// `--via` was given with a SecureOn password or `--changed-when-down`.
+    ViaExcludesPassword,
+    /// The first option was given with one of the others.
+    CannotCombine(&'a str, &'a [&'a str]),
+    /// `--raw` was given with a relay, a strategy or verification.
+    RawExcludes,
+    /// The first option was given without the second.
+    OptionNeeds(&'a str, &'a str),
+    /// The MAC address of `--source-mac` is no unicast address.
+    NotANetworkCard(MacAddr),
+    /// What `--dry-run` would ask the relay at the URL.
+    WouldAsk {
+        url: &'a str,
+        mac: MacAddr,
+    },
+    /// What `--dry-run --raw` would send, by the given path and from the given source.
+    WouldSend {
+        mac: MacAddr,
+        path: &'a str,
+        source: Option<MacAddr>,
+    },
+    /// Waking that many hosts without a terminal to ask needs `--yes`.
+    NeedsConfirmation(usize),
+    /// The relay at the URL cannot verify hosts.
+    CannotVerifyVia(&'a str),
+    /// The start of the error about the verification method, followed by the error.
+    CannotVerifyWith(&'a str),
+    /// The host was skipped, as a host it requires is not up.
+    RequiredNotUp(&'a str),
+    /// The start of the error about waking a host through a relay, followed by the error.
+    CannotWakeVia {
+        host: &'a str,
+        url: &'a str,
+    },
+    /// The MAC address is no address of a network card, see `MacAddr::suspicious`, and the
+    /// check rejects it.
+    RejectedMac(MacAddr),
+}
+
+impl<'a> Message<'a> {
+    /// Returns the message in the given language instead of that of `lang`.
+    pub fn in_lang(&self, lang: Lang) -> String {
+        Localized(lang, self).to_string()
+    }
+
+    /// Returns whether `answer` of the user means yes, in English or the given language.
+    pub fn is_yes(answer: &str, lang: Lang) -> bool {
+        let answer = answer.trim().to_lowercase();
+        answer == "y" || answer == "yes" ||
+        (lang == Lang::German && (answer == "j" || answer == "ja"))
+    }
+}
+
+impl<'a> fmt::Display for Message<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Localized(lang(), self).fmt(f)
+    }
+}
+
+/// A message in a language.
+struct Localized<'a, 'b>(Lang, &'b Message<'a>);
+
+impl<'a, 'b> fmt::Display for Localized<'a, 'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Lang::*;
+        use self::Message::*;
+
+        match (self.0, *self.1) {
+            (English, Error) => f.write_str("Error:"),
+            (German, Error) => f.write_str("Fehler:"),
+            (English, IllegalArgument) => f.write_str("Illegal argument:"),
+            (German, IllegalArgument) => f.write_str("Ungültiges Argument:"),
+            (English, SendError) => f.write_str("Error during sending:"),
+            (German, SendError) => f.write_str("Fehler beim Senden:"),
+            (English, YesNo) => f.write_str("[y/N]"),
+            (German, YesNo) => f.write_str("[j/N]"),
+            (English, ConfirmWake(count)) => write!(f, "Wake {} hosts?", count),
+            (German, ConfirmWake(count)) => write!(f, "{} Rechner wecken?", count),
+            (English, NoMacGiven) => f.write_str("No MAC address given"),
+            (German, NoMacGiven) => f.write_str("Keine MAC-Adresse angegeben"),
+            (English, ExclusiveTargets) => {
+                f.write_str("Either MAC addresses, --range, --ip, --all, --tag, --job or --pick \
+                             can be given")
+            },
+            (German, ExclusiveTargets) => {
+                f.write_str("Es können nur entweder MAC-Adressen, --range, --ip, --all, --tag, \
+                             --job oder --pick angegeben werden")
+            },
+            (English, NoHostSelected) => f.write_str("No host selected"),
+            (German, NoHostSelected) => f.write_str("Kein Rechner ausgewählt"),
+            (English, NoHostWoken) => f.write_str("No host woken"),
+            (German, NoHostWoken) => f.write_str("Kein Rechner geweckt"),
+            (English, NoHostWokenYet) => f.write_str("No host was woken yet"),
+            (German, NoHostWokenYet) => f.write_str("Es wurde noch kein Rechner geweckt"),
+            (English, NotWokenYet(host)) => write!(f, "{} was not woken yet", host),
+            (German, NotWokenYet(host)) => write!(f, "{} wurde noch nicht geweckt", host),
+            (English, Interrupted { woken, total, up, not_woken }) => {
+                write!(f, "Interrupted: {} of {} hosts woken, {} verified up", woken, total, up)?;
+                if !not_woken.is_empty() {
+                    write!(f, "; not woken: {}", not_woken.join(", "))?;
+                }
+                Ok(())
+            },
+            (German, Interrupted { woken, total, up, not_woken }) => {
+                write!(f,
+                       "Abgebrochen: {} von {} Rechnern geweckt, {} davon laufen",
+                       woken,
+                       total,
+                       up)?;
+                if !not_woken.is_empty() {
+                    write!(f, "; nicht geweckt: {}", not_woken.join(", "))?;
+                }
+                Ok(())
+            },
+            (English, Wrote(path)) => write!(f, "Wrote {}", path.display()),
+            (German, Wrote(path)) => write!(f, "{} geschrieben", path.display()),
+            (English, CannotConnect(to)) => write!(f, "cannot connect to {}", to),
+            (German, CannotConnect(to)) => write!(f, "keine Verbindung zu {}", to),
+            (English, UnsupportedLogDestination(destination)) => {
+                write!(f, "unsupported log destination '{}'", destination)
+            },
+            (German, UnsupportedLogDestination(destination)) => {
+                write!(f, "nicht unterstütztes Protokollziel '{}'", destination)
+            },
+            (English, InvalidOption { name, value }) => {
+                write!(f, "invalid --{} '{}'", name, value)
+            },
+            (German, InvalidOption { name, value }) => {
+                write!(f, "ungültiges --{} '{}'", name, value)
+            },
+            (English, InvalidTag(tag)) => {
+                write!(f, "invalid --tag '{}', expected a word or key=value", tag)
+            },
+            (German, InvalidTag(tag)) => {
+                write!(f, "ungültiges --tag '{}', erwartet ein Wort oder Schlüssel=Wert", tag)
+            },
+            (English, UnsupportedFormat(format)) => write!(f, "unsupported --format '{}'", format),
+            (German, UnsupportedFormat(format)) => {
+                write!(f, "nicht unterstütztes --format '{}'", format)
+            },
+            (English, PasswordOptionsExclusive) => {
+                f.write_str("--password and --password-file exclude each other")
+            },
+            (German, PasswordOptionsExclusive) => {
+                f.write_str("--password und --password-file schließen sich aus")
+            },
+            (English, InvalidPassword(source)) => {
+                write!(f, "invalid SecureOn password in {}", source)
+            },
+            (German, InvalidPassword(source)) => {
+                write!(f, "ungültiges SecureOn-Passwort in {}", source)
+            },
+            (English, Environment) => f.write_str("environment"),
+            (German, Environment) => f.write_str("Umgebung"),
+            (English, CannotReadConfig) => f.write_str("cannot read configuration"),
+            (German, CannotReadConfig) => f.write_str("die Konfiguration ist nicht lesbar"),
+            (English, NoBroadcastAddress(cidr)) => write!(f, "{} has no broadcast address", cidr),
+            (German, NoBroadcastAddress(cidr)) => write!(f, "{} hat keine Broadcast-Adresse", cidr),
+            (English, BroadcastOrCidr) => f.write_str("either --broadcast or --cidr can be given"),
+            (German, BroadcastOrCidr) => {
+                f.write_str("es kann nur entweder --broadcast oder --cidr angegeben werden")
+            },
+            (English, VpnSetsInterface) => {
+                f.write_str("--vpn sets the interface and source, so they cannot be given")
+            },
+            (German, VpnSetsInterface) => {
+                f.write_str("--vpn legt die Schnittstelle und die Quelle fest, sie können daher \
+                             nicht angegeben werden")
+            },
+            (English, InternetSetsAddress) => {
+                f.write_str("--internet sets the address and port, so neither --broadcast, \
+                             --cidr, --port nor --vpn can be given")
+            },
+            (German, InternetSetsAddress) => {
+                f.write_str("--internet legt die Adresse und den Port fest, daher können weder \
+                             --broadcast, --cidr, --port noch --vpn angegeben werden")
+            },
+            (English, MulticastReplacesBroadcast) => {
+                f.write_str("--multicast-group replaces the broadcast address, so neither \
+                             --broadcast, --cidr nor --internet can be given")
+            },
+            (German, MulticastReplacesBroadcast) => {
+                f.write_str("--multicast-group ersetzt die Broadcast-Adresse, daher können weder \
+                             --broadcast, --cidr noch --internet angegeben werden")
+            },
+            (English, CannotUseVpn(interface)) => {
+                write!(f, "cannot use VPN interface {}", interface)
+            },
+            (German, CannotUseVpn(interface)) => {
+                write!(f, "die VPN-Schnittstelle {} ist nicht verwendbar", interface)
+            },
+            (English, VpnNeedsNetwork(interface)) => {
+                write!(f,
+                       "--vpn {} needs the network behind the VPN, e.g. --cidr 192.168.1.0/24",
+                       interface)
+            },
+            (German, VpnNeedsNetwork(interface)) => {
+                write!(f,
+                       "--vpn {} braucht das Netz hinter dem VPN, z. B. --cidr 192.168.1.0/24",
+                       interface)
+            },
+            (English, NoAddressFound) => f.write_str("no address found"),
+            (German, NoAddressFound) => f.write_str("keine Adresse gefunden"),
+            (English, NotAHost { target, err, hosts_file }) => {
+                write!(f,
+                       "'{}' is neither a MAC address ({}) nor a host in {}",
+                       target,
+                       err,
+                       hosts_file.display())
+            },
+            (German, NotAHost { target, err, hosts_file }) => {
+                write!(f,
+                       "'{}' ist weder eine MAC-Adresse ({}) noch ein Rechner in {}",
+                       target,
+                       err,
+                       hosts_file.display())
+            },
+            (English, NotKnown { target, err, sources, leases }) => {
+                let names = sources_list(English, sources, leases);
+                write!(f, "'{}' is neither a MAC address ({}) nor known to {}", target, err, names)
+            },
+            (German, NotKnown { target, err, sources, leases }) => {
+                let names = sources_list(German, sources, leases);
+                write!(f,
+                       "'{}' ist weder eine MAC-Adresse ({}) noch {} bekannt",
+                       target,
+                       err,
+                       names)
+            },
+            (English, CannotResolve(target)) => write!(f, "cannot resolve {}", target),
+            (German, CannotResolve(target)) => write!(f, "{} ist nicht auflösbar", target),
+            (English, NoMacAnswer(addr)) => {
+                write!(f, "{} did not answer with its MAC address", addr)
+            },
+            (German, NoMacAnswer(addr)) => {
+                write!(f, "{} hat nicht mit seiner MAC-Adresse geantwortet", addr)
+            },
+            (English, NotInNeighborTable(addr)) => {
+                write!(f,
+                       "{} is not in the neighbor table, which only holds hosts seen in the last \
+                        minutes, try --solicit",
+                       addr)
+            },
+            (German, NotInNeighborTable(addr)) => {
+                write!(f,
+                       "{} ist nicht in der Nachbartabelle, die nur die Rechner der letzten \
+                        Minuten enthält, --solicit fragt nach der MAC-Adresse",
+                       addr)
+            },
+            (English, CannotReadNeighbors) => f.write_str("cannot read the neighbor table"),
+            (German, CannotReadNeighbors) => f.write_str("die Nachbartabelle ist nicht lesbar"),
+            (English, NoHostTagged { hosts_file, tags }) => {
+                write!(f, "no host in {} has the tags {}", hosts_file.display(), tags.join(", "))
+            },
+            (German, NoHostTagged { hosts_file, tags }) => {
+                write!(f,
+                       "kein Rechner in {} hat die Tags {}",
+                       hosts_file.display(),
+                       tags.join(", "))
+            },
+            (English, CannotRead(path)) => write!(f, "cannot read {}", path.display()),
+            (German, CannotRead(path)) => write!(f, "{} ist nicht lesbar", path.display()),
+            (English, CannotCreate(path)) => write!(f, "cannot create {}", path.display()),
+            (German, CannotCreate(path)) => {
+                write!(f, "{} kann nicht angelegt werden", path.display())
+            },
+            (English, CannotWrite(path)) => write!(f, "cannot write {}", path.display()),
+            (German, CannotWrite(path)) => {
+                write!(f, "{} kann nicht geschrieben werden", path.display())
+            },
+            (English, CannotReadHosts(path)) => {
+                write!(f, "cannot read hosts file {}", path.display())
+            },
+            (German, CannotReadHosts(path)) => {
+                write!(f, "die Hosts-Datei {} ist nicht lesbar", path.display())
+            },
+            (English, CannotWriteHosts(path)) => {
+                write!(f, "cannot write hosts file {}", path.display())
+            },
+            (German, CannotWriteHosts(path)) => {
+                write!(f, "die Hosts-Datei {} kann nicht geschrieben werden", path.display())
+            },
+            (English, CannotReplaceHosts(path)) => {
+                write!(f, "cannot replace hosts file {}", path.display())
+            },
+            (German, CannotReplaceHosts(path)) => {
+                write!(f, "die Hosts-Datei {} kann nicht ersetzt werden", path.display())
+            },
+            (English, CannotReadJob(path)) => write!(f, "cannot read the job {}", path.display()),
+            (German, CannotReadJob(path)) => {
+                write!(f, "der Auftrag {} ist nicht lesbar", path.display())
+            },
+            (English, CannotReadLeases(path)) => write!(f, "cannot read leases {}", path.display()),
+            (German, CannotReadLeases(path)) => {
+                write!(f, "die Leases {} sind nicht lesbar", path.display())
+            },
+            (English, CannotOpenAuditLog(path)) => {
+                write!(f, "cannot open audit log {}", path.display())
+            },
+            (German, CannotOpenAuditLog(path)) => {
+                write!(f, "das Audit-Log {} kann nicht geöffnet werden", path.display())
+            },
+            (English, CannotWriteAuditLog) => f.write_str("cannot write audit log"),
+            (German, CannotWriteAuditLog) => {
+                f.write_str("das Audit-Log kann nicht geschrieben werden")
+            },
+            (English, CannotWriteOutput) => f.write_str("cannot write the output"),
+            (German, CannotWriteOutput) => {
+                f.write_str("die Ausgabe kann nicht geschrieben werden")
+            },
+            (English, CannotReadHistory(path)) => {
+                write!(f, "cannot read wake history {}", path.display())
+            },
+            (German, CannotReadHistory(path)) => {
+                write!(f, "der Weckverlauf {} ist nicht lesbar", path.display())
+            },
+            (English, CannotWriteSeen(path)) => {
+                write!(f, "cannot write last-known addresses {}", path.display())
+            },
+            (German, CannotWriteSeen(path)) => {
+                write!(f,
+                       "die zuletzt bekannten Adressen {} können nicht geschrieben werden",
+                       path.display())
+            },
+            (English, UnknownConfigDir) => {
+                f.write_str("the configuration directory is unknown, use --config-dir")
+            },
+            (German, UnknownConfigDir) => {
+                f.write_str("das Konfigurationsverzeichnis ist unbekannt, --config-dir gibt eines \
+                             an")
+            },
+            (English, Exists(path)) => {
+                write!(f, "{} exists, use --force to overwrite it", path.display())
+            },
+            (German, Exists(path)) => {
+                write!(f, "{} existiert, --force überschreibt die Datei", path.display())
+            },
+            (English, NoHistory) => {
+                f.write_str("there is no wake history without a state directory")
+            },
+            (German, NoHistory) => {
+                f.write_str("ohne Zustandsverzeichnis gibt es keinen Weckverlauf")
+            },
+            (English, NoStateDir) => {
+                f.write_str("cannot find the state directory to keep the addresses in")
+            },
+            (German, NoStateDir) => {
+                f.write_str("kein Zustandsverzeichnis gefunden, um die Adressen abzulegen")
+            },
+            (English, CannotListen(address)) => write!(f, "cannot listen on {}", address),
+            (German, CannotListen(address)) => {
+                write!(f, "auf {} kann nicht empfangen werden", address)
+            },
+            (English, UdpNeedsKey) => {
+                f.write_str("--udp needs a key, set [relay] token in the configuration")
+            },
+            (German, UdpNeedsKey) => {
+                f.write_str("--udp braucht einen Schlüssel, [relay] token in der Konfiguration \
+                             setzt ihn")
+            },
+            (English, HostsMigrated { path, count, version }) => {
+                write!(f,
+                       "{}: {} hosts, migrated to version {}",
+                       path.display(),
+                       count,
+                       version)
+            },
+            (German, HostsMigrated { path, count, version }) => {
+                write!(f,
+                       "{}: {} Rechner, auf Version {} umgestellt",
+                       path.display(),
+                       count,
+                       version)
+            },
+            (English, HostsOutdated { path, count, version }) => {
+                write!(f,
+                       "{}: {} hosts, of an older version; --migrate converts it to version {}",
+                       path.display(),
+                       count,
+                       version)
+            },
+            (German, HostsOutdated { path, count, version }) => {
+                write!(f,
+                       "{}: {} Rechner, in einer älteren Version; --migrate stellt sie auf \
+                        Version {} um",
+                       path.display(),
+                       count,
+                       version)
+            },
+            (English, HostsValid { path, count }) => {
+                write!(f, "{}: {} hosts, no errors", path.display(), count)
+            },
+            (German, HostsValid { path, count }) => {
+                write!(f, "{}: {} Rechner, keine Fehler", path.display(), count)
+            },
+            (English, SyncNeedsVia) => {
+                f.write_str("sync needs the --via URL of the daemon to sync with")
+            },
+            (German, SyncNeedsVia) => {
+                f.write_str("sync braucht mit --via die URL des Dienstes, mit dem abgeglichen \
+                             wird")
+            },
+            (English, CannotPull(url)) => write!(f, "cannot pull the hosts file from {}", url),
+            (German, CannotPull(url)) => {
+                write!(f, "die Hosts-Datei kann nicht von {} geholt werden", url)
+            },
+            (English, CannotPush(url)) => write!(f, "cannot push the hosts file to {}", url),
+            (German, CannotPush(url)) => {
+                write!(f, "die Hosts-Datei kann nicht an {} übertragen werden", url)
+            },
+            (English, HostsPulled { path, count, url }) => {
+                write!(f, "{}: {} hosts pulled from {}", path.display(), count, url)
+            },
+            (German, HostsPulled { path, count, url }) => {
+                write!(f, "{}: {} Rechner von {} geholt", path.display(), count, url)
+            },
+            (English, HostsPushed { url, count, path }) => {
+                write!(f, "{}: {} hosts pushed from {}", url, count, path.display())
+            },
+            (German, HostsPushed { url, count, path }) => {
+                write!(f, "{}: {} Rechner aus {} übertragen", url, count, path.display())
+            },
+            (English, HostsSeen { path, seen, total }) => {
+                write!(f, "{}: {} of {} hosts seen", path.display(), seen, total)
+            },
+            (German, HostsSeen { path, seen, total }) => {
+                write!(f, "{}: {} von {} Rechnern gesehen", path.display(), seen, total)
+            },
+            (English, PromiscuousIncludesRaw) => {
+                f.write_str("--promiscuous also receives the raw Ethernet frames of --raw")
+            },
+            (German, PromiscuousIncludesRaw) => {
+                f.write_str("--promiscuous empfängt die rohen Ethernet-Frames von --raw bereits \
+                             mit")
+            },
+            (English, CannotReceiveRaw) => f.write_str("cannot receive raw Ethernet frames"),
+            (German, CannotReceiveRaw) => {
+                f.write_str("rohe Ethernet-Frames können nicht empfangen werden")
+            },
+            (English, CannotReceivePromiscuous(interface)) => {
+                write!(f, "cannot receive in promiscuous mode on {}", interface)
+            },
+            (German, CannotReceivePromiscuous(interface)) => {
+                write!(f, "auf {} kann nicht im Promiscuous-Modus empfangen werden", interface)
+            },
+            (English, CannotSendTo { mac, to }) => {
+                write!(f, "cannot send the packet for {} to {}", mac, to)
+            },
+            (German, CannotSendTo { mac, to }) => {
+                write!(f, "das Paket für {} kann nicht an {} gesendet werden", mac, to)
+            },
+            (English, VerifyNeedsSingleHost) => {
+                f.write_str("--verify needs a single host, use --wait to verify hosts with a \
+                             known IP")
+            },
+            (German, VerifyNeedsSingleHost) => {
+                f.write_str("--verify braucht genau einen Rechner, --wait prüft Rechner mit \
+                             bekannter IP")
+            },
+            (English, ViaExcludesPassword) => {
+                f.write_str("--via cannot be combined with a SecureOn password or \
+                             --changed-when-down")
+            },
+            (German, ViaExcludesPassword) => {
+                f.write_str("--via kann nicht mit einem SecureOn-Passwort oder \
+                             --changed-when-down kombiniert werden")
+            },
+            (English, CannotCombine(option, others)) => {
+                write!(f, "{} cannot be combined with {}", option, or_list(others, "or"))
+            },
+            (German, CannotCombine(option, others)) => {
+                write!(f,
+                       "{} kann nicht mit {} kombiniert werden",
+                       option,
+                       or_list(others, "oder"))
+            },
+            (English, RawExcludes) => {
+                f.write_str("--raw cannot be combined with --via, --internet, --strategy auto or \
+                             verification")
+            },
+            (German, RawExcludes) => {
+                f.write_str("--raw kann nicht mit --via, --internet, --strategy auto oder einer \
+                             Prüfung kombiniert werden")
+            },
+            (English, OptionNeeds(option, needed)) => write!(f, "{} needs {}", option, needed),
+            (German, OptionNeeds(option, needed)) => write!(f, "{} braucht {}", option, needed),
+            (English, NotANetworkCard(mac)) => {
+                write!(f, "--source-mac {} is no address of a network card", mac)
+            },
+            (German, NotANetworkCard(mac)) => {
+                write!(f, "--source-mac {} ist keine Adresse einer Netzwerkkarte", mac)
+            },
+            (English, WouldAsk { url, mac }) => write!(f, "Would ask {} to wake {}", url, mac),
+            (German, WouldAsk { url, mac }) => {
+                write!(f, "Würde {} bitten, {} zu wecken", url, mac)
+            },
+            (English, WouldSend { mac, path, source }) => {
+                write!(f, "Would send the packet for {} as {}", mac, path)?;
+                if let Some(source) = source {
+                    write!(f, " from {}", source)?;
+                }
+                Ok(())
+            },
+            (German, WouldSend { mac, path, source }) => {
+                write!(f, "Würde das Paket für {} als {}", mac, path)?;
+                if let Some(source) = source {
+                    write!(f, " von {}", source)?;
+                }
+                f.write_str(" senden")
+            },
+            (English, NeedsConfirmation(count)) => {
+                write!(f, "waking {} hosts needs confirmation, pass --yes to skip it", count)
+            },
+            (German, NeedsConfirmation(count)) => {
+                write!(f,
+                       "{} Rechner zu wecken muss bestätigt werden, --yes überspringt das",
+                       count)
+            },
+            (English, CannotVerifyVia(url)) => {
+                write!(f, "{} cannot verify hosts, use an http:// relay", url)
+            },
+            (German, CannotVerifyVia(url)) => {
+                write!(f, "{} kann keine Rechner prüfen, ein http://-Relay kann es", url)
+            },
+            (English, CannotVerifyWith(method)) => write!(f, "cannot verify with {}", method),
+            (German, CannotVerifyWith(method)) => {
+                write!(f, "mit {} kann nicht geprüft werden", method)
+            },
+            (English, RequiredNotUp(host)) => {
+                write!(f, "{} was not woken, a host it requires is not up", host)
+            },
+            (German, RequiredNotUp(host)) => {
+                write!(f, "{} wurde nicht geweckt, ein benötigter Rechner läuft nicht", host)
+            },
+            (English, CannotWakeVia { host, url }) => {
+                write!(f, "cannot wake {} via {}", host, url)
+            },
+            (German, CannotWakeVia { host, url }) => {
+                write!(f, "{} kann nicht über {} geweckt werden", host, url)
+            },
+            (English, RejectedMac(mac)) => {
+                let reason = if mac.is_broadcast() {
+                    "the broadcast address"
+                } else if mac.is_zero() {
+                    "the all-zero address"
+                } else {
+                    "a multicast address"
+                };
+                write!(f, "{} is {}, pass --mac-check warn to wake it anyway", mac, reason)
+            },
+            (German, RejectedMac(mac)) => {
+                let reason = if mac.is_broadcast() {
+                    "die Broadcast-Adresse"
+                } else if mac.is_zero() {
+                    "die Null-Adresse"
+                } else {
+                    "eine Multicast-Adresse"
+                };
+                write!(f,
+                       "{} ist {}, mit --mac-check warn wird der Rechner trotzdem geweckt",
+                       mac,
+                       reason)
+            },
+        }
+    }
+}
+
+/// Joins `items` with commas and `or` before the last, e.g. "a, b or c".
+fn or_list<S: AsRef<str>>(items: &[S], or: &str) -> String {
+    match items.split_last() {
+        None => String::new(),
+        Some((last, [])) => last.as_ref().to_string(),
+        Some((last, rest)) => {
+            let rest: Vec<&str> = rest.iter().map(AsRef::as_ref).collect();
+            format!("{} {} {}", rest.join(", "), or, last.as_ref())
+        },
+    }
+}
+
+/// Returns the resolvers of `sources` as the object of "known to", e.g. "the hosts file or the
+/// neighbor table", where the leases resolver reads `leases`.
+fn sources_list(lang: Lang, sources: &[Source], leases: Option<&Path>) -> String {
+    let names: Vec<String> = sources.iter()
+        .map(|source| {
+            let leases = leases.map(|path| format!(" {}", path.display())).unwrap_or_default();
+            match (lang, source) {
+                (Lang::English, Source::Hosts) => "the hosts file".to_string(),
+                (Lang::German, Source::Hosts) => "der Hosts-Datei".to_string(),
+                (Lang::English, Source::Arp) => "the neighbor table".to_string(),
+                (Lang::German, Source::Arp) => "der Nachbartabelle".to_string(),
+                (Lang::English, Source::Leases) => format!("the DHCP leases{}", leases),
+                (Lang::German, Source::Leases) => format!("den DHCP-Leases{}", leases),
+                (Lang::English, Source::Dns) => "DNS".to_string(),
+                (Lang::German, Source::Dns) => "dem DNS".to_string(),
+            }
+        })
+        .collect();
+    match (lang, names.is_empty()) {
+        (Lang::English, true) => "any resolver".to_string(),
+        (Lang::German, true) => "irgendeinem Resolver".to_string(),
+        (Lang::English, false) => or_list(&names, "or"),
+        (Lang::German, false) => or_list(&names, "oder"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use resolve::Source;
+    use MacAddr;
+
+    use super::{Lang, Message};
+
+    #[test]
+    fn test_lang() {
+        assert_eq!(Lang::from_locale("de_DE.UTF-8"), Some(Lang::German));
+        assert_eq!(Lang::from_locale("de_AT@euro"), Some(Lang::German));
+        assert_eq!(Lang::from_locale("C.UTF-8"), Some(Lang::English));
+        assert_eq!(Lang::from_locale("fr_FR"), None);
+        assert_eq!("de".parse::<Lang>(), Ok(Lang::German));
+        assert_eq!("fr".parse::<Lang>(), Err("unknown language 'fr', expected en or de".into()));
+        assert_eq!(Lang::German.to_string(), "de");
+        let vars = |values: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                values.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(Lang::from_vars(vars(&[("LANG", "de_DE.UTF-8")])), Lang::German);
+        assert_eq!(Lang::from_vars(vars(&[("LC_ALL", "C"), ("LANG", "de_DE")])), Lang::English);
+        assert_eq!(Lang::from_vars(vars(&[("LC_ALL", ""), ("LANG", "de_DE")])), Lang::German);
+        assert_eq!(Lang::from_vars(vars(&[("LANG", "sv_SE")])), Lang::English);
+        assert_eq!(Lang::from_vars(vars(&[])), Lang::English);
+    }
+
+    #[test]
+    fn test_messages() {
+        let not_woken = ["nas", "printer"];
+        let interrupted = Message::Interrupted {
+            woken: 1,
+            total: 3,
+            up: 0,
+            not_woken: &not_woken,
+        };
+        assert_eq!(interrupted.in_lang(Lang::English),
+                   "Interrupted: 1 of 3 hosts woken, 0 verified up; not woken: nas, printer");
+        assert_eq!(interrupted.in_lang(Lang::German),
+                   "Abgebrochen: 1 von 3 Rechnern geweckt, 0 davon laufen; nicht geweckt: nas, \
+                    printer");
+        assert_eq!(Message::Wrote(Path::new("hosts.toml")).in_lang(Lang::German),
+                   "hosts.toml geschrieben");
+        assert_eq!(Message::NotWokenYet("nas").in_lang(Lang::English), "nas was not woken yet");
+        let not_known = Message::NotKnown {
+            target: "ZZ",
+            err: "invalid length",
+            sources: &[Source::Hosts, Source::Arp, Source::Leases],
+            leases: Some(Path::new("dnsmasq.leases")),
+        };
+        assert_eq!(not_known.in_lang(Lang::English),
+                   "'ZZ' is neither a MAC address (invalid length) nor known to the hosts file, \
+                    the neighbor table or the DHCP leases dnsmasq.leases");
+        assert_eq!(not_known.in_lang(Lang::German),
+                   "'ZZ' ist weder eine MAC-Adresse (invalid length) noch der Hosts-Datei, der \
+                    Nachbartabelle oder den DHCP-Leases dnsmasq.leases bekannt");
+        let unresolved = Message::NotKnown {
+            target: "ZZ",
+            err: "invalid length",
+            sources: &[],
+            leases: None,
+        };
+        assert_eq!(unresolved.in_lang(Lang::English),
+                   "'ZZ' is neither a MAC address (invalid length) nor known to any resolver");
+        assert_eq!(Message::CannotCombine("--internet", &["--via", "--strategy auto"])
+                       .in_lang(Lang::German),
+                   "--internet kann nicht mit --via oder --strategy auto kombiniert werden");
+        assert_eq!(Message::RejectedMac(MacAddr::new([0; 6])).in_lang(Lang::English),
+                   "00:00:00:00:00:00 is the all-zero address, pass --mac-check warn to wake it \
+                    anyway");
+        let would_send = Message::WouldSend {
+            mac: MacAddr::new([0; 6]),
+            path: "eth0",
+            source: Some(MacAddr::new([2, 0, 0, 0, 0, 1])),
+        };
+        assert_eq!(would_send.in_lang(Lang::German),
+                   "Würde das Paket für 00:00:00:00:00:00 als eth0 von 02:00:00:00:00:01 senden");
+        assert!(Message::is_yes("Ja\n", Lang::German));
+        assert!(!Message::is_yes("ja", Lang::English));
+        assert!(Message::is_yes("y", Lang::German));
+        assert!(!Message::is_yes("", Lang::English));
+    }
+}
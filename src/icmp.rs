@@ -0,0 +1,237 @@
+//! Verification by ICMP echo requests, i.e. ping, for hosts without open TCP ports.
+//!
+//! Raw ICMP sockets need root or the `CAP_NET_RAW` capability. Without them, the unprivileged
+//! ICMP sockets of Linux and macOS are used instead, which Linux only grants to the groups in
+//! the `net.ipv4.ping_group_range` sysctl. Other platforms are not supported.
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::os::unix::io::FromRawFd;
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The length of an echo request: the ICMP header followed by an 8 byte token.
+const REQUEST_LEN: usize = 16;
+
+/// Explains which privileges are missing if no ICMP socket can be opened.
+#[cfg(target_os = "linux")]
+const PRIVILEGE_HINT: &str = "ICMP probes need the CAP_NET_RAW capability, e.g. with \
+                              `setcap cap_net_raw+ep wol`, or a group in the \
+                              net.ipv4.ping_group_range sysctl";
+#[cfg(not(target_os = "linux"))]
+const PRIVILEGE_HINT: &str = "ICMP probes need root privileges";
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod imp {
+    use std::os::raw::c_int;
+
+    pub const AF_INET: c_int = 2;
+    #[cfg(target_os = "linux")]
+    pub const AF_INET6: c_int = 10;
+    #[cfg(target_os = "macos")]
+    pub const AF_INET6: c_int = 30;
+    pub const SOCK_DGRAM: c_int = 2;
+    pub const SOCK_RAW: c_int = 3;
+    pub const IPPROTO_ICMP: c_int = 1;
+    pub const IPPROTO_ICMPV6: c_int = 58;
+
+    extern "C" {
+        pub fn socket(domain: c_int, kind: c_int, protocol: c_int) -> c_int;
+    }
+}
+
+/// A socket sending ICMP echo requests.
+#[derive(Debug)]
+pub struct Pinger {
+    socket: UdpSocket,
+    /// Whether the socket is raw rather than unprivileged.
+    raw: bool,
+}
+
+impl Pinger {
+    /// Opens a raw ICMP socket for the address family of `ip`, or an unprivileged one if raw
+    /// sockets are not permitted. If neither is, the error has the kind
+    /// `io::ErrorKind::PermissionDenied` and names the missing privileges.
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn open(ip: IpAddr) -> io::Result<Pinger> {
+        let (domain, protocol) = match ip {
+            IpAddr::V4(_) => (imp::AF_INET, imp::IPPROTO_ICMP),
+            IpAddr::V6(_) => (imp::AF_INET6, imp::IPPROTO_ICMPV6),
+        };
+        match open_socket(domain, imp::SOCK_RAW, protocol) {
+            Ok(socket) => return Ok(Pinger { socket, raw: true }),
+            Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                debug!("cannot open a raw ICMP socket ({}), trying an unprivileged one", err)
+            },
+            Err(err) => return Err(err),
+        }
+        match open_socket(domain, imp::SOCK_DGRAM, protocol) {
+            Ok(socket) => Ok(Pinger { socket, raw: false }),
+            Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, PRIVILEGE_HINT))
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Opens a raw ICMP socket for the address family of `ip`, or an unprivileged one if raw
+    /// sockets are not permitted. If neither is, the error has the kind
+    /// `io::ErrorKind::PermissionDenied` and names the missing privileges.
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn open(_ip: IpAddr) -> io::Result<Pinger> {
+        Err(io::Error::other("ICMP probes are only supported on Linux and macOS"))
+    }
+
+    /// Returns whether the socket is raw. Unprivileged sockets only receive replies to their
+    /// own requests.
+    pub fn is_raw(&self) -> bool {
+        self.raw
+    }
+
+    /// Sends an echo request to `ip` and returns whether the reply arrived within `timeout`.
+    pub fn ping(&self, ip: IpAddr, timeout: Duration) -> io::Result<bool> {
+        let token = token();
+        self.socket.send_to(&echo_request(ip.is_ipv6(), &token), SocketAddr::new(ip, 0))?;
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0; 1500];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Ok(false);
+            }
+            self.socket.set_read_timeout(Some(remaining))?;
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, from))
+                    if from.ip() == ip && is_reply(&buf[..len], ip.is_ipv6(), &token) => {
+                    return Ok(true)
+                },
+                // Raw sockets also receive other ICMP packets, e.g. the replies to other pings.
+                Ok(_) => {},
+                Err(ref err) if matches!(err.kind(),
+                                         io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    return Ok(false)
+                },
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Pings `ip` once from a new socket and returns whether it replied within `timeout`.
+///
+/// #Example
+///
+/// ```
+/// use std::io;
+/// use std::time::Duration;
+///
+/// match wol::icmp::ping("127.0.0.1".parse().unwrap(), Duration::from_secs(1)) {
+///     Ok(up) => assert!(up),
+///     Err(err) => assert_eq!(err.kind(), io::ErrorKind::PermissionDenied),
+/// }
+/// ```
+pub fn ping(ip: IpAddr, timeout: Duration) -> io::Result<bool> {
+    Pinger::open(ip)?.ping(ip, timeout)
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn open_socket(domain: i32, kind: i32, protocol: i32) -> io::Result<UdpSocket> {
+    let fd = unsafe { imp::socket(domain, kind, protocol) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Sending and receiving work like on a UDP socket, only the port is meaningless.
+    Ok(unsafe { UdpSocket::from_raw_fd(fd) })
+}
+
+/// Returns a token identifying an echo request, which the reply carries back.
+fn token() -> [u8; 8] {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    (nanos as u64).to_be_bytes()
+}
+
+/// Builds an echo request carrying the token. The identifier is the process id; unprivileged
+/// sockets replace it with their own.
+fn echo_request(v6: bool, token: &[u8; 8]) -> [u8; REQUEST_LEN] {
+    let mut request = [0; REQUEST_LEN];
+    request[0] = if v6 { 128 } else { 8 };
+    request[4..6].copy_from_slice(&(process::id() as u16).to_be_bytes());
+    request[6..8].copy_from_slice(&token[6..]);
+    request[8..].copy_from_slice(token);
+    // The kernel computes the checksum of ICMPv6, which covers the IPv6 header.
+    if !v6 {
+        let checksum = checksum(&request);
+        request[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+    request
+}
+
+/// Returns the internet checksum of the data, the one's complement of the one's complement sum
+/// of its 16 bit words.
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data.chunks(2)
+        .map(|word| u32::from(word[0]) << 8 | u32::from(*word.get(1).unwrap_or(&0)))
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Returns whether the packet is the echo reply carrying the token. Raw IPv4 sockets and
+/// unprivileged ones on macOS receive the IP header as well, which is skipped.
+fn is_reply(packet: &[u8], v6: bool, token: &[u8; 8]) -> bool {
+    let icmp = match packet.first() {
+        // ICMP types below 64 never start with the IPv4 version nibble.
+        Some(&first) if !v6 && first >> 4 == 4 => {
+            &packet[(usize::from(first & 0x0F) * 4).min(packet.len())..]
+        },
+        _ => packet,
+    };
+    let reply = if v6 { 129 } else { 0 };
+    icmp.len() >= REQUEST_LEN && icmp[0] == reply && icmp[8..REQUEST_LEN] == token[..]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+
+    use super::{checksum, echo_request, is_reply, ping};
+
+    #[test]
+    fn test_echo_request() {
+        let token = [1, 2, 3, 4, 5, 6, 7, 8];
+        let request = echo_request(false, &token);
+        assert_eq!(request[0], 8);
+        assert_eq!(&request[6..], &[7, 8, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(checksum(&request), 0);
+        let request = echo_request(true, &token);
+        assert_eq!((request[0], request[2], request[3]), (128, 0, 0));
+    }
+
+    #[test]
+    fn test_is_reply() {
+        let token = [1, 2, 3, 4, 5, 6, 7, 8];
+        let mut reply = echo_request(false, &token).to_vec();
+        assert!(!is_reply(&reply, false, &token));
+        reply[0] = 0;
+        assert!(is_reply(&reply, false, &token));
+        assert!(!is_reply(&reply, false, &[0; 8]));
+        assert!(!is_reply(&reply[..12], false, &token));
+        let mut with_header = vec![0x45];
+        with_header.extend_from_slice(&[0; 19]);
+        with_header.extend_from_slice(&reply);
+        assert!(is_reply(&with_header, false, &token));
+        reply[0] = 129;
+        assert!(is_reply(&reply, true, &token));
+    }
+
+    #[test]
+    fn test_ping_localhost() {
+        match ping("127.0.0.1".parse().unwrap(), Duration::from_secs(1)) {
+            Ok(up) => assert!(up),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::PermissionDenied),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Catching ctrl-C, so that long running wakes can stop cleanly and report what they did,
+//! SIGTERM, so that the daemon can shut down gracefully, and SIGHUP, so that the daemon can
+//! reload its settings.
+//!
+//! The handlers only set a flag, which the batch, the waits for hosts and the daemon poll. A
+//! second ctrl-C or SIGTERM terminates the process as usual.
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by the handlers when ctrl-C was pressed or SIGTERM received.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Set by the handler when SIGHUP was received, until it is taken.
+#[cfg(unix)]
+static HANGUP: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod imp {
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+
+    use super::{HANGUP, INTERRUPTED};
+
+    pub const SIGHUP: c_int = 1;
+    pub const SIGINT: c_int = 2;
+    pub const SIGTERM: c_int = 15;
+    pub const SIG_DFL: usize = 0;
+    pub const SIG_ERR: usize = !0;
+
+    extern "C" {
+        pub fn signal(signum: c_int, handler: usize) -> usize;
+    }
+
+    pub extern "C" fn on_interrupt(_signum: c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        // Let the next ctrl-C terminate the process.
+        unsafe {
+            signal(SIGINT, SIG_DFL);
+        }
+    }
+
+    pub extern "C" fn on_terminate(_signum: c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        unsafe {
+            signal(SIGTERM, SIG_DFL);
+        }
+    }
+
+    pub extern "C" fn on_hangup(_signum: c_int) {
+        HANGUP.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Installs the ctrl-C handler and returns the flag it sets. Only supported on Unix.
+#[cfg(unix)]
+pub fn catch_interrupt() -> io::Result<&'static AtomicBool> {
+    let handler = imp::on_interrupt as extern "C" fn(_) as usize;
+    if unsafe { imp::signal(imp::SIGINT, handler) } == imp::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(&INTERRUPTED)
+}
+
+/// Installs the ctrl-C handler and returns the flag it sets. Only supported on Unix.
+#[cfg(not(unix))]
+pub fn catch_interrupt() -> io::Result<&'static AtomicBool> {
+    Err(io::Error::new(io::ErrorKind::Other, "catching ctrl-C is only supported on Unix"))
+}
+
+/// Installs the SIGTERM handler and returns the flag it sets, the same as that of ctrl-C, so
+/// that a service manager stopping the daemon is handled like ctrl-C. Only supported on Unix.
+#[cfg(unix)]
+pub fn catch_terminate() -> io::Result<&'static AtomicBool> {
+    let handler = imp::on_terminate as extern "C" fn(_) as usize;
+    if unsafe { imp::signal(imp::SIGTERM, handler) } == imp::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(&INTERRUPTED)
+}
+
+/// Installs the SIGTERM handler and returns the flag it sets. Only supported on Unix.
+#[cfg(not(unix))]
+pub fn catch_terminate() -> io::Result<&'static AtomicBool> {
+    Err(io::Error::new(io::ErrorKind::Other, "catching SIGTERM is only supported on Unix"))
+}
+
+/// Installs the SIGHUP handler and returns the flag it sets, which the caller resets after
+/// handling it. Only supported on Unix.
+#[cfg(unix)]
+pub fn catch_hangup() -> io::Result<&'static AtomicBool> {
+    let handler = imp::on_hangup as extern "C" fn(_) as usize;
+    if unsafe { imp::signal(imp::SIGHUP, handler) } == imp::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(&HANGUP)
+}
+
+/// Installs the SIGHUP handler and returns the flag it sets, which the caller resets after
+/// handling it. Only supported on Unix.
+#[cfg(not(unix))]
+pub fn catch_hangup() -> io::Result<&'static AtomicBool> {
+    Err(io::Error::new(io::ErrorKind::Other, "catching SIGHUP is only supported on Unix"))
+}
+
+/// Returns whether ctrl-C was pressed or SIGTERM received since their handlers were installed.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
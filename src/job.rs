@@ -0,0 +1,795 @@
+//! Job files describing a wake run, e.g. for `wol wake --job`, so that automation repositories
+//! can check in which hosts to wake and how.
+//!
+//! A job is a list of targets, each a MAC address or the name of a host like the arguments of
+//! `wol wake`, with optional overrides of the send options and verification:
+//!
+//! ```yaml
+//! # wakes.yaml
+//! - target: nas
+//!   verify: true
+//! - target: 00:11:22:33:44:55
+//!   broadcast: 10.0.2.255
+//!   port: 9
+//!   repeat: 3
+//!   ip: 10.0.2.20
+//!   verify: [22, 3389]
+//! - media-pc
+//! ```
+//!
+//! The list can also be the value of a `targets` key. Job files are written in a subset of
+//! YAML: block lists and maps, flow lists and maps like `[22, 3389]`, plain, single- and
+//! double-quoted scalars and comments; anchors, tags and multi-line scalars are not supported.
+//! As JSON is the flow style of YAML, the same job can be given as JSON:
+//!
+//! ```json
+//! [{"target": "nas", "verify": true}, {"target": "media-pc", "port": 9}]
+//! ```
+//!
+//! `verify` is `true` to verify the host with the configured ports, the ports to probe, or
+//! `false`; it replaces the `verify` and `verify_port` of the hosts file. `name` names the host
+//! of a MAC address in the output and `password` is its SecureOn password.
+//!
+//! For one-off bulk wakes of an inventory export, a job can also be a CSV file, with the columns
+//! `name,mac,broadcast,port,password` in this order or named in a header row, which may have
+//! further columns like `ip` and `repeat` and ignores unknown ones; empty fields are no
+//! overrides. A file in the format of `/etc/ethers`, a MAC address and a name per line, is a job
+//! as well. The format is chosen by the extension of the file, see `Job::load`:
+//!
+//! ```text
+//! # inventory.csv
+//! name,mac,broadcast,port,password
+//! nas,00:11:22:33:44:55,10.0.2.255,9,
+//! "lab, rack 2",00:11:22:33:44:66,,,01:02:03:04:05:06
+//! ```
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::iter;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::{self, FromStr};
+
+use hosts::{Host, Ready};
+use packet::parse_password;
+use units::{Port, RepeatCount};
+
+/// A target of a job with its overrides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobEntry {
+    /// The MAC address or the name of the host.
+    pub target: String,
+    /// The name of the host in the output, e.g. for a target which is a MAC address.
+    pub name: Option<String>,
+    /// The IP address of the host, for verification and unicast addressing.
+    pub ip: Option<IpAddr>,
+    /// The UDP port to send to.
+    pub port: Option<u16>,
+    /// The broadcast or multicast address to send to.
+    pub broadcast: Option<IpAddr>,
+    /// How often to send every packet.
+    pub repeat: Option<u32>,
+    /// Whether to verify that the host came up.
+    pub verify: Option<bool>,
+    /// The TCP ports probed to verify the host, empty for those of the configuration.
+    pub verify_ports: Vec<u16>,
+    /// The SecureOn password of the host.
+    pub password: Option<[u8; 6]>,
+}
+
+impl JobEntry {
+    /// Creates an entry for the target without overrides.
+    pub fn new(target: &str) -> JobEntry {
+        JobEntry {
+            target: target.to_string(),
+            name: None,
+            ip: None,
+            port: None,
+            broadcast: None,
+            repeat: None,
+            verify: None,
+            verify_ports: Vec::new(),
+            password: None,
+        }
+    }
+
+    /// Returns the host of the entry: `host`, the host the target resolved to, with the
+    /// overrides of the entry.
+    pub fn apply(&self, mut host: Host) -> Host {
+        if let Some(ref name) = self.name {
+            host.name = name.clone();
+        }
+        host.ip = self.ip.or(host.ip);
+        host.port = self.port.or(host.port);
+        host.broadcast = self.broadcast.or(host.broadcast);
+        host.repeat = self.repeat.or(host.repeat);
+        host.password = self.password.or(host.password);
+        match self.verify {
+            Some(true) => {
+                host.ready = Ready::Verify;
+                if !self.verify_ports.is_empty() {
+                    host.verify_ports = self.verify_ports.clone();
+                }
+            },
+            Some(false) => host.ready = Ready::Sent,
+            None => {},
+        }
+        host
+    }
+
+    /// Creates an entry from a node of the job file, a target or a map with one.
+    fn from_node(node: Node) -> Result<JobEntry, String> {
+        let fields = match node {
+            Node::Scalar(target) => return Ok(JobEntry::new(&target)),
+            Node::List(_) => return Err("expected a target or a map with one".to_string()),
+            Node::Map(fields) => fields,
+        };
+        let mut entry = JobEntry::new("");
+        for (key, value) in fields {
+            match key.as_str() {
+                "target" => entry.target = value.scalar(&key)?,
+                "name" => entry.name = Some(value.scalar(&key)?),
+                "password" => entry.password = Some(parse_job_password(&value.scalar(&key)?)?),
+                "ip" => entry.ip = value.parse(&key, "an IP address")?,
+                "port" => entry.port = value.checked::<Port>(&key)?.map(Port::get),
+                "broadcast" => entry.broadcast = value.parse(&key, "an IP address")?,
+                "repeat" => {
+                    entry.repeat = value.checked::<RepeatCount>(&key)?.map(RepeatCount::get)
+                },
+                "verify" => {
+                    let ports = match value {
+                        Node::List(ports) => ports,
+                        Node::Scalar(ref s) if s.parse::<u16>().is_ok() => vec![value],
+                        _ => {
+                            entry.verify = value.parse(&key, "true, false or ports")?;
+                            continue;
+                        },
+                    };
+                    entry.verify = Some(true);
+                    entry.verify_ports = ports.into_iter()
+                        .map(|port| port.checked::<Port>(&key))
+                        .collect::<Result<Option<Vec<Port>>, String>>()?
+                        .map_or_else(Vec::new, |ports| ports.into_iter().map(Port::get).collect());
+                },
+                _ => return Err(format!("unknown key '{}'", key)),
+            }
+        }
+        if entry.target.is_empty() {
+            return Err("missing the target".to_string());
+        }
+        Ok(entry)
+    }
+}
+
+/// The targets of a job file, in order.
+///
+/// #Example
+///
+/// ```
+/// use wol::job::Job;
+///
+/// let job: Job = "- target: nas\n  port: 9\n  verify: [22, 3389]\n- media-pc".parse().unwrap();
+/// assert_eq!(job.entries[0].port, Some(9));
+/// assert_eq!(job.entries[0].verify_ports, [22, 3389]);
+/// assert_eq!(job.entries[1].target, "media-pc");
+/// let json: Job = r#"[{"target": "nas", "port": 9, "verify": [22, 3389]}, "media-pc"]"#
+///     .parse()
+///     .unwrap();
+/// assert_eq!(json, job);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Job {
+    pub entries: Vec<JobEntry>,
+}
+
+impl Job {
+    /// Reads a job file: CSV if its extension is `.csv`, the format of `/etc/ethers` if it is
+    /// `.ethers` or the file is named `ethers`, and YAML or JSON otherwise.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Job> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let job = match extension.map(str::to_lowercase).as_deref() {
+            Some("csv") => Job::from_csv(&content),
+            Some("ethers") => Job::from_ethers(&content),
+            _ if path.file_name().is_some_and(|name| name == "ethers") => {
+                Job::from_ethers(&content)
+            },
+            _ => content.parse(),
+        };
+        job.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Parses a CSV job, with the columns `name,mac,broadcast,port,password` or those of a
+    /// header row naming its columns, see the module documentation. Rows without a MAC address
+    /// wake the host of their name.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::job::Job;
+    ///
+    /// let job = Job::from_csv("nas,00:11:22:33:44:55,10.0.2.255,9\nmedia-pc").unwrap();
+    /// assert_eq!(job.entries[0].target, "00:11:22:33:44:55");
+    /// assert_eq!(job.entries[0].name.as_deref(), Some("nas"));
+    /// assert_eq!(job.entries[0].port, Some(9));
+    /// assert_eq!(job.entries[1].target, "media-pc");
+    /// ```
+    pub fn from_csv(s: &str) -> Result<Job, String> {
+        const COLUMNS: [&str; 5] = ["name", "mac", "broadcast", "port", "password"];
+        let mut rows = s.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| split_csv(line).map(|fields| (i + 1, fields)))
+            .peekable();
+        let columns: Vec<String> = match rows.peek() {
+            Some(Ok((_, fields))) if fields.iter().any(|f| f.eq_ignore_ascii_case("mac")) => {
+                let header = rows.next().unwrap().unwrap().1;
+                header.iter().map(|column| column.to_lowercase()).collect()
+            },
+            _ => COLUMNS.iter().map(|column| column.to_string()).collect(),
+        };
+        let entries = rows.map(|row| {
+                let (line, fields) = row?;
+                csv_entry(&columns, &fields).map_err(|err| format!("line {}: {}", line, err))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Job { entries })
+    }
+
+    /// Parses a job in the format of `/etc/ethers`: a MAC address and the name of its host per
+    /// line, with comments starting with `#`.
+    pub fn from_ethers(s: &str) -> Result<Job, String> {
+        let entries = s.lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let line = line.split('#').next().unwrap_or_default();
+                let mut fields = line.split_whitespace();
+                let mac = fields.next()?;
+                Some(match (fields.next(), fields.next()) {
+                    (Some(name), None) => {
+                        Ok(JobEntry { name: Some(name.to_string()), ..JobEntry::new(mac) })
+                    },
+                    _ => Err(format!("line {}: expected a MAC address and a name", i + 1)),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Job { entries })
+    }
+}
+
+/// Returns the entry of a CSV row with the given columns.
+fn csv_entry(columns: &[String], fields: &[String]) -> Result<JobEntry, String> {
+    let mut entry = JobEntry::new("");
+    let mut name = None;
+    for (column, field) in columns.iter().zip(fields).filter(|(_, field)| !field.is_empty()) {
+        let invalid = |what: &str| format!("invalid {} '{}', expected {}", column, field, what);
+        match column.as_str() {
+            "name" => name = Some(field.clone()),
+            "mac" => entry.target = field.clone(),
+            "broadcast" | "ip" => {
+                let ip = Some(field.parse().map_err(|_| invalid("an IP address"))?);
+                if column == "ip" { entry.ip = ip } else { entry.broadcast = ip }
+            },
+            "port" => entry.port = Some(field.parse::<Port>()?.get()),
+            "repeat" => entry.repeat = Some(field.parse::<RepeatCount>()?.get()),
+            "password" => entry.password = Some(parse_job_password(field)?),
+            _ => {},
+        }
+    }
+    match name {
+        Some(name) if entry.target.is_empty() => entry.target = name,
+        Some(name) => entry.name = Some(name),
+        None if entry.target.is_empty() => return Err("missing the MAC address".to_string()),
+        None => {},
+    }
+    Ok(entry)
+}
+
+/// Splits a CSV row into its fields, without the quotes of quoted ones.
+fn split_csv(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            ('"', true) => quoted = false,
+            ('"', false) if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            },
+            (',', false) => {
+                fields.push(field.trim().to_string());
+                field.clear();
+            },
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_string());
+    }
+    fields.push(field.trim().to_string());
+    Ok(fields)
+}
+
+/// Parses the SecureOn password of a job.
+fn parse_job_password(s: &str) -> Result<[u8; 6], String> {
+    parse_password(s).map_err(|err| format!("invalid password '{}': {}", s, err))
+}
+
+impl FromStr for Job {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Job, String> {
+        let entries = match parse(s)? {
+            Node::List(entries) => entries,
+            Node::Map(mut fields) if fields.len() == 1 && fields[0].0 == "targets" => {
+                match fields.remove(0).1 {
+                    Node::List(entries) => entries,
+                    _ => return Err("expected a list of targets".to_string()),
+                }
+            },
+            _ => return Err("expected a list of targets".to_string()),
+        };
+        let entries = entries.into_iter()
+            .enumerate()
+            .map(|(i, node)| {
+                JobEntry::from_node(node).map_err(|err| format!("target {}: {}", i + 1, err))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Job { entries })
+    }
+}
+
+/// A value of a job file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    /// A scalar, with quotes and escapes removed.
+    Scalar(String),
+    List(Vec<Node>),
+    /// The keys and values of a map, in order.
+    Map(Vec<(String, Node)>),
+}
+
+impl Node {
+    /// Returns the scalar of the value of `key`.
+    fn scalar(self, key: &str) -> Result<String, String> {
+        match self {
+            Node::Scalar(s) => Ok(s),
+            _ => Err(format!("expected a scalar for '{}'", key)),
+        }
+    }
+
+    /// Parses the scalar of the value of `key`, `None` if it is null. `what` describes the
+    /// expected value in the error message.
+    fn parse<T: FromStr>(self, key: &str, what: &str) -> Result<Option<T>, String> {
+        let s = self.scalar(key)?;
+        match s.as_str() {
+            "" | "~" | "null" => Ok(None),
+            _ => s.parse().map(Some).map_err(|_| format!("expected {} for '{}'", what, key)),
+        }
+    }
+
+    /// Parses a scalar like `parse`, failing with the message of `T`, e.g. of `units::Port`.
+    fn checked<T: FromStr<Err = String>>(self, key: &str) -> Result<Option<T>, String> {
+        let s = self.scalar(key)?;
+        match s.as_str() {
+            "" | "~" | "null" => Ok(None),
+            _ => s.parse().map(Some).map_err(|err| format!("{} for '{}'", err, key)),
+        }
+    }
+}
+
+/// A line of a job file without its comment.
+#[derive(Debug, Clone, Copy)]
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+/// Parses a job file into its top-level node.
+fn parse(s: &str) -> Result<Node, String> {
+    let mut lines = Vec::new();
+    for (i, line) in s.lines().enumerate() {
+        let text = strip_comment(line).trim_end();
+        let content = text.trim_start_matches(' ');
+        if content.starts_with('\t') {
+            return Err(at(i + 1, "tabs are not allowed for indentation"));
+        }
+        if !content.is_empty() && content != "---" {
+            lines.push(Line {
+                number: i + 1,
+                indent: text.len() - content.len(),
+                text: content,
+            });
+        }
+    }
+    let first = match lines.first() {
+        Some(first) => *first,
+        None => return Ok(Node::List(Vec::new())),
+    };
+    if first.text.starts_with('[') || first.text.starts_with('{') {
+        // A flow node, e.g. JSON, may span lines.
+        let text: Vec<&str> = lines.iter().map(|line| line.text).collect();
+        return parse_flow(&text.join(" "), true).map_err(|err| at(first.number, &err));
+    }
+    let mut i = 0;
+    let node = parse_block(&mut lines, &mut i, first.indent)?;
+    match lines.get(i) {
+        Some(line) => Err(at(line.number, "unexpected indentation")),
+        None => Ok(node),
+    }
+}
+
+/// Parses the block node starting at line `i`, which is indented by `indent`, and moves `i`
+/// past it.
+fn parse_block(lines: &mut [Line], i: &mut usize, indent: usize) -> Result<Node, String> {
+    let line = lines[*i];
+    if is_item(line.text) {
+        let mut items = Vec::new();
+        while *i < lines.len() && lines[*i].indent == indent && is_item(lines[*i].text) {
+            let line = lines[*i];
+            let rest = line.text[1..].trim_start_matches(' ');
+            if rest.is_empty() {
+                *i += 1;
+                items.push(parse_nested(lines, i, indent)?);
+            } else {
+                // The item continues like a node indented to its start, e.g. a map whose
+                // further keys are aligned with the first one.
+                lines[*i].indent = indent + line.text.len() - rest.len();
+                lines[*i].text = rest;
+                items.push(parse_block(lines, i, lines[*i].indent)?);
+            }
+        }
+        return Ok(Node::List(items));
+    }
+    if split_key(line.text).is_none() {
+        *i += 1;
+        return parse_flow(line.text, false).map_err(|err| at(line.number, &err));
+    }
+    let mut fields = Vec::new();
+    while *i < lines.len() && lines[*i].indent == indent && !is_item(lines[*i].text) {
+        let line = lines[*i];
+        let (key, rest) = split_key(line.text)
+            .ok_or_else(|| at(line.number, "expected a key and a value like 'port: 9'"))?;
+        let key = parse_flow(key, true).and_then(|key| key.scalar("the key"))
+            .map_err(|err| at(line.number, &err))?;
+        *i += 1;
+        let value = if !rest.is_empty() {
+            parse_flow(rest, false).map_err(|err| at(line.number, &err))?
+        } else if lines.get(*i).is_some_and(|next| next.indent == indent && is_item(next.text)) {
+            // A list may be indented like the key it is the value of.
+            parse_block(lines, i, indent)?
+        } else {
+            parse_nested(lines, i, indent)?
+        };
+        fields.push((key, value));
+    }
+    Ok(Node::Map(fields))
+}
+
+/// Parses the node on the lines after a key or item without value, which are indented further
+/// than `indent`, or returns null if there is none.
+fn parse_nested(lines: &mut [Line], i: &mut usize, indent: usize) -> Result<Node, String> {
+    match lines.get(*i) {
+        Some(next) if next.indent > indent => parse_block(lines, i, next.indent),
+        _ => Ok(Node::Scalar(String::new())),
+    }
+}
+
+/// Parses a flow node, a scalar, list or map on a single line. In `nested` flow, e.g. within a
+/// list or as key, plain scalars end at `,:]}`.
+fn parse_flow(s: &str, nested: bool) -> Result<Node, String> {
+    let mut parser = FlowParser { chars: s.chars().peekable() };
+    let node = parser.node(nested)?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(node),
+        Some(c) => Err(format!("unexpected '{}'", c)),
+    }
+}
+
+/// Reads the nodes of flow style, e.g. JSON.
+struct FlowParser<'a> {
+    chars: iter::Peekable<str::Chars<'a>>,
+}
+
+impl<'a> FlowParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    /// Consumes `c` if it is next, skipping whitespace before it.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&c).is_some()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) { Ok(()) } else { Err(format!("expected '{}'", c)) }
+    }
+
+    fn node(&mut self, nested: bool) -> Result<Node, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('[') => {
+                self.chars.next();
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    items.push(self.node(true)?);
+                    if !self.eat(',') {
+                        self.expect(']')?;
+                        break;
+                    }
+                }
+                Ok(Node::List(items))
+            },
+            Some('{') => {
+                self.chars.next();
+                let mut fields = Vec::new();
+                while !self.eat('}') {
+                    let key = self.node(true)?.scalar("the key")?;
+                    self.expect(':')?;
+                    fields.push((key, self.node(true)?));
+                    if !self.eat(',') {
+                        self.expect('}')?;
+                        break;
+                    }
+                }
+                Ok(Node::Map(fields))
+            },
+            Some('"') => self.double_quoted().map(Node::Scalar),
+            Some('\'') => self.single_quoted().map(Node::Scalar),
+            _ => {
+                let ends = if nested { ",:]}" } else { "" };
+                let mut s = String::new();
+                // A colon ends a plain scalar only before a space, so that MAC and IPv6
+                // addresses need no quotes.
+                while let Some(&c) = self.chars.peek() {
+                    if ends.contains(c) && (c != ':' || self.colon_ends()) {
+                        break;
+                    }
+                    s.push(c);
+                    self.chars.next();
+                }
+                Ok(Node::Scalar(s.trim().to_string()))
+            },
+        }
+    }
+
+    /// Returns whether the colon which is next is followed by whitespace or the end.
+    fn colon_ends(&self) -> bool {
+        let mut ahead = self.chars.clone();
+        ahead.next();
+        ahead.peek().is_none_or(|c| c.is_whitespace())
+    }
+
+    fn double_quoted(&mut self) -> Result<String, String> {
+        self.chars.next();
+        let mut s = String::new();
+        loop {
+            match self.chars.next().ok_or("unterminated string")? {
+                '"' => return Ok(s),
+                '\\' => {
+                    s.push(match self.chars.next().ok_or("unterminated string")? {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let hex: String = self.chars.by_ref().take(4).collect();
+                            u32::from_str_radix(&hex, 16).ok()
+                                .and_then(char::from_u32)
+                                .ok_or("invalid unicode escape")?
+                        },
+                        c => c,
+                    })
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// Reads a single-quoted scalar, in which `''` is a quote.
+    fn single_quoted(&mut self) -> Result<String, String> {
+        self.chars.next();
+        let mut s = String::new();
+        loop {
+            match self.chars.next().ok_or("unterminated string")? {
+                '\'' if self.chars.next_if_eq(&'\'').is_some() => s.push('\''),
+                '\'' => return Ok(s),
+                c => s.push(c),
+            }
+        }
+    }
+}
+
+/// Returns whether the text of a line is an item of a block list.
+fn is_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Splits the text of a line of a block map into its key and value, `None` if it is none.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') if i == 0 => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, ':') => {
+                let rest = &text[i + 1..];
+                if rest.is_empty() || rest.starts_with(' ') {
+                    return Some((&text[..i], rest.trim_start()));
+                }
+            },
+            (None, '[') | (None, '{') if i == 0 => return None,
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Removes the comment from a line, which starts with a `#` outside quotes at the start of the
+/// line or after whitespace.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '#') if previous.is_whitespace() => return &line[..i],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            _ => {},
+        }
+        previous = c;
+    }
+    line
+}
+
+/// Prefixes an error with its line.
+fn at<E: Display>(line: usize, err: E) -> String {
+    format!("line {}: {}", line, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use hosts::{Host, Ready};
+    use MacAddr;
+
+    use super::{Job, JobEntry};
+
+    #[test]
+    fn test_parse_yaml() {
+        let job: Job = "# wakes\n\
+                        targets:\n\
+                        - target: nas   # the NAS\n  \
+                          verify: true\n\
+                        - target: 00:11:22:33:44:55\n  \
+                          broadcast: 10.0.2.255\n  \
+                          port: 9\n  \
+                          repeat: 3\n  \
+                          ip: \"10.0.2.20\"\n  \
+                          verify: 22\n\
+                        - 'media-pc'\n\
+                        -\n  \
+                          target: \"printer #2\"\n  \
+                          verify: false\n"
+            .parse()
+            .unwrap();
+        let targets: Vec<&str> = job.entries.iter().map(|e| e.target.as_str()).collect();
+        assert_eq!(targets, ["nas", "00:11:22:33:44:55", "media-pc", "printer #2"]);
+        assert_eq!((job.entries[0].verify, job.entries[0].verify_ports.len()), (Some(true), 0));
+        let pc = &job.entries[1];
+        assert_eq!((pc.port, pc.repeat, pc.verify_ports.clone()), (Some(9), Some(3), vec![22]));
+        assert_eq!(pc.broadcast, Some("10.0.2.255".parse().unwrap()));
+        assert_eq!(pc.ip, Some("10.0.2.20".parse().unwrap()));
+        assert_eq!(job.entries[2], JobEntry::new("media-pc"));
+        assert_eq!(job.entries[3].verify, Some(false));
+        assert_eq!("".parse::<Job>(), Ok(Job::default()));
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let job: Job = "{\"targets\": [\n\
+                          {\"target\": \"nas\", \"verify\": [22, 3389], \"port\": null},\n\
+                          {\"target\": \"fe80::1\", \"ip\": \"fe80::1\"}\n\
+                        ]}"
+            .parse()
+            .unwrap();
+        assert_eq!(job.entries[0].verify_ports, [22, 3389]);
+        assert_eq!(job.entries[0].port, None);
+        assert_eq!(job.entries[1].ip, Some("fe80::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |s: &str| s.parse::<Job>().unwrap_err();
+        assert_eq!(err("- target: nas\n  prot: 9"), "target 1: unknown key 'prot'");
+        assert_eq!(err("- nas\n- port: 9"), "target 2: missing the target");
+        assert_eq!(err("- target: nas\n  port: 70000"),
+                   "target 1: invalid port '70000', expected 1 to 65535 for 'port'");
+        assert_eq!(err("- target: nas\n  port: 0"),
+                   "target 1: invalid port '0', expected 1 to 65535 for 'port'");
+        assert_eq!(err("- target: nas\n  repeat: 0"),
+                   "target 1: invalid repeat count '0', expected 1 to 100 for 'repeat'");
+        assert_eq!(err("- target: nas\n  verify: [22, 0]"),
+                   "target 1: invalid port '0', expected 1 to 65535 for 'verify'");
+        assert_eq!(err("- target: nas\n  verify: yes"),
+                   "target 1: expected true, false or ports for 'verify'");
+        assert_eq!(err("[\"nas\""), "line 1: expected ']'");
+        assert_eq!(err("- nas\n    - nas"), "line 2: unexpected indentation");
+        assert_eq!(err("- \"nas"), "line 1: unterminated string");
+        assert_eq!(err("\t- nas"), "line 1: tabs are not allowed for indentation");
+        assert_eq!(err("hosts: [nas]"), "expected a list of targets");
+        assert_eq!(err("- [nas]"), "target 1: expected a target or a map with one");
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "# inventory\n\
+                   name,mac,broadcast,port,password\n\
+                   nas,00:11:22:33:44:55,10.0.2.255,9,\n\
+                   \"lab, rack 2\",00:11:22:33:44:66,,,01:02:03:04:05:06\r\n\
+                   media-pc\n";
+        let job = Job::from_csv(csv).unwrap();
+        assert_eq!(job.entries.len(), 3);
+        assert_eq!(job.entries[0].target, "00:11:22:33:44:55");
+        assert_eq!(job.entries[0].broadcast, Some("10.0.2.255".parse().unwrap()));
+        assert_eq!((job.entries[0].port, job.entries[0].password), (Some(9), None));
+        assert_eq!(job.entries[1].name.as_deref(), Some("lab, rack 2"));
+        assert_eq!(job.entries[1].password, Some([1, 2, 3, 4, 5, 6]));
+        assert_eq!(job.entries[2], JobEntry::new("media-pc"));
+        // A header in another order, with further and unknown columns.
+        let job = Job::from_csv("MAC,IP,Owner,repeat\n00:11:22:33:44:55,10.0.2.20,ops,3").unwrap();
+        assert_eq!(job.entries[0].ip, Some("10.0.2.20".parse().unwrap()));
+        assert_eq!((job.entries[0].repeat, job.entries[0].name.clone()), (Some(3), None));
+        let err = |csv: &str| Job::from_csv(csv).unwrap_err();
+        assert_eq!(err("nas,00:11:22:33:44:55,,nine"),
+                   "line 1: invalid port 'nine', expected 1 to 65535");
+        assert_eq!(err("nas,00:11:22:33:44:55,,0"),
+                   "line 1: invalid port '0', expected 1 to 65535");
+        assert_eq!(err("mac,port\n,9"), "line 2: missing the MAC address");
+        assert_eq!(err("mac,repeat\n00:11:22:33:44:55,1000"),
+                   "line 2: invalid repeat count '1000', expected 1 to 100");
+        assert_eq!(err("\"nas,00:11:22:33:44:55"), "unterminated quoted field");
+        assert!(err("nas,00:11:22:33:44:55,,,01:02").starts_with("line 1: invalid password"));
+    }
+
+    #[test]
+    fn test_parse_ethers() {
+        let ethers = "# ethers\n00:11:22:33:44:55 nas\n\n00:11:22:33:44:66\tvm # lab\n";
+        let job = Job::from_ethers(ethers).unwrap();
+        assert_eq!(job.entries.len(), 2);
+        assert_eq!((job.entries[1].target.as_str(), job.entries[1].name.as_deref()),
+                   ("00:11:22:33:44:66", Some("vm")));
+        assert_eq!(Job::from_ethers("00:11:22:33:44:55"),
+                   Err("line 1: expected a MAC address and a name".to_string()));
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut host = Host::new("nas", MacAddr::new([0, 0x11, 0x22, 0x33, 0x44, 0x55]));
+        host.port = Some(7);
+        host.verify_ports = vec![445];
+        let job: Job = "- target: nas\n  port: 9\n  verify: [22]".parse().unwrap();
+        let host = job.entries[0].apply(host);
+        assert_eq!((host.port, host.repeat), (Some(9), None));
+        assert_eq!((host.ready, host.verify_ports.clone()), (Ready::Verify, vec![22]));
+        let host = JobEntry { verify: Some(false), ..JobEntry::new("nas") }.apply(host);
+        assert_eq!((host.ready, host.port), (Ready::Sent, Some(9)));
+        let job: Job = "- target: nas\n  name: storage\n  password: 01-02-03-04-05-06"
+            .parse()
+            .unwrap();
+        let host = job.entries[0].apply(host);
+        assert_eq!((host.name.as_str(), host.password), ("storage", Some([1, 2, 3, 4, 5, 6])));
+    }
+}
@@ -1,162 +1,139 @@
 //! Simple Wake On LAN tool.
 //!
-//! Send the magic packet either per IPv4 with `send_magic_packet_v4` or per IPv6 with
-//! `send_magic_packet_v6`. Therefore, the MAC address of the remote system is required. Use
-//! `parse_mac` to parse MAC address strings like "AB:CD:01:02:03:04".
-use std::net::UdpSocket;
-use std::net::{Ipv6Addr, Ipv4Addr};
-use std::net::ToSocketAddrs;
-
-use std::fmt;
-
-/// Parses the MAC address from a given string.
-///
-/// #Example
-///
-/// ```
-/// let mac = wol::parse_mac("AA:FF:B0:12:34:56".to_string());
-/// assert_eq!(mac, Ok(vec![0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]))
-/// ```
-pub fn parse_mac(mac: String) -> Result<Vec<u8>, ParseError> {
-    let vec: Result<Vec<u8>, std::num::ParseIntError> = mac.split(':')
-        .map(|s| u8::from_str_radix(s, 16))
-        .collect();
-    match vec {
-        Err(e) => Err(ParseError::Number(e)),
-        Ok(vec) => {
-            if vec.len() == 6 {
-                Ok(vec)
-            } else {
-                Err(ParseError::Length)
-            }
-        },
-    }
-}
-
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum ParseError {
-    Number(std::num::ParseIntError),
-    Length,
-}
-
-impl std::error::Error for ParseError {
-    fn description(&self) -> &str {
-        match *self {
-            ParseError::Number(ref err) => err.description(),
-            ParseError::Length => "illegal MAC address length",
-        }
-    }
-
-    fn cause(&self) -> Option<&std::error::Error> {
-        match *self {
-            ParseError::Number(ref err) => Some(err),
-            ParseError::Length => None,
-        }
-    }
-}
-
-
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            ParseError::Number(ref err) => err.fmt(f),
-            ParseError::Length => write!(f, "illegal MAC address length"),
-        }
-    }
-}
-
-impl From<std::num::ParseIntError> for ParseError {
-    fn from(err: std::num::ParseIntError) -> ParseError {
-        ParseError::Number(err)
-    }
-}
-
-/// Sends the magic packet per UDP/IPv4.
-///
-/// #Example
-///
-/// ```
-/// wol::send_magic_packet_v4(vec![0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]);
-/// ```
-pub fn send_magic_packet_v4(mac: Vec<u8>) -> Result<(), &'static str> {
-    let buf = create_payload(mac);
-    let socket = create_socket((Ipv4Addr::new(0, 0, 0, 0), 0)).expect("Could not create socket.");
-    socket.connect((Ipv4Addr::new(255, 255, 255, 255), 0)).expect("Could not create connection.");
-    socket.send(&buf).expect("Could not send packet.");
-    Ok(())
-}
-
-/// Sends the magic packet per UDP/IPv6.
-///
-/// #Example
-///
-/// ```
-/// wol::send_magic_packet_v6(vec![0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]);
-/// ```
-pub fn send_magic_packet_v6(mac: Vec<u8>) -> Result<(), &'static str> {
-    let buf = create_payload(mac);
-    let socket = create_socket((Ipv6Addr::new(0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00), 0))
-        .expect("Could not create socket.");
-    socket.connect((Ipv6Addr::new(0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02), 0))
-        .expect("Could not create connection.");
-    socket.send(&buf).expect("Could not send packet.");
-    Ok(())
-}
-
-/// Creates the payload for the magic packet.
-fn create_payload(mac: Vec<u8>) -> [u8; 17 * 6] {
-    let mut buf: [u8; 17 * 6] = [0xFF; 17 * 6];
-    for x in 1..17 {
-        for y in 0..6 {
-            buf[x * 6 + y] = mac[y];
-        }
-    }
-    buf
-}
-
-/// Creates the UdpSocket.
-fn create_socket<A: ToSocketAddrs>(address: A) -> Result<UdpSocket, std::io::Error> {
-    let socket = UdpSocket::bind(address).unwrap();
-    socket.set_broadcast(true)?;
-    Ok(socket)
-}
-
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn test_parse_mac() {
-        assert_eq!(super::parse_mac("FF:FF:FF:FF:FF:FF".to_string()),
-                   Ok(vec![255, 255, 255, 255, 255, 255]));
-        assert_eq!(super::parse_mac("00:00:00:00:00:00".to_string()),
-                   Ok(vec![0, 0, 0, 0, 0, 0]));
-    }
-
-    #[test]
-    fn test_create_socket_v4() {
-        assert!(super::create_socket("127.0.0.1:0").is_ok());
-    }
-
-    #[test]
-    fn test_create_socket_v6() {
-        assert!(super::create_socket("[::1]:0").is_ok());
-    }
+//! Build a `MagicPacket` for the MAC address of the remote system, a `MacAddr` parsed from
+//! strings like "AB:CD:01:02:03:04" or made of six bytes, e.g. with a SecureOn password, and send
+//! it with a `WolClient`, per IPv4 with `WolClient::v4`, per IPv6 with `WolClient::v6` or to any
+//! destination with `WolClient::new`. `use wol::prelude::*;` imports these types with the error
+//! and report of sending, the stable surface of the crate. Applications managing their own
+//! sockets can send with `send_magic_packet_on`.
+//!
+//! The free functions sending packets of earlier versions, e.g. `send_magic_packet_v4` and
+//! `wake`, are deprecated shims around `WolClient`, whose notes name the replacement.
+//!
+//! The crate is organized in modules whose main items are re-exported here: `mac` parses MAC
+//! addresses, `packet` builds magic packets, `transport` sends them and `error` holds the
+//! common error type. Everything else, e.g. the HTTP daemon in `server`, builds on these. The
+//! daemon needs the `serve` feature and the `wol` binary the default `cli` feature, so that
+//! library users can leave out what they do not use. `cli` also holds the modules only the
+//! binary uses, `i18n`, `manpage` and `pick`; `config` stays in `std`, as the hosts file, which
+//! the daemon reads as well, shares its parser and `ConfigError`.
+//!
+//! Without the default `std` feature, the crate is `no_std` and does not allocate. It then only
+//! contains `MacAddr` and `MagicPacket`, so that embedded devices can build the packet and send
+//! it with their own network stack, or so that it can be compiled to `wasm32-unknown-unknown`.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(any(feature = "std", test))]
+extern crate core;
+
+#[cfg(feature = "std")]
+#[macro_use]
+pub mod log;
+
+pub mod mac;
+pub mod packet;
+pub mod prelude;
+pub mod redact;
+
+#[cfg(feature = "std")]
+pub mod analyze;
+#[cfg(feature = "raw")]
+pub mod arp;
+#[cfg(feature = "std")]
+pub mod audit;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "raw")]
+pub mod dhcp;
+#[cfg(feature = "std")]
+pub mod diagnose;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "serve")]
+pub mod events;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod hosts;
+#[cfg(feature = "cli")]
+pub mod i18n;
+#[cfg(feature = "icmp")]
+pub mod icmp;
+#[cfg(feature = "std")]
+pub mod interrupt;
+#[cfg(feature = "std")]
+pub mod job;
+#[cfg(feature = "std")]
+pub mod listen;
+#[cfg(feature = "cli")]
+pub mod manpage;
+#[cfg(feature = "serve")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod neighbors;
+#[cfg(feature = "std")]
+pub mod output;
+#[cfg(feature = "std")]
+pub mod paths;
+#[cfg(feature = "std")]
+pub mod pcap;
+#[cfg(feature = "cli")]
+pub mod pick;
+#[cfg(feature = "std")]
+pub mod relay;
+#[cfg(feature = "std")]
+pub mod resolve;
+#[cfg(feature = "std")]
+pub mod secrets;
+#[cfg(feature = "std")]
+pub mod seen;
+#[cfg(feature = "std")]
+pub mod selftest;
+#[cfg(feature = "serve")]
+pub mod server;
+#[cfg(feature = "std")]
+pub mod signed;
+#[cfg(feature = "snmp")]
+pub mod snmp;
+#[cfg(feature = "std")]
+pub mod strategy;
+#[cfg(feature = "std")]
+pub mod subnet;
+#[cfg(feature = "std")]
+pub mod sys;
+#[cfg(all(feature = "std", unix))]
+pub mod syslog;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod tokens;
+#[cfg(feature = "std")]
+mod toml;
+#[cfg(feature = "std")]
+pub mod transport;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "std")]
+pub mod units;
+#[cfg(feature = "std")]
+pub mod verify;
+
+pub use mac::{MacAddr, MacFormat, MacRange, ParseError};
+pub use packet::{create_payload, MagicPacket};
+#[cfg(feature = "std")]
+pub use error::{ErrorCode, WolError};
+#[cfg(feature = "std")]
+pub use subnet::broadcast_for;
+#[cfg(feature = "std")]
+pub use transport::{destination_v4, destination_v6, send_magic_packet_on, SendReport, WolClient};
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+pub use mac::parse_mac;
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+pub use transport::{send_dual, send_magic_packet_v4, send_magic_packet_v6, send_packet, wake};
 
-    #[test]
-    fn test_create_payload() {
-        let payload = super::create_payload(vec![0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
-        let result = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
-                      0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
-                      0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
-                      0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
-                      0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
-                      0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
-                      0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
-                      0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
-                      0x00, 0x01, 0x02, 0x03, 0x04, 0x05];
-        assert_eq!(payload.len(), result.len());
-        assert!(payload.iter()
-            .zip(result.iter())
-            .all(|(a, b)| a == b));
-    }
-}
@@ -0,0 +1,489 @@
+//! Receiving magic packets, e.g. to check that they reach a network segment.
+//!
+//! A `Listener` receives magic packets per UDP and, on Linux with the `CAP_NET_RAW` capability,
+//! as raw Ethernet frames of EtherType 0x0842 or, in promiscuous mode, in all frames an
+//! interface sees, also those to other hosts. Everything else is ignored. A `Tracker`
+//! suppresses the repetitions of a packet senders commonly send in a burst and counts the
+//! packets per MAC address.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use analyze;
+use pcap::{self, ETHERTYPE_WOL};
+use sys::RawSocket;
+use output;
+use {MacAddr, MagicPacket};
+
+/// The port magic packets are usually sent to.
+pub const DEFAULT_PORT: u16 = 9;
+
+/// How long identical packets from the same sender count as one burst by default.
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(1);
+
+/// How often the receiving threads check whether to stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How many bursts a `Tracker` remembers before it forgets those which are over.
+const MAX_TRACKED_BURSTS: usize = 1024;
+
+/// The length of the longest Ethernet frame received, with a VLAN tag.
+const MAX_FRAME_LEN: usize = 1518;
+
+/// Where a magic packet came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    /// A UDP datagram from the given address.
+    Udp(SocketAddr),
+    /// A raw Ethernet frame from the given MAC address.
+    Ethernet(MacAddr),
+}
+
+impl Source {
+    /// Returns the name of the transport, "udp" or "ethernet".
+    pub fn transport(&self) -> &'static str {
+        match *self {
+            Source::Udp(_) => "udp",
+            Source::Ethernet(_) => "ethernet",
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::Udp(address) => write!(f, "{}", address),
+            Source::Ethernet(mac) => write!(f, "{}", mac),
+        }
+    }
+}
+
+/// A received magic packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Received {
+    /// The time the packet was received.
+    pub time: SystemTime,
+    pub source: Source,
+    pub packet: MagicPacket,
+    /// The Ethernet frame of the packet, rebuilt for UDP, see `pcap::udp_frame`.
+    pub frame: Vec<u8>,
+}
+
+/// Receives magic packets until cancelled.
+///
+/// #Example
+///
+/// ```
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use wol::listen::Listener;
+/// use wol::{MacAddr, WolClient};
+///
+/// let listener = Listener::bind("127.0.0.1:0").unwrap();
+/// let client = WolClient::new(listener.local_addr().unwrap());
+/// let stop = AtomicBool::new(false);
+/// let mut macs = Vec::new();
+/// std::thread::scope(|scope| {
+///     scope.spawn(|| client.send(&wol::MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]))));
+///     listener.run(&stop, |received| {
+///         macs.push(received.packet.mac());
+///         stop.store(true, Ordering::SeqCst);
+///     }).unwrap();
+/// });
+/// assert_eq!(macs, vec![MacAddr::new([0, 1, 2, 3, 4, 5])]);
+/// ```
+#[derive(Debug)]
+pub struct Listener {
+    udp: UdpSocket,
+    raw: Option<RawSocket>,
+    echo: bool,
+    tolerant: bool,
+}
+
+impl Listener {
+    /// Receives per UDP on the given address, e.g. "0.0.0.0:9".
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Listener> {
+        let udp = UdpSocket::bind(address)?;
+        udp.set_read_timeout(Some(POLL_INTERVAL))?;
+        Ok(Listener {
+            udp,
+            raw: None,
+            echo: false,
+            tolerant: false,
+        })
+    }
+
+    /// Also receives raw Ethernet frames from all interfaces or only from the given one. Only
+    /// supported on Linux and needs the `CAP_NET_RAW` capability.
+    pub fn with_raw(mut self, interface: Option<&str>) -> io::Result<Listener> {
+        let raw = RawSocket::open(ETHERTYPE_WOL, interface)?;
+        raw.set_read_timeout(POLL_INTERVAL)?;
+        self.raw = Some(raw);
+        Ok(self)
+    }
+
+    /// Also receives all frames `interface` sees in promiscuous mode and finds the magic packets
+    /// in them, as raw Ethernet frames and per UDP, also to other hosts and ports, e.g. on the
+    /// mirror or SPAN port of a switch. Replaces `with_raw`. Only supported on Linux and needs
+    /// the `CAP_NET_RAW` capability.
+    pub fn with_promiscuous(mut self, interface: &str) -> io::Result<Listener> {
+        let raw = RawSocket::open_promiscuous(interface)?;
+        raw.set_read_timeout(POLL_INTERVAL)?;
+        self.raw = Some(raw);
+        Ok(self)
+    }
+
+    /// Sends magic packets received per UDP back to their sender, so that `selftest::remote` on
+    /// the sending machine can check that they arrived intact. Packets from the port of the
+    /// listener itself are not echoed, so that two echoing listeners cannot play ping-pong.
+    pub fn with_echo(mut self, echo: bool) -> Listener {
+        self.echo = echo;
+        self
+    }
+
+    /// Also receives magic packets anywhere in UDP datagrams, e.g. behind the header of another
+    /// protocol or followed by padding, instead of only datagrams which are exactly a magic
+    /// packet, see `MagicPacket::find`.
+    pub fn with_tolerant(mut self, tolerant: bool) -> Listener {
+        self.tolerant = tolerant;
+        self
+    }
+
+    /// Returns the address of the UDP socket.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.udp.local_addr()
+    }
+
+    /// Calls `on_packet` for every received magic packet until `cancel` is set, e.g. by
+    /// `interrupt::catch_interrupt`, or receiving fails.
+    pub fn run<F: FnMut(Received)>(self, cancel: &AtomicBool, mut on_packet: F) -> io::Result<()> {
+        let local_addr = self.udp.local_addr()?;
+        let stop = &AtomicBool::new(false);
+        let stopped = move || cancel.load(Ordering::SeqCst) || stop.load(Ordering::SeqCst);
+        let (sender, receiver) = mpsc::channel();
+        let udp = self.udp;
+        let raw = self.raw;
+        let echo = self.echo;
+        let tolerant = self.tolerant;
+        thread::scope(|scope| {
+            let udp_sender = sender.clone();
+            let udp_thread = scope.spawn(move || {
+                let mut buf = [0; 1500];
+                while !stopped() {
+                    let (len, source) = match udp.recv_from(&mut buf) {
+                        Ok(received) => received,
+                        Err(ref err) if is_timeout(err) => continue,
+                        Err(err) => {
+                            stop.store(true, Ordering::SeqCst);
+                            return Err(err);
+                        },
+                    };
+                    let decoded = if tolerant {
+                        MagicPacket::find(&buf[..len]).map(|(_, packet)| packet)
+                    } else {
+                        MagicPacket::decode(&buf[..len])
+                    };
+                    match decoded {
+                        Ok(packet) => {
+                            if echo && source.port() != local_addr.port() {
+                                if let Err(err) = udp.send_to(&buf[..len], source) {
+                                    warn!("cannot echo the packet to {}: {}", source, err);
+                                }
+                            }
+                            let _ = udp_sender.send(Received {
+                                time: SystemTime::now(),
+                                source: Source::Udp(source),
+                                packet,
+                                frame: pcap::udp_frame(source, local_addr, &buf[..len]),
+                            });
+                        },
+                        Err(err) => trace!("ignoring {} bytes from {}: {}", len, source, err),
+                    }
+                }
+                Ok(())
+            });
+            let raw_thread = raw.map(|mut raw| {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let mut buf = [0; MAX_FRAME_LEN];
+                    while !stopped() {
+                        let len = match raw.recv(&mut buf) {
+                            Ok(len) => len,
+                            Err(ref err) if is_timeout(err) => continue,
+                            Err(err) => {
+                                stop.store(true, Ordering::SeqCst);
+                                return Err(err);
+                            },
+                        };
+                        match analyze::decode_frame(SystemTime::now(), &buf[..len]) {
+                            Some(sighting) => {
+                                let _ = sender.send(Received {
+                                    time: sighting.time,
+                                    source: sighting.source,
+                                    packet: sighting.packet,
+                                    frame: buf[..len].to_vec(),
+                                });
+                            },
+                            None => trace!("ignoring frame of {} bytes", len),
+                        }
+                    }
+                    Ok(())
+                })
+            });
+            // The channel closes once both threads have stopped.
+            drop(sender);
+            for received in receiver {
+                on_packet(received);
+            }
+            udp_thread.join().unwrap()?;
+            match raw_thread {
+                Some(raw_thread) => raw_thread.join().unwrap(),
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+/// The counters of the magic packets for one MAC address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacStats {
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+    /// The number of packets, not counting duplicates.
+    pub count: u64,
+    /// The number of duplicates suppressed.
+    pub duplicates: u64,
+    /// The senders in the order they were first seen, IP addresses for UDP and MAC addresses for
+    /// raw frames.
+    pub sources: Vec<String>,
+}
+
+/// Suppresses duplicates and keeps counters per MAC address.
+///
+/// A packet is a duplicate if an identical packet came from the same source within the window
+/// before it, so a burst counts once however long it lasts as long as the gaps are shorter.
+///
+/// #Example
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use wol::listen::{Received, Source, Tracker};
+/// use wol::{MacAddr, MagicPacket};
+///
+/// let mut tracker = Tracker::new(Duration::from_secs(1));
+/// let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+/// let received = Received {
+///     time: SystemTime::now(),
+///     source: Source::Udp("10.0.0.1:4000".parse().unwrap()),
+///     packet,
+///     frame: Vec::new(),
+/// };
+/// assert!(tracker.record(&received));
+/// assert!(!tracker.record(&received));
+/// assert_eq!(tracker.stats()[&packet.mac()].duplicates, 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Tracker {
+    window: Duration,
+    last: HashMap<(Source, MagicPacket), SystemTime>,
+    stats: BTreeMap<MacAddr, MacStats>,
+}
+
+impl Tracker {
+    /// Creates a tracker suppressing duplicates within `window`; zero keeps all packets.
+    pub fn new(window: Duration) -> Tracker {
+        Tracker {
+            window,
+            last: HashMap::new(),
+            stats: BTreeMap::new(),
+        }
+    }
+
+    /// Counts a received packet and returns whether it is new, i.e. not a duplicate.
+    pub fn record(&mut self, received: &Received) -> bool {
+        if self.last.len() >= MAX_TRACKED_BURSTS {
+            // Senders use a new port for every wake, so forget the bursts which are over.
+            let (window, now) = (self.window, received.time);
+            self.last.retain(|_, time| now.duration_since(*time).unwrap_or_default() < window);
+        }
+        let key = (received.source, received.packet);
+        let previous = self.last.insert(key, received.time);
+        let duplicate = previous.is_some_and(|previous| {
+            received.time.duration_since(previous).unwrap_or_default() < self.window
+        });
+        let source = match received.source {
+            Source::Udp(address) => address.ip().to_string(),
+            Source::Ethernet(mac) => mac.to_string(),
+        };
+        let stats = self.stats.entry(received.packet.mac()).or_insert_with(|| {
+            MacStats {
+                first_seen: received.time,
+                last_seen: received.time,
+                count: 0,
+                duplicates: 0,
+                sources: Vec::new(),
+            }
+        });
+        stats.last_seen = received.time;
+        if !stats.sources.contains(&source) {
+            stats.sources.push(source);
+        }
+        if duplicate {
+            stats.duplicates += 1;
+        } else {
+            stats.count += 1;
+        }
+        !duplicate
+    }
+
+    /// Returns the counters per MAC address.
+    pub fn stats(&self) -> &BTreeMap<MacAddr, MacStats> {
+        &self.stats
+    }
+}
+
+impl fmt::Display for Tracker {
+    /// Formats the counters as a table, one line per MAC address.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f,
+                 "{:<17}  {:>6}  {:>10}  {:<24}  {:<24}  SOURCES",
+                 "MAC",
+                 "COUNT",
+                 "DUPLICATES",
+                 "FIRST SEEN",
+                 "LAST SEEN")?;
+        for (mac, stats) in &self.stats {
+            writeln!(f,
+                     "{:<17}  {:>6}  {:>10}  {:<24}  {:<24}  {}",
+                     mac.to_string(),
+                     stats.count,
+                     stats.duplicates,
+                     output::timestamp(stats.first_seen),
+                     output::timestamp(stats.last_seen),
+                     stats.sources.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether the error is a read timeout, which is reported as `WouldBlock` on Unix.
+fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{Listener, Received, Source, Tracker};
+    use {MacAddr, MagicPacket};
+
+    #[test]
+    fn test_run_ignores_other_packets() {
+        let listener = Listener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let stop = AtomicBool::new(false);
+        let mut received = Vec::new();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+                socket.send_to(b"hello", address).unwrap();
+                let packet = MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]));
+                socket.send_to(&packet.to_bytes(), address).unwrap();
+            });
+            listener.run(&stop, |packet| {
+                received.push(packet);
+                stop.store(true, Ordering::SeqCst);
+            }).unwrap();
+        });
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].packet.mac(), MacAddr::new([2, 0, 0, 0, 0, 1]));
+        assert_eq!(received[0].source.transport(), "udp");
+        match received[0].source {
+            Source::Udp(source) => assert_eq!(source.ip(), address.ip()),
+            Source::Ethernet(_) => panic!("received per UDP"),
+        }
+        assert_eq!(received[0].frame.len(), 14 + 20 + 8 + 102);
+    }
+
+    #[test]
+    fn test_run_tolerant() {
+        let listener = Listener::bind("127.0.0.1:0").unwrap().with_tolerant(true);
+        let address = listener.local_addr().unwrap();
+        let stop = AtomicBool::new(false);
+        let mut received = Vec::new();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+                let mut datagram = b"header".to_vec();
+                datagram.extend_from_slice(&MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]))
+                    .to_bytes());
+                datagram.extend_from_slice(&[0; 16]);
+                socket.send_to(&datagram, address).unwrap();
+            });
+            listener.run(&stop, |packet| {
+                received.push(packet);
+                stop.store(true, Ordering::SeqCst);
+            }).unwrap();
+        });
+        assert_eq!(received[0].packet.mac(), MacAddr::new([2, 0, 0, 0, 0, 1]));
+    }
+
+    #[test]
+    fn test_run_cancelled() {
+        let listener = Listener::bind("127.0.0.1:0").unwrap();
+        let stop = AtomicBool::new(true);
+        assert!(listener.run(&stop, |_| panic!("nothing was sent")).is_ok());
+    }
+
+    #[test]
+    fn test_tracker() {
+        let mut tracker = Tracker::new(Duration::from_secs(1));
+        let mac = MacAddr::new([2, 0, 0, 0, 0, 1]);
+        let received = |millis, source: &str, packet| {
+            Received {
+                time: UNIX_EPOCH + Duration::from_millis(millis),
+                source: Source::Udp(source.parse().unwrap()),
+                packet,
+                frame: Vec::new(),
+            }
+        };
+        let packet = MagicPacket::new(mac);
+        assert!(tracker.record(&received(0, "10.0.0.1:4000", packet)));
+        // The burst goes on as long as the gaps are shorter than the window.
+        assert!(!tracker.record(&received(900, "10.0.0.1:4000", packet)));
+        assert!(!tracker.record(&received(1800, "10.0.0.1:4000", packet)));
+        assert!(tracker.record(&received(2800, "10.0.0.1:4000", packet)));
+        // Another sender or another password is not a duplicate.
+        assert!(tracker.record(&received(2800, "10.0.0.2:4000", packet)));
+        let with_password = packet.with_password([1; 6]);
+        assert!(tracker.record(&received(2900, "10.0.0.1:4000", with_password)));
+        let stats = &tracker.stats()[&mac];
+        assert_eq!((stats.count, stats.duplicates), (4, 2));
+        assert_eq!(stats.first_seen, UNIX_EPOCH);
+        assert_eq!(stats.last_seen, UNIX_EPOCH + Duration::from_millis(2900));
+        assert_eq!(stats.sources, vec!["10.0.0.1", "10.0.0.2"]);
+        assert!(tracker.to_string().lines().nth(1).unwrap().starts_with("02:00:00:00:00:01"));
+    }
+
+    #[test]
+    fn test_tracker_without_window() {
+        let mut tracker = Tracker::new(Duration::from_secs(0));
+        let received = Received {
+            time: UNIX_EPOCH,
+            source: Source::Ethernet(MacAddr::new([2, 0, 0, 0, 0, 9])),
+            packet: MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1])),
+            frame: Vec::new(),
+        };
+        assert!(tracker.record(&received));
+        assert!(tracker.record(&received));
+    }
+}
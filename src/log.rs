@@ -0,0 +1,249 @@
+//! Minimal diagnostic logging.
+//!
+//! The library reports what it is doing (parsing, binding sockets, sending) through the macros
+//! of this module. Nothing is logged unless an application raises the maximum level with
+//! `set_max_level`, e.g. from a `RUST_LOG` style filter parsed with `parse_filter`. Messages go
+//! to standard error unless another `Log` implementation is installed with `set_logger`, e.g. one
+//! of the system log destinations in `wol::syslog`.
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The severity of a log message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn from_usize(n: usize) -> Option<Level> {
+        match n {
+            1 => Some(Level::Error),
+            2 => Some(Level::Warn),
+            3 => Some(Level::Info),
+            4 => Some(Level::Debug),
+            5 => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    /// Returns the level `n` steps more verbose than this one, saturating at `Trace`.
+    pub fn more_verbose(self, n: usize) -> Level {
+        Level::from_usize(self as usize + n).unwrap_or(Level::Trace)
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        f.pad(name)
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Level, String> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(Level::Error),
+            "warn" | "warning" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(format!("unknown log level '{}'", s)),
+        }
+    }
+}
+
+/// A named value attached to a log message, e.g. the MAC address of a wake event.
+pub type Field<'a> = (&'a str, &'a dyn fmt::Display);
+
+/// Creates a field for `log_fields`.
+pub fn field<'a, T: fmt::Display>(key: &'a str, value: &'a T) -> Field<'a> {
+    (key, value)
+}
+
+/// A destination for log messages.
+pub trait Log: Send + Sync {
+    /// Handles a single message. `target` is the module path the message originates from.
+    fn log(&self, level: Level, target: &str, message: &fmt::Arguments);
+
+    /// Handles a message with structured fields.
+    ///
+    /// By default, the fields are appended to the message as `key=value` pairs. Destinations
+    /// which understand structured data, like the systemd journal, override this.
+    fn log_fields(&self, level: Level, target: &str, message: &fmt::Arguments, fields: &[Field]) {
+        let mut line = message.to_string();
+        for &(key, value) in fields {
+            line.push_str(&format!(" {}={}", key, value));
+        }
+        self.log(level, target, &format_args!("{}", line));
+    }
+}
+
+/// Writes log messages to standard error.
+pub struct StderrLog;
+
+impl Log for StderrLog {
+    fn log(&self, level: Level, target: &str, message: &fmt::Arguments) {
+        eprintln!("{:5} {}: {}", level, target, message);
+    }
+}
+
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(0);
+static LOGGER: Mutex<Option<Box<dyn Log>>> = Mutex::new(None);
+
+/// Sets the most verbose level which is still logged. `None` disables logging.
+pub fn set_max_level(level: Option<Level>) {
+    MAX_LEVEL.store(level.map_or(0, |l| l as usize), Ordering::Relaxed);
+}
+
+/// Returns the most verbose level which is still logged.
+pub fn max_level() -> Option<Level> {
+    Level::from_usize(MAX_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Returns whether messages of the given level are logged.
+pub fn enabled(level: Level) -> bool {
+    level as usize <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Replaces the destination of log messages, which is standard error by default.
+pub fn set_logger(logger: Box<dyn Log>) {
+    *LOGGER.lock().unwrap_or_else(|e| e.into_inner()) = Some(logger);
+}
+
+/// Logs a message if its level is enabled. Usually called through the logging macros.
+pub fn log(level: Level, target: &str, message: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let logger = LOGGER.lock().unwrap_or_else(|e| e.into_inner());
+    match *logger {
+        Some(ref logger) => logger.log(level, target, &message),
+        None => StderrLog.log(level, target, &message),
+    }
+}
+
+/// Logs a message with structured fields if its level is enabled.
+pub fn log_fields(level: Level, target: &str, message: fmt::Arguments, fields: &[Field]) {
+    if !enabled(level) {
+        return;
+    }
+    let logger = LOGGER.lock().unwrap_or_else(|e| e.into_inner());
+    match *logger {
+        Some(ref logger) => logger.log_fields(level, target, &message, fields),
+        None => StderrLog.log_fields(level, target, &message, fields),
+    }
+}
+
+/// Parses a `RUST_LOG` style filter and returns the level for this crate.
+///
+/// The filter is a comma-separated list of either bare levels (`debug`) or `module=level`
+/// directives; a directive for `wol` takes precedence over a bare level. `off` disables logging.
+/// Returns an error if the filter mentions neither.
+///
+/// #Example
+///
+/// ```
+/// use wol::log::{parse_filter, Level};
+///
+/// assert_eq!(parse_filter("info,wol=trace"), Ok(Some(Level::Trace)));
+/// assert_eq!(parse_filter("warn,hyper=debug"), Ok(Some(Level::Warn)));
+/// assert_eq!(parse_filter("off"), Ok(None));
+/// ```
+pub fn parse_filter(filter: &str) -> Result<Option<Level>, String> {
+    fn level(s: &str) -> Result<Option<Level>, String> {
+        if s.eq_ignore_ascii_case("off") {
+            Ok(None)
+        } else {
+            s.parse().map(Some)
+        }
+    }
+
+    let mut default = None;
+    for directive in filter.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let mut parts = directive.splitn(2, '=');
+        let first = parts.next().unwrap_or("");
+        match parts.next() {
+            Some(l) if first == "wol" || first.starts_with("wol::") => return level(l),
+            Some(_) => (),
+            None => default = Some(level(first)?),
+        }
+    }
+    default.ok_or_else(|| format!("log filter '{}' does not configure this crate", filter))
+}
+
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::log::log($level, module_path!(), format_args!($($arg)+))
+    };
+}
+
+#[allow(unused_macros)]
+macro_rules! event {
+    ($level:expr, $msg:expr; $($key:ident = $value:expr),+) => {
+        $crate::log::log_fields($level,
+                                module_path!(),
+                                format_args!("{}", $msg),
+                                &[$($crate::log::field(stringify!($key), &$value)),+])
+    };
+}
+
+#[allow(unused_macros)]
+macro_rules! error {
+    ($($arg:tt)+) => { log!($crate::log::Level::Error, $($arg)+) };
+}
+
+#[allow(unused_macros)]
+macro_rules! warn {
+    ($($arg:tt)+) => { log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+#[allow(unused_macros)]
+macro_rules! info {
+    ($($arg:tt)+) => { log!($crate::log::Level::Info, $($arg)+) };
+}
+
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($($arg:tt)+) => { log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)+) => { log!($crate::log::Level::Trace, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_filter, Level};
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(parse_filter("debug"), Ok(Some(Level::Debug)));
+        assert_eq!(parse_filter("wol=info"), Ok(Some(Level::Info)));
+        assert_eq!(parse_filter("wol::transport=error, trace"), Ok(Some(Level::Error)));
+        assert_eq!(parse_filter("wol=off"), Ok(None));
+        assert!(parse_filter("other=debug").is_err());
+        assert!(parse_filter("loud").is_err());
+    }
+
+    #[test]
+    fn test_more_verbose() {
+        assert_eq!(Level::Warn.more_verbose(0), Level::Warn);
+        assert_eq!(Level::Warn.more_verbose(2), Level::Debug);
+        assert_eq!(Level::Warn.more_verbose(10), Level::Trace);
+    }
+}
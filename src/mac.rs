@@ -0,0 +1,486 @@
+//! MAC addresses and their parsing.
+//!
+//! This module does not need the standard library nor an allocator, see the `std` feature.
+use core::convert::TryFrom;
+use core::fmt;
+use core::num::ParseIntError;
+use core::str::FromStr;
+
+/// A MAC address.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacAddr([u8; 6]);
+
+impl MacAddr {
+    /// Creates a MAC address from its six octets.
+    pub const fn new(octets: [u8; 6]) -> MacAddr {
+        MacAddr(octets)
+    }
+
+    /// Returns the six octets of the MAC address.
+    pub const fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// Returns whether this is the broadcast address FF:FF:FF:FF:FF:FF.
+    pub fn is_broadcast(&self) -> bool {
+        self.0 == [0xFF; 6]
+    }
+
+    /// Returns whether all octets are zero.
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 6]
+    }
+
+    /// Returns whether this is a group address, i.e. the lowest bit of the first octet is set.
+    /// The broadcast address is one as well.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 1 != 0
+    }
+
+    /// Returns whether the address was assigned locally, i.e. the second lowest bit of the first
+    /// octet is set, e.g. by a virtual machine or by a device randomizing its Wi-Fi address for
+    /// privacy.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::MacAddr;
+    ///
+    /// assert!(MacAddr::new([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]).is_locally_administered());
+    /// assert!(MacAddr::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]).is_universally_administered());
+    /// ```
+    pub fn is_locally_administered(&self) -> bool {
+        self.0[0] & 2 != 0
+    }
+
+    /// Returns whether the address was assigned by the manufacturer, the opposite of
+    /// `is_locally_administered`.
+    pub fn is_universally_administered(&self) -> bool {
+        !self.is_locally_administered()
+    }
+
+    /// Returns why no network card can have this address, or `None` if it can. Such addresses
+    /// are almost always typos, and waking them silently succeeds without waking anything.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::MacAddr;
+    ///
+    /// assert_eq!(MacAddr::new([0xFF; 6]).suspicious(), Some("the broadcast address"));
+    /// let multicast = MacAddr::new([0x01, 0, 0x5E, 0, 0, 1]);
+    /// assert_eq!(multicast.suspicious(), Some("a multicast address"));
+    /// assert_eq!(MacAddr::new([0, 0x11, 0x22, 0x33, 0x44, 0x55]).suspicious(), None);
+    /// ```
+    pub fn suspicious(&self) -> Option<&'static str> {
+        if self.is_broadcast() {
+            Some("the broadcast address")
+        } else if self.is_zero() {
+            Some("the all-zero address")
+        } else if self.is_multicast() {
+            Some("a multicast address")
+        } else {
+            None
+        }
+    }
+
+    /// Returns a value displaying the MAC address in the given format.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::mac::{MacAddr, MacFormat};
+    ///
+    /// let mac = MacAddr::new([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]);
+    /// assert_eq!(mac.format(MacFormat::CISCO).to_string(), "aaff.b012.3456");
+    /// assert_eq!(mac.format("xx-xx-xx-xx-xx-xx".parse().unwrap()).to_string(),
+    ///            "aa-ff-b0-12-34-56");
+    /// ```
+    pub fn format(&self, format: MacFormat) -> FormattedMac {
+        FormattedMac { mac: *self, format }
+    }
+}
+
+impl fmt::Display for MacAddr {
+    /// Formats the MAC address as upper case hex octets separated by colons.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let o = self.0;
+        write!(f, "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", o[0], o[1], o[2], o[3], o[4], o[5])
+    }
+}
+
+impl fmt::Debug for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MacAddr({})", self)
+    }
+}
+
+/// The separator of a `MacFormat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Separator {
+    /// Octets separated by colons, e.g. `AA:FF:B0:12:34:56`.
+    Colon,
+    /// Octets separated by hyphens, e.g. `AA-FF-B0-12-34-56` as used by Windows.
+    Hyphen,
+    /// Groups of two octets separated by dots, e.g. `aaff.b012.3456` as used by Cisco.
+    Dot,
+    /// No separator, e.g. `AAFFB0123456`.
+    None,
+}
+
+/// How to display a MAC address, see `MacAddr::format`.
+///
+/// A format can be parsed from an example with `x` or `X` for the hex digits, e.g.
+/// `xx-xx-xx-xx-xx-xx` or `XXXX.XXXX.XXXX`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MacFormat {
+    pub separator: Separator,
+    pub uppercase: bool,
+}
+
+impl MacFormat {
+    /// Upper case octets separated by colons, the format of `Display`.
+    pub const COLON: MacFormat = MacFormat {
+        separator: Separator::Colon,
+        uppercase: true,
+    };
+    /// Upper case octets separated by hyphens as used by Windows.
+    pub const WINDOWS: MacFormat = MacFormat {
+        separator: Separator::Hyphen,
+        uppercase: true,
+    };
+    /// Lower case groups of two octets separated by dots as used by Cisco.
+    pub const CISCO: MacFormat = MacFormat {
+        separator: Separator::Dot,
+        uppercase: false,
+    };
+}
+
+impl Default for MacFormat {
+    fn default() -> MacFormat {
+        MacFormat::COLON
+    }
+}
+
+impl FromStr for MacFormat {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<MacFormat, UnknownFormat> {
+        let patterns = [("xx:xx:xx:xx:xx:xx", Separator::Colon),
+                        ("xx-xx-xx-xx-xx-xx", Separator::Hyphen),
+                        ("xxxx.xxxx.xxxx", Separator::Dot),
+                        ("xxxxxxxxxxxx", Separator::None)];
+        let separator = match patterns.iter().find(|p| p.0.eq_ignore_ascii_case(s)) {
+            Some(&(_, separator)) => separator,
+            None => return Err(UnknownFormat),
+        };
+        let uppercase = s.contains('X');
+        if uppercase && s.contains('x') {
+            return Err(UnknownFormat);
+        }
+        Ok(MacFormat { separator, uppercase })
+    }
+}
+
+/// The error of parsing a `MacFormat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownFormat;
+
+impl fmt::Display for UnknownFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown MAC address format, expected e.g. XX:XX:XX:XX:XX:XX, \
+                   xx-xx-xx-xx-xx-xx, xxxx.xxxx.xxxx or xxxxxxxxxxxx")
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for UnknownFormat {}
+
+/// A MAC address displayed in a given format, see `MacAddr::format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormattedMac {
+    mac: MacAddr,
+    format: MacFormat,
+}
+
+impl fmt::Display for FormattedMac {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, octet) in self.mac.0.iter().enumerate() {
+            let separator = match self.format.separator {
+                Separator::Colon => Some(':'),
+                Separator::Hyphen => Some('-'),
+                Separator::Dot if i % 2 == 0 => Some('.'),
+                Separator::Dot | Separator::None => None,
+            };
+            if let (true, Some(separator)) = (i > 0, separator) {
+                write!(f, "{}", separator)?;
+            }
+            if self.format.uppercase {
+                write!(f, "{:02X}", octet)?;
+            } else {
+                write!(f, "{:02x}", octet)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<[u8; 6]> for MacAddr {
+    fn from(octets: [u8; 6]) -> MacAddr {
+        MacAddr(octets)
+    }
+}
+
+impl From<MacAddr> for [u8; 6] {
+    fn from(mac: MacAddr) -> [u8; 6] {
+        mac.0
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for MacAddr {
+    type Error = ParseError;
+
+    /// Converts a slice of exactly six bytes.
+    fn try_from(bytes: &'a [u8]) -> Result<MacAddr, ParseError> {
+        if bytes.len() != 6 {
+            return Err(ParseError::Length);
+        }
+        let mut octets = [0; 6];
+        octets.copy_from_slice(bytes);
+        Ok(MacAddr(octets))
+    }
+}
+
+impl<'a> TryFrom<&'a str> for MacAddr {
+    type Error = ParseError;
+
+    /// Parses the MAC address like `from_str`.
+    fn try_from(s: &'a str) -> Result<MacAddr, ParseError> {
+        s.parse()
+    }
+}
+
+impl FromStr for MacAddr {
+    type Err = ParseError;
+
+    /// Parses six hex octets separated by colons, e.g. "AA:FF:B0:12:34:56".
+    fn from_str(s: &str) -> Result<MacAddr, ParseError> {
+        let mut octets = [0; 6];
+        let mut len = 0;
+        for part in s.split(':') {
+            let octet = u8::from_str_radix(part, 16)?;
+            if len < octets.len() {
+                octets[len] = octet;
+            }
+            len += 1;
+        }
+        if len == octets.len() {
+            Ok(MacAddr(octets))
+        } else {
+            Err(ParseError::Length)
+        }
+    }
+}
+
+/// An inclusive range of consecutive MAC addresses, e.g. of machines provisioned in a batch.
+///
+/// #Example
+///
+/// ```
+/// use wol::{MacAddr, MacRange};
+///
+/// let range: MacRange = "AA:BB:CC:00:00:FF-AA:BB:CC:00:01:01".parse().unwrap();
+/// let macs: Vec<String> = range.map(|mac| mac.to_string()).collect();
+/// assert_eq!(macs, ["AA:BB:CC:00:00:FF", "AA:BB:CC:00:01:00", "AA:BB:CC:00:01:01"]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MacRange {
+    next: u64,
+    last: u64,
+}
+
+impl MacRange {
+    /// Creates the range from `first` to `last`, both included. The range is empty if `last` is
+    /// before `first`.
+    pub fn new(first: MacAddr, last: MacAddr) -> MacRange {
+        MacRange {
+            next: to_u64(first),
+            last: to_u64(last),
+        }
+    }
+}
+
+fn to_u64(mac: MacAddr) -> u64 {
+    mac.0.iter().fold(0, |n, &octet| n << 8 | u64::from(octet))
+}
+
+fn from_u64(n: u64) -> MacAddr {
+    let mut octets = [0; 6];
+    octets.copy_from_slice(&n.to_be_bytes()[2..]);
+    MacAddr(octets)
+}
+
+impl Iterator for MacRange {
+    type Item = MacAddr;
+
+    fn next(&mut self) -> Option<MacAddr> {
+        if self.next > self.last {
+            return None;
+        }
+        self.next += 1;
+        Some(from_u64(self.next - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.last + 1).saturating_sub(self.next);
+        match usize::try_from(len) {
+            Ok(len) => (len, Some(len)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+}
+
+impl FromStr for MacRange {
+    type Err = ParseError;
+
+    /// Parses two MAC addresses separated by a hyphen, e.g.
+    /// "AA:BB:CC:00:00:01-AA:BB:CC:00:00:40".
+    fn from_str(s: &str) -> Result<MacRange, ParseError> {
+        let mut parts = s.splitn(2, '-');
+        let first: MacAddr = parts.next().unwrap_or("").trim().parse()?;
+        let last: MacAddr = parts.next().ok_or(ParseError::Range)?.trim().parse()?;
+        if last < first {
+            return Err(ParseError::Range);
+        }
+        Ok(MacRange::new(first, last))
+    }
+}
+
+/// Parses the MAC address from a given string.
+///
+/// #Example
+///
+/// ```
+/// # #![allow(deprecated)]
+/// let mac = wol::mac::parse_mac("AA:FF:B0:12:34:56".to_string());
+/// assert_eq!(mac, Ok(vec![0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]))
+/// ```
+#[cfg(feature = "std")]
+#[deprecated(note = "use `s.parse::<MacAddr>()`, and `MacAddr::octets` for the bytes")]
+pub fn parse_mac(mac: String) -> Result<Vec<u8>, ParseError> {
+    trace!("parsing MAC address '{}'", mac);
+    let result = mac.parse::<MacAddr>().map(|mac| mac.octets().to_vec());
+    if let Err(ref err) = result {
+        debug!("could not parse MAC address '{}': {}", mac, err);
+    }
+    result
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Number(ParseIntError),
+    Length,
+    /// A range is not of the form `FIRST-LAST` or LAST is before FIRST.
+    Range,
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn (::std::error::Error) + 'static)> {
+        match *self {
+            ParseError::Number(ref err) => Some(err),
+            ParseError::Length | ParseError::Range => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Number(ref err) => err.fmt(f),
+            ParseError::Length => write!(f, "illegal MAC address length"),
+            ParseError::Range => write!(f, "illegal MAC address range"),
+        }
+    }
+}
+
+impl From<ParseIntError> for ParseError {
+    fn from(err: ParseIntError) -> ParseError {
+        ParseError::Number(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::TryFrom;
+
+    use super::{MacAddr, MacFormat, MacRange, ParseError};
+
+    #[test]
+    #[cfg(feature = "std")]
+    #[allow(deprecated)]
+    fn test_parse_mac() {
+        assert_eq!(super::parse_mac("FF:FF:FF:FF:FF:FF".to_string()),
+                   Ok(vec![255, 255, 255, 255, 255, 255]));
+        assert_eq!(super::parse_mac("00:00:00:00:00:00".to_string()),
+                   Ok(vec![0, 0, 0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_suspicious() {
+        assert_eq!(MacAddr::new([0; 6]).suspicious(), Some("the all-zero address"));
+        assert!(MacAddr::new([0xFF; 6]).is_multicast());
+        assert_eq!(MacAddr::new([0x33, 0x33, 0, 0, 0, 1]).suspicious(),
+                   Some("a multicast address"));
+        // Locally administered addresses, e.g. of virtual machines, are fine.
+        assert_eq!(MacAddr::new([0x02, 0, 0, 0, 0, 1]).suspicious(), None);
+        assert!(MacAddr::new([0x02, 0, 0, 0, 0, 1]).is_locally_administered());
+        assert!(!MacAddr::new([0xFE, 0, 0, 0, 0, 1]).is_universally_administered());
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("AA:FF:b0:12:34:56".parse(),
+                   Ok(MacAddr::new([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56])));
+        assert_eq!("AA:FF:B0:12:34".parse::<MacAddr>(), Err(ParseError::Length));
+        assert_eq!("AA:FF:B0:12:34:56:78".parse::<MacAddr>(), Err(ParseError::Length));
+        assert!(matches!("AA:FF:B0:12:34:XY".parse::<MacAddr>(), Err(ParseError::Number(_))));
+    }
+
+    #[test]
+    fn test_range() {
+        let first = MacAddr::new([0xAA, 0xBB, 0xCC, 0x00, 0x00, 0x01]);
+        let range: MacRange = "AA:BB:CC:00:00:01-AA:BB:CC:00:00:40".parse().unwrap();
+        assert_eq!(range.size_hint(), (64, Some(64)));
+        assert_eq!(range.clone().next(), Some(first));
+        assert_eq!(range.last(), Some(MacAddr::new([0xAA, 0xBB, 0xCC, 0x00, 0x00, 0x40])));
+        assert_eq!(MacRange::new(first, first).count(), 1);
+        let max = MacAddr::new([0xFF; 6]);
+        assert_eq!(MacRange::new(max, max).count(), 1);
+        assert_eq!("AA:BB:CC:00:00:02-AA:BB:CC:00:00:01".parse::<MacRange>(),
+                   Err(ParseError::Range));
+        assert_eq!("AA:BB:CC:00:00:02".parse::<MacRange>(), Err(ParseError::Range));
+    }
+
+    #[test]
+    fn test_format() {
+        let mac = MacAddr::new([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]);
+        let format = |f: &str| mac.format(f.parse::<MacFormat>().unwrap()).to_string();
+        assert_eq!(format("XX:XX:XX:XX:XX:XX"), mac.to_string());
+        assert_eq!(format("xx:xx:xx:xx:xx:xx"), "aa:ff:b0:12:34:56");
+        assert_eq!(format("XX-XX-XX-XX-XX-XX"), "AA-FF-B0-12-34-56");
+        assert_eq!(format("XXXX.XXXX.XXXX"), "AAFF.B012.3456");
+        assert_eq!(format("xxxxxxxxxxxx"), "aaffb0123456");
+        assert!("xX:xx:xx:xx:xx:xx".parse::<MacFormat>().is_err());
+        assert!("xx:xx".parse::<MacFormat>().is_err());
+    }
+
+    #[test]
+    fn test_conversions() {
+        let mac = MacAddr::from([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]);
+        assert_eq!(MacAddr::try_from("AA:FF:B0:12:34:56"), Ok(mac));
+        assert_eq!(MacAddr::try_from(&[0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56][..]), Ok(mac));
+        assert_eq!(MacAddr::try_from(&[0xAA, 0xFF][..]), Err(ParseError::Length));
+        assert_eq!(<[u8; 6]>::from(mac), mac.octets());
+    }
+}
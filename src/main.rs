@@ -1,57 +1,3487 @@
 extern crate wol;
 extern crate getopts;
 
-use getopts::Options;
+use getopts::{Matches, Options};
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} [-h] [-4|-6] MAC", program);
-    print!("{}", opts.usage(&brief));
+use wol::analyze;
+use wol::audit::{AuditEvent, AuditLog, Initiator};
+use wol::config::{self, Config, MacCheck};
+use wol::batch::{self, Batch, Transport};
+use wol::diagnose::{self, Finding, Status};
+use wol::history::{History, Wake};
+use wol::hosts::{self, ExportFormat, Host, Hosts};
+use wol::i18n::{self, Lang, Message};
+use wol::interrupt;
+use wol::job::Job;
+use wol::listen::{self, Listener, Tracker};
+use wol::log::{self, Level};
+use wol::neighbors::{self, NeighborAddr};
+use wol::manpage::{self, Command, Page, Section};
+use wol::{ErrorCode, MacAddr, MacFormat, MacRange, MagicPacket, WolClient, WolError};
+use wol::output::{self, Format, Printer, Record, Value};
+use wol::packet::parse_password;
+use wol::paths::{self, Paths};
+use wol::pcap::{PcapReader, PcapWriter};
+use wol::pick;
+use wol::redact;
+use wol::relay::{self, Relay};
+use wol::resolve::{self, Chain, Leases, Source};
+use wol::seen::{Seen, Sighting};
+use wol::selftest;
+#[cfg(feature = "serve")]
+use wol::server::{Server, Settings};
+use wol::strategy::{self, Strategy, Vlan};
+use wol::subnet::{self, Addressing, Cidr};
+use wol::sys;
+use wol::transport::Bursts;
+use wol::units::{self, HumanDuration, Interval, Port, RepeatCount};
+use wol::verify;
+
+/// Prints an error message, headed by `Message::Error` in the language of the user.
+macro_rules! print_error {
+    ($($arg:tt)*) => (println!("{} {}", Message::Error, format_args!($($arg)*)))
+}
+
+/// Prints the problem with an argument, headed by `Message::IllegalArgument` in the language of
+/// the user.
+macro_rules! print_illegal {
+    ($($arg:tt)*) => (println!("{} {}", Message::IllegalArgument, format_args!($($arg)*)))
+}
+
+/// The exit codes, which scripts can rely on.
+mod exit {
+    pub const SUCCESS: i32 = 0;
+    /// An error not covered by the other codes, e.g. an unreadable audit log.
+    pub const ERROR: i32 = 1;
+    /// Invalid arguments, configuration or hosts file.
+    pub const USAGE: i32 = 2;
+    /// The magic packet could not be sent.
+    pub const SEND_FAILED: i32 = 3;
+    /// The host did not come up before the verification timed out.
+    pub const NOT_UP: i32 = 4;
+    /// Some hosts of a batch were woken, others were not.
+    pub const PARTIAL: i32 = 5;
+    /// The packet of `wol selftest` did not arrive intact.
+    pub const SELFTEST_FAILED: i32 = 6;
+    /// The user pressed ctrl-C, like the shell reports for SIGINT.
+    pub const INTERRUPTED: i32 = 130;
+
+    /// The exit codes with their meaning, for the help and the man page.
+    pub const CODES: &[(i32, &str)] = &[(SUCCESS, "success"),
+                                        (ERROR, "other errors, e.g. an unreadable audit log"),
+                                        (USAGE, "invalid arguments, configuration or hosts file"),
+                                        (SEND_FAILED, "the magic packet could not be sent"),
+                                        (NOT_UP,
+                                         "the host did not come up before the verification \
+                                          timed out"),
+                                        (PARTIAL, "some hosts of a batch were woken, others were \
+                                                   not"),
+                                        (SELFTEST_FAILED,
+                                         "the packet of wol selftest did not arrive intact"),
+                                        (INTERRUPTED, "interrupted by ctrl-C")];
+
+    /// Describes the exit codes in the help.
+    pub fn help() -> String {
+        let mut help = "\nExit codes:\n".to_string();
+        for &(code, meaning) in CODES {
+            help.push_str(&format!("    {:<3} {}\n", code, meaning));
+        }
+        help
+    }
+
+    /// Returns the exit code of a batch from the codes of the woken hosts. If every host failed
+    /// the same way, that is the exit code; hosts which were skipped because a host they
+    /// require failed are not counted.
+    pub fn for_batch(codes: &[i32], skipped: usize) -> i32 {
+        let failed: Vec<i32> = codes.iter().cloned().filter(|&code| code != SUCCESS).collect();
+        if failed.is_empty() && skipped == 0 {
+            SUCCESS
+        } else if failed.len() == codes.len() && failed.iter().all(|&code| code == failed[0]) {
+            failed[0]
+        } else {
+            PARTIAL
+        }
+    }
+}
+
+/// The options of a mode, which are recorded while they are added to getopts so that the man
+/// page can be generated from them.
+struct Opts {
+    getopts: Options,
+    docs: Vec<manpage::Opt>,
+}
+
+impl Opts {
+    fn new() -> Opts {
+        Opts {
+            getopts: Options::new(),
+            docs: Vec::new(),
+        }
+    }
+
+    fn optflag(&mut self, short: &str, long: &str, desc: &str) -> &mut Opts {
+        self.getopts.optflag(short, long, desc);
+        self.docs.push(manpage::Opt::new(short, long, "", desc));
+        self
+    }
+
+    fn optflagmulti(&mut self, short: &str, long: &str, desc: &str) -> &mut Opts {
+        self.getopts.optflagmulti(short, long, desc);
+        self.docs.push(manpage::Opt::new(short, long, "", desc));
+        self
+    }
+
+    fn optopt(&mut self, short: &str, long: &str, desc: &str, hint: &str) -> &mut Opts {
+        self.getopts.optopt(short, long, desc, hint);
+        self.docs.push(manpage::Opt::new(short, long, hint, desc));
+        self
+    }
+
+    fn optmulti(&mut self, short: &str, long: &str, desc: &str, hint: &str) -> &mut Opts {
+        self.getopts.optmulti(short, long, desc, hint);
+        self.docs.push(manpage::Opt::new(short, long, hint, desc));
+        self
+    }
+
+    fn optflagopt(&mut self, short: &str, long: &str, desc: &str, hint: &str) -> &mut Opts {
+        self.getopts.optflagopt(short, long, desc, hint);
+        self.docs.push(manpage::Opt::new(short, long, hint, desc).with_optional(true));
+        self
+    }
+}
+
+impl Deref for Opts {
+    type Target = Options;
+
+    fn deref(&self) -> &Options {
+        &self.getopts
+    }
+}
+
+/// A mode of wol, selected by the first argument, as shown in the help and the man page.
+struct Mode {
+    name: &'static str,
+    /// The arguments following the program name.
+    synopsis: &'static str,
+    description: &'static str,
+    options: fn() -> Opts,
+}
+
+/// The modes of wol, waking hosts first.
+const MODES: &[Mode] = &[Mode {
+                             name: "wake",
+                             synopsis: "[wake] [-h] [-v|-q] [--log-to DEST] [-4|-6] [-n] \
+                                        [--password PW|--password-file FILE] \
+                                        [--format json|csv|plain] [--verify IP] \
+                                        [-w] [-y] MAC|HOST...|--range FIRST-LAST|\
+                                        --ip IP... [--solicit]|\
+                                        --all|--tag TAG...|--pick",
+                             description: "Sends magic packets to wake the given hosts, given by \
+                                           their MAC address or their name in the hosts file. \
+                                           Without hosts on a terminal, the hosts to wake are \
+                                           picked from the hosts file.",
+                             options: wake_options,
+                         },
+                         #[cfg(feature = "serve")]
+                         Mode {
+                             name: "serve",
+                             synopsis: "serve [-h] [-v|-q] [--log-to DEST] [-4|-6] [--bind ADDR]",
+                             description: "Runs an HTTP daemon which wakes hosts on POST \
+                                           /wake/MAC and exports metrics on GET /metrics.",
+                             options: serve_options,
+                         },
+                         Mode {
+                             name: "config",
+                             synopsis: "config show|paths [-h] [--config FILE] \
+                                        [--config-dir DIR] [--broadcast ADDR] [--port PORT] \
+                                        [--repeat N] [--interface IFACE] [--show-secrets]",
+                             description: "With show, prints the effective configuration, with \
+                                           paths the files it is read from, the hosts file, \
+                                           the audit log and the wake history.",
+                             options: config_options,
+                         },
+                         Mode {
+                             name: "init",
+                             synopsis: "init [-h] [--config-dir DIR] [--force]",
+                             description: "Writes a configuration file and a hosts file with \
+                                           commented examples of all settings to the \
+                                           configuration directory, as a start for new users.",
+                             options: init_options,
+                         },
+                         Mode {
+                             name: "list",
+                             synopsis: "list [-h] [--hosts FILE] [--format json|csv|plain] \
+                                        [--mac-format FORMAT] [--tag TAG...]",
+                             description: "Lists the hosts of the hosts file, or those with \
+                                           all tags given by --tag, also as hosts list.",
+                             options: list_options,
+                         },
+                         Mode {
+                             name: "last",
+                             synopsis: "last [-h] [-n N] [--format json|csv|plain] \
+                                        [--mac-format FORMAT] [HOST]",
+                             description: "Shows when each host was last woken, by whom and \
+                                           whether it came up, or the last wakes of HOST, from \
+                                           the wake history.",
+                             options: last_options,
+                         },
+                         Mode {
+                             name: "history",
+                             synopsis: "history export [-h] [--since DURATION] \
+                                        [--format json|csv|plain] [--mac-format FORMAT]",
+                             description: "With export, prints all wakes of the wake history, \
+                                           the oldest first, with when they were sent, whether \
+                                           the hosts came up and how long they took, e.g. for \
+                                           dashboards tracking hosts which wake unreliably.",
+                             options: history_options,
+                         },
+                         Mode {
+                             name: "hosts",
+                             synopsis: "hosts list|export|check|refresh|sync [-h] [--hosts FILE] \
+                                        [--format json|csv|ethers] [--migrate] \
+                                        [push|pull --via URL]",
+                             description: "With export, prints all keys of the hosts of the \
+                                           hosts file in a fixed order, to share them with \
+                                           other tools. With check, prints all errors of the \
+                                           hosts file, and with --migrate converts a file of \
+                                           an older version to the current one. With \
+                                           refresh, records the addresses of the hosts in the \
+                                           neighbor table or the DHCP leases as the last ones \
+                                           they were seen at, which hosts without ip are sent \
+                                           to and verified at. With sync push or pull, \
+                                           replaces the hosts file of the wol serve daemon at \
+                                           --via with the local one, or the other way round, \
+                                           authenticated with the [relay] token.",
+                             options: hosts_options,
+                         },
+                         Mode {
+                             name: "listen",
+                             synopsis: "listen [-h] [-v|-q] [--log-to DEST] [--bind ADDR] \
+                                        [--port PORT] [--raw [IFACE]] [--promiscuous IFACE] \
+                                        [--pcap FILE] [--echo] [--format json|csv|plain] \
+                                        [--dedup-window DURATION] [--stats-interval DURATION]",
+                             description: "Prints the magic packets arriving at a UDP port, and \
+                                           with --raw those sent over Ethernet or with \
+                                           --promiscuous all seen by an interface, until ctrl-C \
+                                           is pressed.",
+                             options: listen_options,
+                         },
+                         Mode {
+                             name: "analyze",
+                             synopsis: "analyze [-h] [-v|-q] [--format json|csv|plain] \
+                                        [--mac-format FORMAT] FILE",
+                             description: "Lists the magic packets in a capture in the pcap \
+                                           format.",
+                             options: analyze_options,
+                         },
+                         Mode {
+                             name: "replay",
+                             synopsis: "replay [-h] [-v|-q] [-n] [-4|-6] [--to ADDR] \
+                                        [--port PORT,...] [--timing] \
+                                        [--format json|csv|plain] FILE",
+                             description: "Sends the magic packets in a capture in the pcap \
+                                           format again, to their captured destination or to \
+                                           --to and --port.",
+                             options: replay_options,
+                         },
+                         Mode {
+                             name: "selftest",
+                             synopsis: "selftest [-h] [-v|-q] [-4|-6] [--remote] [--bind ADDR] \
+                                        [--timeout DURATION] \
+                                        [--password PW|--password-file FILE] \
+                                        [--format json|csv|plain] --expect MAC",
+                             description: "Sends a magic packet and checks that it arrives \
+                                           intact, at this machine or, with --remote, at wol \
+                                           listen --echo on another machine.",
+                             options: selftest_options,
+                         },
+                         Mode {
+                             name: "doctor",
+                             synopsis: "doctor [-h] [-4|-6] [--config FILE] [--config-dir DIR] \
+                                        [--broadcast ADDR] [--port PORT] [--interface IFACE] \
+                                        [--listen-port PORT]",
+                             description: "Checks the environment of wol: whether it may send \
+                                           broadcasts, the network interfaces with their \
+                                           broadcast addresses, whether the configuration and \
+                                           the hosts file can be read and whether wol listen \
+                                           can bind its port. The report can be pasted into \
+                                           bug reports as it is.",
+                             options: doctor_options,
+                         },
+                         #[cfg(feature = "tui")]
+                         Mode {
+                             name: "tui",
+                             synopsis: "tui [-h] [-4|-6] [--hosts FILE] [--verify-port PORT]",
+                             description: "Shows a dashboard of the hosts of the hosts file, \
+                                           from which they can be woken.",
+                             options: tui_options,
+                         },
+                         Mode {
+                             name: "manpage",
+                             synopsis: "manpage [-h] [--format roff|markdown]",
+                             description: "Prints this manual page, or with --format markdown \
+                                           the same help in markdown.",
+                             options: manpage_options,
+                         }];
+
+/// Prints the help of a mode; the help of waking also lists the other modes and the exit codes.
+fn print_usage(program: &str, name: &str, opts: &Opts) {
+    let synopses: Vec<String> = MODES.iter()
+        .filter(|mode| name == "wake" || mode.name == name)
+        .map(|mode| format!("{} {}", program, mode.synopsis))
+        .collect();
+    print!("{}", opts.usage(&format!("Usage: {}", synopses.join("\n       "))));
+    if name == "wake" {
+        print!("{}", exit::help());
+    }
+}
+
+/// Sets the log level from the command line flags, falling back to `RUST_LOG`.
+fn init_logging(quiet: bool, verbosity: usize) {
+    let default = Some(Level::Warn);
+    let level = if quiet {
+        None
+    } else if verbosity > 0 {
+        Some(Level::Warn.more_verbose(verbosity))
+    } else {
+        match env::var("RUST_LOG") {
+            Ok(filter) => log::parse_filter(&filter).unwrap_or(default),
+            Err(_) => default,
+        }
+    };
+    log::set_max_level(level);
+}
+
+/// Sends log messages to the given destination instead of standard error.
+fn set_log_destination(destination: &str) -> Result<(), String> {
+    match destination {
+        "stderr" => Ok(()),
+        #[cfg(unix)]
+        "syslog" => {
+            let syslog = wol::syslog::Syslog::new("wol")
+                .map_err(|err| format!("{}: {}", Message::CannotConnect("syslog"), err))?;
+            log::set_logger(Box::new(syslog));
+            Ok(())
+        },
+        #[cfg(unix)]
+        "journald" => {
+            let journald = wol::syslog::Journald::new("wol")
+                .map_err(|err| format!("{}: {}", Message::CannotConnect("journald"), err))?;
+            log::set_logger(Box::new(journald));
+            Ok(())
+        },
+        _ => Err(Message::UnsupportedLogDestination(destination).to_string()),
+    }
+}
+
+/// Prints what would be sent where.
+fn print_dry_run(packet: &MagicPacket, client: &WolClient) {
+    println!("destination: {}", client.destination().ip());
+    println!("port:        {}", client.destination().port());
+    println!("interface:   {}", client.interface().unwrap_or("(chosen by the operating system)"));
+    if let Some(netns) = client.netns() {
+        println!("netns:       {}", netns.display());
+    }
+    match client.source() {
+        Some(source) => println!("source:      {}", source),
+        None => println!("source:      (chosen by the operating system)"),
+    }
+    if let Some(port) = client.source_port() {
+        println!("source port: {}", port);
+    }
+    println!("repeat:      {}", client.repeat());
+    println!("bursts:      {}", client.bursts());
+    match client.ttl() {
+        Some(ttl) => println!("ttl:         {}", ttl),
+        None => println!("ttl:         (chosen by the operating system)"),
+    }
+    if let Some(dscp) = client.dscp() {
+        println!("dscp:        {}", dscp);
+    }
+    if let Some(mark) = client.mark() {
+        println!("mark:        {}", mark);
+    }
+    println!("packet:      {}", packet);
+    println!("payload:     {} bytes", packet.to_bytes().len());
+    print!("{}", packet.hexdump());
+}
+
+/// Adds the options shared by all modes: logging, the audit log and help.
+fn add_common_options(opts: &mut Opts) {
+    opts.optopt("", "audit-log", "append a record of every wake to this file", "FILE");
+    opts.optflagmulti("v", "verbose", "log more details, repeat for even more (-vv)");
+    opts.optflag("q", "quiet", "do not log anything");
+    opts.optopt("", "log-to", "where to log to (default: stderr)", "stderr|syslog|journald");
+    opts.optopt("", "lang", "the language of messages (default: from LANG)", "en|de");
+    add_show_secrets_option(opts);
+    opts.optopt("", "config", "read the defaults from FILE instead of /etc/wol.toml and \
+                              ~/.config/wol/config.toml", "FILE");
+    add_config_dir_option(opts);
+    opts.optflag("h", "help", "print this help menu");
+}
+
+/// Adds the option showing SecureOn passwords and tokens.
+fn add_show_secrets_option(opts: &mut Opts) {
+    opts.optflag("",
+                 "show-secrets",
+                 &format!("show SecureOn passwords and tokens instead of {}", redact::REDACTED));
+}
+
+/// Adds the option replacing the directory of the user's configuration and hosts file.
+fn add_config_dir_option(opts: &mut Opts) {
+    opts.optopt("", "config-dir", "read config.toml and hosts.toml from DIR instead of \
+                                  ~/.config/wol or the directory of the platform, see wol config \
+                                  paths", "DIR");
+}
+
+/// Adds the options overriding the defaults of the configuration files.
+fn add_config_options(opts: &mut Opts) {
+    opts.optopt("", "broadcast", "send to this broadcast or multicast address", "ADDR");
+    opts.optopt("", "multicast-group", "send per IPv6 to this multicast group, e.g. a site-local \
+                                       one the routers forward (needs --ttl above 1)", "GROUP");
+    opts.optopt("", "cidr", "send to the directed broadcast address of this IPv4 network",
+                "NET/LEN");
+    opts.optopt("", "port", "send to these UDP ports, e.g. 7,9 (default: 0, any port)",
+                "PORT,...");
+    opts.optopt("", "repeat", "send every packet N times (default: 1)", "N");
+    opts.optopt("", "interface", "send through this network interface (Linux only)", "IFACE");
+    opts.optflag("", "check-link", "fail before sending if the interface is down or has no \
+                                    carrier (Linux, macOS and the BSDs)");
+    opts.optopt("",
+                "netns",
+                "send from this network namespace, a name of ip netns or a file like \
+                 /proc/PID/ns/net (Linux only, needs CAP_SYS_ADMIN)",
+                "NETNS");
+    opts.optopt("", "source", "send from this address of the system", "ADDR");
+    opts.optopt("", "source-port", "send from this UDP port, e.g. one a firewall lets out", "PORT");
+    opts.optopt("",
+                "vpn",
+                "send through this VPN interface, e.g. wg0 or tailscale0, from its address to the \
+                 directed broadcast of --cidr (Linux only)",
+                "IFACE");
+    opts.optopt("",
+                "internet",
+                "wake a host over the internet through the port forward of its router at \
+                 HOST:PORT, sending in bursts",
+                "HOST:PORT");
+    opts.optopt("", "ttl", "send with this TTL or IPv6 hop limit", "N");
+    opts.optopt("",
+                "dscp",
+                "tag the packets with this DSCP, 0 to 63 or a class like ef or af41, for \
+                 switches and routers prioritizing them (Linux only)",
+                "CLASS");
+    opts.optopt("",
+                "mark",
+                "mark the packets for policy routing, like iptables' MARK (Linux only, needs \
+                 CAP_NET_ADMIN)",
+                "MARK");
+    opts.optflag("", "dual-stack", "send per IPv4 to the limited broadcast address and per IPv6 \
+                                    to ff02::1, whichever the host listens on");
+    opts.optopt("", "verify-port", "TCP ports probed in order by --verify, e.g. 22,3389,445 \
+                                    (default: 22)", "PORT,...");
+    opts.optopt("",
+                "verify-timeout",
+                "how long to wait for --verify, in seconds or e.g. 90s (default: 60)",
+                "DURATION");
+    opts.optopt("", "verify-method", "how --verify probes hosts: connect to the TCP port, ping \
+                                      them (icmp feature), send ARP requests or listen for \
+                                      their DHCP requests while booting (raw feature) \
+                                      (default: tcp)",
+                "tcp|icmp|arp|dhcp");
+    opts.optopt("", "hosts", "read the known hosts from FILE (default: \
+                              ~/.config/wol/hosts.toml)", "FILE");
+    opts.optopt("", "mac-check", "what to do about broadcast, all-zero and multicast MAC \
+                                  addresses (default: warn)", "ignore|warn|reject");
+    opts.optopt("", "addressing", "where to send the packets of hosts with an IP address: the \
+                                   directed broadcast of their network if it is connected, else \
+                                   their IP (auto), the broadcast address or their IP (default: \
+                                   auto)", "auto|broadcast|unicast");
+}
+
+/// Parses the value of an option, if present.
+fn parse_opt<T>(matches: &Matches, name: &str) -> Result<Option<T>, String>
+    where T: FromStr,
+          T::Err: Display
+{
+    match matches.opt_str(name) {
+        None => Ok(None),
+        Some(s) => {
+            s.parse().map(Some).map_err(|err| {
+                format!("{}: {}", Message::InvalidOption { name, value: &s }, err)
+            })
+        },
+    }
+}
+
+/// Reads the SecureOn password from `--password`, the first line of `--password-file` or the
+/// `WOL_PASSWORD` environment variable. The latter two keep it out of the shell history and the
+/// process list.
+fn read_password(matches: &Matches) -> Result<Option<[u8; 6]>, String> {
+    let file = matches.opt_str("password-file");
+    let (source, password) = match (matches.opt_str("password"), file) {
+        (Some(_), Some(_)) => {
+            return Err(Message::PasswordOptionsExclusive.to_string())
+        },
+        (Some(password), None) => ("--password".to_string(), password),
+        (None, Some(path)) => {
+            let content = fs::read_to_string(&path)
+                .map_err(|err| format!("{}: {}", Message::CannotRead(Path::new(&path)), err))?;
+            (path, content.lines().next().unwrap_or("").to_string())
+        },
+        (None, None) => {
+            match env::var("WOL_PASSWORD") {
+                Ok(password) => ("WOL_PASSWORD".to_string(), password),
+                Err(_) => return Ok(None),
+            }
+        },
+    };
+    // The password is not repeated in the error, which may end up in logs.
+    parse_password(password.trim())
+        .map(Some)
+        .map_err(|err| format!("{}: {}", Message::InvalidPassword(&source), err))
+}
+
+/// Adds the options giving a SecureOn password.
+fn add_password_options(opts: &mut Opts) {
+    opts.optopt("",
+                "password",
+                "append a SecureOn password, with any separator of MAC addresses",
+                "XX:XX:XX:XX:XX:XX");
+    opts.optopt("",
+                "password-file",
+                "append the SecureOn password in the first line of FILE, which unlike \
+                 --password does not show up in the process list; WOL_PASSWORD is used without \
+                 either",
+                "FILE");
+}
+
+/// A duration given on the command line as seconds, or as a number with a unit, e.g. "500ms".
+struct SecsArg(Duration);
+
+impl FromStr for SecsArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SecsArg, String> {
+        units::parse_duration_or_secs(s).map(SecsArg)
+    }
+}
+
+/// Ports given on the command line separated by commas, e.g. "22,3389,445", none of them 0.
+struct PortsArg(Vec<u16>);
+
+impl FromStr for PortsArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PortsArg, String> {
+        Port::parse_list(s).map(PortsArg)
+    }
+}
+
+/// Reads the configuration from the environment and the configuration files and overrides their
+/// values with the command line flags.
+fn load_config(matches: &Matches) -> Result<Config, String> {
+    let env = Config::from_env().map_err(|err| format!("{}: {}", Message::Environment, err))?;
+    let paths = load_paths(matches);
+    let files = match matches.opt_str("config") {
+        Some(path) => {
+            Config::load(&path)
+                .map_err(|err| format!("{}: {}", Message::CannotRead(Path::new(&path)), err))?
+        },
+        None => {
+            Config::load_all(paths.config_files())
+                .map_err(|err| format!("{}: {}", Message::CannotReadConfig, err))?
+        },
+    };
+    let cidr_broadcast = match parse_opt::<Cidr>(matches, "cidr")? {
+        Some(cidr) => {
+            let broadcast = cidr.broadcast();
+            let cidr = cidr.to_string();
+            Some(broadcast.ok_or_else(|| Message::NoBroadcastAddress(&cidr).to_string())?)
+        },
+        None => None,
+    };
+    if cidr_broadcast.is_some() && matches.opt_present("broadcast") {
+        return Err(Message::BroadcastOrCidr.to_string());
+    }
+    let vpn = matches.opt_str("vpn");
+    let vpn_source = match vpn {
+        Some(_) if matches.opt_present("interface") || matches.opt_present("source") => {
+            return Err(Message::VpnSetsInterface.to_string());
+        },
+        Some(ref interface) => {
+            let source = sys::interface_ipv4(interface)
+                .map_err(|err| format!("{}: {}", Message::CannotUseVpn(interface), err))?;
+            Some(IpAddr::V4(source))
+        },
+        None => None,
+    };
+    let internet = match matches.opt_str("internet") {
+        Some(_) if ["broadcast", "cidr", "port", "vpn"].iter().any(|&o| matches.opt_present(o)) => {
+            return Err(Message::InternetSetsAddress.to_string());
+        },
+        Some(target) => Some(resolve_forward(&target)?),
+        None => None,
+    };
+    if matches.opt_present("multicast-group") &&
+       ["broadcast", "cidr", "internet"].iter().any(|&o| matches.opt_present(o)) {
+        return Err(Message::MulticastReplacesBroadcast.to_string());
+    }
+    let flags = Config {
+        multicast_group: parse_opt(matches, "multicast-group")?,
+        broadcast: parse_opt(matches, "broadcast")?
+            .or(cidr_broadcast)
+            .or(internet.map(|addr| addr.ip())),
+        ports: parse_opt::<PortsArg>(matches, "port")?
+            .map(|ports| ports.0)
+            .or(internet.map(|addr| vec![addr.port()])),
+        repeat: parse_opt::<RepeatCount>(matches, "repeat")?.map(RepeatCount::get),
+        // Set by `--burst`, which only some modes have.
+        bursts: None,
+        interface: matches.opt_str("interface").or(vpn.clone()),
+        check_link: if matches.opt_present("check-link") { Some(true) } else { None },
+        netns: matches.opt_str("netns"),
+        source: parse_opt(matches, "source")?.or(vpn_source),
+        source_port: parse_opt::<Port>(matches, "source-port")?.map(Port::get),
+        ttl: parse_opt(matches, "ttl")?,
+        dscp: parse_opt(matches, "dscp")?,
+        mark: parse_opt(matches, "mark")?,
+        dual_stack: if matches.opt_present("dual-stack") { Some(true) } else { None },
+        verify_ports: parse_opt::<PortsArg>(matches, "verify-port")?.map(|ports| ports.0),
+        verify_timeout: parse_opt::<SecsArg>(matches, "verify-timeout")?.map(|t| t.0),
+        verify_method: parse_opt(matches, "verify-method")?,
+        hosts_file: matches.opt_str("hosts").map(PathBuf::from),
+        // Opened by `apply_common_options`, before the configuration is read.
+        audit_log: None,
+        mac_check: parse_opt(matches, "mac-check")?,
+        cooldown: None,
+        addressing: parse_opt(matches, "addressing")?,
+        // Secrets on the command line would be visible to every user in the process list.
+        relay_token: None,
+        drain_timeout: None,
+        api_tokens: None,
+        resolve_order: None,
+        leases_file: None,
+        switch: None,
+        switch_community: None,
+    };
+    let mut config = env.merge(files).merge(flags);
+    // Neither the limited broadcast nor multicast packets are routed through a tunnel.
+    let routed = config.broadcast.is_some_and(|ip| match ip {
+        IpAddr::V4(ip) => !ip.is_broadcast() && !ip.is_multicast(),
+        IpAddr::V6(ip) => !ip.is_multicast(),
+    });
+    if let (Some(interface), false) = (vpn, routed) {
+        return Err(Message::VpnNeedsNetwork(&interface).to_string());
+    }
+    if config.hosts_file.is_none() && matches.opt_present("config-dir") {
+        config.hosts_file = Some(paths.hosts_file());
+    }
+    Ok(config)
+}
+
+/// Resolves the `HOST:PORT` of a router port forward, e.g. a dynamic DNS name.
+fn resolve_forward(target: &str) -> Result<SocketAddr, String> {
+    let invalid = |err: &dyn Display| {
+        format!("{}: {}", Message::InvalidOption { name: "internet", value: target }, err)
+    };
+    let mut addrs = target.to_socket_addrs().map_err(|err| invalid(&err))?;
+    addrs.next().ok_or_else(|| invalid(&Message::NoAddressFound))
+}
+
+/// Returns the locations of the files of wol, with the directory given by `--config-dir`.
+fn load_paths(matches: &Matches) -> Paths {
+    match matches.opt_str("config-dir") {
+        Some(dir) => Paths::new().with_config_dir(dir),
+        None => Paths::new(),
+    }
+}
+
+/// Resolves the name of a group of the hosts file to its hosts, or anything else to the single
+/// host of `resolve_target`.
+fn resolve_targets(target: &str, config: &Config) -> Result<Vec<Host>, String> {
+    match load_hosts(config)?.members(target) {
+        Some(members) => Ok(members?.into_iter().cloned().collect()),
+        None => resolve_target(target, config).map(|host| vec![host]),
+    }
+}
+
+/// Resolves a MAC address, or anything else by the resolvers of the configuration, e.g. the
+/// name of a host in the hosts file. A MAC address of the hosts file resolves to its host, so
+/// that the send options of the host apply.
+fn resolve_target(target: &str, config: &Config) -> Result<Host, String> {
+    let hosts = load_hosts(config)?;
+    let err = match target.parse::<MacAddr>() {
+        Ok(mac) => return Ok(hosts.by_mac(mac).cloned().unwrap_or_else(|| host_for_mac(mac))),
+        Err(err) => err,
+    };
+    let chain = resolver_chain(config, &hosts);
+    match chain.resolve(target) {
+        Ok(Some(found)) => {
+            let mut host = hosts.by_mac(found.mac).cloned().unwrap_or(found.clone());
+            host.ip = host.ip.or(found.ip);
+            Ok(host)
+        },
+        Ok(None) if config.resolve_order() == [Source::Hosts] => {
+            let message = Message::NotAHost {
+                target,
+                err: &err.to_string(),
+                hosts_file: &config.hosts_file(),
+            };
+            Err(message.to_string())
+        },
+        Ok(None) => {
+            // The same leases as those of the chain, which skips the resolver without any.
+            let leases = config.leases_file.clone().map(Leases::new).or_else(Leases::find);
+            let sources: Vec<Source> = config.resolve_order()
+                .into_iter()
+                .filter(|&source| source != Source::Leases || leases.is_some())
+                .collect();
+            let message = Message::NotKnown {
+                target,
+                err: &err.to_string(),
+                sources: &sources,
+                leases: leases.as_ref().map(Leases::path),
+            };
+            Err(message.to_string())
+        },
+        Err(err) => Err(format!("{}: {}", Message::CannotResolve(target), err)),
+    }
+}
+
+/// Returns the resolvers of the configuration with the given hosts.
+fn resolver_chain(config: &Config, hosts: &Hosts) -> Chain {
+    Chain::from_sources(&config.resolve_order(), hosts, config.leases_file.as_deref())
+}
+
+/// Resolves an IP address to the host with its MAC address in the neighbor table, which is the
+/// host of the hosts file with that MAC address if there is one. With `solicit`, the MAC address
+/// is asked for by ARP or NDP if the table does not have it.
+fn resolve_ip(addr: &NeighborAddr, solicit: bool, config: &Config) -> Result<Host, String> {
+    let mac = if solicit {
+        neighbors::resolve(addr, SOLICIT_TIMEOUT)
+    } else {
+        neighbors::lookup(addr)
+    };
+    let mac = match mac {
+        Ok(Some(mac)) => mac,
+        Ok(None) if solicit => return Err(Message::NoMacAnswer(&addr.to_string()).to_string()),
+        Ok(None) => return Err(Message::NotInNeighborTable(&addr.to_string()).to_string()),
+        Err(err) if solicit => {
+            return Err(format!("{}: {}", Message::CannotResolve(&addr.to_string()), err))
+        },
+        Err(err) => return Err(format!("{}: {}", Message::CannotReadNeighbors, err)),
+    };
+    let mut host = load_hosts(config)?.by_mac(mac).cloned().unwrap_or_else(|| host_for_mac(mac));
+    host.ip = host.ip.or(Some(addr.ip));
+    Ok(host)
+}
+
+/// How long `--solicit` waits for the MAC address of a host.
+const SOLICIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Returns the hosts of the hosts file with all the given tags, failing for invalid tags and if
+/// no host has them, which is more likely a typo than a wish to wake nothing.
+fn load_tagged(config: &Config, tags: &[String]) -> Result<Vec<Host>, String> {
+    if let Some(tag) = tags.iter().find(|tag| !hosts::is_tag(tag)) {
+        return Err(Message::InvalidTag(tag).to_string());
+    }
+    let hosts = load_hosts(config)?;
+    let tagged: Vec<Host> = hosts.tagged(tags).into_iter().cloned().collect();
+    if tagged.is_empty() && !tags.is_empty() {
+        let message = Message::NoHostTagged {
+            hosts_file: &config.hosts_file(),
+            tags,
+        };
+        return Err(message.to_string());
+    }
+    Ok(tagged)
+}
+
+/// Reads the hosts file, which need not exist.
+fn load_hosts(config: &Config) -> Result<Hosts, String> {
+    let path = config.hosts_file();
+    match Hosts::load(&path) {
+        Ok(hosts) => Ok(hosts),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Hosts::default()),
+        Err(e) => Err(format!("{}: {}", Message::CannotReadHosts(&path), e)),
+    }
+}
+
+/// Sets up logging and opens the audit log as requested by the common options.
+fn apply_common_options(matches: &Matches) -> Result<Option<AuditLog>, String> {
+    init_logging(matches.opt_present("q"), matches.opt_count("v"));
+    redact::set_show_secrets(matches.opt_present("show-secrets"));
+    if let Some(lang) = parse_opt::<Lang>(matches, "lang")? {
+        i18n::set_lang(lang);
+    }
+    if let Some(destination) = matches.opt_str("log-to") {
+        set_log_destination(&destination)?;
+    }
+    match matches.opt_str("audit-log") {
+        None => Ok(None),
+        Some(path) => open_audit_log(Path::new(&path)).map(Some),
+    }
+}
+
+/// Returns the audit log given by `--audit-log`, or else opens that of the configuration,
+/// creating its directory, e.g. `~/.local/state/wol` for `audit_log = true`.
+fn or_configured_audit_log(audit_log: Option<AuditLog>,
+                           config: &Config)
+                           -> Result<Option<AuditLog>, String> {
+    let path = match (audit_log, &config.audit_log) {
+        (Some(audit_log), _) => return Ok(Some(audit_log)),
+        (None, Some(path)) => path,
+        (None, None) => return Ok(None),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("{}: {}", Message::CannotCreate(dir), err))?;
+    }
+    open_audit_log(path).map(Some)
+}
+
+/// Returns the wake history in the state directory, if it is known.
+fn open_history() -> Option<History> {
+    Paths::new().history_file().map(History::new)
+}
+
+/// Records a wake in the history. As the history is kept without being asked for, failing to
+/// write it is only logged.
+fn remember(history: Option<&History>, wake: Wake) {
+    if let Some(history) = history {
+        if let Err(err) = history.record(&wake) {
+            log::log(Level::Warn,
+                     "wol",
+                     format_args!("cannot write wake history {}: {}",
+                                  history.path().display(),
+                                  err));
+        }
+    }
+}
+
+/// Returns the addresses hosts were last seen at, in the state directory, if it is known.
+fn open_seen() -> Option<Seen> {
+    Paths::new().seen_file().map(Seen::new)
+}
+
+/// Gives the hosts without IP address the one they were last seen at. As the addresses are kept
+/// without being asked for, failing to read them is only logged.
+fn fill_last_seen<'a, I>(seen: Option<&Seen>, hosts: I)
+    where I: IntoIterator<Item = &'a mut Host>
+{
+    if let Some(seen) = seen {
+        if let Err(err) = seen.fill(hosts) {
+            log::log(Level::Warn,
+                     "wol",
+                     format_args!("cannot read last-known addresses {}: {}",
+                                  seen.path().display(),
+                                  err));
+        }
+    }
+}
+
+/// Records the addresses of the hosts seen up, each with the address a probe reached it at, if
+/// any, or else that of the neighbor table, see `Sighting::find`. Failing to write them is only
+/// logged, like for the history.
+fn refresh_seen(seen: Option<&Seen>, up: &[(&Host, Option<IpAddr>)]) {
+    let seen = match seen {
+        Some(seen) if !up.is_empty() => seen,
+        _ => return,
+    };
+    let table = if up.iter().any(|&(_, probed)| probed.is_none()) {
+        neighbors::table().unwrap_or_else(|err| {
+            log::log(Level::Debug, "wol", format_args!("cannot read the neighbor table: {}", err));
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    let sightings: Vec<Sighting> =
+        up.iter().filter_map(|&(host, probed)| Sighting::find(host.mac, probed, &table)).collect();
+    if let Err(err) = seen.record(&sightings) {
+        log::log(Level::Warn,
+                 "wol",
+                 format_args!("cannot write last-known addresses {}: {}",
+                              seen.path().display(),
+                              err));
+    }
+}
+
+/// Returns the exit code of a command whose output to stdout could not be written: `code` if the
+/// pipe was closed, e.g. by `wol hosts export | head`, whose reader has all it wanted, or else
+/// `exit::ERROR` after printing the error to stderr, unlike `print_error!`.
+fn write_failed(err: &io::Error, code: i32) -> i32 {
+    if err.kind() == io::ErrorKind::BrokenPipe {
+        return code;
+    }
+    eprintln!("{} {}: {}", Message::Error, Message::CannotWriteOutput, err);
+    exit::ERROR
+}
+
+/// Opens an audit log, creating the file if needed.
+fn open_audit_log(path: &Path) -> Result<AuditLog, String> {
+    AuditLog::open(path).map_err(|err| format!("{}: {}", Message::CannotOpenAuditLog(path), err))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    i18n::set_lang(Lang::from_env());
+    let code = match args.get(1).map(String::as_str) {
+        Some("wake") => wake(&program, &args[2..]),
+        #[cfg(feature = "serve")]
+        Some("serve") => serve(&program, &args[2..]),
+        Some("config") => config(&program, &args[2..]),
+        Some("init") => init(&program, &args[2..]),
+        Some("list") => list(&program, &args[2..]),
+        Some("hosts") => hosts(&program, &args[2..]),
+        Some("last") => last(&program, &args[2..]),
+        Some("history") => history(&program, &args[2..]),
+        Some("listen") => listen(&program, &args[2..]),
+        Some("analyze") => analyze(&program, &args[2..]),
+        Some("replay") => replay(&program, &args[2..]),
+        Some("selftest") => selftest(&program, &args[2..]),
+        Some("doctor") => doctor(&program, &args[2..]),
+        #[cfg(feature = "tui")]
+        Some("tui") => tui(&program, &args[2..]),
+        Some("manpage") => manpage(&program, &args[2..]),
+        _ => wake(&program, &args[1..]),
+    };
+    process::exit(code);
+}
+
+/// Returns the options of `wol config`.
+fn config_options() -> Opts {
+    let mut opts = Opts::new();
+    add_config_options(&mut opts);
+    opts.optopt("", "config", "read FILE instead of /etc/wol.toml and ~/.config/wol/config.toml",
+                "FILE");
+    add_config_dir_option(&mut opts);
+    add_show_secrets_option(&mut opts);
+    opts.optflag("h", "help", "print this help menu");
+    opts
+}
+
+/// Shows the effective configuration or the locations of the files.
+fn config(program: &str, args: &[String]) -> i32 {
+    let opts = config_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "config", &opts);
+        return exit::SUCCESS;
+    }
+    redact::set_show_secrets(matches.opt_present("show-secrets"));
+    let show = match matches.free.first().map(String::as_str) {
+        Some("show") => true,
+        Some("paths") => false,
+        _ => {
+            print_usage(program, "config", &opts);
+            return exit::USAGE;
+        },
+    };
+    match load_config(&matches) {
+        Ok(ref config) if show => {
+            print!("{}", config);
+            exit::SUCCESS
+        },
+        Ok(config) => {
+            print_paths(&matches, &config);
+            exit::SUCCESS
+        },
+        Err(err) => {
+            print_error!("{}", err);
+            exit::USAGE
+        },
+    }
+}
+
+/// Prints the configuration files in the order they are read, the hosts file and the audit log,
+/// marking those which do not exist.
+fn print_paths(matches: &Matches, config: &Config) {
+    fn line(label: &str, path: &Path) {
+        let missing = if path.exists() { "" } else { " (missing)" };
+        println!("{:<10} {}{}", label, path.display(), missing);
+    }
+
+    let paths = load_paths(matches);
+    match matches.opt_str("config") {
+        Some(file) => line("config:", Path::new(&file)),
+        None => paths.config_files().iter().for_each(|file| line("config:", file)),
+    }
+    line("hosts:", &config.hosts_file());
+    match (&config.audit_log, paths.audit_log()) {
+        (Some(path), _) => line("audit log:", path),
+        (None, Some(default)) => println!("audit log: (none, audit_log = true writes {})",
+                                          default.display()),
+        (None, None) => println!("audit log: (none)"),
+    }
+    match paths.history_file() {
+        Some(path) => line("history:", &path),
+        None => println!("history:   (none)"),
+    }
+}
+
+/// Returns the options of `wol init`.
+fn init_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optopt("", "config-dir", "write config.toml and hosts.toml to DIR instead of \
+                                  ~/.config/wol or the directory of the platform",
+                "DIR");
+    opts.optflag("", "force", "overwrite existing files");
+    opts.optflag("h", "help", "print this help menu");
+    opts
+}
+
+/// Writes the templates of the configuration and the hosts file, unless they exist.
+fn init(program: &str, args: &[String]) -> i32 {
+    let opts = init_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        print_usage(program, "init", &opts);
+        return if matches.opt_present("h") { exit::SUCCESS } else { exit::USAGE };
+    }
+    let paths = load_paths(&matches);
+    let dir = match paths.config_dir() {
+        Some(dir) => dir,
+        None => {
+            print_error!("{}", Message::UnknownConfigDir);
+            return exit::ERROR;
+        },
+    };
+    let files = [(dir.join(paths::CONFIG_FILE), config::TEMPLATE),
+                 (paths.hosts_file(), hosts::TEMPLATE)];
+    // Nothing is written if a file exists, so that a failed init does not leave half a scaffold.
+    if !matches.opt_present("force") {
+        if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+            print_error!("{}", Message::Exists(path));
+            return exit::ERROR;
+        }
+    }
+    if let Err(err) = fs::create_dir_all(dir) {
+        print_error!("{}: {}", Message::CannotCreate(dir), err);
+        return exit::ERROR;
+    }
+    for (path, template) in &files {
+        if let Err(err) = fs::write(path, template) {
+            print_error!("{}: {}", Message::CannotWrite(path), err);
+            return exit::ERROR;
+        }
+        println!("{}", Message::Wrote(path));
+    }
+    exit::SUCCESS
+}
+
+/// Returns the options of `wol list`.
+fn list_options() -> Opts {
+    let mut opts = Opts::new();
+    add_config_options(&mut opts);
+    opts.optopt("", "config", "read FILE instead of /etc/wol.toml and ~/.config/wol/config.toml",
+                "FILE");
+    add_config_dir_option(&mut opts);
+    opts.optopt("", "format", "output format (default: plain)", "json|csv|plain");
+    opts.optopt("", "mac-format", "how to print MAC addresses (default: XX:XX:XX:XX:XX:XX)",
+                "xx-xx-xx-xx-xx-xx|xxxx.xxxx.xxxx|...");
+    opts.optmulti("", "tag", "only list the hosts with this tag, or with all tags if given \
+                              several times",
+                  "TAG");
+    opts.optflag("h", "help", "print this help menu");
+    opts
+}
+
+/// Lists the hosts of the hosts file, or those with the tags of `--tag`.
+fn list(program: &str, args: &[String]) -> i32 {
+    let opts = list_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "list", &opts);
+        return exit::SUCCESS;
+    }
+    let options = load_config(&matches).and_then(|config| {
+        let format = parse_opt::<Format>(&matches, "format")?.unwrap_or(Format::Plain);
+        let mac_format = parse_opt::<MacFormat>(&matches, "mac-format")?.unwrap_or_default();
+        Ok((load_tagged(&config, &matches.opt_strs("tag"))?, format, mac_format))
+    });
+    let (hosts, format, mac_format) = match options {
+        Ok(options) => options,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    // The addresses are kept without being asked for, so without them the column stays empty.
+    let sightings = open_seen().and_then(|seen| seen.sightings().ok()).unwrap_or_default();
+    let stdout = io::stdout();
+    let mut printer = Printer::new(format, stdout.lock());
+    for host in &hosts {
+        let last_seen = sightings.iter().find(|sighting| sighting.mac == host.mac);
+        let record = Record::new()
+            .field("name", host.name.as_str())
+            .field("mac", host.mac.format(mac_format).to_string())
+            .field("ip", host.ip.map(|ip| ip.to_string()))
+            .field("last_seen", last_seen.map(|sighting| sighting.ip.to_string()))
+            .field("tags", Some(host.tags.join(" ")).filter(|tags| !tags.is_empty()));
+        if let Err(err) = printer.print(&record) {
+            return write_failed(&err, exit::SUCCESS);
+        }
+    }
+    exit::SUCCESS
+}
+
+/// Returns the options of `wol last`.
+fn last_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optopt("n", "count", "show the last N wakes of HOST (default: 1)", "N");
+    opts.optopt("", "format", "output format (default: plain)", "json|csv|plain");
+    opts.optopt("", "mac-format", "how to print MAC addresses (default: XX:XX:XX:XX:XX:XX)",
+                "xx-xx-xx-xx-xx-xx|xxxx.xxxx.xxxx|...");
+    opts.optflag("h", "help", "print this help menu");
+    opts
+}
+
+/// Shows the last wake of every host, or the last wakes of one host, from the wake history.
+fn last(program: &str, args: &[String]) -> i32 {
+    let opts = last_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") || matches.free.len() > 1 {
+        print_usage(program, "last", &opts);
+        return if matches.opt_present("h") { exit::SUCCESS } else { exit::USAGE };
+    }
+    let options = parse_opt::<usize>(&matches, "count").and_then(|count| {
+        let format = parse_opt::<Format>(&matches, "format")?.unwrap_or(Format::Plain);
+        let mac_format = parse_opt::<MacFormat>(&matches, "mac-format")?.unwrap_or_default();
+        Ok((count.unwrap_or(1), format, mac_format))
+    });
+    let (count, format, mac_format) = match options {
+        Ok(options) => options,
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let history = match open_history() {
+        Some(history) => history,
+        None => {
+            print_error!("{}", Message::NoHistory);
+            return exit::ERROR;
+        },
+    };
+    let host = matches.free.first();
+    let wakes = match host {
+        Some(host) => history.last(host, count),
+        None => history.last_of_each(),
+    };
+    let wakes = match wakes {
+        Ok(wakes) => wakes,
+        Err(err) => {
+            print_error!("{}: {}", Message::CannotReadHistory(history.path()), err);
+            return exit::ERROR;
+        },
+    };
+    if wakes.is_empty() {
+        match host {
+            Some(host) => println!("{}", Message::NotWokenYet(host)),
+            None => println!("{}", Message::NoHostWokenYet),
+        }
+        return exit::ERROR;
+    }
+    let stdout = io::stdout();
+    let terminal = stdout.is_terminal();
+    let mut printer = Printer::new(format, stdout.lock());
+    if terminal {
+        printer = printer.with_terminal(output::color_allowed());
+    }
+    let records: Vec<Record> = wakes.iter()
+        .map(|wake| {
+            let mut record = wake.to_record();
+            record.set("mac", wake.mac.format(mac_format).to_string());
+            record
+        })
+        .collect();
+    if let Err(err) = printer.print_all(&records) {
+        return write_failed(&err, exit::SUCCESS);
+    }
+    exit::SUCCESS
+}
+
+/// Returns the options of `wol history`.
+fn history_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optopt("", "since", "only export the wakes of the last DURATION, e.g. 7d", "DURATION");
+    opts.optopt("", "format", "output format (default: json)", "json|csv|plain");
+    opts.optopt("", "mac-format", "how to print MAC addresses (default: XX:XX:XX:XX:XX:XX)",
+                "xx-xx-xx-xx-xx-xx|xxxx.xxxx.xxxx|...");
+    opts.optflag("h", "help", "print this help menu");
+    opts
+}
+
+/// Exports the wakes of the wake history.
+fn history(program: &str, args: &[String]) -> i32 {
+    let opts = history_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "history", &opts);
+        return exit::SUCCESS;
+    }
+    if matches.free.iter().map(String::as_str).ne(["export"]) {
+        print_usage(program, "history", &opts);
+        return exit::USAGE;
+    }
+    let options = parse_opt::<HumanDuration>(&matches, "since").and_then(|since| {
+        let format = parse_opt::<Format>(&matches, "format")?.unwrap_or(Format::Json);
+        let mac_format = parse_opt::<MacFormat>(&matches, "mac-format")?.unwrap_or_default();
+        Ok((since, format, mac_format))
+    });
+    let (since, format, mac_format) = match options {
+        Ok(options) => options,
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let history = match open_history() {
+        Some(history) => history,
+        None => {
+            print_error!("{}", Message::NoHistory);
+            return exit::ERROR;
+        },
+    };
+    let since = since.and_then(|since| SystemTime::now().checked_sub(since.0));
+    let wakes = match history.wakes_since(since.unwrap_or(UNIX_EPOCH)) {
+        Ok(wakes) => wakes,
+        Err(err) => {
+            print_error!("{}: {}", Message::CannotReadHistory(history.path()), err);
+            return exit::ERROR;
+        },
+    };
+    let records: Vec<Record> = wakes.iter()
+        .map(|wake| {
+            let mut record = wake.to_record();
+            record.set("mac", wake.mac.format(mac_format).to_string());
+            record
+        })
+        .collect();
+    let stdout = io::stdout();
+    if let Err(err) = Printer::new(format, stdout.lock()).print_all(&records) {
+        return write_failed(&err, exit::SUCCESS);
+    }
+    exit::SUCCESS
+}
+
+/// Returns the options of `wol serve`.
+#[cfg(feature = "serve")]
+fn serve_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optflag("4", "ipv4", "use IPv4");
+    opts.optflag("6", "ipv6", "use IPv6 (default)");
+    opts.optopt("", "bind", "address to listen on, :PORT for all IPv4 addresses, e.g. in a \
+                             container (default: 127.0.0.1:8080)", "ADDR");
+    opts.optopt("", "udp", "also accept wake requests signed with the [relay] token on this UDP \
+                            address", "ADDR");
+    opts.optopt("", "drain-timeout", "on SIGTERM or ctrl-C, wait DURATION for the requests in \
+                                      progress before cancelling their verifications (default: \
+                                      30s)", "DURATION");
+    add_config_options(&mut opts);
+    add_common_options(&mut opts);
+    opts
+}
+
+/// Reads the settings of the daemon from the configuration and the hosts file, at the start and
+/// on every reload.
+#[cfg(feature = "serve")]
+fn serve_settings(matches: &Matches) -> Result<Settings, String> {
+    let config = load_config(matches)?;
+    let hosts = load_hosts(&config)?;
+    Ok(Settings {
+        client: config.client(matches.opt_present("4")),
+        resolver: resolver_chain(&config, &hosts),
+        hosts,
+        probe: (config.verify_ports(), config.verify_timeout()),
+        method: config.verify_method(),
+        tokens: config.api_tokens(),
+        cooldown: config.cooldown,
+    })
+}
+
+/// Runs the HTTP daemon.
+#[cfg(feature = "serve")]
+fn serve(program: &str, args: &[String]) -> i32 {
+    let opts = serve_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "serve", &opts);
+        return exit::SUCCESS;
+    }
+    let audit_log = match apply_common_options(&matches) {
+        Ok(audit_log) => audit_log,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::ERROR;
+        }
+    };
+    let config = match load_config(&matches) {
+        Ok(config) => config,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let audit_log = match or_configured_audit_log(audit_log, &config) {
+        Ok(audit_log) => audit_log,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::ERROR;
+        }
+    };
+    let settings = match serve_settings(&matches) {
+        Ok(settings) => settings,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let drain_timeout = match parse_opt::<HumanDuration>(&matches, "drain-timeout") {
+        Ok(timeout) => timeout.map(|timeout| timeout.0).or(config.drain_timeout),
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    let bind = match matches.opt_str("bind") {
+        Some(ref port) if port.starts_with(':') => format!("0.0.0.0{}", port),
+        Some(bind) => bind,
+        None => "127.0.0.1:8080".to_string(),
+    };
+    let mut server = match Server::bind(bind.as_str()) {
+        Ok(server) => server.settings(settings),
+        Err(err) => {
+            print_error!("{}: {}", Message::CannotListen(&bind), err);
+            return exit::ERROR;
+        }
+    };
+    if let Some(audit_log) = audit_log {
+        server = server.audit_log(audit_log);
+    }
+    if let Some(history) = open_history() {
+        server = server.history(history);
+    }
+    if let Some(udp) = matches.opt_str("udp") {
+        let key = match config.relay_token {
+            Some(ref key) => key,
+            None => {
+                print_error!("{}", Message::UdpNeedsKey);
+                return exit::USAGE;
+            },
+        };
+        server = match server.signed_udp(udp.as_str(), key) {
+            Ok(server) => server,
+            Err(err) => {
+                print_error!("{}: {}", Message::CannotListen(&udp), err);
+                return exit::ERROR;
+            },
+        };
+    }
+    if config.api_tokens().is_empty() && !bind.starts_with("127.") && !bind.starts_with("[::1]") {
+        log::log(Level::Warn,
+                 "wol",
+                 format_args!("anyone who can reach {} can wake hosts, set [relay] token in \
+                               the configuration to require a token",
+                              bind))
+    }
+    // Admins can sync the hosts file with `wol hosts sync`, after which the daemon reloads.
+    server = server.hosts_file(config.hosts_file());
+    // The listeners, the audit log, the history and the key of --udp stay as they are.
+    let reload_matches = matches.clone();
+    server = server.reload_with(move || serve_settings(&reload_matches));
+    if let Some(timeout) = drain_timeout {
+        server = server.drain_timeout(timeout);
+    }
+    match interrupt::catch_interrupt().and_then(|_| interrupt::catch_terminate()) {
+        Ok(stop) => server = server.shutdown_on(stop),
+        Err(err) => {
+            log::log(Level::Debug, "wol", format_args!("cannot catch SIGTERM and ctrl-C: {}", err))
+        },
+    }
+    match server.run() {
+        Ok(()) => exit::SUCCESS,
+        Err(err) => {
+            print_error!("{}", err);
+            exit::ERROR
+        },
+    }
+}
+
+/// Returns the options of `wol hosts`.
+fn hosts_options() -> Opts {
+    let mut opts = Opts::new();
+    add_config_options(&mut opts);
+    opts.optopt("", "config", "read FILE instead of /etc/wol.toml and ~/.config/wol/config.toml",
+                "FILE");
+    add_config_dir_option(&mut opts);
+    opts.optopt("", "format", "output format of export (default: json)", "json|csv|ethers");
+    opts.optflag("", "migrate", "let check convert a hosts file of an older version");
+    opts.optopt("", "via", "let sync push to or pull from the wol serve daemon at URL", "URL");
+    opts.optflag("h", "help", "print this help menu");
+    opts
+}
+
+/// Exports, checks, refreshes or syncs the hosts of the hosts file, or lists them like `wol list`.
+fn hosts(program: &str, args: &[String]) -> i32 {
+    if args.first().map(String::as_str) == Some("list") {
+        return list(program, &args[1..]);
+    }
+    let opts = hosts_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "hosts", &opts);
+        return exit::SUCCESS;
+    }
+    match matches.free.first().map(String::as_str) {
+        Some("export") => {},
+        Some("check") => return check_hosts(&matches),
+        Some("refresh") => return refresh_hosts(&matches),
+        Some("sync") => return sync_hosts(program, &opts, &matches),
+        _ => {
+            print_usage(program, "hosts", &opts);
+            return exit::USAGE;
+        },
+    }
+    let options = load_config(&matches).and_then(|config| {
+        let format = parse_opt::<ExportFormat>(&matches, "format")?.unwrap_or(ExportFormat::Json);
+        Ok((load_hosts(&config)?, format))
+    });
+    let (hosts, format) = match options {
+        Ok(options) => options,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let stdout = io::stdout();
+    match hosts.export(format, stdout.lock()) {
+        Ok(()) => exit::SUCCESS,
+        Err(err) => write_failed(&err, exit::SUCCESS),
+    }
+}
+
+/// Prints the errors of the hosts file, or migrates it if it has none and `--migrate` is given.
+fn check_hosts(matches: &Matches) -> i32 {
+    let path = match load_config(matches) {
+        Ok(config) => config.hosts_file(),
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => {
+            print_error!("{}: {}", Message::CannotReadHosts(&path), err);
+            return exit::ERROR;
+        },
+    };
+    let errors = Hosts::check(&content);
+    if !errors.is_empty() {
+        for err in &errors {
+            println!("{}:{}", path.display(), err);
+        }
+        return exit::USAGE;
+    }
+    let count = content.parse::<Hosts>().map(|hosts| hosts.len()).unwrap_or_default();
+    match Hosts::migrate(&content) {
+        Ok(Some(migrated)) if matches.opt_present("migrate") => {
+            if let Err(err) = fs::write(&path, migrated) {
+                print_error!("{}: {}", Message::CannotWriteHosts(&path), err);
+                return exit::ERROR;
+            }
+            println!("{}",
+                     Message::HostsMigrated {
+                         path: &path,
+                         count,
+                         version: hosts::VERSION,
+                     });
+        },
+        Ok(Some(_)) => {
+            println!("{}",
+                     Message::HostsOutdated {
+                         path: &path,
+                         count,
+                         version: hosts::VERSION,
+                     })
+        },
+        _ => println!("{}", Message::HostsValid { path: &path, count }),
+    }
+    exit::SUCCESS
+}
+
+/// Replaces the hosts file of the daemon at `--via` with the local one, or the other way round.
+fn sync_hosts(program: &str, opts: &Opts, matches: &Matches) -> i32 {
+    let push = match matches.free.get(1).map(String::as_str) {
+        Some("push") => true,
+        Some("pull") => false,
+        _ => {
+            print_usage(program, "hosts", opts);
+            return exit::USAGE;
+        },
+    };
+    let relay = match matches.opt_str("via").map(|url| Relay::new(&url)) {
+        Some(Ok(relay)) => relay,
+        Some(Err(err)) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        },
+        None => {
+            print_illegal!("{}", Message::SyncNeedsVia);
+            return exit::USAGE;
+        },
+    };
+    let config = match load_config(matches) {
+        Ok(config) => config,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    let relay = match config.relay_token {
+        Some(ref token) => relay.with_token(token),
+        None => relay,
+    };
+    let path = config.hosts_file();
+    if !push {
+        let content = match relay.pull_hosts() {
+            Ok(content) => content,
+            Err(err) => {
+                print_error!("{}: {}", Message::CannotPull(relay.url()), err);
+                return exit::ERROR;
+            },
+        };
+        return match Hosts::replace(&path, &content) {
+            Ok(hosts) => {
+                println!("{}",
+                         Message::HostsPulled {
+                             path: &path,
+                             count: hosts,
+                             url: relay.url(),
+                         });
+                exit::SUCCESS
+            },
+            Err(err) => {
+                print_error!("{}: {}", Message::CannotReplaceHosts(&path), err);
+                exit::ERROR
+            },
+        };
+    }
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => {
+            print_error!("{}: {}", Message::CannotReadHosts(&path), err);
+            return exit::ERROR;
+        },
+    };
+    // Checked here as well, so that the errors point to the lines of the local file.
+    let errors = Hosts::check(&content);
+    if !errors.is_empty() {
+        for err in &errors {
+            println!("{}:{}", path.display(), err);
+        }
+        return exit::USAGE;
+    }
+    match relay.push_hosts(&content) {
+        Ok(hosts) => {
+            println!("{}",
+                     Message::HostsPushed {
+                         url: relay.url(),
+                         count: hosts,
+                         path: &path,
+                     });
+            exit::SUCCESS
+        },
+        Err(err) => {
+            print_error!("{}: {}", Message::CannotPush(relay.url()), err);
+            exit::ERROR
+        },
+    }
+}
+
+/// Records the addresses of the hosts of the hosts file in the neighbor table or, for those not
+/// in it, in the leases of the DHCP server as last seen, and prints them.
+fn refresh_hosts(matches: &Matches) -> i32 {
+    let loaded = load_config(matches).and_then(|config| Ok((load_hosts(&config)?, config)));
+    let (hosts, config) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    let seen = match open_seen() {
+        Some(seen) => seen,
+        None => {
+            print_error!("{}", Message::NoStateDir);
+            return exit::ERROR;
+        },
+    };
+    let table = neighbors::table().unwrap_or_else(|err| {
+        print_error!("{}: {}", Message::CannotReadNeighbors, err);
+        Vec::new()
+    });
+    let leases = match config.leases_file.clone().map(Leases::new).or_else(Leases::find) {
+        Some(leases) => match fs::read_to_string(leases.path()) {
+            Ok(content) => resolve::parse_leases(&content),
+            Err(err) => {
+                print_error!("{}: {}", Message::CannotReadLeases(leases.path()), err);
+                Vec::new()
+            },
+        },
+        None => Vec::new(),
+    };
+    let mut sightings = Vec::new();
+    for host in &hosts {
+        // The last lease is the most recent one.
+        let sighting = Sighting::find(host.mac, None, &table).or_else(|| {
+            leases.iter().rev().find(|lease| lease.mac == host.mac).map(Sighting::from_lease)
+        });
+        if let Some(sighting) = sighting {
+            println!("{} {} ({})", host.name, sighting.ip, sighting.source);
+            sightings.push(sighting);
+        }
+    }
+    if let Err(err) = seen.record(&sightings) {
+        print_error!("{}: {}", Message::CannotWriteSeen(seen.path()), err);
+        return exit::ERROR;
+    }
+    println!("{}",
+             Message::HostsSeen {
+                 path: seen.path(),
+                 seen: sightings.len(),
+                 total: hosts.len(),
+             });
+    exit::SUCCESS
+}
+
+/// Returns the options of `wol listen`.
+fn listen_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optopt("", "bind", "address to receive on (default: 0.0.0.0)", "ADDR");
+    opts.optopt("", "port", "UDP port to receive on (default: 9)", "PORT");
+    opts.optflagopt("", "raw", "also receive raw Ethernet frames from all interfaces or from \
+                                IFACE (Linux only, needs CAP_NET_RAW)", "IFACE");
+    opts.optopt("", "promiscuous", "also receive the magic packets to other hosts seen by IFACE \
+                                    in promiscuous mode, e.g. on a mirror port (Linux only, \
+                                    needs CAP_NET_RAW)", "IFACE");
+    opts.optopt("", "pcap", "write the received packets to FILE in the pcap format", "FILE");
+    opts.optflag("", "echo", "send UDP packets back to their sender for wol selftest --remote");
+    opts.optflag("", "tolerant", "also find magic packets within longer UDP datagrams, e.g. \
+                                  behind another header or followed by padding");
+    opts.optopt("", "dedup-window", "print identical packets from the same sender only once \
+                                     unless DURATION passed since the last (default: 1s)",
+                "DURATION");
+    opts.optopt("", "stats-interval", "print the counters per MAC address to stderr every \
+                                       DURATION and on exit",
+                "DURATION");
+    opts.optopt("", "format", "output format (default: plain)", "json|csv|plain");
+    opts.optopt("", "mac-format", "how to print MAC addresses (default: XX:XX:XX:XX:XX:XX)",
+                "xx-xx-xx-xx-xx-xx|xxxx.xxxx.xxxx|...");
+    add_common_options(&mut opts);
+    opts
+}
+
+/// Prints the magic packets received until ctrl-C is pressed.
+fn listen(program: &str, args: &[String]) -> i32 {
+    let opts = listen_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "listen", &opts);
+        return exit::SUCCESS;
+    }
+    if let Err(err) = apply_common_options(&matches) {
+        print_error!("{}", err);
+        return exit::ERROR;
+    }
+    let options = (|| {
+        let bind = parse_opt::<IpAddr>(&matches, "bind")?.unwrap_or_else(|| [0, 0, 0, 0].into());
+        let port = parse_opt::<Port>(&matches, "port")?.map_or(listen::DEFAULT_PORT, Port::get);
+        let format = parse_opt::<Format>(&matches, "format")?.unwrap_or(Format::Plain);
+        let mac_format = parse_opt::<MacFormat>(&matches, "mac-format")?.unwrap_or_default();
+        let window = parse_opt::<HumanDuration>(&matches, "dedup-window")?
+            .map_or(listen::DEFAULT_DEDUP_WINDOW, |window| window.0);
+        let interval = parse_opt::<Interval>(&matches, "stats-interval")?;
+        Ok::<_, String>(((bind, port), format, mac_format, window, interval.map(Interval::get)))
+    })();
+    let (bind, format, mac_format, window, stats_interval) = match options {
+        Ok(options) => options,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let mut listener = match Listener::bind(bind) {
+        Ok(listener) => {
+            listener.with_echo(matches.opt_present("echo"))
+                .with_tolerant(matches.opt_present("tolerant"))
+        },
+        Err(err) => {
+            print_error!("{}: {}", Message::CannotListen(&format!("{}:{}", bind.0, bind.1)), err);
+            return exit::ERROR;
+        }
+    };
+    if matches.opt_present("raw") && matches.opt_present("promiscuous") {
+        print_error!("{}", Message::PromiscuousIncludesRaw);
+        return exit::USAGE;
+    }
+    if matches.opt_present("raw") {
+        let interface = matches.opt_str("raw");
+        listener = match listener.with_raw(interface.as_deref()) {
+            Ok(listener) => listener,
+            Err(err) => {
+                print_error!("{}: {}", Message::CannotReceiveRaw, err);
+                return exit::ERROR;
+            }
+        };
+    }
+    if let Some(interface) = matches.opt_str("promiscuous") {
+        listener = match listener.with_promiscuous(&interface) {
+            Ok(listener) => listener,
+            Err(err) => {
+                print_error!("{}: {}", Message::CannotReceivePromiscuous(&interface), err);
+                return exit::ERROR;
+            }
+        };
+    }
+    let mut capture = match matches.opt_str("pcap") {
+        None => None,
+        Some(path) => match File::create(&path).and_then(|f| PcapWriter::new(BufWriter::new(f))) {
+            Ok(writer) => Some(writer),
+            Err(err) => {
+                print_error!("{}: {}", Message::CannotWrite(Path::new(&path)), err);
+                return exit::ERROR;
+            }
+        },
+    };
+    let cancel = match interrupt::catch_interrupt() {
+        Ok(cancel) => cancel,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::ERROR;
+        }
+    };
+    let tracker = Mutex::new(Tracker::new(window));
+    let stdout = io::stdout();
+    let mut printer = Printer::new(format, stdout.lock());
+    // Stops listening, as nobody reads what is received any more.
+    let mut write_error = None;
+    let on_packet = |received: listen::Received| {
+        // The capture keeps the duplicates as well.
+        if let Some(ref mut capture) = capture {
+            if let Err(err) = capture.write_frame(received.time, &received.frame) {
+                log::log(Level::Error, "wol", format_args!("cannot write capture: {}", err));
+            }
+        }
+        if !tracker.lock().unwrap().record(&received) {
+            return;
+        }
+        let record = Record::new()
+            .field("time", output::timestamp(received.time))
+            .field("transport", received.source.transport())
+            .field("source", received.source.to_string())
+            .field("mac", received.packet.mac().format(mac_format).to_string())
+            .field("password", received.packet.password().is_some());
+        if let Err(err) = printer.print(&record) {
+            write_error = Some(err);
+            cancel.store(true, Ordering::SeqCst);
+        }
+    };
+    let result = match stats_interval {
+        None => listener.run(cancel, on_packet),
+        Some(interval) => {
+            let done = AtomicBool::new(false);
+            let result = thread::scope(|scope| {
+                scope.spawn(|| print_stats_every(interval, &tracker, &done));
+                let result = listener.run(cancel, on_packet);
+                done.store(true, Ordering::SeqCst);
+                result
+            });
+            eprint!("{}", tracker.lock().unwrap());
+            result
+        },
+    };
+    if let Some(err) = write_error {
+        return write_failed(&err, exit::SUCCESS);
+    }
+    match result {
+        Ok(()) => exit::SUCCESS,
+        Err(err) => {
+            print_error!("{}", err);
+            exit::ERROR
+        },
+    }
+}
+
+/// Prints the counters of the tracker to stderr every `interval` until `done` is set.
+fn print_stats_every(interval: Duration, tracker: &Mutex<Tracker>, done: &AtomicBool) {
+    let mut next = Instant::now() + interval;
+    while !done.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(100));
+        if Instant::now() >= next {
+            eprint!("{}", tracker.lock().unwrap());
+            next += interval;
+        }
+    }
+}
+
+/// Returns the options of `wol analyze`.
+fn analyze_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optopt("", "format", "output format (default: plain)", "json|csv|plain");
+    opts.optopt("", "mac-format", "how to print MAC addresses (default: XX:XX:XX:XX:XX:XX)",
+                "xx-xx-xx-xx-xx-xx|xxxx.xxxx.xxxx|...");
+    add_common_options(&mut opts);
+    opts
+}
+
+/// Prints the magic packets found in a pcap capture.
+fn analyze(program: &str, args: &[String]) -> i32 {
+    let opts = analyze_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "analyze", &opts);
+        return exit::SUCCESS;
+    }
+    if matches.free.len() != 1 {
+        print_usage(program, "analyze", &opts);
+        return exit::USAGE;
+    }
+    if let Err(err) = apply_common_options(&matches) {
+        print_error!("{}", err);
+        return exit::ERROR;
+    }
+    let options = parse_opt::<Format>(&matches, "format").and_then(|format| {
+        let mac_format = parse_opt::<MacFormat>(&matches, "mac-format")?.unwrap_or_default();
+        Ok((format.unwrap_or(Format::Plain), mac_format))
+    });
+    let (format, mac_format) = match options {
+        Ok(options) => options,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let path = &matches.free[0];
+    let scanned = File::open(path)
+        .and_then(|f| PcapReader::new(io::BufReader::new(f)))
+        .and_then(analyze::scan);
+    let (sightings, frames) = match scanned {
+        Ok(scanned) => scanned,
+        Err(err) => {
+            print_error!("{}: {}", Message::CannotRead(Path::new(&path)), err);
+            return exit::ERROR;
+        }
+    };
+    let stdout = io::stdout();
+    let mut printer = Printer::new(format, stdout.lock());
+    for sighting in &sightings {
+        let record = Record::new()
+            .field("time", output::timestamp(sighting.time))
+            .field("transport", sighting.source.transport())
+            .field("source", sighting.source.to_string())
+            .field("destination", sighting.destination.map(|address| address.to_string()))
+            .field("mac", sighting.packet.mac().format(mac_format).to_string())
+            .field("password", sighting.packet.password().is_some());
+        if let Err(err) = printer.print(&record) {
+            return write_failed(&err, exit::SUCCESS);
+        }
+    }
+    log::log(Level::Info,
+             "wol",
+             format_args!("found {} magic packets in {} frames", sightings.len(), frames));
+    exit::SUCCESS
+}
+
+/// Returns the options of `wol replay`.
+fn replay_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optflag("4", "ipv4", "use IPv4 for packets sent over Ethernet");
+    opts.optflag("6", "ipv6", "use IPv6 for packets sent over Ethernet (default)");
+    opts.optflag("n",
+                 "dry-run",
+                 "print the packets and their destinations instead of sending them");
+    opts.optopt("", "to", "send to this address instead of the captured destination", "ADDR");
+    opts.optflag("", "timing", "wait between the packets as long as the capture did");
+    opts.optopt("", "format", "output format (default: plain)", "json|csv|plain");
+    opts.optopt("", "mac-format", "how to print MAC addresses (default: XX:XX:XX:XX:XX:XX)",
+                "xx-xx-xx-xx-xx-xx|xxxx.xxxx.xxxx|...");
+    add_config_options(&mut opts);
+    add_common_options(&mut opts);
+    opts
+}
+
+/// Sends the magic packets of a pcap capture again, e.g. to reproduce what a network saw or to
+/// test a relay end to end. Packets sent per UDP go to their captured destination unless `--to`
+/// or `--port` rewrite it, those sent over Ethernet to the configured one.
+fn replay(program: &str, args: &[String]) -> i32 {
+    let opts = replay_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "replay", &opts);
+        return exit::SUCCESS;
+    }
+    if matches.free.len() != 1 {
+        print_usage(program, "replay", &opts);
+        return exit::USAGE;
+    }
+    let audit_log = match apply_common_options(&matches) {
+        Ok(audit_log) => audit_log,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::ERROR;
+        }
+    };
+    let config = match load_config(&matches) {
+        Ok(config) => config,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let audit_log = match or_configured_audit_log(audit_log, &config) {
+        Ok(audit_log) => audit_log,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::ERROR;
+        }
+    };
+    let options = parse_opt::<IpAddr>(&matches, "to").and_then(|to| {
+        let format = parse_opt::<Format>(&matches, "format")?.unwrap_or(Format::Plain);
+        let mac_format = parse_opt::<MacFormat>(&matches, "mac-format")?.unwrap_or_default();
+        Ok((to, format, mac_format))
+    });
+    let (to, format, mac_format) = match options {
+        Ok(options) => options,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let path = &matches.free[0];
+    let scanned = File::open(path)
+        .and_then(|f| PcapReader::new(io::BufReader::new(f)))
+        .and_then(analyze::scan);
+    let (sightings, frames) = match scanned {
+        Ok(scanned) => scanned,
+        Err(err) => {
+            print_error!("{}: {}", Message::CannotRead(Path::new(&path)), err);
+            return exit::ERROR;
+        }
+    };
+    log::log(Level::Info,
+             "wol",
+             format_args!("replaying {} magic packets of {} frames", sightings.len(), frames));
+    let client = config.client(matches.opt_present("4"));
+    // Only a --port on the command line rewrites the captured port, not one of the configuration.
+    let ports = if matches.opt_present("port") { client.ports() } else { Vec::new() };
+    let stdout = io::stdout();
+    let mut printer = Printer::new(format, stdout.lock());
+    let mut code = exit::SUCCESS;
+    let mut previous = None;
+    for sighting in &sightings {
+        let mut resend = sighting.client(&client);
+        if let Some(to) = to {
+            resend = resend.with_broadcast(to);
+        }
+        resend = resend.with_ports(&ports);
+        if matches.opt_present("dry-run") {
+            print_dry_run(&sighting.packet, &resend);
+            continue;
+        }
+        if let (true, Some(previous)) = (matches.opt_present("timing"), previous) {
+            thread::sleep(sighting.time.duration_since(previous).unwrap_or_default());
+        }
+        previous = Some(sighting.time);
+        let sent = resend.send(&sighting.packet);
+        if let Some(ref audit_log) = audit_log {
+            let event = AuditEvent {
+                initiator: Initiator::current_user(),
+                mac: sighting.packet.mac().to_string(),
+                target: resend.destination().to_string(),
+                outcome: sent.as_ref().map(|_| ()).map_err(|err| err.to_string()),
+            };
+            if let Err(err) = audit_log.record(&event) {
+                print_error!("{}: {}", Message::CannotWriteAuditLog, err);
+            }
+        }
+        let record = Record::new()
+            .field("captured", output::timestamp(sighting.time))
+            .field("mac", sighting.packet.mac().format(mac_format).to_string())
+            .field("destination", resend.destination().to_string());
+        let record = match sent {
+            Ok(report) => record.field("bytes", report.bytes_sent),
+            Err(err) => {
+                let message = Message::CannotSendTo {
+                    mac: sighting.packet.mac(),
+                    to: resend.destination(),
+                };
+                print_error!("{}: {}", message, err);
+                code = exit::ERROR;
+                record.field("error", err.to_string())
+            },
+        };
+        if let Err(err) = printer.print(&record) {
+            return write_failed(&err, code);
+        }
+    }
+    code
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let program = args[0].clone();
-    let mut opts = Options::new();
+/// Returns the options of `wol selftest`.
+fn selftest_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optflag("4", "ipv4", "use IPv4");
+    opts.optflag("6", "ipv6", "use IPv6 (default)");
+    opts.optopt("", "expect", "send a magic packet for MAC and expect it to arrive", "MAC");
+    add_password_options(&mut opts);
+    opts.optflag("", "remote", "expect the packet back from wol listen --echo on another host");
+    opts.optopt("", "bind", "address to receive on without --remote (default: all addresses)",
+                "ADDR");
+    opts.optopt("", "timeout", "how long to wait for the packet (default: 5s)", "DURATION");
+    opts.optopt("", "format", "output format of the result (default: plain)", "json|csv|plain");
+    add_config_options(&mut opts);
+    add_common_options(&mut opts);
+    opts
+}
+
+/// Sends a magic packet and checks that it arrives intact, either at a listener on this machine
+/// or, with `--remote`, at one started with `wol listen --echo` elsewhere.
+fn selftest(program: &str, args: &[String]) -> i32 {
+    let opts = selftest_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "selftest", &opts);
+        return exit::SUCCESS;
+    }
+    if !matches.opt_present("expect") {
+        print_usage(program, "selftest", &opts);
+        return exit::USAGE;
+    }
+    if let Err(err) = apply_common_options(&matches) {
+        print_error!("{}", err);
+        return exit::ERROR;
+    }
+    let options = load_config(&matches).and_then(|config| {
+        let mac = parse_opt::<MacAddr>(&matches, "expect")?.unwrap();
+        let password = read_password(&matches)?;
+        let bind = parse_opt::<IpAddr>(&matches, "bind")?;
+        let timeout = parse_opt::<HumanDuration>(&matches, "timeout")?
+            .map_or(selftest::DEFAULT_TIMEOUT, |timeout| timeout.0);
+        let format = parse_opt::<Format>(&matches, "format")?.unwrap_or(Format::Plain);
+        let mut packet = MagicPacket::new(mac);
+        if let Some(password) = password {
+            packet = packet.with_password(password);
+        }
+        Ok((config.client(matches.opt_present("4")), packet, bind, timeout, format))
+    });
+    let (mut client, packet, bind, timeout, format) = match options {
+        Ok(options) => options,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    // Nothing can listen on port 0, the default for sending.
+    if client.destination().port() == 0 {
+        client = client.with_port(listen::DEFAULT_PORT);
+    }
+    let remote = matches.opt_present("remote");
+    let outcome = if remote {
+        selftest::remote(&client, &packet, timeout)
+    } else {
+        let bind = bind.unwrap_or_else(|| match client.destination() {
+            SocketAddr::V4(_) => [0, 0, 0, 0].into(),
+            SocketAddr::V6(_) => [0u16; 8].into(),
+        });
+        let address = SocketAddr::new(bind, client.destination().port());
+        match Listener::bind(address) {
+            Ok(listener) => selftest::local(&client, &packet, listener, timeout),
+            Err(err) => {
+                print_error!("{}: {}", Message::CannotListen(&address.to_string()), err);
+                return exit::ERROR;
+            }
+        }
+    };
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::ERROR;
+        }
+    };
+    // The record has the same fields for every outcome, as needed for CSV.
+    let (source, elapsed, received) = match outcome {
+        selftest::Outcome::Passed { source, elapsed } => (Some(source), Some(elapsed), None),
+        selftest::Outcome::Mismatch { source, received } => (Some(source), None, Some(received)),
+        selftest::Outcome::Missing => (None, None, None),
+    };
+    let record = Record::new()
+        .field("mac", packet.mac().to_string())
+        .field("target", client.destination().to_string())
+        .field("mode", if remote { "remote" } else { "local" })
+        .field("result", outcome.name())
+        .field("source", source.map(|source| source.to_string()))
+        .field("duration_ms", elapsed.map(output::millis))
+        .field("received", received.map(|received| received.to_string()));
+    let stdout = io::stdout();
+    let code = if outcome.passed() { exit::SUCCESS } else { exit::SELFTEST_FAILED };
+    match Printer::new(format, stdout.lock()).print(&record) {
+        Ok(()) => code,
+        Err(err) => write_failed(&err, code),
+    }
+}
+
+/// Returns the options of `wol doctor`.
+fn doctor_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optflag("4", "ipv4", "check sending per IPv4");
+    opts.optflag("6", "ipv6", "check sending per IPv6 (default)");
+    opts.optopt("",
+                "listen-port",
+                &format!("check that wol listen can bind this UDP port (default: {})",
+                         listen::DEFAULT_PORT),
+                "PORT");
+    add_config_options(&mut opts);
+    add_common_options(&mut opts);
+    opts
+}
+
+/// Checks the environment of wol and prints a report for bug reports, with the version and
+/// system first. Returns an error if a check failed.
+fn doctor(program: &str, args: &[String]) -> i32 {
+    let opts = doctor_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "doctor", &opts);
+        return exit::SUCCESS;
+    }
+    if let Err(err) = apply_common_options(&matches) {
+        print_error!("{}", err);
+        return exit::ERROR;
+    }
+    let listen_port = match parse_opt::<Port>(&matches, "listen-port") {
+        Ok(port) => port.map_or(listen::DEFAULT_PORT, Port::get),
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    println!("wol {} on {} {}", env!("CARGO_PKG_VERSION"), env::consts::OS, env::consts::ARCH);
+    let mut findings = Vec::new();
+    let config = match load_config(&matches) {
+        Ok(config) => {
+            let paths = load_paths(&matches);
+            let files: Vec<String> = match matches.opt_str("config") {
+                Some(file) => vec![file],
+                None => {
+                    paths.config_files()
+                        .iter()
+                        .filter(|file| file.exists())
+                        .map(|file| file.display().to_string())
+                        .collect()
+                },
+            };
+            let message = if files.is_empty() {
+                "no configuration file, the defaults apply".to_string()
+            } else {
+                format!("{} can be read", files.join(", "))
+            };
+            findings.push(Finding::new("config", Status::Ok, message));
+            config
+        },
+        Err(err) => {
+            findings.push(Finding::new("config", Status::Failed, err));
+            Config::default()
+        },
+    };
+    let path = config.hosts_file();
+    findings.push(match load_hosts(&config) {
+        Ok(_) if !path.exists() => {
+            Finding::new("hosts",
+                         Status::Ok,
+                         format!("{} does not exist, only MAC addresses can be woken",
+                                 path.display()))
+        },
+        Ok(hosts) => {
+            Finding::new("hosts",
+                         Status::Ok,
+                         format!("{} can be read, with {} hosts", path.display(), hosts.len()))
+        },
+        Err(err) => Finding::new("hosts", Status::Failed, err),
+    });
+    findings.extend(diagnose::environment(&config.client(matches.opt_present("4")), listen_port));
+    for finding in &findings {
+        println!("{}", finding);
+    }
+    if findings.iter().any(|finding| finding.status == Status::Failed) {
+        exit::ERROR
+    } else {
+        exit::SUCCESS
+    }
+}
+
+/// Returns the options of `wol tui`.
+#[cfg(feature = "tui")]
+fn tui_options() -> Opts {
+    let mut opts = Opts::new();
     opts.optflag("4", "ipv4", "use IPv4");
     opts.optflag("6", "ipv6", "use IPv6 (default)");
+    add_config_options(&mut opts);
+    add_common_options(&mut opts);
+    opts
+}
+
+/// Shows the dashboard of the known hosts.
+#[cfg(feature = "tui")]
+fn tui(program: &str, args: &[String]) -> i32 {
+    let opts = tui_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "tui", &opts);
+        return exit::SUCCESS;
+    }
+    if let Err(err) = apply_common_options(&matches) {
+        print_error!("{}", err);
+        return exit::ERROR;
+    }
+    let loaded = load_config(&matches).and_then(|config| Ok((load_hosts(&config)?, config)));
+    let (hosts, config) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let client = config.client(matches.opt_present("4"));
+    // Hosts without IP address are probed at the one they were last seen at.
+    let mut hosts: Vec<Host> = hosts.iter().cloned().collect();
+    fill_last_seen(open_seen().as_ref(), &mut hosts);
+    match wol::tui::run(hosts, &client, &config.verify_ports()) {
+        Ok(()) => exit::SUCCESS,
+        Err(err) => {
+            print_error!("{}", err);
+            exit::ERROR
+        },
+    }
+}
+
+/// Returns the options of `wol manpage`.
+fn manpage_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optopt("", "format", "output format (default: roff)", "roff|markdown");
     opts.optflag("h", "help", "print this help menu");
-    let matches = match opts.parse(&args[1..]) {
+    opts
+}
+
+/// Prints the man page of wol, generated from the options of its modes.
+fn manpage(program: &str, args: &[String]) -> i32 {
+    let opts = manpage_options();
+    let matches = match opts.parse(args) {
         Ok(m) => m,
         Err(f) => {
-            println!("Illegal argument: {}", f.to_string());
-            return;
+            print_illegal!("{}", f);
+            return exit::USAGE;
         }
     };
     if matches.opt_present("h") {
-        print_usage(&program, opts);
-        return;
+        print_usage(program, "manpage", &opts);
+        return exit::SUCCESS;
     }
-    let mac_str = if !matches.free.is_empty() {
-        matches.free[0].clone()
+    let markdown = match matches.opt_str("format").as_deref() {
+        None | Some("roff") => false,
+        Some("markdown") => true,
+        Some(format) => {
+            print_illegal!("{}", Message::UnsupportedFormat(format));
+            return exit::USAGE;
+        },
+    };
+    let mut page = Page::new("wol",
+                             env!("CARGO_PKG_VERSION"),
+                             "wake up remote computers in the local network");
+    for mode in MODES {
+        page = page.with_command(Command::new(mode.name, mode.synopsis, mode.description)
+            .with_options((mode.options)().docs));
+    }
+    let codes = exit::CODES.iter().fold(Section::new("Exit status",
+                                                     "If every host of a batch failed the same \
+                                                      way, the exit code is that of the \
+                                                      failure."),
+                                        |section, &(code, meaning)| {
+                                            section.with_item(&code.to_string(), meaning)
+                                        });
+    let environment = Section::new("Environment", "")
+        .with_item("RUST_LOG", "the log level without -v or -q, e.g. debug or wol=trace")
+        .with_item("WOL_BROADCAST, WOL_PORT, WOL_INTERFACE, WOL_HOSTS_FILE",
+                   "defaults with a lower precedence than the configuration files")
+        .with_item("WOL_PASSWORD",
+                   "the SecureOn password without --password or --password-file")
+        .with_item("NO_COLOR", "print results on a terminal without colors if set");
+    let files = Section::new("Files",
+                             "The locations on Linux; wol config paths prints those of the \
+                              platform, e.g. in ~/Library/Application Support/wol on macOS or \
+                              %APPDATA%\\wol on Windows.")
+        .with_item("/etc/wol.toml, ~/.config/wol/config.toml",
+                   "the defaults of the options, the latter taking precedence")
+        .with_item("~/.config/wol/hosts.toml", "the known hosts")
+        .with_item("~/.local/state/wol/audit.log", "the audit log with audit_log = true")
+        .with_item("~/.local/state/wol/history.jsonl", "the recent wakes shown by wol last");
+    let page = page.with_section(codes).with_section(environment).with_section(files);
+    if markdown {
+        print!("{}", page.markdown());
     } else {
-        println!("No MAC address given");
-        print_usage(&program, opts);
-        return;
+        print!("{}", page.roff());
+    }
+    exit::SUCCESS
+}
+
+/// Returns the options of `wol wake`.
+fn wake_options() -> Opts {
+    let mut opts = Opts::new();
+    opts.optflag("4", "ipv4", "use IPv4");
+    opts.optflag("6", "ipv6", "use IPv6 (default)");
+    opts.optflag("n", "dry-run", "print the packet and its destination instead of sending it");
+    add_password_options(&mut opts);
+    opts.optopt("", "format", "output format of the result (default: plain)", "json|csv|plain");
+    opts.optopt("", "verify", "wait until the host accepts TCP connections at IP", "IP");
+    opts.optopt("", "range", "wake all MAC addresses from FIRST to LAST", "FIRST-LAST");
+    opts.optmulti("", "ip", "wake the host which had this IP address, by its MAC address in the \
+                             neighbor table of the system (Linux, Windows, macOS and the BSDs); \
+                             link-local IPv6 addresses need their interface, e.g. fe80::1%eth0",
+                  "IP");
+    opts.optflag("", "solicit", "ask for the MAC address of --ip hosts by ARP or NDP if the \
+                                 neighbor table does not have it");
+    opts.optflag("", "all", "wake all hosts of the hosts file");
+    opts.optmulti("", "tag", "wake the hosts of the hosts file with this tag, e.g. rack=3, or \
+                              with all tags if given several times",
+                  "TAG");
+    opts.optopt("", "job", "wake the targets of a YAML, JSON, CSV or ethers job file, with \
+                            their port, broadcast, repeat, verify and password overrides",
+                "FILE");
+    opts.optflag("", "pick", "choose the hosts to wake from the hosts file interactively, the \
+                              default without MAC addresses on a terminal");
+    opts.optflag("y", "yes", "do not ask for confirmation before waking all or more than 10 hosts");
+    opts.optflag("w",
+                 "wait",
+                 "wait until the hosts with a known IP address, or with --verify-method dhcp \
+                  all hosts, come up");
+    opts.optflag("", "changed-when-down",
+                 "only wake hosts which are not up yet and report whether they were changed");
+    opts.optflag("", "ignore-cooldown", "wake hosts even if they were woken within their cooldown");
+    opts.optflag("", "diagnose", "check the interface, route, broadcast address, neighbor \
+                                  table and firewall for hosts which could not be woken or did \
+                                  not come up");
+    opts.optopt("", "strategy", "single sends once as configured, auto sends to hosts with an \
+                                 IP address by limited broadcast, directed broadcast, unicast and \
+                                 raw frame in turn until they come up (default: single)",
+                "single|auto");
+    opts.optopt("", "concurrency", "wake at most N hosts at the same time (default: 16)", "N");
+    opts.optopt("", "attempts", "wake verified hosts which did not come up again, up to N \
+                                 times in all (default: 1)", "N");
+    opts.optopt("", "stagger", "wait DURATION between the packets of the hosts, e.g. 250ms",
+                "DURATION");
+    opts.optopt("", "jitter", "delay every packet by a random time up to DURATION", "DURATION");
+    opts.optopt("", "mac-format", "how to print MAC addresses (default: XX:XX:XX:XX:XX:XX)",
+                "xx-xx-xx-xx-xx-xx|xxxx.xxxx.xxxx|...");
+    opts.optopt("", "via", "ask the wol serve daemon at URL to wake the hosts, authenticated \
+                            with the [relay] token of the configuration", "URL");
+    opts.optopt("", "raw", "send raw Ethernet frames of EtherType 0x0842 through IFACE instead \
+                            of UDP datagrams (raw feature, Linux)", "IFACE");
+    opts.optopt("", "vlan", "tag the --raw frames for this 802.1Q VLAN, to wake hosts on other \
+                             VLANs through a trunk port", "ID");
+    opts.optopt("", "pcp", "the priority of the --vlan tag (default: 0)", "0-7");
+    opts.optopt("", "source-mac", "send the --raw frames from this MAC address instead of that \
+                                   of IFACE", "MAC");
+    opts.optopt("", "burst", "send the repetitions of every packet COUNT times, GAP apart \
+                              (default: 1, with --internet 3x2s)", "COUNTxGAP");
+    add_config_options(&mut opts);
+    add_common_options(&mut opts);
+    opts
+}
+
+/// Wakes the given hosts.
+fn wake(program: &str, args: &[String]) -> i32 {
+    let opts = wake_options();
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            print_illegal!("{}", f);
+            return exit::USAGE;
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(program, "wake", &opts);
+        return exit::SUCCESS;
+    }
+    let audit_log = match apply_common_options(&matches) {
+        Ok(audit_log) => audit_log,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::ERROR;
+        }
+    };
+    let config = match load_config(&matches) {
+        Ok(config) => config,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let audit_log = match or_configured_audit_log(audit_log, &config) {
+        Ok(audit_log) => audit_log,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::ERROR;
+        }
+    };
+    let history = open_history();
+    let verify_ip = match parse_opt::<IpAddr>(&matches, "verify") {
+        Ok(ip) => ip,
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let format = match matches.opt_str("format") {
+        None => Format::Plain,
+        Some(s) => match s.parse() {
+            Ok(format) => format,
+            Err(err) => {
+                print_illegal!("{}", err);
+                return exit::USAGE;
+            }
+        },
+    };
+    let mac_format = match parse_opt::<MacFormat>(&matches, "mac-format") {
+        Ok(mac_format) => mac_format.unwrap_or_default(),
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        }
+    };
+    let targets = [matches.opt_present("range"),
+                   matches.opt_present("ip"),
+                   matches.opt_present("all"),
+                   matches.opt_present("tag"),
+                   matches.opt_present("job"),
+                   matches.opt_present("pick"),
+                   !matches.free.is_empty()];
+    if targets.iter().filter(|&&t| t).count() > 1 {
+        println!("{}", Message::ExclusiveTargets);
+        print_usage(program, "wake", &opts);
+        return exit::USAGE;
+    }
+    let mut hosts: Vec<Host> = if let Some(range) = matches.opt_str("range") {
+        match range.parse::<MacRange>() {
+            Ok(range) => range.map(host_for_mac).collect(),
+            Err(err) => {
+                let message = Message::InvalidOption {
+                    name: "range",
+                    value: &range,
+                };
+                print_illegal!("{}: {}", message, err);
+                return exit::USAGE;
+            },
+        }
+    } else if matches.opt_present("ip") {
+        let addrs: Result<Vec<NeighborAddr>, String> = matches.opt_strs("ip")
+            .iter()
+            .map(|ip| {
+                ip.parse().map_err(|err| {
+                    format!("{}: {}", Message::InvalidOption { name: "ip", value: ip }, err)
+                })
+            })
+            .collect();
+        let solicit = matches.opt_present("solicit");
+        let hosts = addrs.and_then(|addrs| {
+            addrs.iter().map(|addr| resolve_ip(addr, solicit, &config)).collect::<Result<_, _>>()
+        });
+        match hosts {
+            Ok(hosts) => hosts,
+            Err(err) => {
+                print_error!("{}", err);
+                return exit::USAGE;
+            },
+        }
+    } else if matches.opt_present("all") {
+        match load_hosts(&config) {
+            Ok(hosts) => hosts.iter().cloned().collect(),
+            Err(err) => {
+                print_error!("{}", err);
+                return exit::ERROR;
+            },
+        }
+    } else if matches.opt_present("tag") {
+        match load_tagged(&config, &matches.opt_strs("tag")) {
+            Ok(hosts) => hosts,
+            Err(err) => {
+                print_error!("{}", err);
+                return exit::USAGE;
+            },
+        }
+    } else if let Some(path) = matches.opt_str("job") {
+        let job = match Job::load(&path) {
+            Ok(job) => job,
+            Err(err) => {
+                print_error!("{}: {}", Message::CannotReadJob(Path::new(&path)), err);
+                return exit::USAGE;
+            },
+        };
+        let resolved: Result<Vec<Vec<Host>>, String> = job.entries
+            .iter()
+            .map(|entry| {
+                let hosts = resolve_targets(&entry.target, &config)?;
+                Ok(hosts.into_iter().map(|host| entry.apply(host)).collect())
+            })
+            .collect();
+        match resolved {
+            Ok(hosts) => hosts.concat(),
+            Err(err) => {
+                print_error!("{}: {}", path, err);
+                return exit::USAGE;
+            },
+        }
+    } else if !matches.free.is_empty() {
+        let resolved: Result<Vec<Vec<Host>>, _> = matches.free
+            .iter()
+            .map(|target| resolve_targets(target, &config).map_err(|err| (target, err)))
+            .collect();
+        match resolved {
+            Ok(hosts) => hosts.concat(),
+            Err((target, err)) if format == Format::Json => {
+                let record = Record::new()
+                    .field("target", target.as_str())
+                    .field("error", err)
+                    .field("code", ErrorCode::ParseMac.as_str());
+                println!("{}", record.to_json());
+                return exit::USAGE;
+            },
+            Err((_, err)) => {
+                print_error!("{}", err);
+                print_usage(program, "wake", &opts);
+                return exit::USAGE;
+            },
+        }
+    } else {
+        let known = match load_hosts(&config) {
+            Ok(hosts) => hosts,
+            Err(err) => {
+                print_error!("{}", err);
+                return exit::ERROR;
+            },
+        };
+        if !matches.opt_present("pick") && (known.is_empty() || !io::stdin().is_terminal()) {
+            println!("{}", Message::NoMacGiven);
+            print_usage(program, "wake", &opts);
+            return exit::USAGE;
+        }
+        let known: Vec<Host> = known.iter().cloned().collect();
+        // The prompt goes to stderr, so that stdout only holds the results.
+        match pick::pick(&known, io::stdin().lock(), &mut io::stderr()) {
+            Ok(ref picked) if picked.is_empty() => {
+                println!("{}", Message::NoHostSelected);
+                return exit::USAGE;
+            },
+            Ok(picked) => picked,
+            Err(err) => {
+                print_error!("{}", err);
+                return exit::ERROR;
+            },
+        }
+    };
+    if let Some(ip) = verify_ip {
+        if hosts.len() != 1 {
+            println!("{}", Message::VerifyNeedsSingleHost);
+            return exit::USAGE;
+        }
+        hosts[0].ip = Some(ip);
+    }
+    let mut plan = if hosts.iter().any(|host| !host.requires.is_empty()) {
+        match load_hosts(&config).and_then(|file| file.plan(&hosts)) {
+            Ok(plan) => plan,
+            Err(err) => {
+                print_error!("{}", err);
+                return exit::USAGE;
+            },
+        }
+    } else {
+        vec![hosts]
+    };
+    // The send options given on the command line override those of the hosts.
+    for host in plan.iter_mut().flatten() {
+        if matches.opt_present("port") || matches.opt_present("internet") {
+            host.port = None;
+        }
+        if matches.opt_present("broadcast") || matches.opt_present("cidr") ||
+           matches.opt_present("internet") {
+            host.broadcast = None;
+        }
+        if matches.opt_present("repeat") {
+            host.repeat = None;
+        }
+        if matches.opt_present("password") || matches.opt_present("password-file") {
+            host.password = None;
+        }
+    }
+    // Hosts without IP address in the hosts file are sent to and verified at their last one.
+    let seen = open_seen();
+    fill_last_seen(seen.as_ref(), plan.iter_mut().flatten());
+    if config.broadcast.is_none() {
+        address_hosts(&mut plan, config.addressing());
+    }
+    let hosts = plan.concat();
+    if let Err(err) = check_macs(&hosts, config.mac_check()) {
+        print_error!("{}", err);
+        return exit::USAGE;
+    }
+    let password = match read_password(&matches) {
+        Ok(password) => password,
+        Err(err) => {
+            print_error!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    let relay = match matches.opt_str("via").map(|url| Relay::new(&url)).transpose() {
+        Ok(relay) => relay,
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    if relay.is_some() && (password.is_some() || matches.opt_present("changed-when-down")) {
+        println!("{}", Message::ViaExcludesPassword);
+        return exit::USAGE;
+    }
+    let strategy = match parse_opt(&matches, "strategy") {
+        Ok(strategy) => strategy.unwrap_or(Strategy::Single),
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    if relay.is_some() && strategy == Strategy::Auto {
+        println!("{}", Message::CannotCombine("--via", &["--strategy auto"]));
+        return exit::USAGE;
+    }
+    if matches.opt_present("internet") && (relay.is_some() || strategy == Strategy::Auto) {
+        println!("{}", Message::CannotCombine("--internet", &["--via", "--strategy auto"]));
+        return exit::USAGE;
+    }
+    let raw = match raw_path(&matches) {
+        Ok(raw) => raw,
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    let verifies = verify_ip.is_some() || matches.opt_present("wait") ||
+                   matches.opt_present("changed-when-down");
+    if raw.is_some() &&
+       (relay.is_some() || matches.opt_present("internet") || strategy == Strategy::Auto ||
+        verifies) {
+        println!("{}", Message::RawExcludes);
+        return exit::USAGE;
+    }
+    let concurrency = match parse_opt(&matches, "concurrency") {
+        Ok(concurrency) => concurrency.unwrap_or(batch::DEFAULT_CONCURRENCY),
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    let attempts = match parse_opt(&matches, "attempts") {
+        Ok(attempts) => attempts.unwrap_or(1),
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    let pacing = parse_opt::<HumanDuration>(&matches, "stagger")
+        .and_then(|stagger| Ok((stagger, parse_opt::<HumanDuration>(&matches, "jitter")?)));
+    let (stagger, jitter) = match pacing {
+        Ok((stagger, jitter)) => {
+            let zero = Duration::from_secs(0);
+            (stagger.map_or(zero, |d| d.0), jitter.map_or(zero, |d| d.0))
+        },
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    let bursts = match parse_opt::<Bursts>(&matches, "burst") {
+        Ok(Some(bursts)) => bursts,
+        Ok(None) if matches.opt_present("internet") => Bursts::INTERNET,
+        Ok(None) => config.bursts.unwrap_or_default(),
+        Err(err) => {
+            print_illegal!("{}", err);
+            return exit::USAGE;
+        },
+    };
+    let client = config.client(matches.opt_present("4")).with_bursts(bursts);
+    if relay.is_none() && raw.is_none() {
+        check_interface(&client, &hosts);
+    }
+    if matches.opt_present("dry-run") {
+        if let Some(ref relay) = relay {
+            for host in &hosts {
+                println!("{}",
+                         Message::WouldAsk {
+                             url: relay.url(),
+                             mac: host.mac,
+                         });
+            }
+            return exit::SUCCESS;
+        }
+        if let Some((ref raw, source)) = raw {
+            let path = raw.to_string();
+            for host in &hosts {
+                println!("{}",
+                         Message::WouldSend {
+                             mac: host.mac,
+                             path: &path,
+                             source,
+                         });
+            }
+            return exit::SUCCESS;
+        }
+        for host in &hosts {
+            if let Some(ref url) = host.relay {
+                println!("{}", Message::WouldAsk { url, mac: host.mac });
+                continue;
+            }
+            let packet = host.packet();
+            let packet = match (host.password, password) {
+                (None, Some(password)) => packet.with_password(password),
+                _ => packet,
+            };
+            print_dry_run(&packet, &host.client(&client));
+        }
+        return exit::SUCCESS;
+    }
+    // Picked hosts were confirmed while picking them.
+    let picked = matches.free.is_empty() && !matches.opt_present("range") &&
+                 !matches.opt_present("ip") && !matches.opt_present("all") &&
+                 !matches.opt_present("tag") && !matches.opt_present("job");
+    // The hosts with a tag tend to grow unnoticed like all hosts.
+    let all = matches.opt_present("all") || matches.opt_present("tag");
+    if !picked && !matches.opt_present("yes") && batch::needs_confirmation(hosts.len(), all) {
+        if !io::stdin().is_terminal() {
+            print_error!("{}", Message::NeedsConfirmation(hosts.len()));
+            return exit::USAGE;
+        }
+        let question = Message::ConfirmWake(hosts.len()).to_string();
+        match pick::confirm(&question, io::stdin().lock(), &mut io::stderr()) {
+            Ok(true) => {},
+            Ok(false) => {
+                println!("{}", Message::NoHostWoken);
+                return exit::USAGE;
+            },
+            Err(err) => {
+                print_error!("{}", err);
+                return exit::ERROR;
+            },
+        }
+    }
+
+    // Retry loops of automations must not wake a host over and over.
+    let cooling = if matches.opt_present("ignore-cooldown") {
+        BTreeMap::new()
+    } else {
+        cooling_down(history.as_ref(), &hosts, &config)
+    };
+    for (name, &(age, cooldown)) in &cooling {
+        log::log(Level::Info,
+                 "wol",
+                 format_args!("{} was woken {}s ago, within its cooldown of {}s, not waking it",
+                              name,
+                              age.as_secs(),
+                              cooldown.as_secs()));
+    }
+
+    if let Some((raw, source)) = raw {
+        let hosts: Vec<Host> = hosts.into_iter()
+            .filter(|host| !cooling.contains_key(&host.name))
+            .map(|host| Host { password: host.password.or(password), ..host })
+            .collect();
+        return wake_raw(&raw,
+                        source,
+                        &hosts,
+                        format,
+                        mac_format,
+                        audit_log.as_ref(),
+                        history.as_ref());
+    }
+    if let Some(mut relay) = relay {
+        let hosts: Vec<Host> =
+            hosts.into_iter().filter(|host| !cooling.contains_key(&host.name)).collect();
+        if let Some(ref token) = config.relay_token {
+            relay = relay.with_token(token);
+        }
+        let verify = verify_ip.is_some() || matches.opt_present("wait");
+        if verify && !relay.can_verify() {
+            print_error!("{}", Message::CannotVerifyVia(relay.url()));
+            return exit::USAGE;
+        }
+        if verify {
+            // The relay answers once the host is up, which takes up to its verification timeout.
+            relay = relay.with_timeout(config.verify_timeout() + relay::DEFAULT_TIMEOUT);
+        }
+        return wake_via(&relay,
+                        &hosts,
+                        verify,
+                        format,
+                        mac_format,
+                        audit_log.as_ref(),
+                        history.as_ref());
+    }
+    // Only one socket can be bound to a fixed source port at a time.
+    let concurrency = if client.source_port().is_some() { 1 } else { concurrency };
+    let mut batch = Batch::new(client.clone())
+        .with_concurrency(concurrency)
+        .with_attempts(attempts)
+        .with_pacing(stagger, jitter)
+        .with_skip(&cooling.keys().cloned().collect::<Vec<_>>())
+        .with_strategy(strategy);
+    if let Some(password) = password {
+        batch = batch.with_password(password);
+    }
+    if let Some(ref token) = config.relay_token {
+        batch = batch.with_relay_token(token);
+    }
+    let changed_when_down = matches.opt_present("changed-when-down");
+    let method = config.verify_method();
+    batch = batch.with_method(method);
+    // The automatic strategy verifies to know whether to try the next path.
+    if verify_ip.is_some() || matches.opt_present("wait") || changed_when_down ||
+       strategy == Strategy::Auto {
+        // Fail before sending rather than report every host as down, e.g. without privileges.
+        // DHCP snooping probes MAC addresses, also of hosts without IP address.
+        let ip = match method {
+            verify::Method::Dhcp => Some(IpAddr::from([0, 0, 0, 0])),
+            _ => plan.iter().flatten().find_map(|host| host.ip),
+        };
+        if let Some(ip) = ip {
+            if let Err(err) = method.check(ip) {
+                print_error!("{}: {}", Message::CannotVerifyWith(&method.to_string()), err);
+                return exit::ERROR;
+            }
+        }
+        batch = batch.with_verify(&config.verify_ports(), config.verify_timeout())
+            .with_skip_up(changed_when_down);
+    } else {
+        batch = batch.with_probe(&config.verify_ports(), config.verify_timeout());
+    }
+    match interrupt::catch_interrupt() {
+        Ok(interrupted) => batch = batch.with_cancel(interrupted),
+        Err(err) => log::log(Level::Debug, "wol", format_args!("cannot catch ctrl-C: {}", err)),
+    }
+    let mut results = if hosts.len() > 1 && !matches.opt_present("q") {
+        let stderr = io::stderr();
+        let mut progress = output::Progress::new(hosts.len(), stderr.lock());
+        if stderr.is_terminal() {
+            progress = progress.with_terminal();
+        }
+        let results = batch.run_plan_with(&plan, |name, result| {
+            // Progress is only shown, so failing to show it must not fail the wake.
+            let _ = progress.update(name, result.status());
+        });
+        let _ = progress.finish();
+        results
+    } else {
+        batch.run_plan(&plan)
+    };
+    let stdout = io::stdout();
+    let terminal = stdout.is_terminal();
+    let mut printer = Printer::new(format, stdout.lock());
+    if terminal {
+        printer = printer.with_terminal(output::color_allowed());
+    }
+    // Printed at the end, so that the columns of all hosts can be aligned.
+    let mut records = Vec::new();
+    let mut codes = Vec::new();
+    let mut skipped = Vec::new();
+    let mut undiagnosed = Vec::new();
+    let (mut woken, mut up) = (0, 0);
+    // The hosts seen up, with the address a probe reached them at, to refresh their last one.
+    let mut seen_up = Vec::new();
+    let probed = |host: &Host| {
+        host.ip.filter(|_| host.probe.is_none() && method != verify::Method::Dhcp)
+    };
+    // Which transport woke a host is only shown if the hosts were woken by different ones.
+    let first = results.values().next().map(|result| result.transport.clone());
+    let mixed = results.values().any(|result| Some(&result.transport) != first.as_ref());
+    for host in &hosts {
+        let result = match results.remove(&host.name) {
+            Some(result) => result,
+            None => {
+                if !batch.cancelled() {
+                    print_error!("{}", Message::RequiredNotUp(&host.name));
+                }
+                skipped.push(host.name.as_str());
+                continue;
+            },
+        };
+        let mac = host.mac;
+        let target = match (&result.transport, &result.sent) {
+            (&Transport::Relay(_), &Some(Ok(ref report))) => report.destination,
+            _ => host.client(&client).destination(),
+        };
+        let mut record = match result.sent {
+            Some(ref sent) => output::send_result(mac, target, sent, result.elapsed),
+            None => output::up_result(mac, target, result.elapsed),
+        };
+        record.set("mac", mac.format(mac_format).to_string());
+        output::set_verified(&mut record, result.verified);
+        record.set("boot_ms", result.boot_time.map(output::millis));
+        if let Some(boot_time) = result.boot_time {
+            log::log(Level::Info,
+                     "wol",
+                     format_args!("{} came up {:.1}s after the magic packet",
+                                  host.name,
+                                  boot_time.as_secs_f64()));
+        }
+        if let Some(ref path) = result.path {
+            log::log(Level::Info, "wol", format_args!("{} came up after the {}", host.name, path));
+            record.set("path", path.to_string());
+        }
+        if attempts > 1 {
+            record.set("attempts", u64::from(result.attempts));
+        }
+        if mixed {
+            record.set("transport", result.transport.to_string());
+        }
+        if changed_when_down {
+            record.set("changed", result.sent.is_some());
+        }
+        let sent = match result.sent {
+            Some(sent) => sent,
+            None if cooling.contains_key(&host.name) => {
+                record.set("cooldown", true);
+                records.push(record);
+                codes.push(exit::SUCCESS);
+                continue;
+            },
+            None => {
+                log::log(Level::Info, "wol", format_args!("{} is already up", host.name));
+                records.push(record);
+                codes.push(exit::SUCCESS);
+                seen_up.push((host, probed(host)));
+                up += 1;
+                continue;
+            },
+        };
+        if let Some(ref audit_log) = audit_log {
+            let event = AuditEvent {
+                initiator: Initiator::current_user(),
+                mac: mac.to_string(),
+                target: target.to_string(),
+                outcome: sent.as_ref().map(|_| ()).map_err(|err| err.to_string()),
+            };
+            if let Err(err) = audit_log.record(&event) {
+                print_error!("{}: {}", Message::CannotWriteAuditLog, err);
+            }
+        }
+        remember(history.as_ref(),
+                 Wake::new(&host.name,
+                           mac,
+                           Initiator::current_user(),
+                           sent.as_ref().map(|_| ()).map_err(|err| err.to_string()),
+                           result.verified)
+                     .with_boot_time(result.boot_time));
+        if sent.is_ok() {
+            woken += 1;
+        }
+        if result.verified == Some(true) {
+            seen_up.push((host, probed(host)));
+            up += 1;
+        }
+        codes.push(match (&sent, result.verified) {
+            (Err(_), _) => exit::SEND_FAILED,
+            (Ok(_), Some(false)) => exit::NOT_UP,
+            (Ok(_), _) => exit::SUCCESS,
+        });
+        if sent.is_err() || result.verified == Some(false) {
+            undiagnosed.push(host);
+        }
+        match sent {
+            // On a terminal, the record shows the error with the results of the other hosts.
+            Err(_) if terminal && hosts.len() > 1 => records.push(record),
+            Err(err) => {
+                println!("{} {}", Message::SendError, err);
+                if let Some(hint) = WolError::from(err).hint() {
+                    log::log(Level::Warn, "wol", format_args!("{}", hint));
+                }
+                if hosts.len() == 1 {
+                    print_usage(program, "wake", &opts);
+                    return exit::SEND_FAILED;
+                }
+            },
+            Ok(report) => {
+                log::log_fields(Level::Info,
+                                "wol",
+                                format_args!("magic packet sent"),
+                                &[("mac", &mac),
+                                  ("target", &report.destination),
+                                  ("local", &report.local_addr),
+                                  ("result", &"ok")]);
+                records.push(record);
+            },
+        }
+    }
+    if let Err(err) = printer.print_all(&records) {
+        return write_failed(&err, exit::for_batch(&codes, skipped.len()));
+    }
+    refresh_seen(seen.as_ref(), &seen_up);
+    if matches.opt_present("diagnose") {
+        print_diagnoses(&undiagnosed, &client, &config, format);
+    }
+    if batch.cancelled() {
+        println!("{}",
+                 Message::Interrupted {
+                     woken,
+                     total: hosts.len(),
+                     up,
+                     not_woken: &skipped,
+                 });
+        return exit::INTERRUPTED;
+    }
+    exit::for_batch(&codes, skipped.len())
+}
+
+/// Prints the checks of `diagnose::run` and, with a configured switch, `diagnose::switch_port`
+/// for the hosts which were not woken or did not come up, to stderr unless the format is plain,
+/// so that the results can still be parsed.
+fn print_diagnoses(hosts: &[&Host], client: &WolClient, config: &Config, format: Format) {
+    for host in hosts {
+        let mut report = format!("Diagnosis of {}:\n", host.name);
+        let mut findings = diagnose::run(&host.client(client), host);
+        if let Some(ref switch) = config.switch {
+            findings.push(diagnose::switch_port(switch, config.switch_community(), host.mac));
+        }
+        for finding in findings {
+            report.push_str(&format!("  {}\n", finding));
+        }
+        if format == Format::Plain {
+            print!("{}", report);
+        } else {
+            eprint!("{}", report);
+        }
+    }
+}
+
+/// Wakes the hosts one after the other through a relay, which verifies those with a known IP
+/// address if `verify` is set. Returns the exit code.
+fn wake_via(relay: &Relay,
+            hosts: &[Host],
+            verify: bool,
+            format: Format,
+            mac_format: MacFormat,
+            audit_log: Option<&AuditLog>,
+            history: Option<&History>)
+            -> i32 {
+    let stdout = io::stdout();
+    let terminal = stdout.is_terminal();
+    let mut printer = Printer::new(format, stdout.lock());
+    if terminal {
+        printer = printer.with_terminal(output::color_allowed());
+    }
+    let mut records = Vec::new();
+    let mut codes = Vec::new();
+    for host in hosts {
+        let mut record = match relay.wake(host.mac, if verify { host.ip } else { None }) {
+            Ok(record) => record,
+            Err(err) => {
+                let message = Message::CannotWakeVia {
+                    host: &host.name,
+                    url: relay.url(),
+                };
+                print_error!("{}: {}", message, err);
+                codes.push(exit::ERROR);
+                continue;
+            },
+        };
+        let error = match record.get("error") {
+            Some(Value::Str(error)) => Some(error.clone()),
+            _ => None,
+        };
+        if let Some(audit_log) = audit_log {
+            let event = AuditEvent {
+                initiator: Initiator::current_user(),
+                mac: host.mac.to_string(),
+                target: relay.url().to_string(),
+                outcome: error.clone().map_or(Ok(()), Err),
+            };
+            if let Err(err) = audit_log.record(&event) {
+                print_error!("{}: {}", Message::CannotWriteAuditLog, err);
+            }
+        }
+        let verified = match record.get("verified") {
+            Some(&Value::Bool(verified)) => Some(verified),
+            _ => None,
+        };
+        remember(history,
+                 Wake::new(&host.name,
+                           host.mac,
+                           Initiator::current_user(),
+                           error.clone().map_or(Ok(()), Err),
+                           verified));
+        if error.is_none() {
+            log::log_fields(Level::Info,
+                            "wol",
+                            format_args!("magic packet sent by relay"),
+                            &[("mac", &host.mac), ("relay", &relay.url()), ("result", &"ok")]);
+        }
+        codes.push(match (error, record.get("verified")) {
+            (Some(_), _) => exit::SEND_FAILED,
+            (None, Some(&Value::Bool(false))) => exit::NOT_UP,
+            (None, _) => exit::SUCCESS,
+        });
+        record.set("mac", host.mac.format(mac_format).to_string());
+        records.push(record);
+    }
+    if let Err(err) = printer.print_all(&records) {
+        return write_failed(&err, exit::for_batch(&codes, 0));
+    }
+    exit::for_batch(&codes, 0)
+}
+
+/// Returns the path of `--raw`, tagged with `--vlan` and `--pcp` if given, and the source MAC
+/// address of `--source-mac`.
+fn raw_path(matches: &Matches) -> Result<Option<(strategy::Path, Option<MacAddr>)>, String> {
+    let vlan = match (parse_opt::<u16>(matches, "vlan")?, parse_opt::<u8>(matches, "pcp")?) {
+        (Some(id), pcp) => Some(Vlan::new(id, pcp.unwrap_or(0))?),
+        (None, Some(_)) => return Err(Message::OptionNeeds("--pcp", "--vlan").to_string()),
+        (None, None) => None,
+    };
+    let source = parse_opt::<MacAddr>(matches, "source-mac")?;
+    if let Some(source) = source {
+        if source.is_multicast() || source.is_zero() {
+            return Err(Message::NotANetworkCard(source).to_string());
+        }
+    }
+    let path = match (matches.opt_str("raw"), vlan) {
+        (Some(interface), Some(vlan)) => strategy::Path::TaggedFrame(interface, vlan),
+        (Some(interface), None) => strategy::Path::RawFrame(interface),
+        (None, Some(_)) => return Err(Message::OptionNeeds("--vlan", "--raw").to_string()),
+        (None, None) if source.is_some() => {
+            return Err(Message::OptionNeeds("--source-mac", "--raw").to_string())
+        },
+        (None, None) => return Ok(None),
     };
-    let send_magic_packet =
-    if matches.opt_present("4") {
-        wol::send_magic_packet_v4
+    Ok(Some((path, source)))
+}
+
+/// Sends the packets of the hosts as raw Ethernet frames along `path`, from `source` if given,
+/// and prints the results.
+fn wake_raw(path: &strategy::Path,
+            source: Option<MacAddr>,
+            hosts: &[Host],
+            format: Format,
+            mac_format: MacFormat,
+            audit_log: Option<&AuditLog>,
+            history: Option<&History>)
+            -> i32 {
+    let stdout = io::stdout();
+    let terminal = stdout.is_terminal();
+    let mut printer = Printer::new(format, stdout.lock());
+    if terminal {
+        printer = printer.with_terminal(output::color_allowed());
+    }
+    if let Some(source) = source {
+        log::log(Level::Warn,
+                 "wol",
+                 format_args!("sending raw frames from the spoofed MAC address {}, which needs \
+                               CAP_NET_RAW: switches will send the traffic for it to this \
+                               machine until its owner sends again",
+                              source));
+    }
+    let client = WolClient::v4();
+    let mut records = Vec::new();
+    let mut codes = Vec::new();
+    // Shown once after the records, as all hosts fail alike without the privileges.
+    let mut hint = None;
+    for host in hosts {
+        let packet = host.packet();
+        let start = Instant::now();
+        let sent = match source {
+            Some(source) => path.send_from(&client, &packet, source),
+            None => path.send(&client, &packet),
+        };
+        let outcome = sent.map(|_| ());
+        if let Some(audit_log) = audit_log {
+            let event = AuditEvent {
+                initiator: Initiator::current_user(),
+                mac: host.mac.to_string(),
+                target: path.to_string(),
+                outcome: outcome.as_ref().map_err(|err| err.to_string()).cloned(),
+            };
+            if let Err(err) = audit_log.record(&event) {
+                print_error!("{}: {}", Message::CannotWriteAuditLog, err);
+            }
+        }
+        remember(history,
+                 Wake::new(&host.name,
+                           host.mac,
+                           Initiator::current_user(),
+                           outcome.as_ref().map_err(|err| err.to_string()).cloned(),
+                           None));
+        if outcome.is_ok() {
+            log::log_fields(Level::Info,
+                            "wol",
+                            format_args!("magic packet sent"),
+                            &[("mac", &host.mac), ("path", path), ("result", &"ok")]);
+        }
+        codes.push(if outcome.is_ok() { exit::SUCCESS } else { exit::SEND_FAILED });
+        let error = outcome.as_ref().err();
+        if let Some(err) = error {
+            hint = hint.or_else(|| WolError::from_raw(err.kind().into()).hint());
+        }
+        records.push(Record::new()
+            .field("mac", host.mac.format(mac_format).to_string())
+            .field("target", path.to_string())
+            .field("duration_ms", output::millis(start.elapsed()))
+            .field("error", error.map(|err| err.to_string()))
+            .field("code", error.map(|err| ErrorCode::of(err).as_str())));
+    }
+    if let Err(err) = printer.print_all(&records) {
+        return write_failed(&err, exit::for_batch(&codes, 0));
+    }
+    if let Some(hint) = hint {
+        log::log(Level::Warn, "wol", format_args!("{}", hint));
+    }
+    exit::for_batch(&codes, 0)
+}
+
+/// Returns the hosts which were woken successfully within their cooldown according to the wake
+/// history, with the time since that wake and the cooldown, by host name.
+fn cooling_down(history: Option<&History>,
+                hosts: &[Host],
+                config: &Config)
+                -> BTreeMap<String, (Duration, Duration)> {
+    let cooldowns: Vec<(&Host, Duration)> = hosts.iter()
+        .filter_map(|host| host.cooldown.or(config.cooldown).map(|cooldown| (host, cooldown)))
+        .collect();
+    let wakes = match history {
+        Some(history) if !cooldowns.is_empty() => match history.wakes() {
+            Ok(wakes) => wakes,
+            Err(err) => {
+                log::log(Level::Warn,
+                         "wol",
+                         format_args!("cannot read wake history {}, ignoring cooldowns: {}",
+                                      history.path().display(),
+                                      err));
+                return BTreeMap::new();
+            },
+        },
+        _ => return BTreeMap::new(),
+    };
+    cooldowns.into_iter()
+        .filter_map(|(host, cooldown)| {
+            let age = wakes.iter()
+                .rev()
+                .find(|wake| (wake.host == host.name || wake.mac == host.mac) &&
+                             wake.outcome.is_ok())?
+                .age()?;
+            if age < cooldown { Some((host.name.clone(), (age, cooldown))) } else { None }
+        })
+        .collect()
+}
+
+/// Sends the packets of the hosts with an IP address and without a broadcast address of their
+/// own as chosen by `addressing`, see `subnet::target_for`.
+fn address_hosts(plan: &mut [Vec<Host>], addressing: Addressing) {
+    if addressing == Addressing::Broadcast ||
+       !plan.iter().flatten().any(|host| host.ip.is_some() && host.broadcast.is_none()) {
+        return;
+    }
+    let networks = if addressing == Addressing::Auto {
+        match subnet::connected_networks() {
+            Ok(networks) => networks,
+            Err(err) => {
+                log::log(Level::Warn,
+                         "wol",
+                         format_args!("cannot read the connected networks, sending to the \
+                                       broadcast address: {}",
+                                      err));
+                return;
+            },
+        }
     } else {
-        wol::send_magic_packet_v6
+        Vec::new()
     };
+    for host in plan.iter_mut().flatten().filter(|host| host.broadcast.is_none()) {
+        if let Some(ip) = host.ip {
+            host.broadcast = subnet::target_for(ip, addressing, &networks);
+            if let Some(target) = host.broadcast {
+                log::log(Level::Debug,
+                         "wol",
+                         format_args!("sending to {} for {} at {}", target, host.name, ip));
+            }
+        }
+    }
+}
 
-    let mac = wol::parse_mac(mac_str);
-    match mac {
+/// Warns about the destinations of the hosts which the interface of the client is not connected
+/// to, e.g. the broadcast address of the wired network with `--interface wlan0`, as no packet
+/// sent there arrives.
+fn check_interface(client: &WolClient, hosts: &[Host]) {
+    let interface = match client.interface() {
+        Some(interface) => interface,
+        None => return,
+    };
+    let networks = match client.netns() {
+        Some(netns) => sys::in_netns(netns, || subnet::interface_networks(interface)),
+        None => subnet::interface_networks(interface),
+    };
+    let networks = match networks {
+        Ok(networks) => networks,
         Err(err) => {
-            println!("Error during parsing of MAC address: {}", err);
-            print_usage(&program, opts);
+            log::log(Level::Debug,
+                     "wol",
+                     format_args!("cannot read the networks of {}: {}", interface, err));
             return;
         },
-        Ok(mac) => send_magic_packet(mac).unwrap_or_else(|err| {
-            println!("Error during sending: {}", err);
-            print_usage(&program, opts);
-            return;
-        }),
+    };
+    let mut destinations: Vec<IpAddr> = hosts.iter()
+        .filter(|host| host.relay.is_none())
+        .map(|host| host.client(client).destination().ip())
+        .collect();
+    destinations.sort();
+    destinations.dedup();
+    let listed: Vec<String> = networks.iter().map(Cidr::to_string).collect();
+    let listed = if listed.is_empty() { "none".to_string() } else { listed.join(", ") };
+    for destination in destinations.into_iter().filter(|&ip| !subnet::reaches(&networks, ip)) {
+        log::log(Level::Warn,
+                 "wol",
+                 format_args!("{} is not on a network of {} ({}), the packets will not arrive",
+                              destination,
+                              interface,
+                              listed));
+    }
+}
+
+/// Warns about or rejects MAC addresses which no network card can have, as configured. Unless
+/// the check is off, locally administered addresses get a hint, as they are often randomized.
+fn check_macs(hosts: &[Host], check: MacCheck) -> Result<(), String> {
+    for host in hosts {
+        match (host.mac.suspicious(), check) {
+            (_, MacCheck::Ignore) => {},
+            (None, _) if host.mac.is_locally_administered() => {
+                log::log(Level::Warn,
+                         "wol",
+                         format_args!("{} is locally administered, often a randomized Wi-Fi \
+                                       or virtual machine address; make sure it is that of the \
+                                       network card which wakes the host",
+                                      host.mac))
+            },
+            (None, _) => {},
+            (Some(reason), MacCheck::Warn) => {
+                log::log(Level::Warn,
+                         "wol",
+                         format_args!("{} is {}, which is most likely a typo", host.mac, reason))
+            },
+            (Some(_), MacCheck::Reject) => return Err(Message::RejectedMac(host.mac).to_string()),
+        }
     }
+    Ok(())
+}
+
+/// Returns a host named after its MAC address.
+fn host_for_mac(mac: MacAddr) -> Host {
+    Host::new(&mac.to_string(), mac)
 }
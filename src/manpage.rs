@@ -0,0 +1,318 @@
+//! Man pages and markdown help generated from the definitions of the command line options, so
+//! that packages can ship documentation which matches the binary.
+//!
+//! A `Page` holds the commands of a program with their options and further sections like the
+//! exit codes, and renders them as roff for `man` or as markdown.
+
+/// A command line option.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opt {
+    /// The short name without dash, empty if there is none.
+    pub short: String,
+    /// The long name without dashes, empty if there is none.
+    pub long: String,
+    /// The name of the argument, empty for flags.
+    pub hint: String,
+    /// Whether the argument can be left out.
+    pub optional: bool,
+    pub description: String,
+}
+
+impl Opt {
+    /// Creates an option; `hint` is empty for flags.
+    pub fn new(short: &str, long: &str, hint: &str, description: &str) -> Opt {
+        Opt {
+            short: short.to_string(),
+            long: long.to_string(),
+            hint: hint.to_string(),
+            optional: false,
+            description: description.to_string(),
+        }
+    }
+
+    /// Sets whether the argument can be left out.
+    pub fn with_optional(mut self, optional: bool) -> Opt {
+        self.optional = optional;
+        self
+    }
+
+    /// Returns the option as written on the command line, e.g. "-v, --verbose", "--port PORT"
+    /// or "--raw [IFACE]".
+    pub fn usage(&self) -> String {
+        let mut names: Vec<String> = Vec::new();
+        if !self.short.is_empty() {
+            names.push(format!("-{}", self.short));
+        }
+        if !self.long.is_empty() {
+            names.push(format!("--{}", self.long));
+        }
+        let mut usage = names.join(", ");
+        if self.optional {
+            usage.push_str(&format!(" [{}]", self.hint));
+        } else if !self.hint.is_empty() {
+            usage.push_str(&format!(" {}", self.hint));
+        }
+        usage
+    }
+}
+
+/// A command of the program, e.g. a subcommand like `serve`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub name: String,
+    /// The arguments following the program name, e.g. "serve [--bind ADDR]".
+    pub synopsis: String,
+    pub description: String,
+    pub options: Vec<Opt>,
+}
+
+impl Command {
+    pub fn new(name: &str, synopsis: &str, description: &str) -> Command {
+        Command {
+            name: name.to_string(),
+            synopsis: synopsis.to_string(),
+            description: description.to_string(),
+            options: Vec::new(),
+        }
+    }
+
+    /// Sets the options of the command.
+    pub fn with_options(mut self, options: Vec<Opt>) -> Command {
+        self.options = options;
+        self
+    }
+}
+
+/// A further section of a page, e.g. the exit codes, with text and a list of terms like the
+/// codes and their descriptions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub title: String,
+    /// Paragraphs separated by empty lines.
+    pub text: String,
+    pub items: Vec<(String, String)>,
+}
+
+impl Section {
+    pub fn new(title: &str, text: &str) -> Section {
+        Section {
+            title: title.to_string(),
+            text: text.to_string(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Adds a term and its description.
+    pub fn with_item(mut self, term: &str, description: &str) -> Section {
+        self.items.push((term.to_string(), description.to_string()));
+        self
+    }
+}
+
+/// The documentation of a program.
+///
+/// #Example
+///
+/// ```
+/// use wol::manpage::{Command, Opt, Page};
+///
+/// let page = Page::new("wol", "0.1.0", "wake up remote computers")
+///     .with_command(Command::new("wake", "[wake] MAC", "Wakes the host.")
+///         .with_options(vec![Opt::new("", "port", "PORT", "send to this UDP port")]));
+/// assert!(page.roff().contains(".B \\-\\-port \\fIPORT\\fR\n"));
+/// assert!(page.markdown().contains("* `--port PORT` send to this UDP port\n"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub name: String,
+    pub version: String,
+    /// A line describing the program, shown after its name.
+    pub summary: String,
+    pub commands: Vec<Command>,
+    pub sections: Vec<Section>,
+}
+
+impl Page {
+    pub fn new(name: &str, version: &str, summary: &str) -> Page {
+        Page {
+            name: name.to_string(),
+            version: version.to_string(),
+            summary: summary.to_string(),
+            commands: Vec::new(),
+            sections: Vec::new(),
+        }
+    }
+
+    pub fn with_command(mut self, command: Command) -> Page {
+        self.commands.push(command);
+        self
+    }
+
+    pub fn with_section(mut self, section: Section) -> Page {
+        self.sections.push(section);
+        self
+    }
+
+    /// Renders the page as a man page of section 1. It carries no date, so that builds are
+    /// reproducible.
+    pub fn roff(&self) -> String {
+        let mut page = format!(".TH {} 1 \"\" \"{} {}\" \"User Commands\"\n",
+                               self.name.to_uppercase(),
+                               roff_escape(&self.name),
+                               roff_escape(&self.version));
+        page.push_str(&format!(".SH NAME\n{} \\- {}\n", roff_escape(&self.name),
+                               roff_escape(&self.summary)));
+        page.push_str(".SH SYNOPSIS\n");
+        for command in &self.commands {
+            page.push_str(&format!(".B {}\n{}\n.br\n", roff_escape(&self.name),
+                                   roff_escape(&command.synopsis)));
+        }
+        for command in &self.commands {
+            page.push_str(&format!(".SH \"{} {}\"\n",
+                                   roff_escape(&self.name).to_uppercase(),
+                                   roff_escape(&command.name).to_uppercase()));
+            push_roff_text(&mut page, &command.description);
+            for option in &command.options {
+                page.push_str(&format!(".TP\n{}\n{}\n", roff_option(option),
+                                       roff_line(&option.description)));
+            }
+        }
+        for section in &self.sections {
+            page.push_str(&format!(".SH \"{}\"\n", roff_escape(&section.title.to_uppercase())));
+            push_roff_text(&mut page, &section.text);
+            for (term, description) in &section.items {
+                page.push_str(&format!(".TP\n.B {}\n{}\n", roff_escape(term),
+                                       roff_line(description)));
+            }
+        }
+        page
+    }
+
+    /// Renders the page as markdown, e.g. for a website or the repository.
+    pub fn markdown(&self) -> String {
+        let mut page = format!("# {}\n\n{} {}: {}\n\n## Synopsis\n\n```\n",
+                               self.name,
+                               self.name,
+                               self.version,
+                               self.summary);
+        for command in &self.commands {
+            page.push_str(&format!("{} {}\n", self.name, command.synopsis));
+        }
+        page.push_str("```\n");
+        for command in &self.commands {
+            page.push_str(&format!("\n## {} {}\n\n", self.name, command.name));
+            push_markdown_text(&mut page, &command.description);
+            for option in &command.options {
+                page.push_str(&format!("* `{}` {}\n", option.usage(), option.description));
+            }
+        }
+        for section in &self.sections {
+            page.push_str(&format!("\n## {}\n\n", section.title));
+            push_markdown_text(&mut page, &section.text);
+            for (term, description) in &section.items {
+                page.push_str(&format!("* `{}` {}\n", term, description));
+            }
+        }
+        page
+    }
+}
+
+/// Escapes backslashes and dashes, which roff would otherwise render as hyphens.
+fn roff_escape(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
+
+/// Escapes a line of text, which must not start with a control character.
+fn roff_line(text: &str) -> String {
+    let escaped = roff_escape(text);
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+/// Returns the option in bold with its argument in italics.
+fn roff_option(option: &Opt) -> String {
+    let names = Opt::new(&option.short, &option.long, "", "").usage();
+    let hint = match (option.optional, option.hint.is_empty()) {
+        (true, _) => format!(" [\\fI{}\\fR]", roff_escape(&option.hint)),
+        (false, false) => format!(" \\fI{}\\fR", roff_escape(&option.hint)),
+        (false, true) => String::new(),
+    };
+    format!(".B {}{}", roff_escape(&names), hint)
+}
+
+/// Appends paragraphs separated by empty lines.
+fn push_roff_text(page: &mut String, text: &str) {
+    for paragraph in paragraphs(text) {
+        page.push_str(&format!(".PP\n{}\n", roff_line(&paragraph)));
+    }
+}
+
+fn push_markdown_text(page: &mut String, text: &str) {
+    for paragraph in paragraphs(text) {
+        page.push_str(&format!("{}\n\n", paragraph));
+    }
+}
+
+/// Returns the paragraphs of the text, each joined into one line.
+fn paragraphs(text: &str) -> Vec<String> {
+    text.split("\n\n")
+        .map(|paragraph| paragraph.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{paragraphs, roff_line, Command, Opt, Page, Section};
+
+    fn page() -> Page {
+        Page::new("wol", "0.1.0", "wake up remote computers")
+            .with_command(Command::new("listen",
+                                       "listen [--raw [IFACE]]",
+                                       "Prints the magic packets.\n\n.Stops on ctrl-C.")
+                .with_options(vec![Opt::new("v", "verbose", "", "log more details"),
+                                   Opt::new("", "raw", "IFACE", "receive raw frames")
+                                       .with_optional(true)]))
+            .with_section(Section::new("Exit codes", "").with_item("2", "invalid arguments"))
+    }
+
+    #[test]
+    fn test_usage() {
+        assert_eq!(Opt::new("v", "verbose", "", "").usage(), "-v, --verbose");
+        assert_eq!(Opt::new("", "port", "PORT", "").usage(), "--port PORT");
+        assert_eq!(Opt::new("", "raw", "IFACE", "").with_optional(true).usage(), "--raw [IFACE]");
+    }
+
+    #[test]
+    fn test_roff() {
+        let roff = page().roff();
+        assert!(roff.starts_with(".TH WOL 1 \"\" \"wol 0.1.0\" \"User Commands\"\n\
+                                  .SH NAME\nwol \\- wake up remote computers\n\
+                                  .SH SYNOPSIS\n.B wol\nlisten [\\-\\-raw [IFACE]]\n.br\n"));
+        assert!(roff.contains(".SH \"WOL LISTEN\"\n.PP\nPrints the magic packets.\n\
+                               .PP\n\\&.Stops on ctrl\\-C.\n"));
+        assert!(roff.contains(".TP\n.B \\-v, \\-\\-verbose\nlog more details\n"));
+        assert!(roff.contains(".TP\n.B \\-\\-raw [\\fIIFACE\\fR]\nreceive raw frames\n"));
+        assert!(roff.ends_with(".SH \"EXIT CODES\"\n.TP\n.B 2\ninvalid arguments\n"));
+    }
+
+    #[test]
+    fn test_markdown() {
+        let markdown = page().markdown();
+        assert!(markdown.starts_with("# wol\n\nwol 0.1.0: wake up remote computers\n\n\
+                                      ## Synopsis\n\n```\nwol listen [--raw [IFACE]]\n```\n"));
+        assert!(markdown.contains("## wol listen\n\nPrints the magic packets.\n\n"));
+        assert!(markdown.contains("* `-v, --verbose` log more details\n\
+                                   * `--raw [IFACE]` receive raw frames\n"));
+        assert!(markdown.ends_with("## Exit codes\n\n* `2` invalid arguments\n"));
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(paragraphs("one\n  line\n\ntwo\n\n"), vec!["one line", "two"]);
+        assert_eq!(roff_line("'quoted' \\n"), "\\&'quoted' \\en");
+    }
+}
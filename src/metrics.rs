@@ -0,0 +1,183 @@
+//! Counters about sent packets and a histogram of boot times in the Prometheus text format.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The upper bounds in seconds of the buckets of the boot time histogram.
+pub const BOOT_BUCKETS: [f64; 8] = [5.0, 10.0, 20.0, 30.0, 45.0, 60.0, 120.0, 300.0];
+
+/// The number of boot times per bucket, their sum and count.
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// The number of boot times up to the bound of each bucket; larger ones are only counted.
+    buckets: [u64; BOOT_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// Counters of a long running process, e.g. `wol serve`, which can be shared between threads.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    packets_sent: AtomicU64,
+    send_failures: AtomicU64,
+    verify_successes: AtomicU64,
+    verify_timeouts: AtomicU64,
+    coalesced: AtomicU64,
+    last_wake: Mutex<BTreeMap<String, SystemTime>>,
+    boot_times: Mutex<BTreeMap<String, Histogram>>,
+}
+
+impl Metrics {
+    /// Creates a set of counters starting at zero.
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Counts a successfully sent magic packet for the given MAC address.
+    pub fn record_sent(&self, mac: &str) {
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.last_wake
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(mac.to_string(), SystemTime::now());
+    }
+
+    /// Counts a magic packet which could not be sent.
+    pub fn record_send_failure(&self) {
+        self.send_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts the result of verifying that a host is awake.
+    pub fn record_verify(&self, success: bool) {
+        if success {
+            self.verify_successes.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.verify_timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a wake request which was answered with the result of a concurrent one.
+    pub fn record_coalesced(&self) {
+        self.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records how long the host with the given MAC address took from the magic packet until it
+    /// was verified to be up.
+    pub fn record_boot_time(&self, mac: &str, boot_time: Duration) {
+        let secs = boot_time.as_secs_f64();
+        let mut boot_times = self.boot_times.lock().unwrap_or_else(|e| e.into_inner());
+        let histogram = boot_times.entry(mac.to_string()).or_default();
+        for (count, bound) in histogram.buckets.iter_mut().zip(BOOT_BUCKETS.iter()) {
+            if secs <= *bound {
+                *count += 1;
+            }
+        }
+        histogram.sum += secs;
+        histogram.count += 1;
+    }
+
+    /// Returns the number of successfully sent magic packets.
+    pub fn packets_sent(&self) -> u64 {
+        self.packets_sent.load(Ordering::Relaxed)
+    }
+
+    /// Renders all counters in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [("wol_packets_sent_total",
+                         "Magic packets sent successfully.",
+                         &self.packets_sent),
+                        ("wol_send_failures_total",
+                         "Magic packets which could not be sent.",
+                         &self.send_failures),
+                        ("wol_verify_success_total",
+                         "Hosts verified to be awake after a wake.",
+                         &self.verify_successes),
+                        ("wol_verify_timeout_total",
+                         "Hosts not awake before the verification timed out.",
+                         &self.verify_timeouts),
+                        ("wol_wake_requests_coalesced_total",
+                         "Wake requests answered with the result of a concurrent one.",
+                         &self.coalesced)];
+        for &(name, help, counter) in &counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+        }
+        let name = "wol_last_wake_timestamp_seconds";
+        let _ = writeln!(out,
+                         "# HELP {} Time of the last magic packet sent per MAC address.",
+                         name);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (mac, time) in self.last_wake.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+            let _ = writeln!(out, "{}{{mac=\"{}\"}} {:.3}", name, mac, secs);
+        }
+        let name = "wol_boot_seconds";
+        let _ = writeln!(out,
+                         "# HELP {} Time from the magic packet until the host was up per MAC \
+                          address.",
+                         name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (mac, histogram) in self.boot_times.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            for (count, bound) in histogram.buckets.iter().zip(BOOT_BUCKETS.iter()) {
+                let _ = writeln!(out,
+                                 "{}_bucket{{mac=\"{}\",le=\"{}\"}} {}",
+                                 name,
+                                 mac,
+                                 bound,
+                                 count);
+            }
+            let _ = writeln!(out,
+                             "{}_bucket{{mac=\"{}\",le=\"+Inf\"}} {}",
+                             name,
+                             mac,
+                             histogram.count);
+            let _ = writeln!(out, "{}_sum{{mac=\"{}\"}} {:.3}", name, mac, histogram.sum);
+            let _ = writeln!(out, "{}_count{{mac=\"{}\"}} {}", name, mac, histogram.count);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Metrics;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.record_sent("00:01:02:03:04:05");
+        metrics.record_sent("00:01:02:03:04:05");
+        metrics.record_send_failure();
+        metrics.record_verify(false);
+        metrics.record_coalesced();
+        let text = metrics.render();
+        assert!(text.contains("\nwol_packets_sent_total 2\n"));
+        assert!(text.contains("\nwol_send_failures_total 1\n"));
+        assert!(text.contains("\nwol_verify_success_total 0\n"));
+        assert!(text.contains("\nwol_verify_timeout_total 1\n"));
+        assert!(text.contains("\nwol_wake_requests_coalesced_total 1\n"));
+        assert!(text.contains("\nwol_last_wake_timestamp_seconds{mac=\"00:01:02:03:04:05\"} "));
+        assert!(text.contains("\n# TYPE wol_boot_seconds histogram\n"));
+    }
+
+    #[test]
+    fn test_boot_times() {
+        let metrics = Metrics::new();
+        metrics.record_boot_time("00:01:02:03:04:05", Duration::from_secs(8));
+        metrics.record_boot_time("00:01:02:03:04:05", Duration::from_millis(400_500));
+        let text = metrics.render();
+        let mac = "mac=\"00:01:02:03:04:05\"";
+        assert!(text.contains(&format!("wol_boot_seconds_bucket{{{},le=\"5\"}} 0\n", mac)));
+        assert!(text.contains(&format!("wol_boot_seconds_bucket{{{},le=\"10\"}} 1\n", mac)));
+        assert!(text.contains(&format!("wol_boot_seconds_bucket{{{},le=\"300\"}} 1\n", mac)));
+        assert!(text.contains(&format!("wol_boot_seconds_bucket{{{},le=\"+Inf\"}} 2\n", mac)));
+        assert!(text.contains(&format!("wol_boot_seconds_sum{{{}}} 408.500\n", mac)));
+        assert!(text.contains(&format!("wol_boot_seconds_count{{{}}} 2\n", mac)));
+    }
+}
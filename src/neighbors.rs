@@ -0,0 +1,524 @@
+//! The neighbor table of the operating system, which maps the IP addresses of hosts on the local
+//! links to their MAC addresses, to wake a host by the IP address it had while it was up.
+//!
+//! The table is read from `/proc/net/arp` and, for the IPv6 neighbors of NDP, the output of
+//! `ip -6 neigh` on Linux, and with `GetIpNetTable2` on Windows. On macOS and the BSDs it is read
+//! from the output of `arp -an` and `ndp -an`, which is the same on all of them, unlike the
+//! routing messages of `sysctl` they print. Entries expire a few minutes after a host went quiet,
+//! so a host is best looked up soon after it went down, or asked for its MAC address with
+//! `resolve`. Entries of hosts which did not answer have no MAC address and are left out.
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr, SocketAddrV6, UdpSocket};
+#[cfg(any(target_os = "linux", windows, test))]
+use std::net::Ipv4Addr;
+#[cfg(any(windows, test))]
+use std::net::Ipv6Addr;
+#[cfg(any(target_os = "linux",
+          target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly"))]
+use std::process::Command;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sys;
+
+use MacAddr;
+
+/// An entry of the neighbor table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbor {
+    pub ip: IpAddr,
+    pub mac: MacAddr,
+    /// The name of the interface the neighbor is on, e.g. "eth0", or its index on Windows.
+    pub interface: String,
+}
+
+/// An IP address with the interface it is on, which link-local IPv6 addresses need, e.g.
+/// `fe80::1%eth0`, or `fe80::1%12` with the index of the interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NeighborAddr {
+    pub ip: IpAddr,
+    pub interface: Option<String>,
+}
+
+impl FromStr for NeighborAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<NeighborAddr, String> {
+        let (ip, interface) = match s.find('%') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+        let ip: IpAddr = ip.parse().map_err(|_| format!("'{}' is not an IP address", ip))?;
+        match interface {
+            Some("") => Err("expected an interface after '%'".to_string()),
+            Some(_) if ip.is_ipv4() => Err("only IPv6 addresses have an interface".to_string()),
+            _ => {
+                Ok(NeighborAddr {
+                    ip,
+                    interface: interface.map(str::to_string),
+                })
+            },
+        }
+    }
+}
+
+impl fmt::Display for NeighborAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.interface {
+            Some(ref interface) => write!(f, "{}%{}", self.ip, interface),
+            None => write!(f, "{}", self.ip),
+        }
+    }
+}
+
+/// Reads the neighbor table.
+#[cfg(target_os = "linux")]
+pub fn table() -> io::Result<Vec<Neighbor>> {
+    let mut neighbors = parse_proc(&::std::fs::read_to_string("/proc/net/arp")?);
+    // The IPv6 neighbors are only available over netlink, which the ip tool speaks.
+    if let Ok(output) = run("ip", &["-6", "neigh", "show"]) {
+        neighbors.extend(parse_ip_neigh(&output));
+    }
+    Ok(neighbors)
+}
+
+/// Reads the neighbor table.
+#[cfg(windows)]
+pub fn table() -> io::Result<Vec<Neighbor>> {
+    windows::table()
+}
+
+/// Reads the neighbor table.
+#[cfg(any(target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly"))]
+pub fn table() -> io::Result<Vec<Neighbor>> {
+    let mut neighbors = parse_bsd(&run("arp", &["-an"])?);
+    // Systems without IPv6 have no ndp.
+    if let Ok(output) = run("ndp", &["-an"]) {
+        neighbors.extend(parse_bsd(&output));
+    }
+    Ok(neighbors)
+}
+
+/// Reads the neighbor table, which is only supported on Linux, Windows, macOS and the BSDs.
+#[cfg(not(any(target_os = "linux",
+              windows,
+              target_os = "macos",
+              target_os = "freebsd",
+              target_os = "openbsd",
+              target_os = "netbsd",
+              target_os = "dragonfly")))]
+pub fn table() -> io::Result<Vec<Neighbor>> {
+    Err(io::Error::other("reading the neighbor table is only supported on Linux, Windows, macOS \
+                          and the BSDs"))
+}
+
+/// Returns the MAC address of the host with the given address in the neighbor table, on the
+/// interface of the address if it has one.
+pub fn lookup(addr: &NeighborAddr) -> io::Result<Option<MacAddr>> {
+    let on_interface = |neighbor: &Neighbor| match addr.interface {
+        Some(ref interface) => neighbor.interface == *interface,
+        None => true,
+    };
+    Ok(table()?
+        .into_iter()
+        .find(|neighbor| neighbor.ip == addr.ip && on_interface(neighbor))
+        .map(|neighbor| neighbor.mac))
+}
+
+/// Asks for the MAC address of the host with the given address, by ARP or a neighbor
+/// solicitation of NDP, and waits up to `timeout` for it to appear in the neighbor table.
+///
+/// Instead of sending the request itself, which needs a raw socket, this sends an empty UDP
+/// datagram to the discard port, before which the system asks for the MAC address. The network
+/// cards of sleeping hosts often answer on their behalf.
+pub fn resolve(addr: &NeighborAddr, timeout: Duration) -> io::Result<Option<MacAddr>> {
+    if let Some(mac) = lookup(addr)? {
+        return Ok(Some(mac));
+    }
+    let destination = match (addr.ip, &addr.interface) {
+        (IpAddr::V6(ip), Some(interface)) => {
+            let index = match interface.parse() {
+                Ok(index) => index,
+                Err(_) => sys::interface_index(interface)?,
+            };
+            SocketAddr::V6(SocketAddrV6::new(ip, DISCARD_PORT, 0, index))
+        },
+        (ip, _) => SocketAddr::new(ip, DISCARD_PORT),
+    };
+    let local: SocketAddr = if destination.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }
+        .parse()
+        .unwrap();
+    UdpSocket::bind(local)?.send_to(&[], destination)?;
+    let start = Instant::now();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if let Some(mac) = lookup(addr)? {
+            return Ok(Some(mac));
+        }
+        if start.elapsed() >= timeout {
+            return Ok(None);
+        }
+    }
+}
+
+/// The port of the discard service, to which `resolve` sends.
+const DISCARD_PORT: u16 = 9;
+/// How often `resolve` reads the neighbor table while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs a tool of the system and returns what it printed.
+#[cfg(any(target_os = "linux",
+          target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly"))]
+fn run(tool: &str, args: &[&str]) -> io::Result<String> {
+    let output = Command::new(tool).args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!("{} {} failed: {}",
+                                            tool,
+                                            args.join(" "),
+                                            stderr.trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parses `/proc/net/arp`, skipping incomplete entries.
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc(table: &str) -> Vec<Neighbor> {
+    // The ARP flag of entries with a MAC address, ATF_COM.
+    const COMPLETE: u32 = 0x2;
+    table.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return None;
+            }
+            let flags = u32::from_str_radix(fields[2].trim_start_matches("0x"), 16).ok()?;
+            if flags & COMPLETE == 0 {
+                return None;
+            }
+            Some(Neighbor {
+                ip: IpAddr::V4(fields[0].parse::<Ipv4Addr>().ok()?),
+                mac: fields[3].parse().ok()?,
+                interface: fields[5].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parses the output of `ip -6 neigh show` on Linux, e.g.
+/// `fe80::1 dev eth0 lladdr 00:11:22:33:44:55 router REACHABLE`, skipping the entries without
+/// MAC address.
+#[cfg(any(target_os = "linux", test))]
+fn parse_ip_neigh(output: &str) -> Vec<Neighbor> {
+    output.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let ip = fields.next()?.parse().ok()?;
+            let (mut mac, mut interface) = (None, None);
+            while let Some(key) = fields.next() {
+                match key {
+                    "dev" => interface = fields.next(),
+                    "lladdr" => mac = fields.next().and_then(|mac| mac.parse().ok()),
+                    "FAILED" | "INCOMPLETE" => return None,
+                    _ => {},
+                }
+            }
+            Some(Neighbor {
+                ip,
+                mac: mac?,
+                interface: interface?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parses the output of `arp -an` and `ndp -an` on macOS and the BSDs, skipping incomplete
+/// entries. Most print lines like `? (192.168.1.1) at 0:11:22:33:44:55 on en0 ...`, while `ndp`
+/// and the `arp` of OpenBSD print columns of the address, the MAC address and the interface.
+#[cfg(any(target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly",
+          test))]
+fn parse_bsd(output: &str) -> Vec<Neighbor> {
+    // The octets of the MAC addresses lack leading zeros on macOS.
+    fn mac(s: &str) -> Option<MacAddr> {
+        let octets: Vec<u8> = s.split(':')
+            .map(|octet| if octet.len() <= 2 { u8::from_str_radix(octet, 16).ok() } else { None })
+            .collect::<Option<_>>()?;
+        let mut mac = [0; 6];
+        if octets.len() != mac.len() {
+            return None;
+        }
+        mac.copy_from_slice(&octets);
+        Some(MacAddr::new(mac))
+    }
+
+    output.lines()
+        .filter_map(|line| {
+            let (ip, fields) = match (line.find('('), line.find(')')) {
+                (Some(open), Some(close)) if open < close => {
+                    let mut fields = line[close + 1..].split_whitespace();
+                    let fields = (fields.next(), fields.next(), fields.next(), fields.next());
+                    match fields {
+                        (Some("at"), Some(mac), Some("on"), Some(interface)) => {
+                            (&line[open + 1..close], (mac, interface))
+                        },
+                        _ => return None,
+                    }
+                },
+                _ => {
+                    let mut fields = line.split_whitespace();
+                    (fields.next()?, (fields.next()?, fields.next()?))
+                },
+            };
+            // The zone of link-local IPv6 addresses, e.g. fe80::1%en0, is their interface.
+            let ip = ip.split('%').next()?.parse().ok()?;
+            Some(Neighbor {
+                ip,
+                mac: mac(fields.0)?,
+                interface: fields.1.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The layout of the rows of `GetIpNetTable2`, `MIB_IPNET_ROW2`, which is decoded on every
+/// platform to be tested.
+#[cfg(any(windows, test))]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MibIpnetRow2 {
+    /// A `SOCKADDR_INET`: the family, the port and the rest of `SOCKADDR_IN` or `SOCKADDR_IN6`.
+    family: u16,
+    port: u16,
+    address: [u8; 24],
+    interface_index: u32,
+    interface_luid: u64,
+    physical_address: [u8; 32],
+    physical_address_length: u32,
+    state: i32,
+    flags: u8,
+    reachability_time: u32,
+}
+
+#[cfg(any(windows, test))]
+const AF_INET: u16 = 2;
+#[cfg(any(windows, test))]
+const AF_INET6: u16 = 23;
+
+#[cfg(any(windows, test))]
+impl MibIpnetRow2 {
+    /// Returns the neighbor of the row, unless it is unreachable or incomplete.
+    fn neighbor(&self) -> Option<Neighbor> {
+        // NlnsUnreachable and NlnsIncomplete, the states without a MAC address.
+        if self.state < 2 || self.physical_address_length != 6 {
+            return None;
+        }
+        let ip = match self.family {
+            AF_INET => {
+                IpAddr::V4(Ipv4Addr::new(self.address[0],
+                                         self.address[1],
+                                         self.address[2],
+                                         self.address[3]))
+            },
+            AF_INET6 => {
+                // The address follows the flow info.
+                let mut octets = [0; 16];
+                octets.copy_from_slice(&self.address[4..20]);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            },
+            _ => return None,
+        };
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&self.physical_address[..6]);
+        Some(Neighbor {
+            ip,
+            mac: MacAddr::new(mac),
+            interface: self.interface_index.to_string(),
+        })
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::io;
+    use std::os::raw::c_void;
+    use std::ptr;
+    use std::slice;
+
+    use super::{MibIpnetRow2, Neighbor};
+
+    /// The family of both IPv4 and IPv6 entries.
+    const AF_UNSPEC: u16 = 0;
+
+    /// `MIB_IPNET_TABLE2`, whose rows follow the number of rows.
+    #[repr(C)]
+    struct MibIpnetTable2 {
+        num_entries: u32,
+        table: [MibIpnetRow2; 1],
+    }
+
+    #[link(name = "iphlpapi")]
+    extern "system" {
+        fn GetIpNetTable2(family: u16, table: *mut *mut MibIpnetTable2) -> u32;
+        fn FreeMibTable(memory: *mut c_void);
+    }
+
+    pub fn table() -> io::Result<Vec<Neighbor>> {
+        let mut table: *mut MibIpnetTable2 = ptr::null_mut();
+        let ret = unsafe { GetIpNetTable2(AF_UNSPEC, &mut table) };
+        if ret != 0 {
+            return Err(io::Error::from_raw_os_error(ret as i32));
+        }
+        let neighbors = unsafe {
+            let len = (*table).num_entries as usize;
+            let rows = slice::from_raw_parts((*table).table.as_ptr(), len);
+            rows.iter().filter_map(MibIpnetRow2::neighbor).collect()
+        };
+        unsafe { FreeMibTable(table as *mut c_void) };
+        Ok(neighbors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use std::net::IpAddr;
+
+    use super::{parse_bsd, parse_ip_neigh, parse_proc, MibIpnetRow2, Neighbor, NeighborAddr,
+                AF_INET, AF_INET6};
+    use MacAddr;
+
+    #[test]
+    fn test_parse_proc() {
+        let table = "IP address     HW type  Flags  HW address         Mask  Device\n\
+                     192.168.1.1    0x1      0x2    00:11:22:33:44:55  *     eth0\n\
+                     192.168.1.9    0x1      0x0    00:00:00:00:00:00  *     eth0\n\
+                     10.0.0.7       0x1      0x6    aa:bb:cc:dd:ee:ff  *     wlan0\n";
+        assert_eq!(parse_proc(table),
+                   [Neighbor {
+                        ip: "192.168.1.1".parse().unwrap(),
+                        mac: MacAddr::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+                        interface: "eth0".to_string(),
+                    },
+                    Neighbor {
+                        ip: "10.0.0.7".parse().unwrap(),
+                        mac: MacAddr::new([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]),
+                        interface: "wlan0".to_string(),
+                    }]);
+    }
+
+    #[test]
+    fn test_neighbor_addr() {
+        let addr: NeighborAddr = "fe80::1%eth0".parse().unwrap();
+        assert_eq!(addr.ip, "fe80::1".parse::<IpAddr>().unwrap());
+        assert_eq!(addr.interface.as_deref(), Some("eth0"));
+        assert_eq!(addr.to_string(), "fe80::1%eth0");
+        assert_eq!("10.0.0.7".parse::<NeighborAddr>().unwrap().interface, None);
+        assert_eq!("10.0.0.7%eth0".parse::<NeighborAddr>().unwrap_err(),
+                   "only IPv6 addresses have an interface");
+        assert_eq!("fe80::1%".parse::<NeighborAddr>().unwrap_err(),
+                   "expected an interface after '%'");
+        assert_eq!("nas".parse::<NeighborAddr>().unwrap_err(), "'nas' is not an IP address");
+    }
+
+    #[test]
+    fn test_parse_ip_neigh() {
+        let output = "fe80::1 dev eth0 lladdr 00:11:22:33:44:55 router REACHABLE\n\
+                      2001:db8::7 dev eth0 lladdr aa:bb:cc:dd:ee:ff STALE\n\
+                      2001:db8::9 dev eth0 FAILED\n\
+                      2001:db8::a dev eth1 lladdr 00:00:00:00:00:0a INCOMPLETE\n";
+        assert_eq!(parse_ip_neigh(output),
+                   [Neighbor {
+                        ip: "fe80::1".parse().unwrap(),
+                        mac: MacAddr::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+                        interface: "eth0".to_string(),
+                    },
+                    Neighbor {
+                        ip: "2001:db8::7".parse().unwrap(),
+                        mac: MacAddr::new([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]),
+                        interface: "eth0".to_string(),
+                    }]);
+    }
+
+    #[test]
+    fn test_parse_bsd() {
+        let neighbor = |ip: &str, mac: [u8; 6], interface: &str| {
+            Neighbor {
+                ip: ip.parse().unwrap(),
+                mac: MacAddr::new(mac),
+                interface: interface.to_string(),
+            }
+        };
+        let macos = "? (192.168.1.1) at 0:11:22:3:44:55 on en0 ifscope [ethernet]\n\
+                     ? (192.168.1.9) at (incomplete) on en0 ifscope [ethernet]\n\
+                     ? (192.168.1.255) at ff:ff:ff:ff:ff:ff on en0 ifscope [ethernet]\n";
+        assert_eq!(parse_bsd(macos),
+                   [neighbor("192.168.1.1", [0x00, 0x11, 0x22, 0x03, 0x44, 0x55], "en0"),
+                    neighbor("192.168.1.255", [0xFF; 6], "en0")]);
+        let freebsd = "? (10.0.0.7) at aa:bb:cc:dd:ee:ff on em0 expires in 1183 seconds \
+                       [ethernet]\n";
+        assert_eq!(parse_bsd(freebsd),
+                   [neighbor("10.0.0.7", [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF], "em0")]);
+        let ndp = "Neighbor                 Linklayer Address  Netif Expire    St Flgs Prbs\n\
+                   fe80::1%en0              0:11:22:33:44:55     en0 23h59m58s S  R\n\
+                   2001:db8::9              (incomplete)         en0 expired   N\n";
+        assert_eq!(parse_bsd(ndp),
+                   [neighbor("fe80::1", [0x00, 0x11, 0x22, 0x33, 0x44, 0x55], "en0")]);
+        let openbsd = "Host        Ethernet Address   Netif Expire    Flags\n\
+                       10.0.0.7    aa:bb:cc:dd:ee:ff  em0   19m59s\n";
+        assert_eq!(parse_bsd(openbsd),
+                   [neighbor("10.0.0.7", [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF], "em0")]);
+    }
+
+    #[test]
+    fn test_windows_row() {
+        // The size of MIB_IPNET_ROW2 in the Windows SDK.
+        assert_eq!(mem::size_of::<MibIpnetRow2>(), 88);
+        let mut row = MibIpnetRow2 {
+            family: AF_INET,
+            port: 0,
+            address: [0; 24],
+            interface_index: 12,
+            interface_luid: 0,
+            physical_address: [0; 32],
+            physical_address_length: 6,
+            // NlnsReachable
+            state: 5,
+            flags: 0,
+            reachability_time: 0,
+        };
+        row.address[..4].copy_from_slice(&[192, 168, 1, 20]);
+        row.physical_address[..6].copy_from_slice(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x66]);
+        let neighbor = row.neighbor().unwrap();
+        assert_eq!(neighbor.ip, "192.168.1.20".parse::<IpAddr>().unwrap());
+        assert_eq!(neighbor.mac, MacAddr::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x66]));
+        assert_eq!(neighbor.interface, "12");
+
+        row.family = AF_INET6;
+        row.address = [0; 24];
+        row.address[4..6].copy_from_slice(&[0xfe, 0x80]);
+        row.address[19] = 1;
+        assert_eq!(row.neighbor().unwrap().ip, "fe80::1".parse::<IpAddr>().unwrap());
+        // NlnsIncomplete
+        row.state = 1;
+        assert_eq!(row.neighbor(), None);
+    }
+}
@@ -0,0 +1,846 @@
+//! Machine-readable output of results.
+//!
+//! A `Record` is an ordered list of named fields which can be rendered as a human readable line,
+//! as a JSON object or as a CSV row. `Printer` writes a stream of records in one `Format`, one
+//! record per line, so that scripts can consume the output line by line. On a terminal, plain
+//! results are aligned in columns and headed by their `Status`, colored unless `NO_COLOR` is set.
+use std::env;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::iter;
+use std::str::{self, FromStr};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use error::ErrorCode;
+use {MacAddr, SendReport};
+
+/// The output format of the command line tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Human readable output.
+    Plain,
+    /// One JSON object per line.
+    Json,
+    /// Comma-separated values with a header line.
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("unknown output format '{}', expected json, csv or plain", s)),
+        }
+    }
+}
+
+/// A single value of a record field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(u64),
+    Float(f64),
+    Str(String),
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(i: u64) -> Value {
+        Value::Int(i)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(i: usize) -> Value {
+        Value::Int(i as u64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Value {
+        Value::Float(f)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::Str(s)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Value {
+        Value::Str(s.to_string())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(o: Option<T>) -> Value {
+        match o {
+            Some(v) => v.into(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => Ok(()),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Str(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Value {
+    /// Renders the value as JSON.
+    pub fn to_json(&self) -> String {
+        match *self {
+            Value::Null => "null".to_string(),
+            Value::Float(x) if !x.is_finite() => "null".to_string(),
+            Value::Str(ref s) => json_string(s),
+            ref v => v.to_string(),
+        }
+    }
+}
+
+/// An ordered list of named values, e.g. the result of waking one host.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Record {
+    fields: Vec<(String, Value)>,
+}
+
+impl Record {
+    /// Creates an empty record.
+    pub fn new() -> Record {
+        Record { fields: Vec::new() }
+    }
+
+    /// Appends a field to the record.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::output::Record;
+    ///
+    /// let record = Record::new().field("mac", "AA:FF:B0:12:34:56").field("bytes", 102u64);
+    /// assert_eq!(record.to_json(), r#"{"mac":"AA:FF:B0:12:34:56","bytes":102}"#);
+    /// ```
+    pub fn field<V: Into<Value>>(mut self, name: &str, value: V) -> Record {
+        self.fields.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Replaces the value of the field with the given name, appending the field if it is missing.
+    pub fn set<V: Into<Value>>(&mut self, name: &str, value: V) {
+        let value = value.into();
+        match self.fields.iter_mut().find(|(n, _)| n == name) {
+            Some(field) => field.1 = value,
+            None => self.fields.push((name.to_string(), value)),
+        }
+    }
+
+    /// Returns the names and values of the fields in order.
+    pub fn fields(&self) -> &[(String, Value)] {
+        &self.fields
+    }
+
+    /// Returns the value of the field with the given name.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    /// Renders the record as a single JSON object.
+    pub fn to_json(&self) -> String {
+        let fields: Vec<String> = self.fields
+            .iter()
+            .map(|(name, value)| format!("{}:{}", json_string(name), value.to_json()))
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+
+    /// Renders the field names as a CSV header line.
+    pub fn csv_header(&self) -> String {
+        let names: Vec<String> = self.fields.iter().map(|(name, _)| csv_field(name)).collect();
+        names.join(",")
+    }
+
+    /// Renders the field values as a CSV row.
+    pub fn to_csv(&self) -> String {
+        let values: Vec<String> = self.fields
+            .iter()
+            .map(|(_, value)| csv_field(&value.to_string()))
+            .collect();
+        values.join(",")
+    }
+
+    /// Renders the record as `name=value` pairs for humans, omitting empty values.
+    pub fn to_plain(&self) -> String {
+        let fields: Vec<String> = self.fields
+            .iter()
+            .filter(|(_, value)| *value != Value::Null)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        fields.join(" ")
+    }
+}
+
+impl FromStr for Record {
+    type Err = String;
+
+    /// Parses a flat JSON object like those of `Record::to_json`, e.g. the answer of a relay.
+    /// Nested objects and arrays are not supported.
+    fn from_str(s: &str) -> Result<Record, String> {
+        let mut parser = JsonParser { chars: s.trim().chars().peekable() };
+        parser.expect('{')?;
+        let mut record = Record::new();
+        if parser.eat('}') {
+            return parser.end(record);
+        }
+        loop {
+            let name = parser.string()?;
+            parser.expect(':')?;
+            let value = parser.value()?;
+            record.fields.push((name, value));
+            if parser.eat('}') {
+                return parser.end(record);
+            }
+            parser.expect(',')?;
+        }
+    }
+}
+
+/// Reads the values of a flat JSON object.
+struct JsonParser<'a> {
+    chars: iter::Peekable<str::Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    /// Consumes `c` if it is next, skipping whitespace before it.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&c).is_some()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) { Ok(()) } else { Err(format!("expected '{}'", c)) }
+    }
+
+    fn end(&mut self, record: Record) -> Result<Record, String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            None => Ok(record),
+            Some(_) => Err("unexpected characters after the object".to_string()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next().ok_or("unterminated string")? {
+                '"' => return Ok(s),
+                '\\' => {
+                    s.push(match self.chars.next().ok_or("unterminated string")? {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let hex: String = self.chars.by_ref().take(4).collect();
+                            u32::from_str_radix(&hex, 16).ok()
+                                .and_then(char::from_u32)
+                                .ok_or("invalid unicode escape")?
+                        },
+                        c => c,
+                    })
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'"') {
+            return Ok(Value::Str(self.string()?));
+        }
+        let mut token = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_alphanumeric() || "+-.".contains(*c)) {
+            token.push(c);
+        }
+        match token.as_str() {
+            "null" => Ok(Value::Null),
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => {
+                token.parse()
+                    .map(Value::Int)
+                    .or_else(|_| token.parse().map(Value::Float))
+                    .map_err(|_| format!("unsupported value '{}'", token))
+            },
+        }
+    }
+}
+
+/// Creates the record describing the result of sending a magic packet to `mac`, which took
+/// `elapsed`. The record has the same fields whether sending succeeded or failed.
+pub fn send_result(mac: MacAddr,
+                   destination: SocketAddr,
+                   result: &Result<SendReport, io::Error>,
+                   elapsed: Duration)
+                   -> Record {
+    result_record(mac, destination, Some(result), elapsed)
+}
+
+/// Creates the record of a host which was already up, so that no magic packet was sent. The
+/// record has the same fields as those of `send_result`.
+pub fn up_result(mac: MacAddr, destination: SocketAddr, elapsed: Duration) -> Record {
+    result_record(mac, destination, None, elapsed)
+}
+
+fn result_record(mac: MacAddr,
+                 destination: SocketAddr,
+                 result: Option<&Result<SendReport, io::Error>>,
+                 elapsed: Duration)
+                 -> Record {
+    let report = result.and_then(|result| result.as_ref().ok());
+    let error = result.and_then(|result| result.as_ref().err());
+    Record::new()
+        .field("mac", mac.to_string())
+        .field("target", destination.to_string())
+        .field("local", report.map(|r| r.local_addr.to_string()))
+        .field("time", report.map(|r| timestamp(r.timestamp)))
+        .field("bytes", report.map(|r| r.bytes_sent))
+        .field("verified", None::<bool>)
+        .field("boot_ms", None::<f64>)
+        .field("duration_ms", millis(elapsed))
+        .field("error", error.map(|err| err.to_string()))
+        .field("code", error.map(|err| ErrorCode::of(err).as_str()))
+}
+
+/// Sets the `verified` field of a record of `send_result`, with the code `VERIFY_TIMEOUT` if the
+/// host did not come up.
+pub fn set_verified(record: &mut Record, verified: Option<bool>) {
+    record.set("verified", verified);
+    if verified == Some(false) {
+        record.set("code", ErrorCode::VerifyTimeout.as_str());
+    }
+}
+
+/// Returns the duration in milliseconds, rounded to microseconds, as in the `duration_ms` and
+/// `boot_ms` fields.
+pub fn millis(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1e6).round() / 1e3
+}
+
+/// The outcome of waking a host, as shown on a terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Sending the magic packet failed.
+    Fail,
+    /// Sending timed out or the host did not come up in time.
+    Timeout,
+}
+
+impl Status {
+    /// Returns the status of a result record like those of `send_result` from its `error` and
+    /// `verified` fields, or `None` for other records.
+    pub fn of(record: &Record) -> Option<Status> {
+        match (record.get("error")?, record.get("verified")) {
+            (Value::Str(error), _) if error.contains("timed out") => Some(Status::Timeout),
+            (Value::Str(_), _) => Some(Status::Fail),
+            (_, Some(Value::Bool(false))) => Some(Status::Timeout),
+            _ => Some(Status::Ok),
+        }
+    }
+
+    /// Returns the label, "OK", "FAIL" or "TIMEOUT".
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Fail => "FAIL",
+            Status::Timeout => "TIMEOUT",
+        }
+    }
+
+    /// Returns the ANSI escape sequence of the color of the status: green, red or yellow.
+    fn color(self) -> &'static str {
+        match self {
+            Status::Ok => "\x1b[32m",
+            Status::Fail => "\x1b[31m",
+            Status::Timeout => "\x1b[33m",
+        }
+    }
+}
+
+/// Returns whether colors may be used on a terminal, which is unless the `NO_COLOR` environment
+/// variable is set to a non-empty value, see https://no-color.org.
+pub fn color_allowed() -> bool {
+    env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Writes records in a given format, one per line.
+///
+/// For CSV, the header line is derived from the first record written.
+pub struct Printer<W: Write> {
+    format: Format,
+    out: W,
+    header_written: bool,
+    terminal: bool,
+    color: bool,
+}
+
+impl<W: Write> Printer<W> {
+    /// Creates a printer writing to `out`.
+    pub fn new(format: Format, out: W) -> Printer<W> {
+        Printer {
+            format,
+            out,
+            header_written: false,
+            terminal: false,
+            color: false,
+        }
+    }
+
+    /// Prints plain records for reading on a terminal: headed by their status, see `Status`,
+    /// which is colored if `color` is set, and aligned in columns by `print_all`.
+    pub fn with_terminal(mut self, color: bool) -> Printer<W> {
+        self.terminal = true;
+        self.color = color;
+        self
+    }
+
+    /// Returns the format of this printer.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Writes a single record.
+    pub fn print(&mut self, record: &Record) -> io::Result<()> {
+        match self.format {
+            Format::Plain if self.terminal => {
+                let fields: Vec<String> = record.fields
+                    .iter()
+                    .filter(|(_, value)| *value != Value::Null)
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                let line = format!("{}{}", self.status(record), fields.join(" "));
+                writeln!(self.out, "{}", line.trim_end())
+            },
+            Format::Plain => writeln!(self.out, "{}", record.to_plain()),
+            Format::Json => writeln!(self.out, "{}", record.to_json()),
+            Format::Csv => {
+                if !self.header_written {
+                    writeln!(self.out, "{}", record.csv_header())?;
+                    self.header_written = true;
+                }
+                writeln!(self.out, "{}", record.to_csv())
+            }
+        }
+    }
+
+    /// Writes records, aligning the fields of plain records in columns on a terminal.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::output::{Format, Printer, Record};
+    ///
+    /// let mut printer = Printer::new(Format::Plain, Vec::new()).with_terminal(false);
+    /// printer.print_all(&[Record::new().field("host", "nas").field("error", None::<String>),
+    ///                     Record::new().field("host", "desktop").field("error", "failed")])
+    ///     .unwrap();
+    /// assert_eq!(String::from_utf8(printer.into_inner()).unwrap(),
+    ///            "OK       host=nas\nFAIL     host=desktop  error=failed\n");
+    /// ```
+    pub fn print_all(&mut self, records: &[Record]) -> io::Result<()> {
+        if self.format != Format::Plain || !self.terminal {
+            return records.iter().try_for_each(|record| self.print(record));
+        }
+        let cells = |record: &Record| -> Vec<(String, String)> {
+            record.fields
+                .iter()
+                .map(|(name, value)| match *value {
+                    Value::Null => (name.clone(), String::new()),
+                    ref value => (name.clone(), format!("{}={}", name, value)),
+                })
+                .collect()
+        };
+        // The width of every field name over all records, in the order of first appearance.
+        let mut widths: Vec<(String, usize)> = Vec::new();
+        for record in records {
+            for (name, cell) in cells(record) {
+                match widths.iter_mut().find(|(n, _)| *n == name) {
+                    Some(width) => width.1 = width.1.max(cell.len()),
+                    None => widths.push((name, cell.len())),
+                }
+            }
+        }
+        for record in records {
+            let cells = cells(record);
+            let mut line = self.status(record);
+            for (name, width) in widths.iter().filter(|&&(_, width)| width > 0) {
+                let cell = cells.iter().find(|(n, _)| n == name).map_or("", |(_, c)| c.as_str());
+                line.push_str(&format!("{:<width$}  ", cell, width = width));
+            }
+            writeln!(self.out, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Returns the status column of a record on a terminal, empty for records without status.
+    fn status(&self, record: &Record) -> String {
+        match Status::of(record) {
+            None => String::new(),
+            Some(status) if self.color => {
+                format!("{}{:<7}\x1b[0m  ", status.color(), status.label())
+            },
+            Some(status) => format!("{:<7}  ", status.label()),
+        }
+    }
+}
+
+/// Shows how many hosts of a batch are done and how many of them failed, as a bar redrawn in
+/// place on a terminal and as one line per host elsewhere.
+///
+/// #Example
+///
+/// ```
+/// use wol::output::{Progress, Status};
+///
+/// let mut progress = Progress::new(2, Vec::new());
+/// progress.update("nas", Status::Ok).unwrap();
+/// progress.update("desktop", Status::Fail).unwrap();
+/// assert_eq!(String::from_utf8(progress.into_inner()).unwrap(),
+///            "[1/2] nas: OK\n[2/2] desktop: FAIL\n");
+/// ```
+pub struct Progress<W: Write> {
+    out: W,
+    total: usize,
+    ok: usize,
+    failed: usize,
+    terminal: bool,
+}
+
+impl<W: Write> Progress<W> {
+    /// The number of characters of the bar on a terminal.
+    const WIDTH: usize = 30;
+
+    /// Creates a progress display for `total` hosts writing to `out`.
+    pub fn new(total: usize, out: W) -> Progress<W> {
+        Progress {
+            out,
+            total,
+            ok: 0,
+            failed: 0,
+            terminal: false,
+        }
+    }
+
+    /// Redraws a bar in place instead of writing a line per host, for terminals.
+    pub fn with_terminal(mut self) -> Progress<W> {
+        self.terminal = true;
+        self
+    }
+
+    /// Counts the host with the given name as done with `status`.
+    pub fn update(&mut self, name: &str, status: Status) -> io::Result<()> {
+        match status {
+            Status::Ok => self.ok += 1,
+            Status::Fail | Status::Timeout => self.failed += 1,
+        }
+        let done = self.ok + self.failed;
+        if !self.terminal {
+            return writeln!(self.out, "[{}/{}] {}: {}", done, self.total, name, status.label());
+        }
+        let filled = Self::WIDTH * done / self.total.max(1);
+        write!(self.out,
+               "\r[{}{}] {}/{}  {} ok  {} failed",
+               "#".repeat(filled),
+               ".".repeat(Self::WIDTH - filled.min(Self::WIDTH)),
+               done,
+               self.total,
+               self.ok,
+               self.failed)?;
+        self.out.flush()
+    }
+
+    /// Removes the bar from the terminal, so that the results can be printed in its place.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.terminal {
+            write!(self.out, "\r\x1b[K")?;
+        }
+        self.out.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Formats a point in time as an RFC 3339 timestamp in UTC, e.g. `2017-03-01T12:30:00.250Z`.
+pub fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+    // Converts days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60,
+            since_epoch.subsec_millis())
+}
+
+/// Parses a timestamp of `timestamp` back, returning `None` if it is not in that format.
+pub fn parse_timestamp(s: &str) -> Option<SystemTime> {
+    let b = s.as_bytes();
+    let separators = [(4, b'-'), (7, b'-'), (10, b'T'), (13, b':'), (16, b':'), (19, b'.'),
+                      (23, b'Z')];
+    if b.len() != 24 || separators.iter().any(|&(i, c)| b[i] != c) {
+        return None;
+    }
+    let number = |range: ::std::ops::Range<usize>| {
+        b[range].iter().try_fold(0, |n: i64, &c| {
+            if c.is_ascii_digit() { Some(n * 10 + (c - b'0') as i64) } else { None }
+        })
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 ||
+       minute > 59 || second > 59 {
+        return None;
+    }
+    // The inverse of `timestamp`, see
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+    let millis = number(20..23)?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs as u64) + Duration::from_millis(millis as u64))
+}
+
+/// Formats bytes as a hex dump with 16 bytes per line, each line starting with the offset and
+/// ending with the printable ASCII characters.
+///
+/// #Example
+///
+/// ```
+/// assert_eq!(wol::output::hexdump(b"\xFF\xFFwol"),
+///            "0000  ff ff 77 6f 6c                                   |..wol|\n");
+/// ```
+pub fn hexdump(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, line) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = line.iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        out.push_str(&format!("{:04x}  {:<48} |{}|\n", i * 16, hex.join(" "), ascii));
+    }
+    out
+}
+
+/// Quotes and escapes a string for JSON.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quotes a CSV field if it contains separators, quotes or line breaks.
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') || s.contains('\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+
+    use super::{send_result, set_verified, up_result, Format, Printer, Progress, Record, Status,
+                Value};
+    use MacAddr;
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("json".parse(), Ok(Format::Json));
+        assert_eq!("csv".parse(), Ok(Format::Csv));
+        assert_eq!("plain".parse(), Ok(Format::Plain));
+        assert!("xml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn test_timestamp() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        assert_eq!(super::timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(super::timestamp(UNIX_EPOCH + Duration::from_millis(1_488_371_400_250)),
+                   "2017-03-01T12:30:00.250Z");
+        assert_eq!(super::timestamp(UNIX_EPOCH + Duration::from_secs(951_825_600)),
+                   "2000-02-29T12:00:00.000Z");
+        for millis in &[0, 951_825_600_000, 1_488_371_400_250, 4_102_444_799_999] {
+            let time = UNIX_EPOCH + Duration::from_millis(*millis);
+            assert_eq!(super::parse_timestamp(&super::timestamp(time)), Some(time));
+        }
+        assert_eq!(super::parse_timestamp("2017-03-01 12:30:00.250Z"), None);
+        assert_eq!(super::parse_timestamp("2017-13-01T12:30:00.250Z"), None);
+        assert_eq!(super::parse_timestamp("2017-03-01T12:30:00.25Z"), None);
+    }
+
+    #[test]
+    fn test_json_escaping() {
+        let record = Record::new().field("a\"b", "x\\y\n").field("none", None::<u64>);
+        assert_eq!(record.to_json(), r#"{"a\"b":"x\\y\n","none":null}"#);
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let record = Record::new()
+            .field("mac", "AA:FF:B0:12:34:56")
+            .field("bytes", 102u64)
+            .field("verified", true)
+            .field("boot_ms", 1234.5)
+            .field("error", "a \"quoted\"\n\u{1}")
+            .field("local", None::<String>);
+        assert_eq!(record.to_json().parse(), Ok(record));
+        assert_eq!(" { \"a\" : -1 } ".parse(), Ok(Record::new().field("a", -1.0)));
+        assert_eq!("{}".parse(), Ok(Record::new()));
+        assert_eq!(r#"{"a":"\u00e9"}"#.parse(), Ok(Record::new().field("a", "\u{e9}")));
+        assert!("{\"a\":[1]}".parse::<Record>().is_err());
+        assert!("{\"a\":1".parse::<Record>().is_err());
+        assert!("{\"a\":1} x".parse::<Record>().is_err());
+        assert!("[]".parse::<Record>().is_err());
+    }
+
+    #[test]
+    fn test_csv_printer() {
+        let mut out = Vec::new();
+        {
+            let mut printer = Printer::new(Format::Csv, &mut out);
+            printer.print(&Record::new().field("mac", "00:01:02:03:04:05").field("error", "a, b"))
+                .unwrap();
+            printer.print(&Record::new().field("mac", "00:01:02:03:04:06").field("error", ""))
+                .unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "mac,error\n00:01:02:03:04:05,\"a, b\"\n00:01:02:03:04:06,\n");
+    }
+
+    #[test]
+    fn test_status() {
+        let ok = Record::new().field("verified", None::<bool>).field("error", None::<String>);
+        assert_eq!(Status::of(&ok), Some(Status::Ok));
+        let down = Record::new().field("verified", false).field("error", None::<String>);
+        assert_eq!(Status::of(&down), Some(Status::Timeout));
+        let failed = Record::new().field("error", "sending to 10.0.0.255:9 timed out");
+        assert_eq!(Status::of(&failed), Some(Status::Timeout));
+        assert_eq!(Status::of(&Record::new().field("error", "no route")), Some(Status::Fail));
+        assert_eq!(Status::of(&Record::new().field("mac", "00:01:02:03:04:05")), None);
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let mac = MacAddr::new([0, 1, 2, 3, 4, 5]);
+        let target = "10.0.0.255:9".parse().unwrap();
+        let elapsed = Duration::from_millis(1);
+        let failed = Err(io::Error::from(io::ErrorKind::AddrNotAvailable));
+        let record = send_result(mac, target, &failed, elapsed);
+        assert_eq!(record.get("code"), Some(&Value::Str("BIND_FAILED".to_string())));
+        let mut record = up_result(mac, target, elapsed);
+        assert_eq!(record.get("code"), Some(&Value::Null));
+        set_verified(&mut record, Some(false));
+        assert_eq!(record.get("code"), Some(&Value::Str("VERIFY_TIMEOUT".to_string())));
+    }
+
+    #[test]
+    fn test_progress() {
+        let mut progress = Progress::new(4, Vec::new()).with_terminal();
+        progress.update("nas", Status::Ok).unwrap();
+        progress.update("desktop", Status::Timeout).unwrap();
+        progress.finish().unwrap();
+        let out = String::from_utf8(progress.into_inner()).unwrap();
+        assert_eq!(out,
+                   format!("\r[{}{}] 1/4  1 ok  0 failed\r[{}{}] 2/4  1 ok  1 failed\r\x1b[K",
+                           "#".repeat(7),
+                           ".".repeat(23),
+                           "#".repeat(15),
+                           ".".repeat(15)));
+    }
+
+    #[test]
+    fn test_terminal_printer() {
+        let mut printer = Printer::new(Format::Plain, Vec::new()).with_terminal(true);
+        printer.print(&Record::new().field("mac", "00:01:02:03:04:05").field("error", "no route"))
+            .unwrap();
+        printer.print(&Record::new().field("name", "nas")).unwrap();
+        assert_eq!(String::from_utf8(printer.into_inner()).unwrap(),
+                   "\x1b[31mFAIL   \x1b[0m  mac=00:01:02:03:04:05 error=no route\nname=nas\n");
+        // Not on a terminal, the output stays the same for scripts.
+        let mut printer = Printer::new(Format::Plain, Vec::new());
+        printer.print_all(&[Record::new().field("error", "no route")]).unwrap();
+        assert_eq!(String::from_utf8(printer.into_inner()).unwrap(), "error=no route\n");
+    }
+}
@@ -0,0 +1,621 @@
+//! Construction and parsing of magic packets.
+//!
+//! This module does not need the standard library nor an allocator, see the `std` feature:
+//! `MagicPacket::payload` builds the packet on the stack, ready to be handed to any network
+//! stack.
+use core::fmt;
+use core::ops::Deref;
+
+use mac::{MacAddr, ParseError};
+use redact::Secret;
+
+/// The length of a magic packet without SecureOn password.
+pub const PACKET_LEN: usize = PacketFormat::ETHERNET.packet_len();
+
+/// The length of a magic packet with SecureOn password.
+pub const PACKET_WITH_PASSWORD_LEN: usize = PACKET_LEN + 6;
+
+/// The length of the longest payload `MagicPacket::decode` and `MagicPacket::find` look at, that
+/// of the largest UDP datagram over IPv4.
+pub const MAX_PAYLOAD_LEN: usize = 65_507;
+
+/// Why bytes are no magic packet, see `MagicPacket::decode` and `MagicPacket::find`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecodeError {
+    /// Shorter than a magic packet, with the number of bytes.
+    Truncated(usize),
+    /// Longer than `MAX_PAYLOAD_LEN`, with the number of bytes.
+    Oversized(usize),
+    /// Neither the length of a packet with nor without password, with the number of bytes.
+    Length(usize),
+    /// The synchronization stream is not six bytes 0xFF.
+    Sync,
+    /// The repetition of the MAC address with this index, from 1 to 15, differs from the first.
+    Repetition(usize),
+    /// No magic packet anywhere in the bytes.
+    NotFound,
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for DecodeError {}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Truncated(len) => {
+                write!(f, "{} bytes are too short for a magic packet of {}", len, PACKET_LEN)
+            },
+            DecodeError::Oversized(len) => {
+                write!(f, "{} bytes are more than the {} looked at", len, MAX_PAYLOAD_LEN)
+            },
+            DecodeError::Length(len) => {
+                write!(f,
+                       "{} bytes are neither the {} of a magic packet nor the {} with password",
+                       len,
+                       PACKET_LEN,
+                       PACKET_WITH_PASSWORD_LEN)
+            },
+            DecodeError::Sync => f.write_str("the synchronization stream is not 6 bytes 0xFF"),
+            DecodeError::Repetition(i) => {
+                write!(f, "repetition {} of the MAC address differs from the first", i + 1)
+            },
+            DecodeError::NotFound => f.write_str("no magic packet found"),
+        }
+    }
+}
+
+/// The layout of a magic packet: a synchronization stream of `sync_len` bytes 0xFF followed by
+/// the hardware address of `addr_len` bytes, repeated `repetitions` times.
+///
+/// `MagicPacket` builds packets for Ethernet, with six bytes each of synchronization stream and
+/// MAC address. Other layouts are for network cards with longer hardware addresses, e.g. the 20
+/// bytes of IP over InfiniBand, whose payload is built with `write` and sent as is, e.g. with
+/// `WolClient::send_payload`.
+///
+/// #Example
+///
+/// ```
+/// use wol::packet::PacketFormat;
+///
+/// let guid = [0x80, 0, 0x02, 0x08, 0xFE, 0x80, 0, 0, 0, 0, 0, 0, 0, 0x02, 0xC9, 0x03, 0, 0x0A,
+///             0x5D, 0x21];
+/// let mut buf = [0; 512];
+/// let len = PacketFormat::INFINIBAND.write(&guid, &mut buf).unwrap();
+/// assert_eq!(len, 6 + 16 * 20);
+/// assert_eq!(PacketFormat::INFINIBAND.parse(&buf[..len]), Some(&guid[..]));
+/// assert_eq!(PacketFormat::ETHERNET.write(&guid, &mut buf), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PacketFormat {
+    /// The number of bytes 0xFF before the first repetition of the address.
+    pub sync_len: usize,
+    /// The length of the hardware address.
+    pub addr_len: usize,
+    /// How often the address is repeated.
+    pub repetitions: usize,
+}
+
+impl PacketFormat {
+    /// The layout of magic packets for Ethernet and Wi-Fi, with MAC addresses of six bytes.
+    pub const ETHERNET: PacketFormat = PacketFormat::new(6, 16);
+
+    /// The layout of magic packets for IP over InfiniBand, with hardware addresses of 20 bytes.
+    pub const INFINIBAND: PacketFormat = PacketFormat::new(20, 16);
+
+    /// Creates the layout for hardware addresses of `addr_len` bytes, repeated `repetitions`
+    /// times after a synchronization stream of six bytes.
+    pub const fn new(addr_len: usize, repetitions: usize) -> PacketFormat {
+        PacketFormat {
+            sync_len: 6,
+            addr_len,
+            repetitions,
+        }
+    }
+
+    /// Returns the length of the packet without password.
+    pub const fn packet_len(&self) -> usize {
+        self.sync_len + self.addr_len * self.repetitions
+    }
+
+    /// Writes the packet for the hardware address `addr` to the start of `buf` and returns its
+    /// length, or `None` if `addr` is not `addr_len` bytes long or the packet does not fit into
+    /// `buf`.
+    pub fn write(&self, addr: &[u8], buf: &mut [u8]) -> Option<usize> {
+        let len = self.packet_len();
+        if addr.len() != self.addr_len || buf.len() < len {
+            return None;
+        }
+        for byte in &mut buf[..self.sync_len] {
+            *byte = 0xFF;
+        }
+        if self.addr_len > 0 {
+            for chunk in buf[self.sync_len..len].chunks_mut(self.addr_len) {
+                chunk.copy_from_slice(addr);
+            }
+        }
+        Some(len)
+    }
+
+    /// Returns the hardware address of a packet of this layout without password, or `None` if
+    /// `bytes` are not such a packet.
+    pub fn parse<'a>(&self, bytes: &'a [u8]) -> Option<&'a [u8]> {
+        if bytes.len() != self.packet_len() || self.addr_len == 0 || self.repetitions == 0 {
+            return None;
+        }
+        let (sync, addrs) = bytes.split_at(self.sync_len);
+        if sync.iter().any(|&byte| byte != 0xFF) {
+            return None;
+        }
+        let addr = &addrs[..self.addr_len];
+        if addrs.chunks(self.addr_len).all(|chunk| chunk == addr) {
+            Some(addr)
+        } else {
+            None
+        }
+    }
+}
+
+/// A magic packet: a synchronization stream of six bytes 0xFF, the MAC address of the target
+/// repeated 16 times and an optional SecureOn password of six bytes.
+///
+/// The `Display` implementation summarizes the packet on one line, while `hexdump` shows each
+/// section on its own line. Both redact the password unless secrets are shown, see
+/// `redact::set_show_secrets`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MagicPacket {
+    mac: MacAddr,
+    password: Option<[u8; 6]>,
+}
+
+impl MagicPacket {
+    /// Creates a magic packet for the given MAC address.
+    pub fn new(mac: MacAddr) -> MagicPacket {
+        MagicPacket {
+            mac,
+            password: None,
+        }
+    }
+
+    /// Appends a SecureOn password to the packet.
+    pub fn with_password(mut self, password: [u8; 6]) -> MagicPacket {
+        self.password = Some(password);
+        self
+    }
+
+    /// Parses the payload of a magic packet as received, e.g. by a listener. Returns `None`
+    /// unless it has the length of a magic packet, with or without password, and consists of the
+    /// synchronization stream followed by 16 repetitions of one MAC address.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::{MacAddr, MagicPacket};
+    ///
+    /// let packet = MagicPacket::new(MacAddr::new([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]));
+    /// assert_eq!(MagicPacket::parse(&packet.payload()), Some(packet));
+    /// assert_eq!(MagicPacket::parse(&[0xFF; 6]), None);
+    /// ```
+    pub fn parse(bytes: &[u8]) -> Option<MagicPacket> {
+        MagicPacket::decode(bytes).ok()
+    }
+
+    /// Parses the payload of a magic packet like `parse`, but tells why the bytes are none.
+    /// Never panics, whatever the bytes, so that untrusted input can be handed over as
+    /// received, e.g. by a relay.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::packet::DecodeError;
+    /// use wol::{MacAddr, MagicPacket};
+    ///
+    /// let mut bytes = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5])).to_bytes();
+    /// assert!(MagicPacket::decode(&bytes).is_ok());
+    /// assert_eq!(MagicPacket::decode(&bytes[..50]), Err(DecodeError::Truncated(50)));
+    /// bytes[40] = 0;
+    /// assert_eq!(MagicPacket::decode(&bytes), Err(DecodeError::Repetition(5)));
+    /// ```
+    pub fn decode(bytes: &[u8]) -> Result<MagicPacket, DecodeError> {
+        match bytes.len() {
+            len if len < PACKET_LEN => return Err(DecodeError::Truncated(len)),
+            len if len > MAX_PAYLOAD_LEN => return Err(DecodeError::Oversized(len)),
+            PACKET_LEN | PACKET_WITH_PASSWORD_LEN => {},
+            len => return Err(DecodeError::Length(len)),
+        }
+        let (packet, password) = bytes.split_at(PACKET_LEN);
+        let (sync, addrs) = packet.split_at(6);
+        if sync.iter().any(|&byte| byte != 0xFF) {
+            return Err(DecodeError::Sync);
+        }
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&addrs[..6]);
+        if let Some(i) = addrs.chunks(6).position(|chunk| chunk != mac) {
+            return Err(DecodeError::Repetition(i));
+        }
+        let packet = MagicPacket::new(MacAddr::new(mac));
+        match password.len() {
+            6 => {
+                let mut password_bytes = [0; 6];
+                password_bytes.copy_from_slice(password);
+                Ok(packet.with_password(password_bytes))
+            },
+            _ => Ok(packet),
+        }
+    }
+
+    /// Finds a magic packet anywhere in a payload, e.g. behind the header of another protocol
+    /// or followed by padding, as network cards do, and returns its offset and the packet. Six
+    /// bytes right at the end of the payload after the packet are taken as its password. Never
+    /// panics, like `decode`.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::packet::DecodeError;
+    /// use wol::{MacAddr, MagicPacket};
+    ///
+    /// let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+    /// let mut bytes = b"garbage".to_vec();
+    /// bytes.extend_from_slice(&packet.to_bytes());
+    /// bytes.extend_from_slice(&[0; 20]);
+    /// assert_eq!(MagicPacket::find(&bytes), Ok((7, packet)));
+    /// assert_eq!(MagicPacket::find(&[0; 200]), Err(DecodeError::NotFound));
+    /// ```
+    pub fn find(bytes: &[u8]) -> Result<(usize, MagicPacket), DecodeError> {
+        match bytes.len() {
+            len if len < PACKET_LEN => return Err(DecodeError::Truncated(len)),
+            len if len > MAX_PAYLOAD_LEN => return Err(DecodeError::Oversized(len)),
+            _ => {},
+        }
+        for offset in 0..=bytes.len() - PACKET_LEN {
+            let rest = &bytes[offset..];
+            let len = if rest.len() == PACKET_WITH_PASSWORD_LEN { rest.len() } else { PACKET_LEN };
+            if let Ok(packet) = MagicPacket::decode(&rest[..len]) {
+                return Ok((offset, packet));
+            }
+        }
+        Err(DecodeError::NotFound)
+    }
+
+    /// Returns the MAC address of the target.
+    pub fn mac(&self) -> MacAddr {
+        self.mac
+    }
+
+    /// Returns the SecureOn password, if any.
+    pub fn password(&self) -> Option<[u8; 6]> {
+        self.password
+    }
+
+    /// Builds the bytes of the packet without allocating.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::{MacAddr, MagicPacket};
+    ///
+    /// let packet = MagicPacket::new(MacAddr::new([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]));
+    /// let payload = packet.payload();
+    /// assert_eq!(payload.len(), 102);
+    /// assert_eq!(&payload[..8], &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xAA, 0xFF]);
+    /// ```
+    pub fn payload(&self) -> Payload {
+        let mut bytes = [0; PACKET_WITH_PASSWORD_LEN];
+        let len = self.write_to(&mut bytes);
+        Payload { bytes, len }
+    }
+
+    /// Writes the bytes of the packet to the start of `buf` and returns their number, so that
+    /// one buffer can be reused for many packets.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::packet::PACKET_WITH_PASSWORD_LEN;
+    /// use wol::{MacAddr, MagicPacket};
+    ///
+    /// let mut buf = [0; PACKET_WITH_PASSWORD_LEN];
+    /// for last in 1..=3 {
+    ///     let packet = MagicPacket::new(MacAddr::new([0, 0, 0, 0, 0, last]));
+    ///     let len = packet.write_to(&mut buf);
+    ///     assert_eq!(&buf[..len], &packet.payload()[..]);
+    /// }
+    /// ```
+    pub fn write_to(&self, buf: &mut [u8; PACKET_WITH_PASSWORD_LEN]) -> usize {
+        PacketFormat::ETHERNET.write(&self.mac.octets(), buf);
+        match self.password {
+            Some(password) => {
+                buf[PACKET_LEN..].copy_from_slice(&password);
+                PACKET_WITH_PASSWORD_LEN
+            },
+            None => PACKET_LEN,
+        }
+    }
+
+    /// Returns the bytes of the packet, 102 bytes or 108 bytes with a password.
+    #[cfg(feature = "std")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.payload().to_vec()
+    }
+
+    /// Formats the packet as annotated hex dump with one line per section.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::{MacAddr, MagicPacket};
+    ///
+    /// let packet = MagicPacket::new(MacAddr::new([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]));
+    /// let dump = packet.hexdump();
+    /// assert!(dump.starts_with("0000  sync      ff ff ff ff ff ff\n\
+    ///                           0006  mac  1    aa ff b0 12 34 56\n"));
+    /// assert!(dump.ends_with("0060  mac 16    aa ff b0 12 34 56\n"));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn hexdump(&self) -> String {
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+        }
+
+        let format = PacketFormat::ETHERNET;
+        let mac = hex(&self.mac.octets());
+        let mut out = format!("{:04x}  sync      {}\n", 0, hex(&[0xFF; 6]));
+        for i in 1..=format.repetitions {
+            let offset = format.sync_len + (i - 1) * format.addr_len;
+            out.push_str(&format!("{:04x}  mac {:2}    {}\n", offset, i, mac));
+        }
+        if let Some(password) = self.password {
+            out.push_str(&format!("{:04x}  password  {}\n", PACKET_LEN, Secret(hex(&password))));
+        }
+        out
+    }
+}
+
+/// Creates the payload for the magic packet: six bytes of 0xFF followed by the MAC address
+/// repeated 16 times. As a `const fn`, it can compute packets at compile time, e.g. for static
+/// data of firmware, see also `magic_packet!`.
+///
+/// #Example
+///
+/// ```
+/// const PAYLOAD: [u8; 102] = wol::packet::create_payload([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]);
+/// assert_eq!(&PAYLOAD[..8], &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xAA, 0xFF]);
+/// ```
+pub const fn create_payload(mac: [u8; 6]) -> [u8; PACKET_LEN] {
+    let sync_len = PacketFormat::ETHERNET.sync_len;
+    let mut buf = [0xFF; PACKET_LEN];
+    let mut i = sync_len;
+    while i < PACKET_LEN {
+        buf[i] = mac[(i - sync_len) % 6];
+        i += 1;
+    }
+    buf
+}
+
+/// Computes the payload of a magic packet at compile time from the six octets of a MAC address,
+/// given as an array or one by one.
+///
+/// #Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate wol;
+///
+/// static WAKE_NAS: [u8; 102] = magic_packet!(0x00, 0x11, 0x22, 0x33, 0x44, 0x66);
+///
+/// fn main() {
+///     assert_eq!(WAKE_NAS, magic_packet!([0x00, 0x11, 0x22, 0x33, 0x44, 0x66]));
+/// }
+/// ```
+#[macro_export]
+macro_rules! magic_packet {
+    ($mac:expr) => {
+        $crate::packet::create_payload($mac)
+    };
+    ($a:expr, $b:expr, $c:expr, $d:expr, $e:expr, $f:expr) => {
+        $crate::packet::create_payload([$a, $b, $c, $d, $e, $f])
+    };
+}
+
+/// Parses a SecureOn password written like a MAC address with any of its separators, i.e.
+/// `01:02:03:04:05:06`, `01-02-03-04-05-06`, `0102.0304.0506` or `010203040506`.
+///
+/// #Example
+///
+/// ```
+/// use wol::packet::parse_password;
+///
+/// assert_eq!(parse_password("0102.0304.05ab"), Ok([0x01, 0x02, 0x03, 0x04, 0x05, 0xAB]));
+/// ```
+pub fn parse_password(s: &str) -> Result<[u8; 6], ParseError> {
+    let (separator, group_len) = if s.contains(':') {
+        (':', 2)
+    } else if s.contains('-') {
+        ('-', 2)
+    } else if s.contains('.') {
+        ('.', 4)
+    } else {
+        (' ', 12)
+    };
+    let mut password = [0; 6];
+    let mut len = 0;
+    for group in s.split(separator) {
+        if group.len() != group_len {
+            return Err(ParseError::Length);
+        }
+        for i in (0..group_len).step_by(2) {
+            let octet = group.get(i..i + 2).ok_or(ParseError::Length)?;
+            if len == password.len() {
+                return Err(ParseError::Length);
+            }
+            password[len] = u8::from_str_radix(octet, 16)?;
+            len += 1;
+        }
+    }
+    if len == password.len() {
+        Ok(password)
+    } else {
+        Err(ParseError::Length)
+    }
+}
+
+impl fmt::Display for MagicPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FF:FF:FF:FF:FF:FF + 16 x {}", self.mac)?;
+        if let Some(password) = self.password {
+            write!(f, " + password {}", Secret(MacAddr::new(password)))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MagicPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MagicPacket")
+            .field("sync", &"FF:FF:FF:FF:FF:FF")
+            .field("mac", &self.mac)
+            .field("repetitions", &16)
+            .field("password", &self.password.map(|password| Secret(MacAddr::new(password))))
+            .finish()
+    }
+}
+
+/// The bytes of a magic packet, stored on the stack.
+#[derive(Clone, Copy)]
+pub struct Payload {
+    bytes: [u8; PACKET_WITH_PASSWORD_LEN],
+    len: usize,
+}
+
+impl Deref for Payload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for Payload {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use mac::{MacAddr, ParseError};
+    use super::{create_payload, parse_password, DecodeError, MagicPacket, PacketFormat,
+                MAX_PAYLOAD_LEN};
+
+    #[test]
+    fn test_create_payload() {
+        const PAYLOAD: [u8; 102] = create_payload([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        assert_eq!(&PAYLOAD[..6], &[0xff; 6]);
+        assert!(PAYLOAD[6..].chunks(6).all(|chunk| chunk == [0x00, 0x01, 0x02, 0x03, 0x04, 0x05]));
+        let packet = MagicPacket::new(MacAddr::new([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]));
+        assert_eq!(&PAYLOAD[..], &packet.payload()[..]);
+        assert_eq!(magic_packet!(0x00, 0x01, 0x02, 0x03, 0x04, 0x05), PAYLOAD);
+    }
+
+    #[test]
+    fn test_magic_packet_with_password() {
+        let mac = MacAddr::new([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let packet = MagicPacket::new(mac).with_password([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]);
+        let payload = packet.payload();
+        assert_eq!(payload.len(), 108);
+        assert_eq!(&payload[96..], &[0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
+                                     0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]);
+        assert_eq!(packet.to_string(),
+                   "FF:FF:FF:FF:FF:FF + 16 x 00:01:02:03:04:05 + password ••••");
+        assert!(packet.hexdump().ends_with("0066  password  ••••\n"));
+        assert!(format!("{:?}", packet).ends_with("password: Some(••••) }"));
+        assert_eq!(MagicPacket::parse(&payload), Some(packet));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let mut bytes = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5])).to_bytes();
+        bytes[100] = 0xFF;
+        assert_eq!(MagicPacket::parse(&bytes), None);
+        assert_eq!(MagicPacket::parse(&bytes[..101]), None);
+        assert_eq!(MagicPacket::parse(&[0; 102]), None);
+        assert_eq!(MagicPacket::decode(&bytes), Err(DecodeError::Repetition(15)));
+        assert_eq!(MagicPacket::decode(&[0; 102]), Err(DecodeError::Sync));
+        assert_eq!(MagicPacket::decode(&[0xFF; 105]), Err(DecodeError::Length(105)));
+        assert_eq!(MagicPacket::decode(&[]), Err(DecodeError::Truncated(0)));
+        assert_eq!(MagicPacket::find(&[0; MAX_PAYLOAD_LEN + 1]),
+                   Err(DecodeError::Oversized(MAX_PAYLOAD_LEN + 1)));
+        assert_eq!(DecodeError::Repetition(15).to_string(),
+                   "repetition 16 of the MAC address differs from the first");
+    }
+
+    #[test]
+    fn test_find() {
+        let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+        // A run of more than six bytes 0xFF before the packet.
+        let mut bytes = [0xFF; 4].to_vec();
+        bytes.extend_from_slice(&packet.with_password([9; 6]).to_bytes());
+        assert_eq!(MagicPacket::find(&bytes), Ok((4, packet.with_password([9; 6]))));
+        bytes.push(0);
+        assert_eq!(MagicPacket::find(&bytes), Ok((4, packet)));
+        assert_eq!(MagicPacket::find(&bytes[..105]), Err(DecodeError::NotFound));
+    }
+
+    #[test]
+    fn test_decode_garbage() {
+        // Pseudo-random bytes of all lengths around those of packets, with the start of a
+        // packet, must be rejected without panicking.
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut buf = [0u8; 256];
+        for len in 0..buf.len() {
+            for byte in buf.iter_mut() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                *byte = state as u8;
+            }
+            buf[..6.min(len)].copy_from_slice(&[0xFF; 6][..6.min(len)]);
+            assert!(MagicPacket::decode(&buf[..len]).is_err());
+            assert!(MagicPacket::find(&buf[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_packet_format() {
+        let format = PacketFormat::new(8, 4);
+        let mut buf = [0; 64];
+        assert_eq!(format.write(&[1; 8], &mut buf), Some(6 + 4 * 8));
+        assert_eq!(&buf[..6], &[0xFF; 6]);
+        assert!(buf[6..38].iter().all(|&byte| byte == 1));
+        assert_eq!(format.parse(&buf[..38]), Some(&[1; 8][..]));
+        assert_eq!(format.parse(&buf[..37]), None);
+        buf[37] = 2;
+        assert_eq!(format.parse(&buf[..38]), None);
+        assert_eq!(format.write(&[1; 6], &mut buf), None);
+        assert_eq!(PacketFormat::new(20, 16).write(&[1; 20], &mut buf), None);
+        let mac = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05];
+        let mut payload = [0; 102];
+        assert_eq!(PacketFormat::ETHERNET.write(&mac, &mut payload), Some(102));
+        assert_eq!(payload, create_payload(mac));
+        assert_eq!(PacketFormat::INFINIBAND.packet_len(), 326);
+    }
+
+    #[test]
+    fn test_parse_password() {
+        let password = [0x01, 0x02, 0x03, 0x04, 0x05, 0xAB];
+        for s in &["01:02:03:04:05:ab", "01-02-03-04-05-AB", "0102.0304.05ab", "0102030405ab"] {
+            assert_eq!(parse_password(s), Ok(password));
+        }
+        for s in &["01:02:03:04:05", "01:02:03:04:05:06:07", "1:2:3:4:5:6", "0102.030405ab",
+                   "0102030405", "01-02-03:04-05-06", "0102030405äb"] {
+            assert_eq!(parse_password(s), Err(ParseError::Length), "{}", s);
+        }
+        assert!(matches!(parse_password("01:02:03:04:05:xy"), Err(ParseError::Number(_))));
+    }
+}
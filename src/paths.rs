@@ -0,0 +1,233 @@
+//! The locations of the files of wol, following the conventions of each platform:
+//!
+//! - Linux and other Unix systems: the configuration and hosts file in `$XDG_CONFIG_HOME/wol`,
+//!   by default `~/.config/wol`, and the audit log, wake history and last-known addresses in
+//!   `$XDG_STATE_HOME/wol`, by default `~/.local/state/wol`.
+//! - macOS: all of them in `~/Library/Application Support/wol`.
+//! - Windows: the configuration and hosts file in `%APPDATA%\wol` and the audit log, wake
+//!   history and last-known addresses in `%LOCALAPPDATA%\wol`.
+//!
+//! The configuration of the system is read first, from `/etc/wol.toml` or
+//! `%ProgramData%\wol\config.toml` on Windows.
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The name of the configuration file in the configuration directory.
+pub const CONFIG_FILE: &str = "config.toml";
+/// The name of the hosts file in the configuration directory.
+pub const HOSTS_FILE: &str = "hosts.toml";
+/// The name of the audit log in the state directory.
+pub const AUDIT_LOG: &str = "audit.log";
+/// The name of the wake history in the state directory.
+pub const HISTORY_FILE: &str = "history.jsonl";
+/// The name of the addresses hosts were last seen at in the state directory.
+pub const SEEN_FILE: &str = "seen.jsonl";
+
+/// The conventions of a platform for the locations of files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// The XDG base directories of Linux and other Unix systems.
+    Xdg,
+    /// The application support directory of macOS.
+    MacOs,
+    /// The application data directories of Windows.
+    Windows,
+}
+
+impl Platform {
+    /// Returns the platform wol was built for.
+    pub fn current() -> Platform {
+        if cfg!(windows) {
+            Platform::Windows
+        } else if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else {
+            Platform::Xdg
+        }
+    }
+}
+
+/// The locations of the files of wol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    system_dir: PathBuf,
+    system_config: PathBuf,
+    config_dir: Option<PathBuf>,
+    state_dir: Option<PathBuf>,
+}
+
+impl Paths {
+    /// Returns the locations on this platform according to the environment.
+    ///
+    /// Earlier versions of wol used `~/.config/wol` on every platform, which is still used if
+    /// it exists and the directory of the platform does not.
+    pub fn new() -> Paths {
+        let paths = Paths::from_vars(Platform::current(), |name| env::var_os(name));
+        let legacy = match env::var_os("HOME") {
+            Some(home) => Path::new(&home).join(".config").join("wol"),
+            None => return paths,
+        };
+        match paths.config_dir {
+            Some(ref dir) if dir != &legacy && !dir.exists() && legacy.is_dir() => {
+                paths.with_config_dir(legacy)
+            },
+            _ => paths,
+        }
+    }
+
+    /// Returns the locations on the given platform, looking up environment variables with
+    /// `var`. The user's directories are unknown without the variables they derive from.
+    pub fn from_vars<F>(platform: Platform, var: F) -> Paths
+        where F: Fn(&str) -> Option<OsString>
+    {
+        let home = var("HOME").map(PathBuf::from);
+        // The XDG specification asks to ignore relative paths.
+        let xdg = |name: &str, default: &str| {
+            match var(name).map(PathBuf::from).filter(|dir| dir.is_absolute()) {
+                Some(dir) => Some(dir),
+                None => home.as_ref().map(|home| home.join(default)),
+            }
+        };
+        let wol = |dir: Option<PathBuf>| dir.map(|dir| dir.join("wol"));
+        match platform {
+            Platform::Xdg => {
+                Paths {
+                    system_dir: PathBuf::from("/etc/wol"),
+                    system_config: PathBuf::from("/etc/wol.toml"),
+                    config_dir: wol(xdg("XDG_CONFIG_HOME", ".config")),
+                    state_dir: wol(xdg("XDG_STATE_HOME", ".local/state")),
+                }
+            },
+            Platform::MacOs => {
+                let dir = wol(home.map(|home| home.join("Library").join("Application Support")));
+                Paths {
+                    system_dir: PathBuf::from("/etc/wol"),
+                    system_config: PathBuf::from("/etc/wol.toml"),
+                    config_dir: dir.clone(),
+                    state_dir: dir,
+                }
+            },
+            Platform::Windows => {
+                let program_data = var("ProgramData").unwrap_or_else(|| "C:\\ProgramData".into());
+                let system_dir = Path::new(&program_data).join("wol");
+                let app_data = var("APPDATA").map(PathBuf::from);
+                Paths {
+                    system_config: system_dir.join(CONFIG_FILE),
+                    system_dir,
+                    config_dir: wol(app_data.clone()),
+                    state_dir: wol(var("LOCALAPPDATA").map(PathBuf::from).or(app_data)),
+                }
+            },
+        }
+    }
+
+    /// Replaces the directory of the user's configuration and hosts file.
+    pub fn with_config_dir<P: Into<PathBuf>>(mut self, dir: P) -> Paths {
+        self.config_dir = Some(dir.into());
+        self
+    }
+
+    /// Returns the configuration file of the system.
+    pub fn system_config(&self) -> &Path {
+        &self.system_config
+    }
+
+    /// Returns the directory of the user's configuration and hosts file.
+    pub fn config_dir(&self) -> Option<&Path> {
+        self.config_dir.as_deref()
+    }
+
+    /// Returns the configuration files in the order they are read, the system's first.
+    pub fn config_files(&self) -> Vec<PathBuf> {
+        let mut files = vec![self.system_config.clone()];
+        files.extend(self.config_dir().map(|dir| dir.join(CONFIG_FILE)));
+        files
+    }
+
+    /// Returns the default hosts file, that of the system without a configuration directory.
+    pub fn hosts_file(&self) -> PathBuf {
+        self.config_dir().unwrap_or(&self.system_dir).join(HOSTS_FILE)
+    }
+
+    /// Returns the default audit log, if the state directory is known.
+    pub fn audit_log(&self) -> Option<PathBuf> {
+        self.state_dir.as_ref().map(|dir| dir.join(AUDIT_LOG))
+    }
+
+    /// Returns the wake history, if the state directory is known.
+    pub fn history_file(&self) -> Option<PathBuf> {
+        self.state_dir.as_ref().map(|dir| dir.join(HISTORY_FILE))
+    }
+
+    /// Returns the addresses hosts were last seen at, see `seen::Seen`, if the state directory
+    /// is known.
+    pub fn seen_file(&self) -> Option<PathBuf> {
+        self.state_dir.as_ref().map(|dir| dir.join(SEEN_FILE))
+    }
+}
+
+impl Default for Paths {
+    fn default() -> Paths {
+        Paths::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+
+    use super::{Paths, Platform};
+
+    fn paths(platform: Platform, vars: &[(&str, &str)]) -> Paths {
+        Paths::from_vars(platform, |name| {
+            vars.iter().find(|(n, _)| *n == name).map(|(_, v)| OsString::from(v))
+        })
+    }
+
+    #[test]
+    fn test_xdg() {
+        let home = paths(Platform::Xdg, &[("HOME", "/home/a")]);
+        assert_eq!(home.config_files(),
+                   [PathBuf::from("/etc/wol.toml"),
+                    PathBuf::from("/home/a/.config/wol/config.toml")]);
+        assert_eq!(home.hosts_file(), Path::new("/home/a/.config/wol/hosts.toml"));
+        assert_eq!(home.audit_log().unwrap(), Path::new("/home/a/.local/state/wol/audit.log"));
+        assert_eq!(home.history_file().unwrap(),
+                   Path::new("/home/a/.local/state/wol/history.jsonl"));
+        assert_eq!(home.seen_file().unwrap(), Path::new("/home/a/.local/state/wol/seen.jsonl"));
+
+        let xdg = paths(Platform::Xdg,
+                        &[("HOME", "/home/a"),
+                          ("XDG_CONFIG_HOME", "/cfg"),
+                          ("XDG_STATE_HOME", "state")]);
+        assert_eq!(xdg.hosts_file(), Path::new("/cfg/wol/hosts.toml"));
+        assert_eq!(xdg.audit_log().unwrap(), Path::new("/home/a/.local/state/wol/audit.log"));
+
+        let nobody = paths(Platform::Xdg, &[]);
+        assert_eq!(nobody.config_files(), [PathBuf::from("/etc/wol.toml")]);
+        assert_eq!(nobody.hosts_file(), Path::new("/etc/wol/hosts.toml"));
+        assert_eq!(nobody.audit_log(), None);
+        let dir = nobody.with_config_dir("/srv/wol");
+        assert_eq!(dir.hosts_file(), Path::new("/srv/wol/hosts.toml"));
+    }
+
+    #[test]
+    fn test_other_platforms() {
+        let mac = paths(Platform::MacOs, &[("HOME", "/Users/a")]);
+        let support = Path::new("/Users/a/Library/Application Support/wol");
+        assert_eq!(mac.config_dir(), Some(support));
+        assert_eq!(mac.audit_log(), Some(support.join("audit.log")));
+
+        let windows = paths(Platform::Windows,
+                            &[("APPDATA", "C:\\Users\\a\\AppData\\Roaming"),
+                              ("LOCALAPPDATA", "C:\\Users\\a\\AppData\\Local")]);
+        assert_eq!(windows.system_config(),
+                   Path::new("C:\\ProgramData").join("wol").join("config.toml"));
+        assert_eq!(windows.hosts_file(),
+                   Path::new("C:\\Users\\a\\AppData\\Roaming").join("wol").join("hosts.toml"));
+        assert_eq!(windows.audit_log(),
+                   Some(Path::new("C:\\Users\\a\\AppData\\Local").join("wol").join("audit.log")));
+    }
+}
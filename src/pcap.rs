@@ -0,0 +1,320 @@
+//! Captures in the pcap format, e.g. for analysis in Wireshark.
+//!
+//! Captures hold Ethernet frames with nanosecond timestamps. Magic packets received per UDP
+//! have lost their Ethernet and IP headers, so `udp_frame` rebuilds a frame around them from
+//! the addresses of the datagram. `PcapReader` reads captures of Ethernet frames written by wol,
+//! tcpdump or Wireshark in the pcap (not pcapng) format.
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The magic number of pcap files with microsecond timestamps.
+const MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+
+/// The magic number of pcap files with nanosecond timestamps.
+const MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+
+/// The first bytes of pcapng files, which are not supported.
+const MAGIC_PCAPNG: u32 = 0x0A0D_0D0A;
+
+/// The largest frame read, as in libpcap, to reject corrupt captures early.
+const MAX_FRAME_LEN: u32 = 262_144;
+
+/// The link type of Ethernet frames.
+pub const LINKTYPE_ETHERNET: u32 = 1;
+
+/// The largest frame written.
+const SNAPLEN: u32 = 65535;
+
+/// The EtherType of IPv4.
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+
+/// The EtherType of IPv6.
+pub const ETHERTYPE_IPV6: u16 = 0x86DD;
+
+/// The EtherType of magic packets sent directly over Ethernet.
+pub const ETHERTYPE_WOL: u16 = 0x0842;
+
+/// The EtherType of IEEE 802.1Q VLAN tags, followed by the rest of the tag and the EtherType of
+/// the frame.
+pub const ETHERTYPE_VLAN: u16 = 0x8100;
+
+/// Writes frames to a pcap file.
+///
+/// #Example
+///
+/// ```
+/// use std::time::SystemTime;
+/// use wol::pcap::{udp_frame, PcapWriter};
+///
+/// let mut writer = PcapWriter::new(Vec::new()).unwrap();
+/// let source = "10.0.0.1:4000".parse().unwrap();
+/// let frame = udp_frame(source, "10.0.0.255:9".parse().unwrap(), &[1]);
+/// writer.write_frame(SystemTime::now(), &frame).unwrap();
+/// assert_eq!(writer.into_inner().len(), 24 + 16 + frame.len());
+/// ```
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the file header for Ethernet frames.
+    pub fn new(mut out: W) -> io::Result<PcapWriter<W>> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&MAGIC_NANOS.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&[0; 8]);
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        out.write_all(&header)?;
+        Ok(PcapWriter { out })
+    }
+
+    /// Appends a frame received at `time` and flushes it, so that the capture is complete even
+    /// if the process is killed.
+    pub fn write_frame(&mut self, time: SystemTime, frame: &[u8]) -> io::Result<()> {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let len = frame.len().min(SNAPLEN as usize);
+        let mut record = Vec::with_capacity(16 + len);
+        record.extend_from_slice(&(since_epoch.as_secs() as u32).to_le_bytes());
+        record.extend_from_slice(&since_epoch.subsec_nanos().to_le_bytes());
+        record.extend_from_slice(&(len as u32).to_le_bytes());
+        record.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        record.extend_from_slice(&frame[..len]);
+        self.out.write_all(&record)?;
+        self.out.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Reads the frames of a pcap file.
+///
+/// #Example
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use wol::pcap::{PcapReader, PcapWriter};
+///
+/// let mut writer = PcapWriter::new(Vec::new()).unwrap();
+/// writer.write_frame(UNIX_EPOCH + Duration::from_nanos(1_500), &[1, 2, 3]).unwrap();
+/// let capture = writer.into_inner();
+/// let mut reader = PcapReader::new(&capture[..]).unwrap();
+/// let (time, frame) = reader.next().unwrap().unwrap();
+/// assert_eq!(time, UNIX_EPOCH + Duration::from_nanos(1_500));
+/// assert_eq!(frame, vec![1, 2, 3]);
+/// assert!(reader.next().is_none());
+/// ```
+#[derive(Debug)]
+pub struct PcapReader<R: Read> {
+    input: R,
+    big_endian: bool,
+    nanos: bool,
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Reads the file header and fails with `io::ErrorKind::InvalidData` unless the file is a
+    /// pcap capture of Ethernet frames.
+    pub fn new(mut input: R) -> io::Result<PcapReader<R>> {
+        let mut header = [0; 24];
+        input.read_exact(&mut header)?;
+        let magic = [header[0], header[1], header[2], header[3]];
+        let (big_endian, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (MAGIC_MICROS, _) => (false, false),
+            (MAGIC_NANOS, _) => (false, true),
+            (_, MAGIC_MICROS) => (true, false),
+            (_, MAGIC_NANOS) => (true, true),
+            (MAGIC_PCAPNG, _) => return Err(invalid("pcapng captures are not supported, \
+                                                      save the capture as pcap")),
+            _ => return Err(invalid("not a pcap capture")),
+        };
+        let reader = PcapReader { input, big_endian, nanos };
+        match reader.u32_at(&header, 20) {
+            LINKTYPE_ETHERNET => Ok(reader),
+            link_type => Err(invalid(&format!("unsupported link type {}, only Ethernet \
+                                               captures are supported",
+                                              link_type))),
+        }
+    }
+
+    /// Returns the `u32` at `offset` in the byte order of the file.
+    fn u32_at(&self, bytes: &[u8], offset: usize) -> u32 {
+        let value = [bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]];
+        if self.big_endian {
+            u32::from_be_bytes(value)
+        } else {
+            u32::from_le_bytes(value)
+        }
+    }
+
+    /// Reads the next frame, or returns `None` at the end of the file.
+    fn read_frame(&mut self) -> io::Result<Option<(SystemTime, Vec<u8>)>> {
+        let mut header = [0; 16];
+        match self.input.read(&mut header[..1])? {
+            0 => return Ok(None),
+            _ => self.input.read_exact(&mut header[1..])?,
+        }
+        let secs = u64::from(self.u32_at(&header, 0));
+        let fraction = self.u32_at(&header, 4);
+        let nanos = if self.nanos { fraction } else { fraction.saturating_mul(1000) };
+        let len = self.u32_at(&header, 8);
+        if len > MAX_FRAME_LEN {
+            return Err(invalid(&format!("frame of {} bytes, the capture is corrupt", len)));
+        }
+        let mut frame = vec![0; len as usize];
+        self.input.read_exact(&mut frame)?;
+        let time = UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_nanos(u64::from(nanos));
+        Ok(Some((time, frame)))
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = io::Result<(SystemTime, Vec<u8>)>;
+
+    /// Returns the next frame with the time it was captured.
+    fn next(&mut self) -> Option<io::Result<(SystemTime, Vec<u8>)>> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Returns an error for an invalid capture.
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Builds the Ethernet frame of a UDP datagram. The MAC addresses are unknown and left zero,
+/// except for the broadcast MAC address of broadcast and multicast destinations.
+pub fn udp_frame(source: SocketAddr, destination: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let broadcast = match destination.ip() {
+        IpAddr::V4(ip) => ip.is_broadcast() || ip.is_multicast(),
+        IpAddr::V6(ip) => ip.is_multicast(),
+    };
+    let mut frame = Vec::with_capacity(62 + payload.len());
+    frame.extend_from_slice(&[if broadcast { 0xFF } else { 0 }; 6]);
+    frame.extend_from_slice(&[0; 6]);
+    let udp_len = 8 + payload.len() as u16;
+    let mut udp = Vec::with_capacity(udp_len as usize);
+    udp.extend_from_slice(&source.port().to_be_bytes());
+    udp.extend_from_slice(&destination.port().to_be_bytes());
+    udp.extend_from_slice(&udp_len.to_be_bytes());
+    udp.extend_from_slice(&[0, 0]);
+    udp.extend_from_slice(payload);
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+            let mut ip = vec![0x45, 0];
+            ip.extend_from_slice(&(20 + udp_len).to_be_bytes());
+            // Identification, flags and fragment offset, TTL 64, protocol UDP, checksum.
+            ip.extend_from_slice(&[0, 0, 0x40, 0, 64, 17, 0, 0]);
+            ip.extend_from_slice(&src.octets());
+            ip.extend_from_slice(&dst.octets());
+            let checksum = checksum(&ip);
+            ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+            frame.extend_from_slice(&ip);
+        },
+        (src, dst) => {
+            let (src, dst) = (to_v6(src), to_v6(dst));
+            frame.extend_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+            frame.extend_from_slice(&[0x60, 0, 0, 0]);
+            frame.extend_from_slice(&udp_len.to_be_bytes());
+            frame.extend_from_slice(&[17, 64]);
+            frame.extend_from_slice(&src);
+            frame.extend_from_slice(&dst);
+            // The UDP checksum is mandatory for IPv6 and covers a pseudo header.
+            let mut pseudo = Vec::with_capacity(40 + udp.len());
+            pseudo.extend_from_slice(&src);
+            pseudo.extend_from_slice(&dst);
+            pseudo.extend_from_slice(&u32::from(udp_len).to_be_bytes());
+            pseudo.extend_from_slice(&[0, 0, 0, 17]);
+            pseudo.extend_from_slice(&udp);
+            let checksum = match checksum(&pseudo) {
+                0 => 0xFFFF,
+                checksum => checksum,
+            };
+            udp[6..8].copy_from_slice(&checksum.to_be_bytes());
+        },
+    }
+    frame.extend_from_slice(&udp);
+    frame
+}
+
+/// Returns the octets of an IPv6 address or of an IPv4 address mapped to IPv6.
+fn to_v6(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+/// Computes the internet checksum of RFC 1071.
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data.chunks(2)
+        .map(|pair| u32::from(pair[0]) << 8 | u32::from(*pair.get(1).unwrap_or(&0)))
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{checksum, udp_frame, PcapReader, PcapWriter};
+
+    #[test]
+    fn test_udp_frame_v4() {
+        let frame = udp_frame("192.168.1.2:4000".parse().unwrap(),
+                              "255.255.255.255:9".parse().unwrap(),
+                              &[0xFF; 102]);
+        assert_eq!(frame.len(), 14 + 20 + 8 + 102);
+        assert_eq!(&frame[..6], &[0xFF; 6]);
+        assert_eq!(&frame[12..14], &[0x08, 0x00]);
+        // A correct IPv4 header checksum makes the checksum over the header zero.
+        assert_eq!(checksum(&frame[14..34]), 0);
+        assert_eq!(&frame[34..38], &[0x0F, 0xA0, 0x00, 0x09]);
+    }
+
+    #[test]
+    fn test_udp_frame_v6() {
+        let frame = udp_frame("[fe80::1]:4000".parse().unwrap(),
+                              "[ff02::1]:9".parse().unwrap(),
+                              &[]);
+        assert_eq!(frame.len(), 14 + 40 + 8);
+        assert_eq!(&frame[12..14], &[0x86, 0xDD]);
+        assert_ne!(&frame[60..62], &[0, 0]);
+    }
+
+    #[test]
+    fn test_read_micros_big_endian() {
+        let mut capture = vec![0xA1, 0xB2, 0xC3, 0xD4, 0, 2, 0, 4];
+        capture.extend_from_slice(&[0; 8]);
+        capture.extend_from_slice(&[0, 0, 0xFF, 0xFF, 0, 0, 0, 1]);
+        capture.extend_from_slice(&[0, 0, 0, 10, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 1, 0xAB]);
+        let frames: Vec<_> = PcapReader::new(&capture[..]).unwrap().map(Result::unwrap).collect();
+        assert_eq!(frames,
+                   vec![(UNIX_EPOCH + Duration::new(10, 2000), vec![0xAB])]);
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        let err = PcapReader::new(&b"not a capture, really not"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let pcapng = [0x0A, 0x0D, 0x0D, 0x0A, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                      0, 0];
+        assert!(PcapReader::new(&pcapng[..]).unwrap_err().to_string().contains("pcapng"));
+        // A frame cut off in the middle.
+        let mut capture = PcapWriter::new(Vec::new()).unwrap();
+        capture.write_frame(UNIX_EPOCH, &[1, 2, 3]).unwrap();
+        let capture = capture.into_inner();
+        let mut reader = PcapReader::new(&capture[..capture.len() - 1]).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
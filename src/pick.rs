@@ -0,0 +1,204 @@
+//! Interactive selection of hosts by fuzzy search over their names, like `fzf`.
+//!
+//! The prompt is line based, so it works on any terminal and with piped input: a line of text
+//! filters the hosts, a line of numbers selects them from the current list.
+use std::io::{self, BufRead, Write};
+
+use hosts::Host;
+use i18n::{self, Message};
+
+/// Returns how well `query` matches `candidate`, higher is better, or `None` if the characters
+/// of the query do not appear in the candidate in order. Case is ignored; consecutive matches and
+/// matches at the start of the candidate or of a word score higher.
+///
+/// #Example
+///
+/// ```
+/// use wol::pick::fuzzy_score;
+///
+/// assert!(fuzzy_score("dsk", "desktop").is_some());
+/// assert!(fuzzy_score("desk", "desktop") > fuzzy_score("desk", "old-desk-pc"));
+/// assert_eq!(fuzzy_score("nas", "desktop"), None);
+/// ```
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = position + candidate[position..].iter().position(|&other| other == c)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    // Prefer shorter candidates among equal matches.
+    Some(score * 1000 - candidate.len() as i64)
+}
+
+/// Returns the hosts matching `query`, best match first.
+pub fn filter<'a>(query: &str, hosts: &'a [Host]) -> Vec<&'a Host> {
+    let mut matches: Vec<(i64, &Host)> = hosts.iter()
+        .filter_map(|host| fuzzy_score(query, &host.name).map(|score| (score, host)))
+        .collect();
+    // The sort is stable, so equal matches keep the order of the hosts file.
+    matches.sort_by_key(|&(score, _)| -score);
+    matches.into_iter().map(|(_, host)| host).collect()
+}
+
+/// Parses a line of numbers separated by spaces or commas, e.g. "1 3" or "2,4".
+fn parse_numbers(line: &str) -> Option<Vec<usize>> {
+    line.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|number| !number.is_empty())
+        .map(|number| number.parse().ok())
+        .collect()
+}
+
+/// Lets the user select hosts: prints the hosts numbered to `output` and reads lines from
+/// `input`. A line of numbers selects the hosts with these numbers, other text filters the
+/// list. An empty line selects the only host left by a filter and otherwise cancels, like the
+/// end of the input. Returns the selected hosts, which are empty if the user cancelled.
+///
+/// #Example
+///
+/// ```
+/// use wol::hosts::Host;
+/// use wol::pick::pick;
+/// use wol::MacAddr;
+///
+/// let hosts = vec![Host::new("desktop", MacAddr::new([0, 1, 2, 3, 4, 5])),
+///                  Host::new("nas", MacAddr::new([0, 1, 2, 3, 4, 6]))];
+/// let picked = pick(&hosts, &b"na\n\n"[..], &mut Vec::new()).unwrap();
+/// assert_eq!(picked, vec![hosts[1].clone()]);
+/// ```
+pub fn pick<R: BufRead, W: Write>(hosts: &[Host],
+                                  mut input: R,
+                                  output: &mut W)
+                                  -> io::Result<Vec<Host>> {
+    let mut shown: Vec<&Host> = hosts.iter().collect();
+    loop {
+        for (i, host) in shown.iter().enumerate() {
+            writeln!(output, "{:>3}) {:<20} {}", i + 1, host.name, host.mac)?;
+        }
+        write!(output, "Filter, or numbers to wake (empty to cancel): ")?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(Vec::new());
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(match shown.len() {
+                1 => vec![shown[0].clone()],
+                _ => Vec::new(),
+            });
+        }
+        match parse_numbers(line) {
+            Some(numbers) => {
+                let picked: Option<Vec<Host>> = numbers.iter()
+                    .map(|&n| n.checked_sub(1).and_then(|i| shown.get(i)).map(|&h| h.clone()))
+                    .collect();
+                match picked {
+                    Some(picked) => return Ok(picked),
+                    None => writeln!(output, "Choose numbers from 1 to {}", shown.len())?,
+                }
+            },
+            None => {
+                let matches = filter(line, hosts);
+                if matches.is_empty() {
+                    writeln!(output, "No host matches '{}'", line)?;
+                } else {
+                    shown = matches;
+                }
+            },
+        }
+    }
+}
+
+/// Asks a yes/no question on `output` and returns whether the user answered "y" or "yes", or
+/// yes in the language of `i18n::lang`. Any other answer and the end of the input count as no.
+///
+/// #Example
+///
+/// ```
+/// use wol::pick::confirm;
+///
+/// assert!(confirm("Wake 42 hosts?", &b"y\n"[..], &mut Vec::new()).unwrap());
+/// assert!(!confirm("Wake 42 hosts?", &b""[..], &mut Vec::new()).unwrap());
+/// ```
+pub fn confirm<R: BufRead, W: Write>(question: &str,
+                                     mut input: R,
+                                     output: &mut W)
+                                     -> io::Result<bool> {
+    write!(output, "{} {} ", question, Message::YesNo)?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(Message::is_yes(&answer, i18n::lang()))
+}
+
+#[cfg(test)]
+mod tests {
+    use hosts::Host;
+    use MacAddr;
+
+    use super::{confirm, filter, fuzzy_score, parse_numbers, pick};
+
+    fn hosts() -> Vec<Host> {
+        ["desktop", "nas", "vm-host", "old-desktop"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Host::new(name, MacAddr::new([0, 0, 0, 0, 0, i as u8])))
+            .collect()
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "nas"), Some(-3));
+        assert!(fuzzy_score("VMH", "vm-host").is_some());
+        assert_eq!(fuzzy_score("tsd", "desktop"), None);
+        assert!(fuzzy_score("host", "vm-host") > fuzzy_score("host", "h-o-s-t"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let hosts = hosts();
+        let names: Vec<&str> = filter("desk", &hosts).iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, vec!["desktop", "old-desktop"]);
+        assert!(filter("xyz", &hosts).is_empty());
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut output = Vec::new();
+        assert!(confirm("Wake?", &b" YES \n"[..], &mut output).unwrap());
+        assert_eq!(output, b"Wake? [y/N] ");
+        assert!(!confirm("Wake?", &b"\n"[..], &mut Vec::new()).unwrap());
+        assert!(!confirm("Wake?", &b"yep\n"[..], &mut Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        assert_eq!(parse_numbers("1 3,4"), Some(vec![1, 3, 4]));
+        assert_eq!(parse_numbers("1 nas"), None);
+    }
+
+    #[test]
+    fn test_pick() {
+        let hosts = hosts();
+        let mut output = Vec::new();
+        let picked = pick(&hosts, &b"desk\n9\n2\n"[..], &mut output).unwrap();
+        assert_eq!(picked, vec![hosts[3].clone()]);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Choose numbers from 1 to 2"));
+        assert_eq!(pick(&hosts, &b"1 2\n"[..], &mut Vec::new()).unwrap(), hosts[..2].to_vec());
+        assert!(pick(&hosts, &b"\n"[..], &mut Vec::new()).unwrap().is_empty());
+        assert!(pick(&hosts, &b"xyz\n"[..], &mut Vec::new()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,26 @@
+//! The types most programs waking hosts need, for a single glob import.
+//!
+//! The prelude is the stable surface of the crate: its items keep their names and signatures,
+//! while those of the other modules may still change. It holds the MAC address, the magic
+//! packet and, with the `std` feature, the client sending it with its report and error.
+//!
+//! #Example
+//!
+//! ```
+//! use std::net::UdpSocket;
+//! use wol::prelude::*;
+//!
+//! # fn main() -> Result<(), WolError> {
+//! let receiver = UdpSocket::bind("127.0.0.1:0")?;
+//! let mac: MacAddr = "AA:FF:B0:12:34:56".parse()?;
+//! let report: SendReport = WolClient::new(receiver.local_addr()?).send(&MagicPacket::new(mac))?;
+//! assert_eq!(report.bytes_sent, 102);
+//! # Ok(())
+//! # }
+//! ```
+pub use mac::MacAddr;
+pub use packet::MagicPacket;
+#[cfg(feature = "std")]
+pub use error::WolError;
+#[cfg(feature = "std")]
+pub use transport::{SendReport, WolClient};
@@ -0,0 +1,56 @@
+//! Redaction of secrets, i.e. SecureOn passwords and tokens, wherever wol displays them: in log
+//! messages, dry-run dumps and the shown configuration. They are replaced with `REDACTED` unless
+//! an application asks to show them with `set_show_secrets`, e.g. for `--show-secrets`.
+//!
+//! This module does not need the standard library, see the `std` feature.
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// What secrets are shown as.
+pub const REDACTED: &str = "••••";
+
+static SHOW_SECRETS: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether secrets are shown instead of `REDACTED`, for the whole process.
+pub fn set_show_secrets(show: bool) {
+    SHOW_SECRETS.store(show, Ordering::Relaxed);
+}
+
+/// Returns whether secrets are shown.
+pub fn show_secrets() -> bool {
+    SHOW_SECRETS.load(Ordering::Relaxed)
+}
+
+/// A secret, which displays as `REDACTED` unless secrets are shown.
+///
+/// #Example
+///
+/// ```
+/// use wol::redact::{self, Secret};
+///
+/// assert_eq!(format!("token {}", Secret("s3cret")), "token ••••");
+/// redact::set_show_secrets(true);
+/// assert_eq!(format!("token {}", Secret("s3cret")), "token s3cret");
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Secret<T>(pub T);
+
+impl<T: fmt::Display> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if show_secrets() {
+            self.0.fmt(f)
+        } else {
+            f.pad(REDACTED)
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if show_secrets() {
+            self.0.fmt(f)
+        } else {
+            f.pad(REDACTED)
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! A client of the REST API of `wol serve`, to wake hosts through a relay in their LAN, e.g.
+//! from a laptop outside of it.
+//!
+//! `https://` URLs are not supported, as wol has no TLS implementation. To reach a relay over
+//! the internet, put it behind a reverse proxy terminating TLS and connect through a tunnel like
+//! SSH or a VPN, or let the proxy listen on the tunnel only. With `udp://` URLs, the requests
+//! are signed datagrams instead, see the `signed` module, which are not answered.
+//!
+//! Besides waking hosts, the relay can share its hosts file, see `Relay::pull_hosts` and
+//! `Relay::push_hosts`, so that a laptop and an always-on relay wake the same hosts.
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime};
+
+use output::{self, Record, Value};
+use redact::Secret;
+use secrets;
+use signed::SignedRequest;
+use {MacAddr, SendReport};
+
+/// How long to wait for the relay to accept the connection and to answer, unless set otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum size of an answer of the relay.
+const MAX_RESPONSE_SIZE: u64 = 64 * 1024;
+
+/// A relay, i.e. another instance of wol running `wol serve`.
+///
+/// #Example
+///
+/// ```
+/// use wol::relay::Relay;
+///
+/// let relay = Relay::new("http://relay.example:8080/wol").unwrap().with_token("s3cret");
+/// assert_eq!(relay.url(), "http://relay.example:8080/wol");
+/// assert!(Relay::new("https://relay.example").is_err());
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Relay {
+    url: String,
+    /// The host and port as given in the URL, e.g. "[::1]:8080".
+    authority: String,
+    host: String,
+    port: u16,
+    /// The path the API is mounted at, without trailing slash.
+    prefix: String,
+    /// Whether requests are signed datagrams rather than HTTP requests.
+    udp: bool,
+    token: Option<String>,
+    timeout: Duration,
+}
+
+impl Relay {
+    /// Creates a client of the relay at an URL like `http://relay.example:8080` or
+    /// `udp://relay.example:9009`. Fails with `io::ErrorKind::InvalidInput` for malformed URLs,
+    /// UDP URLs without port and other schemes.
+    pub fn new(url: &str) -> io::Result<Relay> {
+        fn invalid(message: String) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidInput, message)
+        }
+
+        let (rest, udp) = match url.split_once("://") {
+            Some(("http", rest)) => (rest, false),
+            Some(("udp", rest)) => (rest, true),
+            Some(("https", _)) => {
+                return Err(invalid("https is not supported, as wol has no TLS; use an http:// \
+                                    URL through a tunnel or a TLS terminating proxy"
+                    .to_string()))
+            },
+            _ => {
+                return Err(invalid(format!("invalid relay URL '{}', expected http://HOST or \
+                                            udp://HOST:PORT",
+                                           url)))
+            },
+        };
+        let (authority, prefix) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, ""),
+        };
+        // IPv6 addresses are enclosed in brackets, so that their colons are not taken for the
+        // port separator.
+        let (host, port) = match authority.rfind(':') {
+            Some(colon) if !authority[colon..].contains(']') => {
+                let port = authority[colon + 1..]
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid port in relay URL '{}'", url)))?;
+                (&authority[..colon], port)
+            },
+            _ if udp => return Err(invalid(format!("missing port in relay URL '{}'", url))),
+            _ => (authority, 80),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(invalid(format!("missing host in relay URL '{}'", url)));
+        }
+        Ok(Relay {
+            url: url.to_string(),
+            authority: authority.to_string(),
+            host: host.to_string(),
+            port,
+            prefix: prefix.trim_end_matches('/').to_string(),
+            udp,
+            token: None,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Authenticates with the given bearer token, see `Server::token`, or signs with it as key
+    /// for `udp://` relays.
+    pub fn with_token(mut self, token: &str) -> Relay {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Waits at most `timeout` for the connection and for each read of the answer. When the
+    /// relay verifies the host, this has to cover its verification timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Relay {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the URL of the relay as given.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns whether the relay can verify that hosts came up, which `udp://` relays cannot.
+    pub fn can_verify(&self) -> bool {
+        !self.udp
+    }
+
+    /// Asks the relay to wake `mac` and, if `verify` is set, to wait until the host is up at
+    /// that address. Returns the result record of the relay, which holds an `error` field if
+    /// the relay could not send the magic packet. Other failures, e.g. a wrong token, are
+    /// returned as errors.
+    pub fn wake(&self, mac: MacAddr, verify: Option<IpAddr>) -> io::Result<Record> {
+        if self.udp {
+            if verify.is_some() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "udp:// relays cannot verify hosts"));
+            }
+            return self.send_signed(mac);
+        }
+        let mut path = format!("{}/wake/{}", self.prefix, mac);
+        if let Some(ip) = verify {
+            path.push_str(&format!("?verify={}", ip));
+        }
+        let (status, body) = self.request("POST", &path, "")?;
+        debug!("relay {} answered {}: {}", self.url, status, body);
+        match (status, body.parse::<Record>().ok()) {
+            // The relay failed to send, which its record describes like a local failure.
+            (200, Some(record)) | (500, Some(record)) => Ok(record),
+            (200, None) => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, "invalid answer from the relay"))
+            },
+            (404, _) => {
+                Err(io::Error::other(format!("{} is not the URL of a wol relay", self.url)))
+            },
+            (status, _) => Err(self.error(status, &body)),
+        }
+    }
+
+    /// Returns the hosts file of the relay as it is, with its comments, e.g. to replace the
+    /// local one with it. Needs an admin token, see `Server::hosts_file`. Fails with
+    /// `io::ErrorKind::PermissionDenied` for hosts files referencing secrets by command, which
+    /// would run here once the file is read, like `PUT /hosts` of the relay does.
+    pub fn pull_hosts(&self) -> io::Result<String> {
+        self.check_http("share hosts files")?;
+        let (status, body) = self.request("GET", &format!("{}/hosts", self.prefix), "")?;
+        match status {
+            200 if secrets::references_command(&body) => {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                   "the hosts file references secrets by command, which would \
+                                    run here, reference them by keyring instead"))
+            },
+            200 => Ok(body),
+            status => Err(self.error(status, &body)),
+        }
+    }
+
+    /// Replaces the hosts file of the relay with `content`, after which the relay reloads, and
+    /// returns the number of its hosts. Needs an admin token, see `Server::hosts_file`.
+    pub fn push_hosts(&self, content: &str) -> io::Result<usize> {
+        self.check_http("share hosts files")?;
+        let (status, body) = self.request("PUT", &format!("{}/hosts", self.prefix), content)?;
+        debug!("relay {} answered {}: {}", self.url, status, body);
+        let hosts = body.parse::<Record>().ok().and_then(|record| record.get("hosts").cloned());
+        match (status, hosts) {
+            (200, Some(Value::Int(hosts))) => Ok(hosts as usize),
+            (200, _) => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, "invalid answer from the relay"))
+            },
+            (status, _) => Err(self.error(status, &body)),
+        }
+    }
+
+    /// Fails with `io::ErrorKind::InvalidInput` for `udp://` relays, which cannot do `what`.
+    fn check_http(&self, what: &str) -> io::Result<()> {
+        if self.udp {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("udp:// relays cannot {}", what)));
+        }
+        Ok(())
+    }
+
+    /// Returns the error of an answer of the relay with the given status, with the message of
+    /// its error record, if any.
+    fn error(&self, status: u16, body: &str) -> io::Error {
+        let record: Option<Record> = body.parse().ok();
+        let message = record.as_ref().and_then(|record| match record.get("error") {
+            Some(Value::Str(error)) => Some(error.clone()),
+            _ => None,
+        });
+        match status {
+            401 => {
+                io::Error::new(io::ErrorKind::PermissionDenied,
+                               "the relay rejected the token, check [relay] token")
+            },
+            403 => {
+                let message = message.unwrap_or_else(|| "the token is not allowed".to_string());
+                io::Error::new(io::ErrorKind::PermissionDenied,
+                               format!("the relay refused: {}", message))
+            },
+            status => {
+                let message = message.unwrap_or_else(|| "no details".to_string());
+                io::Error::other(format!("the relay answered {}: {}", status, message))
+            },
+        }
+    }
+
+    /// Sends a signed request to wake `mac` and returns the record of sending it, whose target
+    /// is the relay.
+    fn send_signed(&self, mac: MacAddr) -> io::Result<Record> {
+        let key = self.token.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput,
+                           "udp:// relays need a key, set [relay] token")
+        })?;
+        let destination = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+                                          format!("{} has no address", self.host)))?;
+        let local: SocketAddr = if destination.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let start = Instant::now();
+        let socket = UdpSocket::bind(local)?;
+        let request = SignedRequest::new(mac);
+        let bytes_sent = socket.send_to(&request.encode(key.as_bytes()), destination)?;
+        let report = SendReport {
+            bytes_sent,
+            destination,
+            local_addr: socket.local_addr()?,
+            timestamp: SystemTime::now(),
+        };
+        Ok(output::send_result(mac, destination, &Ok(report), start.elapsed()))
+    }
+
+    /// Sends a request with the given method and body and returns the status and body of the
+    /// answer.
+    fn request(&self, method: &str, path: &str, body: &str) -> io::Result<(u16, String)> {
+        let addresses: Vec<_> = (self.host.as_str(), self.port).to_socket_addrs()?.collect();
+        let mut last_err = io::Error::new(io::ErrorKind::NotFound,
+                                          format!("{} has no address", self.host));
+        let mut stream = None;
+        for address in addresses {
+            match TcpStream::connect_timeout(&address, self.timeout) {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                },
+                Err(err) => last_err = err,
+            }
+        }
+        let mut stream = stream.ok_or(last_err)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let authorization = match self.token {
+            Some(ref token) => format!("Authorization: Bearer {}\r\n", token),
+            None => String::new(),
+        };
+        write!(stream,
+               "{} {} HTTP/1.1\r\nHost: {}\r\n{}Content-Length: {}\r\nConnection: \
+                close\r\n\r\n{}",
+               method,
+               path,
+               self.authority,
+               authorization,
+               body.len(),
+               body)?;
+        stream.flush()?;
+        let mut response = Vec::new();
+        stream.take(MAX_RESPONSE_SIZE + 1).read_to_end(&mut response)?;
+        // Cut off, a hosts file would lose hosts.
+        if response.len() as u64 > MAX_RESPONSE_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "the answer of the relay is too large"));
+        }
+        parse_response(&String::from_utf8_lossy(&response))
+    }
+}
+
+impl fmt::Debug for Relay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Relay")
+            .field("url", &self.url)
+            .field("udp", &self.udp)
+            .field("token", &self.token.as_ref().map(Secret))
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Returns the status and body of an HTTP/1.x response which ends with the connection.
+fn parse_response(response: &str) -> io::Result<(u16, String)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid HTTP response");
+    let (head, body) = response.split_once("\r\n\r\n").ok_or_else(invalid)?;
+    let status_line = head.lines().next().unwrap_or("");
+    let mut parts = status_line.split(' ');
+    if !parts.next().unwrap_or("").starts_with("HTTP/1.") {
+        return Err(invalid());
+    }
+    let status = parts.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+    Ok((status, body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, UdpSocket};
+    use std::path::Path;
+    use std::thread;
+
+    use super::{parse_response, Relay};
+    use output::Value;
+    use signed::{self, Verifier};
+    use MacAddr;
+
+    #[test]
+    fn test_new() {
+        let relay = Relay::new("http://relay.example").unwrap();
+        assert_eq!((relay.host.as_str(), relay.port, relay.prefix.as_str()),
+                   ("relay.example", 80, ""));
+        let relay = Relay::new("http://[::1]:8080/wol/").unwrap();
+        assert_eq!((relay.host.as_str(), relay.port, relay.prefix.as_str()), ("::1", 8080, "/wol"));
+        assert_eq!(relay.authority, "[::1]:8080");
+        assert_eq!(Relay::new("http://[::1]").unwrap().port, 80);
+        assert!(Relay::new("https://relay.example").is_err());
+        assert!(Relay::new("relay.example").is_err());
+        assert!(Relay::new("http://relay.example:http").is_err());
+        assert!(Relay::new("http://:8080").is_err());
+        let relay = Relay::new("udp://relay.example:9009").unwrap();
+        assert!(relay.udp && !relay.can_verify());
+        assert!(Relay::new("udp://relay.example").is_err());
+    }
+
+    #[test]
+    fn test_wake_signed() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let url = format!("udp://{}", socket.local_addr().unwrap());
+        let mac = MacAddr::new([0, 1, 2, 3, 4, 5]);
+        assert!(Relay::new(&url).unwrap().wake(mac, None).is_err());
+        let relay = Relay::new(&url).unwrap().with_token("s3cret");
+        assert!(relay.wake(mac, Some("10.0.0.5".parse().unwrap())).is_err());
+        let record = relay.wake(mac, None).unwrap();
+        assert_eq!(record.get("bytes"), Some(&Value::Int(signed::REQUEST_LEN as u64)));
+        let mut buf = [0; 100];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(Verifier::new(b"s3cret").verify(&buf[..len]).unwrap().mac, mac);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = "HTTP/1.1 401 Unauthorized\r\nContent-Length: 2\r\n\r\n{}";
+        assert_eq!(parse_response(response).unwrap(), (401, "{}".to_string()));
+        assert!(parse_response("SSH-2.0-OpenSSH\r\n\r\n").is_err());
+        assert!(parse_response("HTTP/1.1 200 OK\r\n").is_err());
+    }
+
+    #[test]
+    fn test_wake() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/wol", listener.local_addr().unwrap());
+        let relay = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for answer in &["200 OK\r\n\r\n{\"mac\":\"00:01:02:03:04:05\",\"verified\":true}",
+                            "401 Unauthorized\r\n\r\n{\"error\":\"wrong token\"}"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut lines = BufReader::new(&stream).lines();
+                let mut request = Vec::new();
+                while let Some(line) = lines.next().transpose().unwrap() {
+                    if line.is_empty() {
+                        break;
+                    }
+                    request.push(line);
+                }
+                write!(&stream, "HTTP/1.1 {}", answer).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        let mac = MacAddr::new([0, 1, 2, 3, 4, 5]);
+        let relay_client = Relay::new(&url).unwrap().with_token("s3cret");
+        let record = relay_client.wake(mac, Some("10.0.0.5".parse().unwrap())).unwrap();
+        assert_eq!(record.get("verified"), Some(&Value::Bool(true)));
+        assert!(relay_client.wake(mac, None).is_err());
+
+        let requests = relay.join().unwrap();
+        assert_eq!(requests[0][0], "POST /wol/wake/00:01:02:03:04:05?verify=10.0.0.5 HTTP/1.1");
+        assert!(requests[0].contains(&"Authorization: Bearer s3cret".to_string()));
+        assert_eq!(requests[1][0], "POST /wol/wake/00:01:02:03:04:05 HTTP/1.1");
+    }
+    #[test]
+    fn test_sync_hosts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let relay = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for answer in &["200 OK\r\n\r\n[nas]\nmac = \"00:01:02:03:04:05\"\n",
+                            "200 OK\r\n\r\n[nas]\nmac = \"00:01:02:03:04:05\"\n\
+                             password = { cmd = \"touch wol-pwned\" }\n",
+                            "200 OK\r\n\r\n{\"hosts\":2,\"reloaded\":true}",
+                            "403 Forbidden\r\n\r\n{\"error\":\"token guest may not replace \
+                             the hosts file\",\"code\":\"FORBIDDEN\"}"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let (mut request, mut length) = (String::new(), 0);
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                write!(&stream, "HTTP/1.1 {}", answer).unwrap();
+                requests.push((request, String::from_utf8(body).unwrap()));
+            }
+            requests
+        });
+
+        let relay_client = Relay::new(&url).unwrap().with_token("s3cret");
+        assert_eq!(relay_client.pull_hosts().unwrap(), "[nas]\nmac = \"00:01:02:03:04:05\"\n");
+        let err = relay_client.pull_hosts().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(!Path::new("wol-pwned").exists());
+        let content = "[nas]\nmac = \"00:01:02:03:04:05\"\n\
+                       [pc]\nmac = \"00:01:02:03:04:06\"\n";
+        assert_eq!(relay_client.push_hosts(content).unwrap(), 2);
+        let err = relay_client.push_hosts(content).unwrap_err();
+        assert_eq!(err.to_string(),
+                   "the relay refused: token guest may not replace the hosts file");
+
+        let requests = relay.join().unwrap();
+        assert!(requests[0].0.starts_with("GET /hosts HTTP/1.1\r\n"));
+        assert!(requests[2].0.starts_with("PUT /hosts HTTP/1.1\r\n"));
+        assert!(requests[2].0.contains("Authorization: Bearer s3cret\r\n"));
+        assert_eq!(requests[2].1, content);
+        assert!(Relay::new("udp://relay.example:9009").unwrap().pull_hosts().is_err());
+    }
+}
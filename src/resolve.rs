@@ -0,0 +1,403 @@
+//! Resolving the targets of a wake, e.g. `wol wake nas`, to hosts with their MAC address.
+//!
+//! A `Chain` asks its resolvers in order until one knows the target. The sources of the `wol`
+//! binary, see `Source`, are the hosts file, the neighbor table (ARP and NDP) for IP addresses,
+//! the leases of a DHCP server for host names and IP addresses, and DNS for host names, whose
+//! addresses are then looked up in the neighbor table. Applications can add resolvers of their
+//! own, e.g. a `Static` list of hosts.
+//!
+//! MAC addresses need no resolving and are not passed to the chain.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use hosts::{Host, Hosts};
+use neighbors::{self, NeighborAddr};
+use MacAddr;
+
+/// The leases files of dnsmasq and ISC dhcpd, the first existing of which is read by default.
+pub const DEFAULT_LEASES: [&str; 3] = ["/var/lib/misc/dnsmasq.leases",
+                                       "/var/lib/dhcp/dhcpd.leases",
+                                       "/var/db/dhcpd.leases"];
+
+/// Resolves targets to hosts.
+pub trait Resolver: fmt::Debug + Send + Sync {
+    /// Returns the host `target` stands for, or `None` if this resolver does not know it.
+    fn resolve(&self, target: &str) -> io::Result<Option<Host>>;
+
+    /// Returns what the resolver asks, for messages, e.g. "the neighbor table".
+    fn name(&self) -> String;
+}
+
+impl Resolver for Hosts {
+    /// Resolves the names of the hosts.
+    fn resolve(&self, target: &str) -> io::Result<Option<Host>> {
+        Ok(self.get(target).cloned())
+    }
+
+    fn name(&self) -> String {
+        "the hosts file".to_string()
+    }
+}
+
+/// Resolves the names of a fixed list of hosts.
+#[derive(Debug, Clone, Default)]
+pub struct Static {
+    hosts: Vec<Host>,
+}
+
+impl Static {
+    /// Creates a resolver for the given hosts.
+    pub fn new(hosts: Vec<Host>) -> Static {
+        Static { hosts }
+    }
+}
+
+impl Resolver for Static {
+    fn resolve(&self, target: &str) -> io::Result<Option<Host>> {
+        Ok(self.hosts.iter().find(|host| host.name == target).cloned())
+    }
+
+    fn name(&self) -> String {
+        "the static hosts".to_string()
+    }
+}
+
+/// Resolves IP addresses, with the interface of link-local IPv6 addresses, by the neighbor
+/// table of the system, see `neighbors::lookup`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Neighbors;
+
+impl Resolver for Neighbors {
+    fn resolve(&self, target: &str) -> io::Result<Option<Host>> {
+        let addr: NeighborAddr = match target.parse() {
+            Ok(addr) => addr,
+            Err(_) => return Ok(None),
+        };
+        Ok(neighbors::lookup(&addr)?.map(|mac| host(target, mac, addr.ip)))
+    }
+
+    fn name(&self) -> String {
+        "the neighbor table".to_string()
+    }
+}
+
+/// Resolves host names by DNS and their addresses by the neighbor table. Only hosts seen in the
+/// last minutes are in the table, so this mostly helps right after a host went to sleep.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Dns;
+
+impl Resolver for Dns {
+    fn resolve(&self, target: &str) -> io::Result<Option<Host>> {
+        if target.parse::<IpAddr>().is_ok() {
+            return Ok(None);
+        }
+        // Unknown names are no error, another resolver may know them.
+        let addresses = match (target, 0).to_socket_addrs() {
+            Ok(addresses) => addresses,
+            Err(err) => {
+                debug!("cannot resolve {} by DNS: {}", target, err);
+                return Ok(None);
+            },
+        };
+        for address in addresses {
+            let addr = NeighborAddr {
+                ip: address.ip(),
+                interface: None,
+            };
+            match neighbors::lookup(&addr)? {
+                Some(mac) => return Ok(Some(host(target, mac, address.ip()))),
+                None => debug!("{} is {}, which is not in the neighbor table", target, addr),
+            }
+        }
+        Ok(None)
+    }
+
+    fn name(&self) -> String {
+        "DNS".to_string()
+    }
+}
+
+/// A lease of a DHCP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub ip: IpAddr,
+    pub mac: MacAddr,
+    /// The host name the client sent, if any.
+    pub hostname: Option<String>,
+}
+
+/// Parses the leases file of dnsmasq, with lines like
+/// `1700000000 00:11:22:33:44:55 192.168.1.10 nas *`, or of ISC dhcpd, with blocks like
+/// `lease 192.168.1.10 { hardware ethernet 00:11:22:33:44:55; client-hostname "nas"; }`.
+/// Entries which cannot be read are skipped.
+pub fn parse_leases(s: &str) -> Vec<Lease> {
+    if s.lines().any(|line| line.trim_start().starts_with("lease ")) {
+        return parse_dhcpd_leases(s);
+    }
+    s.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            Some(Lease {
+                mac: fields[1].parse().ok()?,
+                ip: fields[2].parse().ok()?,
+                hostname: Some(fields[3]).filter(|name| *name != "*").map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+fn parse_dhcpd_leases(s: &str) -> Vec<Lease> {
+    let mut leases = Vec::new();
+    let mut current: Option<(Option<IpAddr>, Option<MacAddr>, Option<String>)> = None;
+    for line in s.lines() {
+        let line = line.trim().trim_end_matches(';');
+        if let Some(rest) = line.strip_prefix("lease ") {
+            current = Some((rest.trim_end_matches('{').trim().parse().ok(), None, None));
+        } else if line == "}" {
+            if let Some((Some(ip), Some(mac), hostname)) = current.take() {
+                leases.push(Lease { ip, mac, hostname });
+            }
+        } else if let Some((_, ref mut mac, ref mut hostname)) = current {
+            if let Some(value) = line.strip_prefix("hardware ethernet ") {
+                *mac = value.parse().ok();
+            } else if let Some(value) = line.strip_prefix("client-hostname ") {
+                *hostname = Some(value.trim_matches('"').to_string());
+            }
+        }
+    }
+    leases
+}
+
+/// Resolves host names and IP addresses by the leases file of a DHCP server, see
+/// `parse_leases`. The file is read on every lookup, as the server keeps changing it.
+#[derive(Debug, Clone)]
+pub struct Leases {
+    path: PathBuf,
+}
+
+impl Leases {
+    /// Creates a resolver reading the given leases file.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Leases {
+        Leases { path: path.into() }
+    }
+
+    /// Creates a resolver reading the first existing file of `DEFAULT_LEASES`, if any.
+    pub fn find() -> Option<Leases> {
+        DEFAULT_LEASES.iter().map(Path::new).find(|path| path.exists()).map(Leases::new)
+    }
+
+    /// Returns the path of the leases file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Resolver for Leases {
+    fn resolve(&self, target: &str) -> io::Result<Option<Host>> {
+        let leases = parse_leases(&fs::read_to_string(&self.path)?);
+        let ip = target.parse::<IpAddr>().ok();
+        // The last lease is the most recent one.
+        let lease = leases.iter().rev().find(|lease| {
+            Some(lease.ip) == ip ||
+            lease.hostname.as_ref().is_some_and(|name| name.eq_ignore_ascii_case(target))
+        });
+        Ok(lease.map(|lease| host(target, lease.mac, lease.ip)))
+    }
+
+    fn name(&self) -> String {
+        format!("the DHCP leases {}", self.path.display())
+    }
+}
+
+/// Returns a host named after the target with the address it was resolved by.
+fn host(target: &str, mac: MacAddr, ip: IpAddr) -> Host {
+    Host {
+        ip: Some(ip),
+        ..Host::new(target, mac)
+    }
+}
+
+/// The resolvers of the `wol` binary, which can be chosen in the configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The hosts file.
+    Hosts,
+    /// The neighbor table, see `Neighbors`.
+    Arp,
+    /// The leases file of a DHCP server, see `Leases`.
+    Leases,
+    /// DNS, see `Dns`.
+    Dns,
+}
+
+/// The sources used unless the configuration chooses others.
+pub const DEFAULT_SOURCES: [Source; 2] = [Source::Hosts, Source::Arp];
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Source::Hosts => "hosts",
+            Source::Arp => "arp",
+            Source::Leases => "leases",
+            Source::Dns => "dns",
+        })
+    }
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Source, String> {
+        match s {
+            "hosts" => Ok(Source::Hosts),
+            "arp" => Ok(Source::Arp),
+            "leases" => Ok(Source::Leases),
+            "dns" => Ok(Source::Dns),
+            _ => Err(format!("unknown resolver '{}', expected hosts, arp, leases or dns", s)),
+        }
+    }
+}
+
+/// Resolvers which are asked in order.
+///
+/// #Example
+///
+/// ```
+/// use wol::hosts::Host;
+/// use wol::resolve::{Chain, Static};
+/// use wol::MacAddr;
+///
+/// let nas = Host::new("nas", MacAddr::new([0, 1, 2, 3, 4, 5]));
+/// let chain = Chain::new().with_resolver(Static::new(vec![nas]));
+/// assert_eq!(chain.resolve("nas").unwrap().unwrap().mac, MacAddr::new([0, 1, 2, 3, 4, 5]));
+/// assert!(chain.resolve("desktop").unwrap().is_none());
+/// ```
+#[derive(Debug, Default)]
+pub struct Chain {
+    resolvers: Vec<Box<dyn Resolver>>,
+}
+
+impl Chain {
+    /// Creates a chain without resolvers, which knows no target.
+    pub fn new() -> Chain {
+        Chain::default()
+    }
+
+    /// Creates the chain of the given sources, with the hosts of the hosts file and the leases
+    /// file given or found by `Leases::find`. Leases are skipped if there is no leases file.
+    pub fn from_sources(sources: &[Source], hosts: &Hosts, leases: Option<&Path>) -> Chain {
+        let mut chain = Chain::new();
+        for source in sources {
+            chain = match *source {
+                Source::Hosts => chain.with_resolver(hosts.clone()),
+                Source::Arp => chain.with_resolver(Neighbors),
+                Source::Leases => match leases.map(Leases::new).or_else(Leases::find) {
+                    Some(leases) => chain.with_resolver(leases),
+                    None => {
+                        debug!("no DHCP leases file found, skipping the leases resolver");
+                        chain
+                    },
+                },
+                Source::Dns => chain.with_resolver(Dns),
+            };
+        }
+        chain
+    }
+
+    /// Appends a resolver, which is asked if the previous ones do not know a target.
+    pub fn with_resolver<R: Resolver + 'static>(mut self, resolver: R) -> Chain {
+        self.resolvers.push(Box::new(resolver));
+        self
+    }
+
+    /// Returns the names of the resolvers, see `Resolver::name`.
+    pub fn names(&self) -> Vec<String> {
+        self.resolvers.iter().map(|resolver| resolver.name()).collect()
+    }
+
+    /// Returns the host of the first resolver which knows `target`. Resolvers which fail, e.g.
+    /// as their file cannot be read, are skipped with a warning.
+    pub fn resolve(&self, target: &str) -> io::Result<Option<Host>> {
+        for resolver in &self.resolvers {
+            match resolver.resolve(target) {
+                Ok(Some(host)) => {
+                    debug!("resolved {} to {} by {}", target, host.mac, resolver.name());
+                    return Ok(Some(host));
+                },
+                Ok(None) => {},
+                Err(err) => warn!("cannot resolve {} by {}: {}", target, resolver.name(), err),
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io;
+    use std::process;
+
+    use hosts::{Host, Hosts};
+    use MacAddr;
+    use super::{parse_leases, Chain, Leases, Resolver, Source, Static};
+
+    #[test]
+    fn test_parse_leases() {
+        let dnsmasq = "1700000000 00:11:22:33:44:55 192.168.1.10 nas 01:00:11:22:33:44:55\n\
+                       1700000100 00:11:22:33:44:66 192.168.1.11 * *\n\
+                       garbage\n";
+        let leases = parse_leases(dnsmasq);
+        assert_eq!(leases.len(), 2);
+        assert_eq!(leases[0].hostname.as_deref(), Some("nas"));
+        assert_eq!(leases[1].hostname, None);
+        assert_eq!(leases[1].ip, "192.168.1.11".parse::<::std::net::IpAddr>().unwrap());
+
+        let dhcpd = "# The format of this file is documented in dhcpd.leases(5).\n\
+                     lease 192.168.1.20 {\n  starts 3 2023/11/14 22:13:20;\n  \
+                     hardware ethernet 00:11:22:33:44:77;\n  client-hostname \"vm-host\";\n}\n\
+                     lease 192.168.1.21 {\n  binding state free;\n}\n";
+        let leases = parse_leases(dhcpd);
+        assert_eq!(leases.len(), 1);
+        assert_eq!(leases[0].mac, MacAddr::new([0, 0x11, 0x22, 0x33, 0x44, 0x77]));
+        assert_eq!(leases[0].hostname.as_deref(), Some("vm-host"));
+    }
+
+    #[test]
+    fn test_chain() {
+        let path = env::temp_dir().join(format!("wol-leases-{}", process::id()));
+        fs::write(&path, "1700000000 00:11:22:33:44:55 192.168.1.10 NAS *\n").unwrap();
+        let hosts: Hosts = "[desktop]\nmac = \"00:11:22:33:44:66\"".parse().unwrap();
+        let chain = Chain::from_sources(&[Source::Hosts, Source::Leases], &hosts, Some(&path));
+        assert_eq!(chain.resolve("desktop").unwrap().unwrap().name, "desktop");
+        let nas = chain.resolve("nas").unwrap().unwrap();
+        assert_eq!((nas.name.as_str(), nas.ip), ("nas", Some("192.168.1.10".parse().unwrap())));
+        assert_eq!(chain.resolve("192.168.1.10").unwrap().unwrap().mac, nas.mac);
+        assert!(chain.resolve("printer").unwrap().is_none());
+
+        // A resolver which fails is skipped.
+        fs::remove_file(&path).unwrap();
+        let printer = Host::new("printer", MacAddr::new([0, 1, 2, 3, 4, 5]));
+        let chain = Chain::new()
+            .with_resolver(Leases::new(&path))
+            .with_resolver(Static::new(vec![printer]));
+        assert_eq!(Leases::new(&path).resolve("nas").unwrap_err().kind(),
+                   io::ErrorKind::NotFound);
+        assert!(chain.resolve("printer").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_parse_source() {
+        assert_eq!("arp".parse(), Ok(Source::Arp));
+        assert_eq!(Source::Leases.to_string(), "leases");
+        assert!("mdns".parse::<Source>().is_err());
+    }
+}
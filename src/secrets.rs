@@ -0,0 +1,165 @@
+//! Secrets kept out of the configuration and the hosts file, so that both can be committed, e.g.
+//! to a dotfiles repository, without exposing credentials.
+//!
+//! Wherever a secret is expected, i.e. the SecureOn `password` of a host, the `[relay] token`,
+//! the `token` of the `[tokens]` table and the SNMP `community` of the `[switch]` table, an
+//! inline table can reference it instead of giving it:
+//!
+//! ```toml
+//! [nas]
+//! mac = "00:11:22:33:44:55"
+//! password = { keyring = "nas" }
+//!
+//! [relay]
+//! token = { cmd = "age -d -i ~/.config/wol/key.txt ~/.config/wol/relay-token.age" }
+//! ```
+//!
+//! `keyring` looks the secret up in the keyring of the system under the service `wol` and the
+//! given account: in the Secret Service, e.g. the GNOME keyring or KWallet, with `secret-tool`
+//! on Linux and the BSDs, and in the login keychain with `security` on macOS. `cmd` runs a
+//! command with the shell and takes what it prints, without the trailing newline, e.g. of a
+//! password manager like `pass` or of `age`, `gpg` or `sops` decrypting an encrypted file.
+//!
+//! Secrets are read whenever the file referencing them is read, so that a reload of the daemon
+//! picks up a changed one.
+use std::fmt;
+use std::io;
+use std::process::{Command, Stdio};
+
+use toml::{self, Value};
+
+/// The service secrets are stored under in the keyring of the system.
+pub const KEYRING_SERVICE: &str = "wol";
+
+/// Where a secret is kept.
+///
+/// #Example
+///
+/// ```
+/// use wol::secrets::SecretRef;
+///
+/// let token = SecretRef::Command("echo s3cret".to_string());
+/// # #[cfg(unix)]
+/// assert_eq!(token.read().unwrap(), "s3cret");
+/// assert_eq!(SecretRef::Keyring("nas".to_string()).to_string(), "keyring account 'nas'");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretRef {
+    /// The account of the secret in the keyring of the system, under `KEYRING_SERVICE`.
+    Keyring(String),
+    /// A command printing the secret.
+    Command(String),
+}
+
+impl SecretRef {
+    /// Reads the secret. Fails with `io::ErrorKind::NotFound` if the keyring has no such secret
+    /// or the tool to read it is not installed, and with `io::ErrorKind::Unsupported` for the
+    /// keyring of other systems.
+    pub fn read(&self) -> io::Result<String> {
+        let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let (mut command, tool) = match *self {
+            SecretRef::Keyring(ref account) => (keyring_command(account)?, keyring_tool()),
+            SecretRef::Command(ref command) => {
+                let mut child = Command::new(shell);
+                child.args([flag, command]);
+                (child, shell)
+            },
+        };
+        // Errors go to the terminal, as do the prompts of e.g. gpg.
+        let output = match command.stdin(Stdio::null()).stderr(Stdio::inherit()).output() {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                let message = format!("{} is not installed", tool);
+                return Err(io::Error::new(io::ErrorKind::NotFound, message));
+            },
+            output => output?,
+        };
+        if !output.status.success() {
+            let message = match *self {
+                SecretRef::Keyring(_) => format!("the keyring has no {}", self),
+                SecretRef::Command(_) => format!("the command failed with {}", output.status),
+            };
+            return Err(io::Error::new(io::ErrorKind::NotFound, message));
+        }
+        let secret = String::from_utf8(output.stdout)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "the secret is not UTF-8"))?;
+        Ok(secret.trim_end_matches(&['\r', '\n'][..]).to_string())
+    }
+}
+
+impl fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SecretRef::Keyring(ref account) => write!(f, "keyring account '{}'", account),
+            SecretRef::Command(ref command) => write!(f, "command '{}'", command),
+        }
+    }
+}
+
+/// Returns whether a configuration or hosts file references a secret by command, e.g. to refuse
+/// reading a file received from elsewhere, whose commands would run here. Files which cannot be
+/// parsed reference none, as nothing of them is read.
+pub fn references_command(content: &str) -> bool {
+    fn is_command(value: &Value) -> bool {
+        match *value {
+            Value::Table(ref keys) if keys.len() == 1 && keys[0].0 == "cmd" => true,
+            Value::Table(ref keys) => keys.iter().any(|(_, value)| is_command(value)),
+            _ => false,
+        }
+    }
+
+    toml::parse(content).is_ok_and(|entries| entries.iter().any(|entry| is_command(&entry.value)))
+}
+
+/// Returns the tool reading the keyring of this system.
+fn keyring_tool() -> &'static str {
+    if cfg!(target_os = "macos") { "security" } else { "secret-tool" }
+}
+
+/// Returns the command printing the secret of `account` in the keyring.
+#[cfg(target_os = "macos")]
+fn keyring_command(account: &str) -> io::Result<Command> {
+    let mut command = Command::new("security");
+    command.args(["find-generic-password", "-s", KEYRING_SERVICE, "-a", account, "-w"]);
+    Ok(command)
+}
+
+/// Returns the command printing the secret of `account` in the keyring.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn keyring_command(account: &str) -> io::Result<Command> {
+    let mut command = Command::new("secret-tool");
+    command.args(["lookup", "service", KEYRING_SERVICE, "account", account]);
+    Ok(command)
+}
+
+/// Returns the command printing the secret of `account` in the keyring.
+#[cfg(not(unix))]
+fn keyring_command(_account: &str) -> io::Result<Command> {
+    Err(io::Error::new(io::ErrorKind::Unsupported,
+                       "the keyring is only supported on Linux, the BSDs and macOS, use cmd"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{references_command, SecretRef};
+
+    #[test]
+    #[cfg(unix)]
+    fn test_read_command() {
+        let secret = SecretRef::Command("printf 's3cret\\n\\n'".to_string());
+        assert_eq!(secret.read().unwrap(), "s3cret");
+        let failing = SecretRef::Command("exit 3".to_string()).read().unwrap_err();
+        assert_eq!(failing.kind(), io::ErrorKind::NotFound);
+        assert!(failing.to_string().starts_with("the command failed with exit status: 3"));
+    }
+
+    #[test]
+    fn test_references_command() {
+        assert!(references_command("[nas]\npassword = { cmd = \"pass show nas\" }"));
+        assert!(!references_command("[nas]\npassword = { keyring = \"nas\" }"));
+        assert!(references_command("[tokens]\nguest = { token = { cmd = \"pass show g\" } }"));
+        assert!(!references_command("[nas]\nprobe = { type = \"command\", cmd = \"true\" }"));
+        assert!(!references_command("[nas\npassword = { cmd = \"pass show nas\" }"));
+    }
+}
@@ -0,0 +1,281 @@
+//! The IP addresses hosts were last seen at, so that hosts without an `ip` in the hosts file are
+//! still sent their packets directly and verified, and hosts whose address changed are found.
+//!
+//! An address is recorded whenever a host is seen up: when a verification probe reached it, when
+//! the neighbor table (ARP and NDP) has it while the host is up, or when it is imported from the
+//! leases of a DHCP server. The addresses are kept in the state directory next to the history,
+//! see `Paths::seen_file`, with one JSON object per MAC address and line, so that the hosts file
+//! with its comments is never rewritten. An `ip` in the hosts file takes precedence.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use hosts::Host;
+use neighbors::Neighbor;
+use output::{parse_timestamp, timestamp, Record, Value};
+use resolve::Lease;
+use MacAddr;
+
+/// How the address of a host was seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// A verification probe reached the host at the address.
+    Probe,
+    /// The neighbor table had the address while the host was up.
+    Arp,
+    /// A lease of the DHCP server had the address, see `resolve::parse_leases`.
+    Dhcp,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Source::Probe => "probe",
+            Source::Arp => "arp",
+            Source::Dhcp => "dhcp",
+        })
+    }
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Source, String> {
+        match s {
+            "probe" => Ok(Source::Probe),
+            "arp" => Ok(Source::Arp),
+            "dhcp" => Ok(Source::Dhcp),
+            _ => Err(format!("unknown source '{}', expected probe, arp or dhcp", s)),
+        }
+    }
+}
+
+/// The address a host was last seen at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sighting {
+    pub mac: MacAddr,
+    pub ip: IpAddr,
+    /// When the host was seen, as RFC 3339 timestamp in UTC.
+    pub time: String,
+    pub source: Source,
+}
+
+impl Sighting {
+    /// Creates a sighting of the host with the given MAC address just now.
+    pub fn new(mac: MacAddr, ip: IpAddr, source: Source) -> Sighting {
+        Sighting {
+            mac,
+            ip,
+            time: timestamp(SystemTime::now()),
+            source,
+        }
+    }
+
+    /// Returns the sighting of a host seen up: at the address a probe reached it at, if any, or
+    /// else at its address in the neighbor table, preferring IPv4, whose subnets can be sent to.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::neighbors::Neighbor;
+    /// use wol::seen::{Sighting, Source};
+    ///
+    /// let mac = "00:11:22:33:44:55".parse().unwrap();
+    /// let table = [Neighbor {
+    ///     ip: "192.168.1.42".parse().unwrap(),
+    ///     mac,
+    ///     interface: "eth0".to_string(),
+    /// }];
+    /// let sighting = Sighting::find(mac, None, &table).unwrap();
+    /// assert_eq!(sighting.ip.to_string(), "192.168.1.42");
+    /// assert_eq!(sighting.source, Source::Arp);
+    /// ```
+    pub fn find(mac: MacAddr, probed: Option<IpAddr>, neighbors: &[Neighbor]) -> Option<Sighting> {
+        if let Some(ip) = probed {
+            return Some(Sighting::new(mac, ip, Source::Probe));
+        }
+        let mut addresses: Vec<IpAddr> =
+            neighbors.iter().filter(|neighbor| neighbor.mac == mac).map(|n| n.ip).collect();
+        addresses.sort_by_key(|ip| !ip.is_ipv4());
+        addresses.first().map(|&ip| Sighting::new(mac, ip, Source::Arp))
+    }
+
+    /// Returns the sighting of the host of a DHCP lease.
+    pub fn from_lease(lease: &Lease) -> Sighting {
+        Sighting::new(lease.mac, lease.ip, Source::Dhcp)
+    }
+
+    /// Returns the time since the host was seen, `None` if it cannot be read or is in the future.
+    pub fn age(&self) -> Option<Duration> {
+        SystemTime::now().duration_since(parse_timestamp(&self.time)?).ok()
+    }
+
+    /// Converts the sighting into a record.
+    pub fn to_record(&self) -> Record {
+        Record::new()
+            .field("mac", self.mac.to_string())
+            .field("ip", self.ip.to_string())
+            .field("time", self.time.as_str())
+            .field("source", self.source.to_string())
+    }
+
+    /// Converts a record of `to_record` back, returning `None` if a field is missing or invalid.
+    pub fn from_record(record: &Record) -> Option<Sighting> {
+        let string = |name| match record.get(name) {
+            Some(Value::Str(s)) => Some(s.as_str()),
+            _ => None,
+        };
+        Some(Sighting {
+            mac: string("mac")?.parse().ok()?,
+            ip: string("ip")?.parse().ok()?,
+            time: string("time")?.to_string(),
+            source: string("source")?.parse().ok()?,
+        })
+    }
+}
+
+/// The file of the addresses hosts were last seen at, which can be shared between threads.
+#[derive(Debug)]
+pub struct Seen {
+    path: PathBuf,
+    /// Keeps the threads of this process from rewriting the file at the same time.
+    lock: Mutex<()>,
+}
+
+impl Seen {
+    /// Uses the file at the given path, which is created with its directory on the first record.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Seen {
+        Seen {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the last sighting of every host. Lines which cannot be read are skipped, and a
+    /// missing file has no sightings.
+    pub fn sightings(&self) -> io::Result<Vec<Sighting>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        Ok(content.lines()
+            .filter_map(|line| line.parse().ok())
+            .filter_map(|record| Sighting::from_record(&record))
+            .collect())
+    }
+
+    /// Returns the last sighting of the host with the given MAC address.
+    pub fn get(&self, mac: MacAddr) -> io::Result<Option<Sighting>> {
+        Ok(self.sightings()?.into_iter().find(|sighting| sighting.mac == mac))
+    }
+
+    /// Records sightings, replacing the earlier ones of their hosts.
+    pub fn record(&self, sightings: &[Sighting]) -> io::Result<()> {
+        if sightings.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut all = self.sightings()?;
+        for sighting in sightings {
+            all.retain(|seen| seen.mac != sighting.mac);
+            all.push(sighting.clone());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Replaced at once, so that other processes never read half of the file.
+        let temp = self.path.with_extension("tmp");
+        let lines: String =
+            all.iter().map(|seen| format!("{}\n", seen.to_record().to_json())).collect();
+        fs::write(&temp, lines)?;
+        fs::rename(&temp, &self.path)
+    }
+
+    /// Gives the hosts without IP address the address they were last seen at, and returns how
+    /// many got one.
+    pub fn fill<'a, I>(&self, hosts: I) -> io::Result<usize>
+        where I: IntoIterator<Item = &'a mut Host>
+    {
+        let sightings = self.sightings()?;
+        let mut filled = 0;
+        for host in hosts.into_iter().filter(|host| host.ip.is_none()) {
+            if let Some(sighting) = sightings.iter().find(|seen| seen.mac == host.mac) {
+                debug!("{} was last seen at {} ({})", host.name, sighting.ip, sighting.source);
+                host.ip = Some(sighting.ip);
+                filled += 1;
+            }
+        }
+        Ok(filled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use hosts::Host;
+    use neighbors::Neighbor;
+    use MacAddr;
+    use super::{Seen, Sighting, Source};
+
+    fn mac(last: u8) -> MacAddr {
+        MacAddr::new([0, 0, 0, 0, 0, last])
+    }
+
+    #[test]
+    fn test_find() {
+        let neighbor = |ip: &str, last| {
+            Neighbor {
+                ip: ip.parse().unwrap(),
+                mac: mac(last),
+                interface: "eth0".to_string(),
+            }
+        };
+        let table =
+            [neighbor("fd00::2", 2), neighbor("192.168.1.2", 2), neighbor("192.168.1.3", 3)];
+        let probed = Sighting::find(mac(2), Some("192.168.1.20".parse().unwrap()), &table).unwrap();
+        assert_eq!((probed.ip, probed.source), ("192.168.1.20".parse().unwrap(), Source::Probe));
+        let arp = Sighting::find(mac(2), None, &table).unwrap();
+        assert_eq!((arp.ip, arp.source), ("192.168.1.2".parse().unwrap(), Source::Arp));
+        assert_eq!(Sighting::find(mac(4), None, &table), None);
+    }
+
+    #[test]
+    fn test_record() {
+        let dir = env::temp_dir().join(format!("wol-seen-{}", process::id()));
+        let seen = Seen::new(dir.join("state").join("seen.jsonl"));
+        assert_eq!(seen.sightings().unwrap(), []);
+        let nas = Sighting::new(mac(1), "192.168.1.20".parse().unwrap(), Source::Dhcp);
+        let desktop = Sighting::new(mac(2), "192.168.1.10".parse().unwrap(), Source::Arp);
+        seen.record(&[nas.clone(), desktop.clone()]).unwrap();
+        let moved = [Sighting::new(mac(1), "192.168.1.21".parse().unwrap(), Source::Probe)];
+        seen.record(&moved).unwrap();
+        assert_eq!(seen.sightings().unwrap(), [desktop, moved[0].clone()]);
+        assert_eq!(seen.get(mac(1)).unwrap().as_ref(), moved.first());
+        assert!(moved[0].age().is_some());
+
+        let pc = Host {
+            ip: Some("10.0.0.2".parse().unwrap()),
+            ..Host::new("pc", mac(2))
+        };
+        let mut hosts = vec![Host::new("nas", mac(1)), pc, Host::new("printer", mac(3))];
+        assert_eq!(seen.fill(&mut hosts).unwrap(), 1);
+        let ips: Vec<String> =
+            hosts.iter().map(|host| host.ip.map(|ip| ip.to_string()).unwrap_or_default()).collect();
+        assert_eq!(ips, ["192.168.1.21", "10.0.0.2", ""]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
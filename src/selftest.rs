@@ -0,0 +1,193 @@
+//! End-to-end tests of the path magic packets take, to validate firewalls and bridges before
+//! blaming the network card of a host.
+//!
+//! `local` sends a magic packet and receives it with a `Listener` on the same machine; `remote`
+//! expects a listener on another machine, started with `wol listen --echo`, to send it back.
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use listen::{Listener, Source};
+use {MagicPacket, WolClient};
+
+/// How long to wait for the packet by default.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often `local` checks whether the packet arrived.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The result of a self test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The packet arrived intact from `source`, `elapsed` after it was sent.
+    Passed { source: Source, elapsed: Duration },
+    /// Only other magic packets arrived, e.g. with another MAC address or password; `received`
+    /// is the last of them.
+    Mismatch { source: Source, received: MagicPacket },
+    /// No magic packet arrived before the timeout.
+    Missing,
+}
+
+impl Outcome {
+    /// Returns whether the test passed.
+    pub fn passed(&self) -> bool {
+        matches!(*self, Outcome::Passed { .. })
+    }
+
+    /// Returns the name of the outcome, "passed", "mismatch" or "missing".
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Outcome::Passed { .. } => "passed",
+            Outcome::Mismatch { .. } => "mismatch",
+            Outcome::Missing => "missing",
+        }
+    }
+}
+
+/// Sends the packet with the client and waits up to `timeout` for the listener to receive it.
+///
+/// #Example
+///
+/// ```
+/// use std::time::Duration;
+/// use wol::listen::Listener;
+/// use wol::{selftest, MacAddr, MagicPacket, WolClient};
+///
+/// let listener = Listener::bind("127.0.0.1:0").unwrap();
+/// let client = WolClient::new(listener.local_addr().unwrap());
+/// let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+/// let outcome = selftest::local(&client, &packet, listener, Duration::from_secs(5)).unwrap();
+/// assert!(outcome.passed());
+/// ```
+pub fn local(client: &WolClient,
+             packet: &MagicPacket,
+             listener: Listener,
+             timeout: Duration)
+             -> io::Result<Outcome> {
+    let stop = AtomicBool::new(false);
+    let outcome = Mutex::new(Outcome::Missing);
+    let start = Instant::now();
+    thread::scope(|scope| {
+        let receiving = scope.spawn(|| {
+            listener.run(&stop, |received| {
+                let mut outcome = outcome.lock().unwrap();
+                if received.packet == *packet {
+                    *outcome = Outcome::Passed {
+                        source: received.source,
+                        elapsed: start.elapsed(),
+                    };
+                    stop.store(true, Ordering::SeqCst);
+                } else if !outcome.passed() {
+                    *outcome = Outcome::Mismatch {
+                        source: received.source,
+                        received: received.packet,
+                    };
+                }
+            })
+        });
+        let sent = client.send(packet);
+        while sent.is_ok() && !stop.load(Ordering::SeqCst) && start.elapsed() < timeout {
+            thread::sleep(POLL_INTERVAL);
+        }
+        stop.store(true, Ordering::SeqCst);
+        receiving.join().unwrap()?;
+        sent.map(|_| ())
+    })?;
+    Ok(outcome.into_inner().unwrap())
+}
+
+/// Sends the packet with the client and waits up to `timeout` for a listener started with
+/// `wol listen --echo` to send it back.
+pub fn remote(client: &WolClient, packet: &MagicPacket, timeout: Duration) -> io::Result<Outcome> {
+    let (socket, destination) = client.open_socket()?;
+    let start = Instant::now();
+    for _ in 0..client.repeat() {
+        socket.send_to(&packet.payload(), destination)?;
+    }
+    debug!("sent {} to {}, waiting for the echo", packet, destination);
+    let mut outcome = Outcome::Missing;
+    let mut buf = [0; 1500];
+    loop {
+        let remaining = timeout.saturating_sub(start.elapsed());
+        if remaining == Duration::from_secs(0) {
+            return Ok(outcome);
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let (len, source): (usize, SocketAddr) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock ||
+                            err.kind() == io::ErrorKind::TimedOut => return Ok(outcome),
+            Err(err) => return Err(err),
+        };
+        match MagicPacket::parse(&buf[..len]) {
+            Some(received) if received == *packet => {
+                return Ok(Outcome::Passed {
+                    source: Source::Udp(source),
+                    elapsed: start.elapsed(),
+                });
+            },
+            Some(received) => {
+                outcome = Outcome::Mismatch {
+                    source: Source::Udp(source),
+                    received,
+                }
+            },
+            None => trace!("ignoring {} bytes from {}", len, source),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use listen::Listener;
+    use {MacAddr, MagicPacket, WolClient};
+
+    use super::{local, remote, Outcome};
+
+    #[test]
+    fn test_local_missing_and_mismatch() {
+        let packet = MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]));
+        let timeout = Duration::from_millis(300);
+        // The packet is sent elsewhere, e.g. a firewall dropped it.
+        let elsewhere = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = WolClient::new(elsewhere.local_addr().unwrap());
+        let listener = Listener::bind("127.0.0.1:0").unwrap();
+        assert_eq!(local(&client, &packet, listener, timeout).unwrap(), Outcome::Missing);
+        // Another magic packet, already waiting when the listener starts.
+        let listener = Listener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let other = packet.with_password([1; 6]);
+        elsewhere.send_to(&other.to_bytes(), address).unwrap();
+        match local(&client, &packet, listener, timeout).unwrap() {
+            Outcome::Mismatch { received, .. } => assert_eq!(received, other),
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_remote_echo() {
+        let listener = Listener::bind("127.0.0.1:0").unwrap().with_echo(true);
+        let client = WolClient::new(listener.local_addr().unwrap());
+        let packet = MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]));
+        let stop = AtomicBool::new(false);
+        let outcome = thread::scope(|scope| {
+            scope.spawn(|| listener.run(&stop, |_| {}).unwrap());
+            let outcome = remote(&client, &packet, Duration::from_secs(5)).unwrap();
+            stop.store(true, Ordering::SeqCst);
+            outcome
+        });
+        assert!(outcome.passed());
+        let silent = Listener::bind("127.0.0.1:0").unwrap();
+        let client = WolClient::new(silent.local_addr().unwrap());
+        let outcome = remote(&client, &packet, Duration::from_millis(200)).unwrap();
+        assert_eq!(outcome, Outcome::Missing);
+    }
+}
@@ -0,0 +1,1676 @@
+//! A small HTTP daemon to wake hosts remotely.
+//!
+//! The daemon answers the following requests:
+//!
+//! * `POST /wake/<MAC>` sends a magic packet for the given MAC address, or the name of a known
+//!   host, and answers with the result as a JSON object. Known hosts are sent to with their own
+//!   send options, which `?port=`, `?repeat=` and `?burst=` override, parsed like the options
+//!   of the command line, see `units`. With `?verify=<IP>`, the answer waits until the host is
+//!   up at IP or the verification timed out, and holds the boot time, which also goes to the
+//!   metrics.
+//! * `GET /metrics` returns the counters of the daemon in the Prometheus text format.
+//! * `GET /events` streams the events of all wake requests as server-sent events until the
+//!   client closes the connection, see the `events` module.
+//! * `GET /healthz` answers 200 as long as the daemon runs, for liveness probes.
+//! * `GET /readyz` answers 200 if the daemon can wake hosts, i.e. it can open the socket magic
+//!   packets are sent from and its last reload did not fail, and 503 otherwise, for readiness
+//!   probes. Neither needs the token.
+//! * `GET /requests` returns the last wake requests with their client, token, user agent, target
+//!   and result as a JSON array, oldest first, for troubleshooting. Every wake request is logged
+//!   with these fields as well.
+//! * `POST /reload` replaces the `Settings` of the daemon, e.g. with those of the changed
+//!   configuration and hosts file, see `Server::reload_with`. On Unix, SIGHUP does the same.
+//! * `GET /hosts` returns the hosts file of the daemon as it is, with its comments, and
+//!   `PUT /hosts` replaces it with the body of the request and reloads the daemon, so that
+//!   several instances of wol can share one hosts file, see `Server::hosts_file` and
+//!   `Relay::pull_hosts`. Both need an admin token, and are refused without tokens. Hosts files
+//!   referencing secrets by command are refused, see `secrets`.
+//!
+//! Concurrent requests to wake the same host, with the same verification, share one magic
+//! packet and its result, which holds `"coalesced":true` for all but the first request.
+//!
+//! A host woken within its cooldown, see `Server::cooldown`, is not woken again; the answer
+//! holds `"cooldown":true` instead of the result of sending.
+//!
+//! With tokens, wake and reload requests must carry one of them in an `Authorization: Bearer
+//! <token>` header, so that the daemon can be reached from outside the LAN, e.g. by
+//! `wol wake --via`. A token may only wake its hosts, and only reload the daemon with the admin
+//! scope, see `tokens`; other requests are answered with 403. With tokens, `GET /requests` and
+//! `GET /events` need an admin token as well. Clients which cannot speak HTTP can send requests
+//! signed with a shared key to a UDP socket instead, see the `signed` module.
+//!
+//! Every connection is handled on its own thread and closed after one request or the end of the
+//! event stream. At most `DEFAULT_MAX_CONNECTIONS` connections, event streams included, are open
+//! at once, see `Server::max_connections`; further ones are answered with 503 and `BUSY` right
+//! away, so that slow or many clients cannot exhaust the threads of the machine.
+//!
+//! With `Server::shutdown_on`, the daemon stops once the flag is set, e.g. on SIGTERM or ctrl-C:
+//! it closes its sockets, waits up to the drain timeout for the requests in progress to be
+//! answered and then cancels the verifications still waiting for their hosts, which are answered
+//! and recorded in the history as sent but not verified. Event streams end as well. The audit
+//! log is written as each packet is sent, so nothing of it is lost.
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use audit::{AuditEvent, AuditLog, Initiator};
+use error::ErrorCode;
+use events::{self, EventBus};
+use history::{History, Wake};
+use hosts::{Host, Hosts};
+use interrupt;
+use log::Level;
+use metrics::Metrics;
+use output;
+use output::{Record, Value};
+use resolve::Chain;
+use secrets;
+use signed::{self, Verifier};
+use tokens::{self, ApiToken, Scope};
+use transport::Bursts;
+use units::{self, Port, RepeatCount};
+use verify::{self, Method};
+use {MacAddr, SendReport, WolClient};
+
+/// The maximum size of the request line and headers.
+const MAX_HEADER_SIZE: usize = 8 * 1024;
+
+/// The maximum size of a request body.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// How long to wait for a client to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to send a comment to event stream clients while there are no events, so that
+/// proxies keep the connection open and clients which went away are noticed.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A parsed HTTP request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Returns the value of the first header with the given name, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// An HTTP response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+    /// The code of the error the response reports, if any, e.g. "VERIFY_TIMEOUT".
+    pub code: Option<String>,
+}
+
+impl Response {
+    /// Creates a JSON response.
+    pub fn json(status: u16, body: String) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body,
+            code: None,
+        }
+    }
+
+    /// Creates a plain text response.
+    pub fn text(status: u16, body: String) -> Response {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body,
+            code: None,
+        }
+    }
+
+    /// Creates a JSON response with an error message and its code.
+    pub fn error(status: u16, code: ErrorCode, message: &str) -> Response {
+        let record = Record::new().field("error", message).field("code", code.as_str());
+        Response {
+            code: Some(code.as_str().to_string()),
+            ..Response::json(status, record.to_json())
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            500 => "Internal Server Error",
+            503 => "Service Unavailable",
+            _ => "",
+        }
+    }
+}
+
+/// Reads a single HTTP/1.x request.
+pub fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    fn invalid(message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+    }
+
+    let mut remaining = MAX_HEADER_SIZE;
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        let n = reader.by_ref().take(remaining as u64).read_line(&mut line)?;
+        if n == 0 && remaining == 0 {
+            return Err(invalid("request header too large"));
+        } else if n == 0 {
+            return Err(invalid("connection closed before end of request"));
+        }
+        remaining -= n;
+        let line = line.trim_end_matches(&['\r', '\n'][..]).to_string();
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line);
+    }
+    let mut request_line = lines.first().ok_or_else(|| invalid("empty request"))?.split(' ');
+    let method = request_line.next().unwrap_or("").to_string();
+    let path = request_line.next().ok_or_else(|| invalid("missing request path"))?.to_string();
+    let mut headers = Vec::new();
+    for line in &lines[1..] {
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim().to_string();
+        let value = parts.next().ok_or_else(|| invalid("malformed header"))?.trim().to_string();
+        headers.push((name, value));
+    }
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: Vec::new(),
+    };
+    let length = match request.header("Content-Length") {
+        Some(length) => length.parse().map_err(|_| invalid("malformed Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_SIZE {
+        return Err(invalid("request body too large"));
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+/// Writes an HTTP/1.1 response and asks the client to close the connection.
+pub fn write_response<W: Write>(writer: &mut W, response: &Response) -> io::Result<()> {
+    write!(writer,
+           "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+           response.status,
+           response.reason(),
+           response.content_type,
+           response.body.len())?;
+    writer.write_all(response.body.as_bytes())?;
+    writer.flush()
+}
+
+/// How many wake requests `GET /requests` returns.
+const REQUEST_LOG_LEN: usize = 100;
+
+/// How often the daemon checks whether it received SIGHUP.
+const HANGUP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a daemon which is shutting down waits for the requests in progress by default.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many connections the daemon handles at once by default, each on its own thread.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 256;
+
+/// How often a daemon which can be shut down checks for connections and whether to stop.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the cancelled verifications get to answer their requests, once the drain timeout
+/// has passed. Probes notice the cancellation within a second.
+const CANCEL_GRACE: Duration = Duration::from_secs(3);
+
+/// The settings of a server which a reload replaces, usually read from the configuration and
+/// the hosts file.
+#[derive(Debug)]
+pub struct Settings {
+    /// The client sending magic packets to hosts which are not known or have no send options.
+    pub client: WolClient,
+    /// The hosts woken by name and with their send options, see `Host::client`.
+    pub hosts: Hosts,
+    /// Resolves targets which are neither a MAC address nor the name of a host.
+    pub resolver: Chain,
+    /// The ports probed to verify hosts and how long to wait for them.
+    pub probe: (Vec<u16>, Duration),
+    pub method: Method,
+    /// The bearer tokens of which wake and reload requests must carry one, if any.
+    pub tokens: Vec<ApiToken>,
+    /// The cooldown of hosts without one of their own.
+    pub cooldown: Option<Duration>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            client: WolClient::v6(),
+            hosts: Hosts::default(),
+            resolver: Chain::new(),
+            probe: (vec![verify::DEFAULT_PORT], verify::DEFAULT_TIMEOUT),
+            method: Method::Tcp,
+            tokens: Vec::new(),
+            cooldown: None,
+        }
+    }
+}
+
+/// Returns new settings for a reload, or why they cannot be read.
+type Reload = dyn Fn() -> Result<Settings, String> + Send + Sync;
+
+/// The state shared by all connections of a server.
+struct State {
+    /// The settings, replaced as a whole on reload, so that requests in progress finish with
+    /// those they started with.
+    settings: RwLock<Arc<Settings>>,
+    reload: Option<Box<Reload>>,
+    /// The hosts file shared by `GET /hosts` and `PUT /hosts`, if any.
+    hosts_file: Option<PathBuf>,
+    /// Why the last reload failed, until one succeeds.
+    reload_error: Mutex<Option<String>>,
+    metrics: Metrics,
+    events: EventBus,
+    /// The last wake requests, for `GET /requests`.
+    requests: Mutex<VecDeque<Record>>,
+    audit_log: Option<AuditLog>,
+    history: Option<History>,
+    /// When a magic packet was last sent to each MAC address, for the cooldowns.
+    last_sent: Mutex<HashMap<MacAddr, Instant>>,
+    /// The wakes in progress by MAC address and verification address, which later requests for
+    /// the same host wait for instead of sending again.
+    flights: Mutex<HashMap<FlightKey, Arc<Flight>>>,
+    /// The number of requests being handled, without event streams.
+    active: AtomicUsize,
+    /// The number of open connections, with event streams, each on its own thread.
+    connections: AtomicUsize,
+    /// Set once the daemon shuts down, which ends event streams and signed requests.
+    stopping: AtomicBool,
+    /// Set once the drain timeout passed, which cancels the verifications in progress.
+    cancel: AtomicBool,
+}
+
+impl State {
+    fn new(settings: Settings) -> State {
+        State {
+            settings: RwLock::new(Arc::new(settings)),
+            reload: None,
+            hosts_file: None,
+            reload_error: Mutex::new(None),
+            metrics: Metrics::new(),
+            events: EventBus::new(),
+            requests: Mutex::new(VecDeque::new()),
+            audit_log: None,
+            history: None,
+            last_sent: Mutex::new(HashMap::new()),
+            flights: Mutex::new(HashMap::new()),
+            active: AtomicUsize::new(0),
+            connections: AtomicUsize::new(0),
+            stopping: AtomicBool::new(false),
+            cancel: AtomicBool::new(false),
+        }
+    }
+
+    /// Counts a request as being handled until the returned guard is dropped.
+    fn begin(&self) -> Active<'_> {
+        self.active.fetch_add(1, Ordering::SeqCst);
+        Active(&self.active)
+    }
+
+    /// Returns the current settings.
+    fn settings(&self) -> Arc<Settings> {
+        self.settings.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Returns the settings to change before the server runs, when they are not shared yet.
+    fn settings_mut(&mut self) -> &mut Settings {
+        let settings = self.settings.get_mut().unwrap_or_else(|e| e.into_inner());
+        Arc::get_mut(settings).expect("the settings are shared once the server runs")
+    }
+}
+
+/// A request being handled, see `State::begin`, or an open connection.
+struct Active<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for Active<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The MAC address, the verification address and the send parameters of a wake in progress.
+type FlightKey = (MacAddr, Option<IpAddr>, SendParams);
+
+/// The parameters of a wake request overriding how the packet is sent, see `send_params`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+struct SendParams {
+    port: Option<Port>,
+    repeat: Option<RepeatCount>,
+    bursts: Option<Bursts>,
+}
+
+impl SendParams {
+    /// Returns `client` sending with the parameters.
+    fn apply(self, mut client: WolClient) -> WolClient {
+        if let Some(port) = self.port {
+            client = client.with_port(port.get());
+        }
+        if let Some(repeat) = self.repeat {
+            client = client.with_repeat(repeat.get());
+        }
+        if let Some(bursts) = self.bursts {
+            client = client.with_bursts(bursts);
+        }
+        client
+    }
+}
+
+/// A wake in progress, whose status and result are set once it is done.
+#[derive(Debug, Default)]
+struct Flight {
+    result: Mutex<Option<(u16, Record)>>,
+    done: Condvar,
+}
+
+/// The HTTP daemon.
+pub struct Server {
+    listener: TcpListener,
+    /// The socket receiving signed wake requests and their verifier.
+    signed: Option<(UdpSocket, Verifier)>,
+    /// The flag stopping the daemon, and how long it waits for the requests in progress then.
+    shutdown: Option<&'static AtomicBool>,
+    drain_timeout: Duration,
+    max_connections: usize,
+    state: State,
+}
+
+impl Server {
+    /// Binds the daemon to the given address.
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Server> {
+        let listener = TcpListener::bind(address)?;
+        Ok(Server {
+            listener,
+            signed: None,
+            shutdown: None,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            state: State::new(Settings::default()),
+        })
+    }
+
+    /// Sends magic packets per IPv4 instead of IPv6.
+    pub fn ipv4(mut self, use_v4: bool) -> Server {
+        self.state.settings_mut().client = if use_v4 { WolClient::v4() } else { WolClient::v6() };
+        self
+    }
+
+    /// Sends magic packets with the given client, e.g. to use another port.
+    pub fn client(mut self, client: WolClient) -> Server {
+        self.state.settings_mut().client = client;
+        self
+    }
+
+    /// Verifies hosts for requests with `?verify=<IP>` with the given method, probing the TCP
+    /// ports in order and waiting at most `timeout`.
+    pub fn verify(mut self, ports: &[u16], timeout: Duration, method: Method) -> Server {
+        let settings = self.state.settings_mut();
+        settings.probe = (ports.to_vec(), timeout);
+        settings.method = method;
+        self
+    }
+
+    /// Wakes the given hosts by name and with their send options, see `Host::client`.
+    pub fn hosts(mut self, hosts: Hosts) -> Server {
+        self.state.settings_mut().hosts = hosts;
+        self
+    }
+
+    /// Resolves targets which are neither a MAC address nor the name of a host with the given
+    /// resolvers, e.g. IP addresses by the neighbor table.
+    pub fn resolver(mut self, resolver: Chain) -> Server {
+        self.state.settings_mut().resolver = resolver;
+        self
+    }
+
+    /// Requires wake and reload requests to carry the given bearer token, which may do anything.
+    pub fn token(self, token: &str) -> Server {
+        self.api_token(ApiToken::new("relay", token).with_scope(Scope::Admin))
+    }
+
+    /// Requires wake and reload requests to carry one of the given bearer tokens, this or those
+    /// added before, limited to what the token may do.
+    pub fn api_token(mut self, token: ApiToken) -> Server {
+        self.state.settings_mut().tokens.push(token);
+        self
+    }
+
+    /// Also accepts wake requests signed with `key` on the given UDP address.
+    pub fn signed_udp<A: ToSocketAddrs>(mut self, address: A, key: &str) -> io::Result<Server> {
+        self.signed = Some((UdpSocket::bind(address)?, Verifier::new(key.as_bytes())));
+        Ok(self)
+    }
+
+    /// Records every wake request in the given audit log.
+    pub fn audit_log(mut self, audit_log: AuditLog) -> Server {
+        self.state.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Does not wake a host again within `cooldown` after it was woken, unless the host has a
+    /// cooldown of its own, see `Host::cooldown`.
+    pub fn cooldown(mut self, cooldown: Duration) -> Server {
+        self.state.settings_mut().cooldown = Some(cooldown);
+        self
+    }
+
+    /// Replaces all settings at once, e.g. with those `reload_with` uses as well.
+    pub fn settings(mut self, settings: Settings) -> Server {
+        *self.state.settings_mut() = settings;
+        self
+    }
+
+    /// Replaces the settings with those `reload` returns on `POST /reload` and, on Unix, on
+    /// SIGHUP, e.g. by reading the configuration and the hosts file again. The listening
+    /// sockets, the key of signed requests, the cooldowns, the metrics and the wakes in progress
+    /// are kept; if `reload` fails, so are the settings.
+    pub fn reload_with<F>(mut self, reload: F) -> Server
+        where F: Fn() -> Result<Settings, String> + Send + Sync + 'static
+    {
+        self.state.reload = Some(Box::new(reload));
+        self
+    }
+
+    /// Shares the hosts file at `path` with admins: `GET /hosts` returns it and `PUT /hosts`
+    /// replaces it, after which the daemon reloads its settings if it can, see `reload_with`. The
+    /// hosts it wakes by name are those of `hosts` or the reload, which should read this file.
+    pub fn hosts_file<P: Into<PathBuf>>(mut self, path: P) -> Server {
+        self.state.hosts_file = Some(path.into());
+        self
+    }
+
+    /// Records every wake in the given history, see `wol last`.
+    pub fn history(mut self, history: History) -> Server {
+        self.state.history = Some(history);
+        self
+    }
+
+    /// Stops the daemon once `flag` is set, e.g. by `interrupt::catch_interrupt`, after the
+    /// requests in progress were answered, see the module documentation.
+    pub fn shutdown_on(mut self, flag: &'static AtomicBool) -> Server {
+        self.shutdown = Some(flag);
+        self
+    }
+
+    /// Waits at most `timeout` for the requests in progress when shutting down, instead of
+    /// `DEFAULT_DRAIN_TIMEOUT`, before cancelling their verifications.
+    pub fn drain_timeout(mut self, timeout: Duration) -> Server {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Handles at most `max` connections at once, event streams included, instead of
+    /// `DEFAULT_MAX_CONNECTIONS`, and answers further ones with 503.
+    pub fn max_connections(mut self, max: usize) -> Server {
+        self.max_connections = max;
+        self
+    }
+
+    /// Returns the address the daemon is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves requests forever, or until the flag of `shutdown_on` is set.
+    pub fn run(self) -> io::Result<()> {
+        info!("listening on {}", self.listener.local_addr()?);
+        let mut state = self.state;
+        // The connection threads send from one socket instead of binding one per request.
+        let settings = state.settings_mut();
+        settings.client = settings.client.clone().with_shared_socket(true);
+        let state = Arc::new(state);
+        if state.reload.is_some() {
+            match interrupt::catch_hangup() {
+                Ok(hangup) => {
+                    let state = state.clone();
+                    thread::spawn(move || loop {
+                        thread::sleep(HANGUP_POLL_INTERVAL);
+                        if hangup.swap(false, Ordering::SeqCst) {
+                            let _ = reload(&state);
+                        }
+                    });
+                },
+                Err(err) => debug!("cannot catch SIGHUP, reloading only per request: {}", err),
+            }
+        }
+        if let Some((socket, verifier)) = self.signed {
+            info!("receiving signed wake requests on {}", socket.local_addr()?);
+            let state = state.clone();
+            thread::spawn(move || receive_signed(&state, &socket, verifier));
+        }
+        // Accepting without blocking lets the daemon notice the flag between connections.
+        if self.shutdown.is_some() {
+            self.listener.set_nonblocking(true)?;
+        }
+        while !self.shutdown.is_some_and(|flag| flag.load(Ordering::SeqCst)) {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(STOP_POLL_INTERVAL);
+                    continue;
+                },
+                Err(err) => {
+                    warn!("could not accept connection: {}", err);
+                    continue;
+                },
+            };
+            // Accepted sockets inherit the non-blocking mode on some systems.
+            let _ = stream.set_nonblocking(false);
+            if state.connections.load(Ordering::SeqCst) >= self.max_connections {
+                refuse_busy(&stream, self.max_connections);
+                continue;
+            }
+            let state = state.clone();
+            state.connections.fetch_add(1, Ordering::SeqCst);
+            state.active.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || {
+                let _connection = Active(&state.connections);
+                handle_connection(&state, stream, Active(&state.active))
+            });
+        }
+        drop(self.listener);
+        drain(&state, self.drain_timeout);
+        Ok(())
+    }
+}
+
+/// Stops the daemon: waits up to `timeout` for the requests in progress, then cancels the
+/// verifications still waiting and gives them `CANCEL_GRACE` to answer and record their wakes.
+fn drain(state: &State, timeout: Duration) {
+    state.stopping.store(true, Ordering::SeqCst);
+    let wait = |timeout: Duration| {
+        let deadline = Instant::now() + timeout;
+        while state.active.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+            thread::sleep(STOP_POLL_INTERVAL);
+        }
+        state.active.load(Ordering::SeqCst)
+    };
+    let active = state.active.load(Ordering::SeqCst);
+    if active > 0 {
+        info!("shutting down, waiting up to {} for {} requests in progress",
+              units::format_duration(timeout),
+              active);
+    }
+    if wait(timeout) > 0 {
+        let flights = state.flights.lock().unwrap_or_else(|e| e.into_inner());
+        let macs: Vec<String> = flights.keys().map(|key| key.0.to_string()).collect();
+        drop(flights);
+        warn!("cancelling the verification of {} after the drain timeout",
+              if macs.is_empty() { "no host".to_string() } else { macs.join(", ") });
+        state.cancel.store(true, Ordering::SeqCst);
+        let left = wait(CANCEL_GRACE);
+        if left > 0 {
+            warn!("stopping with {} requests unanswered", left);
+        }
+    }
+    info!("stopped");
+}
+
+/// Answers a connection over the limit of `max` with 503 on the accepting thread, without
+/// reading the request, which a client stalling the answer cannot hold up for long.
+fn refuse_busy(stream: &TcpStream, max: usize) {
+    let peer = stream.peer_addr().map(|peer| peer.to_string()).unwrap_or_default();
+    warn!("refusing the connection of {}, {} connections are open", peer, max);
+    let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+    let message = format!("the daemon is busy with {} connections, try again later", max);
+    let response = Response::error(503, ErrorCode::Busy, &message);
+    if let Err(err) = write_response(&mut &*stream, &response) {
+        debug!("could not answer {}: {}", peer, err);
+    }
+}
+
+fn handle_connection(state: &State, stream: TcpStream, active: Active) {
+    let peer = match stream.peer_addr() {
+        Ok(peer) => peer,
+        Err(_) => return,
+    };
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let mut reader = BufReader::new(&stream);
+    let response = match read_request(&mut reader) {
+        Ok(ref request) if request.method == "GET" &&
+                           request.path.split('?').next() == Some("/events") => {
+            match authorize_admin(&state.settings(), request, "read the events") {
+                Err(response) => response,
+                Ok(()) => {
+                    debug!("event stream for {}", peer);
+                    // Streams last until the client goes away, which a shutdown does not wait
+                    // for.
+                    drop(active);
+                    if let Err(err) = stream_events(state, &stream) {
+                        debug!("event stream for {} ended: {}", peer, err);
+                    }
+                    return;
+                },
+            }
+        },
+        Ok(request) => {
+            debug!("{} {} from {}", request.method, request.path, peer);
+            handle(state, &request, peer.ip())
+        },
+        Err(err) => {
+            debug!("invalid request from {}: {}", peer, err);
+            Response::error(400, ErrorCode::BadRequest, &err.to_string())
+        },
+    };
+    if let Err(err) = write_response(&mut &stream, &response) {
+        debug!("could not answer {}: {}", peer, err);
+    }
+}
+
+fn handle(state: &State, request: &Request, client: IpAddr) -> Response {
+    let mut parts = request.path.splitn(2, '?');
+    let path = parts.next().unwrap_or("");
+    let query = parts.next().unwrap_or("");
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["metrics"]) => Response::text(200, state.metrics.render()),
+        ("GET", ["healthz"]) => Response::json(200, Record::new().field("alive", true).to_json()),
+        ("GET", ["readyz"]) => ready(state),
+        ("GET", ["requests"]) => {
+            match authorize_admin(&state.settings(), request, "read the requests") {
+                Err(response) => response,
+                Ok(()) => {
+                    let requests = state.requests.lock().unwrap_or_else(|e| e.into_inner());
+                    let requests: Vec<String> = requests.iter().map(Record::to_json).collect();
+                    Response::json(200, format!("[{}]", requests.join(",")))
+                },
+            }
+        },
+        ("POST", ["reload"]) => {
+            match authorize_admin(&state.settings(), request, "reload the daemon") {
+                Err(response) => response,
+                Ok(()) if state.reload.is_none() => {
+                    Response::error(404, ErrorCode::NotFound, "reloading is not enabled")
+                },
+                Ok(()) => match reload(state) {
+                    Ok(hosts) => {
+                        let record = Record::new().field("reloaded", true).field("hosts", hosts);
+                        Response::json(200, record.to_json())
+                    },
+                    Err(err) => Response::error(500, ErrorCode::ReloadFailed, &err),
+                },
+            }
+        },
+        ("GET", ["hosts"]) => {
+            match authorize_hosts_file(state, request, "read the hosts file") {
+                Err(response) => response,
+                Ok(path) => match fs::read_to_string(path) {
+                    Ok(content) => Response::text(200, content),
+                    // Without hosts file, the daemon has no hosts.
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                        Response::text(200, String::new())
+                    },
+                    Err(err) => {
+                        let message = format!("cannot read the hosts file: {}", err);
+                        Response::error(500, ErrorCode::HostsFileFailed, &message)
+                    },
+                },
+            }
+        },
+        ("PUT", ["hosts"]) => {
+            match authorize_hosts_file(state, request, "replace the hosts file") {
+                Err(response) => response,
+                Ok(path) => replace_hosts(state, path, &request.body),
+            }
+        },
+        ("POST", ["wake", target]) => {
+            let settings = state.settings();
+            let token = authorize(&settings, request);
+            let params = verify_param(state, query)
+                .and_then(|verify| Ok((verify, send_params(state, query)?)));
+            let response = match (&token, params) {
+                (Err(response), _) => response.clone(),
+                (&Ok(token), Ok((verify, params))) => {
+                    wake(state, target, verify, params, client, token)
+                },
+                (Ok(_), Err(err)) => Response::error(400, ErrorCode::BadRequest, &err),
+            };
+            log_request(state, request, client, token.ok().flatten(), target, &response);
+            response
+        },
+        (_, ["metrics"]) | (_, ["events"]) | (_, ["healthz"]) | (_, ["readyz"]) |
+        (_, ["requests"]) | (_, ["wake", _]) | (_, ["reload"]) | (_, ["hosts"]) => {
+            Response::error(405, ErrorCode::MethodNotAllowed, "method not allowed")
+        },
+        _ => Response::error(404, ErrorCode::NotFound, "not found"),
+    }
+}
+
+/// Replaces the settings with those of the reload function of the server and returns the number
+/// of hosts. The settings are kept if it fails.
+fn reload(state: &State) -> Result<usize, String> {
+    let reload = state.reload.as_ref().ok_or("reloading is not enabled")?;
+    let mut settings = reload().map_err(|err| {
+        error!("cannot reload, keeping the settings: {}", err);
+        *state.reload_error.lock().unwrap() = Some(err.clone());
+        err
+    })?;
+    settings.client = settings.client.with_shared_socket(true);
+    let hosts = settings.hosts.len();
+    *state.settings.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(settings);
+    *state.reload_error.lock().unwrap() = None;
+    info!("reloaded the settings with {} hosts", hosts);
+    Ok(hosts)
+}
+
+/// Replaces the hosts file with `content` and reloads the settings, if the daemon can, answering
+/// with the number of hosts.
+fn replace_hosts(state: &State, path: &Path, content: &[u8]) -> Response {
+    let content = match String::from_utf8(content.to_vec()) {
+        Ok(content) => content,
+        Err(_) => return Response::error(400, ErrorCode::BadRequest, "the hosts file is not UTF-8"),
+    };
+    // Reading the file would run them with the privileges of the daemon.
+    if secrets::references_command(&content) {
+        return Response::error(403,
+                               ErrorCode::Forbidden,
+                               "the daemon does not run the commands of secrets of pushed \
+                                hosts files, reference them by keyring instead");
+    }
+    let hosts = match Hosts::replace(path, &content) {
+        Ok(hosts) => hosts,
+        Err(ref err) if err.kind() == io::ErrorKind::InvalidData => {
+            return Response::error(400, ErrorCode::BadRequest, &err.to_string())
+        },
+        Err(err) => {
+            let message = format!("cannot write the hosts file: {}", err);
+            return Response::error(500, ErrorCode::HostsFileFailed, &message);
+        },
+    };
+    info!("replaced the hosts file {} with {} hosts", path.display(), hosts);
+    let reloaded = state.reload.is_some();
+    if reloaded {
+        if let Err(err) = reload(state) {
+            return Response::error(500, ErrorCode::ReloadFailed, &err);
+        }
+    }
+    let record = Record::new().field("hosts", hosts).field("reloaded", reloaded);
+    Response::json(200, record.to_json())
+}
+
+/// Answers whether the daemon is ready to wake hosts, with the address magic packets are sent
+/// from or why it is not ready.
+fn ready(state: &State) -> Response {
+    let socket = state.settings().client.local_addr();
+    let reload_error = state.reload_error.lock().unwrap().clone();
+    let ready = socket.is_ok() && reload_error.is_none();
+    let mut record = Record::new().field("ready", ready);
+    match socket {
+        Ok(address) => record.set("socket", address.to_string()),
+        Err(err) => record.set("socket_error", err.to_string()),
+    }
+    if let Some(err) = reload_error {
+        record.set("config_error", err);
+    }
+    Response::json(if ready { 200 } else { 503 }, record.to_json())
+}
+
+/// Sends the events to the client until it goes away.
+fn stream_events(state: &State, mut stream: &TcpStream) -> io::Result<()> {
+    let events = state.events.subscribe();
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                       Cache-Control: no-cache\r\nConnection: close\r\n\r\n")?;
+    let mut last_written = Instant::now();
+    while !state.stopping.load(Ordering::SeqCst) {
+        match events.recv_timeout(HANGUP_POLL_INTERVAL) {
+            Ok(event) => stream.write_all(events::sse_frame(&event).as_bytes())?,
+            Err(RecvTimeoutError::Timeout) if last_written.elapsed() >= KEEPALIVE_INTERVAL => {
+                stream.write_all(b": keepalive\n\n")?
+            },
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        last_written = Instant::now();
+    }
+    Ok(())
+}
+
+/// Returns the token of the server the request carries, `None` if the server has no tokens, or
+/// the response refusing a request without one.
+fn authorize<'a>(settings: &'a Settings,
+                 request: &Request)
+                 -> Result<Option<&'a ApiToken>, Response> {
+    if settings.tokens.is_empty() {
+        return Ok(None);
+    }
+    request.header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|given| tokens::find(&settings.tokens, given))
+        .map(Some)
+        .ok_or_else(|| {
+            Response::error(401, ErrorCode::Unauthorized, "missing or wrong bearer token")
+        })
+}
+
+/// Checks that the request carries an admin token of the server, if it has tokens, or returns
+/// the response refusing it.
+fn authorize_admin(settings: &Settings, request: &Request, action: &str) -> Result<(), Response> {
+    match authorize(settings, request)? {
+        Some(token) if !token.is_admin() => {
+            let message = format!("token {} may not {}", token.name, action);
+            Err(Response::error(403, ErrorCode::Forbidden, &message))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Returns the shared hosts file if the request carries an admin token, or the response refusing
+/// it. Unlike other requests, those for the hosts file are refused if the daemon has no tokens.
+fn authorize_hosts_file<'a>(state: &'a State,
+                            request: &Request,
+                            action: &str)
+                            -> Result<&'a Path, Response> {
+    let path = state.hosts_file.as_deref().ok_or_else(|| {
+        Response::error(404, ErrorCode::NotFound, "the daemon does not share its hosts file")
+    })?;
+    let settings = state.settings();
+    if settings.tokens.is_empty() {
+        let message = format!("the daemon has no tokens, set [relay] token to {}", action);
+        return Err(Response::error(403, ErrorCode::Forbidden, &message));
+    }
+    authorize_admin(&settings, request, action)?;
+    Ok(path)
+}
+
+/// Logs a wake request with its client, token, user agent, target and result, and keeps it for
+/// `GET /requests`.
+fn log_request(state: &State,
+               request: &Request,
+               client: IpAddr,
+               token: Option<&ApiToken>,
+               target: &str,
+               response: &Response) {
+    let token = token.map(|token| token.name.as_str());
+    let user_agent = request.header("User-Agent");
+    let result = response.code.as_deref().unwrap_or("ok");
+    event!(Level::Info, "wake request"; client_ip = client, token = token.unwrap_or("-"),
+           user_agent = format!("{:?}", user_agent.unwrap_or("")), target = target,
+           status = response.status, result = result);
+    let record = Record::new()
+        .field("time", output::timestamp(SystemTime::now()))
+        .field("client", client.to_string())
+        .field("token", token)
+        .field("user_agent", user_agent)
+        .field("target", target)
+        .field("status", u64::from(response.status))
+        .field("result", result);
+    let mut requests = state.requests.lock().unwrap_or_else(|e| e.into_inner());
+    if requests.len() == REQUEST_LOG_LEN {
+        requests.pop_front();
+    }
+    requests.push_back(record);
+}
+
+/// Returns the IP address of the `verify` parameter of a wake request and how long to wait for
+/// it, which the `timeout` parameter can shorten from the timeout of the server.
+fn verify_param(state: &State, query: &str) -> Result<Option<(IpAddr, Duration)>, String> {
+    let ip = match query_param(query, "verify").map(str::parse::<IpAddr>).transpose() {
+        Ok(ip) => ip,
+        Err(err) => return Err(format!("invalid verify address: {}", err)),
+    };
+    let timeout = match query_param(query, "timeout").map(units::parse_duration_or_secs) {
+        // Capped, so that clients cannot keep a thread of the server busy for longer.
+        Some(Ok(timeout)) => timeout.min(state.settings().probe.1),
+        Some(Err(err)) => return Err(format!("invalid timeout: {}", err)),
+        None => state.settings().probe.1,
+    };
+    Ok(ip.map(|ip| (ip, timeout)))
+}
+
+/// Returns the `port`, `repeat` and `burst` parameters of a wake request.
+fn send_params(state: &State, query: &str) -> Result<SendParams, String> {
+    let port = query_param(query, "port").map(str::parse::<Port>).transpose()?;
+    let repeat = query_param(query, "repeat").map(str::parse::<RepeatCount>).transpose()?;
+    let bursts = match query_param(query, "burst").map(str::parse::<Bursts>) {
+        Some(Ok(bursts)) => {
+            // Capped like the timeout, so that clients cannot keep a thread of the server busy
+            // for longer.
+            let timeout = state.settings().probe.1;
+            if bursts.gap.checked_mul(bursts.count - 1).is_none_or(|last| last > timeout) {
+                return Err(format!("invalid burst: the bursts take longer than the timeout \
+                                    of {}",
+                                   units::format_duration(timeout)));
+            }
+            Some(bursts)
+        },
+        Some(Err(err)) => return Err(format!("invalid burst: {}", err)),
+        None => None,
+    };
+    Ok(SendParams { port, repeat, bursts })
+}
+
+/// Returns the value of the parameter with the given name in the query string of a URL.
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| {
+        let mut parts = pair.splitn(2, '=');
+        if parts.next() == Some(name) { parts.next() } else { None }
+    })
+}
+
+/// Receives signed wake requests forever. They are not answered, see the `signed` module.
+fn receive_signed(state: &State, socket: &UdpSocket, mut verifier: Verifier) {
+    let mut buf = [0; signed::REQUEST_LEN + 1];
+    // Waking up regularly lets the receiver notice a shutdown.
+    if let Err(err) = socket.set_read_timeout(Some(HANGUP_POLL_INTERVAL)) {
+        debug!("cannot set the read timeout of the signed requests: {}", err);
+    }
+    while !state.stopping.load(Ordering::SeqCst) {
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock ||
+                            err.kind() == io::ErrorKind::TimedOut => continue,
+            Err(err) => {
+                warn!("could not receive signed wake request: {}", err);
+                continue;
+            },
+        };
+        match verifier.verify(&buf[..len]) {
+            // The outcome is logged, counted and audited like that of any wake request.
+            Ok(request) => {
+                let _active = state.begin();
+                if in_flight(state, request.mac) {
+                    info!("{} is being woken already", request.mac);
+                    continue;
+                }
+                if let Err(age) = claim(state, request.mac) {
+                    info!("{} was woken {}s ago, within its cooldown", request.mac, age.as_secs());
+                    continue;
+                }
+                let (_, _, result) = send(state, request.mac, SendParams::default(), peer.ip());
+                remember(state, request.mac, peer.ip(), &result, None, None);
+            },
+            Err(err) => warn!("rejected wake request from {}: {}", peer, err),
+        }
+    }
+}
+
+/// Claims a wake of `mac` if its host has a cooldown. Fails with the time since the last wake
+/// if it is within the cooldown.
+fn claim(state: &State, mac: MacAddr) -> Result<(), Duration> {
+    let settings = state.settings();
+    let cooldown = match settings.hosts.by_mac(mac).and_then(|host| host.cooldown) {
+        Some(cooldown) => cooldown,
+        None => match settings.cooldown {
+            Some(cooldown) => cooldown,
+            None => return Ok(()),
+        },
+    };
+    let mut last_sent = state.last_sent.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    match last_sent.get(&mac) {
+        Some(&sent) if now.duration_since(sent) < cooldown => Err(now.duration_since(sent)),
+        _ => {
+            last_sent.insert(mac, now);
+            Ok(())
+        },
+    }
+}
+
+/// Sends a magic packet to `mac` on behalf of `client`, counting and auditing it. Returns the
+/// target, when sending started and the result.
+fn send(state: &State, mac: MacAddr, params: SendParams, client: IpAddr)
+        -> (SocketAddr, Instant, io::Result<SendReport>) {
+    let settings = state.settings();
+    let host =
+        settings.hosts.by_mac(mac).cloned().unwrap_or_else(|| Host::new(&mac.to_string(), mac));
+    let sender = params.apply(host.client(&settings.client));
+    let target = sender.destination();
+    state.events.publish(events::WAKE_REQUESTED,
+                         &mac.to_string(),
+                         Record::new().field("client", client.to_string()));
+    let start = Instant::now();
+    let result = sender.send(&host.packet());
+    let outcome = if result.is_ok() { "ok" } else { "error" };
+    event!(Level::Info, "wake requested"; mac = mac, target = target, client_ip = client,
+           result = outcome);
+    match result {
+        Ok(ref report) => {
+            state.metrics.record_sent(&mac.to_string());
+            let details = Record::new()
+                .field("target", report.destination.to_string())
+                .field("bytes", report.bytes_sent);
+            state.events.publish(events::PACKET_SENT, &mac.to_string(), details);
+        },
+        Err(ref err) => {
+            // A failed wake does not count for the cooldown, so that it can be retried.
+            state.last_sent.lock().unwrap_or_else(|e| e.into_inner()).remove(&mac);
+            state.metrics.record_send_failure();
+            let details = Record::new().field("error", err.to_string());
+            state.events.publish(events::SEND_FAILED, &mac.to_string(), details);
+        },
+    }
+    if let Some(ref audit_log) = state.audit_log {
+        let event = AuditEvent {
+            initiator: Initiator::Api(client.to_string()),
+            mac: mac.to_string(),
+            target: target.to_string(),
+            outcome: result.as_ref().map(|_| ()).map_err(|err| err.to_string()),
+        };
+        if let Err(err) = audit_log.record(&event) {
+            error!("cannot write audit log: {}", err);
+        }
+    }
+    (target, start, result)
+}
+
+/// Records a wake in the history of the daemon, if it keeps one.
+fn remember(state: &State,
+            mac: MacAddr,
+            client: IpAddr,
+            result: &io::Result<SendReport>,
+            verified: Option<bool>,
+            boot_time: Option<Duration>) {
+    let history = match state.history {
+        Some(ref history) => history,
+        None => return,
+    };
+    let settings = state.settings();
+    let host =
+        settings.hosts.by_mac(mac).map_or_else(|| mac.to_string(), |host| host.name.clone());
+    let wake = Wake::new(&host,
+                         mac,
+                         Initiator::Api(client.to_string()),
+                         result.as_ref().map(|_| ()).map_err(|err| err.to_string()),
+                         verified)
+        .with_boot_time(boot_time);
+    if let Err(err) = history.record(&wake) {
+        error!("cannot write wake history {}: {}", history.path().display(), err);
+    }
+}
+
+fn wake(state: &State,
+        target: &str,
+        verify: Option<(IpAddr, Duration)>,
+        params: SendParams,
+        client: IpAddr,
+        token: Option<&ApiToken>)
+        -> Response {
+    let settings = state.settings();
+    let mac: MacAddr = match (target.parse(), settings.hosts.get(target)) {
+        (Ok(mac), _) => mac,
+        (Err(_), Some(host)) => host.mac,
+        (Err(err), None) => match settings.resolver.resolve(target) {
+            Ok(Some(host)) => host.mac,
+            Ok(None) => {
+                let message = format!("invalid MAC address: {}", err);
+                return Response::error(400, ErrorCode::ParseMac, &message);
+            },
+            Err(err) => {
+                let message = format!("cannot resolve: {}", err);
+                return Response::error(500, ErrorCode::ResolveFailed, &message);
+            },
+        },
+    };
+    if let Some(token) = token.filter(|token| !token.may_wake(&settings.hosts, mac)) {
+        let message = format!("token {} may not wake {}", token.name, target);
+        return Response::error(403, ErrorCode::Forbidden, &message);
+    }
+    let (status, record) = coalesce(state, (mac, verify.map(|(ip, _)| ip), params), || {
+        wake_once(state, mac, verify, params, client)
+    });
+    let code = match record.get("code") {
+        Some(Value::Str(code)) => Some(code.clone()),
+        _ => None,
+    };
+    Response { code, ..Response::json(status, record.to_json()) }
+}
+
+/// Returns whether a wake of `mac` is in progress.
+fn in_flight(state: &State, mac: MacAddr) -> bool {
+    state.flights.lock().unwrap_or_else(|e| e.into_inner()).keys().any(|key| key.0 == mac)
+}
+
+/// Runs `wake` unless a wake with the same key is in progress, whose result is returned instead
+/// with `"coalesced":true` once it is done.
+fn coalesce<F>(state: &State, key: FlightKey, wake: F) -> (u16, Record)
+    where F: FnOnce() -> (u16, Record)
+{
+    let (flight, first) = {
+        let mut flights = state.flights.lock().unwrap_or_else(|e| e.into_inner());
+        match flights.get(&key) {
+            Some(flight) => (flight.clone(), false),
+            None => {
+                let flight = Arc::new(Flight::default());
+                flights.insert(key, flight.clone());
+                (flight, true)
+            },
+        }
+    };
+    if first {
+        let result = wake();
+        *flight.result.lock().unwrap_or_else(|e| e.into_inner()) = Some(result.clone());
+        state.flights.lock().unwrap_or_else(|e| e.into_inner()).remove(&key);
+        flight.done.notify_all();
+        return result;
+    }
+    debug!("{} is being woken already, waiting for the result", key.0);
+    state.metrics.record_coalesced();
+    let mut result = flight.result.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        if let Some((status, ref record)) = *result {
+            let mut record = record.clone();
+            record.set("coalesced", true);
+            return (status, record);
+        }
+        result = flight.done.wait(result).unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+/// Wakes `mac` with `params` unless it is within its cooldown, verifying it at the IP address of
+/// `verify` for up to its timeout if given, and returns the status and the result.
+fn wake_once(state: &State,
+             mac: MacAddr,
+             verify: Option<(IpAddr, Duration)>,
+             params: SendParams,
+             client: IpAddr)
+             -> (u16, Record) {
+    if let Err(age) = claim(state, mac) {
+        info!("{} was woken {}s ago, within its cooldown", mac, age.as_secs());
+        let record = Record::new()
+            .field("mac", mac.to_string())
+            .field("cooldown", true)
+            .field("woken_ms", output::millis(age));
+        return (200, record);
+    }
+    let (target, start, result) = send(state, mac, params, client);
+    let verified = match (&result, verify) {
+        (Ok(_), Some((ip, timeout))) => {
+            let settings = state.settings();
+            let ports = &settings.probe.0;
+            let up = match settings.method {
+                Method::Dhcp => verify::wait_until_booted(mac, timeout, &state.cancel),
+                method => verify::wait_until_up(method, ip, ports, timeout, &state.cancel),
+            };
+            // Cancelled by a shutdown, the wake is answered and recorded as not verified.
+            if let Some(up) = up {
+                state.metrics.record_verify(up);
+            }
+            up
+        },
+        _ => None,
+    };
+    let elapsed = start.elapsed();
+    let boot_time = if verified == Some(true) { Some(elapsed) } else { None };
+    if let Some(boot_time) = boot_time {
+        state.metrics.record_boot_time(&mac.to_string(), boot_time);
+    }
+    match verified {
+        Some(true) => {
+            let details = Record::new().field("boot_ms", boot_time.map(output::millis));
+            state.events.publish(events::VERIFY_SUCCEEDED, &mac.to_string(), details);
+        },
+        Some(false) => {
+            state.events.publish(events::VERIFY_TIMEOUT, &mac.to_string(), Record::new())
+        },
+        None => {},
+    }
+    remember(state, mac, client, &result, verified, boot_time);
+    let status = if result.is_ok() { 200 } else { 500 };
+    let mut record = output::send_result(mac, target, &result, elapsed);
+    output::set_verified(&mut record, verified);
+    record.set("boot_ms", boot_time.map(output::millis));
+    (status, record)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Cursor, Write};
+    use std::net::{TcpListener, TcpStream, UdpSocket};
+    use std::process;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{handle, query_param, read_request, receive_signed, send_params, verify_param,
+                History, Hosts, SendParams, Server, Settings, State, WolClient};
+    use hosts::Host;
+    use resolve::{Chain, Static};
+    use signed::{SignedRequest, Verifier};
+    use tokens::{ApiToken, Scope};
+    use transport::Bursts;
+    use verify;
+    use MacAddr;
+
+    #[test]
+    fn test_read_request() {
+        let mut input = Cursor::new(&b"POST /wake/00:01:02:03:04:05 HTTP/1.1\r\nHost: relay\r\n\
+                                       Content-Length: 2\r\n\r\n{}"[..]);
+        let request = read_request(&mut input).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/wake/00:01:02:03:04:05");
+        assert_eq!(request.header("host"), Some("relay"));
+        assert_eq!(request.body, b"{}");
+    }
+
+    #[test]
+    fn test_read_truncated_request() {
+        let mut input = Cursor::new(&b"GET /metrics HTTP/1.1\r\nHost: relay\r\n"[..]);
+        assert!(read_request(&mut input).is_err());
+    }
+
+    #[test]
+    fn test_routing() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let host = TcpListener::bind("127.0.0.1:0").unwrap();
+        let history = env::temp_dir().join(format!("wol-server-history-{}", process::id()));
+        let mut state = State::new(Settings {
+            client: WolClient::new(receiver.local_addr().unwrap()),
+            probe: (vec![host.local_addr().unwrap().port()], Duration::from_secs(1)),
+            ..Settings::default()
+        });
+        state.history = Some(History::new(&history));
+        let request = |method: &str, path: &str| {
+            let raw = format!("{} {} HTTP/1.1\r\n\r\n", method, path);
+            read_request(&mut Cursor::new(raw.into_bytes())).unwrap()
+        };
+        let client = "127.0.0.1".parse().unwrap();
+        assert_eq!(handle(&state, &request("GET", "/metrics"), client).status, 200);
+        assert_eq!(handle(&state, &request("GET", "/wake/00:01:02:03:04:05"), client).status,
+                   405);
+        assert_eq!(handle(&state, &request("POST", "/wake/00:01:02"), client).status, 400);
+        assert_eq!(handle(&state, &request("GET", "/"), client).status, 404);
+
+        let path = "/wake/00:01:02:03:04:05?verify=127.0.0.1";
+        let response = handle(&state, &request("POST", path), client);
+        assert_eq!(response.status, 200);
+        assert!(response.body.contains("\"verified\":true,\"boot_ms\":"));
+        let count = "\nwol_boot_seconds_count{mac=\"00:01:02:03:04:05\"} 1\n";
+        assert!(state.metrics.render().contains(count));
+        let wakes = state.history.as_ref().unwrap().wakes().unwrap();
+        assert_eq!(wakes.len(), 1);
+        assert_eq!((wakes[0].initiator.name(), wakes[0].verified), ("127.0.0.1", Some(true)));
+        let path = "/wake/00:01:02:03:04:05?verify=localhost";
+        assert_eq!(handle(&state, &request("POST", path), client).status, 400);
+
+        // Known hosts are woken by name and sent to with their own options.
+        let printer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let hosts = format!("[printer]\nmac = \"00:01:02:03:04:06\"\nport = {}\n\
+                             cooldown = \"1h\"",
+                            printer.local_addr().unwrap().port());
+        state.settings_mut().hosts = hosts.parse::<Hosts>().unwrap();
+        let response = handle(&state, &request("POST", "/wake/printer"), client);
+        assert_eq!(response.status, 200);
+        let target = format!("\"target\":\"{}\"", printer.local_addr().unwrap());
+        assert!(response.body.contains(&target));
+        assert_eq!(handle(&state, &request("POST", "/wake/scanner"), client).status, 400);
+        let scanner = Host::new("scanner", MacAddr::new([0, 1, 2, 3, 4, 7]));
+        state.settings_mut().resolver = Chain::new().with_resolver(Static::new(vec![scanner]));
+        let response = handle(&state, &request("POST", "/wake/scanner"), client);
+        assert!(response.body.contains("\"mac\":\"00:01:02:03:04:07\""));
+
+        // Within its cooldown, a host is not woken again.
+        let response = handle(&state, &request("POST", "/wake/00:01:02:03:04:06"), client);
+        assert_eq!(response.status, 200);
+        assert!(response.body.contains("\"cooldown\":true"));
+        assert_eq!(state.history.as_ref().unwrap().wakes().unwrap().len(), 3);
+
+        let response = handle(&state, &request("GET", "/readyz"), client);
+        assert_eq!(response.status, 200);
+        assert!(response.body.starts_with("{\"ready\":true,\"socket\":\"127.0.0.1:"));
+
+        // A failed reload keeps the settings, but the daemon is not ready until one succeeds.
+        assert_eq!(handle(&state, &request("POST", "/reload"), client).status, 404);
+        assert_eq!(handle(&state, &request("GET", "/reload"), client).status, 405);
+        state.reload = Some(Box::new(|| Err("invalid hosts file".to_string())));
+        let response = handle(&state, &request("POST", "/reload"), client);
+        assert_eq!(response.status, 500);
+        assert!(response.body.contains("\"code\":\"RELOAD_FAILED\""));
+        assert_eq!(state.settings().hosts.len(), 1);
+        let response = handle(&state, &request("GET", "/readyz"), client);
+        assert_eq!(response.status, 503);
+        assert!(response.body.ends_with(",\"config_error\":\"invalid hosts file\"}"));
+        assert_eq!(handle(&state, &request("GET", "/healthz"), client).body, "{\"alive\":true}");
+
+        state.settings_mut().tokens = vec![ApiToken::new("relay", "s3cret")
+                                               .with_scope(Scope::Admin),
+                                           ApiToken::new("guest", "g4est")
+                                               .with_hosts(vec!["printer".to_string()])];
+        let mut wake = request("POST", "/wake/00:01:02:03:04:05");
+        let response = handle(&state, &wake, client);
+        assert_eq!(response.status, 401);
+        assert!(response.body.contains("\"code\":\"UNAUTHORIZED\""));
+        wake.headers.push(("authorization".to_string(), "Bearer s3cre".to_string()));
+        assert_eq!(handle(&state, &wake, client).status, 401);
+        wake.headers[0].1 = "Bearer s3cret".to_string();
+        assert_eq!(handle(&state, &wake, client).status, 200);
+        assert_eq!(handle(&state, &request("GET", "/metrics"), client).status, 200);
+
+        // A guest may only wake its hosts and not reload the daemon.
+        wake.headers[0].1 = "Bearer g4est".to_string();
+        let response = handle(&state, &wake, client);
+        assert_eq!(response.status, 403);
+        assert!(response.body.contains("\"code\":\"FORBIDDEN\""));
+        let mut printer = request("POST", "/wake/printer");
+        printer.headers = wake.headers.clone();
+        assert_eq!(handle(&state, &printer, client).status, 200);
+        let mut reload = request("POST", "/reload");
+        reload.headers = wake.headers.clone();
+        assert_eq!(handle(&state, &reload, client).status, 403);
+
+        // The wake requests are kept with their token and result for admins.
+        let mut requests = request("GET", "/requests");
+        requests.headers = wake.headers.clone();
+        assert_eq!(handle(&state, &requests, client).status, 403);
+        requests.headers[0].1 = "Bearer s3cret".to_string();
+        let response = handle(&state, &requests, client);
+        assert_eq!(response.status, 200);
+        assert!(response.body.starts_with("[{\"time\":\""));
+        assert!(response.body.contains("\"client\":\"127.0.0.1\",\"token\":null,\
+                                        \"user_agent\":null,\"target\":\"printer\",\
+                                        \"status\":200,\"result\":\"ok\"}"));
+        assert!(response.body.contains("\"token\":\"guest\",\"user_agent\":null,\
+                                        \"target\":\"00:01:02:03:04:05\",\"status\":403,\
+                                        \"result\":\"FORBIDDEN\"}"));
+        assert_eq!(response.body.matches("\"time\"").count(), 12);
+
+        let destination = receiver.local_addr().unwrap();
+        state.reload = Some(Box::new(move || {
+            Ok(Settings { client: WolClient::new(destination), ..Settings::default() })
+        }));
+        let mut reload = request("POST", "/reload");
+        assert_eq!(handle(&state, &reload, client).status, 401);
+        reload.headers.push(("authorization".to_string(), "Bearer s3cret".to_string()));
+        let response = handle(&state, &reload, client);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "{\"reloaded\":true,\"hosts\":0}");
+        assert_eq!((state.settings().hosts.len(), state.settings().tokens.len()), (0, 0));
+        assert_eq!(handle(&state, &request("GET", "/readyz"), client).status, 200);
+        assert_eq!(handle(&state, &request("POST", "/healthz"), client).status, 405);
+        fs::remove_file(history).unwrap();
+    }
+
+    #[test]
+    fn test_hosts_file() {
+        let dir = env::temp_dir().join(format!("wol-server-hosts-{}", process::id()));
+        let mut state = State::new(Settings::default());
+        let request = |method: &str, body: &str| {
+            let raw = format!("{} /hosts HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\
+                               Content-Length: {}\r\n\r\n{}",
+                              method,
+                              body.len(),
+                              body);
+            read_request(&mut Cursor::new(raw.into_bytes())).unwrap()
+        };
+        let client = "127.0.0.1".parse().unwrap();
+        assert_eq!(handle(&state, &request("GET", ""), client).status, 404);
+        state.hosts_file = Some(dir.join("hosts.toml"));
+        // Without tokens, anyone could replace the hosts.
+        assert_eq!(handle(&state, &request("GET", ""), client).status, 403);
+        state.settings_mut().tokens.push(ApiToken::new("guest", "g4est"));
+        assert_eq!(handle(&state, &request("GET", ""), client).status, 401);
+        state.settings_mut().tokens.push(ApiToken::new("ops", "s3cret").with_scope(Scope::Admin));
+        let response = handle(&state, &request("GET", ""), client);
+        assert_eq!((response.status, response.body.as_str()), (200, ""));
+
+        let content = "# lab\n[nas]\nmac = \"00:01:02:03:04:05\"\n";
+        let response = handle(&state, &request("PUT", content), client);
+        assert_eq!(response.body, "{\"hosts\":1,\"reloaded\":false}");
+        assert_eq!(handle(&state, &request("GET", ""), client).body, content);
+        let response = handle(&state, &request("PUT", "[nas]\nmac = \"00:01\""), client);
+        assert_eq!(response.status, 400);
+        assert!(response.body.contains("2: expected a MAC address"));
+        let secret = "[nas]\nmac = \"00:01:02:03:04:05\"\npassword = { cmd = \"touch pwned\" }";
+        assert_eq!(handle(&state, &request("PUT", secret), client).status, 403);
+        assert_eq!(handle(&state, &request("GET", ""), client).body, content);
+
+        let path = dir.join("hosts.toml");
+        state.reload = Some(Box::new(move || {
+            let hosts = Hosts::load(&path).map_err(|err| err.to_string())?;
+            Ok(Settings { hosts, ..Settings::default() })
+        }));
+        let content = "[nas]\nmac = \"00:01:02:03:04:05\"\n[pc]\nmac = \"00:01:02:03:04:06\"";
+        let response = handle(&state, &request("PUT", content), client);
+        assert_eq!(response.body, "{\"hosts\":2,\"reloaded\":true}");
+        assert!(state.settings().hosts.get("pc").is_some());
+        assert_eq!(handle(&state, &request("DELETE", ""), client).status, 405);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let state = Arc::new(State::new(Settings {
+            client: WolClient::new(receiver.local_addr().unwrap()),
+            probe: (vec![closed], Duration::from_millis(500)),
+            ..Settings::default()
+        }));
+        let barrier = Arc::new(Barrier::new(4));
+        let requests: Vec<_> = (0..4)
+            .map(|_| {
+                let (state, barrier) = (state.clone(), barrier.clone());
+                thread::spawn(move || {
+                    let raw = "POST /wake/00:01:02:03:04:05?verify=127.0.0.1 HTTP/1.1\r\n\r\n";
+                    let request = read_request(&mut Cursor::new(raw.as_bytes())).unwrap();
+                    barrier.wait();
+                    handle(&state, &request, "127.0.0.1".parse().unwrap())
+                })
+            })
+            .collect();
+        let responses: Vec<_> = requests.into_iter().map(|t| t.join().unwrap()).collect();
+        assert_eq!(state.metrics.packets_sent(), 1);
+        assert!(responses.iter().all(|response| response.body.contains("\"verified\":false")));
+        assert!(responses.iter().all(|r| r.body.contains("\"code\":\"VERIFY_TIMEOUT\"")));
+        let coalesced = responses.iter().filter(|r| r.body.contains("\"coalesced\":true")).count();
+        assert_eq!(coalesced, 3);
+        assert!(state.flights.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_signed_udp() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let state = Arc::new(State::new(Settings {
+            client: WolClient::new(receiver.local_addr().unwrap()),
+            ..Settings::default()
+        }));
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let daemon = state.clone();
+        thread::spawn(move || receive_signed(&daemon, &socket, Verifier::new(b"s3cret")));
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let forged = SignedRequest::new(MacAddr::new([0, 1, 2, 3, 4, 6]));
+        sender.send_to(&forged.encode(b"secret"), address).unwrap();
+        let request = SignedRequest::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+        sender.send_to(&request.encode(b"s3cret"), address).unwrap();
+        let mut buf = [0; 200];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(len, 102);
+        assert_eq!(&buf[6..12], &[0, 1, 2, 3, 4, 5]);
+        // The packet is counted after it was sent.
+        for _ in 0..100 {
+            if state.metrics.packets_sent() > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(state.metrics.packets_sent(), 1);
+    }
+
+    #[test]
+    fn test_events() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = Server::bind("127.0.0.1:0")
+            .unwrap()
+            .client(WolClient::new(receiver.local_addr().unwrap()));
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        (&stream).write_all(b"GET /events HTTP/1.1\r\n\r\n").unwrap();
+        let mut lines = BufReader::new(&stream).lines().map(Result::unwrap);
+        assert_eq!(lines.next().unwrap(), "HTTP/1.1 200 OK");
+        let headers: Vec<String> = lines.by_ref().take_while(|line| !line.is_empty()).collect();
+        assert!(headers.contains(&"Content-Type: text/event-stream".to_string()));
+
+        let wake = TcpStream::connect(address).unwrap();
+        (&wake).write_all(b"POST /wake/00:01:02:03:04:05 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(lines.next().unwrap(), "event: wake_requested");
+        assert!(lines.next().unwrap().starts_with("data: {\"mac\":\"00:01:02:03:04:05\""));
+        assert_eq!(lines.nth(1).unwrap(), "event: packet_sent");
+        assert!(lines.next().unwrap().ends_with("\"bytes\":102}"));
+    }
+
+    #[test]
+    fn test_events_token() {
+        let server = Server::bind("127.0.0.1:0")
+            .unwrap()
+            .token("s3cret")
+            .api_token(ApiToken::new("guest", "g4est"));
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let status = |authorization: &str| {
+            let stream = TcpStream::connect(address).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let request = format!("GET /events HTTP/1.1\r\n{}\r\n", authorization);
+            (&stream).write_all(request.as_bytes()).unwrap();
+            BufReader::new(&stream).lines().next().unwrap().unwrap()
+        };
+        assert_eq!(status(""), "HTTP/1.1 401 Unauthorized");
+        assert_eq!(status("Authorization: Bearer g4est\r\n"), "HTTP/1.1 403 Forbidden");
+        assert_eq!(status("Authorization: Bearer s3cret\r\n"), "HTTP/1.1 200 OK");
+    }
+
+    #[test]
+    fn test_max_connections() {
+        let server = Server::bind("127.0.0.1:0").unwrap().max_connections(1);
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let request = |path: &str| {
+            let stream = TcpStream::connect(address).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            (&stream).write_all(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes()).unwrap();
+            let status = BufReader::new(&stream).lines().next().unwrap().unwrap();
+            (stream, status)
+        };
+        assert_eq!(request("/healthz").1, "HTTP/1.1 200 OK");
+        // The answered request freed its connection, which the event stream then holds.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let _events = loop {
+            let (stream, status) = request("/events");
+            if status == "HTTP/1.1 200 OK" {
+                break stream;
+            }
+            assert!(Instant::now() < deadline, "the answered request still holds its connection");
+            thread::sleep(Duration::from_millis(50));
+        };
+        let (_, status) = request("/healthz");
+        assert_eq!(status, "HTTP/1.1 503 Service Unavailable");
+    }
+
+    #[test]
+    fn test_shutdown() {
+        static STOP: AtomicBool = AtomicBool::new(false);
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let server = Server::bind("127.0.0.1:0")
+            .unwrap()
+            .client(WolClient::new(receiver.local_addr().unwrap()))
+            .verify(&[closed], Duration::from_secs(60), verify::Method::Tcp)
+            .shutdown_on(&STOP)
+            .drain_timeout(Duration::from_millis(200));
+        let address = server.local_addr().unwrap();
+        let daemon = thread::spawn(move || server.run());
+
+        let wake = TcpStream::connect(address).unwrap();
+        wake.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        (&wake).write_all(b"POST /wake/00:01:02:03:04:05?verify=127.0.0.1 HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut buf = [0; 102];
+        receiver.recv(&mut buf).unwrap();
+        let start = Instant::now();
+        STOP.store(true, Ordering::SeqCst);
+        daemon.join().unwrap().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        // The verification was cancelled, the wake is answered as sent.
+        let mut lines = BufReader::new(&wake).lines().map(Result::unwrap);
+        assert_eq!(lines.next().unwrap(), "HTTP/1.1 200 OK");
+        let body = lines.last().unwrap();
+        assert!(body.starts_with("{\"mac\":\"00:01:02:03:04:05\""));
+        assert!(body.contains("\"verified\":null") && body.contains("\"error\":null"));
+        assert!(TcpStream::connect(address).is_err());
+    }
+
+    #[test]
+    fn test_query_param() {
+        assert_eq!(query_param("a=1&verify=10.0.0.1", "verify"), Some("10.0.0.1"));
+        assert_eq!(query_param("verify", "verify"), None);
+        assert_eq!(query_param("", "verify"), None);
+    }
+
+    #[test]
+    fn test_verify_param() {
+        let state = Server::bind("127.0.0.1:0").unwrap().state;
+        let ip = "10.0.0.1".parse().unwrap();
+        let default = Ok(Some((ip, verify::DEFAULT_TIMEOUT)));
+        assert_eq!(verify_param(&state, "verify=10.0.0.1"), default);
+        assert_eq!(verify_param(&state, "verify=10.0.0.1&timeout=500ms"),
+                   Ok(Some((ip, Duration::from_millis(500)))));
+        assert_eq!(verify_param(&state, "verify=10.0.0.1&timeout=1h"), default);
+        assert_eq!(verify_param(&state, "timeout=5s"), Ok(None));
+        assert_eq!(verify_param(&state, "verify=10.0.0.1&timeout=5x"),
+                   Err("invalid timeout: expected a unit of ms, s, m, h or d".to_string()));
+    }
+
+    #[test]
+    fn test_send_params() {
+        let state = Server::bind("127.0.0.1:0").unwrap().state;
+        assert_eq!(send_params(&state, ""), Ok(SendParams::default()));
+        let params = send_params(&state, "port=7&repeat=3&burst=3x2s").unwrap();
+        let client = params.apply(WolClient::v4());
+        assert_eq!((client.destination().port(), client.repeat()), (7, 3));
+        assert_eq!(client.bursts(), Bursts::new(3, Duration::from_secs(2)));
+        assert_eq!(send_params(&state, "port=0"),
+                   Err("invalid port '0', expected 1 to 65535".to_string()));
+        assert_eq!(send_params(&state, "repeat=101"),
+                   Err("invalid repeat count '101', expected 1 to 100".to_string()));
+        let err = "invalid burst: invalid interval '5ms', expected at least 10ms";
+        assert_eq!(send_params(&state, "burst=3x5ms"), Err(err.to_string()));
+        assert_eq!(send_params(&state, "burst=100x1m"),
+                   Err("invalid burst: the bursts take longer than the timeout of 1m".to_string()));
+    }
+}
@@ -0,0 +1,310 @@
+//! Signed wake requests over UDP, for clients which cannot speak HTTP, e.g. microcontrollers
+//! and routers.
+//!
+//! A request is a single datagram of 59 bytes:
+//!
+//! | Bytes  | Content                                                    |
+//! |--------|------------------------------------------------------------|
+//! | 0..4   | the magic `WOLS`                                           |
+//! | 4      | the version, 1                                             |
+//! | 5..11  | the MAC address to wake                                    |
+//! | 11..19 | the time of the request in seconds since the Unix epoch, big endian |
+//! | 19..27 | a nonce, which must differ between requests                |
+//! | 27..59 | the HMAC-SHA256 of bytes 0..27 with the shared key         |
+//!
+//! The receiver accepts a request only if the signature matches, the time is within a window
+//! around its own clock and the nonce was not seen in that window, so that recorded requests
+//! cannot be replayed. Requests are not answered, so that forged ones cannot be used to reflect
+//! traffic to other hosts.
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use redact::Secret;
+use MacAddr;
+
+/// The length of a signed request.
+pub const REQUEST_LEN: usize = 59;
+
+/// How far the time of a request may differ from the clock of the receiver, unless set
+/// otherwise.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(30);
+
+const MAGIC: &[u8; 4] = b"WOLS";
+const VERSION: u8 = 1;
+
+/// The length of the signed part of a request.
+const SIGNED_LEN: usize = 27;
+
+/// A request to wake a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignedRequest {
+    pub mac: MacAddr,
+    /// The time of the request in seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub nonce: [u8; 8],
+}
+
+impl SignedRequest {
+    /// Creates a request to wake `mac`, stamped with the current time and a random nonce.
+    pub fn new(mac: MacAddr) -> SignedRequest {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        // The hasher is seeded randomly; the time makes the nonce unique even if it was not.
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(now.as_nanos());
+        SignedRequest {
+            mac,
+            timestamp: now.as_secs(),
+            nonce: hasher.finish().to_be_bytes(),
+        }
+    }
+
+    /// Encodes the request and signs it with `key`.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use wol::MacAddr;
+    /// use wol::signed::{SignedRequest, Verifier};
+    ///
+    /// let request = SignedRequest::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+    /// let mut verifier = Verifier::new(b"s3cret");
+    /// assert_eq!(verifier.verify(&request.encode(b"s3cret")).unwrap(), request);
+    /// assert!(verifier.verify(&request.encode(b"s3cret")).is_err());
+    /// ```
+    pub fn encode(&self, key: &[u8]) -> [u8; REQUEST_LEN] {
+        let mut datagram = [0; REQUEST_LEN];
+        datagram[..4].copy_from_slice(MAGIC);
+        datagram[4] = VERSION;
+        datagram[5..11].copy_from_slice(&self.mac.octets());
+        datagram[11..19].copy_from_slice(&self.timestamp.to_be_bytes());
+        datagram[19..27].copy_from_slice(&self.nonce);
+        let signature = hmac_sha256(key, &datagram[..SIGNED_LEN]);
+        datagram[SIGNED_LEN..].copy_from_slice(&signature);
+        datagram
+    }
+}
+
+/// Checks the signature, the time and the nonce of requests.
+#[derive(Clone)]
+pub struct Verifier {
+    key: Vec<u8>,
+    window: Duration,
+    /// The nonces of the accepted requests with their time, until they leave the window.
+    seen: HashMap<[u8; 8], u64>,
+}
+
+impl Verifier {
+    /// Creates a verifier of requests signed with `key`.
+    pub fn new(key: &[u8]) -> Verifier {
+        Verifier {
+            key: key.to_vec(),
+            window: DEFAULT_WINDOW,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Accepts requests whose time differs at most `window` from the clock.
+    pub fn with_window(mut self, window: Duration) -> Verifier {
+        self.window = window;
+        self
+    }
+
+    /// Decodes and checks a request received now.
+    pub fn verify(&mut self, datagram: &[u8]) -> io::Result<SignedRequest> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        self.verify_at(datagram, now)
+    }
+
+    /// Decodes and checks a request received at `now`, in seconds since the Unix epoch. Fails
+    /// with `io::ErrorKind::InvalidData` for datagrams which are no signed requests and with
+    /// `io::ErrorKind::PermissionDenied` for wrong signatures, stale times and replays.
+    fn verify_at(&mut self, datagram: &[u8], now: u64) -> io::Result<SignedRequest> {
+        let denied = |message: &str| io::Error::new(io::ErrorKind::PermissionDenied, message);
+        if datagram.len() != REQUEST_LEN || &datagram[..4] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a signed wake request"));
+        }
+        if datagram[4] != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("unsupported version {}", datagram[4])));
+        }
+        let signature = hmac_sha256(&self.key, &datagram[..SIGNED_LEN]);
+        // Compares every byte, so that the time taken does not tell how much of it matched.
+        let diff = signature.iter().zip(&datagram[SIGNED_LEN..]).fold(0, |d, (a, b)| d | (a ^ b));
+        if diff != 0 {
+            return Err(denied("wrong signature"));
+        }
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&datagram[5..11]);
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&datagram[11..19]);
+        let mut nonce = [0; 8];
+        nonce.copy_from_slice(&datagram[19..27]);
+        let request = SignedRequest {
+            mac: MacAddr::new(mac),
+            timestamp: u64::from_be_bytes(timestamp),
+            nonce,
+        };
+        let window = self.window.as_secs();
+        if request.timestamp.abs_diff(now) > window {
+            return Err(denied("the time of the request is outside the window, check the clocks"));
+        }
+        self.seen.retain(|_, &mut timestamp| timestamp.abs_diff(now) <= window);
+        if self.seen.insert(nonce, request.timestamp).is_some() {
+            return Err(denied("replayed request"));
+        }
+        Ok(request)
+    }
+}
+
+impl fmt::Debug for Verifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Verifier")
+            .field("key", &Secret(&self.key))
+            .field("window", &self.window)
+            .field("seen", &self.seen.len())
+            .finish()
+    }
+}
+
+/// The round constants of SHA-256.
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Returns the SHA-256 hash of the data.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+                               0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+    // The data is padded with a one bit, zeros and its length in bits to whole blocks.
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (s, v) in state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(*v);
+        }
+    }
+    let mut hash = [0; 32];
+    for (bytes, word) in hash.chunks_mut(4).zip(&state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    hash
+}
+
+/// Returns the HMAC-SHA256 of the data with the given key, see RFC 2104.
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&sha256(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    inner.extend_from_slice(data);
+    let mut outer: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    outer.extend_from_slice(&sha256(&inner));
+    sha256(&outer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{hmac_sha256, sha256, SignedRequest, Verifier};
+    use MacAddr;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(hex(&sha256(b"")),
+                   "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(hex(&sha256(b"abc")),
+                   "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(hex(&sha256(&[b'a'; 1000])),
+                   "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3");
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // The test cases 2 and 6 of RFC 4231.
+        assert_eq!(hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+                   "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        assert_eq!(hex(&hmac_sha256(&[0xaa; 131],
+                                    b"Test Using Larger Than Block-Size Key - Hash Key First")),
+                   "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+    }
+
+    #[test]
+    fn test_verify() {
+        let request = SignedRequest {
+            mac: MacAddr::new([0, 1, 2, 3, 4, 5]),
+            timestamp: 1_000_000,
+            nonce: [1; 8],
+        };
+        let datagram = request.encode(b"s3cret");
+        assert_eq!(&datagram[..11], b"WOLS\x01\x00\x01\x02\x03\x04\x05");
+        let mut verifier = Verifier::new(b"s3cret");
+        let kind = |result: io::Result<SignedRequest>| result.unwrap_err().kind();
+        assert_eq!(kind(verifier.verify_at(&datagram, 1_000_031)),
+                   io::ErrorKind::PermissionDenied);
+        assert_eq!(verifier.verify_at(&datagram, 999_970).unwrap(), request);
+        assert_eq!(kind(verifier.verify_at(&datagram, 1_000_000)),
+                   io::ErrorKind::PermissionDenied);
+        assert_eq!(kind(Verifier::new(b"secret").verify_at(&datagram, 1_000_000)),
+                   io::ErrorKind::PermissionDenied);
+        let mut forged = datagram;
+        forged[10] = 6;
+        assert_eq!(kind(Verifier::new(b"s3cret").verify_at(&forged, 1_000_000)),
+                   io::ErrorKind::PermissionDenied);
+        assert_eq!(kind(verifier.verify_at(&datagram[..58], 1_000_000)),
+                   io::ErrorKind::InvalidData);
+
+        // Nonces are forgotten once their requests left the window.
+        let later = SignedRequest { timestamp: 1_000_100, ..request };
+        assert!(verifier.verify_at(&later.encode(b"s3cret"), 1_000_100).is_ok());
+        assert_eq!(verifier.seen.len(), 1);
+    }
+}
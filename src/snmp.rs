@@ -0,0 +1,425 @@
+//! Asking a managed switch per SNMP on which of its ports a MAC address was seen.
+//!
+//! A host which does not wake although the packet was sent is often on another segment than
+//! thought. Switches learn the port behind every source MAC address in their forwarding
+//! database, which the BRIDGE-MIB of RFC 4188 exposes: `dot1dTpFdbPort` maps a MAC address to
+//! a bridge port, `dot1dBasePortIfIndex` the bridge port to an interface, and `ifName` of the
+//! IF-MIB gives the interface its name, e.g. "Gi1/0/12". `Switch` gets these with SNMPv2c GET
+//! requests, encoded here, as this is all wol needs of SNMP.
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use MacAddr;
+
+/// The UDP port of SNMP agents.
+pub const DEFAULT_PORT: u16 = 161;
+
+/// How long to wait for an answer of the switch by default.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// `dot1dTpFdbPort`, indexed by the six octets of the MAC address.
+const DOT1D_TP_FDB_PORT: &[u32] = &[1, 3, 6, 1, 2, 1, 17, 4, 3, 1, 2];
+
+/// `dot1dBasePortIfIndex`, indexed by the bridge port.
+const DOT1D_BASE_PORT_IF_INDEX: &[u32] = &[1, 3, 6, 1, 2, 1, 17, 1, 4, 1, 2];
+
+/// `ifName`, indexed by the interface index.
+const IF_NAME: &[u32] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 1];
+
+/// The version field of SNMPv2c messages.
+const VERSION_2C: i64 = 1;
+
+/// How often a request is sent before giving up, as UDP datagrams get lost.
+const ATTEMPTS: u32 = 2;
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_GET_REQUEST: u8 = 0xA0;
+const TAG_GET_RESPONSE: u8 = 0xA2;
+
+/// The port of a switch a MAC address was seen on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchPort {
+    /// The number of the port in the bridge.
+    pub bridge_port: u32,
+    /// The name of the interface of the port, if the switch has the IF-MIB.
+    pub name: Option<String>,
+}
+
+impl fmt::Display for SwitchPort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "{} (bridge port {})", name, self.bridge_port),
+            None => write!(f, "bridge port {}", self.bridge_port),
+        }
+    }
+}
+
+/// A managed switch asked per SNMPv2c.
+///
+/// #Example
+///
+/// ```no_run
+/// use wol::snmp::Switch;
+/// use wol::MacAddr;
+///
+/// let switch = Switch::new("10.0.0.2:161".parse().unwrap(), "public");
+/// match switch.port_of(MacAddr::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55])).unwrap() {
+///     Some(port) => println!("seen on {}", port),
+///     None => println!("not in the forwarding database"),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Switch {
+    address: SocketAddr,
+    community: String,
+    timeout: Duration,
+}
+
+impl Switch {
+    /// Creates a switch whose SNMP agent listens at `address` and accepts `community`.
+    pub fn new(address: SocketAddr, community: &str) -> Switch {
+        Switch {
+            address,
+            community: community.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Resolves a host name or address with an optional port, e.g. "core-sw" or
+    /// "10.0.0.2:1161", to the switch.
+    pub fn resolve(address: &str, community: &str) -> io::Result<Switch> {
+        let resolved = match address.to_socket_addrs() {
+            Ok(mut addrs) => addrs.next(),
+            Err(_) => (address, DEFAULT_PORT).to_socket_addrs()?.next(),
+        };
+        let address = resolved.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no address of {}", address))
+        })?;
+        Ok(Switch::new(address, community))
+    }
+
+    /// Waits at most `timeout` for each answer of the switch.
+    pub fn with_timeout(mut self, timeout: Duration) -> Switch {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the address of the SNMP agent.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Returns the port the switch last saw `mac` on, or `None` if its forwarding database has
+    /// no entry for it, e.g. because the entry aged out while the host slept.
+    pub fn port_of(&self, mac: MacAddr) -> io::Result<Option<SwitchPort>> {
+        let mut oid = DOT1D_TP_FDB_PORT.to_vec();
+        oid.extend(mac.octets().iter().map(|&octet| u32::from(octet)));
+        let bridge_port = match self.get(&oid)? {
+            Value::Integer(port) => {
+                u32::try_from(port).map_err(|_| invalid("bridge port out of range"))?
+            },
+            Value::Missing => return Ok(None),
+            Value::Bytes(_) => return Err(invalid("expected a bridge port")),
+        };
+        // The name is a nicety; switches without the IF-MIB still tell the bridge port.
+        let name = self.interface_name(bridge_port).unwrap_or_else(|err| {
+            debug!("cannot get the name of bridge port {} of {}: {}",
+                   bridge_port,
+                   self.address,
+                   err);
+            None
+        });
+        Ok(Some(SwitchPort { bridge_port, name }))
+    }
+
+    /// Returns the name of the interface of a bridge port, if the switch knows it.
+    fn interface_name(&self, bridge_port: u32) -> io::Result<Option<String>> {
+        let mut oid = DOT1D_BASE_PORT_IF_INDEX.to_vec();
+        oid.push(bridge_port);
+        let if_index = match self.get(&oid)? {
+            Value::Integer(index) => u32::try_from(index).map_err(|_| invalid("bad ifIndex"))?,
+            _ => return Ok(None),
+        };
+        let mut oid = IF_NAME.to_vec();
+        oid.push(if_index);
+        match self.get(&oid)? {
+            Value::Bytes(name) => Ok(Some(String::from_utf8_lossy(&name).into_owned())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Gets the value of one object, sending the request again if no answer arrives.
+    fn get(&self, oid: &[u32]) -> io::Result<Value> {
+        let local: SocketAddr = match self.address {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(self.address)?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        let request_id = request_id();
+        let request = get_request(&self.community, request_id, oid);
+        let mut buf = [0; 1500];
+        for attempt in 1..=ATTEMPTS {
+            socket.send(&request)?;
+            match socket.recv(&mut buf) {
+                Ok(len) => return parse_response(&buf[..len], request_id),
+                Err(ref err) if is_timeout(err) && attempt < ATTEMPTS => continue,
+                Err(ref err) if is_timeout(err) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut,
+                           format!("no answer from {}, check the address and community",
+                                   self.address)))
+    }
+}
+
+/// The value of an object in a response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Integer(i64),
+    /// An octet string, e.g. a name.
+    Bytes(Vec<u8>),
+    /// `noSuchObject`, `noSuchInstance` or `endOfMibView`.
+    Missing,
+}
+
+/// Returns a request ID which differs between processes and calls.
+fn request_id() -> i32 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+    ((nanos ^ process::id().rotate_left(16)) & 0x7FFF_FFFF) as i32
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid SNMP response: {}", message))
+}
+
+/// Encodes an SNMPv2c GetRequest for one object.
+fn get_request(community: &str, request_id: i32, oid: &[u32]) -> Vec<u8> {
+    let varbind = tlv(TAG_SEQUENCE, &[encode_oid(oid), tlv(TAG_NULL, &[])].concat());
+    let pdu = [integer(i64::from(request_id)), integer(0), integer(0), tlv(TAG_SEQUENCE, &varbind)]
+        .concat();
+    let message = [integer(VERSION_2C),
+                   tlv(TAG_OCTET_STRING, community.as_bytes()),
+                   tlv(TAG_GET_REQUEST, &pdu)]
+        .concat();
+    tlv(TAG_SEQUENCE, &message)
+}
+
+/// Decodes the value of the first object of the GetResponse to the request with `request_id`.
+fn parse_response(bytes: &[u8], request_id: i32) -> io::Result<Value> {
+    let mut message = Reader(bytes).expect(TAG_SEQUENCE)?;
+    if message.integer()? != VERSION_2C {
+        return Err(invalid("not SNMPv2c"));
+    }
+    message.expect(TAG_OCTET_STRING)?;
+    let mut pdu = message.expect(TAG_GET_RESPONSE)?;
+    if pdu.integer()? != i64::from(request_id) {
+        return Err(invalid("answer to another request"));
+    }
+    let error_status = pdu.integer()?;
+    pdu.integer()?;
+    if error_status == 2 {
+        // noSuchName, as SNMPv1 agents answer for objects they do not have.
+        return Ok(Value::Missing);
+    }
+    if error_status != 0 {
+        return Err(invalid(&format!("error status {}", error_status)));
+    }
+    let mut varbind = pdu.expect(TAG_SEQUENCE)?.expect(TAG_SEQUENCE)?;
+    varbind.expect(TAG_OID)?;
+    let (tag, content) = varbind.next()?;
+    match tag {
+        TAG_INTEGER => Ok(Value::Integer(decode_integer(content)?)),
+        TAG_OCTET_STRING => Ok(Value::Bytes(content.to_vec())),
+        0x80..=0x82 => Ok(Value::Missing),
+        _ => Err(invalid(&format!("unexpected type 0x{:02x}", tag))),
+    }
+}
+
+/// Encodes a tag, the length of the content in the definite form and the content.
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let octets: Vec<u8> =
+            len.to_be_bytes().iter().cloned().skip_while(|&octet| octet == 0).collect();
+        out.push(0x80 | octets.len() as u8);
+        out.extend(octets);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Encodes an integer in the fewest octets of two's complement.
+fn integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < 7 {
+        let (first, second) = (bytes[start], bytes[start + 1]);
+        if (first == 0 && second & 0x80 == 0) || (first == 0xFF && second & 0x80 != 0) {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    tlv(TAG_INTEGER, &bytes[start..])
+}
+
+/// Encodes an object identifier, whose first two arcs share the first octet.
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut content = vec![(oid[0] * 40 + oid[1]) as u8];
+    for &arc in &oid[2..] {
+        let mut octets = vec![(arc & 0x7F) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            octets.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        content.extend(octets.iter().rev());
+    }
+    tlv(TAG_OID, &content)
+}
+
+fn decode_integer(content: &[u8]) -> io::Result<i64> {
+    if content.is_empty() || content.len() > 8 {
+        return Err(invalid("integer of bad length"));
+    }
+    let negative = content[0] & 0x80 != 0;
+    Ok(content.iter().fold(if negative { -1 } else { 0 }, |value, &octet| {
+        (value << 8) | i64::from(octet)
+    }))
+}
+
+/// Reads the elements of a BER encoded sequence one after the other.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Returns the tag and content of the next element.
+    fn next(&mut self) -> io::Result<(u8, &'a [u8])> {
+        let truncated = || invalid("truncated");
+        let tag = *self.0.first().ok_or_else(truncated)?;
+        let first = *self.0.get(1).ok_or_else(truncated)?;
+        let (len, header) = if first < 0x80 {
+            (usize::from(first), 2)
+        } else {
+            let count = usize::from(first & 0x7F);
+            if count == 0 || count > 4 {
+                return Err(invalid("unsupported length"));
+            }
+            let octets = self.0.get(2..2 + count).ok_or_else(truncated)?;
+            (octets.iter().fold(0, |len, &octet| (len << 8) | usize::from(octet)), 2 + count)
+        };
+        let content = self.0.get(header..header + len).ok_or_else(truncated)?;
+        self.0 = &self.0[header + len..];
+        Ok((tag, content))
+    }
+
+    /// Returns a reader of the content of the next element, which must have the given tag.
+    fn expect(&mut self, tag: u8) -> io::Result<Reader<'a>> {
+        match self.next()? {
+            (found, content) if found == tag => Ok(Reader(content)),
+            (found, _) => {
+                Err(invalid(&format!("expected type 0x{:02x}, got 0x{:02x}", tag, found)))
+            },
+        }
+    }
+
+    fn integer(&mut self) -> io::Result<i64> {
+        decode_integer(self.expect(TAG_INTEGER)?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::thread;
+
+    use super::{encode_oid, get_request, integer, parse_response, tlv, Reader, Switch,
+                SwitchPort, Value, DOT1D_TP_FDB_PORT, TAG_GET_REQUEST, TAG_OCTET_STRING,
+                TAG_SEQUENCE};
+    use MacAddr;
+
+    /// Encodes the GetResponse to `request_id` with the given value.
+    fn response(request_id: i64, oid: &[u32], value: Vec<u8>) -> Vec<u8> {
+        let varbind = tlv(TAG_SEQUENCE, &[encode_oid(oid), value].concat());
+        let pdu = [integer(request_id), integer(0), integer(0), tlv(TAG_SEQUENCE, &varbind)]
+            .concat();
+        let message = [integer(1), tlv(TAG_OCTET_STRING, b"public"), tlv(0xA2, &pdu)].concat();
+        tlv(TAG_SEQUENCE, &message)
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(integer(0), [0x02, 0x01, 0x00]);
+        assert_eq!(integer(128), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(integer(-1), [0x02, 0x01, 0xFF]);
+        assert_eq!(encode_oid(&[1, 3, 6, 1, 2, 1, 17, 300]),
+                   [0x06, 0x08, 0x2B, 6, 1, 2, 1, 17, 0x82, 0x2C]);
+        assert_eq!(&tlv(0x04, &[0; 200])[..3], &[0x04, 0x81, 200]);
+        assert_eq!(get_request("public", 7, &[1, 3, 6, 1]),
+                   [0x30, 0x21, 0x02, 0x01, 0x01, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c',
+                    0xA0, 0x14, 0x02, 0x01, 0x07, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00, 0x30, 0x09,
+                    0x30, 0x07, 0x06, 0x03, 0x2B, 0x06, 0x01, 0x05, 0x00]);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let oid = [1, 3, 6, 1];
+        assert_eq!(parse_response(&response(7, &oid, integer(12)), 7).unwrap(),
+                   Value::Integer(12));
+        assert_eq!(parse_response(&response(7, &oid, tlv(0x04, b"Gi1/0/12")), 7).unwrap(),
+                   Value::Bytes(b"Gi1/0/12".to_vec()));
+        assert_eq!(parse_response(&response(7, &oid, tlv(0x81, &[])), 7).unwrap(),
+                   Value::Missing);
+        assert!(parse_response(&response(8, &oid, integer(12)), 7).is_err());
+        assert!(parse_response(&response(7, &oid, integer(12))[..20], 7).is_err());
+        let mut reader = Reader(&[0x02, 0x01, 0xFE]);
+        assert_eq!(reader.integer().unwrap(), -2);
+        assert!(reader.expect(TAG_GET_REQUEST).is_err());
+    }
+
+    #[test]
+    fn test_port_of() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let switch = Switch::resolve(&agent.local_addr().unwrap().to_string(), "public").unwrap();
+        let mac = MacAddr::new([0, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        let answers = thread::spawn(move || {
+            let values = [integer(12), integer(10012), tlv(0x04, b"Gi1/0/12")];
+            for value in values.iter() {
+                let mut buf = [0; 1500];
+                let (len, peer) = agent.recv_from(&mut buf).unwrap();
+                let mut message = Reader(&buf[..len]).expect(TAG_SEQUENCE).unwrap();
+                message.integer().unwrap();
+                message.expect(TAG_OCTET_STRING).unwrap();
+                let mut pdu = message.expect(TAG_GET_REQUEST).unwrap();
+                let request_id = pdu.integer().unwrap();
+                let response = response(request_id, DOT1D_TP_FDB_PORT, value.clone());
+                agent.send_to(&response, peer).unwrap();
+            }
+        });
+        let port = switch.port_of(mac).unwrap().unwrap();
+        answers.join().unwrap();
+        assert_eq!(port,
+                   SwitchPort {
+                       bridge_port: 12,
+                       name: Some("Gi1/0/12".to_string()),
+                   });
+        assert_eq!(port.to_string(), "Gi1/0/12 (bridge port 12)");
+    }
+}
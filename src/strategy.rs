@@ -0,0 +1,335 @@
+//! Trying several ways of reaching a host until one wakes it.
+//!
+//! Which packets reach a sleeping host depends on the network: routers drop the limited
+//! broadcast, many have directed broadcasts turned off, and unicast only works if the switch or
+//! router still knows the MAC address of the host. With `Strategy::Auto`, `Batch` sends along
+//! each `Path` in turn and verifies in between, so that users need not know their network to
+//! find out what works.
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use subnet::Cidr;
+use {MacAddr, MagicPacket, SendReport, WolClient};
+
+/// How the magic packet of a host is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Once, to the broadcast address or IP address the host is configured with.
+    Single,
+    /// Along each path of `paths` in turn until the host comes up, for hosts with an IP address.
+    Auto,
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Strategy::Single => "single",
+            Strategy::Auto => "auto",
+        })
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Strategy, String> {
+        match s {
+            "single" => Ok(Strategy::Single),
+            "auto" => Ok(Strategy::Auto),
+            _ => Err(format!("unknown strategy '{}', expected single or auto", s)),
+        }
+    }
+}
+
+/// One way of sending the magic packet to a host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Path {
+    /// To 255.255.255.255, which reaches the networks of all interfaces but no router.
+    LimitedBroadcast,
+    /// To the broadcast address of the network of the host.
+    DirectedBroadcast(IpAddr),
+    /// To the IP address of the host.
+    Unicast(IpAddr),
+    /// As Ethernet frame with EtherType 0x0842 through the given interface, without IP, which
+    /// needs the `raw` feature and the `CAP_NET_RAW` capability.
+    RawFrame(String),
+    /// As raw frame like `RawFrame`, tagged for the given VLAN, through an interface connected to
+    /// a trunk port.
+    TaggedFrame(String, Vlan),
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Path::LimitedBroadcast => f.write_str("limited broadcast"),
+            Path::DirectedBroadcast(ip) => write!(f, "directed broadcast to {}", ip),
+            Path::Unicast(ip) => write!(f, "unicast to {}", ip),
+            Path::RawFrame(ref interface) => write!(f, "raw frame on {}", interface),
+            Path::TaggedFrame(ref interface, vlan) => {
+                write!(f, "raw frame on {}, {}", interface, vlan)
+            },
+        }
+    }
+}
+
+impl Path {
+    /// Sends the packet along this path with the port and repetitions of `client`. Returns the
+    /// report of the datagram, or `None` for a raw frame, which has no socket addresses.
+    pub fn send(&self,
+                client: &WolClient,
+                packet: &MagicPacket)
+                -> io::Result<Option<SendReport>> {
+        self.send_with(client, packet, None)
+    }
+
+    /// Sends the packet like `send`, but raw frames from the MAC address `source` instead of
+    /// that of their interface, e.g. for network cards which only accept frames from a known
+    /// management station. Other machines then see `source` on the port of this machine.
+    pub fn send_from(&self,
+                     client: &WolClient,
+                     packet: &MagicPacket,
+                     source: MacAddr)
+                     -> io::Result<Option<SendReport>> {
+        self.send_with(client, packet, Some(source))
+    }
+
+    fn send_with(&self,
+                 client: &WolClient,
+                 packet: &MagicPacket,
+                 source: Option<MacAddr>)
+                 -> io::Result<Option<SendReport>> {
+        let client = match *self {
+            Path::LimitedBroadcast => client.clone().with_broadcast(Ipv4Addr::BROADCAST.into()),
+            Path::DirectedBroadcast(ip) | Path::Unicast(ip) => client.clone().with_broadcast(ip),
+            Path::RawFrame(ref interface) => {
+                return send_frame(interface, None, source, packet).map(|_| None)
+            },
+            Path::TaggedFrame(ref interface, vlan) => {
+                return send_frame(interface, Some(vlan), source, packet).map(|_| None)
+            },
+        };
+        client.send(packet).map(Some)
+    }
+}
+
+/// The IEEE 802.1Q tag of a VLAN, with which a raw frame sent through a trunk port reaches the
+/// hosts of the VLAN without this machine having an interface in it.
+///
+/// #Example
+///
+/// ```
+/// use wol::strategy::Vlan;
+///
+/// let vlan = Vlan::new(30, 5).unwrap();
+/// assert_eq!(vlan.to_string(), "VLAN 30, priority 5");
+/// assert!(Vlan::new(4095, 0).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Vlan {
+    id: u16,
+    pcp: u8,
+}
+
+impl Vlan {
+    /// Creates the tag of the VLAN with the given ID, from 1 to 4094, and the priority code
+    /// point, from 0 (best effort, the default of untagged frames) to 7.
+    pub fn new(id: u16, pcp: u8) -> Result<Vlan, String> {
+        if !(1..=4094).contains(&id) {
+            return Err(format!("VLAN ID {} out of range 1-4094", id));
+        }
+        if pcp > 7 {
+            return Err(format!("priority {} out of range 0-7", pcp));
+        }
+        Ok(Vlan { id, pcp })
+    }
+
+    /// Returns the ID of the VLAN.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the priority code point.
+    pub fn pcp(&self) -> u8 {
+        self.pcp
+    }
+
+    /// Returns the tag control information: the priority, the drop eligible bit, which is
+    /// unset, and the ID.
+    #[cfg(any(feature = "raw", test))]
+    fn tci(&self) -> u16 {
+        u16::from(self.pcp) << 13 | self.id
+    }
+}
+
+impl fmt::Display for Vlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VLAN {}", self.id)?;
+        if self.pcp != 0 {
+            write!(f, ", priority {}", self.pcp)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the paths to the host at `ip` in the order they are tried, given the networks the
+/// system is directly connected to: the broadcasts first, as a sleeping host does not answer
+/// ARP, then unicast for hosts behind routers and last, with the `raw` feature, a raw frame for
+/// hosts on a connected network whose card ignores UDP. IPv6 has no broadcast, so hosts with an
+/// IPv6 address are only sent to directly.
+///
+/// #Example
+///
+/// ```
+/// use wol::strategy::{self, Path};
+///
+/// let lan = "192.168.1.0/24".parse().unwrap();
+/// let paths = strategy::paths("10.0.0.7".parse().unwrap(), &[lan]);
+/// assert_eq!(paths, [Path::LimitedBroadcast, Path::Unicast("10.0.0.7".parse().unwrap())]);
+/// ```
+pub fn paths(ip: IpAddr, networks: &[Cidr]) -> Vec<Path> {
+    let ipv4 = match ip {
+        IpAddr::V4(ipv4) => ipv4,
+        IpAddr::V6(_) => return vec![Path::Unicast(ip)],
+    };
+    let mut paths = vec![Path::LimitedBroadcast];
+    let network = networks.iter().find(|network| network.contains(ip));
+    if let Some(broadcast) = network.and_then(Cidr::broadcast) {
+        paths.push(Path::DirectedBroadcast(broadcast));
+    }
+    paths.push(Path::Unicast(ip));
+    if network.is_some() {
+        if let Some(interface) = raw_interface(ipv4) {
+            paths.push(Path::RawFrame(interface));
+        }
+    }
+    paths
+}
+
+/// Returns the interface raw frames to `ip` are sent through.
+#[cfg(feature = "raw")]
+fn raw_interface(ip: Ipv4Addr) -> Option<String> {
+    ::arp::interface_for(ip).ok()
+}
+
+/// Returns the interface raw frames to `ip` are sent through.
+#[cfg(not(feature = "raw"))]
+fn raw_interface(_ip: Ipv4Addr) -> Option<String> {
+    None
+}
+
+/// Sends the packet as broadcast Ethernet frame through `interface`, tagged for `vlan` if given,
+/// from `source` or else the MAC address of the interface.
+#[cfg(feature = "raw")]
+fn send_frame(interface: &str,
+              vlan: Option<Vlan>,
+              source: Option<MacAddr>,
+              packet: &MagicPacket)
+              -> io::Result<usize> {
+    use pcap::ETHERTYPE_WOL;
+    use sys::{self, RawSocket};
+
+    let mut socket = RawSocket::open(ETHERTYPE_WOL, Some(interface)).map_err(|err| {
+        if err.kind() == io::ErrorKind::PermissionDenied {
+            io::Error::new(err.kind(), "raw frames need the CAP_NET_RAW capability")
+        } else {
+            err
+        }
+    })?;
+    let source = match source {
+        Some(source) => source,
+        None => sys::interface_mac(interface)?,
+    };
+    socket.send(&frame(source.octets(), vlan, packet))
+}
+
+/// Sends the packet as broadcast Ethernet frame through `interface`, tagged for `vlan` if given,
+/// from `source` or else the MAC address of the interface.
+#[cfg(not(feature = "raw"))]
+fn send_frame(_interface: &str,
+              _vlan: Option<Vlan>,
+              _source: Option<MacAddr>,
+              _packet: &MagicPacket)
+              -> io::Result<usize> {
+    Err(io::Error::other("raw frames need Linux and the raw feature"))
+}
+
+/// Builds the broadcast frame carrying the packet, sent from `source`, with the tag of `vlan`
+/// after the addresses if given.
+#[cfg(any(feature = "raw", test))]
+fn frame(source: [u8; 6], vlan: Option<Vlan>, packet: &MagicPacket) -> Vec<u8> {
+    let mut frame = vec![0xFF; 6];
+    frame.extend_from_slice(&source);
+    if let Some(vlan) = vlan {
+        frame.extend_from_slice(&::pcap::ETHERTYPE_VLAN.to_be_bytes());
+        frame.extend_from_slice(&vlan.tci().to_be_bytes());
+    }
+    frame.extend_from_slice(&::pcap::ETHERTYPE_WOL.to_be_bytes());
+    frame.extend_from_slice(&packet.to_bytes());
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::time::SystemTime;
+
+    use analyze::decode_frame;
+    use super::{frame, paths, Path, Strategy, Vlan};
+    use {MacAddr, MagicPacket};
+
+    #[test]
+    fn test_paths() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let lan = "192.168.1.0/24".parse().unwrap();
+        let paths = paths(ip("192.168.1.10"), &[lan]);
+        assert_eq!(&paths[..3],
+                   &[Path::LimitedBroadcast,
+                     Path::DirectedBroadcast(ip("192.168.1.255")),
+                     Path::Unicast(ip("192.168.1.10"))]);
+        assert!(paths[3..].iter().all(|path| matches!(path, Path::RawFrame(_))));
+        assert_eq!(super::paths(ip("fd00::7"), &[lan]), [Path::Unicast(ip("fd00::7"))]);
+        assert_eq!(Path::DirectedBroadcast(ip("192.168.1.255")).to_string(),
+                   "directed broadcast to 192.168.1.255");
+    }
+
+    #[test]
+    fn test_frame() {
+        let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+        let frame = frame([0xA; 6], None, &packet);
+        assert_eq!(frame.len(), 14 + 102);
+        assert_eq!(&frame[..6], &[0xFF; 6]);
+        assert_eq!(&frame[6..12], &[0xA; 6]);
+        assert_eq!(&frame[12..14], &[0x08, 0x42]);
+        assert_eq!(&frame[14..], &packet.to_bytes()[..]);
+    }
+
+    #[test]
+    fn test_tagged_frame() {
+        let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+        let frame = frame([0xA; 6], Some(Vlan::new(30, 5).unwrap()), &packet);
+        assert_eq!(frame.len(), 18 + 102);
+        assert_eq!(&frame[12..18], &[0x81, 0x00, 0xA0, 30, 0x08, 0x42]);
+        assert_eq!(&frame[18..], &packet.to_bytes()[..]);
+        let sighting = decode_frame(SystemTime::now(), &frame).unwrap();
+        assert_eq!(sighting.packet, packet);
+    }
+
+    #[test]
+    fn test_vlan() {
+        assert_eq!(Vlan::new(0, 0), Err("VLAN ID 0 out of range 1-4094".to_string()));
+        assert_eq!(Vlan::new(1, 8), Err("priority 8 out of range 0-7".to_string()));
+        let vlan = Vlan::new(4094, 7).unwrap();
+        assert_eq!((vlan.id(), vlan.pcp(), vlan.tci()), (4094, 7, 0xEFFE));
+        assert_eq!(Path::TaggedFrame("eth0".to_string(), Vlan::new(30, 0).unwrap()).to_string(),
+                   "raw frame on eth0, VLAN 30");
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert_eq!("auto".parse(), Ok(Strategy::Auto));
+        assert_eq!(Strategy::Single.to_string(), "single");
+        assert!("all".parse::<Strategy>().unwrap_err().contains("expected single or auto"));
+    }
+}
@@ -0,0 +1,664 @@
+//! Subnet calculations, e.g. the directed broadcast address of a network, and the networks the
+//! system is directly connected to, to choose where to send the packet of a host, see
+//! `Addressing`, and whether the link of an interface is up, see `link_state`.
+use std::fmt;
+#[cfg(any(target_os = "linux", test))]
+use std::fs;
+use std::io;
+#[cfg(any(target_os = "linux", test))]
+use std::net::Ipv6Addr;
+use std::net::{IpAddr, Ipv4Addr};
+#[cfg(any(target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly"))]
+use std::process::Command;
+use std::str::FromStr;
+
+/// Returns the directed broadcast address of the IPv4 network of `ip` with the given prefix
+/// length, e.g. 10.1.7.255 for 10.1.4.0/22.
+///
+/// Returns `None` for IPv6, which has no broadcast, and for prefix lengths above 32.
+///
+/// #Example
+///
+/// ```
+/// use std::net::IpAddr;
+///
+/// let ip: IpAddr = "10.1.4.0".parse().unwrap();
+/// assert_eq!(wol::broadcast_for(ip, 22), Some("10.1.7.255".parse().unwrap()));
+/// ```
+pub fn broadcast_for(ip: IpAddr, prefix_len: u8) -> Option<IpAddr> {
+    match ip {
+        IpAddr::V4(ip) if prefix_len <= 32 => {
+            let host_mask = u32::MAX.checked_shr(u32::from(prefix_len)).unwrap_or(0);
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from(ip) | host_mask)))
+        },
+        _ => None,
+    }
+}
+
+/// A network in CIDR notation, e.g. 10.1.4.0/22.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl Cidr {
+    /// Returns the directed broadcast address of the network, see `broadcast_for`.
+    pub fn broadcast(&self) -> Option<IpAddr> {
+        broadcast_for(self.addr, self.prefix_len)
+    }
+
+    /// Returns whether `ip` is in the network.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix_len)).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix_len)).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Where to send the magic packet of a host with a known IP address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Addressing {
+    /// To the directed broadcast address of its network if the system is directly connected to
+    /// it, otherwise to its IP address, see `target_for`.
+    Auto,
+    /// To the configured or default broadcast address, regardless of its IP address.
+    Broadcast,
+    /// To its IP address, e.g. for routers with a static ARP entry for the host.
+    Unicast,
+}
+
+impl fmt::Display for Addressing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Addressing::Auto => "auto",
+            Addressing::Broadcast => "broadcast",
+            Addressing::Unicast => "unicast",
+        })
+    }
+}
+
+impl FromStr for Addressing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Addressing, String> {
+        match s {
+            "auto" => Ok(Addressing::Auto),
+            "broadcast" => Ok(Addressing::Broadcast),
+            "unicast" => Ok(Addressing::Unicast),
+            _ => Err(format!("unknown addressing '{}', expected auto, broadcast or unicast", s)),
+        }
+    }
+}
+
+/// Returns where to send the packet of the host at `ip`, or `None` for the configured or default
+/// broadcast address, given the networks the system is directly connected to.
+///
+/// With `Addressing::Auto`, a host on a connected IPv4 network gets the directed broadcast of
+/// that network, as a sleeping host does not answer ARP and cannot be sent to directly, and a
+/// host on a connected IPv6 network the default multicast group. Other hosts are behind a
+/// router, which may know their MAC address, e.g. from a static entry, so they are sent to
+/// directly. The networks are those of the interfaces rather than the neighbor table, which
+/// holds hosts of other subnets with proxy ARP.
+///
+/// #Example
+///
+/// ```
+/// use wol::subnet::{self, Addressing, Cidr};
+///
+/// let lan: Cidr = "192.168.1.0/24".parse().unwrap();
+/// let target = |ip: &str| subnet::target_for(ip.parse().unwrap(), Addressing::Auto, &[lan]);
+/// assert_eq!(target("192.168.1.10"), Some("192.168.1.255".parse().unwrap()));
+/// assert_eq!(target("10.0.0.7"), Some("10.0.0.7".parse().unwrap()));
+/// ```
+pub fn target_for(ip: IpAddr, addressing: Addressing, networks: &[Cidr]) -> Option<IpAddr> {
+    match addressing {
+        Addressing::Broadcast => None,
+        Addressing::Unicast => Some(ip),
+        Addressing::Auto => match networks.iter().find(|network| network.contains(ip)) {
+            Some(network) => network.broadcast(),
+            None => Some(ip),
+        },
+    }
+}
+
+/// Returns the networks the system is directly connected to, without those of the loopback
+/// interface.
+///
+/// They are read from the routing tables in `/proc/net` on Linux and from the output of
+/// `ifconfig -a` on macOS and the BSDs. Other systems are not supported.
+#[cfg(target_os = "linux")]
+pub fn connected_networks() -> io::Result<Vec<Cidr>> {
+    networks_of(None)
+}
+
+/// Returns the networks the network interface with the given name, e.g. "eth0", is directly
+/// connected to, like `connected_networks`.
+#[cfg(target_os = "linux")]
+pub fn interface_networks(interface: &str) -> io::Result<Vec<Cidr>> {
+    networks_of(Some(interface))
+}
+
+/// Returns the networks of the interface or, without one, of all but the loopback interface.
+#[cfg(target_os = "linux")]
+fn networks_of(interface: Option<&str>) -> io::Result<Vec<Cidr>> {
+    let mut networks = parse_proc_route(&fs::read_to_string("/proc/net/route")?, interface);
+    // Systems without IPv6 have no IPv6 routing table.
+    if let Ok(table) = fs::read_to_string("/proc/net/ipv6_route") {
+        networks.extend(parse_proc_ipv6_route(&table, interface));
+    }
+    Ok(networks)
+}
+
+/// Returns the networks the system is directly connected to, without those of the loopback
+/// interface.
+#[cfg(any(target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly"))]
+pub fn connected_networks() -> io::Result<Vec<Cidr>> {
+    let output = Command::new("ifconfig").arg("-a").output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("ifconfig -a failed"));
+    }
+    Ok(parse_ifconfig(&String::from_utf8_lossy(&output.stdout), None))
+}
+
+/// Returns the networks the network interface with the given name, e.g. "en0", is directly
+/// connected to, like `connected_networks`.
+#[cfg(any(target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly"))]
+pub fn interface_networks(interface: &str) -> io::Result<Vec<Cidr>> {
+    let output = Command::new("ifconfig").arg(interface).output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(io::ErrorKind::NotFound,
+                                  format!("no network interface {}", interface)));
+    }
+    Ok(parse_ifconfig(&String::from_utf8_lossy(&output.stdout), Some(interface)))
+}
+
+/// Returns the networks the system is directly connected to, which is not supported here.
+#[cfg(not(any(target_os = "linux",
+              target_os = "macos",
+              target_os = "freebsd",
+              target_os = "openbsd",
+              target_os = "netbsd",
+              target_os = "dragonfly")))]
+pub fn connected_networks() -> io::Result<Vec<Cidr>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported,
+                       "reading the connected networks is not supported on this system"))
+}
+
+/// Returns the networks of a network interface, which is not supported here.
+#[cfg(not(any(target_os = "linux",
+              target_os = "macos",
+              target_os = "freebsd",
+              target_os = "openbsd",
+              target_os = "netbsd",
+              target_os = "dragonfly")))]
+pub fn interface_networks(_interface: &str) -> io::Result<Vec<Cidr>> {
+    connected_networks()
+}
+
+/// Returns the names of the network interfaces of the system but the loopback interface, e.g.
+/// to list their networks with `interface_networks`.
+///
+/// They are read from `/proc/net/dev` on Linux and from the output of `ifconfig -l` on macOS and
+/// the BSDs. Other systems are not supported.
+#[cfg(target_os = "linux")]
+pub fn interface_names() -> io::Result<Vec<String>> {
+    Ok(parse_proc_dev(&fs::read_to_string("/proc/net/dev")?))
+}
+
+/// Returns the names of the network interfaces of the system but the loopback interface.
+#[cfg(any(target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly"))]
+pub fn interface_names() -> io::Result<Vec<String>> {
+    let output = Command::new("ifconfig").arg("-l").output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("ifconfig -l failed"));
+    }
+    let names = String::from_utf8_lossy(&output.stdout);
+    Ok(names.split_whitespace()
+        .filter(|name| !name.starts_with("lo"))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Returns the names of the network interfaces, which is not supported here.
+#[cfg(not(any(target_os = "linux",
+              target_os = "macos",
+              target_os = "freebsd",
+              target_os = "openbsd",
+              target_os = "netbsd",
+              target_os = "dragonfly")))]
+pub fn interface_names() -> io::Result<Vec<String>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported,
+                       "listing the network interfaces is not supported on this system"))
+}
+
+/// The state of the link of a network interface, see `link_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link {
+    /// Whether the interface was brought up, e.g. with `ip link set eth0 up` or by
+    /// NetworkManager or systemd-networkd.
+    pub up: bool,
+    /// Whether the interface has a carrier, e.g. a plugged-in cable or an associated access
+    /// point. Interfaces without the notion, e.g. tunnels, always have one while they are up.
+    pub carrier: bool,
+}
+
+impl Link {
+    /// Fails with `io::ErrorKind::NetworkDown` unless the interface is up and has a carrier, as
+    /// packets sent through it would be dropped without an error.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::io;
+    /// use wol::subnet::Link;
+    ///
+    /// let unplugged = Link { up: true, carrier: false };
+    /// let err = unplugged.check("eth0").unwrap_err();
+    /// assert_eq!(err.kind(), io::ErrorKind::NetworkDown);
+    /// assert_eq!(err.to_string(), "interface eth0 has no carrier, is its cable plugged in?");
+    /// ```
+    pub fn check(self, interface: &str) -> io::Result<()> {
+        let problem = match self {
+            Link { up: false, .. } => "is down",
+            Link { carrier: false, .. } => "has no carrier, is its cable plugged in?",
+            _ => return Ok(()),
+        };
+        Err(io::Error::new(io::ErrorKind::NetworkDown,
+                           format!("interface {} {}", interface, problem)))
+    }
+}
+
+/// Returns the state of the link of the network interface with the given name, e.g. "eth0".
+///
+/// It is read from `/sys/class/net` on Linux, which NetworkManager and systemd-networkd keep up
+/// to date like netlink, and from the output of `ifconfig` on macOS and the BSDs. Other systems
+/// are not supported.
+#[cfg(target_os = "linux")]
+pub fn link_state(interface: &str) -> io::Result<Link> {
+    let dir = std::path::Path::new("/sys/class/net").join(interface);
+    let flags = fs::read_to_string(dir.join("flags")).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => {
+            io::Error::new(io::ErrorKind::NotFound, format!("no network interface {}", interface))
+        },
+        _ => err,
+    })?;
+    let flags = u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid interface flags"))?;
+    // Reading the carrier of an interface which is down fails.
+    let carrier =
+        fs::read_to_string(dir.join("carrier")).is_ok_and(|carrier| carrier.trim() == "1");
+    Ok(Link {
+        up: flags & IFF_UP != 0,
+        carrier,
+    })
+}
+
+/// The flag of interfaces which are up, of `/sys/class/net/*/flags`.
+#[cfg(target_os = "linux")]
+const IFF_UP: u32 = 0x1;
+
+/// Returns the state of the link of the network interface with the given name, e.g. "en0".
+#[cfg(any(target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly"))]
+pub fn link_state(interface: &str) -> io::Result<Link> {
+    let output = Command::new("ifconfig").arg(interface).output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(io::ErrorKind::NotFound,
+                                  format!("no network interface {}", interface)));
+    }
+    Ok(parse_ifconfig_link(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Returns the state of the link of a network interface, which is not supported here.
+#[cfg(not(any(target_os = "linux",
+              target_os = "macos",
+              target_os = "freebsd",
+              target_os = "openbsd",
+              target_os = "netbsd",
+              target_os = "dragonfly")))]
+pub fn link_state(_interface: &str) -> io::Result<Link> {
+    Err(io::Error::new(io::ErrorKind::Unsupported,
+                       "reading the state of network interfaces is not supported on this system"))
+}
+
+/// Parses the output of `ifconfig IFACE` on macOS and the BSDs: the interface is up if its
+/// flags have `UP`, and has a carrier unless its `status` is other than `active`, as interfaces
+/// without carrier have no status.
+#[cfg(any(target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly",
+          test))]
+fn parse_ifconfig_link(output: &str) -> Link {
+    let flags = output.lines()
+        .next()
+        .and_then(|line| line.split('<').nth(1))
+        .and_then(|flags| flags.split('>').next())
+        .unwrap_or_default();
+    let status = output.lines().find_map(|line| line.trim().strip_prefix("status:"));
+    Link {
+        up: flags.split(',').any(|flag| flag == "UP"),
+        carrier: status.is_none_or(|status| status.trim() == "active"),
+    }
+}
+
+/// Returns whether packets to `destination` sent through an interface connected to `networks`
+/// can arrive on one of them: if it is the limited broadcast, a multicast group, or an address
+/// or the directed broadcast of one of the networks. A destination beyond them would need a
+/// router, which forwards no broadcasts of another network.
+///
+/// #Example
+///
+/// ```
+/// use wol::subnet::{self, Cidr};
+///
+/// let wlan: Vec<Cidr> = vec!["192.168.1.0/24".parse().unwrap()];
+/// assert!(subnet::reaches(&wlan, "192.168.1.255".parse().unwrap()));
+/// assert!(!subnet::reaches(&wlan, "10.0.2.255".parse().unwrap()));
+/// ```
+pub fn reaches(networks: &[Cidr], destination: IpAddr) -> bool {
+    let special = match destination {
+        IpAddr::V4(ip) => ip.is_broadcast() || ip.is_multicast(),
+        IpAddr::V6(ip) => ip.is_multicast(),
+    };
+    special || networks.iter().any(|network| network.contains(destination))
+}
+
+/// Returns whether a route or address of `name` is one of `interface` or, without one, not of
+/// the loopback interface.
+#[cfg(any(target_os = "linux", test))]
+fn of_interface(name: &str, interface: Option<&str>) -> bool {
+    interface.map_or(name != "lo", |interface| name == interface)
+}
+
+/// Parses `/proc/net/dev`, which has two header lines and then a line per interface starting
+/// with its name and a colon, keeping all interfaces but the loopback interface.
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc_dev(table: &str) -> Vec<String> {
+    table.lines()
+        .skip(2)
+        .filter_map(|line| line.split(':').next().map(str::trim))
+        .filter(|name| !name.is_empty() && of_interface(name, None))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parses `/proc/net/route`, keeping the routes without gateway except the default route, of
+/// the interface if given.
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc_route(table: &str, interface: Option<&str>) -> Vec<Cidr> {
+    let hex = |s: &str| u32::from_str_radix(s, 16).ok();
+    table.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 || !of_interface(fields[0], interface) || hex(fields[2])? != 0 {
+                return None;
+            }
+            // The addresses are in the byte order of the system, little endian on most.
+            let addr = Ipv4Addr::from(u32::from_be(hex(fields[1])?.to_le()));
+            let mask = u32::from_be(hex(fields[7])?.to_le());
+            if mask == 0 {
+                return None;
+            }
+            Some(Cidr {
+                addr: IpAddr::V4(addr),
+                prefix_len: mask.count_ones() as u8,
+            })
+        })
+        .collect()
+}
+
+/// Parses `/proc/net/ipv6_route`, keeping the unicast networks without next hop, i.e. neither
+/// the routes to single addresses nor those of multicast and the loopback interface, of the
+/// interface if given.
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc_ipv6_route(table: &str, interface: Option<&str>) -> Vec<Cidr> {
+    table.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let next_hop = fields.get(4).map_or("", |hop| hop.trim_start_matches('0'));
+            if fields.len() < 10 || !of_interface(fields[9], interface) || !next_hop.is_empty() {
+                return None;
+            }
+            let addr = Ipv6Addr::from(u128::from_str_radix(fields[0], 16).ok()?);
+            let prefix_len = u8::from_str_radix(fields[1], 16).ok()?;
+            if prefix_len == 0 || prefix_len == 128 || addr.is_multicast() {
+                return None;
+            }
+            Some(Cidr {
+                addr: IpAddr::V6(addr),
+                prefix_len,
+            })
+        })
+        .collect()
+}
+
+/// Parses the output of `ifconfig -a`, with lines like `inet 192.168.1.5 netmask 0xffffff00`
+/// and `inet6 fe80::1%en0 prefixlen 64`, keeping the networks of the interface if given and
+/// else those of all but the loopback interfaces.
+#[cfg(any(target_os = "macos",
+          target_os = "freebsd",
+          target_os = "openbsd",
+          target_os = "netbsd",
+          target_os = "dragonfly",
+          test))]
+fn parse_ifconfig(output: &str, interface: Option<&str>) -> Vec<Cidr> {
+    let mut skipped = false;
+    output.lines()
+        .filter_map(|line| {
+            if !line.starts_with(char::is_whitespace) {
+                let name = line.split(':').next().unwrap_or_default();
+                skipped = interface.map_or(line.starts_with("lo"), |interface| name != interface);
+                return None;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if skipped || fields.len() < 4 {
+                return None;
+            }
+            let addr: IpAddr = fields[1].split('%').next()?.parse().ok()?;
+            let prefix_len = match (fields[0], fields[2]) {
+                ("inet", "netmask") => {
+                    u32::from_str_radix(fields[3].trim_start_matches("0x"), 16).ok()?.count_ones()
+                },
+                ("inet6", "prefixlen") => fields[3].parse().ok()?,
+                _ => return None,
+            };
+            Some(Cidr {
+                addr,
+                prefix_len: prefix_len as u8,
+            })
+        })
+        .collect()
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Cidr, String> {
+        let mut parts = s.splitn(2, '/');
+        let addr: IpAddr = parts.next()
+            .unwrap_or("")
+            .parse()
+            .map_err(|_| format!("invalid network address in '{}'", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = parts.next()
+            .and_then(|len| len.parse().ok())
+            .filter(|&len| len <= max)
+            .ok_or_else(|| format!("invalid prefix length in '{}'", s))?;
+        Ok(Cidr { addr, prefix_len })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{broadcast_for, parse_ifconfig, parse_ifconfig_link, parse_proc_dev,
+                parse_proc_ipv6_route, parse_proc_route, reaches, target_for, Addressing, Cidr};
+
+    #[test]
+    fn test_broadcast_for() {
+        let broadcast = |s: &str| s.parse::<Cidr>().unwrap().broadcast().map(|ip| ip.to_string());
+        assert_eq!(broadcast("10.1.4.0/22"), Some("10.1.7.255".to_string()));
+        assert_eq!(broadcast("192.168.1.77/24"), Some("192.168.1.255".to_string()));
+        assert_eq!(broadcast("10.0.0.1/32"), Some("10.0.0.1".to_string()));
+        assert_eq!(broadcast("10.0.0.1/0"), Some("255.255.255.255".to_string()));
+        assert_eq!(broadcast("fd00::/64"), None);
+        assert_eq!(broadcast_for("10.0.0.1".parse().unwrap(), 33), None);
+    }
+
+    #[test]
+    fn test_parse_cidr() {
+        assert!("10.1.4.0".parse::<Cidr>().is_err());
+        assert!("10.1.4.0/33".parse::<Cidr>().is_err());
+        assert!("10.1.4/22".parse::<Cidr>().is_err());
+        assert_eq!("fd00::/64".parse::<Cidr>().unwrap().to_string(), "fd00::/64");
+    }
+
+    #[test]
+    fn test_contains() {
+        let net: Cidr = "10.1.4.0/22".parse().unwrap();
+        assert!(net.contains("10.1.7.200".parse().unwrap()));
+        assert!(!net.contains("10.1.8.1".parse().unwrap()));
+        assert!(!net.contains("fd00::1".parse().unwrap()));
+        assert!("fd00::/64".parse::<Cidr>().unwrap().contains("fd00::1".parse().unwrap()));
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains("8.8.8.8".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_reaches() {
+        let networks = ["192.168.1.0/24".parse().unwrap(), "fe80::/64".parse().unwrap()];
+        let reaches = |ip: &str| reaches(&networks, ip.parse().unwrap());
+        assert!(reaches("192.168.1.255") && reaches("192.168.1.10") && reaches("fe80::1"));
+        assert!(reaches("255.255.255.255") && reaches("224.0.0.1") && reaches("ff02::1"));
+        assert!(!reaches("10.0.2.255") && !reaches("192.168.2.255") && !reaches("fd00::1"));
+        assert!(!super::reaches(&[], "192.168.1.255".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_target_for() {
+        let networks = ["192.168.1.0/24".parse().unwrap(), "fd00::/64".parse().unwrap()];
+        let target = |ip: &str, addressing| target_for(ip.parse().unwrap(), addressing, &networks);
+        assert_eq!(target("192.168.1.10", Addressing::Auto),
+                   Some("192.168.1.255".parse().unwrap()));
+        assert_eq!(target("fd00::10", Addressing::Auto), None);
+        assert_eq!(target("10.0.0.7", Addressing::Auto), Some("10.0.0.7".parse().unwrap()));
+        assert_eq!(target("10.0.0.7", Addressing::Broadcast), None);
+        assert_eq!(target("192.168.1.10", Addressing::Unicast),
+                   Some("192.168.1.10".parse().unwrap()));
+        assert_eq!("unicast".parse(), Ok(Addressing::Unicast));
+        assert!("anycast".parse::<Addressing>().is_err());
+    }
+
+    #[test]
+    fn test_parse_routes() {
+        let route = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\t\
+                     MTU\tWindow\tIRTT\n\
+                     eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n\
+                     eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n\
+                     eth1\t0000010A\t00000000\t0001\t0\t0\t0\t00FCFFFF\t0\t0\t0\n";
+        let networks: Vec<String> =
+            parse_proc_route(route, None).iter().map(|n| n.to_string()).collect();
+        assert_eq!(networks, ["192.168.1.0/24", "10.1.0.0/22"]);
+        let networks: Vec<String> =
+            parse_proc_route(route, Some("eth1")).iter().map(|n| n.to_string()).collect();
+        assert_eq!(networks, ["10.1.0.0/22"]);
+
+        let zero = "00000000000000000000000000000000";
+        let ipv6_route = format!("fd000000000000000000000000000000 40 {0} 00 {0} 00000100 \
+                                  00000001 00000000 00000001 eth0\n\
+                                  fe800000000000000000000000000000 40 {0} 00 {0} 00000100 \
+                                  00000001 00000000 00000001 eth0\n\
+                                  00000000000000000000000000000000 00 {0} 00 \
+                                  fe800000000000000000000000000001 00000400 00000001 \
+                                  00000000 00000003 eth0\n\
+                                  fd000000000000000000000000000005 80 {0} 00 {0} 00000000 \
+                                  00000002 00000000 80200001 eth0\n\
+                                  ff000000000000000000000000000000 08 {0} 00 {0} 00000100 \
+                                  00000001 00000000 00000001 eth0\n",
+                                 zero);
+        let networks: Vec<String> =
+            parse_proc_ipv6_route(&ipv6_route, None).iter().map(|n| n.to_string()).collect();
+        assert_eq!(networks, ["fd00::/64", "fe80::/64"]);
+        assert!(parse_proc_ipv6_route(&ipv6_route, Some("eth1")).is_empty());
+    }
+
+    #[test]
+    fn test_parse_proc_dev() {
+        let table = "Inter-|   Receive                            |  Transmit\n \
+                     face |bytes    packets errs drop fifo frame compressed multicast|bytes\n    \
+                     lo: 1024      16    0    0    0     0          0         0     1024\n  \
+                     eth0: 987654  1234    0    0    0     0          0        12   123456\n\
+                     wlan0:    0      0    0    0    0     0          0         0        0\n";
+        assert_eq!(parse_proc_dev(table), ["eth0", "wlan0"]);
+    }
+
+    #[test]
+    fn test_parse_ifconfig_link() {
+        let link = |output: &str| {
+            let link = parse_ifconfig_link(output);
+            (link.up, link.carrier)
+        };
+        assert_eq!(link("en0: flags=8863<UP,BROADCAST,SMART,RUNNING,SIMPLEX,MULTICAST> mtu 1500\n\
+                         \tstatus: active\n"),
+                   (true, true));
+        assert_eq!(link("em0: flags=8843<UP,BROADCAST,RUNNING,SIMPLEX,MULTICAST> metric 0\n\
+                         \tmedia: Ethernet autoselect\n\tstatus: no carrier\n"),
+                   (true, false));
+        assert_eq!(link("en1: flags=8822<BROADCAST,SMART,SIMPLEX,MULTICAST> mtu 1500\n\
+                         \tstatus: inactive\n"),
+                   (false, false));
+        assert_eq!(link("utun0: flags=8051<UP,POINTOPOINT,RUNNING,MULTICAST> mtu 1380\n"),
+                   (true, true));
+    }
+
+    #[test]
+    fn test_parse_ifconfig() {
+        let output = "lo0: flags=8049<UP,LOOPBACK,RUNNING,MULTICAST> mtu 16384\n\
+                      \tinet 127.0.0.1 netmask 0xff000000\n\
+                      en0: flags=8863<UP,BROADCAST,SMART,RUNNING,SIMPLEX,MULTICAST> mtu 1500\n\
+                      \tether 00:11:22:33:44:55\n\
+                      \tinet6 fe80::1%en0 prefixlen 64 secured scopeid 0x4\n\
+                      \tinet 192.168.1.5 netmask 0xffffff00 broadcast 192.168.1.255\n";
+        let networks: Vec<String> =
+            parse_ifconfig(output, None).iter().map(|n| n.to_string()).collect();
+        assert_eq!(networks, ["fe80::1/64", "192.168.1.5/24"]);
+        let networks: Vec<String> =
+            parse_ifconfig(output, Some("lo0")).iter().map(|n| n.to_string()).collect();
+        assert_eq!(networks, ["127.0.0.1/8"]);
+    }
+}
@@ -0,0 +1,460 @@
+//! Socket options and sockets which are not exposed by the standard library.
+//!
+//! The options are set with `setsockopt` from the C library the standard library links anyway,
+//! so no further dependency is needed. They are only available on Linux; elsewhere the functions
+//! fail with `io::ErrorKind::Other`. Raw sockets and the MAC addresses of interfaces
+//! additionally need the `raw` feature.
+#[cfg(target_os = "linux")]
+use std::ffi::CString;
+#[cfg(any(feature = "raw", target_os = "linux"))]
+use std::fs;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::Ipv4Addr;
+use std::net::UdpSocket;
+#[cfg(all(feature = "raw", target_os = "linux"))]
+use std::os::unix::io::FromRawFd;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "raw")]
+use MacAddr;
+
+/// The protocol of raw sockets receiving frames of all EtherTypes, `ETH_P_ALL`.
+#[cfg(all(feature = "raw", target_os = "linux"))]
+const ETHERTYPE_ALL: u16 = 0x0003;
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::io;
+    use std::os::raw::{c_char, c_int, c_uint, c_void};
+    use std::os::raw::c_ulong;
+    #[cfg(feature = "raw")]
+    use std::os::raw::c_long;
+    use std::os::unix::io::AsRawFd;
+    #[cfg(feature = "raw")]
+    use std::time::Duration;
+
+    pub const SOL_SOCKET: c_int = 1;
+    #[cfg(feature = "raw")]
+    pub const SO_RCVTIMEO: c_int = 20;
+    pub const SO_BINDTODEVICE: c_int = 25;
+    pub const SO_MARK: c_int = 36;
+    #[cfg(feature = "raw")]
+    pub const AF_PACKET: c_int = 17;
+    #[cfg(feature = "raw")]
+    pub const SOCK_RAW: c_int = 3;
+    #[cfg(feature = "raw")]
+    pub const SOL_PACKET: c_int = 263;
+    #[cfg(feature = "raw")]
+    pub const PACKET_ADD_MEMBERSHIP: c_int = 1;
+    #[cfg(feature = "raw")]
+    pub const PACKET_MR_PROMISC: u16 = 1;
+    pub const SIOCGIFADDR: c_ulong = 0x8915;
+    pub const IPPROTO_IP: c_int = 0;
+    pub const IP_TOS: c_int = 1;
+    pub const IPPROTO_IPV6: c_int = 41;
+    pub const IPV6_UNICAST_HOPS: c_int = 16;
+    pub const IPV6_MULTICAST_IF: c_int = 17;
+    pub const IPV6_MULTICAST_HOPS: c_int = 18;
+    pub const IPV6_TCLASS: c_int = 67;
+    pub const CLONE_NEWNET: c_int = 0x4000_0000;
+
+    extern "C" {
+        fn setsockopt(socket: c_int,
+                      level: c_int,
+                      name: c_int,
+                      value: *const c_void,
+                      len: u32)
+                      -> c_int;
+        pub fn if_nametoindex(name: *const c_char) -> c_uint;
+        #[cfg(feature = "raw")]
+        pub fn socket(domain: c_int, kind: c_int, protocol: c_int) -> c_int;
+        #[cfg(feature = "raw")]
+        pub fn bind(socket: c_int, address: *const c_void, len: u32) -> c_int;
+        pub fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+        pub fn setns(fd: c_int, nstype: c_int) -> c_int;
+    }
+
+    /// Returns a `struct timeval` as raw value for `set_option`.
+    #[cfg(feature = "raw")]
+    pub fn timeval(duration: Duration) -> Vec<u8> {
+        let mut value = (duration.as_secs() as c_long).to_ne_bytes().to_vec();
+        value.extend_from_slice(&(duration.subsec_micros() as c_long).to_ne_bytes());
+        value
+    }
+
+    /// Sets a socket option to the given raw value.
+    pub fn set_option<S: AsRawFd>(socket: &S,
+                                  level: c_int,
+                                  name: c_int,
+                                  value: &[u8])
+                                  -> io::Result<()> {
+        let ret = unsafe {
+            setsockopt(socket.as_raw_fd(),
+                       level,
+                       name,
+                       value.as_ptr() as *const c_void,
+                       value.len() as u32)
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+/// Returns the error for options not supported on this platform.
+#[cfg(not(target_os = "linux"))]
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("{} is only supported on Linux", what))
+}
+
+/// Returns the error for raw sockets, which are only supported on Linux with the `raw` feature.
+#[cfg(not(all(feature = "raw", target_os = "linux")))]
+fn raw_unsupported() -> io::Error {
+    io::Error::other("receiving raw Ethernet frames needs Linux and the raw feature")
+}
+
+/// Restricts the socket to send and receive through the given network interface, e.g. "eth0".
+#[cfg(target_os = "linux")]
+pub fn bind_to_device(socket: &UdpSocket, interface: &str) -> io::Result<()> {
+    imp::set_option(socket, imp::SOL_SOCKET, imp::SO_BINDTODEVICE, interface.as_bytes())
+}
+
+/// Restricts the socket to send and receive through the given network interface, e.g. "eth0".
+#[cfg(not(target_os = "linux"))]
+pub fn bind_to_device(_socket: &UdpSocket, _interface: &str) -> io::Result<()> {
+    Err(unsupported("binding to an interface"))
+}
+
+/// Sets the hop limit of unicast and multicast packets sent from an IPv6 socket, the IPv6
+/// counterpart of `UdpSocket::set_ttl`.
+#[cfg(target_os = "linux")]
+pub fn set_hop_limit_v6(socket: &UdpSocket, hops: u32) -> io::Result<()> {
+    let value = (hops as i32).to_ne_bytes();
+    imp::set_option(socket, imp::IPPROTO_IPV6, imp::IPV6_UNICAST_HOPS, &value)?;
+    imp::set_option(socket, imp::IPPROTO_IPV6, imp::IPV6_MULTICAST_HOPS, &value)
+}
+
+/// Sets the hop limit of unicast and multicast packets sent from an IPv6 socket, the IPv6
+/// counterpart of `UdpSocket::set_ttl`.
+#[cfg(not(target_os = "linux"))]
+pub fn set_hop_limit_v6(_socket: &UdpSocket, _hops: u32) -> io::Result<()> {
+    Err(unsupported("setting the IPv6 hop limit"))
+}
+
+/// Sets the mark of the packets sent from the socket, which policy routing and firewall rules
+/// can match, e.g. `ip rule add fwmark 7 table 100`. Needs the `CAP_NET_ADMIN` capability.
+#[cfg(target_os = "linux")]
+pub fn set_mark(socket: &UdpSocket, mark: u32) -> io::Result<()> {
+    imp::set_option(socket, imp::SOL_SOCKET, imp::SO_MARK, &mark.to_ne_bytes())
+}
+
+/// Sets the mark of the packets sent from the socket, which policy routing and firewall rules
+/// can match, e.g. `ip rule add fwmark 7 table 100`. Needs the `CAP_NET_ADMIN` capability.
+#[cfg(not(target_os = "linux"))]
+pub fn set_mark(_socket: &UdpSocket, _mark: u32) -> io::Result<()> {
+    Err(unsupported("marking packets"))
+}
+
+/// Sets the type of service byte of the IPv4 header, or the traffic class of the IPv6 header,
+/// of the packets sent from the socket, e.g. to a DSCP shifted by 2.
+#[cfg(target_os = "linux")]
+pub fn set_traffic_class(socket: &UdpSocket, ipv6: bool, class: u8) -> io::Result<()> {
+    let value = i32::from(class).to_ne_bytes();
+    if ipv6 {
+        imp::set_option(socket, imp::IPPROTO_IPV6, imp::IPV6_TCLASS, &value)
+    } else {
+        imp::set_option(socket, imp::IPPROTO_IP, imp::IP_TOS, &value)
+    }
+}
+
+/// Sets the type of service byte of the IPv4 header, or the traffic class of the IPv6 header,
+/// of the packets sent from the socket, e.g. to a DSCP shifted by 2.
+#[cfg(not(target_os = "linux"))]
+pub fn set_traffic_class(_socket: &UdpSocket, _ipv6: bool, _class: u8) -> io::Result<()> {
+    Err(unsupported("setting the DSCP"))
+}
+
+/// Calls `f` in the network namespace of the file `netns`, e.g. `/run/netns/blue` or
+/// `/proc/1234/ns/net`, and returns its result. The sockets `f` opens stay in the namespace.
+///
+/// `f` runs on a thread of its own, which enters the namespace, so that the other threads stay
+/// in theirs. Entering a namespace needs the `CAP_SYS_ADMIN` capability.
+#[cfg(target_os = "linux")]
+pub fn in_netns<T, F>(netns: &Path, f: F) -> io::Result<T>
+    where T: Send,
+          F: FnOnce() -> io::Result<T> + Send
+{
+    let file = File::open(netns).map_err(|err| {
+        io::Error::new(err.kind(),
+                       format!("cannot open the network namespace {}: {}", netns.display(), err))
+    })?;
+    thread::scope(|scope| {
+        scope.spawn(|| {
+                if unsafe { imp::setns(file.as_raw_fd(), imp::CLONE_NEWNET) } != 0 {
+                    let err = io::Error::last_os_error();
+                    return Err(io::Error::new(err.kind(),
+                                              format!("cannot enter the network namespace {}: \
+                                                       {}",
+                                                      netns.display(),
+                                                      err)));
+                }
+                f()
+            })
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("the network namespace thread panicked")))
+    })
+}
+
+/// Calls `f` in the network namespace of the file `netns`, e.g. `/run/netns/blue` or
+/// `/proc/1234/ns/net`, and returns its result. The sockets `f` opens stay in the namespace.
+#[cfg(not(target_os = "linux"))]
+pub fn in_netns<T, F>(_netns: &Path, _f: F) -> io::Result<T>
+    where T: Send,
+          F: FnOnce() -> io::Result<T> + Send
+{
+    Err(unsupported("sending from a network namespace"))
+}
+
+/// Returns the index of the network interface with the given name, e.g. "eth0", which is also
+/// the zone index of its link-local IPv6 addresses.
+#[cfg(target_os = "linux")]
+pub fn interface_index(interface: &str) -> io::Result<u32> {
+    let name = CString::new(interface)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "interface name contains NUL"))?;
+    match unsafe { imp::if_nametoindex(name.as_ptr()) } {
+        0 => Err(io::Error::last_os_error()),
+        index => Ok(index),
+    }
+}
+
+/// Returns the index of the network interface with the given name, e.g. "eth0", which is also
+/// the zone index of its link-local IPv6 addresses.
+#[cfg(not(target_os = "linux"))]
+pub fn interface_index(_interface: &str) -> io::Result<u32> {
+    Err(unsupported("looking up interfaces"))
+}
+
+/// Sends IPv6 multicast packets from the socket through the interface with the given index.
+#[cfg(target_os = "linux")]
+pub fn set_multicast_if_v6(socket: &UdpSocket, index: u32) -> io::Result<()> {
+    let value = (index as i32).to_ne_bytes();
+    imp::set_option(socket, imp::IPPROTO_IPV6, imp::IPV6_MULTICAST_IF, &value)
+}
+
+/// Sends IPv6 multicast packets from the socket through the interface with the given index.
+#[cfg(not(target_os = "linux"))]
+pub fn set_multicast_if_v6(_socket: &UdpSocket, _index: u32) -> io::Result<()> {
+    Err(unsupported("choosing the IPv6 multicast interface"))
+}
+
+/// Returns the names of the network interfaces with an IPv6 address, without the loopback
+/// interface, e.g. to send to a link-local multicast group through each.
+#[cfg(target_os = "linux")]
+pub fn ipv6_interfaces() -> io::Result<Vec<String>> {
+    let mut interfaces: Vec<String> = Vec::new();
+    // Lines of the address, index, prefix length, scope, flags and name of the interface.
+    for line in fs::read_to_string("/proc/net/if_inet6")?.lines() {
+        match line.split_whitespace().nth(5) {
+            Some(name) if name != "lo" && !interfaces.iter().any(|known| known == name) => {
+                interfaces.push(name.to_string())
+            },
+            _ => {},
+        }
+    }
+    Ok(interfaces)
+}
+
+/// Returns the names of the network interfaces with an IPv6 address, without the loopback
+/// interface, e.g. to send to a link-local multicast group through each.
+#[cfg(not(target_os = "linux"))]
+pub fn ipv6_interfaces() -> io::Result<Vec<String>> {
+    Err(unsupported("listing the IPv6 interfaces"))
+}
+
+/// Returns the IPv4 address of the network interface with the given name, e.g. "eth0".
+#[cfg(target_os = "linux")]
+pub fn interface_ipv4(interface: &str) -> io::Result<Ipv4Addr> {
+    use std::os::unix::io::AsRawFd;
+
+    if interface.len() >= 16 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "interface name is too long"));
+    }
+    // struct ifreq: the name, followed by the address as struct sockaddr_in.
+    let mut request = [0u8; 40];
+    request[..interface.len()].copy_from_slice(interface.as_bytes());
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    if unsafe { imp::ioctl(socket.as_raw_fd(), imp::SIOCGIFADDR, request.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Ipv4Addr::new(request[20], request[21], request[22], request[23]))
+}
+
+/// Returns the IPv4 address of the network interface with the given name, e.g. "eth0".
+#[cfg(not(target_os = "linux"))]
+pub fn interface_ipv4(_interface: &str) -> io::Result<Ipv4Addr> {
+    Err(unsupported("looking up interfaces"))
+}
+
+/// Returns the MAC address of the network interface with the given name, e.g. "eth0".
+#[cfg(feature = "raw")]
+pub fn interface_mac(interface: &str) -> io::Result<MacAddr> {
+    let path = format!("/sys/class/net/{}/address", interface);
+    fs::read_to_string(&path)?
+        .trim()
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, err)))
+}
+
+/// A raw socket receiving whole Ethernet frames of one EtherType, e.g. 0x0842 for magic packets
+/// sent without IP, and sending frames if bound to an interface. Opening one needs the
+/// `CAP_NET_RAW` capability.
+#[derive(Debug)]
+pub struct RawSocket {
+    file: File,
+}
+
+impl RawSocket {
+    /// Opens a socket receiving the frames with the given EtherType from all interfaces or only
+    /// from the given one.
+    #[cfg(all(feature = "raw", target_os = "linux"))]
+    pub fn open(ethertype: u16, interface: Option<&str>) -> io::Result<RawSocket> {
+        let protocol = i32::from(ethertype.to_be());
+        let fd = unsafe { imp::socket(imp::AF_PACKET, imp::SOCK_RAW, protocol) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = RawSocket { file: unsafe { File::from_raw_fd(fd) } };
+        if let Some(interface) = interface {
+            // struct sockaddr_ll with family, protocol and interface index, the rest zeroed.
+            let mut address = [0u8; 20];
+            address[..2].copy_from_slice(&(imp::AF_PACKET as u16).to_ne_bytes());
+            address[2..4].copy_from_slice(&ethertype.to_be_bytes());
+            address[4..8].copy_from_slice(&(interface_index(interface)? as i32).to_ne_bytes());
+            let ret = unsafe {
+                imp::bind(fd, address.as_ptr() as *const _, address.len() as u32)
+            };
+            if ret != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(socket)
+    }
+
+    /// Opens a socket receiving the frames with the given EtherType from all interfaces or only
+    /// from the given one.
+    #[cfg(not(all(feature = "raw", target_os = "linux")))]
+    pub fn open(_ethertype: u16, _interface: Option<&str>) -> io::Result<RawSocket> {
+        Err(raw_unsupported())
+    }
+
+    /// Opens a socket receiving the frames of all EtherTypes from `interface` in promiscuous
+    /// mode, i.e. also those addressed to other machines, e.g. on the mirror port of a switch.
+    /// The interface leaves promiscuous mode once the socket is closed.
+    #[cfg(all(feature = "raw", target_os = "linux"))]
+    pub fn open_promiscuous(interface: &str) -> io::Result<RawSocket> {
+        let socket = RawSocket::open(ETHERTYPE_ALL, Some(interface))?;
+        // struct packet_mreq with interface index and type, without address.
+        let mut request = [0u8; 16];
+        request[..4].copy_from_slice(&(interface_index(interface)? as i32).to_ne_bytes());
+        request[4..6].copy_from_slice(&imp::PACKET_MR_PROMISC.to_ne_bytes());
+        imp::set_option(&socket.file, imp::SOL_PACKET, imp::PACKET_ADD_MEMBERSHIP, &request)?;
+        Ok(socket)
+    }
+
+    /// Opens a socket receiving the frames of all EtherTypes from `interface` in promiscuous
+    /// mode, i.e. also those addressed to other machines, e.g. on the mirror port of a switch.
+    /// The interface leaves promiscuous mode once the socket is closed.
+    #[cfg(not(all(feature = "raw", target_os = "linux")))]
+    pub fn open_promiscuous(_interface: &str) -> io::Result<RawSocket> {
+        Err(raw_unsupported())
+    }
+
+    /// Makes `recv` fail with `io::ErrorKind::WouldBlock` if no frame arrived within `timeout`.
+    #[cfg(all(feature = "raw", target_os = "linux"))]
+    pub fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        imp::set_option(&self.file, imp::SOL_SOCKET, imp::SO_RCVTIMEO, &imp::timeval(timeout))
+    }
+
+    /// Makes `recv` fail with `io::ErrorKind::WouldBlock` if no frame arrived within `timeout`.
+    #[cfg(not(all(feature = "raw", target_os = "linux")))]
+    pub fn set_read_timeout(&self, _timeout: Duration) -> io::Result<()> {
+        Err(raw_unsupported())
+    }
+
+    /// Receives the next frame, including its Ethernet header.
+    pub fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+
+    /// Sends a frame, including its Ethernet header, through the interface the socket was
+    /// opened for.
+    pub fn send(&mut self, frame: &[u8]) -> io::Result<usize> {
+        self.file.write(frame)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::io;
+    use std::net::UdpSocket;
+    use std::path::Path;
+
+    use super::{bind_to_device, in_netns, interface_index, ipv6_interfaces, set_hop_limit_v6,
+                set_multicast_if_v6, set_traffic_class};
+
+    #[test]
+    fn test_bind_to_unknown_device() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert!(bind_to_device(&socket, "no-such-interface0").is_err());
+    }
+
+    #[test]
+    fn test_set_hop_limit_v6() {
+        let socket = UdpSocket::bind("[::1]:0").unwrap();
+        assert!(set_hop_limit_v6(&socket, 4).is_ok());
+        assert!(set_hop_limit_v6(&socket, 256).is_err());
+    }
+
+    #[test]
+    fn test_set_traffic_class() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert!(set_traffic_class(&socket, false, 46 << 2).is_ok());
+        let socket = UdpSocket::bind("[::1]:0").unwrap();
+        assert!(set_traffic_class(&socket, true, 46 << 2).is_ok());
+    }
+
+    #[test]
+    fn test_in_netns() {
+        let err = in_netns(Path::new("/run/netns/no-such-netns"), || Ok(())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        // Entering the own namespace again only needs the privileges.
+        match in_netns(Path::new("/proc/self/ns/net"), || UdpSocket::bind("127.0.0.1:0")) {
+            Ok(socket) => assert!(socket.local_addr().is_ok()),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::PermissionDenied),
+        }
+    }
+
+    #[test]
+    fn test_multicast_if_v6() {
+        assert!(interface_index("no-such-interface0").is_err());
+        let index = interface_index("lo").unwrap();
+        let socket = UdpSocket::bind("[::1]:0").unwrap();
+        assert!(set_multicast_if_v6(&socket, index).is_ok());
+    }
+
+    #[test]
+    fn test_ipv6_interfaces() {
+        // Systems without IPv6 have no list of the addresses.
+        if let Ok(interfaces) = ipv6_interfaces() {
+            assert!(!interfaces.iter().any(|interface| interface == "lo"));
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Log destinations for the system log on Unix.
+//!
+//! `Syslog` writes to the local syslog socket `/dev/log`, appending structured fields as
+//! `key=value` pairs. `Journald` speaks the native protocol of the systemd journal, so that
+//! fields like the MAC address end up as separate journal fields (`MAC=...`), which can be
+//! queried with `journalctl MAC=AA:BB:CC:DD:EE:FF`.
+use std::fmt;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::process;
+
+use log::{Field, Level, Log};
+
+/// The syslog facility used for all messages, `daemon`.
+const FACILITY_DAEMON: u8 = 3;
+
+/// The default path of the syslog socket.
+pub const SYSLOG_SOCKET: &str = "/dev/log";
+
+/// The default path of the native journald socket.
+pub const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Returns the syslog severity of a level.
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Writes log messages to the local syslog daemon.
+pub struct Syslog {
+    socket: UnixDatagram,
+    identifier: String,
+}
+
+impl Syslog {
+    /// Connects to the syslog socket at `/dev/log`.
+    pub fn new(identifier: &str) -> io::Result<Syslog> {
+        Syslog::with_path(identifier, SYSLOG_SOCKET)
+    }
+
+    /// Connects to the syslog socket at the given path.
+    pub fn with_path<P: AsRef<Path>>(identifier: &str, path: P) -> io::Result<Syslog> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Syslog {
+            socket,
+            identifier: identifier.to_string(),
+        })
+    }
+
+    /// Formats a message in the traditional BSD syslog format expected by `/dev/log`.
+    fn format(&self, level: Level, message: &fmt::Arguments) -> String {
+        format!("<{}>{}[{}]: {}",
+                FACILITY_DAEMON * 8 + severity(level),
+                self.identifier,
+                process::id(),
+                message)
+    }
+}
+
+impl Log for Syslog {
+    fn log(&self, level: Level, _target: &str, message: &fmt::Arguments) {
+        let _ = self.socket.send(self.format(level, message).as_bytes());
+    }
+}
+
+/// Writes log messages to the systemd journal using its native protocol.
+pub struct Journald {
+    socket: UnixDatagram,
+    identifier: String,
+}
+
+impl Journald {
+    /// Connects to the journal socket at `/run/systemd/journal/socket`.
+    pub fn new(identifier: &str) -> io::Result<Journald> {
+        Journald::with_path(identifier, JOURNALD_SOCKET)
+    }
+
+    /// Connects to the journal socket at the given path.
+    pub fn with_path<P: AsRef<Path>>(identifier: &str, path: P) -> io::Result<Journald> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Journald {
+            socket,
+            identifier: identifier.to_string(),
+        })
+    }
+
+    /// Formats a journal entry. Field names are uppercased; line breaks in values are replaced,
+    /// since they would need the binary variant of the protocol.
+    fn format(&self, level: Level, target: &str, message: &fmt::Arguments, fields: &[Field])
+              -> String {
+        let mut entry = String::new();
+        let mut add = |key: &str, value: &dyn fmt::Display| {
+            let value = value.to_string().replace('\n', " ");
+            entry.push_str(&format!("{}={}\n", key, value));
+        };
+        add("MESSAGE", message);
+        add("PRIORITY", &severity(level));
+        add("SYSLOG_IDENTIFIER", &self.identifier);
+        add("SYSLOG_FACILITY", &FACILITY_DAEMON);
+        add("CODE_MODULE", &target);
+        for &(key, value) in fields {
+            let key: String = key.chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+                .collect();
+            add(key.trim_start_matches('_'), value);
+        }
+        entry
+    }
+}
+
+impl Log for Journald {
+    fn log(&self, level: Level, target: &str, message: &fmt::Arguments) {
+        self.log_fields(level, target, message, &[]);
+    }
+
+    fn log_fields(&self, level: Level, target: &str, message: &fmt::Arguments, fields: &[Field]) {
+        let _ = self.socket.send(self.format(level, target, message, fields).as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixDatagram;
+
+    use log::{Level, Log};
+    use super::{Journald, Syslog};
+
+    #[test]
+    fn test_syslog_format() {
+        let (_receiver, sender) = UnixDatagram::pair().unwrap();
+        let syslog = Syslog {
+            socket: sender,
+            identifier: "wol".to_string(),
+        };
+        let line = syslog.format(Level::Warn, &format_args!("hello"));
+        assert!(line.starts_with("<28>wol["));
+        assert!(line.ends_with("]: hello"));
+    }
+
+    #[test]
+    fn test_journald_fields() {
+        let (receiver, sender) = UnixDatagram::pair().unwrap();
+        let journald = Journald {
+            socket: sender,
+            identifier: "wol".to_string(),
+        };
+        journald.log_fields(Level::Info,
+                            "wol",
+                            &format_args!("magic packet sent"),
+                            &[("mac", &"00:01:02:03:04:05"), ("client-ip", &"::1")]);
+        let mut buf = [0; 512];
+        let n = receiver.recv(&mut buf).unwrap();
+        let entry = String::from_utf8_lossy(&buf[..n]);
+        assert!(entry.starts_with("MESSAGE=magic packet sent\nPRIORITY=6\n"));
+        assert!(entry.contains("\nMAC=00:01:02:03:04:05\n"));
+        assert!(entry.contains("\nCLIENT_IP=::1\n"));
+    }
+}
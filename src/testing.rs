@@ -0,0 +1,240 @@
+//! Helpers for the tests of applications using wol, which check what they send without a real
+//! network.
+//!
+//! A `Capture` receives the magic packets sent to a UDP socket on the loopback interface, so
+//! that a test can hand its client to the code under test and assert that a packet for a MAC
+//! address was sent. A `FakeHost` accepts TCP connections on the loopback interface like a host
+//! which came up, so that verification succeeds. Both only use the loopback interface and need
+//! no privileges; they are behind the `testing` feature, e.g. for dev-dependencies:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! wol = { version = "0.1", features = ["testing"] }
+//! ```
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use hosts::Host;
+use listen::{Listener, Received};
+use {MacAddr, MagicPacket, WolClient};
+
+/// How long `Capture::assert_sent` waits for a packet.
+pub const DEFAULT_WAIT: Duration = Duration::from_secs(2);
+
+/// The packets received by a `Capture`, with the condition signalled for each.
+type Packets = (Mutex<Vec<Received>>, Condvar);
+
+/// Receives the magic packets sent to a UDP socket on the loopback interface in the background,
+/// until dropped.
+///
+/// #Example
+///
+/// ```
+/// use wol::testing::Capture;
+/// use wol::{MacAddr, MagicPacket};
+///
+/// let capture = Capture::start().unwrap();
+/// let mac = MacAddr::new([0, 0x11, 0x22, 0x33, 0x44, 0x55]);
+/// // The code under test sends with the client of the capture.
+/// capture.client().with_repeat(2).send(&MagicPacket::new(mac)).unwrap();
+/// capture.assert_sent(mac);
+/// assert_eq!(capture.wait_for_count(2, std::time::Duration::from_secs(1)), 2);
+/// assert_eq!(capture.packets(), [MagicPacket::new(mac), MagicPacket::new(mac)]);
+/// ```
+#[derive(Debug)]
+pub struct Capture {
+    address: SocketAddr,
+    packets: Arc<Packets>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Capture {
+    /// Binds a UDP socket to a free port of 127.0.0.1 and starts receiving.
+    pub fn start() -> io::Result<Capture> {
+        let listener = Listener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let address = listener.local_addr()?;
+        let packets = Arc::new((Mutex::new(Vec::new()), Condvar::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (packets, stop) = (packets.clone(), stop.clone());
+            thread::spawn(move || {
+                listener.run(&stop, |received| {
+                    packets.0.lock().unwrap().push(received);
+                    packets.1.notify_all();
+                })
+            })
+        };
+        Ok(Capture {
+            address,
+            packets,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the address packets are captured at.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Returns a client sending to the capture, which can be configured further, e.g. with
+    /// `WolClient::with_repeat`; the destination must stay that of the capture.
+    pub fn client(&self) -> WolClient {
+        WolClient::new(self.address)
+    }
+
+    /// Returns the packets received so far, in order and with repetitions.
+    pub fn received(&self) -> Vec<Received> {
+        self.packets.0.lock().unwrap().clone()
+    }
+
+    /// Returns the magic packets received so far, in order and with repetitions.
+    pub fn packets(&self) -> Vec<MagicPacket> {
+        self.packets.0.lock().unwrap().iter().map(|received| received.packet).collect()
+    }
+
+    /// Forgets the packets received so far.
+    pub fn clear(&self) {
+        self.packets.0.lock().unwrap().clear();
+    }
+
+    /// Waits until a packet for `mac` was received or `timeout` has passed, and returns the
+    /// first one.
+    pub fn wait_for(&self, mac: MacAddr, timeout: Duration) -> Option<Received> {
+        let find = |packets: &[Received]| {
+            packets.iter().find(|received| received.packet.mac() == mac).cloned()
+        };
+        self.wait(timeout, find)
+    }
+
+    /// Waits until `count` packets were received or `timeout` has passed, and returns the
+    /// number received.
+    pub fn wait_for_count(&self, count: usize, timeout: Duration) -> usize {
+        self.wait(timeout, |packets| Some(packets.len()).filter(|&len| len >= count))
+            .unwrap_or_else(|| self.packets.0.lock().unwrap().len())
+    }
+
+    /// Panics unless a packet for `mac` is received within `DEFAULT_WAIT`.
+    pub fn assert_sent(&self, mac: MacAddr) {
+        if self.wait_for(mac, DEFAULT_WAIT).is_none() {
+            let macs: Vec<String> =
+                self.packets().iter().map(|packet| packet.mac().to_string()).collect();
+            panic!("no magic packet for {} was sent, only for [{}]", mac, macs.join(", "));
+        }
+    }
+
+    /// Panics if a packet for `mac` is received within `wait`.
+    pub fn assert_not_sent(&self, mac: MacAddr, wait: Duration) {
+        if let Some(received) = self.wait_for(mac, wait) {
+            panic!("a magic packet for {} was sent from {}", mac, received.source);
+        }
+    }
+
+    /// Waits until `found` finds something in the packets received or `timeout` has passed.
+    fn wait<T, F>(&self, timeout: Duration, found: F) -> Option<T>
+        where F: Fn(&[Received]) -> Option<T>
+    {
+        let deadline = Instant::now() + timeout;
+        let mut packets = self.packets.0.lock().unwrap();
+        loop {
+            if let Some(found) = found(&packets) {
+                return Some(found);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return None;
+            }
+            packets = self.packets.1.wait_timeout(packets, remaining).unwrap().0;
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Accepts TCP connections on a free port of 127.0.0.1 like a host which is up, until dropped.
+///
+/// #Example
+///
+/// ```
+/// use std::time::Duration;
+/// use wol::batch::Batch;
+/// use wol::testing::{Capture, FakeHost};
+/// use wol::MacAddr;
+///
+/// let capture = Capture::start().unwrap();
+/// let nas = FakeHost::start().unwrap();
+/// let results = Batch::new(capture.client())
+///     .with_verify(&[22], Duration::from_secs(1))
+///     .run(&[nas.host("nas", MacAddr::new([0, 0x11, 0x22, 0x33, 0x44, 0x55]))]);
+/// assert_eq!(results["nas"].verified, Some(true));
+/// ```
+#[derive(Debug)]
+pub struct FakeHost {
+    listener: TcpListener,
+}
+
+impl FakeHost {
+    /// Binds a TCP listener to a free port of 127.0.0.1. Connections are accepted by the
+    /// operating system, so that probes succeed without a thread accepting them.
+    pub fn start() -> io::Result<FakeHost> {
+        Ok(FakeHost { listener: TcpListener::bind((Ipv4Addr::LOCALHOST, 0))? })
+    }
+
+    /// Returns the port the fake host accepts connections on.
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map(|address| address.port()).unwrap_or_default()
+    }
+
+    /// Returns a host with the name and MAC address whose verification probes the fake host.
+    pub fn host(&self, name: &str, mac: MacAddr) -> Host {
+        Host {
+            ip: Some(Ipv4Addr::LOCALHOST.into()),
+            verify_ports: vec![self.port()],
+            ..Host::new(name, mac)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use MacAddr;
+    use MagicPacket;
+
+    use super::Capture;
+
+    #[test]
+    fn test_capture() {
+        let capture = Capture::start().unwrap();
+        let (pc, nas) = (MacAddr::new([0, 1, 2, 3, 4, 5]), MacAddr::new([0, 1, 2, 3, 4, 6]));
+        capture.client().send(&MagicPacket::new(pc).with_password([1; 6])).unwrap();
+        let received = capture.wait_for(pc, Duration::from_secs(1)).unwrap();
+        assert_eq!(received.packet.password(), Some([1; 6]));
+        assert!(capture.wait_for(nas, Duration::from_millis(10)).is_none());
+        capture.assert_not_sent(nas, Duration::from_millis(10));
+        capture.clear();
+        assert!(capture.packets().is_empty());
+        assert_eq!(capture.wait_for_count(1, Duration::from_millis(10)), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "no magic packet for 00:01:02:03:04:06 was sent, only for")]
+    fn test_assert_sent() {
+        let capture = Capture::start().unwrap();
+        capture.client().send(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]))).unwrap();
+        capture.assert_sent(MacAddr::new([0, 1, 2, 3, 4, 6]));
+    }
+}
@@ -0,0 +1,160 @@
+//! The API tokens of the daemon, each limited to some hosts and to what it may do, so that e.g.
+//! a guest can wake the media PC but not the whole office.
+//!
+//! The tokens are configured in the `[tokens]` table of the configuration, each under a name
+//! which log messages use instead of the token:
+//!
+//! ```toml
+//! [tokens]
+//! guest = { token = "g4est", hosts = ["media-pc"] }
+//! ops = { token = "0ps", scope = "admin" }
+//! ```
+//!
+//! A token without hosts may wake all hosts, else those named and those with the given MAC
+//! addresses. The scope `wake`, the default, only allows wake requests, `admin` also reloading
+//! the daemon and sharing its hosts file. The `[relay] token` is an admin token for all hosts.
+use std::fmt;
+use std::str::FromStr;
+
+use hosts::Hosts;
+use redact::Secret;
+use MacAddr;
+
+/// What a token may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Wake its hosts.
+    Wake,
+    /// Wake its hosts and administer the daemon, i.e. reload it and share its hosts file.
+    Admin,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Scope::Wake => "wake",
+            Scope::Admin => "admin",
+        })
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Scope, String> {
+        match s {
+            "wake" => Ok(Scope::Wake),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(format!("unknown scope '{}', expected wake or admin", s)),
+        }
+    }
+}
+
+/// A named bearer token of the daemon.
+///
+/// #Example
+///
+/// ```
+/// use wol::hosts::Hosts;
+/// use wol::tokens::ApiToken;
+///
+/// let hosts: Hosts = "[media-pc]\nmac = \"00:11:22:33:44:55\"".parse().unwrap();
+/// let guest = ApiToken::new("guest", "g4est").with_hosts(vec!["media-pc".to_string()]);
+/// assert!(guest.matches("g4est"));
+/// assert!(guest.may_wake(&hosts, "00:11:22:33:44:55".parse().unwrap()));
+/// assert!(!guest.may_wake(&hosts, "00:11:22:33:44:56".parse().unwrap()));
+/// assert!(!guest.is_admin());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiToken {
+    /// The name of the token, e.g. its user, which is no secret.
+    pub name: String,
+    pub token: Secret<String>,
+    /// The names or MAC addresses of the hosts the token may wake, empty for all hosts.
+    pub hosts: Vec<String>,
+    pub scope: Scope,
+}
+
+impl ApiToken {
+    /// Creates a token which may wake all hosts but not administer the daemon.
+    pub fn new(name: &str, token: &str) -> ApiToken {
+        ApiToken {
+            name: name.to_string(),
+            token: Secret(token.to_string()),
+            hosts: Vec::new(),
+            scope: Scope::Wake,
+        }
+    }
+
+    /// Only allows waking the given hosts, by name or MAC address.
+    pub fn with_hosts(mut self, hosts: Vec<String>) -> ApiToken {
+        self.hosts = hosts;
+        self
+    }
+
+    /// Allows what the scope allows.
+    pub fn with_scope(mut self, scope: Scope) -> ApiToken {
+        self.scope = scope;
+        self
+    }
+
+    /// Returns whether `token` is this token.
+    pub fn matches(&self, token: &str) -> bool {
+        let own = &self.token.0;
+        // Compares every byte, so that the time taken does not tell how much of the token matched.
+        token.len() == own.len() &&
+        token.bytes().zip(own.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+
+    /// Returns whether the token may wake the host with the MAC address: if it has no hosts, or
+    /// one of them is the MAC address or the name of a host of `hosts` with it.
+    pub fn may_wake(&self, hosts: &Hosts, mac: MacAddr) -> bool {
+        self.hosts.is_empty() ||
+        self.hosts.iter().any(|host| {
+            host.parse::<MacAddr>().ok().or_else(|| hosts.get(host).map(|host| host.mac)) ==
+            Some(mac)
+        })
+    }
+
+    /// Returns whether the token may administer the daemon.
+    pub fn is_admin(&self) -> bool {
+        self.scope == Scope::Admin
+    }
+}
+
+/// Returns the token of `tokens` which `token` is, if any.
+pub fn find<'a>(tokens: &'a [ApiToken], token: &str) -> Option<&'a ApiToken> {
+    tokens.iter().find(|candidate| candidate.matches(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use hosts::Hosts;
+
+    use super::{find, ApiToken, Scope};
+
+    #[test]
+    fn test_tokens() {
+        let hosts: Hosts = "[media-pc]\nmac = \"00:11:22:33:44:55\"\n\
+                            [nas]\nmac = \"00:11:22:33:44:56\""
+            .parse()
+            .unwrap();
+        let tokens = [ApiToken::new("guest", "g4est")
+                          .with_hosts(vec!["media-pc".to_string(),
+                                           "00:11:22:33:44:57".to_string()]),
+                      ApiToken::new("ops", "0ps").with_scope(Scope::Admin)];
+        let guest = find(&tokens, "g4est").unwrap();
+        assert_eq!(guest.name, "guest");
+        assert!(guest.may_wake(&hosts, "00:11:22:33:44:55".parse().unwrap()));
+        assert!(guest.may_wake(&hosts, "00:11:22:33:44:57".parse().unwrap()));
+        assert!(!guest.may_wake(&hosts, "00:11:22:33:44:56".parse().unwrap()));
+        let ops = find(&tokens, "0ps").unwrap();
+        assert!(ops.is_admin() && ops.may_wake(&hosts, "00:11:22:33:44:56".parse().unwrap()));
+        assert_eq!(find(&tokens, "0p"), None);
+        assert_eq!(find(&tokens, "g4esT"), None);
+        assert_eq!(format!("{:?}", ops.token), "••••");
+        assert_eq!("admin".parse::<Scope>(), Ok(Scope::Admin));
+        assert_eq!("root".parse::<Scope>(),
+                   Err("unknown scope 'root', expected wake or admin".to_string()));
+    }
+}
@@ -0,0 +1,375 @@
+//! A parser for the subset of TOML used by the configuration and hosts files: tables, comments
+//! and keys with string, integer or boolean values or single-line arrays or inline tables of
+//! them.
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::time::Duration;
+
+use config::ConfigError;
+use secrets::SecretRef;
+use units;
+
+/// A value of the TOML subset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+    /// An inline table like `{ type = "command", cmd = "true" }`, its keys in order.
+    Table(Vec<(String, Value)>),
+}
+
+/// A `key = value` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The line of the entry, starting at 1.
+    pub line: usize,
+    /// The table the entry belongs to, empty before the first table.
+    pub table: String,
+    pub key: String,
+    pub value: Value,
+}
+
+impl Entry {
+    /// Returns the key qualified by its table, e.g. `verify.port`.
+    pub fn name(&self) -> String {
+        if self.table.is_empty() {
+            self.key.clone()
+        } else {
+            format!("{}.{}", self.table, self.key)
+        }
+    }
+
+    /// Returns an error at the line of the entry.
+    pub fn error(&self, message: &str) -> ConfigError {
+        ConfigError::new(self.line, message)
+    }
+
+    /// Returns the error for a key which is not expected.
+    pub fn unknown(&self) -> ConfigError {
+        self.error(&format!("unknown key '{}'", self.name()))
+    }
+
+    /// Returns the value as string.
+    pub fn as_str(&self) -> Result<&str, ConfigError> {
+        match self.value {
+            Value::Str(ref s) => Ok(s),
+            _ => Err(self.error("expected a string")),
+        }
+    }
+
+    /// Returns the value as boolean.
+    pub fn as_bool(&self) -> Result<bool, ConfigError> {
+        match self.value {
+            Value::Bool(b) => Ok(b),
+            _ => Err(self.error("expected true or false")),
+        }
+    }
+
+    /// Returns the value as integer of the expected type, failing if it is out of range.
+    pub fn as_int<T: TryFrom<i64>>(&self) -> Result<T, ConfigError> {
+        match self.value {
+            Value::Int(i) => T::try_from(i).map_err(|_| self.error("number out of range")),
+            _ => Err(self.error("expected a number")),
+        }
+    }
+
+    /// Returns the integer value checked by parsing it as `T`, e.g. as `units::Port`, so that it
+    /// fails with the messages of the command line.
+    pub fn as_checked<T: FromStr<Err = String>>(&self) -> Result<T, ConfigError> {
+        self.as_int::<i64>()?.to_string().parse().map_err(|err: String| self.error(&err))
+    }
+
+    /// Returns the value as non-empty array of integers checked like `as_checked`, or a single
+    /// integer as array of one.
+    pub fn as_checked_array<T: FromStr<Err = String>>(&self) -> Result<Vec<T>, ConfigError> {
+        self.as_int_array::<i64>()?
+            .iter()
+            .map(|i| i.to_string().parse().map_err(|err: String| self.error(&err)))
+            .collect()
+    }
+
+    /// Returns the value as array of strings.
+    pub fn as_str_array(&self) -> Result<Vec<String>, ConfigError> {
+        let err = || self.error("expected an array of strings");
+        match self.value {
+            Value::Array(ref values) => {
+                values.iter()
+                    .map(|value| match *value {
+                        Value::Str(ref s) => Ok(s.clone()),
+                        _ => Err(err()),
+                    })
+                    .collect()
+            },
+            _ => Err(err()),
+        }
+    }
+
+    /// Returns the value as non-empty array of integers of the expected type, or a single
+    /// integer as array of one.
+    pub fn as_int_array<T: TryFrom<i64>>(&self) -> Result<Vec<T>, ConfigError> {
+        let values = match self.value {
+            Value::Int(_) => return Ok(vec![self.as_int()?]),
+            Value::Array(ref values) if !values.is_empty() => values,
+            _ => return Err(self.error("expected a number or an array of numbers")),
+        };
+        values.iter()
+            .map(|value| match *value {
+                Value::Int(i) => T::try_from(i).map_err(|_| self.error("number out of range")),
+                _ => Err(self.error("expected a number or an array of numbers")),
+            })
+            .collect()
+    }
+
+    /// Returns the keys of an inline table as entries of the table named like this entry, e.g.
+    /// `probe.cmd`, so that errors name them.
+    pub fn as_table(&self) -> Result<Vec<Entry>, ConfigError> {
+        match self.value {
+            Value::Table(ref values) => {
+                Ok(values.iter()
+                    .map(|(key, value)| {
+                        Entry {
+                            line: self.line,
+                            table: self.name(),
+                            key: key.clone(),
+                            value: value.clone(),
+                        }
+                    })
+                    .collect())
+            },
+            _ => Err(self.error("expected an inline table like { key = value }")),
+        }
+    }
+
+    /// Returns the value as secret, given as string or referenced by an inline table like
+    /// `{ keyring = "nas" }` or `{ cmd = "pass show wol/nas" }`, which is read, see `secrets`.
+    pub fn as_secret(&self) -> Result<String, ConfigError> {
+        let reference = match self.as_secret_ref()? {
+            Some(reference) => reference,
+            None => return Ok(self.as_str()?.to_string()),
+        };
+        reference.read().map_err(|err| {
+            self.error(&format!("cannot read the secret of the {}: {}", reference, err))
+        })
+    }
+
+    /// Returns where the secret of the value is kept without reading it, `None` if it is given as
+    /// string, e.g. to check a file without running the commands of its secrets.
+    pub fn as_secret_ref(&self) -> Result<Option<SecretRef>, ConfigError> {
+        let keys = match self.value {
+            Value::Str(_) => return Ok(None),
+            Value::Table(_) => self.as_table()?,
+            _ => {
+                return Err(self.error("expected a string or an inline table like \
+                                       { keyring = \"account\" }"))
+            },
+        };
+        let reference = match keys.as_slice() {
+            [key] if key.key == "keyring" => SecretRef::Keyring(key.as_str()?.to_string()),
+            [key] if key.key == "cmd" => SecretRef::Command(key.as_str()?.to_string()),
+            [key] => return Err(key.unknown()),
+            _ => return Err(self.error("expected either keyring or cmd")),
+        };
+        Ok(Some(reference))
+    }
+
+    /// Returns the value as duration, given as a number of seconds or a string like "5m", see
+    /// `units::parse_duration`.
+    pub fn as_duration(&self) -> Result<Duration, ConfigError> {
+        match self.value {
+            Value::Int(_) => Ok(Duration::from_secs(self.as_int()?)),
+            Value::Str(ref s) => units::parse_duration(s).map_err(|err| self.error(&err)),
+            _ => Err(self.error("expected a duration like \"5m\"")),
+        }
+    }
+
+    /// Parses the string value, e.g. as IP address. `what` describes the expected value in the
+    /// error message.
+    pub fn parse<T: FromStr>(&self, what: &str) -> Result<T, ConfigError> {
+        self.as_str()?.parse().map_err(|_| self.error(&format!("expected {}", what)))
+    }
+}
+
+/// Quotes a string value.
+pub fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+/// Splits a file of the TOML subset into its entries.
+pub fn parse(s: &str) -> Result<Vec<Entry>, ConfigError> {
+    let mut entries = Vec::new();
+    let mut table = String::new();
+    for (i, line) in s.lines().enumerate() {
+        let line_no = i + 1;
+        let err = |message: &str| ConfigError::new(line_no, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            let end = line.find(']').ok_or_else(|| err("missing ']'"))?;
+            if !is_comment(&line[end + 1..]) {
+                return Err(err("unexpected characters after table"));
+            }
+            table = line[1..end].trim().to_string();
+            continue;
+        }
+        let eq = line.find('=').ok_or_else(|| err("expected 'key = value'"))?;
+        let key = line[..eq].trim();
+        if key.is_empty() {
+            return Err(err("missing key"));
+        }
+        let (value, rest) = parse_value(line[eq + 1..].trim()).map_err(&err)?;
+        if !is_comment(rest) {
+            return Err(err("unexpected characters after value"));
+        }
+        entries.push(Entry {
+            line: line_no,
+            table: table.clone(),
+            key: key.to_string(),
+            value,
+        });
+    }
+    Ok(entries)
+}
+
+/// Returns whether `s` is empty or a comment.
+fn is_comment(s: &str) -> bool {
+    let s = s.trim();
+    s.is_empty() || s.starts_with('#')
+}
+
+/// Parses the value at the start of `s` and returns it with the rest of `s`.
+fn parse_value(s: &str) -> Result<(Value, &str), &'static str> {
+    if s.starts_with('"') {
+        let mut value = String::new();
+        let mut chars = s.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::Str(value), &s[i + 1..])),
+                '\\' => {
+                    match chars.next() {
+                        Some((_, '"')) => value.push('"'),
+                        Some((_, '\\')) => value.push('\\'),
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        _ => return Err("unsupported escape sequence"),
+                    }
+                },
+                c => value.push(c),
+            }
+        }
+        return Err("unterminated string");
+    }
+    if let Some(array) = s.strip_prefix('[') {
+        let mut values = Vec::new();
+        let mut rest = array.trim_start();
+        loop {
+            if rest.is_empty() || rest.starts_with('#') {
+                return Err("unterminated array");
+            }
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), after));
+            }
+            let (value, after) = parse_value(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after.trim_start();
+            } else if !rest.starts_with(']') && !rest.is_empty() {
+                return Err("expected ',' or ']' in array");
+            }
+        }
+    }
+    if let Some(table) = s.strip_prefix('{') {
+        let mut values = Vec::new();
+        let mut rest = table.trim_start();
+        loop {
+            if rest.is_empty() || rest.starts_with('#') {
+                return Err("unterminated inline table");
+            }
+            if let Some(after) = rest.strip_prefix('}') {
+                return Ok((Value::Table(values), after));
+            }
+            let eq = rest.find('=').ok_or("expected 'key = value' in inline table")?;
+            let key = rest[..eq].trim();
+            if key.is_empty() {
+                return Err("missing key");
+            }
+            let (value, after) = parse_value(rest[eq + 1..].trim_start())?;
+            values.push((key.to_string(), value));
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after.trim_start();
+            } else if !rest.starts_with('}') && !rest.is_empty() {
+                return Err("expected ',' or '}' in inline table");
+            }
+        }
+    }
+    let end = s.find(|c: char| c.is_whitespace() || "#,]}".contains(c)).unwrap_or(s.len());
+    let (token, rest) = s.split_at(end);
+    let value = match token {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::Int(token.replace('_', "").parse().map_err(|_| "expected a value")?),
+    };
+    Ok((value, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse, quote, Value};
+
+    #[test]
+    fn test_duration() {
+        let duration = |s: &str| parse(s).unwrap()[0].as_duration().map_err(|e| e.to_string());
+        assert_eq!(duration("a = \"5m\""), Ok(Duration::from_secs(300)));
+        assert_eq!(duration("a = 30"), Ok(Duration::from_secs(30)));
+        assert_eq!(duration("a = \"5 min\""),
+                   Err("1: expected a unit of ms, s, m, h or d".to_string()));
+    }
+
+    #[test]
+    fn test_int_array() {
+        let ports = |s: &str| parse(s).unwrap()[0].as_int_array::<u16>().map_err(|e| e.to_string());
+        assert_eq!(ports("a = [22, 3389]"), Ok(vec![22, 3389]));
+        assert_eq!(ports("a = 22"), Ok(vec![22]));
+        assert_eq!(ports("a = []"), Err("1: expected a number or an array of numbers".to_string()));
+        assert_eq!(ports("a = [22, 65536]"), Err("1: number out of range".to_string()));
+    }
+
+    #[test]
+    fn test_parse() {
+        let entries = parse("a = \"x \\\"y\\\"\" # comment\n[t]\nb = -1_000\nc = true").unwrap();
+        let values: Vec<_> = entries.iter().map(|e| (e.name(), e.value.clone())).collect();
+        assert_eq!(values,
+                   vec![("a".to_string(), Value::Str("x \"y\"".to_string())),
+                        ("t.b".to_string(), Value::Int(-1000)),
+                        ("t.c".to_string(), Value::Bool(true))]);
+        assert_eq!(parse("a = [\"x\", 1, [true]] # comment").unwrap()[0].value,
+                   Value::Array(vec![Value::Str("x".to_string()),
+                                     Value::Int(1),
+                                     Value::Array(vec![Value::Bool(true)])]));
+        assert_eq!(parse("a = [1, 2").unwrap_err().to_string(), "1: unterminated array");
+        assert_eq!(quote("x \"y\""), "\"x \\\"y\\\"\"");
+        assert_eq!(parse("a = 1 2").unwrap_err().to_string(),
+                   "1: unexpected characters after value");
+    }
+
+    #[test]
+    fn test_inline_table() {
+        let entries = parse("[nas]\nprobe = { type = \"command\", timeout = 10 } # comment");
+        let entries = entries.unwrap();
+        let keys = entries[0].as_table().unwrap();
+        assert_eq!(keys[0].name(), "nas.probe.type");
+        assert_eq!(keys[0].as_str().unwrap(), "command");
+        assert_eq!(keys[1].value, Value::Int(10));
+        assert_eq!(parse("a = {}").unwrap()[0].value, Value::Table(Vec::new()));
+        assert_eq!(parse("a = { b = 1").unwrap_err().to_string(), "1: unterminated inline table");
+        assert_eq!(parse("a = 1").unwrap()[0].as_table().unwrap_err().to_string(),
+                   "1: expected an inline table like { key = value }");
+    }
+}
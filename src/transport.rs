@@ -0,0 +1,1483 @@
+//! Sending magic packets per UDP.
+//!
+//! `WolClient` sends with a configurable destination, number of repetitions, interface and TTL,
+//! while the free functions send a single packet with a new socket each.
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use error::WolError;
+use packet::PACKET_WITH_PASSWORD_LEN;
+use subnet;
+use sys;
+use units::{self, Interval};
+use verify::{self, Backoff, Prober};
+use {MacAddr, MagicPacket};
+
+/// The directory of the network namespaces named by `ip netns`.
+pub const NETNS_DIR: &str = "/run/netns";
+
+/// Returns the destination of magic packets sent per UDP/IPv4, the limited broadcast address.
+pub fn destination_v4() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::new(255, 255, 255, 255), 0))
+}
+
+/// Returns the destination of magic packets sent per UDP/IPv6.
+pub fn destination_v6() -> SocketAddr {
+    SocketAddr::from((Ipv6Addr::new(0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02), 0))
+}
+
+/// An IPv6 multicast group to send magic packets to, e.g. a site-local group which the routers
+/// of an organization forward to the networks of the hosts instead of directed broadcasts.
+///
+/// #Example
+///
+/// ```
+/// use wol::transport::MulticastGroup;
+///
+/// let group: MulticastGroup = "ff05::1:3".parse().unwrap();
+/// assert_eq!((group.scope(), group.is_routed()), (5, true));
+/// assert!("ff02::1".parse::<MulticastGroup>().is_ok_and(|group| !group.is_routed()));
+/// assert!("fd00::1".parse::<MulticastGroup>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MulticastGroup(Ipv6Addr);
+
+impl MulticastGroup {
+    /// Returns the group of an IPv6 multicast address, `None` for other addresses.
+    pub fn new(address: Ipv6Addr) -> Option<MulticastGroup> {
+        if address.is_multicast() { Some(MulticastGroup(address)) } else { None }
+    }
+
+    /// Returns the address of the group.
+    pub fn address(self) -> Ipv6Addr {
+        self.0
+    }
+
+    /// Returns the scope of the group, e.g. 2 for link-local, 5 for site-local, 8 for
+    /// organization-local or 14 for global groups.
+    pub fn scope(self) -> u8 {
+        (self.0.segments()[0] & 0x000F) as u8
+    }
+
+    /// Returns whether routers may forward packets to the group beyond the link, i.e. its scope
+    /// is wider than link-local. They only do if the hop limit is above 1, see
+    /// `WolClient::with_ttl`.
+    pub fn is_routed(self) -> bool {
+        self.scope() > 2
+    }
+}
+
+impl fmt::Display for MulticastGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for MulticastGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MulticastGroup, String> {
+        s.parse()
+            .ok()
+            .and_then(MulticastGroup::new)
+            .ok_or_else(|| "expected an IPv6 multicast group like ff05::1:3".to_string())
+    }
+}
+
+/// Where and when a magic packet was sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendReport {
+    /// The number of bytes sent, summed over all repetitions.
+    pub bytes_sent: usize,
+    /// The address the packet was sent to.
+    pub destination: SocketAddr,
+    /// The local address of the socket the packet was sent from.
+    pub local_addr: SocketAddr,
+    /// The time the packet was sent.
+    pub timestamp: SystemTime,
+}
+
+impl fmt::Display for SendReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sent {} bytes from {} to {}", self.bytes_sent, self.local_addr, self.destination)
+    }
+}
+
+/// Sends the magic packet per UDP/IPv4.
+///
+/// #Example
+///
+/// ```
+/// # #![allow(deprecated)]
+/// let report = wol::send_magic_packet_v4([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]).unwrap();
+/// assert_eq!(report.bytes_sent, 102);
+/// assert_eq!(report.destination, wol::destination_v4());
+/// ```
+#[deprecated(note = "use `WolClient::v4().send(&MagicPacket::new(mac))`")]
+pub fn send_magic_packet_v4<M: Into<MacAddr>>(mac: M) -> Result<SendReport, io::Error> {
+    WolClient::v4().send(&MagicPacket::new(mac.into()))
+}
+
+/// Sends the magic packet per UDP/IPv6.
+///
+/// #Example
+///
+/// ```
+/// # #![allow(deprecated)]
+/// use std::convert::TryFrom;
+/// use wol::MacAddr;
+///
+/// let mac = MacAddr::try_from("AA:FF:B0:12:34:56").unwrap();
+/// let report = wol::send_magic_packet_v6(mac).unwrap();
+/// assert_eq!(report.bytes_sent, 102);
+/// ```
+#[deprecated(note = "use `WolClient::v6().send(&MagicPacket::new(mac))`")]
+pub fn send_magic_packet_v6<M: Into<MacAddr>>(mac: M) -> Result<SendReport, io::Error> {
+    WolClient::v6().send(&MagicPacket::new(mac.into()))
+}
+
+/// Sends the magic packet per UDP/IPv4 to the limited broadcast address and per UDP/IPv6 to the
+/// all-nodes multicast group ff02::1, for hosts whose network card listens on only one of them.
+/// IPv6 is skipped with a warning if the system cannot send per IPv6, see
+/// `WolClient::with_dual_stack`.
+///
+/// #Example
+///
+/// ```
+/// # #![allow(deprecated)]
+/// let report = wol::send_dual([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]).unwrap();
+/// assert_eq!(report.destination, wol::destination_v4());
+/// ```
+#[deprecated(note = "use `WolClient::v4().with_dual_stack(true).send(&MagicPacket::new(mac))`")]
+pub fn send_dual<M: Into<MacAddr>>(mac: M) -> Result<SendReport, io::Error> {
+    WolClient::v4().with_dual_stack(true).send(&MagicPacket::new(mac.into()))
+}
+
+/// Sends a magic packet to the given destination, e.g. `destination_v4()`.
+#[deprecated(note = "use `WolClient::new(destination).send(packet)`")]
+pub fn send_packet(packet: &MagicPacket, destination: SocketAddr) -> Result<SendReport, io::Error> {
+    WolClient::new(destination).send(packet)
+}
+
+/// Sends the magic packet from a socket managed by the caller, e.g. one with custom bind options
+/// or in another network namespace.
+///
+/// The socket is used as is: to send to a broadcast address, it must allow broadcasts with
+/// `UdpSocket::set_broadcast`.
+///
+/// #Example
+///
+/// ```
+/// use std::net::UdpSocket;
+///
+/// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let mac = [0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56];
+/// let report = wol::send_magic_packet_on(&socket, mac, receiver.local_addr().unwrap()).unwrap();
+/// assert_eq!(report.local_addr, socket.local_addr().unwrap());
+/// ```
+pub fn send_magic_packet_on<M>(socket: &UdpSocket,
+                               mac: M,
+                               destination: SocketAddr)
+                               -> Result<SendReport, io::Error>
+    where M: Into<MacAddr>
+{
+    let bytes_sent = socket.send_to(&MagicPacket::new(mac.into()).payload(), destination)?;
+    report(socket, destination, bytes_sent)
+}
+
+/// Parses the MAC address and sends a magic packet to the given destination.
+///
+/// #Example
+///
+/// ```
+/// # #![allow(deprecated)]
+/// let report = wol::wake("AA:FF:B0:12:34:56", wol::destination_v4()).unwrap();
+/// assert_eq!(report.bytes_sent, 102);
+/// assert!(wol::wake("AA:FF:B0", wol::destination_v4()).is_err());
+/// ```
+#[deprecated(note = "parse the `MacAddr` and use `WolClient::new(destination).send(&packet)`")]
+pub fn wake(mac: &str, destination: SocketAddr) -> Result<SendReport, WolError> {
+    let mac: MacAddr = mac.parse()?;
+    Ok(WolClient::new(destination).send(&MagicPacket::new(mac))?)
+}
+
+/// How often the repetitions of a packet are sent, and how far apart: `count` bursts of the
+/// repetitions, each `gap` after the previous one.
+///
+/// Waking a host over the internet goes through a port forward of its router to the host's IP
+/// address. Once the host has slept for a while, the router forgets its MAC address and drops
+/// the packets while it asks per ARP, which the sleeping host does not answer; some routers only
+/// drop the first packet of a burst while they ask. Sending several bursts seconds apart, as
+/// the wake-on-LAN apps for phones do, gets packets through in both cases more often than
+/// sending them all at once.
+///
+/// #Example
+///
+/// ```
+/// use std::time::Duration;
+/// use wol::transport::Bursts;
+///
+/// let bursts: Bursts = "3x2s".parse().unwrap();
+/// assert_eq!(bursts, Bursts::new(3, Duration::from_secs(2)));
+/// assert_eq!(bursts.to_string(), "3x2s");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bursts {
+    /// The number of bursts, at least one.
+    pub count: u32,
+    /// The time between the start of one burst and the next.
+    pub gap: Duration,
+}
+
+impl Bursts {
+    /// A single burst, sending all repetitions at once.
+    pub const SINGLE: Bursts = Bursts { count: 1, gap: Duration::from_secs(0) };
+
+    /// The bursts of `wol --internet`: three bursts two seconds apart.
+    pub const INTERNET: Bursts = Bursts { count: 3, gap: Duration::from_secs(2) };
+
+    /// Creates `count` bursts, at least one, `gap` apart.
+    pub fn new(count: u32, gap: Duration) -> Bursts {
+        Bursts { count: count.max(1), gap }
+    }
+}
+
+impl Default for Bursts {
+    fn default() -> Bursts {
+        Bursts::SINGLE
+    }
+}
+
+impl fmt::Display for Bursts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.count == 1 {
+            f.write_str("1")
+        } else {
+            write!(f, "{}x{}", self.count, units::format_duration(self.gap))
+        }
+    }
+}
+
+impl FromStr for Bursts {
+    type Err = String;
+
+    /// Parses "COUNTxGAP", e.g. "3x2s", or "1" for a single burst.
+    fn from_str(s: &str) -> Result<Bursts, String> {
+        if s == "1" {
+            return Ok(Bursts::SINGLE);
+        }
+        let (count, gap) = s.split_once('x')
+            .ok_or_else(|| "expected COUNTxGAP, e.g. 3x2s".to_string())?;
+        let count = count.parse().map_err(|_| format!("invalid number of bursts '{}'", count))?;
+        if count == 0 {
+            return Err("expected at least one burst".to_string());
+        }
+        Ok(Bursts::new(count, gap.parse::<Interval>()?.get()))
+    }
+}
+
+/// A Differentiated Services Code Point, the class in the IP header by which managed switches
+/// and routers prioritize packets.
+///
+/// #Example
+///
+/// ```
+/// use wol::transport::Dscp;
+///
+/// assert_eq!("ef".parse(), Ok(Dscp::EF));
+/// assert_eq!("af41".parse::<Dscp>().unwrap().value(), 34);
+/// assert_eq!("cs6".parse::<Dscp>().unwrap().traffic_class(), 0xC0);
+/// assert!("64".parse::<Dscp>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dscp(u8);
+
+impl Dscp {
+    /// Expedited forwarding, the class of traffic which must not wait.
+    pub const EF: Dscp = Dscp(46);
+
+    /// Returns the code point of the value if it is below 64.
+    pub fn new(value: u8) -> Option<Dscp> {
+        if value < 64 { Some(Dscp(value)) } else { None }
+    }
+
+    /// Returns the value of the code point, from 0 to 63.
+    pub fn value(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the type of service byte of IPv4 or the traffic class of IPv6 with this code
+    /// point and without congestion notification.
+    pub fn traffic_class(self) -> u8 {
+        self.0 << 2
+    }
+}
+
+impl fmt::Display for Dscp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Dscp {
+    type Err = String;
+
+    /// Parses a number from 0 to 63 or the name of a standard class: `ef`, `cs0` to `cs7` or
+    /// `af11` to `af43`.
+    fn from_str(s: &str) -> Result<Dscp, String> {
+        let lower = s.to_lowercase();
+        let digits = |prefix: &str| -> Option<Vec<u8>> {
+            let digits = lower.strip_prefix(prefix)?;
+            digits.chars().map(|c| c.to_digit(10).map(|d| d as u8)).collect()
+        };
+        let value = match (lower.as_str(), digits("cs"), digits("af")) {
+            ("ef", _, _) => Some(Dscp::EF.0),
+            (_, Some(ref class), _) if class.len() == 1 && class[0] <= 7 => Some(class[0] * 8),
+            (_, _, Some(ref class)) if class.len() == 2 && (1..=4).contains(&class[0]) &&
+                                       (1..=3).contains(&class[1]) => {
+                Some(class[0] * 8 + class[1] * 2)
+            },
+            _ => s.parse().ok(),
+        };
+        value.and_then(Dscp::new)
+            .ok_or_else(|| "expected a DSCP from 0 to 63 or a class like ef, cs1 or af41".into())
+    }
+}
+
+/// A limit of the packets a client sends: `per_second` on average, with up to `burst` packets at
+/// once after a pause. See `WolClient::with_rate_limit`.
+///
+/// #Example
+///
+/// ```
+/// use wol::transport::RateLimit;
+///
+/// let limit = RateLimit::new(100, 20);
+/// assert_eq!((limit.per_second, limit.burst), (100, 20));
+/// assert_eq!(RateLimit::new(0, 0), RateLimit::new(1, 1));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RateLimit {
+    /// The packets sent per second on average, at least one.
+    pub per_second: u32,
+    /// The packets which can be sent at once after a pause, at least one.
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// Creates a limit of `per_second` packets per second, at least one, in bursts of up to
+    /// `burst` packets, at least one.
+    pub fn new(per_second: u32, burst: u32) -> RateLimit {
+        RateLimit {
+            per_second: per_second.max(1),
+            burst: burst.max(1),
+        }
+    }
+}
+
+/// What the rate limit of a client did so far, see `WolClient::rate_limit_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RateLimitStats {
+    /// The packets sent under the limit, including the delayed ones.
+    pub packets: u64,
+    /// The packets which had to wait as they were sent faster than the limit allows.
+    pub delayed: u64,
+    /// How long the delayed packets waited in all.
+    pub delay: Duration,
+}
+
+/// The token bucket of a rate limit, shared by the clones of a client.
+#[derive(Debug, Clone)]
+struct Limiter {
+    limit: RateLimit,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// The packets which can be sent right away, negative while packets wait for their turn.
+    tokens: f64,
+    refilled: Instant,
+    stats: RateLimitStats,
+}
+
+impl Limiter {
+    fn new(limit: RateLimit) -> Limiter {
+        let bucket = Bucket {
+            tokens: limit.burst as f64,
+            refilled: Instant::now(),
+            stats: RateLimitStats::default(),
+        };
+        Limiter {
+            limit,
+            bucket: Arc::new(Mutex::new(bucket)),
+        }
+    }
+
+    /// Waits until the next packet may be sent. The packet takes its token before waiting, so
+    /// that threads sending concurrently are let through one after the other.
+    fn acquire(&self) {
+        let rate = self.limit.per_second as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refill).min(self.limit.burst as f64) - 1.0;
+            bucket.refilled = now;
+            bucket.stats.packets += 1;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            let wait = Duration::from_secs_f64(-bucket.tokens / rate);
+            bucket.stats.delayed += 1;
+            bucket.stats.delay += wait;
+            wait
+        };
+        trace!("the rate limit is reached, waiting {:?}", wait);
+        thread::sleep(wait);
+    }
+
+    fn stats(&self) -> RateLimitStats {
+        self.bucket.lock().unwrap().stats
+    }
+}
+
+impl PartialEq for Limiter {
+    /// Limiters are equal if their limits are, whatever they let through so far.
+    fn eq(&self, other: &Limiter) -> bool {
+        self.limit == other.limit
+    }
+}
+
+impl Eq for Limiter {}
+
+/// Sends magic packets with a configurable destination, number of repetitions, interface and
+/// TTL.
+///
+/// The client is `Send + Sync`, so threads can share it, e.g. behind an `Arc`; with
+/// `with_shared_socket`, they also share one socket instead of opening one per packet.
+///
+/// #Example
+///
+/// ```
+/// use std::net::UdpSocket;
+/// use wol::{MacAddr, MagicPacket, WolClient};
+///
+/// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let port = receiver.local_addr().unwrap().port();
+/// let client = WolClient::v4().with_broadcast("127.0.0.1".parse().unwrap()).with_port(port);
+/// let packet = MagicPacket::new(MacAddr::new([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]));
+/// let report = client.with_repeat(3).send(&packet).unwrap();
+/// assert_eq!(report.bytes_sent, 3 * 102);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WolClient {
+    destination: SocketAddr,
+    /// The ports sent to after the one of the destination.
+    more_ports: Vec<u16>,
+    dual_stack: bool,
+    repeat: u32,
+    bursts: Bursts,
+    interface: Option<String>,
+    check_link: bool,
+    netns: Option<PathBuf>,
+    source: Option<IpAddr>,
+    source_port: Option<u16>,
+    ttl: Option<u32>,
+    dscp: Option<Dscp>,
+    mark: Option<u32>,
+    timeout: Option<Duration>,
+    shared: Option<SharedSocket>,
+    limiter: Option<Limiter>,
+}
+
+/// The socket of a client with a shared socket and its destination, opened on the first send
+/// and shared by the clones of the client. It is only locked to open the socket or hand it out;
+/// sending on a `UdpSocket` needs no lock.
+#[derive(Debug, Clone, Default)]
+struct SharedSocket(Arc<Mutex<Option<Connected>>>);
+
+/// A socket connected to the destination, which it is returned with.
+type Connected = Arc<(UdpSocket, SocketAddr)>;
+
+impl PartialEq for SharedSocket {
+    /// Clients are equal if they are configured alike, whether they opened their socket or not.
+    fn eq(&self, _: &SharedSocket) -> bool {
+        true
+    }
+}
+
+impl Eq for SharedSocket {}
+
+impl WolClient {
+    /// Creates a client sending a single packet to the given destination.
+    pub fn new(destination: SocketAddr) -> WolClient {
+        WolClient {
+            destination,
+            more_ports: Vec::new(),
+            dual_stack: false,
+            repeat: 1,
+            bursts: Bursts::SINGLE,
+            interface: None,
+            check_link: false,
+            netns: None,
+            source: None,
+            source_port: None,
+            ttl: None,
+            dscp: None,
+            mark: None,
+            timeout: None,
+            shared: None,
+            limiter: None,
+        }
+    }
+
+    /// Creates a client sending per UDP/IPv4 to `destination_v4()`.
+    pub fn v4() -> WolClient {
+        WolClient::new(destination_v4())
+    }
+
+    /// Creates a client sending per UDP/IPv6 to `destination_v6()`.
+    pub fn v6() -> WolClient {
+        WolClient::new(destination_v6())
+    }
+
+    /// Sends to the given broadcast or multicast address, keeping the port.
+    pub fn with_broadcast(mut self, address: IpAddr) -> WolClient {
+        self.destination.set_ip(address);
+        self.reconfigured()
+    }
+
+    /// Sends per IPv6 to the given multicast group, keeping the port. Groups which are routed
+    /// beyond the link also need a hop limit above 1, see `with_ttl`.
+    pub fn with_multicast_group(self, group: MulticastGroup) -> WolClient {
+        self.with_broadcast(IpAddr::V6(group.address()))
+    }
+
+    /// Sends to the given UDP port.
+    pub fn with_port(mut self, port: u16) -> WolClient {
+        self.destination.set_port(port);
+        self.more_ports.clear();
+        self.reconfigured()
+    }
+
+    /// Sends every packet to each of the given UDP ports, e.g. 7 and 9 for network cards which
+    /// listen on either. The packets to all but the first port are sent from sockets of their
+    /// own, also with a shared socket. An empty list keeps the port.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use wol::{MacAddr, MagicPacket, WolClient};
+    ///
+    /// let first = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let second = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let ports = [first.local_addr().unwrap().port(), second.local_addr().unwrap().port()];
+    /// let client = WolClient::new(first.local_addr().unwrap()).with_ports(&ports);
+    /// let report = client.send(&MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]))).unwrap();
+    /// assert_eq!(report.bytes_sent, 2 * 102);
+    /// assert_eq!(second.recv(&mut [0; 102]).unwrap(), 102);
+    /// ```
+    pub fn with_ports(mut self, ports: &[u16]) -> WolClient {
+        if let Some((&port, more)) = ports.split_first() {
+            self.destination.set_port(port);
+            self.more_ports = more.to_vec();
+        }
+        self.reconfigured()
+    }
+
+    /// Sends every packet also per the other IP version: per IPv6 to the all-nodes multicast
+    /// group ff02::1 if the destination is an IPv4 address, through the interface of the client
+    /// or else each interface with an IPv6 address, otherwise per IPv4 to the limited broadcast
+    /// address, on the same ports. Useful if it is unknown which the network card of
+    /// the host listens on. The other version is skipped with a warning if the system cannot
+    /// send per it, e.g. without IPv6 address; the report is that of the destination, with the
+    /// bytes sent per both versions.
+    pub fn with_dual_stack(mut self, dual_stack: bool) -> WolClient {
+        self.dual_stack = dual_stack;
+        self
+    }
+
+    /// Sends every packet `repeat` times, at least once.
+    pub fn with_repeat(mut self, repeat: u32) -> WolClient {
+        self.repeat = repeat.max(1);
+        self
+    }
+
+    /// Sends the repetitions of every packet again in the given bursts, e.g. to a host behind
+    /// a router port forward, see `Bursts`. `send` sleeps between the bursts; `send_all` sends
+    /// a single burst.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use std::time::Duration;
+    /// use wol::transport::Bursts;
+    /// use wol::{MacAddr, MagicPacket, WolClient};
+    ///
+    /// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let client = WolClient::new(receiver.local_addr().unwrap())
+    ///     .with_repeat(2)
+    ///     .with_bursts(Bursts::new(3, Duration::from_millis(10)));
+    /// let report = client.send(&MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, 1]))).unwrap();
+    /// assert_eq!(report.bytes_sent, 3 * 2 * 102);
+    /// ```
+    pub fn with_bursts(mut self, bursts: Bursts) -> WolClient {
+        self.bursts = Bursts::new(bursts.count, bursts.gap);
+        self
+    }
+
+    /// Sends through the given network interface, e.g. "eth0", instead of the one chosen by the
+    /// operating system. IPv6 multicast packets are sent on the interface and link-local
+    /// destinations get its zone index. Only supported on Linux.
+    pub fn with_interface<S: Into<String>>(mut self, interface: S) -> WolClient {
+        self.interface = Some(interface.into());
+        self.reconfigured()
+    }
+
+    /// Checks that the interface of the client is up and has a carrier before opening a socket,
+    /// failing with `io::ErrorKind::NetworkDown` otherwise, see `subnet::link_state`, instead of
+    /// sending into a link which drops the packets without an error. Clients without interface
+    /// and systems whose interfaces cannot be read are not checked.
+    pub fn with_link_check(mut self, check_link: bool) -> WolClient {
+        self.check_link = check_link;
+        self.reconfigured()
+    }
+
+    /// Sends from the given network namespace instead of that of the process: a name of
+    /// `ip netns`, e.g. "blue", or the path of a namespace file, e.g. `/proc/1234/ns/net` of a
+    /// container's process or `/proc/self/fd/3` of an inherited descriptor. The interface is one
+    /// of the namespace. Only supported on Linux and needs the `CAP_SYS_ADMIN` capability.
+    pub fn with_netns(mut self, netns: &str) -> WolClient {
+        self.netns = Some(if netns.contains('/') {
+            PathBuf::from(netns)
+        } else {
+            Path::new(NETNS_DIR).join(netns)
+        });
+        self.reconfigured()
+    }
+
+    /// Sends from the given address instead of the one chosen by the operating system, e.g. the
+    /// address of a WireGuard interface, whose peer only accepts packets from the addresses it
+    /// is configured with. The address must be of the family of the destination.
+    pub fn with_source(mut self, source: IpAddr) -> WolClient {
+        self.source = Some(source);
+        self.reconfigured()
+    }
+
+    /// Sends from the given UDP port instead of one chosen by the operating system, e.g. for
+    /// firewalls which only let packets from known source ports out. Only one socket can be
+    /// bound to the port at a time, so sending fails with `io::ErrorKind::AddrInUse` while
+    /// another one sends from it, e.g. with several ports, dual stack or concurrent sends. Ports
+    /// below 1024 need privileges on most systems.
+    pub fn with_source_port(mut self, port: u16) -> WolClient {
+        self.source_port = Some(port);
+        self.reconfigured()
+    }
+
+    /// Sends with the given TTL, or hop limit for IPv6, so that directed broadcasts and unicast
+    /// packets can cross the given number of routers. Setting the IPv6 hop limit is only
+    /// supported on Linux.
+    pub fn with_ttl(mut self, ttl: u32) -> WolClient {
+        self.ttl = Some(ttl);
+        self.reconfigured()
+    }
+
+    /// Sends with the given DSCP in the IP header, e.g. `Dscp::EF`, so that managed switches and
+    /// routers prioritize the packets. Only supported on Linux.
+    pub fn with_dscp(mut self, dscp: Dscp) -> WolClient {
+        self.dscp = Some(dscp);
+        self.reconfigured()
+    }
+
+    /// Marks the packets sent, so that policy routing and firewall rules can steer them, e.g.
+    /// `ip rule add fwmark 7 table 100`. Only supported on Linux and needs the `CAP_NET_ADMIN`
+    /// capability.
+    pub fn with_mark(mut self, mark: u32) -> WolClient {
+        self.mark = Some(mark);
+        self.reconfigured()
+    }
+
+    /// Gives up sending after `timeout` instead of blocking indefinitely, e.g. if the send
+    /// buffer of an unroutable destination is full. The error then has the kind
+    /// `io::ErrorKind::TimedOut` and converts to `WolError::Timeout`.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use std::time::Duration;
+    /// use wol::{MacAddr, MagicPacket, WolClient, WolError};
+    ///
+    /// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let client = WolClient::new(receiver.local_addr().unwrap())
+    ///     .with_timeout(Duration::from_secs(1));
+    /// let packet = MagicPacket::new(MacAddr::new([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]));
+    /// match client.send(&packet).map_err(WolError::from) {
+    ///     Ok(report) => assert_eq!(report.bytes_sent, 102),
+    ///     Err(WolError::Timeout) => println!("timed out"),
+    ///     Err(err) => panic!("{}", err),
+    /// }
+    /// ```
+    pub fn with_timeout(mut self, timeout: Duration) -> WolClient {
+        self.timeout = Some(timeout);
+        self.reconfigured()
+    }
+
+    /// Sends all packets from one socket, opened on the first send and kept until the client and
+    /// its clones are dropped, instead of opening a socket per packet. Threads sharing the
+    /// client, e.g. the handlers of `wol serve`, then send concurrently on the same socket. If
+    /// sending fails, the socket is closed and the next send opens a new one.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use wol::{MacAddr, MagicPacket, WolClient};
+    ///
+    /// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let client = Arc::new(WolClient::new(receiver.local_addr().unwrap())
+    ///     .with_shared_socket(true));
+    /// let threads: Vec<_> = (0..4u8)
+    ///     .map(|i| {
+    ///         let client = client.clone();
+    ///         thread::spawn(move || {
+    ///             let packet = MagicPacket::new(MacAddr::new([2, 0, 0, 0, 0, i]));
+    ///             client.send(&packet).unwrap().local_addr
+    ///         })
+    ///     })
+    ///     .collect();
+    /// let local_addrs: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    /// assert!(local_addrs.iter().all(|addr| *addr == local_addrs[0]));
+    /// ```
+    pub fn with_shared_socket(mut self, shared: bool) -> WolClient {
+        self.shared = if shared { Some(SharedSocket::default()) } else { None };
+        self
+    }
+
+    /// Sends at most `limit.per_second` packets per second on average, with up to `limit.burst`
+    /// at once, and waits before sending the packets beyond, so that a bug looping over `send`
+    /// cannot flood the network with broadcasts. Every repetition and every port counts as a
+    /// packet. The limit is shared by the clones of the client, e.g. the threads of a server;
+    /// `rate_limit_stats` tells how often it applied.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use wol::transport::RateLimit;
+    /// use wol::{MacAddr, MacRange, MagicPacket, WolClient};
+    ///
+    /// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let client = WolClient::new(receiver.local_addr().unwrap())
+    ///     .with_rate_limit(RateLimit::new(200, 2));
+    /// let first = MacAddr::new([2, 0, 0, 0, 0, 1]);
+    /// let range = MacRange::new(first, MacAddr::new([2, 0, 0, 0, 0, 4]));
+    /// client.send_all(range.map(MagicPacket::new)).unwrap();
+    /// let stats = client.rate_limit_stats().unwrap();
+    /// assert_eq!((stats.packets, stats.delayed), (4, 2));
+    /// ```
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> WolClient {
+        self.limiter = Some(Limiter::new(limit));
+        self
+    }
+
+    /// Drops the shared socket, if any, after a setting it was opened with changed.
+    fn reconfigured(mut self) -> WolClient {
+        if self.shared.is_some() {
+            self.shared = Some(SharedSocket::default());
+        }
+        self
+    }
+
+    /// Returns the address packets are sent to.
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+
+    /// Returns the UDP ports packets are sent to, first that of the destination.
+    pub fn ports(&self) -> Vec<u16> {
+        let mut ports = vec![self.destination.port()];
+        ports.extend_from_slice(&self.more_ports);
+        ports
+    }
+
+    /// Returns whether packets are sent per IPv4 and IPv6.
+    pub fn dual_stack(&self) -> bool {
+        self.dual_stack
+    }
+
+    /// Returns how often every packet is sent.
+    pub fn repeat(&self) -> u32 {
+        self.repeat
+    }
+
+    /// Returns the bursts the repetitions of every packet are sent in.
+    pub fn bursts(&self) -> Bursts {
+        self.bursts
+    }
+
+    /// Returns the network interface packets are sent through, if any.
+    pub fn interface(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    /// Returns whether the link of the interface is checked before sending.
+    pub fn link_check(&self) -> bool {
+        self.check_link
+    }
+
+    /// Returns the file of the network namespace packets are sent from, if not that of the
+    /// process.
+    pub fn netns(&self) -> Option<&Path> {
+        self.netns.as_deref()
+    }
+
+    /// Returns the address packets are sent from, if not chosen by the operating system.
+    pub fn source(&self) -> Option<IpAddr> {
+        self.source
+    }
+
+    /// Returns the UDP port packets are sent from, if not chosen by the operating system.
+    pub fn source_port(&self) -> Option<u16> {
+        self.source_port
+    }
+
+    /// Returns the TTL packets are sent with, if not the default of the operating system.
+    pub fn ttl(&self) -> Option<u32> {
+        self.ttl
+    }
+
+    /// Returns the DSCP packets are sent with, if any.
+    pub fn dscp(&self) -> Option<Dscp> {
+        self.dscp
+    }
+
+    /// Returns the mark of the packets sent, if any.
+    pub fn mark(&self) -> Option<u32> {
+        self.mark
+    }
+
+    /// Returns how long sending may block, if limited.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Returns whether all packets are sent from one shared socket.
+    pub fn shared_socket(&self) -> bool {
+        self.shared.is_some()
+    }
+
+    /// Returns the limit of the packets sent per second, if any.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.limiter.as_ref().map(|limiter| limiter.limit)
+    }
+
+    /// Returns what the rate limit did so far, for this client and its clones, if it has one.
+    pub fn rate_limit_stats(&self) -> Option<RateLimitStats> {
+        self.limiter.as_ref().map(Limiter::stats)
+    }
+
+    /// Returns the address packets are sent from, opening a socket without sending: the shared
+    /// one, which stays open for the next packet, or a new one. Fails like `send` if no socket
+    /// can be opened, e.g. because the interface or the source address is gone.
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.with_socket(|socket, _| socket.local_addr())
+    }
+
+    /// Sends the magic packet.
+    pub fn send(&self, packet: &MagicPacket) -> Result<SendReport, io::Error> {
+        self.send_payload(&packet.payload())
+    }
+
+    /// Sends all magic packets from one socket, writing each into the same buffer on the stack.
+    /// Unlike `send` per packet, this neither allocates nor opens a socket per packet, e.g. to
+    /// wake the thousands of machines of a lab. The report sums the bytes of all packets; the
+    /// first failure stops sending. With several ports, all packets are sent to one port after
+    /// the other.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use wol::{MacAddr, MacRange, MagicPacket, WolClient};
+    ///
+    /// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let client = WolClient::new(receiver.local_addr().unwrap());
+    /// let first = MacAddr::new([2, 0, 0, 0, 0, 1]);
+    /// let range = MacRange::new(first, MacAddr::new([2, 0, 0, 0, 0, 9]));
+    /// let report = client.send_all(range.map(MagicPacket::new)).unwrap();
+    /// assert_eq!(report.bytes_sent, 9 * 102);
+    /// ```
+    pub fn send_all<I>(&self, packets: I) -> Result<SendReport, io::Error>
+        where I: IntoIterator<Item = MagicPacket>
+    {
+        let more = self.open_more_sockets()?;
+        self.with_socket(|socket, destination| {
+            let mut buf = [0; PACKET_WITH_PASSWORD_LEN];
+            let mut bytes_sent = 0;
+            for packet in packets {
+                let len = packet.write_to(&mut buf);
+                bytes_sent += self.send_repeated(socket, destination, &buf[..len])?;
+                for (socket, destination) in &more {
+                    bytes_sent += self.send_repeated(socket, *destination, &buf[..len])?;
+                }
+            }
+            report(socket, destination, bytes_sent)
+        })
+    }
+
+    /// Sends the magic packet and waits until `prober` finds the host up, e.g. until it accepts
+    /// TCP connections at a socket address, re-sending the packet with exponentially growing
+    /// intervals in case it got lost. Returns whether the host came up within `timeout`, or
+    /// `WolError::Cancelled` as soon as `cancel` is set, e.g. from another thread or a ctrl-C
+    /// handler.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// use std::net::{TcpListener, UdpSocket};
+    /// use std::sync::atomic::AtomicBool;
+    /// use std::time::Duration;
+    /// use wol::verify::Backoff;
+    /// use wol::{MacAddr, MagicPacket, WolClient};
+    ///
+    /// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let host = TcpListener::bind("127.0.0.1:0").unwrap();
+    /// let client = WolClient::new(receiver.local_addr().unwrap());
+    /// let packet = MagicPacket::new(MacAddr::new([0xAA, 0xFF, 0xB0, 0x12, 0x34, 0x56]));
+    /// let cancel = AtomicBool::new(false);
+    /// let up = client.wake_and_wait(&packet,
+    ///                               host.local_addr().unwrap(),
+    ///                               Duration::from_secs(5),
+    ///                               Backoff::default(),
+    ///                               &cancel);
+    /// assert!(up.unwrap());
+    /// ```
+    pub fn wake_and_wait<P: Prober>(&self,
+                                    packet: &MagicPacket,
+                                    prober: P,
+                                    timeout: Duration,
+                                    backoff: Backoff,
+                                    cancel: &AtomicBool)
+                                    -> Result<bool, WolError> {
+        let start = Instant::now();
+        let mut interval = backoff.initial;
+        loop {
+            self.send(packet)?;
+            let remaining = timeout.saturating_sub(start.elapsed());
+            match verify::wait_for(&prober, interval.min(remaining), cancel) {
+                None => return Err(WolError::Cancelled),
+                Some(true) => return Ok(true),
+                Some(false) if start.elapsed() >= timeout => return Ok(false),
+                Some(false) => debug!("the host is not up yet, sending again"),
+            }
+            interval = backoff.next(interval);
+        }
+    }
+
+    /// Creates a socket of the address family of the destination, set up for the interface and
+    /// TTL of the client, and returns it with the destination, which gets the zone of the
+    /// interface if it needs one. The socket is created in the network namespace of the client.
+    pub(crate) fn open_socket(&self) -> Result<(UdpSocket, SocketAddr), io::Error> {
+        match self.netns {
+            Some(ref netns) => {
+                debug!("opening the socket in the network namespace {}", netns.display());
+                sys::in_netns(netns, || self.open_socket_here())
+            },
+            None => self.open_socket_here(),
+        }
+    }
+
+    /// Opens the socket of `open_socket` in the network namespace of the calling thread.
+    fn open_socket_here(&self) -> Result<(UdpSocket, SocketAddr), io::Error> {
+        if let (true, Some(interface)) = (self.check_link, self.interface.as_ref()) {
+            match subnet::link_state(interface) {
+                Ok(link) => link.check(interface)?,
+                Err(ref err) if err.kind() == io::ErrorKind::Unsupported => {
+                    debug!("cannot check the link of {}: {}", interface, err)
+                },
+                Err(err) => return Err(err),
+            }
+        }
+        let mut destination = self.destination;
+        let source = match (destination, self.source) {
+            (SocketAddr::V4(_), Some(source @ IpAddr::V4(_))) |
+            (SocketAddr::V6(_), Some(source @ IpAddr::V6(_))) => source,
+            (_, Some(source)) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("cannot send to {} from {}",
+                                                  destination.ip(),
+                                                  source)));
+            },
+            (SocketAddr::V4(_), None) => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+            (SocketAddr::V6(_), None) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+        };
+        let socket = create_socket((source, self.source_port.unwrap_or(0))).map_err(|err| {
+            match self.source_port {
+                Some(port) if err.kind() == io::ErrorKind::AddrInUse => {
+                    io::Error::new(err.kind(),
+                                   format!("source port {} is in use, by another socket sending \
+                                            from it or another program",
+                                           port))
+                },
+                _ => err,
+            }
+        })?;
+        match (self.interface.as_ref(), &mut destination) {
+            (Some(interface), &mut SocketAddr::V4(_)) => {
+                sys::bind_to_device(&socket, interface)?;
+                debug!("bound UDP socket to interface {}", interface);
+            },
+            (Some(interface), &mut SocketAddr::V6(ref mut address)) => {
+                // Binding to the device would not choose the interface of multicast packets.
+                let index = sys::interface_index(interface)?;
+                sys::set_multicast_if_v6(&socket, index)?;
+                if is_link_local(address.ip()) && address.scope_id() == 0 {
+                    address.set_scope_id(index);
+                }
+                debug!("sending through interface {} (index {})", interface, index);
+            },
+            (None, _) => {},
+        }
+        if let Some(ttl) = self.ttl {
+            match destination {
+                SocketAddr::V4(_) => {
+                    socket.set_ttl(ttl)?;
+                    socket.set_multicast_ttl_v4(ttl)?;
+                },
+                SocketAddr::V6(_) => sys::set_hop_limit_v6(&socket, ttl)?,
+            }
+        }
+        if let Some(dscp) = self.dscp {
+            sys::set_traffic_class(&socket, destination.is_ipv6(), dscp.traffic_class())?;
+        }
+        if let Some(mark) = self.mark {
+            sys::set_mark(&socket, mark)?;
+        }
+        socket.set_write_timeout(self.timeout)?;
+        Ok((socket, destination))
+    }
+
+    /// Sends the payload as is, like `send` sends that of a magic packet, e.g. one built with
+    /// `packet::PacketFormat` for a hardware address which is no MAC address.
+    ///
+    /// The payload is sent from a new socket of the address family of the destination, or from
+    /// the shared one, and from sockets of their own to the further ports and the other IP
+    /// version.
+    pub fn send_payload(&self, buf: &[u8]) -> Result<SendReport, io::Error> {
+        let more = self.open_more_sockets()?;
+        self.with_socket(|socket, destination| {
+            let mut bytes_sent = 0;
+            for burst in 0..self.bursts.count {
+                if burst > 0 {
+                    thread::sleep(self.bursts.gap);
+                }
+                bytes_sent += self.send_repeated(socket, destination, buf)?;
+                for (socket, destination) in &more {
+                    bytes_sent += self.send_repeated(socket, *destination, buf)?;
+                }
+            }
+            report(socket, destination, bytes_sent)
+        })
+    }
+
+    /// Opens a socket connected to the destination for each port after the first and, with
+    /// dual stack, for each port of the other IP version.
+    fn open_more_sockets(&self) -> Result<Vec<(UdpSocket, SocketAddr)>, io::Error> {
+        let connect = |client: WolClient| -> Result<(UdpSocket, SocketAddr), io::Error> {
+            let (socket, destination) = client.open_socket()?;
+            socket.connect(destination)?;
+            Ok((socket, destination))
+        };
+        let mut sockets = self.more_ports
+            .iter()
+            .map(|&port| connect(self.clone().with_port(port)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.dual_stack {
+            let other = match self.destination {
+                SocketAddr::V4(_) => IpAddr::V6(Ipv6Addr::new(0xFF02, 0, 0, 0, 0, 0, 0, 1)),
+                SocketAddr::V6(_) => IpAddr::V4(Ipv4Addr::BROADCAST),
+            };
+            let mut clients = vec![self.clone().with_broadcast(other)];
+            // The link-local multicast group needs an interface, so it is sent to on each.
+            if other.is_ipv6() && self.interface.is_none() {
+                match sys::ipv6_interfaces() {
+                    Ok(interfaces) => {
+                        clients = interfaces.into_iter()
+                            .map(|interface| clients[0].clone().with_interface(interface))
+                            .collect()
+                    },
+                    Err(err) => debug!("cannot list the IPv6 interfaces: {}", err),
+                }
+            }
+            for client in clients {
+                for port in self.ports() {
+                    match connect(client.clone().with_port(port)) {
+                        Ok(socket) => sockets.push(socket),
+                        Err(err) => {
+                            let destination = SocketAddr::new(other, port);
+                            warn!("cannot send to {} as well: {}", destination, err);
+                            break;
+                        },
+                    }
+                }
+            }
+        }
+        Ok(sockets)
+    }
+
+    /// Calls `send` with a socket connected to the destination: the shared socket, opened if
+    /// needed and closed again if `send` fails, or a new one.
+    fn with_socket<T, F>(&self, send: F) -> Result<T, io::Error>
+        where F: FnOnce(&UdpSocket, SocketAddr) -> Result<T, io::Error>
+    {
+        let shared = match self.shared {
+            Some(ref shared) => shared,
+            None => {
+                let (socket, destination) = self.open_socket()?;
+                socket.connect(destination)?;
+                return send(&socket, destination);
+            },
+        };
+        let opened = {
+            let mut cached = shared.0.lock().unwrap();
+            match *cached {
+                Some(ref opened) => opened.clone(),
+                None => {
+                    let (socket, destination) = self.open_socket()?;
+                    socket.connect(destination)?;
+                    debug!("opened shared socket {}", socket.local_addr()?);
+                    cached.insert(Arc::new((socket, destination))).clone()
+                },
+            }
+        };
+        let result = send(&opened.0, opened.1);
+        if result.is_err() {
+            let mut cached = shared.0.lock().unwrap();
+            // Another thread may have replaced the socket already.
+            if cached.as_ref().is_some_and(|current| Arc::ptr_eq(current, &opened)) {
+                *cached = None;
+            }
+        }
+        result
+    }
+
+    /// Sends the payload `repeat` times on the connected socket and returns the bytes sent.
+    fn send_repeated(&self,
+                     socket: &UdpSocket,
+                     destination: SocketAddr,
+                     buf: &[u8])
+                     -> Result<usize, io::Error> {
+        let mut bytes_sent = 0;
+        for _ in 0..self.repeat {
+            if let Some(ref limiter) = self.limiter {
+                limiter.acquire();
+            }
+            match socket.send(buf) {
+                Ok(n) => bytes_sent += n,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    // Timeouts are reported as WouldBlock on Unix.
+                    let message = format!("sending to {} timed out", destination);
+                    error!("{}", message);
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, message));
+                },
+                Err(err) => {
+                    error!("sending to {} failed: {}", destination, err);
+                    return Err(err);
+                },
+            }
+        }
+        Ok(bytes_sent)
+    }
+}
+
+/// Returns whether the address is only valid on a link and thus needs a zone index: a
+/// link-local unicast address or a multicast address with link-local or smaller scope.
+fn is_link_local(ip: &Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    first & 0xFFC0 == 0xFE80 || (first & 0xFF00 == 0xFF00 && first & 0x000F <= 2)
+}
+
+/// Creates the UdpSocket.
+fn create_socket<A: ToSocketAddrs>(address: A) -> Result<UdpSocket, io::Error> {
+    let socket = UdpSocket::bind(address)?;
+    socket.set_broadcast(true)?;
+    if let Ok(addr) = socket.local_addr() {
+        debug!("bound UDP socket to {}", addr);
+    }
+    Ok(socket)
+}
+
+/// Returns and logs the report of sending from the socket.
+fn report(socket: &UdpSocket,
+          destination: SocketAddr,
+          bytes_sent: usize)
+          -> Result<SendReport, io::Error> {
+    let report = SendReport {
+        bytes_sent,
+        destination,
+        local_addr: socket.local_addr()?,
+        timestamp: SystemTime::now(),
+    };
+    debug!("{}", report);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::net::{TcpListener, UdpSocket};
+    use std::path::Path;
+    use std::sync::atomic::AtomicBool;
+    use std::time::{Duration, Instant};
+
+    use super::{create_socket, is_link_local, Bursts, Dscp, RateLimit, WolClient};
+    use verify::Backoff;
+    use {MacAddr, MagicPacket};
+
+    #[test]
+    fn test_create_socket_v4() {
+        assert!(create_socket("127.0.0.1:0").is_ok());
+    }
+
+    #[test]
+    fn test_create_socket_v6() {
+        assert!(create_socket("[::1]:0").is_ok());
+    }
+
+    #[test]
+    fn test_is_link_local() {
+        assert!(is_link_local(&"fe80::1".parse().unwrap()));
+        assert!(is_link_local(&"ff02::1".parse().unwrap()));
+        assert!(!is_link_local(&"ff05::1".parse().unwrap()));
+        assert!(!is_link_local(&"2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_client_ports() {
+        let first = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let ports = [first.local_addr().unwrap().port(), second.local_addr().unwrap().port()];
+        let client = WolClient::new(first.local_addr().unwrap())
+            .with_ports(&ports)
+            .with_repeat(2)
+            .with_shared_socket(true);
+        assert_eq!(client.ports(), ports);
+        let packets = [MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5])),
+                       MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 6]))];
+        let report = client.send_all(packets.iter().cloned()).unwrap();
+        assert_eq!(report.bytes_sent, 2 * 2 * 2 * 102);
+        assert_eq!(report.destination, first.local_addr().unwrap());
+        let mut buf = [0; 128];
+        for _ in 0..4 {
+            assert_eq!(second.recv(&mut buf).unwrap(), 102);
+        }
+        assert_eq!(client.with_ports(&[]).with_port(9).ports(), [9]);
+    }
+
+    #[test]
+    fn test_client_dual_stack() {
+        // Whether the other IP version can be sent per, and on how many interfaces, depends on
+        // the network of the system.
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = WolClient::new(receiver.local_addr().unwrap()).with_dual_stack(true);
+        assert!(client.dual_stack());
+        let report = client.send(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]))).unwrap();
+        assert_eq!(report.destination, receiver.local_addr().unwrap());
+        assert!(report.bytes_sent > 0 && report.bytes_sent.is_multiple_of(102));
+        assert_eq!(receiver.recv(&mut [0; 128]).unwrap(), 102);
+        receiver.set_nonblocking(true).unwrap();
+        assert_eq!(receiver.recv(&mut [0; 128]).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_client_link_check() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = WolClient::new(receiver.local_addr().unwrap()).with_interface("wol-none0");
+        assert!(!client.link_check());
+        let err = client.with_link_check(true)
+            .send(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5])))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "no network interface wol-none0");
+    }
+
+    #[test]
+    fn test_client_repeat() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = WolClient::new(receiver.local_addr().unwrap()).with_repeat(0);
+        assert_eq!(client.repeat(), 1);
+        let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+        let report = client.with_repeat(2).send(&packet).unwrap();
+        assert_eq!(report.bytes_sent, 204);
+        let mut buf = [0; 128];
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+    }
+
+    #[test]
+    fn test_client_bursts() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gap = Duration::from_millis(50);
+        let client = WolClient::new(receiver.local_addr().unwrap())
+            .with_bursts(Bursts::new(2, gap));
+        assert_eq!(client.bursts(), Bursts { count: 2, gap });
+        let start = Instant::now();
+        let report = client.send(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]))).unwrap();
+        assert!(start.elapsed() >= gap);
+        assert_eq!(report.bytes_sent, 204);
+        assert_eq!(Bursts::new(0, gap).count, 1);
+    }
+
+    #[test]
+    fn test_parse_bursts() {
+        assert_eq!("5x500ms".parse(), Ok(Bursts::new(5, Duration::from_millis(500))));
+        assert_eq!("1".parse(), Ok(Bursts::SINGLE));
+        assert_eq!(Bursts::SINGLE.to_string(), "1");
+        assert_eq!(Bursts::INTERNET.to_string(), "3x2s");
+        assert_eq!("3".parse::<Bursts>(), Err("expected COUNTxGAP, e.g. 3x2s".to_string()));
+        assert_eq!("0x1s".parse::<Bursts>(), Err("expected at least one burst".to_string()));
+        assert_eq!("ax1s".parse::<Bursts>(), Err("invalid number of bursts 'a'".to_string()));
+        assert!("3x2".parse::<Bursts>().unwrap_err().contains("expected a unit"));
+        assert_eq!("3x0s".parse::<Bursts>(),
+                   Err("invalid interval '0s', expected at least 10ms".to_string()));
+    }
+
+    #[test]
+    fn test_client_ttl() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = WolClient::new(receiver.local_addr().unwrap()).with_ttl(1);
+        assert_eq!(client.ttl(), Some(1));
+        assert!(client.send(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]))).is_ok());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_client_dscp() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = WolClient::new(receiver.local_addr().unwrap()).with_dscp(Dscp::EF);
+        assert_eq!(client.dscp(), Some(Dscp::EF));
+        assert!(client.send(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]))).is_ok());
+        assert_eq!("AF11".parse::<Dscp>().map(Dscp::value), Ok(10));
+        assert_eq!("cs0".parse::<Dscp>().map(Dscp::value), Ok(0));
+        assert!("af44".parse::<Dscp>().is_err());
+        assert!("cs8".parse::<Dscp>().is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_client_netns() {
+        let client = WolClient::new("127.0.0.1:9".parse().unwrap()).with_netns("blue");
+        assert_eq!(client.netns(), Some(Path::new("/run/netns/blue")));
+        let client = client.with_netns("/proc/1/ns/net");
+        assert_eq!(client.netns(), Some(Path::new("/proc/1/ns/net")));
+        let err = client.with_netns("no-such-netns").open_socket().unwrap_err();
+        assert!(err.to_string().contains("/run/netns/no-such-netns"), "{}", err);
+    }
+
+    #[test]
+    fn test_client_source() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = WolClient::new(receiver.local_addr().unwrap())
+            .with_source("127.0.0.1".parse().unwrap());
+        assert_eq!(client.source(), Some("127.0.0.1".parse().unwrap()));
+        let report = client.send(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]))).unwrap();
+        let mut buf = [0; 128];
+        let (_, from) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(from, report.local_addr);
+        let err = client.with_source("::1".parse().unwrap())
+            .send(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5])))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_client_source_port() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let client = WolClient::new(receiver.local_addr().unwrap()).with_source_port(port);
+        assert_eq!(client.source_port(), Some(port));
+        let report = client.send(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]))).unwrap();
+        assert_eq!(report.local_addr.port(), port);
+        let mut buf = [0; 128];
+        assert_eq!(receiver.recv_from(&mut buf).unwrap().1.port(), port);
+        let _taken = UdpSocket::bind(("0.0.0.0", port)).unwrap();
+        let err = client.send(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(err.to_string().starts_with(&format!("source port {} is in use", port)), "{}", err);
+    }
+
+    #[test]
+    fn test_wake_and_wait_resends() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let backoff = Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(20),
+        };
+        let up = WolClient::new(receiver.local_addr().unwrap())
+            .wake_and_wait(&MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5])),
+                           closed,
+                           Duration::from_millis(50),
+                           backoff,
+                           &AtomicBool::new(false));
+        assert!(!up.unwrap());
+        let mut buf = [0; 128];
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+    }
+
+    #[test]
+    fn test_client_shared_socket() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<WolClient>();
+
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = WolClient::new(receiver.local_addr().unwrap()).with_shared_socket(true);
+        assert!(client.shared_socket());
+        assert_eq!(client, WolClient::new(receiver.local_addr().unwrap()).with_shared_socket(true));
+        let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+        let first = client.local_addr().unwrap();
+        assert_eq!(client.send(&packet).unwrap().local_addr, first);
+        assert_eq!(client.clone().send(&packet).unwrap().local_addr, first);
+        // Changing the destination opens another socket.
+        let other = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let moved = client.with_port(other.local_addr().unwrap().port());
+        assert_ne!(moved.send(&packet).unwrap().local_addr, first);
+        assert_eq!(other.recv(&mut [0; 128]).unwrap(), 102);
+    }
+
+    #[test]
+    fn test_client_rate_limit() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = WolClient::new(receiver.local_addr().unwrap());
+        assert_eq!((client.rate_limit(), client.rate_limit_stats()), (None, None));
+        let client = client.with_rate_limit(RateLimit::new(50, 1));
+        assert_eq!(client.rate_limit(), Some(RateLimit::new(50, 1)));
+        let packet = MagicPacket::new(MacAddr::new([0, 1, 2, 3, 4, 5]));
+        let start = Instant::now();
+        client.clone().with_repeat(3).send(&packet).unwrap();
+        // The clones share the limit: the first packet passes, the others wait 20 ms each.
+        client.send(&packet).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(55));
+        let stats = client.rate_limit_stats().unwrap();
+        assert_eq!((stats.packets, stats.delayed), (4, 3));
+        assert!(stats.delay >= Duration::from_millis(55));
+        assert_eq!(client, WolClient::new(receiver.local_addr().unwrap())
+            .with_rate_limit(RateLimit::new(50, 1)));
+    }
+}
@@ -0,0 +1,347 @@
+//! A terminal dashboard listing the known hosts with their state, from which they can be woken.
+//!
+//! The hosts with an IP address are probed in the background on the verification port. The
+//! terminal is switched to non-canonical mode with the C library's `tcsetattr`, so no terminal
+//! library is needed; this is only supported on Linux.
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use hosts::Host;
+use output;
+use verify::Method;
+use WolClient;
+
+/// How often the hosts are probed.
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long to wait for a key before redrawing the state.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::io;
+    use std::os::raw::c_int;
+
+    pub const ISIG: u32 = 0o1;
+    pub const ICANON: u32 = 0o2;
+    pub const ECHO: u32 = 0o10;
+    pub const VTIME: usize = 5;
+    pub const VMIN: usize = 6;
+    const TCSANOW: c_int = 0;
+
+    /// `struct termios` of Linux.
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct Termios {
+        pub iflag: u32,
+        pub oflag: u32,
+        pub cflag: u32,
+        pub lflag: u32,
+        pub line: u8,
+        pub cc: [u8; 32],
+        pub ispeed: u32,
+        pub ospeed: u32,
+    }
+
+    extern "C" {
+        fn tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+        fn tcsetattr(fd: c_int, action: c_int, termios: *const Termios) -> c_int;
+    }
+
+    /// Returns the settings of standard input.
+    pub fn get() -> io::Result<Termios> {
+        let mut termios = Termios {
+            iflag: 0,
+            oflag: 0,
+            cflag: 0,
+            lflag: 0,
+            line: 0,
+            cc: [0; 32],
+            ispeed: 0,
+            ospeed: 0,
+        };
+        match unsafe { tcgetattr(0, &mut termios) } {
+            0 => Ok(termios),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Changes the settings of standard input.
+    pub fn set(termios: &Termios) -> io::Result<()> {
+        match unsafe { tcsetattr(0, TCSANOW, termios) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+/// Keeps the terminal in non-canonical mode without echo while alive: keys are read as they
+/// are pressed, reads time out, and ctrl-C arrives as a key instead of a signal.
+#[cfg(target_os = "linux")]
+struct RawMode(imp::Termios);
+
+#[cfg(target_os = "linux")]
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let original = imp::get()?;
+        let mut raw = original;
+        raw.lflag &= !(imp::ICANON | imp::ECHO | imp::ISIG);
+        raw.cc[imp::VMIN] = 0;
+        raw.cc[imp::VTIME] = (REDRAW_INTERVAL.as_millis() / 100) as u8;
+        imp::set(&raw)?;
+        Ok(RawMode(original))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = imp::set(&self.0);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct RawMode;
+
+#[cfg(not(target_os = "linux"))]
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        Err(io::Error::new(io::ErrorKind::Other, "the dashboard is only supported on Linux"))
+    }
+}
+
+/// A key pressed in the dashboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    /// Wakes the selected host.
+    Wake,
+    /// Wakes all hosts.
+    WakeAll,
+    Quit,
+}
+
+/// Returns the keys in the bytes read from the terminal, ignoring unknown ones.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let key = match bytes[i] {
+            0x1B if bytes[i + 1..].starts_with(b"[A") => {
+                i += 2;
+                Some(Key::Up)
+            },
+            0x1B if bytes[i + 1..].starts_with(b"[B") => {
+                i += 2;
+                Some(Key::Down)
+            },
+            b'k' => Some(Key::Up),
+            b'j' => Some(Key::Down),
+            b'w' | b'\r' | b'\n' => Some(Key::Wake),
+            b'a' => Some(Key::WakeAll),
+            // ctrl-C, as signals are off.
+            b'q' | 3 => Some(Key::Quit),
+            _ => None,
+        };
+        keys.extend(key);
+        i += 1;
+    }
+    keys
+}
+
+/// A host as shown in the dashboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub host: Host,
+    /// Whether the host accepted a connection at the last probe; `None` before the first probe
+    /// and for hosts without IP address.
+    pub up: Option<bool>,
+    /// When the host was last woken from the dashboard.
+    pub last_wake: Option<SystemTime>,
+}
+
+/// Renders the rows as a screen, highlighting the selected one, followed by the status line and
+/// the help.
+pub fn render(rows: &[Row], selected: usize, status: &str) -> String {
+    let mut screen = String::from("\x1b[2J\x1b[H");
+    screen.push_str(&format!("{:<20}  {:<17}  {:<39}  {:<7}  LAST WAKE\r\n",
+                             "HOST",
+                             "MAC",
+                             "IP",
+                             "STATE"));
+    for (i, row) in rows.iter().enumerate() {
+        let state = match row.up {
+            Some(true) => "up",
+            Some(false) => "down",
+            None if row.host.ip.is_some() => "...",
+            None => "-",
+        };
+        let line = format!("{:<20}  {:<17}  {:<39}  {:<7}  {}",
+                           row.host.name,
+                           row.host.mac.to_string(),
+                           row.host.ip.map_or_else(|| "-".to_string(), |ip| ip.to_string()),
+                           state,
+                           row.last_wake.map_or_else(|| "-".to_string(), output::timestamp));
+        if i == selected {
+            screen.push_str(&format!("\x1b[7m{}\x1b[0m\r\n", line));
+        } else {
+            screen.push_str(&format!("{}\r\n", line));
+        }
+    }
+    screen.push_str(&format!("\r\n{}\r\n", status));
+    screen.push_str("up/down or j/k: select   w or enter: wake   a: wake all   q: quit\r\n");
+    screen
+}
+
+/// Shows the dashboard for the hosts until the user quits. Hosts are woken with the client and
+/// probed on their own ports or else on `verify_ports`, in order.
+pub fn run(hosts: Vec<Host>, client: &WolClient, verify_ports: &[u16]) -> io::Result<()> {
+    let rows: Mutex<Vec<Row>> = Mutex::new(hosts.into_iter()
+        .map(|host| {
+            Row {
+                host,
+                up: None,
+                last_wake: None,
+            }
+        })
+        .collect());
+    let quit = AtomicBool::new(false);
+    let _raw_mode = RawMode::enable()?;
+    let mut stdout = io::stdout();
+    // Hide the cursor while the dashboard is shown.
+    write!(stdout, "\x1b[?25l")?;
+    let result = thread::scope(|scope| {
+        scope.spawn(|| probe_until(&rows, verify_ports, &quit));
+        let result = interact(&rows, client, &mut stdout);
+        quit.store(true, Ordering::SeqCst);
+        result
+    });
+    write!(stdout, "\x1b[?25h\r\n")?;
+    stdout.flush()?;
+    result
+}
+
+/// Reads and handles keys until the user quits.
+fn interact(rows: &Mutex<Vec<Row>>, client: &WolClient, out: &mut io::Stdout) -> io::Result<()> {
+    let mut stdin = io::stdin();
+    let mut selected = 0;
+    let mut status = String::new();
+    let mut buf = [0; 16];
+    loop {
+        {
+            let rows = rows.lock().unwrap();
+            write!(out, "{}", render(&rows, selected, &status))?;
+            out.flush()?;
+        }
+        let len = stdin.read(&mut buf)?;
+        for key in parse_keys(&buf[..len]) {
+            let mut rows = rows.lock().unwrap();
+            match key {
+                Key::Up => selected = selected.saturating_sub(1),
+                Key::Down => selected = (selected + 1).min(rows.len().saturating_sub(1)),
+                Key::Wake => {
+                    if let Some(row) = rows.get_mut(selected) {
+                        status = wake(row, client);
+                    }
+                },
+                Key::WakeAll => {
+                    let failed = rows.iter_mut()
+                        .map(|row| wake(row, client))
+                        .filter(|status| status.starts_with("cannot"))
+                        .count();
+                    status = format!("woke {} hosts, {} failed", rows.len() - failed, failed);
+                },
+                Key::Quit => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Sends the magic packet for the host of the row and returns the status to show.
+fn wake(row: &mut Row, client: &WolClient) -> String {
+    match row.host.client(client).send(&row.host.packet()) {
+        Ok(_) => {
+            row.last_wake = Some(SystemTime::now());
+            format!("woke {}", row.host.name)
+        },
+        Err(err) => format!("cannot wake {}: {}", row.host.name, err),
+    }
+}
+
+/// Probes the hosts with an IP address every `PROBE_INTERVAL` until `quit` is set.
+fn probe_until(rows: &Mutex<Vec<Row>>, ports: &[u16], quit: &AtomicBool) {
+    while !quit.load(Ordering::SeqCst) {
+        let start = Instant::now();
+        let targets: Vec<Option<(IpAddr, Vec<u16>)>> = rows.lock()
+            .unwrap()
+            .iter()
+            .map(|row| {
+                let own = &row.host.verify_ports;
+                let ports = if own.is_empty() { ports } else { own };
+                row.host.ip.map(|ip| (ip, ports.to_vec()))
+            })
+            .collect();
+        let states: Vec<Option<bool>> = thread::scope(|scope| {
+            let probes: Vec<_> = targets.iter()
+                .map(|target| {
+                    scope.spawn(move || {
+                        target.as_ref().map(|(ip, ports)| Method::Tcp.is_up_on(*ip, ports))
+                    })
+                })
+                .collect();
+            probes.into_iter().map(|probe| probe.join().unwrap()).collect()
+        });
+        for (row, up) in rows.lock().unwrap().iter_mut().zip(states) {
+            row.up = up;
+        }
+        while !quit.load(Ordering::SeqCst) && start.elapsed() < PROBE_INTERVAL {
+            thread::sleep(REDRAW_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use hosts::Host;
+    use MacAddr;
+
+    use super::{parse_keys, render, Key, Row};
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys(b"\x1b[A\x1b[Bjkw\ra?q\x03"),
+                   vec![Key::Up, Key::Down, Key::Down, Key::Up, Key::Wake, Key::Wake,
+                        Key::WakeAll, Key::Quit, Key::Quit]);
+        assert_eq!(parse_keys(b"\x1b"), vec![]);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut desktop = Host::new("desktop", MacAddr::new([0, 0x11, 0x22, 0x33, 0x44, 0x55]));
+        desktop.ip = Some("192.168.1.10".parse().unwrap());
+        let rows = vec![Row {
+                            host: desktop,
+                            up: Some(true),
+                            last_wake: Some(UNIX_EPOCH),
+                        },
+                        Row {
+                            host: Host::new("nas", MacAddr::new([0, 0x11, 0x22, 0x33, 0x44, 0x66])),
+                            up: None,
+                            last_wake: None,
+                        }];
+        let screen = render(&rows, 1, "woke nas");
+        let lines: Vec<&str> = screen.split("\r\n").collect();
+        assert!(lines[1].starts_with("desktop "));
+        assert!(lines[1].contains(" up "));
+        assert!(lines[1].ends_with("1970-01-01T00:00:00.000Z"));
+        assert!(lines[2].starts_with("\x1b[7mnas "));
+        assert_eq!(lines[4], "woke nas");
+    }
+}
@@ -0,0 +1,268 @@
+//! Durations as people write them, e.g. "500ms", "5m" or "7d", and the numbers with bounds:
+//! ports, repeat counts and intervals.
+//!
+//! The command line, the configuration files, the job files and the query parameters of
+//! `wol serve` all parse them here, so that they accept the same units and bounds and fail with
+//! the same messages, when they are read rather than when they are used.
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The most times a packet can be repeated, see `RepeatCount`.
+pub const MAX_REPEAT: u32 = 100;
+
+/// The shortest `Interval`.
+pub const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Parses a duration as a number with one of the units ms, s, m, h or d, e.g. "250ms", "5m",
+/// "1.5h" or "7d".
+///
+/// #Example
+///
+/// ```
+/// use std::time::Duration;
+/// use wol::units;
+///
+/// assert_eq!(units::parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+/// assert!(units::parse_duration("5").is_err());
+/// ```
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (value, unit) = split_number(s)?;
+    let secs = match unit {
+        "ms" => value / 1000.0,
+        "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        "d" => value * 86400.0,
+        _ => return Err("expected a unit of ms, s, m, h or d".to_string()),
+    };
+    Duration::try_from_secs_f64(secs).map_err(|_| "duration out of range".to_string())
+}
+
+/// Parses a duration like `parse_duration`, or a number without unit as seconds, for the
+/// options which took seconds before they took units.
+pub fn parse_duration_or_secs(s: &str) -> Result<Duration, String> {
+    match s.parse() {
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => parse_duration(s),
+    }
+}
+
+/// Formats a duration with the largest unit of `parse_duration` it is a whole number of.
+pub fn format_duration(duration: Duration) -> String {
+    let (secs, millis) = (duration.as_secs(), duration.subsec_millis());
+    if millis != 0 {
+        format!("{}ms", duration.as_millis())
+    } else if secs != 0 && secs.is_multiple_of(86400) {
+        format!("{}d", secs / 86400)
+    } else if secs != 0 && secs.is_multiple_of(3600) {
+        format!("{}h", secs / 3600)
+    } else if secs != 0 && secs.is_multiple_of(60) {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Splits a string into its leading non-negative decimal number and the rest.
+fn split_number(s: &str) -> Result<(f64, &str), String> {
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let value = s[..split].parse().map_err(|_| "expected a number".to_string())?;
+    Ok((value, &s[split..]))
+}
+
+/// A duration parsed with `parse_duration` and displayed with `format_duration`, e.g. for
+/// options parsed with `FromStr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct HumanDuration(pub Duration);
+
+impl FromStr for HumanDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<HumanDuration, String> {
+        parse_duration(s).map(HumanDuration)
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&format_duration(self.0))
+    }
+}
+
+impl From<HumanDuration> for Duration {
+    fn from(duration: HumanDuration) -> Duration {
+        duration.0
+    }
+}
+
+/// A TCP or UDP port other than 0, e.g. one magic packets are sent to or from or one probed to
+/// verify hosts. Port 0 means any port to send from, and is only the default port sent to, see
+/// `transport::destination_v4`, as network cards take magic packets on any port; given
+/// explicitly, it is most likely a mistake.
+///
+/// #Example
+///
+/// ```
+/// use wol::units::Port;
+///
+/// assert_eq!("3389".parse::<Port>().unwrap().get(), 3389);
+/// assert_eq!("0".parse::<Port>().unwrap_err(), "invalid port '0', expected 1 to 65535");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Port(u16);
+
+impl Port {
+    /// Returns the port, failing for 0.
+    pub fn new(port: u16) -> Result<Port, String> {
+        match port {
+            0 => Err("invalid port '0', expected 1 to 65535".to_string()),
+            port => Ok(Port(port)),
+        }
+    }
+
+    /// Returns the number of the port.
+    pub fn get(self) -> u16 {
+        self.0
+    }
+
+    /// Parses a comma-separated list of ports, e.g. "22,3389".
+    pub fn parse_list(s: &str) -> Result<Vec<u16>, String> {
+        s.split(',').map(|port| port.trim().parse().map(Port::get)).collect()
+    }
+}
+
+impl FromStr for Port {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Port, String> {
+        s.parse()
+            .map_err(|_| format!("invalid port '{}', expected 1 to 65535", s))
+            .and_then(Port::new)
+    }
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// How many times a packet is sent, from 1 to `MAX_REPEAT`, as more would flood the network
+/// rather than make the packet arrive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RepeatCount(u32);
+
+impl RepeatCount {
+    /// Returns the count, failing outside of 1 to `MAX_REPEAT`.
+    pub fn new(count: u32) -> Result<RepeatCount, String> {
+        if (1..=MAX_REPEAT).contains(&count) {
+            Ok(RepeatCount(count))
+        } else {
+            Err(format!("invalid repeat count '{}', expected 1 to {}", count, MAX_REPEAT))
+        }
+    }
+
+    /// Returns the count.
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl FromStr for RepeatCount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RepeatCount, String> {
+        s.parse()
+            .map_err(|_| format!("invalid repeat count '{}', expected 1 to {}", s, MAX_REPEAT))
+            .and_then(RepeatCount::new)
+    }
+}
+
+impl fmt::Display for RepeatCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The time between things done again and again, e.g. bursts of packets or printed statistics,
+/// parsed like `HumanDuration` but at least `MIN_INTERVAL`, so that they cannot keep a core busy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval(Duration);
+
+impl Interval {
+    /// Returns the interval, failing below `MIN_INTERVAL`.
+    pub fn new(interval: Duration) -> Result<Interval, String> {
+        if interval < MIN_INTERVAL {
+            return Err(format!("invalid interval '{}', expected at least {}",
+                               format_duration(interval),
+                               format_duration(MIN_INTERVAL)));
+        }
+        Ok(Interval(interval))
+    }
+
+    /// Returns the interval.
+    pub fn get(self) -> Duration {
+        self.0
+    }
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Interval, String> {
+        parse_duration(s).and_then(Interval::new)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&format_duration(self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{format_duration, parse_duration, parse_duration_or_secs, HumanDuration, Interval,
+                Port, RepeatCount};
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("0.5s"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 86400)));
+        assert_eq!(parse_duration("5"), Err("expected a unit of ms, s, m, h or d".to_string()));
+        assert_eq!(parse_duration("m"), Err("expected a number".to_string()));
+        assert_eq!(parse_duration("-1s"), Err("expected a number".to_string()));
+        assert_eq!(parse_duration("99999999999999999999h"),
+                   Err("duration out of range".to_string()));
+        assert_eq!(parse_duration_or_secs("60"), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_duration_or_secs("500ms"), Ok(Duration::from_millis(500)));
+        for s in &["250ms", "90s", "5m", "2h", "36h", "7d", "0s"] {
+            assert_eq!(format_duration(parse_duration(s).unwrap()), *s);
+            assert_eq!(s.parse::<HumanDuration>().unwrap().to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn test_bounds() {
+        assert_eq!("9".parse::<Port>().map(Port::get), Ok(9));
+        assert_eq!("ssh".parse::<Port>(),
+                   Err("invalid port 'ssh', expected 1 to 65535".to_string()));
+        assert_eq!(Port::parse_list("22, 3389"), Ok(vec![22, 3389]));
+        assert!(Port::parse_list("22,0").is_err());
+        assert_eq!("100".parse::<RepeatCount>().map(RepeatCount::get), Ok(100));
+        for s in &["0", "101", "-1"] {
+            assert_eq!(s.parse::<RepeatCount>(),
+                       Err(format!("invalid repeat count '{}', expected 1 to 100", s)));
+        }
+        assert_eq!("10ms".parse::<Interval>().map(Interval::get), Ok(Duration::from_millis(10)));
+        assert_eq!("0s".parse::<Interval>(),
+                   Err("invalid interval '0s', expected at least 10ms".to_string()));
+        assert!("5".parse::<Interval>().is_err());
+    }
+}
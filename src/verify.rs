@@ -0,0 +1,488 @@
+//! Verification that a woken host came up.
+//!
+//! A host counts as up as soon as it accepts a TCP connection on one of a list of ports, e.g. 22
+//! for SSH or 3389 for remote desktop, or, with `Method::Icmp` or `Method::Arp`, as soon as it
+//! answers a ping or an ARP request. `Method::Dhcp` instead waits for the DHCP broadcasts of a
+//! booting host, see `wait_until_booted`. Other checks, e.g. whether an application answers on its
+//! health endpoint, implement `Prober`, like `CommandProbe`, which runs a command.
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "raw")]
+use arp;
+#[cfg(feature = "raw")]
+use dhcp;
+#[cfg(feature = "icmp")]
+use icmp;
+use units::Port;
+use MacAddr;
+
+/// The default port probed to verify that a host is up.
+pub const DEFAULT_PORT: u16 = 22;
+
+/// The default time to wait for a host to come up.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a single connection attempt may take.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The intervals between re-sends of the magic packet while waiting for a host, which double
+/// from `initial` up to `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// Returns the interval following `interval`.
+    pub fn next(&self, interval: Duration) -> Duration {
+        (interval * 2).min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    /// Re-sends after 1, 2, 4, 8, 16 and then every 30 seconds.
+    fn default() -> Backoff {
+        Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(30),
+        }
+    }
+}
+
+/// How hosts are probed to find out whether they are up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Connecting to a TCP port.
+    Tcp,
+    /// Sending ICMP echo requests, for hosts without open ports. This needs the `icmp` feature
+    /// and privileges, see `icmp::Pinger::open`.
+    Icmp,
+    /// Sending ARP requests, for hosts on a local subnet which also ignore pings. This needs
+    /// the `raw` feature, Linux and privileges, see `arp::Prober::open`.
+    Arp,
+    /// Listening for the DHCP DISCOVER or REQUEST a host broadcasts while it boots, which
+    /// confirms that it powered on before any service is reachable. This probes the MAC
+    /// address, not the IP address, of hosts on the local segment and needs the `raw` feature,
+    /// Linux and privileges, see `dhcp::Snooper::open`.
+    Dhcp,
+}
+
+impl Method {
+    /// Returns an error if hosts like `ip` cannot be probed with this method, e.g. because ICMP
+    /// sockets need privileges this process lacks.
+    pub fn check(&self, ip: IpAddr) -> io::Result<()> {
+        match *self {
+            Method::Tcp => Ok(()),
+            #[cfg(feature = "icmp")]
+            Method::Icmp => icmp::Pinger::open(ip).map(|_| ()),
+            #[cfg(not(feature = "icmp"))]
+            Method::Icmp => Err(io::Error::other("ICMP probes need the icmp feature")),
+            #[cfg(feature = "raw")]
+            Method::Arp => arp::Prober::open(ipv4(ip)?).map(|_| ()),
+            #[cfg(not(feature = "raw"))]
+            Method::Arp => ipv4(ip).and(Err(io::Error::other("ARP probes need the raw feature"))),
+            #[cfg(feature = "raw")]
+            Method::Dhcp => dhcp::Snooper::open(MacAddr::new([0; 6]), None).map(|_| ()),
+            #[cfg(not(feature = "raw"))]
+            Method::Dhcp => Err(io::Error::other("DHCP snooping needs the raw feature")),
+        }
+    }
+
+    /// Probes once whether the host at `address` is up. ICMP ignores the port; if the host
+    /// cannot be pinged, it counts as down. DHCP cannot probe addresses, so that hosts count as
+    /// down until `wait_until_booted` saw them boot.
+    pub fn is_up(&self, address: SocketAddr) -> bool {
+        let result = match *self {
+            Method::Tcp => TcpStream::connect_timeout(&address, ATTEMPT_TIMEOUT).map(|_| true),
+            #[cfg(feature = "icmp")]
+            Method::Icmp => icmp::ping(address.ip(), ATTEMPT_TIMEOUT),
+            #[cfg(feature = "raw")]
+            Method::Arp => {
+                ipv4(address.ip()).and_then(|ip| arp::probe(ip, ATTEMPT_TIMEOUT))
+                    .map(|mac| mac.is_some())
+            },
+            Method::Dhcp => Ok(false),
+            #[cfg(not(all(feature = "icmp", feature = "raw")))]
+            _ => self.check(address.ip()).map(|_| false),
+        };
+        match result {
+            Ok(up) => up,
+            Err(err) => {
+                trace!("{} is not up yet: {}", address, err);
+                false
+            },
+        }
+    }
+
+    /// Probes once whether the host at `ip` is up, trying the TCP ports in order until one
+    /// accepts a connection. The other methods probe once and ignore the ports.
+    pub fn is_up_on(&self, ip: IpAddr, ports: &[u16]) -> bool {
+        match *self {
+            Method::Tcp => ports.iter().any(|&port| self.is_up(SocketAddr::new(ip, port))),
+            _ => self.is_up(SocketAddr::new(ip, 0)),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Method::Tcp => "tcp",
+            Method::Icmp => "icmp",
+            Method::Arp => "arp",
+            Method::Dhcp => "dhcp",
+        })
+    }
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Method, String> {
+        match s {
+            "tcp" => Ok(Method::Tcp),
+            "icmp" => Ok(Method::Icmp),
+            "arp" => Ok(Method::Arp),
+            "dhcp" => Ok(Method::Dhcp),
+            _ => {
+                Err(format!("unknown verification method '{}', expected tcp, icmp, arp or dhcp",
+                            s))
+            },
+        }
+    }
+}
+
+/// A check whether a host is up, probed until it succeeds by `wait_for` and
+/// `WolClient::wake_and_wait`.
+///
+/// A socket address probes by connecting to the TCP port, `Probe` with any `Method` and
+/// `CommandProbe` by running a command; closures returning whether the host is up are probers
+/// as well.
+///
+/// #Example
+///
+/// ```
+/// use std::sync::atomic::AtomicBool;
+/// use std::time::Duration;
+/// use wol::verify;
+///
+/// let healthy = || true; // e.g. whether GET /health of the application returns 200
+/// let cancel = AtomicBool::new(false);
+/// assert_eq!(verify::wait_for(&healthy, Duration::from_secs(1), &cancel), Some(true));
+/// ```
+pub trait Prober {
+    /// Probes once whether the host is up. Errors, e.g. a refused connection, count as down.
+    fn is_up(&self) -> bool;
+}
+
+impl Prober for SocketAddr {
+    fn is_up(&self) -> bool {
+        Method::Tcp.is_up(*self)
+    }
+}
+
+impl<F: Fn() -> bool> Prober for F {
+    fn is_up(&self) -> bool {
+        self()
+    }
+}
+
+/// Probes a host with one of the built-in methods.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probe {
+    method: Method,
+    ip: IpAddr,
+    ports: Vec<u16>,
+}
+
+impl Probe {
+    /// Creates a probe of the host at `ip` with `method`, on the TCP ports in order.
+    pub fn new(method: Method, ip: IpAddr, ports: &[u16]) -> Probe {
+        Probe {
+            method,
+            ip,
+            ports: ports.to_vec(),
+        }
+    }
+}
+
+impl Prober for Probe {
+    fn is_up(&self) -> bool {
+        self.method.is_up_on(self.ip, &self.ports)
+    }
+}
+
+/// Probes a host by running a command line with the shell, which counts the host as up if the
+/// command exits successfully, e.g. `ssh nas true`. Commands running longer than their timeout
+/// are killed and count as down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandProbe {
+    command: String,
+    timeout: Duration,
+}
+
+impl CommandProbe {
+    /// The default time a command may run.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Creates a probe running `command` with `sh -c`, or `cmd /C` on Windows.
+    pub fn new(command: &str) -> CommandProbe {
+        CommandProbe {
+            command: command.to_string(),
+            timeout: CommandProbe::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Kills the command once it has run for `timeout` instead of the default 10 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> CommandProbe {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the command line.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Returns the time the command may run.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Runs the command and returns whether it exited successfully within the timeout.
+    fn run(&self) -> io::Result<bool> {
+        let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let mut child = Command::new(shell)
+            .args([flag, &self.command])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let start = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status.success());
+            }
+            if start.elapsed() >= self.timeout {
+                child.kill()?;
+                child.wait()?;
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "the command timed out"));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Prober for CommandProbe {
+    fn is_up(&self) -> bool {
+        match self.run() {
+            Ok(up) => up,
+            Err(err) => {
+                trace!("'{}' failed: {}", self.command, err);
+                false
+            },
+        }
+    }
+}
+
+/// Returns the IPv4 address, which ARP needs.
+fn ipv4(ip: IpAddr) -> io::Result<Ipv4Addr> {
+    match ip {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(_) => {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, "ARP probes need an IPv4 address"))
+        },
+    }
+}
+
+/// Parses a list of TCP ports separated by commas, e.g. "22,3389,445".
+///
+/// #Example
+///
+/// ```
+/// # #![allow(deprecated)]
+/// use wol::verify::parse_ports;
+///
+/// assert_eq!(parse_ports("22, 3389"), Ok(vec![22, 3389]));
+/// assert!(parse_ports("").is_err());
+/// assert!(parse_ports("22,0").is_err());
+/// ```
+#[deprecated(note = "use `wol::units::Port::parse_list(s)`, which rejects port 0 as well")]
+pub fn parse_ports(s: &str) -> Result<Vec<u16>, String> {
+    Port::parse_list(s)
+}
+
+/// Tries once to connect to `address` and returns whether the host accepted the connection.
+pub fn is_up(address: SocketAddr) -> bool {
+    Method::Tcp.is_up(address)
+}
+
+/// Tries to connect to `address` until it succeeds or `timeout` has passed. Returns whether the
+/// host accepted a connection.
+pub fn wait_for_port(address: SocketAddr, timeout: Duration) -> bool {
+    wait_for_port_or_cancel(address, timeout, &AtomicBool::new(false)).unwrap_or(false)
+}
+
+/// Like `wait_for_port`, but returns `None` as soon as `cancel` is set, e.g. from a ctrl-C
+/// handler.
+pub fn wait_for_port_or_cancel(address: SocketAddr,
+                               timeout: Duration,
+                               cancel: &AtomicBool)
+                               -> Option<bool> {
+    wait_until_up(Method::Tcp, address.ip(), &[address.port()], timeout, cancel)
+}
+
+/// Probes the host at `ip` with the given method, on the TCP ports in order, until it is up or
+/// `timeout` has passed. Returns whether it came up, or `None` as soon as `cancel` is set.
+pub fn wait_until_up(method: Method,
+                     ip: IpAddr,
+                     ports: &[u16],
+                     timeout: Duration,
+                     cancel: &AtomicBool)
+                     -> Option<bool> {
+    let start = Instant::now();
+    let up = wait_for(&Probe::new(method, ip, ports), timeout, cancel);
+    match up {
+        None => debug!("waiting for {} was cancelled", ip),
+        Some(true) => debug!("{} is up after {:?}", ip, start.elapsed()),
+        Some(false) => debug!("{} did not come up within {:?}", ip, timeout),
+    }
+    up
+}
+
+/// Listens for the DHCP DISCOVER or REQUEST of `mac` on all interfaces until it was seen or
+/// `timeout` has passed, for `Method::Dhcp`. Returns whether the host booted, or `None` as soon
+/// as `cancel` is set. Call it right after sending the magic packet.
+pub fn wait_until_booted(mac: MacAddr, timeout: Duration, cancel: &AtomicBool) -> Option<bool> {
+    #[cfg(feature = "raw")]
+    let booted = match dhcp::Snooper::open(mac, None) {
+        Ok(snooper) => wait_for(&snooper, timeout, cancel),
+        Err(err) => {
+            debug!("cannot listen for the DHCP messages of {}: {}", mac, err);
+            Some(false)
+        },
+    };
+    #[cfg(not(feature = "raw"))]
+    let booted = {
+        debug!("cannot listen for the DHCP messages of {}: needs the raw feature", mac);
+        let _ = (timeout, cancel);
+        Some(false)
+    };
+    if booted == Some(true) {
+        debug!("{} sent a DHCP message", mac);
+    }
+    booted
+}
+
+/// Probes with `prober`, at most once a second, until the host is up or `timeout` has passed.
+/// Returns whether it came up, or `None` as soon as `cancel` is set.
+pub fn wait_for<P>(prober: &P, timeout: Duration, cancel: &AtomicBool) -> Option<bool>
+    where P: Prober + ?Sized
+{
+    let start = Instant::now();
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            return None;
+        }
+        let attempt = Instant::now();
+        if prober.is_up() {
+            return Some(true);
+        }
+        if start.elapsed() >= timeout {
+            return Some(false);
+        }
+        // Connection refused returns immediately, so wait for the rest of the attempt.
+        if let Some(rest) = ATTEMPT_TIMEOUT.checked_sub(attempt.elapsed()) {
+            thread::sleep(rest.min(timeout.saturating_sub(start.elapsed())));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicBool;
+    use std::time::{Duration, Instant};
+
+    use super::{is_up, wait_for, wait_for_port, wait_for_port_or_cancel,
+                wait_until_up, Backoff, CommandProbe, Method, Prober};
+
+    #[test]
+    fn test_wait_for_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        assert!(wait_for_port(address, Duration::from_secs(1)));
+        assert!(is_up(address));
+        drop(listener);
+        assert!(!wait_for_port(address, Duration::from_millis(0)));
+        assert!(!is_up(address));
+        let cancel = AtomicBool::new(true);
+        assert_eq!(wait_for_port_or_cancel(address, Duration::from_secs(10), &cancel), None);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_ports() {
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let ip = "127.0.0.1".parse().unwrap();
+        assert!(Method::Tcp.is_up_on(ip, &[closed, open]));
+        assert!(!Method::Tcp.is_up_on(ip, &[closed]));
+        let cancel = AtomicBool::new(false);
+        assert_eq!(wait_until_up(Method::Tcp, ip, &[closed, open], Duration::from_secs(1), &cancel),
+                   Some(true));
+        assert_eq!(super::parse_ports("22,3389,445"), Ok(vec![22, 3389, 445]));
+        assert_eq!(super::parse_ports("22,ssh"),
+                   Err("invalid port 'ssh', expected 1 to 65535".to_string()));
+    }
+
+    #[test]
+    fn test_probers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        assert!(listener.local_addr().unwrap().is_up());
+        let cancel = AtomicBool::new(false);
+        assert_eq!(wait_for(&|| false, Duration::from_millis(10), &cancel), Some(false));
+        let prober: &dyn Prober = &CommandProbe::new("exit 0");
+        assert_eq!(wait_for(prober, Duration::from_secs(1), &cancel), Some(true));
+        assert!(!CommandProbe::new("exit 1").is_up());
+        let slow = CommandProbe::new("sleep 5").with_timeout(Duration::from_millis(50));
+        let start = Instant::now();
+        assert!(!slow.is_up());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_method() {
+        assert_eq!("icmp".parse::<Method>(), Ok(Method::Icmp));
+        assert_eq!(Method::Tcp.to_string(), "tcp");
+        assert_eq!("arp".parse::<Method>(), Ok(Method::Arp));
+        assert_eq!("dhcp".parse::<Method>(), Ok(Method::Dhcp));
+        assert_eq!(Method::Dhcp.to_string(), "dhcp");
+        assert!(!Method::Dhcp.is_up("127.0.0.1:22".parse().unwrap()));
+        assert!("udp".parse::<Method>().is_err());
+        assert!(Method::Arp.check("::1".parse().unwrap()).is_err());
+        assert!(Method::Tcp.check("127.0.0.1".parse().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let backoff = Backoff::default();
+        let intervals: Vec<u64> = (0..7)
+            .scan(backoff.initial, |interval, _| {
+                let current = *interval;
+                *interval = backoff.next(current);
+                Some(current.as_secs())
+            })
+            .collect();
+        assert_eq!(intervals, vec![1, 2, 4, 8, 16, 30, 30]);
+    }
+}